@@ -0,0 +1,36 @@
+// Times compute_dx and compute_dy over a sweep of square sizes and reports
+// the median run time and the throughput of each.
+//
+//   cargo run --release --example bench_sizes [samples]
+
+#![allow(clippy::needless_return)]
+
+use std::env;
+use std::time::Duration;
+
+use rmm::kernels::{compute_dx, compute_dy};
+use rmm::matrix::construct_randomized_matrix_seeded;
+use rmm::throughput::{dx_bytes_moved, dy_bytes_moved, Throughput};
+use rmm::timing::{measure, TimingConfig, Warmup};
+
+const SIZES: [usize; 5] = [64, 256, 512, 1024, 2048];
+
+fn main() {
+    let samples: usize = env::args().nth(1).map_or(5, |value| value.parse().expect("samples must be a number"));
+    let config: TimingConfig = TimingConfig { warmup: Warmup::Fixed(1), samples, ..TimingConfig::default() };
+
+    println!("{:>6} {:>12} {:>10} {:>12} {:>10}", "size", "dx median", "dx GB/s", "dy median", "dy GB/s");
+
+    for size in SIZES {
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(size, size, size as u64);
+
+        let (_, dx_report) = measure(&config, || compute_dx(&arr, size, size));
+        let (_, dy_report) = measure(&config, || compute_dy(&arr, size, size));
+        let (dx_time, dy_time): (Duration, Duration) = (dx_report.median(), dy_report.median());
+
+        let dx: Throughput = Throughput::new(size * size, dx_bytes_moved(size, size, 2, 2), dx_time);
+        let dy: Throughput = Throughput::new(size * size, dy_bytes_moved(size, size, 2, 2), dy_time);
+
+        println!("{:>6} {:>12?} {:>10.2} {:>12?} {:>10.2}", size, dx_time, dx.gb_per_sec, dy_time, dy.gb_per_sec);
+    }
+}
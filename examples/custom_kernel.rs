@@ -0,0 +1,37 @@
+// Builds a matrix with Matrix::from_fn and runs a user-defined kernel over it:
+// a [1, -2, 1] second difference, which is zero on the linear ramp along the
+// rows and picks out the step in the middle of every column.
+//
+//   cargo run --example custom_kernel
+
+#![allow(clippy::needless_return)]
+
+use rmm::arith::ArithPolicy;
+use rmm::conv::Kernel;
+use rmm::matrix::Matrix;
+
+const ROWS: usize = 6;
+const COLS: usize = 8;
+
+fn main() {
+    // A ramp along each row, plus a step of 50 halfway down.
+    let input: Matrix<u8> = Matrix::from_fn(ROWS, COLS, |row, col| (10 * col + if row >= ROWS / 2 { 50 } else { 0 }) as u8);
+    let laplacian: Kernel = Kernel::new(vec![1, -2, 1]).expect("a non-empty kernel");
+
+    let along_rows: Matrix<i16> = laplacian.apply_rows(&input, ArithPolicy::Checked).expect("sums fit an i16");
+    let along_cols: Matrix<i16> = laplacian.apply_cols(&input, ArithPolicy::Checked).expect("sums fit an i16");
+
+    println!("input {}x{}:", input.rows, input.cols);
+    print_matrix(&input);
+    println!("kernel {:?} along the rows, {}x{}:", laplacian.taps(), along_rows.rows, along_rows.cols);
+    print_matrix(&along_rows);
+    println!("kernel {:?} along the columns, {}x{}:", laplacian.taps(), along_cols.rows, along_cols.cols);
+    print_matrix(&along_cols);
+}
+
+fn print_matrix<T: Copy + std::fmt::Display>(matrix: &Matrix<T>) {
+    for row in 0..matrix.rows {
+        let line: Vec<String> = (0..matrix.cols).map(|col| format!("{:>5}", matrix.get(row, col))).collect();
+        println!("{}", line.join(""));
+    }
+}
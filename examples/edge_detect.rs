@@ -0,0 +1,37 @@
+// Loads a greyscale PGM image, takes its Sobel gradient magnitude and writes
+// the pixels above a threshold as a black and white PGM.
+//
+//   cargo run --example edge_detect [input.pgm] [output.pgm] [threshold]
+//
+// Without arguments it reads examples/data/shapes.pgm and writes edges.pgm to
+// the temporary directory.
+
+#![allow(clippy::needless_return)]
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use rmm::conv::sobel;
+use rmm::gradient::magnitude;
+use rmm::io::{read_pgm, write_pgm};
+
+// Magnitudes reach 4 * 255 * sqrt(2); this keeps clear steps and drops noise.
+const DEFAULT_THRESHOLD: f32 = 200.0;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let input: PathBuf = args.first().map_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/data/shapes.pgm"), PathBuf::from);
+    let output: PathBuf = args.get(1).map_or_else(|| env::temp_dir().join("edges.pgm"), PathBuf::from);
+    let threshold: f32 = args.get(2).map_or(DEFAULT_THRESHOLD, |value| value.parse().expect("threshold must be a number"));
+
+    let (pixels, rows, cols): (Vec<u8>, usize, usize) = read_pgm(&input).unwrap_or_else(|error| panic!("cannot read {}: {}", input.display(), error));
+
+    let (gx, gy): (Vec<i16>, Vec<i16>) = sobel(&pixels, rows, cols).expect("pixels match the header");
+    let strength: Vec<f32> = magnitude(&gx, &gy, rows, cols).expect("gx and gy have the image's shape");
+    let edges: Vec<u8> = strength.iter().map(|&value| if value > threshold { 255 } else { 0 }).collect();
+
+    write_pgm(&output, &edges, rows, cols).unwrap_or_else(|error| panic!("cannot write {}: {}", output.display(), error));
+
+    let count: usize = edges.iter().filter(|&&value| value != 0).count();
+    println!("{}x{} image, {} edge pixels above {}, written to {}", rows, cols, count, threshold, output.display());
+}
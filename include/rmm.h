@@ -0,0 +1,54 @@
+/*
+ * C interface to the [-1, 0, 1] gradient kernels of the rmm library, built
+ * with the ffi feature (see src/ffi.rs):
+ *
+ *   cargo rustc --lib --release --features ffi --crate-type cdylib
+ *
+ * Matrices are row-major. The caller allocates every buffer: query the
+ * output length, allocate it once, and pass it in.
+ *
+ *   size_t len = rmm_dx_output_len(rows, cols);
+ *   int16_t *dx = malloc(len * sizeof(int16_t));
+ *   if (rmm_compute_dx_into(input, rows, cols, dx, len) != RMM_OK) { ... }
+ */
+
+#ifndef RMM_H
+#define RMM_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum {
+    RMM_OK = 0,
+    /* The input or the output pointer is NULL. */
+    RMM_NULL_POINTER = 1,
+    /* out_len is less than the output length of the dimensions. */
+    RMM_BUFFER_TOO_SMALL = 2,
+    /* rows * cols, or the output length, does not fit a size_t. */
+    RMM_DIMENSION_OVERFLOW = 3,
+} RmmStatus;
+
+/* Returned by the length queries when the dimensions overflow. */
+#define RMM_LEN_OVERFLOW SIZE_MAX
+
+/* rows * (cols + 2), or RMM_LEN_OVERFLOW. */
+size_t rmm_dx_output_len(size_t rows, size_t cols);
+
+/* (rows + 2) * cols, or RMM_LEN_OVERFLOW. */
+size_t rmm_dy_output_len(size_t rows, size_t cols);
+
+/* Write Dx (Dy) of the rows x cols matrix at arr to the first
+ * rmm_dx_output_len (rmm_dy_output_len) elements of out, which holds out_len.
+ * Nothing is written unless RMM_OK is returned. */
+RmmStatus rmm_compute_dx_into(const uint8_t *arr, size_t rows, size_t cols, int16_t *out, size_t out_len);
+RmmStatus rmm_compute_dy_into(const uint8_t *arr, size_t rows, size_t cols, int16_t *out, size_t out_len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,86 @@
+// Element access used by the kernels. The index math lives in the kernels
+// and is shared by both implementations below, so the safe and the unchecked
+// paths cannot drift apart; only the way an element is read or written
+// differs.
+pub(crate) trait Access {
+    fn get<T: Copy>(data: &[T], index: usize) -> T;
+    fn set<T>(data: &mut [T], index: usize, value: T);
+    fn slice<T>(data: &[T], start: usize, end: usize) -> &[T];
+    fn slice_mut<T>(data: &mut [T], start: usize, end: usize) -> &mut [T];
+}
+
+// Ordinary bounds-checked indexing.
+pub(crate) struct Checked;
+
+impl Access for Checked {
+    #[inline(always)]
+    fn get<T: Copy>(data: &[T], index: usize) -> T {
+        return data[index];
+    }
+
+    #[inline(always)]
+    fn set<T>(data: &mut [T], index: usize, value: T) {
+        data[index] = value;
+    }
+
+    #[inline(always)]
+    fn slice<T>(data: &[T], start: usize, end: usize) -> &[T] {
+        return &data[start..end];
+    }
+
+    #[inline(always)]
+    fn slice_mut<T>(data: &mut [T], start: usize, end: usize) -> &mut [T] {
+        return &mut data[start..end];
+    }
+}
+
+// Indexing without bounds checks, enabled by the unsafe-fast feature. Only
+// used by kernels that, before the first access, validate every input with
+// check_len or check_strided and size every output with checked_elements.
+// Those checks fail with DimError::TooLarge instead of wrapping, so each size
+// the loops derive their indices from is exact; a wrapped product could
+// otherwise pass as a short buffer. Debug builds still assert each index.
+#[cfg(feature = "unsafe-fast")]
+pub(crate) struct Unchecked;
+
+#[cfg(feature = "unsafe-fast")]
+impl Access for Unchecked {
+    #[inline(always)]
+    fn get<T: Copy>(data: &[T], index: usize) -> T {
+        debug_assert!(index < data.len());
+        // SAFETY: the kernel checked its sizes as described above, so the
+        // index is in bounds.
+        return unsafe { *data.get_unchecked(index) };
+    }
+
+    #[inline(always)]
+    fn set<T>(data: &mut [T], index: usize, value: T) {
+        debug_assert!(index < data.len());
+        // SAFETY: the kernel checked its sizes as described above, so the
+        // index is in bounds.
+        unsafe { *data.get_unchecked_mut(index) = value };
+    }
+
+    #[inline(always)]
+    fn slice<T>(data: &[T], start: usize, end: usize) -> &[T] {
+        debug_assert!(start <= end && end <= data.len());
+        // SAFETY: the kernel checked its sizes as described above, so the
+        // index is in bounds.
+        return unsafe { data.get_unchecked(start..end) };
+    }
+
+    #[inline(always)]
+    fn slice_mut<T>(data: &mut [T], start: usize, end: usize) -> &mut [T] {
+        debug_assert!(start <= end && end <= data.len());
+        // SAFETY: the kernel checked its sizes as described above, so the
+        // index is in bounds.
+        return unsafe { data.get_unchecked_mut(start..end) };
+    }
+}
+
+// The access mode used by the public kernels.
+#[cfg(feature = "unsafe-fast")]
+pub(crate) type Fast = Unchecked;
+
+#[cfg(not(feature = "unsafe-fast"))]
+pub(crate) type Fast = Checked;
@@ -0,0 +1,113 @@
+use crate::error::{ArithError, DimError};
+
+// How integer results that do not fit the output type are handled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ArithPolicy {
+    // Keep the low bits, like an `as` cast. This is what the fixed kernels do.
+    #[default]
+    Wrapping,
+    // Clamp to the output type's range.
+    Saturating,
+    // Fail with ArithError::Overflow at the first value that does not fit.
+    Checked,
+}
+
+impl ArithPolicy {
+    pub fn parse(value: &str) -> Option<ArithPolicy> {
+        return match value {
+            "wrapping" => Some(ArithPolicy::Wrapping),
+            "saturating" => Some(ArithPolicy::Saturating),
+            "checked" => Some(ArithPolicy::Checked),
+            _ => None,
+        };
+    }
+}
+
+// Element types that can be read into the i64 accumulator.
+pub trait Widen: Copy {
+    fn widen(self) -> i64;
+}
+
+// Element types an i64 accumulator can be written back into.
+pub trait Narrow: Copy + Default {
+    // Converts value under the given policy, returning None if the policy is
+    // Checked and the value is out of range.
+    fn narrow(value: i64, policy: ArithPolicy) -> Option<Self>;
+}
+
+// The conversions are #[inline] so that generic loops instantiated in other
+// crates, such as the const convolutions, can fold them in and vectorize.
+macro_rules! impl_widen {
+    ($($t:ty),*) => {
+        $(impl Widen for $t {
+            #[inline]
+            fn widen(self) -> i64 {
+                return self as i64;
+            }
+        })*
+    };
+}
+
+macro_rules! impl_narrow {
+    ($($t:ty),*) => {
+        $(impl Narrow for $t {
+            #[inline]
+            fn narrow(value: i64, policy: ArithPolicy) -> Option<Self> {
+                return match policy {
+                    ArithPolicy::Wrapping => Some(value as $t),
+                    ArithPolicy::Saturating => Some(value.clamp(<$t>::MIN as i64, <$t>::MAX as i64) as $t),
+                    ArithPolicy::Checked => <$t>::try_from(value).ok(),
+                };
+            }
+        })*
+    };
+}
+
+impl_widen!(u8, u16, i16, i32);
+impl_narrow!(i16, i32);
+
+// Writes value into out[index] under policy, reporting an overflow at index.
+pub(crate) fn store<O: Narrow>(out: &mut [O], index: usize, value: i64, policy: ArithPolicy) -> Result<(), ArithError> {
+    out[index] = match O::narrow(value, policy) {
+        Some(narrowed) => narrowed,
+        None => return Err(ArithError::Overflow { index, value }),
+    };
+
+    return Ok(());
+}
+
+// Applies f to each pair of elements of a and b under policy.
+fn zip_map<T: Widen + Narrow>(a: &[T], b: &[T], policy: ArithPolicy, f: impl Fn(i64, i64) -> i64) -> Result<Vec<T>, ArithError> {
+    if a.len() != b.len() {
+        return Err(ArithError::Dim(DimError::LengthMismatch { expected: a.len(), found: b.len() }));
+    }
+
+    let mut out: Vec<T> = vec![T::default(); a.len()];
+
+    for index in 0..a.len() {
+        store(&mut out, index, f(a[index].widen(), b[index].widen()), policy)?;
+    }
+
+    return Ok(out);
+}
+
+// Element-wise a + b.
+pub fn add<T: Widen + Narrow>(a: &[T], b: &[T], policy: ArithPolicy) -> Result<Vec<T>, ArithError> {
+    return zip_map(a, b, policy, |x, y| x + y);
+}
+
+// Element-wise a - b.
+pub fn sub<T: Widen + Narrow>(a: &[T], b: &[T], policy: ArithPolicy) -> Result<Vec<T>, ArithError> {
+    return zip_map(a, b, policy, |x, y| x - y);
+}
+
+// Multiplies every element by factor.
+pub fn scale<T: Widen + Narrow>(a: &[T], factor: i32, policy: ArithPolicy) -> Result<Vec<T>, ArithError> {
+    let mut out: Vec<T> = vec![T::default(); a.len()];
+
+    for (index, value) in a.iter().enumerate() {
+        store(&mut out, index, value.widen() * factor as i64, policy)?;
+    }
+
+    return Ok(out);
+}
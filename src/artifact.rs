@@ -0,0 +1,264 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use serde_json::{json, Map, Value};
+use rmm::convert_dtype::convert_dtype;
+use rmm::conv::ConvMode;
+use rmm::io::write_bin;
+use rmm::matrix::Layout;
+use rmm::stats::{checksum, count_nonzero, float_stats_with, get_max, get_min, get_sum, percentiles, Accumulator, FloatStats, CHECKSUM_BASIS};
+use rmm::timing::TimingReport;
+use crate::cli::Options;
+use crate::{write_bin_values, Gradients, DEFAULT_PERCENTILES, GENERATED_DISTRIBUTION};
+
+// Files of a run artifact, in the order they are listed in the manifest.
+const FILES: [&str; 6] = ["manifest.json", "input.bin", "dx.bin", "dy.bin", "stats.json", "timing.json"];
+
+// Everything a run artifact records besides the gradients themselves.
+pub struct Run<'a> {
+    pub args: &'a [String],
+    pub options: &'a Options,
+    // None when the input was read from a file.
+    pub seed: Option<u64>,
+    // The matrix the kernels ran on, after any rotation and resizing.
+    pub input: &'a [u8],
+    pub rows: usize,
+    pub cols: usize,
+    // Fingerprint of input, see rmm::stats::fingerprint.
+    pub fingerprint: u64,
+    // The steps that produced input, see build_input.
+    pub stages: &'a [Stage],
+}
+
+// One step of the recorded pipeline and the checksum of what it produced,
+// which verify-run recomputes: the input fingerprint for the input stages, a
+// checksum of the values for dx and dy.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stage {
+    pub name: &'static str,
+    pub description: String,
+    pub checksum: u64,
+}
+
+// The dx and dy stages of a run with options.
+pub fn gradient_stages(options: &Options, gradients: &Gradients) -> Vec<Stage> {
+    let kernel: Vec<i32> = options.kernel.clone().unwrap_or(vec![-1, 0, 1]);
+    let layout: &str = if options.layout == Layout::ColMajor { "col" } else { "row" };
+
+    return [("dx", &gradients.dx), ("dy", &gradients.dy)].into_iter().map(|(name, output)| Stage {
+        name,
+        description: format!("{}x{}, kernel {:?}{}, pad {}, {}-major{}", output.rows, output.cols, kernel,
+                             if options.conv_mode == Some(ConvMode::CrossCorrelation) { " cross-correlated" } else { "" }, pad(options), layout,
+                             if options.crop_output { ", cropped" } else { "" }),
+        checksum: checksum(CHECKSUM_BASIS, &output.data),
+    }).collect();
+}
+
+// Padding a run with options applies, the kernel length minus one unless set.
+fn pad(options: &Options) -> usize {
+    return options.pad.unwrap_or(options.kernel.as_ref().map_or(2, |kernel| kernel.len() - 1));
+}
+
+// Fails early, before any work is done, if dir cannot become an artifact.
+pub fn check_target(dir: &Path) {
+    if dir.exists() {
+        eprintln!("{} already exists; refusing to overwrite a run artifact", dir.display());
+        process::exit(1);
+    }
+}
+
+// Writes a self-describing run artifact to dir:
+//
+//     manifest.json  version, command line, seed and parameters, the
+//                    pipeline stages with their checksums, file list
+//     input.bin      the input matrix, in the binary matrix format
+//     dx.bin, dy.bin the results, converted to --output-dtype
+//     stats.json     min, max, sum, non-zero count, percentiles, mean,
+//                    variance and Frobenius norm of the unconverted
+//                    results, the accumulator the last three were reduced
+//                    in, plus heap usage with the mem-stats feature
+//     timing.json    every timed sample plus the warm-up details
+//
+// The files are written to a temporary sibling directory that is renamed to
+// dir at the end, so an interrupted run never leaves a partial artifact
+// under the requested name.
+pub fn write_artifact(dir: &Path, run: &Run, gradients: &Gradients) -> std::io::Result<()> {
+    let name: String = dir.file_name().map_or("artifact".to_string(), |name| name.to_string_lossy().into_owned());
+    let staging: PathBuf = dir.with_file_name(format!(".{}.tmp-{}", name, process::id()));
+
+    let written: std::io::Result<()> = fs::create_dir_all(&staging).and_then(|_| write_files(&staging, run, gradients));
+
+    // A failed rename, e.g. because dir appeared meanwhile, must not leave
+    // the staging directory behind either.
+    if let Err(err) = written.and_then(|_| fs::rename(&staging, dir)) {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(err);
+    }
+
+    return Ok(());
+}
+
+fn write_files(dir: &Path, run: &Run, gradients: &Gradients) -> std::io::Result<()> {
+    let options: &Options = run.options;
+    let manifest: Value = json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "args": run.args,
+        "seed": run.seed,
+        "input_file": options.input,
+        "rows": options.rows,
+        "cols": options.cols,
+        "input": {
+            "rows": run.rows,
+            "cols": run.cols,
+            "fingerprint": format!("{:016x}", run.fingerprint),
+            "distribution": if options.input.is_none() { Some(GENERATED_DISTRIBUTION) } else { None },
+        },
+        "kernel": options.kernel.clone().unwrap_or(vec![-1, 0, 1]),
+        "pad": pad(options),
+        "arith": options.arith.map(|policy| format!("{:?}", policy).to_lowercase()),
+        "conv_mode": options.conv_mode.unwrap_or_default().name(),
+        "layout": if options.layout == Layout::ColMajor { "col" } else { "row" },
+        "crop_output": options.crop_output,
+        "output_dtype": options.output_dtype.unwrap_or_default().to_string(),
+        "pipeline": run.stages.iter().chain(&gradient_stages(options, gradients)).map(|stage| json!({
+            "stage": stage.name,
+            "description": stage.description,
+            "checksum": format!("{:016x}", stage.checksum),
+        })).collect::<Vec<Value>>(),
+        "files": FILES,
+    });
+
+    let accumulator: Accumulator = options.accumulate.unwrap_or_default();
+    let mut stats: Map<String, Value> = Map::new();
+    let mut timing: Map<String, Value> = Map::new();
+
+    stats.insert("accumulator".to_string(), json!(accumulator.name()));
+
+    for (name, output, report) in [("dx", &gradients.dx, &gradients.dx_timing), ("dy", &gradients.dy, &gradients.dy_timing)] {
+        let data: &[i16] = &output.data;
+        write_bin_values(&dir.join(format!("{}.bin", name)), &convert_dtype(data, options.output_dtype.unwrap_or_default()), output.rows, output.cols)?;
+
+        let ps: &[f64] = options.percentiles.as_deref().unwrap_or(&DEFAULT_PERCENTILES);
+        let values: Map<String, Value> = ps.iter().zip(percentiles(data, ps)).map(|(p, value)| (format!("p{}", p), json!(value))).collect();
+        let floats: FloatStats = float_stats_with(&data.iter().map(|&value| value as f32).collect::<Vec<f32>>(), 1, accumulator);
+
+        stats.insert(name.to_string(), json!({
+            "rows": output.rows,
+            "cols": output.cols,
+            "min": get_min(data),
+            "max": get_max(data),
+            "sum": get_sum(data),
+            "nonzero": count_nonzero(data),
+            "percentiles": values,
+            "mean": floats.mean,
+            "variance": floats.variance,
+            "frobenius": floats.frobenius,
+        }));
+        timing.insert(name.to_string(), timing_json(report));
+    }
+
+    // Only present in builds with the mem-stats feature.
+    if let Some(memory) = &gradients.memory {
+        stats.insert("memory".to_string(), json!({
+            "peak_rss_estimate": memory.peak_bytes,
+            "alloc_count": memory.alloc_count,
+        }));
+    }
+
+    write_bin(&dir.join("input.bin"), run.input, run.rows, run.cols)?;
+    fs::write(dir.join("manifest.json"), to_pretty(&manifest))?;
+    fs::write(dir.join("stats.json"), to_pretty(&Value::Object(stats)))?;
+    fs::write(dir.join("timing.json"), to_pretty(&Value::Object(timing)))?;
+
+    return Ok(());
+}
+
+pub(crate) fn timing_json(report: &TimingReport) -> Value {
+    return json!({
+        "median_ns": report.median().as_nanos() as u64,
+        "samples_ns": report.samples.iter().map(|sample| sample.as_nanos() as u64).collect::<Vec<u64>>(),
+        "discarded": report.discarded,
+        "steady": report.steady,
+    });
+}
+
+fn to_pretty(value: &Value) -> String {
+    return serde_json::to_string_pretty(value).expect("JSON values always serialize") + "\n";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli;
+    use crate::compute_gradients;
+    use rmm::stats::fingerprint;
+
+    fn temp_path(name: &str) -> PathBuf {
+        return std::env::temp_dir().join(format!("rmm-artifact-{}-{}", process::id(), name));
+    }
+
+    fn run_options(flags: &[&str]) -> Options {
+        return cli::parse_args(&["matician-coding-challenge"].iter().chain(flags).map(|flag| flag.to_string()).collect::<Vec<String>>());
+    }
+
+    // Names of the entries of dir, sorted.
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned()).collect();
+
+        names.sort();
+        return names;
+    }
+
+    #[test]
+    fn a_finished_artifact_holds_exactly_its_manifest_files() {
+        let parent: PathBuf = temp_path("finished");
+        let dir: PathBuf = parent.join("run");
+        let _ = fs::remove_dir_all(&parent);
+        fs::create_dir_all(&parent).unwrap();
+
+        let args: Vec<String> = ["matician-coding-challenge", "--output-dir", "run"].iter().map(|arg| arg.to_string()).collect();
+        let options: Options = run_options(&["--output-dir", "run"]);
+        let (rows, cols): (usize, usize) = (4, 6);
+        let input: Vec<u8> = rmm::matrix::construct_randomized_matrix_seeded(rows, cols, 7);
+        let gradients: Gradients = compute_gradients(&input, rows, cols, &options);
+        let run: Run = Run { args: &args, options: &options, seed: Some(7), input: &input, rows, cols,
+                             fingerprint: fingerprint(&input, rows, cols).unwrap(), stages: &[] };
+
+        write_artifact(&dir, &run, &gradients).unwrap();
+
+        let manifest: Value = serde_json::from_str(&fs::read_to_string(dir.join("manifest.json")).unwrap()).unwrap();
+        let mut listed: Vec<String> = manifest["files"].as_array().unwrap().iter().map(|file| file.as_str().unwrap().to_string()).collect();
+        listed.sort();
+
+        assert_eq!(entries(&dir), listed);
+        // Only the renamed artifact is left next to it, no staging directory.
+        assert_eq!(entries(&parent), vec!["run".to_string()]);
+        assert_eq!(manifest["input"]["fingerprint"], format!("{:016x}", run.fingerprint));
+        assert_eq!(manifest["pipeline"].as_array().unwrap().len(), 2);
+
+        fs::remove_dir_all(&parent).unwrap();
+    }
+
+    #[test]
+    fn a_failed_write_leaves_no_staging_directory() {
+        let parent: PathBuf = temp_path("failed");
+        let _ = fs::remove_dir_all(&parent);
+        fs::create_dir_all(&parent).unwrap();
+        // A non-empty directory where the artifact should go makes the final
+        // rename fail.
+        let dir: PathBuf = parent.join("run");
+        fs::create_dir_all(dir.join("occupied")).unwrap();
+
+        let args: Vec<String> = vec!["matician-coding-challenge".to_string()];
+        let options: Options = run_options(&[]);
+        let input: Vec<u8> = vec![1, 2, 3, 4];
+        let gradients: Gradients = compute_gradients(&input, 2, 2, &options);
+        let run: Run = Run { args: &args, options: &options, seed: None, input: &input, rows: 2, cols: 2, fingerprint: 0, stages: &[] };
+
+        assert!(write_artifact(&dir, &run, &gradients).is_err());
+        assert_eq!(entries(&parent), vec!["run".to_string()]);
+        assert_eq!(entries(&dir), vec!["occupied".to_string()]);
+
+        fs::remove_dir_all(&parent).unwrap();
+    }
+}
@@ -0,0 +1,202 @@
+use std::ops::Range;
+use crate::error::{check_len, DimError};
+use crate::index::flat;
+use crate::matmul::Scalar;
+
+// A banded matrix: every entry (row, col) with col < row - bandwidth_lower or
+// col > row + bandwidth_upper is zero and not stored. Triangular matrices are
+// the special cases with one bandwidth 0 and the other cols - 1 (or rows - 1),
+// and a tridiagonal matrix has both bandwidths 1.
+//
+// data holds the band row by row, bandwidth_lower + bandwidth_upper + 1
+// entries per row: entry (row, col) lives at row * width + col + bandwidth_lower
+// - row. Slots of the band that fall outside the matrix, e.g. left of column
+// 0 in the first rows, are zero.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BandedMatrix<T> {
+    pub rows: usize,
+    pub cols: usize,
+    pub bandwidth_lower: usize,
+    pub bandwidth_upper: usize,
+    pub data: Vec<T>,
+}
+
+impl<T: Scalar> BandedMatrix<T> {
+    // Converts a dense row-major matrix, detecting the narrowest band that
+    // holds all of its non-zeros. A zero matrix has both bandwidths 0.
+    pub fn from_dense(dense: &[T], rows: usize, cols: usize) -> Result<BandedMatrix<T>, DimError> {
+        check_len(dense.len(), rows, cols)?;
+
+        let (mut lower, mut upper): (usize, usize) = (0, 0);
+
+        for row in 0..rows {
+            for col in 0..cols {
+                if dense[flat(row, col, cols)] != T::default() {
+                    lower = lower.max(row.saturating_sub(col));
+                    upper = upper.max(col.saturating_sub(row));
+                }
+            }
+        }
+
+        let mut banded: BandedMatrix<T> = BandedMatrix { rows, cols, bandwidth_lower: lower, bandwidth_upper: upper,
+                                                         data: vec![T::default(); rows * (lower + upper + 1)] };
+        let width: usize = banded.width();
+
+        for row in 0..rows {
+            for col in banded.band(row) {
+                banded.data[flat(row, col + lower - row, width)] = dense[flat(row, col, cols)];
+            }
+        }
+
+        return Ok(banded);
+    }
+
+    // Entries stored per row.
+    pub fn width(&self) -> usize {
+        return self.bandwidth_lower + self.bandwidth_upper + 1;
+    }
+
+    // Columns of row that lie inside the band and the matrix.
+    pub fn band(&self, row: usize) -> Range<usize> {
+        let start: usize = row.saturating_sub(self.bandwidth_lower).min(self.cols);
+        return start..(row + self.bandwidth_upper + 1).min(self.cols).max(start);
+    }
+
+    pub fn to_dense(&self) -> Vec<T> {
+        let width: usize = self.width();
+        let mut dense: Vec<T> = vec![T::default(); self.rows * self.cols];
+
+        for row in 0..self.rows {
+            for col in self.band(row) {
+                dense[row * self.cols + col] = self.data[row * width + col + self.bandwidth_lower - row];
+            }
+        }
+
+        return dense;
+    }
+
+    // The stored entries of row, in column order, for the columns band(row).
+    fn row_entries(&self, row: usize) -> &[T] {
+        let band: Range<usize> = self.band(row);
+        let start: usize = row * self.width() + band.start + self.bandwidth_lower - row;
+
+        return &self.data[start..start + band.len()];
+    }
+}
+
+// Banded a times a dense b_rows x b_cols matrix b, giving a dense rows x
+// b_cols matrix. Each stored entry scales one row of b, so the cost is
+// rows * width * b_cols rather than the rows * cols * b_cols of a dense
+// product.
+pub fn matmul_banded_dense<T: Scalar>(a: &BandedMatrix<T>, b: &[T], b_rows: usize, b_cols: usize) -> Result<Vec<T>, DimError> {
+    check_len(a.data.len(), a.rows, a.width())?;
+    check_len(b.len(), b_rows, b_cols)?;
+
+    if b_rows != a.cols {
+        return Err(DimError::Mismatch { what: "inner dimension", expected: a.cols, found: b_rows });
+    }
+
+    let mut c: Vec<T> = vec![T::default(); a.rows * b_cols];
+
+    for row in 0..a.rows {
+        let c_row: &mut [T] = &mut c[row * b_cols..(row + 1) * b_cols];
+
+        for (col, &value) in a.band(row).zip(a.row_entries(row)) {
+            for (out, &b_value) in c_row.iter_mut().zip(&b[col * b_cols..(col + 1) * b_cols]) {
+                *out += value * b_value;
+            }
+        }
+    }
+
+    return Ok(c);
+}
+
+// Banded a times the vector x.
+pub fn matvec_banded<T: Scalar>(a: &BandedMatrix<T>, x: &[T]) -> Result<Vec<T>, DimError> {
+    check_len(a.data.len(), a.rows, a.width())?;
+
+    if x.len() != a.cols {
+        return Err(DimError::Mismatch { what: "vector length", expected: a.cols, found: x.len() });
+    }
+
+    return Ok((0..a.rows).map(|row| {
+        let mut sum: T = T::default();
+
+        for (&value, &x_value) in a.row_entries(row).iter().zip(&x[a.band(row)]) {
+            sum += value * x_value;
+        }
+
+        sum
+    }).collect());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matmul::matmul;
+    use crate::matrix::construct_randomized_matrix_seeded;
+
+    // A dense n x n matrix whose entries are non-zero exactly where
+    // row - lower <= col <= row + upper.
+    fn band_dense(n: usize, lower: usize, upper: usize, seed: u64) -> Vec<i64> {
+        let bytes: Vec<u8> = construct_randomized_matrix_seeded(n, n, seed);
+
+        return (0..n * n).map(|index| {
+            let (row, col): (usize, usize) = (index / n, index % n);
+
+            if col + lower >= row && col <= row + upper { bytes[index] as i64 + 1 } else { 0 }
+        }).collect();
+    }
+
+    #[test]
+    fn banded_and_triangular_products_match_dense_matmul() {
+        for n in [1, 2, 5, 9] {
+            // A 1 x 1 matrix has no band wider than 0.
+            for bandwidth in [0, 1.min(n - 1), n - 1] {
+                // Symmetric band, then lower and upper triangles of that width.
+                for (lower, upper) in [(bandwidth, bandwidth), (bandwidth, 0), (0, bandwidth)] {
+                    let dense: Vec<i64> = band_dense(n, lower, upper, (n * 31 + lower * 7 + upper) as u64);
+                    let banded: BandedMatrix<i64> = BandedMatrix::from_dense(&dense, n, n).unwrap();
+
+                    assert_eq!((banded.bandwidth_lower, banded.bandwidth_upper), (lower, upper), "n {} band ({}, {})", n, lower, upper);
+                    assert_eq!(banded.to_dense(), dense);
+
+                    let b_cols: usize = 3;
+                    let b: Vec<i64> = construct_randomized_matrix_seeded(n, b_cols, n as u64).iter().map(|&v| v as i64 - 128).collect();
+
+                    assert_eq!(matmul_banded_dense(&banded, &b, n, b_cols).unwrap(), matmul(&dense, n, n, &b, n, b_cols).unwrap(),
+                               "n {} band ({}, {})", n, lower, upper);
+
+                    let x: Vec<i64> = b.iter().step_by(b_cols).copied().collect();
+
+                    assert_eq!(matvec_banded(&banded, &x).unwrap(), matmul(&dense, n, n, &x, n, 1).unwrap(),
+                               "n {} band ({}, {})", n, lower, upper);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rectangular_band_matches_dense_matmul() {
+        let (rows, cols): (usize, usize) = (4, 7);
+        let dense: Vec<i64> = (0..rows * cols).map(|index| {
+            let (row, col): (usize, usize) = (index / cols, index % cols);
+
+            if col + 1 >= row && col <= row + 2 { (index + 1) as i64 } else { 0 }
+        }).collect();
+        let banded: BandedMatrix<i64> = BandedMatrix::from_dense(&dense, rows, cols).unwrap();
+        let b: Vec<i64> = (0..cols * 2).map(|v| v as i64 - 5).collect();
+
+        assert_eq!(banded.to_dense(), dense);
+        assert_eq!(matmul_banded_dense(&banded, &b, cols, 2).unwrap(), matmul(&dense, rows, cols, &b, cols, 2).unwrap());
+    }
+
+    #[test]
+    fn mismatched_operands_are_errors() {
+        let banded: BandedMatrix<i64> = BandedMatrix::from_dense(&[1, 2, 3, 4], 2, 2).unwrap();
+
+        assert_eq!(matmul_banded_dense(&banded, &[0; 6], 3, 2), Err(DimError::Mismatch { what: "inner dimension", expected: 2, found: 3 }));
+        assert_eq!(matvec_banded(&banded, &[0; 3]), Err(DimError::Mismatch { what: "vector length", expected: 2, found: 3 }));
+        assert!(BandedMatrix::<i64>::from_dense(&[0; 5], 2, 2).is_err());
+    }
+}
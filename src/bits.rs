@@ -0,0 +1,214 @@
+use crate::error::{check_len, DimError};
+use crate::index::row_range;
+
+// Bits per storage word.
+const WORD_BITS: usize = 64;
+
+// A 0/1 matrix packed one bit per element, e.g. a mask or thresholded edge
+// map, using an eighth of the memory of a u8 matrix. Each row starts on a
+// word boundary: bit col % 64 of words[row * words_per_row + col / 64] is
+// element (row, col). Bits past the last column of a row are always 0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BitMatrix {
+    pub rows: usize,
+    pub cols: usize,
+    pub words_per_row: usize,
+    pub words: Vec<u64>,
+}
+
+impl BitMatrix {
+    // A rows x cols matrix of zeros.
+    pub fn new(rows: usize, cols: usize) -> BitMatrix {
+        let words_per_row: usize = cols.div_ceil(WORD_BITS);
+
+        return BitMatrix { rows, cols, words_per_row, words: vec![0; rows * words_per_row] };
+    }
+
+    // Packs a u8 matrix, treating every non-zero element as 1.
+    pub fn from_u8(arr: &[u8], rows: usize, cols: usize) -> Result<BitMatrix, DimError> {
+        check_len(arr.len(), rows, cols)?;
+
+        let mut matrix: BitMatrix = BitMatrix::new(rows, cols);
+
+        for row in 0..rows {
+            let out: &mut [u64] = &mut matrix.words[row * matrix.words_per_row..(row + 1) * matrix.words_per_row];
+
+            for (word, chunk) in out.iter_mut().zip(arr[row_range(row, cols)].chunks(WORD_BITS)) {
+                *word = chunk.iter().enumerate().fold(0, |word, (bit, &value)| word | ((value != 0) as u64) << bit);
+            }
+        }
+
+        return Ok(matrix);
+    }
+
+    // Unpacks into a row-major u8 matrix of 0s and 1s.
+    pub fn to_u8(&self) -> Vec<u8> {
+        let mut arr: Vec<u8> = Vec::with_capacity(self.rows * self.cols);
+
+        for row in 0..self.rows {
+            arr.extend((0..self.cols).map(|col| self.get(row, col) as u8));
+        }
+
+        return arr;
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> bool {
+        assert!(row < self.rows && col < self.cols, "({}, {}) outside the {}x{} matrix", row, col, self.rows, self.cols);
+
+        return self.words[row * self.words_per_row + col / WORD_BITS] >> (col % WORD_BITS) & 1 == 1;
+    }
+
+    pub fn set(&mut self, row: usize, col: usize, value: bool) {
+        assert!(row < self.rows && col < self.cols, "({}, {}) outside the {}x{} matrix", row, col, self.rows, self.cols);
+
+        let word: &mut u64 = &mut self.words[row * self.words_per_row + col / WORD_BITS];
+        let mask: u64 = 1 << (col % WORD_BITS);
+
+        if value {
+            *word |= mask;
+        } else {
+            *word &= !mask;
+        }
+    }
+
+    // Number of elements that are 1.
+    pub fn count_ones(&self) -> usize {
+        return self.words.iter().map(|word| word.count_ones() as usize).sum();
+    }
+
+    // The words of row, or None for a row of the padding around the matrix.
+    fn row_words(&self, row: isize) -> Option<&[u64]> {
+        if row < 0 || row as usize >= self.rows {
+            return None;
+        }
+
+        let start: usize = row as usize * self.words_per_row;
+        return Some(&self.words[start..start + self.words_per_row]);
+    }
+}
+
+// compute_dx for a 0/1 matrix, working on the packed words: the result is the
+// rows x (cols + 2) matrix compute_dx gives for matrix.to_u8(), with every
+// value in {-1, 0, 1}. Each output word is the row shifted by two columns
+// minus the row itself, so only words where the two differ are expanded.
+pub fn compute_dx_bits(matrix: &BitMatrix) -> Vec<i8> {
+    let new_cols: usize = matrix.cols + 2;
+    let mut dx: Vec<i8> = vec![0; matrix.rows * new_cols];
+
+    for row in 0..matrix.rows {
+        let words: &[u64] = matrix.row_words(row as isize).expect("row inside the matrix");
+        let word = |index: usize| words.get(index).copied().unwrap_or(0);
+        let out: &mut [i8] = &mut dx[row_range(row, new_cols)];
+
+        for index in 0..new_cols.div_ceil(WORD_BITS) {
+            // Bit j of shifted is element (row, j - 2).
+            let shifted: u64 = word(index) << 2 | if index > 0 { word(index - 1) >> (WORD_BITS - 2) } else { 0 };
+            expand_difference(out, index * WORD_BITS, shifted, word(index));
+        }
+    }
+
+    return dx;
+}
+
+// compute_dy for a 0/1 matrix, see compute_dx_bits. The result is the
+// (rows + 2) x cols matrix compute_dy gives for matrix.to_u8().
+pub fn compute_dy_bits(matrix: &BitMatrix) -> Vec<i8> {
+    let cols: usize = matrix.cols;
+    let mut dy: Vec<i8> = vec![0; (matrix.rows + 2) * cols];
+    let zeros: Vec<u64> = vec![0; matrix.words_per_row];
+
+    for row in 0..matrix.rows + 2 {
+        // Output row r is input row r - 2 minus input row r.
+        let above: &[u64] = matrix.row_words(row as isize - 2).unwrap_or(&zeros);
+        let below: &[u64] = matrix.row_words(row as isize).unwrap_or(&zeros);
+        let out: &mut [i8] = &mut dy[row_range(row, cols)];
+
+        for (index, (&a, &b)) in above.iter().zip(below).enumerate() {
+            expand_difference(out, index * WORD_BITS, a, b);
+        }
+    }
+
+    return dy;
+}
+
+// Writes a - b for the 64 bits of a and b into out[offset..], skipping bits
+// past the end of out. out must be zero where the difference is zero.
+fn expand_difference(out: &mut [i8], offset: usize, a: u64, b: u64) {
+    for (mut bits, value) in [(a & !b, 1), (b & !a, -1)] {
+        while bits != 0 {
+            let position: usize = offset + bits.trailing_zeros() as usize;
+
+            if position < out.len() {
+                out[position] = value;
+            }
+
+            bits &= bits - 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernels::{compute_dx, compute_dy};
+    use crate::matrix::construct_randomized_matrix_seeded;
+
+    // Widths on both sides of one and two word boundaries.
+    const WIDTHS: [usize; 5] = [1, 63, 64, 65, 129];
+
+    // A random 0/1 matrix.
+    fn mask(rows: usize, cols: usize, seed: u64) -> Vec<u8> {
+        return construct_randomized_matrix_seeded(rows, cols, seed).iter().map(|&value| value & 1).collect();
+    }
+
+    #[test]
+    fn packing_round_trips() {
+        for (seed, &cols) in WIDTHS.iter().enumerate() {
+            let arr: Vec<u8> = mask(5, cols, seed as u64);
+            let matrix: BitMatrix = BitMatrix::from_u8(&arr, 5, cols).unwrap();
+
+            assert_eq!(matrix.words_per_row, cols.div_ceil(64));
+            assert_eq!(matrix.to_u8(), arr, "width {}", cols);
+            assert_eq!(matrix.count_ones(), arr.iter().filter(|&&value| value == 1).count());
+        }
+
+        // Any non-zero value packs as 1.
+        assert_eq!(BitMatrix::from_u8(&[0, 7, 255], 1, 3).unwrap().to_u8(), vec![0, 1, 1]);
+        assert!(BitMatrix::from_u8(&[0; 5], 2, 3).is_err());
+    }
+
+    #[test]
+    fn packed_kernels_match_the_unpacked_ones() {
+        for (seed, &cols) in WIDTHS.iter().enumerate() {
+            for rows in [1, 2, 7] {
+                let arr: Vec<u8> = mask(rows, cols, (seed * 10 + rows) as u64);
+                let matrix: BitMatrix = BitMatrix::from_u8(&arr, rows, cols).unwrap();
+                let widen = |values: Vec<i8>| -> Vec<i16> { values.iter().map(|&value| value as i16).collect() };
+
+                assert_eq!(widen(compute_dx_bits(&matrix)), compute_dx(&arr, rows, cols).data, "Dx {}x{}", rows, cols);
+                assert_eq!(widen(compute_dy_bits(&matrix)), compute_dy(&arr, rows, cols).data, "Dy {}x{}", rows, cols);
+            }
+        }
+
+        // All ones, so every bit crosses a word boundary somewhere.
+        for &cols in &WIDTHS {
+            let arr: Vec<u8> = vec![1; 3 * cols];
+            let matrix: BitMatrix = BitMatrix::from_u8(&arr, 3, cols).unwrap();
+
+            assert_eq!(compute_dx_bits(&matrix).iter().map(|&value| value as i16).collect::<Vec<i16>>(), compute_dx(&arr, 3, cols).data);
+        }
+    }
+
+    #[test]
+    fn set_and_get_address_single_bits() {
+        let mut matrix: BitMatrix = BitMatrix::new(2, 129);
+        matrix.set(1, 64, true);
+        matrix.set(0, 128, true);
+        matrix.set(0, 128, false);
+        matrix.set(0, 63, true);
+
+        assert!(matrix.get(1, 64) && matrix.get(0, 63));
+        assert!(!matrix.get(0, 128) && !matrix.get(1, 63));
+        assert_eq!(matrix.count_ones(), 2);
+    }
+}
@@ -0,0 +1,113 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::OnceLock;
+use libloading::Library;
+use crate::error::{check_len, DimError};
+
+// CBLAS shared libraries tried in order when the backend is first used.
+const LIBRARY_NAMES: [&str; 7] = [
+    "libopenblas.so.0",
+    "libopenblas.so",
+    "libcblas.so.3",
+    "libcblas.so",
+    "libblas.so.3",
+    "libmkl_rt.so",
+    "/System/Library/Frameworks/Accelerate.framework/Accelerate",
+];
+
+// CblasRowMajor and CblasNoTrans from cblas.h.
+const CBLAS_ROW_MAJOR: i32 = 101;
+const CBLAS_NO_TRANS: i32 = 111;
+
+type SgemmFn = unsafe extern "C" fn(i32, i32, i32, i32, i32, i32, f32, *const f32, i32, *const f32, i32, f32, *mut f32, i32);
+
+// Error returned by the BLAS backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlasError {
+    Dim(DimError),
+    // No library exporting cblas_sgemm could be loaded.
+    Unavailable,
+    // A dimension does not fit the 32-bit integers CBLAS takes.
+    TooLarge(usize),
+}
+
+impl fmt::Display for BlasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            BlasError::Dim(err) => write!(f, "{}", err),
+            BlasError::Unavailable => write!(f, "no CBLAS library found (tried {})", LIBRARY_NAMES.join(", ")),
+            BlasError::TooLarge(dim) => write!(f, "dimension {} is too large for CBLAS", dim),
+        };
+    }
+}
+
+impl Error for BlasError {}
+
+impl From<DimError> for BlasError {
+    fn from(err: DimError) -> BlasError {
+        return BlasError::Dim(err);
+    }
+}
+
+// The loaded library and its sgemm, kept for the life of the process.
+struct Backend {
+    _library: Library,
+    sgemm: SgemmFn,
+}
+
+fn backend() -> Option<&'static Backend> {
+    static BACKEND: OnceLock<Option<Backend>> = OnceLock::new();
+
+    return BACKEND.get_or_init(|| {
+        for name in LIBRARY_NAMES {
+            // SAFETY: loading a BLAS library runs no initialisation code we
+            // depend on, and the symbol is looked up with its C signature.
+            unsafe {
+                let library: Library = match Library::new(name) {
+                    Ok(library) => library,
+                    Err(_) => continue,
+                };
+
+                let sgemm: SgemmFn = match library.get::<SgemmFn>(b"cblas_sgemm\0") {
+                    Ok(symbol) => *symbol,
+                    Err(_) => continue,
+                };
+
+                return Some(Backend { _library: library, sgemm });
+            }
+        }
+
+        return None;
+    }).as_ref();
+}
+
+// Whether a CBLAS library could be loaded on this machine.
+pub fn blas_available() -> bool {
+    return backend().is_some();
+}
+
+// Multiplies two f32 matrices with the system's cblas_sgemm.
+pub fn matmul_blas(a: &[f32], a_rows: usize, a_cols: usize, b: &[f32], b_rows: usize,
+                   b_cols: usize) -> Result<Vec<f32>, BlasError> {
+    check_len(a.len(), a_rows, a_cols)?;
+    check_len(b.len(), b_rows, b_cols)?;
+
+    if a_cols != b_rows {
+        return Err(BlasError::Dim(DimError::Mismatch { what: "inner dimension", expected: a_cols, found: b_rows }));
+    }
+
+    let backend: &Backend = backend().ok_or(BlasError::Unavailable)?;
+    let m: i32 = i32::try_from(a_rows).map_err(|_| BlasError::TooLarge(a_rows))?;
+    let k: i32 = i32::try_from(a_cols).map_err(|_| BlasError::TooLarge(a_cols))?;
+    let n: i32 = i32::try_from(b_cols).map_err(|_| BlasError::TooLarge(b_cols))?;
+    let mut c: Vec<f32> = vec![0.0; a_rows * b_cols];
+
+    // Leading dimensions must be at least 1 even for empty matrices.
+    // SAFETY: the buffer lengths were checked against the dimensions above.
+    unsafe {
+        (backend.sgemm)(CBLAS_ROW_MAJOR, CBLAS_NO_TRANS, CBLAS_NO_TRANS, m, n, k, 1.0, a.as_ptr(), k.max(1),
+                        b.as_ptr(), n.max(1), 0.0, c.as_mut_ptr(), n.max(1));
+    }
+
+    return Ok(c);
+}
@@ -0,0 +1,445 @@
+use std::process;
+use rmm::convert_dtype::OutputDType;
+use rmm::evict;
+use rmm::kernels::SECOND_DIFFERENCE;
+use rmm::matrix::Layout;
+use crate::cli::{Backend, Operator, Options};
+
+// Share of the system memory, in percent, a run may use unless --max-memory
+// is given. Only builds with the system-memory feature can detect it.
+const SYSTEM_MEMORY_PERCENT: u128 = 75;
+
+// Bytes a run may use when the system memory cannot be detected and no
+// --max-memory is given: low enough for most machines, so that a huge run is
+// refused rather than left to abort in the allocator.
+const FALLBACK_LIMIT: u128 = 4 << 30;
+
+// Exit status of a run refused because it would need more than the limit.
+pub(crate) const EXIT_OVER_BUDGET: i32 = 3;
+
+// One buffer a run will allocate.
+pub(crate) struct Buffer {
+    pub name: &'static str,
+    pub bytes: u128,
+}
+
+// The buffers a run will allocate, in the order it allocates them. Sizes are
+// exact for the buffers listed; small ones (histograms, timing samples, top-k
+// lists) are left out.
+pub(crate) struct MemoryEstimate {
+    pub buffers: Vec<Buffer>,
+}
+
+impl MemoryEstimate {
+    // Bytes of all buffers together. Some are dropped before later ones are
+    // allocated, so this is an upper bound on the peak.
+    pub fn total(&self) -> u128 {
+        return self.buffers.iter().map(|buffer| buffer.bytes).sum();
+    }
+
+    fn push(&mut self, name: &'static str, elements: u128, element_size: usize) {
+        self.buffers.push(Buffer { name, bytes: elements * element_size as u128 });
+    }
+}
+
+// The memory a u8 run of options needs on a rows x cols input, the shape
+// input::resolve gives before --rotate-input, --resize and --equalize. Only
+// the options are looked at, so the estimate can be made before anything is
+// allocated. The --dtype q8.8 and u4 runs are not covered.
+pub(crate) fn estimate(options: &Options, rows: usize, cols: usize) -> MemoryEstimate {
+    let mut estimate: MemoryEstimate = MemoryEstimate { buffers: Vec::new() };
+    let (mut rows, mut cols): (usize, usize) = (rows, cols);
+
+    estimate.push("input", area(rows, cols), 1);
+
+    // Each step copies the input into a new matrix.
+    if options.rotate_input > 0 {
+        if options.rotate_input % 180 == 90 {
+            (rows, cols) = (cols, rows);
+        }
+
+        estimate.push("rotated input", area(rows, cols), 1);
+    }
+
+    if let Some((new_rows, new_cols)) = options.resize {
+        (rows, cols) = (new_rows, new_cols);
+        estimate.push("resized input", area(rows, cols), 1);
+    }
+
+    if options.equalize {
+        estimate.push("equalized input", area(rows, cols), 1);
+    }
+
+    // The same order as run tries the modes in.
+    if options.compare_impls {
+        estimate.push("reference Dx and Dy", first_outputs(rows, cols, 2), 2);
+        estimate.push("Dx and Dy of an implementation", first_outputs(rows, cols, 2), 2);
+    } else if options.backend == Backend::Gpu {
+        estimate.push("Dx and Dy", first_outputs(rows, cols, 2), 2);
+    } else if let Some(kernels) = &options.kernels {
+        // The bank outputs are held while each kernel is rerun on its own, and
+        // every timed run produces its result while the previous one is still
+        // held (see gradient_buffers).
+        let row_outputs: u128 = kernels.iter().map(|kernel| area(rows, cols + kernel.len() - 1)).sum();
+        let col_outputs: u128 = kernels.iter().map(|kernel| area(rows + kernel.len() - 1, cols)).sum();
+
+        estimate.push("kernel bank outputs", row_outputs + col_outputs, 4);
+        estimate.push("next timed bank outputs", row_outputs.max(col_outputs), 4);
+        estimate.push("separate kernel outputs", 2 * row_outputs.max(col_outputs), 4);
+    } else if options.pipeline.is_some() {
+        // A stage reads one image and writes the next; the widest are Dx and Dy.
+        estimate.push("pipeline images", 4 * area(rows, cols), 2);
+    } else if options.scale_report > 0 {
+        // Levels are reported one at a time, so only level 0's results count.
+        estimate.push("pyramid levels", pyramid_area(rows, cols, options.scale_report), 1);
+        estimate.push("level Dx and Dy", first_outputs(rows, cols, 2), 2);
+        estimate.push("cropped level Dx and Dy", 2 * area(rows, cols), 2);
+        estimate.push("level magnitude", area(rows, cols), 2);
+    } else if options.operator == Operator::Directional {
+        estimate.push("uncropped Dx and Dy", first_outputs(rows, cols, 2), 2);
+        estimate.push("Dx, Dy, D45 and D135", 4 * area(rows, cols), 2);
+        estimate.push("max |D|", area(rows, cols), 2);
+        estimate.push("next timed result", area(rows, cols), 2);
+    } else if options.match_template.is_some() {
+        // Bounded by a 1x1 template, which has the most placements.
+        estimate.push("dot products", area(rows, cols), 8);
+        estimate.push("next timed dot products", area(rows, cols), 8);
+        estimate.push("values and squares", 2 * area(rows, cols), 8);
+        estimate.push("integral images", 2 * area(rows + 1, cols + 1), 8);
+        estimate.push("normalized correlation", 2 * area(rows, cols), 4);
+    } else if let Some(strip_rows) = options.time_strips {
+        // One strip at a time, as in process_tiles.
+        let strip_rows: usize = strip_rows.min(rows);
+        estimate.push("strip and halo", area(strip_rows + 2, cols + 2), 1);
+        estimate.push("strip Dx and Dy", 2 * area(strip_rows, cols), 2);
+    } else if options.pyramid > 0 {
+        estimate.push("pyramid levels", pyramid_area(rows, cols, options.pyramid), 1);
+        gradient_buffers(&mut estimate, options, rows, cols);
+    } else {
+        gradient_buffers(&mut estimate, options, rows, cols);
+    }
+
+    return estimate;
+}
+
+// The buffers of compute_gradients and write_outputs on a rows x cols input.
+fn gradient_buffers(estimate: &mut MemoryEstimate, options: &Options, rows: usize, cols: usize) {
+    let kernel_len: usize = match (options.operator, &options.kernel) {
+        (Operator::SecondDerivative, _) => SECOND_DIFFERENCE.len(),
+        (_, Some(kernel)) => kernel.len(),
+        (_, None) => 3,
+    };
+    let pad: usize = options.pad.unwrap_or(kernel_len - 1);
+    let out_rows: usize = options.rows_range.map_or(rows, |(start, end)| end - start);
+    let out_cols: usize = options.cols_range.map_or(cols, |(start, end)| end - start);
+    let (dx, dy): (u128, u128) = (area(out_rows, out_cols + pad), area(out_rows + pad, out_cols));
+    let (dx, dy): (u128, u128) = if options.crop_output { (area(out_rows, out_cols), area(out_rows, out_cols)) } else { (dx, dy) };
+    let largest: u128 = dx.max(dy);
+    let region: u128 = area(out_rows, out_cols);
+
+    if options.layout == Layout::ColMajor {
+        estimate.push("col-major input", area(rows, cols), 1);
+    }
+
+    // Streamed over between --cache-state cold runs.
+    if let Some(llc_size) = options.llc_size {
+        estimate.push("eviction buffer", evict::buffer_len(llc_size) as u128, 1);
+    }
+
+    estimate.push("Dx and Dy", first_outputs(out_rows, out_cols, pad), 2);
+
+    // A timed run produces its result while the previous one is still held.
+    estimate.push("next timed result", area(out_rows, out_cols + pad).max(area(out_rows + pad, out_cols)), 2);
+
+    if options.layout == Layout::ColMajor {
+        estimate.push("row-major Dx and Dy", first_outputs(out_rows, out_cols, pad), 2);
+    }
+
+    if options.crop_output {
+        estimate.push("cropped Dx and Dy", dx + dy, 2);
+    }
+
+    if options.verify {
+        estimate.push("reference Dx and Dy", first_outputs(out_rows, out_cols, pad), 4);
+    }
+
+    if options.magnitude_l1 || options.ascii {
+        estimate.push("L1 magnitude", region, 2);
+    }
+
+    if options.adaptive_threshold.is_some() {
+        estimate.push("threshold magnitude", region, 2);
+        estimate.push("integral image", area(out_rows + 1, out_cols + 1), 8);
+        estimate.push("threshold mask", region, 1);
+    }
+
+    if options.components.is_some() {
+        estimate.push("component labels", region, 4);
+    }
+
+    if let Some(rolling) = options.rolling {
+        let source: u128 = if rolling.input { area(rows, cols) } else { dx };
+        estimate.push("rolling source and result", 2 * source, 2);
+    }
+
+    if options.harris.is_some() {
+        // The products are smoothed one field at a time, each through its own
+        // integral image.
+        estimate.push("gradient products", region, 12);
+        estimate.push("smoothed field input", region, 4);
+        estimate.push("field integral image", area(out_rows + 1, out_cols + 1), 8);
+        estimate.push("structure tensor", 3 * region, 4);
+        estimate.push("Harris response", region, 4);
+    }
+
+    // Dx and Dy are written one after the other, each converted for the
+    // files (whether or not any are written) and first mapped through --lut.
+    if options.lut.is_some() {
+        estimate.push("--lut copy", largest, 2);
+    }
+
+    let element_size: usize = match options.output_dtype.unwrap_or_default() {
+        OutputDType::U8 => 1,
+        OutputDType::I16 => 2,
+        OutputDType::I32 | OutputDType::F32 => 4,
+    };
+    estimate.push("converted output", largest, element_size);
+
+    if options.output_pgm.is_some() || options.u8_output {
+        estimate.push("|output| as u8", largest, 1);
+    }
+
+    if options.output_heatmap.is_some() {
+        estimate.push("heatmap magnitude", 2 * region, 2);
+        estimate.push("heatmap pixels", largest.max(region), 3);
+    }
+}
+
+// Dx and Dy of a rows x cols input with pad columns and rows of padding.
+fn first_outputs(rows: usize, cols: usize, pad: usize) -> u128 {
+    return area(rows, cols + pad) + area(rows + pad, cols);
+}
+
+// Elements of the levels build_pyramid makes, level 0 being a copy.
+fn pyramid_area(rows: usize, cols: usize, levels: usize) -> u128 {
+    let (mut rows, mut cols): (usize, usize) = (rows, cols);
+    let mut total: u128 = area(rows, cols);
+
+    for _ in 1..levels {
+        if rows / 2 == 0 || cols / 2 == 0 {
+            break;
+        }
+
+        (rows, cols) = (rows / 2, cols / 2);
+        total += area(rows, cols);
+    }
+
+    return total;
+}
+
+fn area(rows: usize, cols: usize) -> u128 {
+    return rows as u128 * cols as u128;
+}
+
+// Bytes a run may use: --max-memory, else SYSTEM_MEMORY_PERCENT of the
+// system memory if it can be detected, else FALLBACK_LIMIT. Described for
+// messages.
+pub(crate) fn limit(options: &Options) -> (u128, String) {
+    if let Some(bytes) = options.max_memory {
+        return (bytes, "--max-memory".to_string());
+    }
+
+    return match system_memory() {
+        Some(system) => (system * SYSTEM_MEMORY_PERCENT / 100, format!("{}% of the {} of system memory", SYSTEM_MEMORY_PERCENT, format_bytes(system))),
+        None => (FALLBACK_LIMIT, "the default, as this build cannot detect the system memory".to_string()),
+    };
+}
+
+// Prints the estimate for a run of options on a rows x cols input and exits
+// with EXIT_OVER_BUDGET, saying what would be needed and what to do instead,
+// if it is over the limit.
+pub(crate) fn enforce(options: &Options, rows: usize, cols: usize) {
+    let estimate: MemoryEstimate = estimate(options, rows, cols);
+    let total: u128 = estimate.total();
+
+    let (limit, source): (u128, String) = limit(options);
+
+    println!("Memory estimate: {} (limit {}, {})", format_bytes(total), format_bytes(limit), source);
+
+    if total <= limit {
+        return;
+    }
+
+    let buffers: Vec<String> = estimate.buffers.iter().map(|buffer| format!("{} {}", buffer.name, format_bytes(buffer.bytes))).collect();
+
+    eprintln!("Refusing to run on {}x{}: it needs an estimated {} ({}), more than the {} limit ({}).", rows, cols, format_bytes(total),
+              buffers.join(", "), format_bytes(limit), source);
+    eprintln!("Raise --max-memory, or compute a band at a time with --rows-range START..END (or --cols-range) and --output-stream, \
+               which hold only the band's results.");
+    process::exit(EXIT_OVER_BUDGET);
+}
+
+// bytes in the largest binary unit that keeps it at 1 or more, e.g. 1.5 GiB.
+pub(crate) fn format_bytes(bytes: u128) -> String {
+    const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+    if bytes < 1024 {
+        return format!("{} bytes", bytes);
+    }
+
+    let mut value: f64 = bytes as f64 / 1024.0;
+    let mut unit: usize = 0;
+
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    return format!("{:.1} {}", value, UNITS[unit]);
+}
+
+// Physical memory of the machine.
+#[cfg(all(feature = "system-memory", unix))]
+fn system_memory() -> Option<u128> {
+    // SAFETY: sysconf only reads system configuration values.
+    let (pages, page_size) = unsafe { (libc::sysconf(libc::_SC_PHYS_PAGES), libc::sysconf(libc::_SC_PAGESIZE)) };
+
+    return (pages > 0 && page_size > 0).then(|| pages as u128 * page_size as u128);
+}
+
+#[cfg(not(all(feature = "system-memory", unix)))]
+fn system_memory() -> Option<u128> {
+    return None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli;
+
+    fn run_options(flags: &[&str]) -> Options {
+        return cli::parse_args(&["matician-coding-challenge"].iter().chain(flags).map(|flag| flag.to_string()).collect::<Vec<String>>());
+    }
+
+    fn names(estimate: &MemoryEstimate) -> Vec<&'static str> {
+        return estimate.buffers.iter().map(|buffer| buffer.name).collect();
+    }
+
+    fn bytes(estimate: &MemoryEstimate) -> Vec<u128> {
+        return estimate.buffers.iter().map(|buffer| buffer.bytes).collect();
+    }
+
+    #[test]
+    fn a_default_run_holds_the_input_both_outputs_and_one_more() {
+        for (rows, cols) in [(0, 0), (1, 1), (3, 7), (64, 5), (1000, 1000)] {
+            let estimate: MemoryEstimate = estimate(&run_options(&[]), rows, cols);
+            let (dx, dy): (u128, u128) = ((rows * (cols + 2)) as u128, ((rows + 2) * cols) as u128);
+
+            assert_eq!(names(&estimate), ["input", "Dx and Dy", "next timed result", "converted output"]);
+            assert_eq!(bytes(&estimate), [(rows * cols) as u128, 2 * (dx + dy), 2 * dx.max(dy), 2 * dx.max(dy)], "{}x{}", rows, cols);
+            assert_eq!(estimate.total(), bytes(&estimate).iter().sum::<u128>());
+        }
+    }
+
+    #[test]
+    fn estimates_depend_only_on_the_options_and_shape() {
+        let flags: [&str; 4] = ["--crop-output", "--verify", "--output-dtype", "f32"];
+        let first: Vec<u128> = bytes(&estimate(&run_options(&flags), 40, 30));
+
+        assert_eq!(bytes(&estimate(&run_options(&flags), 40, 30)), first);
+        assert_ne!(bytes(&estimate(&run_options(&flags), 40, 31)), first);
+        assert_ne!(bytes(&estimate(&run_options(&[]), 40, 30)), first);
+    }
+
+    #[test]
+    fn options_change_the_buffers_they_allocate() {
+        let (rows, cols): (usize, usize) = (6, 9);
+        let area: u128 = (rows * cols) as u128;
+
+        // Without padding Dx and Dy are shaped as the input.
+        assert_eq!(bytes(&estimate(&run_options(&["--pad", "0"]), rows, cols)), [area, 4 * area, 2 * area, 2 * area]);
+
+        // --crop-output adds the cropped pair and converts those instead.
+        let cropped: MemoryEstimate = estimate(&run_options(&["--crop-output", "--output-dtype", "i32"]), rows, cols);
+        assert_eq!(names(&cropped), ["input", "Dx and Dy", "next timed result", "cropped Dx and Dy", "converted output"]);
+        assert_eq!(bytes(&cropped)[3..], [4 * area, 4 * area]);
+
+        // A bank of two kernels, 3 and 2 taps: all four outputs in i32.
+        let bank: MemoryEstimate = estimate(&run_options(&["--kernels", "1,2,1;1,-1"]), rows, cols);
+        let row_outputs: u128 = (rows * (cols + 2) + rows * (cols + 1)) as u128;
+        let col_outputs: u128 = ((rows + 2) * cols + (rows + 1) * cols) as u128;
+        assert_eq!(bytes(&bank), [area, 4 * (row_outputs + col_outputs), 4 * row_outputs.max(col_outputs), 8 * row_outputs.max(col_outputs)]);
+    }
+
+    #[test]
+    fn input_steps_reshape_what_follows() {
+        // After a quarter turn or a resize, the run is that of the new shape.
+        let rotated: MemoryEstimate = estimate(&run_options(&["--rotate-input", "90"]), 3, 7);
+        let resized: MemoryEstimate = estimate(&run_options(&["--resize", "4x6", "--equalize"]), 100, 100);
+
+        assert_eq!(names(&rotated)[..2], ["input", "rotated input"]);
+        assert_eq!(bytes(&rotated)[1..], bytes(&estimate(&run_options(&[]), 7, 3))[..]);
+        assert_eq!(names(&resized)[..3], ["input", "resized input", "equalized input"]);
+        assert_eq!(bytes(&resized)[0], 10_000);
+        assert_eq!(bytes(&resized)[2..], bytes(&estimate(&run_options(&[]), 4, 6))[..]);
+
+        // A half turn keeps the shape.
+        assert_eq!(bytes(&estimate(&run_options(&["--rotate-input", "180"]), 3, 7))[1..], bytes(&estimate(&run_options(&[]), 3, 7))[..]);
+    }
+
+    #[test]
+    fn huge_inputs_are_over_the_default_limit() {
+        // Far beyond what could be allocated, without overflowing.
+        let huge: u128 = estimate(&run_options(&[]), 200_000, 200_000).total();
+
+        assert_eq!(huge, 360_003_200_000);
+        assert!(huge > FALLBACK_LIMIT);
+        assert!(estimate(&run_options(&[]), 1 << 32, 1 << 32).total() > 1 << 64);
+    }
+
+    #[test]
+    fn the_limit_is_max_memory_else_detected_else_the_fallback() {
+        assert_eq!(limit(&run_options(&["--max-memory", "3G"])), (3 << 30, "--max-memory".to_string()));
+
+        let (bytes, source): (u128, String) = limit(&run_options(&[]));
+
+        match system_memory() {
+            Some(system) => assert_eq!((bytes, source), (system * 3 / 4, format!("75% of the {} of system memory", format_bytes(system)))),
+            None => assert_eq!((bytes, source.as_str()), (FALLBACK_LIMIT, "the default, as this build cannot detect the system memory")),
+        }
+
+        #[cfg(not(all(feature = "system-memory", unix)))]
+        assert_eq!(limit(&run_options(&[])).0, FALLBACK_LIMIT);
+    }
+
+    #[test]
+    fn bytes_are_formatted_in_the_largest_unit() {
+        for (bytes, text) in [(0, "0 bytes"), (1023, "1023 bytes"), (1024, "1.0 KiB"), (1536, "1.5 KiB"), (4 << 30, "4.0 GiB"), (1 << 60, "1.0 EiB"), (1 << 70, "1024.0 EiB")] {
+            assert_eq!(format_bytes(bytes), text);
+        }
+    }
+
+    #[cfg(feature = "mem-stats")]
+    #[test]
+    fn estimates_cover_the_measured_peak() {
+        use rmm::matrix::construct_randomized_matrix_seeded;
+        use rmm::memstats;
+
+        // Large enough that the buffers the estimate leaves out do not matter.
+        let (rows, cols): (usize, usize) = (192, 256);
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, 1);
+
+        for flags in [&[][..], &["--crop-output", "--verify"], &["--harris"], &["--adaptive-threshold", "3:0", "--components", "4"]] {
+            let options: Options = run_options(flags);
+
+            // These runs stay on this thread, so its counters see all of them.
+            memstats::thread_reset();
+            let live: usize = memstats::thread_snapshot().current_bytes;
+            crate::compute_gradients(&arr, rows, cols, &options);
+            let peak: u128 = (memstats::thread_snapshot().peak_bytes - live + arr.len()) as u128;
+            let estimate: u128 = estimate(&options, rows, cols).total();
+
+            assert!(peak > 0 && peak <= estimate, "{:?} peaked at {} bytes, estimated at {}", flags, peak, estimate);
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
+use serde_json::{json, Value};
+use rmm::conv::ConvMode;
+use rmm::io::{read_matrix, write_bin, DType, StoredMatrix};
+use rmm::stats::{checksum_bytes, CHECKSUM_BASIS};
+use rmm::timing::TimingReport;
+use crate::artifact::timing_json;
+use crate::cli::Options;
+
+// Layout of a cache entry. Entries written with any other version are
+// recomputed and replaced.
+const CACHE_VERSION: u64 = 1;
+
+// One entry of a --cache-dir cache: the Dx/Dy results of one input under one
+// set of kernel parameters, in a directory named after the hash of both:
+//
+//     entry.json      cache version, the descriptor and both timing reports
+//     dx.bin, dy.bin  the results, in the binary matrix format
+//
+// Results are stored as the kernels return them, before any cropping, so
+// the entry serves runs with and without --crop-output alike.
+pub struct Entry {
+    dir: PathBuf,
+    // Everything the results depend on, e.g. "input 5f1c... 512x512
+    // kernel [-1, 0, 1] pad 2 ...". Stored in the entry and compared on
+    // load, so a hash collision is a miss rather than wrong results.
+    descriptor: String,
+}
+
+// Results loaded from an entry.
+pub struct Hit {
+    pub dx: Vec<i16>,
+    pub dy: Vec<i16>,
+    pub dx_timing: TimingReport,
+    pub dy_timing: TimingReport,
+}
+
+// What the cache did for a run, reported with the results.
+pub enum Outcome {
+    // The results were loaded from this entry.
+    Hit(PathBuf),
+    // The results were computed and stored in this entry.
+    Stored(PathBuf),
+    // The results were computed but could not be stored, for this reason.
+    Unstored(String),
+}
+
+impl Entry {
+    // The entry for a rows x cols input with the given fingerprint run with
+    // options. Only options that change the values of Dx and Dy are part of
+    // the key: the layout and threads give identical results, and cropping
+    // happens after the cache.
+    pub fn new(cache_dir: &Path, fingerprint: u64, rows: usize, cols: usize, options: &Options) -> Entry {
+        let mut descriptor: String = format!("input {:016x} {}x{} operator {:?} kernel {:?} pad {:?} arith {:?} rows {:?} cols {:?} border zero",
+                                             fingerprint, rows, cols, options.operator, options.kernel, options.pad, options.arith,
+                                             options.rows_range, options.cols_range);
+
+        // Added only when it changes the results, so existing convolution
+        // entries keep their keys.
+        if options.conv_mode == Some(ConvMode::CrossCorrelation) {
+            descriptor.push_str(" cross-correlation");
+        }
+
+        let key: u64 = checksum_bytes(CHECKSUM_BASIS, descriptor.as_bytes());
+
+        return Entry { dir: cache_dir.join(format!("{:016x}", key)), descriptor };
+    }
+
+    pub fn dir(&self) -> &Path {
+        return &self.dir;
+    }
+
+    // The stored results, if the entry exists and holds Dx and Dy of the
+    // expected (rows, cols) shapes. A damaged, foreign or outdated entry is
+    // reported on stderr and treated as a miss, so the run recomputes and
+    // replaces it.
+    pub fn load(&self, dx_shape: (usize, usize), dy_shape: (usize, usize)) -> Option<Hit> {
+        if !self.dir.exists() {
+            return None;
+        }
+
+        return match self.read(dx_shape, dy_shape) {
+            Ok(hit) => Some(hit),
+            Err(reason) => {
+                eprintln!("warning: ignoring cache entry {}: {}", self.dir.display(), reason);
+                None
+            }
+        };
+    }
+
+    fn read(&self, dx_shape: (usize, usize), dy_shape: (usize, usize)) -> Result<Hit, String> {
+        let text: String = fs::read_to_string(self.dir.join("entry.json")).map_err(|err| format!("entry.json: {}", err))?;
+        let entry: Value = serde_json::from_str(&text).map_err(|err| format!("entry.json: {}", err))?;
+        let version: Option<u64> = entry["version"].as_u64();
+
+        if version != Some(CACHE_VERSION) {
+            return Err(format!("written by cache version {}, expected {}", version.map_or("unknown".to_string(), |v| v.to_string()), CACHE_VERSION));
+        }
+
+        if entry["descriptor"].as_str() != Some(self.descriptor.as_str()) {
+            return Err("entry is for different parameters".to_string());
+        }
+
+        return Ok(Hit {
+            dx: read_result(&self.dir.join("dx.bin"), dx_shape)?,
+            dy: read_result(&self.dir.join("dy.bin"), dy_shape)?,
+            dx_timing: parse_timing(&entry["dx"]).ok_or("entry.json: invalid dx timing")?,
+            dy_timing: parse_timing(&entry["dy"]).ok_or("entry.json: invalid dy timing")?,
+        });
+    }
+
+    // Stores freshly computed results, replacing whatever the entry held.
+    // Written to a temporary sibling that is renamed into place, so an
+    // interrupted run never leaves a partial entry behind.
+    pub fn store(&self, dx: (&[i16], usize, usize), dy: (&[i16], usize, usize), dx_timing: &TimingReport,
+                 dy_timing: &TimingReport) -> Outcome {
+        let name: String = self.dir.file_name().map_or("entry".to_string(), |name| name.to_string_lossy().into_owned());
+        let staging: PathBuf = self.dir.with_file_name(format!(".{}.tmp-{}", name, process::id()));
+        let entry: Value = json!({
+            "version": CACHE_VERSION,
+            "descriptor": self.descriptor,
+            "dx": timing_json(dx_timing),
+            "dy": timing_json(dy_timing),
+        });
+
+        let written: std::io::Result<()> = fs::create_dir_all(&staging)
+            .and_then(|_| write_bin(&staging.join("dx.bin"), dx.0, dx.1, dx.2))
+            .and_then(|_| write_bin(&staging.join("dy.bin"), dy.0, dy.1, dy.2))
+            .and_then(|_| fs::write(staging.join("entry.json"), entry.to_string() + "\n"))
+            .and_then(|_| if self.dir.exists() { fs::remove_dir_all(&self.dir) } else { Ok(()) })
+            .and_then(|_| fs::rename(&staging, &self.dir));
+
+        return match written {
+            Ok(()) => Outcome::Stored(self.dir.clone()),
+            Err(err) => {
+                let _ = fs::remove_dir_all(&staging);
+                Outcome::Unstored(format!("{}: {}", self.dir.display(), err))
+            }
+        };
+    }
+}
+
+fn read_result(path: &Path, (rows, cols): (usize, usize)) -> Result<Vec<i16>, String> {
+    let name: String = path.file_name().map_or(String::new(), |name| name.to_string_lossy().into_owned());
+    let stored: StoredMatrix = read_matrix(path).map_err(|err| format!("{}: {}", name, err))?;
+
+    if stored.dtype != Some(DType::I16) || (stored.rows, stored.cols) != (rows, cols) {
+        return Err(format!("{} is a {}x{} {} matrix, expected {}x{} i16", name, stored.rows, stored.cols,
+                           stored.dtype.map_or("CSV".to_string(), |dtype| dtype.to_string()), rows, cols));
+    }
+
+    return Ok(stored.values.into_iter().map(|value| value as i16).collect());
+}
+
+// The inverse of timing_json.
+fn parse_timing(value: &Value) -> Option<TimingReport> {
+    let samples: Vec<Duration> = value["samples_ns"].as_array()?.iter().map(|sample| sample.as_u64().map(Duration::from_nanos))
+        .collect::<Option<Vec<Duration>>>()?;
+
+    return Some(TimingReport { samples, discarded: value["discarded"].as_u64()? as usize, steady: value["steady"].as_bool()? });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli;
+
+    fn temp_path(name: &str) -> PathBuf {
+        return std::env::temp_dir().join(format!("rmm-cache-{}-{}", std::process::id(), name));
+    }
+
+    fn run_options(flags: &[&str]) -> Options {
+        return cli::parse_args(&["matician-coding-challenge"].iter().chain(flags).map(|flag| flag.to_string()).collect::<Vec<String>>());
+    }
+
+    fn timing(nanos: &[u64], discarded: usize, steady: bool) -> TimingReport {
+        return TimingReport { samples: nanos.iter().map(|&ns| Duration::from_nanos(ns)).collect(), discarded, steady };
+    }
+
+    // (samples, discarded, steady), TimingReport having no PartialEq.
+    fn parts(report: &TimingReport) -> (Vec<Duration>, usize, bool) {
+        return (report.samples.clone(), report.discarded, report.steady);
+    }
+
+    // An entry for a 2x3 input, with Dx 2x5 and Dy 4x3 results stored.
+    fn stored(dir: &Path) -> Entry {
+        let entry: Entry = Entry::new(dir, 0xfeed, 2, 3, &run_options(&[]));
+        let dx: Vec<i16> = (0..10).map(|value| value - 5).collect();
+        let dy: Vec<i16> = (0..12).map(|value| value * 3).collect();
+
+        assert!(matches!(entry.store((&dx, 2, 5), (&dy, 4, 3), &timing(&[120, 80, 100], 2, true), &timing(&[7], 0, false)), Outcome::Stored(_)));
+
+        return entry;
+    }
+
+    #[test]
+    fn an_empty_cache_misses() {
+        let dir: PathBuf = temp_path("empty");
+        fs::create_dir_all(&dir).unwrap();
+
+        assert!(Entry::new(&dir, 1, 2, 3, &run_options(&[])).load((2, 5), (4, 3)).is_none());
+        // So does a cache directory that does not exist yet.
+        assert!(Entry::new(&dir.join("missing"), 1, 2, 3, &run_options(&[])).load((2, 5), (4, 3)).is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_stored_entry_hits_with_its_timings() {
+        let dir: PathBuf = temp_path("hit");
+        let entry: Entry = stored(&dir);
+        let hit: Hit = entry.load((2, 5), (4, 3)).expect("a hit");
+
+        // Only the entry itself, no staging directory, is left.
+        assert_eq!(fs::read_dir(&dir).unwrap().map(|item| item.unwrap().path()).collect::<Vec<PathBuf>>(), [entry.dir().to_path_buf()]);
+
+        assert_eq!(hit.dx, (0..10).map(|value| value - 5).collect::<Vec<i16>>());
+        assert_eq!(hit.dy, (0..12).map(|value| value * 3).collect::<Vec<i16>>());
+        assert_eq!(parts(&hit.dx_timing), parts(&timing(&[120, 80, 100], 2, true)));
+        assert_eq!(parts(&hit.dy_timing), parts(&timing(&[7], 0, false)));
+
+        // The same key for the same input and parameters; any change misses.
+        assert_eq!(Entry::new(&dir, 0xfeed, 2, 3, &run_options(&[])).dir(), entry.dir());
+        assert!(Entry::new(&dir, 0xfeee, 2, 3, &run_options(&[])).load((2, 5), (4, 3)).is_none());
+        assert!(Entry::new(&dir, 0xfeed, 2, 3, &run_options(&["--pad", "0"])).load((2, 5), (4, 3)).is_none());
+        // Other shapes than the ones stored are refused.
+        assert!(entry.load((2, 4), (4, 3)).is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_truncated_result_is_a_miss() {
+        let dir: PathBuf = temp_path("truncated");
+        let entry: Entry = stored(&dir);
+        let dx: PathBuf = entry.dir().join("dx.bin");
+        let bytes: Vec<u8> = fs::read(&dx).unwrap();
+        fs::write(&dx, &bytes[..bytes.len() - 3]).unwrap();
+
+        assert!(entry.load((2, 5), (4, 3)).is_none());
+
+        // Storing again replaces the damaged entry.
+        let entry: Entry = stored(&dir);
+        assert!(entry.load((2, 5), (4, 3)).is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn another_cache_version_is_a_miss() {
+        let dir: PathBuf = temp_path("version");
+        let entry: Entry = stored(&dir);
+        let path: PathBuf = entry.dir().join("entry.json");
+        let mut json: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        json["version"] = json!(2);
+        fs::write(&path, json.to_string()).unwrap();
+
+        assert!(entry.load((2, 5), (4, 3)).is_none());
+        assert_eq!(entry.read((2, 5), (4, 3)).err(), Some("written by cache version 2, expected 1".to_string()));
+
+        fs::write(&path, "{").unwrap();
+        assert!(entry.load((2, 5), (4, 3)).is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,21 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::error::CancelError;
+
+// Rows processed between two checks of a cancel token. A check is a single
+// relaxed atomic load, so even for narrow matrices the overhead is noise.
+pub const CANCEL_CHECK_ROWS: usize = 64;
+
+// Fails with CancelError::Cancelled if cancel is set. completed and total
+// describe how far the operation got, for the error message.
+pub(crate) fn check_cancel(cancel: &AtomicBool, completed: usize, total: usize) -> Result<(), CancelError> {
+    if cancel.load(Ordering::Relaxed) {
+        return Err(CancelError::Cancelled { completed, total });
+    }
+
+    return Ok(());
+}
+
+// Row ranges of CANCEL_CHECK_ROWS rows covering 0..rows.
+pub(crate) fn row_chunks(rows: usize) -> impl Iterator<Item = (usize, usize)> {
+    return (0..rows).step_by(CANCEL_CHECK_ROWS).map(move |r0| (r0, (r0 + CANCEL_CHECK_ROWS).min(rows)));
+}
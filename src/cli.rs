@@ -0,0 +1,1005 @@
+use std::time::Duration;
+use rmm::arith::ArithPolicy;
+use rmm::components::Connectivity;
+use rmm::conv::ConvMode;
+use rmm::convert_dtype::OutputDType;
+use rmm::evict::{detect_llc_size, CacheState, DEFAULT_LLC_SIZE};
+use rmm::matrix::Layout;
+use rmm::stages::{parse_pipeline, Stage};
+use rmm::stats::Accumulator;
+use rmm::timing::{parse_duration, Warmup};
+use crate::config;
+use crate::repro::{self, Repro};
+
+// Device the built-in Dx/Dy kernels run on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    #[default]
+    Cpu,
+    // wgpu compute shaders; needs the gpu feature and an adapter.
+    Gpu,
+}
+
+// Element type of the input matrix.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InputType {
+    #[default]
+    U8,
+    // Q8.8 fixed point, read as raw i16 values from --input.
+    Q8_8,
+    // 4-bit values packed two per byte, see rmm::nibble. Generated inputs are
+    // scaled to 0..=15; values read from --input must already fit.
+    U4,
+}
+
+// Derivative the built-in kernels compute.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Operator {
+    // Dx and Dy, the [-1, 0, 1] differences.
+    #[default]
+    FirstDerivative,
+    // Dxx and Dyy, the [1, -2, 1] second differences.
+    SecondDerivative,
+    // The differences along both axes and both diagonals and their largest
+    // absolute value, see rmm::kernels::max_directional_gradient. Replaces
+    // the Dx/Dy run.
+    Directional,
+}
+
+// A rolling extreme along the rows, from --rolling max:32 or min:8:input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rolling {
+    // The maximum of every window rather than its minimum.
+    pub max: bool,
+    pub window: usize,
+    // Applied to the input matrix instead of |Dx|.
+    pub input: bool,
+}
+
+// Parameters of the Harris corner detector, from --harris k=K radius=R.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HarrisParams {
+    // Sensitivity in det - k * trace^2.
+    pub k: f32,
+    // Radius of the box window the structure tensor is smoothed over.
+    pub radius: usize,
+}
+
+impl Default for HarrisParams {
+    fn default() -> HarrisParams {
+        return HarrisParams { k: 0.04, radius: 2 };
+    }
+}
+
+// Bounds the minimum and maximum of one output must stay within, from
+// --assert-min-max NAME:MIN:MAX.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RangeAssertion {
+    // "dx" or "dy".
+    pub output: &'static str,
+    pub min: i64,
+    pub max: i64,
+}
+
+// Command line options accepted by the binary.
+#[derive(Default)]
+pub struct Options {
+    // Dimensions given on the command line, if any; see input::resolve for
+    // how they combine with --input.
+    pub rows: Option<usize>,
+    pub cols: Option<usize>,
+    // Every size given with --size, run one after another; rows and cols
+    // hold the first.
+    pub sizes: Vec<(usize, usize)>,
+    // Seed for the generated input; a fresh random matrix when absent.
+    pub seed: Option<u64>,
+    // File the input is read from instead of being generated: Matrix Market
+    // for .mtx, otherwise the binary format or CSV.
+    pub input: Option<String>,
+    // How the input's values are interpreted.
+    pub dtype: InputType,
+    // Band of input rows start..end the kernels are restricted to; the
+    // results and their statistics then cover only the band.
+    pub rows_range: Option<(usize, usize)>,
+    // The same for a band of input columns; with --rows-range the kernels
+    // cover only the rectangle where the two bands cross.
+    pub cols_range: Option<(usize, usize)>,
+    // Remove the padding from Dx/Dy so both results are rows x cols.
+    pub crop_output: bool,
+    // Clockwise rotation applied to the generated input, in degrees.
+    pub rotate_input: usize,
+    // Number of pyramid levels to compute gradients on, 0 to disable.
+    pub pyramid: usize,
+    // Number of pyramid levels to summarize in a scale report instead of the
+    // normal Dx/Dy run, 0 to disable, and the file to also write it to as
+    // JSON.
+    pub scale_report: usize,
+    pub scale_json: Option<String>,
+    // Accumulator of every float statistic a run reports, currently those
+    // of the scale report and of the stats.json of --output-dir; f64 unless
+    // given. Runs without float statistics accept and ignore it.
+    pub accumulate: Option<Accumulator>,
+    // Dimensions the input is resized to before the kernels run.
+    pub resize: Option<(usize, usize)>,
+    // Equalize the histogram of the input before the kernels run.
+    pub equalize: bool,
+    // Also report the L1 gradient magnitude |Dx| + |Dy|.
+    pub magnitude_l1: bool,
+    // Percentiles of Dx/Dy to print instead of the default 1, 50 and 99.
+    pub percentiles: Option<Vec<f64>>,
+    // Print this many of the strongest Dx/Dy values with their locations.
+    pub top_k: Option<usize>,
+    // Print the number of sign changes along the rows and columns of Dx/Dy.
+    pub zero_crossings: bool,
+    // Cell size of the orientation histogram to compute, if any, and its
+    // number of bins.
+    pub hog: Option<usize>,
+    pub hog_bins: Option<usize>,
+    // Parameters of the Harris corners to report, if any; as many corners
+    // as --top-k asks for are listed.
+    pub harris: Option<HarrisParams>,
+    // Window radius and offset of the adaptive threshold applied to the L1
+    // gradient magnitude, whose result is written with Dx/Dy.
+    pub adaptive_threshold: Option<(usize, f64)>,
+    // Rolling maximum or minimum along the rows of |Dx| or the input, whose
+    // result is written with Dx/Dy.
+    pub rolling: Option<Rolling>,
+    // Connectivity of the components of the --adaptive-threshold mask to
+    // count and measure, if any.
+    pub components: Option<Connectivity>,
+    // Draw the L1 gradient magnitude and/or the input as ASCII art, at most
+    // ascii_width characters wide (the terminal width by default).
+    pub ascii: bool,
+    pub ascii_input: bool,
+    pub ascii_width: Option<usize>,
+    // Directories Dx/Dy are written to as CSV and PGM.
+    pub output_csv: Option<String>,
+    pub output_pgm: Option<String>,
+    // Directory to write Dx/Dy to in the binary matrix format.
+    pub output_bin: Option<String>,
+    // Directory to create a complete run artifact in, see artifact.rs.
+    pub output_dir: Option<String>,
+    // Directory Dx/Dy results are cached in, keyed by input and parameters,
+    // see cache.rs.
+    pub cache_dir: Option<String>,
+    // Directory to write color PPM heatmaps of Dx, Dy and the L1 magnitude to.
+    pub output_heatmap: Option<String>,
+    // Directory to write the --adaptive-threshold mask to as run-length
+    // encoded runs, in both the binary and the text RLE format.
+    pub output_rle: Option<String>,
+    // Directory to write Dx/Dy to row by row in the streaming format, see
+    // rmm::stream.
+    pub output_stream: Option<String>,
+    // Directory to write the value histograms of Dx/Dy to, see
+    // rmm::histogram, and their bin count.
+    pub save_hist: Option<String>,
+    pub hist_bins: Option<usize>,
+    // Write |Dx| and |Dy| saturated into u8 instead of the raw i16 values.
+    pub u8_output: bool,
+    // Element type Dx/Dy are converted to before the CSV, binary, stream and
+    // artifact writers see them, see rmm::convert_dtype.
+    pub output_dtype: Option<OutputDType>,
+    // Lookup table file that Dx/Dy are mapped through before they are
+    // written, see rmm::postprocess::Lut256.
+    pub lut: Option<String>,
+    // Whether the built-in kernels compute first or second derivatives.
+    pub operator: Operator,
+    // Matrix file (CSV or binary) of a template to find in the input, see
+    // rmm::template, instead of the normal Dx/Dy run.
+    pub match_template: Option<String>,
+    // Rows per strip to time the built-in kernels strip by strip in, see
+    // rmm::tiles::time_strips, instead of the normal Dx/Dy run.
+    pub time_strips: Option<usize>,
+    // User supplied 1D kernel used instead of [-1, 0, 1] for both Dx and Dy.
+    pub kernel: Option<Vec<i32>>,
+    // Kernels applied together in a single pass over the input, as a kernel
+    // bank, instead of the normal Dx/Dy run.
+    pub kernels: Option<Vec<Vec<i32>>>,
+    // Stages applied to the input in order instead of the normal Dx/Dy run.
+    pub pipeline: Option<Vec<Stage>>,
+    // Overflow handling for the generic convolution path.
+    pub arith: Option<ArithPolicy>,
+    // Whether the generic path flips the kernel (convolution, the default and
+    // what the built-in kernels do) or not.
+    pub conv_mode: Option<ConvMode>,
+    // Padding of the generic convolution output, instead of
+    // kernel.len() - 1 elements along the convolved axis.
+    pub pad: Option<usize>,
+    // Recompute Dx/Dy exactly after the run and report every element the
+    // kernels wrapped, listing at most check_overflow_limit of them.
+    pub check_overflow: bool,
+    pub check_overflow_limit: Option<usize>,
+    // Storage layout the input is converted to before the kernels run.
+    pub layout: Layout,
+    // Panel width for compute_dy, overriding the automatic choice.
+    pub dy_block_cols: Option<usize>,
+    // Time every available kernel implementation instead of a normal run.
+    pub compare_impls: bool,
+    // Break the Dx/Dy durations down into allocation, border and interior.
+    pub profile_phases: bool,
+    // Compare Dx/Dy with a scalar i32 reference and report error metrics.
+    pub verify: bool,
+    // Untimed runs before each kernel is timed, a count or adaptive.
+    pub warmup: Option<Warmup>,
+    // Upper bound on the time adaptive warm-up may take per kernel.
+    pub max_bench_time: Option<Duration>,
+    // Abort the run if it takes longer than this.
+    pub timeout: Option<Duration>,
+    // Refuse runs estimated to need more bytes than this, see budget.
+    pub max_memory: Option<u128>,
+    // Cache states the Dx/Dy kernels are timed in, the first for the main
+    // timings; empty for the usual runs, which leave the caches alone.
+    pub cache_states: Vec<CacheState>,
+    // Last-level cache size the cold runs evict, resolved at parsing from
+    // --llc-size, the detected size or DEFAULT_LLC_SIZE.
+    pub llc_size: Option<usize>,
+    // File to record the run in for --repro, see repro.
+    pub emit_repro: Option<String>,
+    // The recorded run this one replays, with --repro.
+    pub replay: Option<Repro>,
+    // Where the built-in kernels run.
+    pub backend: Backend,
+    // Threads the built-in kernels are split across.
+    pub threads: Option<usize>,
+    // Pin those threads to cores spread over the NUMA nodes, and generate the
+    // input on them, see rmm::numa. Has no effect on a single node.
+    pub pin_threads: bool,
+    // Checks on the results; a run that violates one exits with a failure
+    // status. The checksum covers Dx followed by Dy.
+    pub assert_min_max: Vec<RangeAssertion>,
+    pub assert_checksum: Option<u64>,
+}
+
+// Flags that take no value.
+pub const SWITCHES: [&str; 12] = [
+    "--crop-output", "--magnitude-l1", "--u8-output", "--compare-impls", "--ascii", "--ascii-input", "--zero-crossings",
+    "--check-overflow", "--profile-phases", "--verify", "--equalize", "--pin-threads",
+];
+
+pub fn parse_args(args: &[String]) -> Options {
+    let mut options: Options = Options::default();
+    let mut positional: Vec<&str> = Vec::new();
+
+    // The config file is applied first so that explicit flags override it,
+    // wherever --config appears on the command line.
+    if let Some(index) = args.iter().position(|arg| arg == "--config") {
+        let path: &str = args.get(index + 1).map(|path| path.trim()).unwrap_or_else(|| panic!("--config requires a value"));
+        config::apply_config(&mut options, path);
+    }
+
+    // A replay runs the recorded command line instead of this one.
+    if let Some(index) = args.iter().position(|arg| arg == "--repro") {
+        let path: &str = args.get(index + 1).map(|path| path.trim()).unwrap_or_else(|| panic!("--repro requires a value"));
+        let force: bool = args.iter().any(|arg| arg == "--force");
+
+        if args.len() != 3 + force as usize {
+            panic!("--repro replays the recorded run and takes no other flag than --force");
+        }
+
+        return repro::replay_options(repro::read(path, force).unwrap_or_else(|err| panic!("{}", err)));
+    }
+
+    let mut iter = args[1..].iter().peekable();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--config" => {
+                flag_value(&mut iter, arg);
+            }
+            // Several NAME:MIN:MAX values may follow as separate arguments.
+            "--assert-min-max" => {
+                let mut specs: Vec<&str> = vec![flag_value(&mut iter, arg)];
+
+                while let Some(spec) = iter.next_if(|next| ["dx:", "dy:"].iter().any(|name| next.trim().starts_with(name))) {
+                    specs.push(spec.trim());
+                }
+
+                apply_value(&mut options, arg, &specs.join(","));
+            }
+            // Its k=K and radius=R parameters are optional separate arguments.
+            "--harris" => {
+                let mut params: Vec<&str> = Vec::new();
+
+                while let Some(param) = iter.next_if(|next| ["k=", "radius="].iter().any(|name| next.trim().starts_with(name))) {
+                    params.push(param.trim());
+                }
+
+                apply_value(&mut options, arg, &params.join(","));
+            }
+            flag if is_switch(flag) => {
+                apply_switch(&mut options, flag, true);
+            }
+            flag if flag.starts_with("--") => {
+                let value: &str = flag_value(&mut iter, arg);
+
+                if !apply_value(&mut options, flag, value) {
+                    panic!("Unknown flag {}", flag);
+                }
+            }
+            value => positional.push(value),
+        }
+    }
+
+    if !options.sizes.is_empty() && !positional.is_empty() {
+        panic!("--size cannot be combined with positional rows and columns");
+    }
+
+    // At most one of them matches the file.
+    if options.sizes.len() > 1 && options.input.is_some() {
+        panic!("Several --size values cannot be combined with --input");
+    }
+
+    if positional.len() >= 2 {
+        options.rows = Some(positional[0].trim().parse().expect("Invalid rows argument"));
+        options.cols = Some(positional[1].trim().parse().expect("Invalid cols argument"));
+    } else if let Some(&(rows, cols)) = options.sizes.first() {
+        (options.rows, options.cols) = (Some(rows), Some(cols));
+    }
+
+    // Each run would overwrite the previous one's files.
+    if options.sizes.len() > 1 && (options.output_csv.is_some() || options.output_pgm.is_some() || options.output_bin.is_some()
+        || options.output_dir.is_some() || options.output_heatmap.is_some() || options.output_rle.is_some()
+        || options.output_stream.is_some() || options.save_hist.is_some() || options.scale_json.is_some()) {
+        panic!("Several --size values cannot be combined with output files");
+    }
+
+    if options.layout == Layout::ColMajor && (options.kernel.is_some() || options.arith.is_some() || options.pad.is_some() || options.conv_mode.is_some()) {
+        panic!("--layout col is only supported with the built-in kernel");
+    }
+
+    if options.output_dir.is_some() && (options.kernels.is_some() || options.pyramid > 0 || options.compare_impls) {
+        panic!("--output-dir is only supported for a normal Dx/Dy run");
+    }
+
+    if options.kernels.is_some() && (options.kernel.is_some() || options.arith.is_some() || options.pad.is_some() || options.conv_mode.is_some() || options.layout == Layout::ColMajor) {
+        panic!("--kernels cannot be combined with --kernel, --arith, --pad, --conv-mode or --layout col");
+    }
+
+    if options.backend == Backend::Gpu && (options.kernel.is_some() || options.arith.is_some() || options.pad.is_some() || options.conv_mode.is_some() || options.kernels.is_some()
+        || options.layout == Layout::ColMajor) {
+        panic!("--backend gpu is only supported with the built-in row-major kernel");
+    }
+
+    if options.dtype == InputType::Q8_8 && (options.input.is_none() || options.kernels.is_some() || options.layout == Layout::ColMajor
+        || options.backend == Backend::Gpu || options.threads.is_some() || options.output_dir.is_some() || options.pyramid > 0
+        || options.compare_impls || options.equalize || options.output_dtype.is_some() || options.save_hist.is_some()) {
+        panic!("--dtype q8.8 requires --input and supports only --kernel, --arith, --pad, --crop-output, --check-overflow and --output-csv");
+    }
+
+    // verify-run recomputes the artifact results, which it could not match.
+    if options.lut.is_some() && (options.output_dir.is_some() || options.dtype == InputType::Q8_8) {
+        panic!("--lut cannot be combined with --output-dir or --dtype q8.8");
+    }
+
+    if options.hist_bins.is_some() && options.save_hist.is_none() {
+        panic!("--hist-bins sets the bins of --save-hist and requires it");
+    }
+
+    // --u8-output is --output-dtype u8 for the CSV files alone.
+    if options.output_dtype.is_some() && options.u8_output {
+        panic!("--output-dtype cannot be combined with --u8-output");
+    }
+
+    // Findings are positions in the full convolution.
+    if options.check_overflow && options.pad.is_some() {
+        panic!("--check-overflow cannot be combined with --pad");
+    }
+
+    if options.check_overflow && (options.kernels.is_some() || options.compare_impls || options.backend == Backend::Gpu) {
+        panic!("--check-overflow is only supported for a normal Dx/Dy run");
+    }
+
+    let asserting: bool = !options.assert_min_max.is_empty() || options.assert_checksum.is_some();
+
+    if asserting && (options.kernels.is_some() || options.compare_impls || options.pyramid > 0 || options.backend == Backend::Gpu
+        || options.dtype == InputType::Q8_8) {
+        panic!("--assert-min-max and --assert-checksum are only supported for a normal Dx/Dy run");
+    }
+
+    if options.assert_checksum.is_some() && options.sizes.len() > 1 {
+        panic!("--assert-checksum cannot be combined with several --size values");
+    }
+
+    // The GPU backend and --compare-impls always check their results.
+    if options.verify && (options.kernels.is_some() || options.dtype == InputType::Q8_8) {
+        panic!("--verify is only supported for a normal Dx/Dy run");
+    }
+
+    if options.threads.is_some() && (options.kernel.is_some() || options.arith.is_some() || options.pad.is_some() || options.conv_mode.is_some() || options.kernels.is_some()
+        || options.layout == Layout::ColMajor || options.dy_block_cols.is_some() || options.backend == Backend::Gpu) {
+        panic!("--threads is only supported with the built-in row-major CPU kernel");
+    }
+
+    if options.pin_threads && options.threads.is_none() {
+        panic!("--pin-threads pins the --threads workers and requires it");
+    }
+
+    if options.profile_phases && (options.kernel.is_some() || options.arith.is_some() || options.pad.is_some() || options.conv_mode.is_some() || options.kernels.is_some()
+        || options.layout == Layout::ColMajor || options.backend == Backend::Gpu || options.dtype == InputType::Q8_8) {
+        panic!("--profile-phases is only supported with the built-in row-major CPU kernel");
+    }
+
+    if options.operator == Operator::SecondDerivative && (options.kernel.is_some() || options.arith.is_some() || options.pad.is_some() || options.conv_mode.is_some() || options.kernels.is_some()
+        || options.layout == Layout::ColMajor || options.dy_block_cols.is_some() || options.threads.is_some()
+        || options.backend == Backend::Gpu || options.compare_impls || options.profile_phases || options.dtype == InputType::Q8_8
+        || options.output_dir.is_some() || options.hog.is_some()) {
+        panic!("--operator second-derivative is only supported with the built-in row-major CPU kernel and cannot be combined with --hog or --output-dir");
+    }
+
+    if options.adaptive_threshold.is_some() && (options.kernels.is_some() || options.compare_impls || options.backend == Backend::Gpu
+        || options.dtype == InputType::Q8_8) {
+        panic!("--adaptive-threshold is only supported for a normal Dx/Dy run");
+    }
+
+    if options.output_rle.is_some() && options.adaptive_threshold.is_none() {
+        panic!("--output-rle writes the --adaptive-threshold mask and requires it");
+    }
+
+    if options.components.is_some() && options.adaptive_threshold.is_none() {
+        panic!("--components labels the --adaptive-threshold mask and requires it");
+    }
+
+    if options.rolling.is_some() && (options.kernels.is_some() || options.compare_impls || options.backend == Backend::Gpu
+        || options.dtype == InputType::Q8_8) {
+        panic!("--rolling is only supported for a normal Dx/Dy run");
+    }
+
+    if options.harris.is_some() && (options.kernels.is_some() || options.compare_impls || options.backend == Backend::Gpu
+        || options.dtype == InputType::Q8_8 || options.operator != Operator::FirstDerivative) {
+        panic!("--harris is only supported for a normal Dx/Dy run");
+    }
+
+    if (options.rows_range.is_some() || options.cols_range.is_some()) && (options.kernel.is_some() || options.arith.is_some() || options.pad.is_some() || options.conv_mode.is_some()
+        || options.kernels.is_some() || options.layout == Layout::ColMajor || options.dy_block_cols.is_some() || options.threads.is_some()
+        || options.backend == Backend::Gpu || options.operator != Operator::FirstDerivative || options.compare_impls
+        || options.profile_phases || options.check_overflow || options.verify || options.pyramid > 0 || options.output_dir.is_some()
+        || options.dtype == InputType::Q8_8) {
+        panic!("--rows-range and --cols-range are only supported with the built-in row-major CPU kernel and its plain outputs");
+    }
+
+    if options.max_memory.is_some() && options.dtype != InputType::U8 {
+        panic!("--max-memory is only supported for u8 inputs");
+    }
+
+    if !options.cache_states.is_empty() && (options.kernels.is_some() || options.compare_impls || options.backend == Backend::Gpu
+        || options.dtype != InputType::U8 || options.operator == Operator::Directional || options.pipeline.is_some() || options.scale_report > 0) {
+        panic!("--cache-state is only supported for a normal Dx/Dy run");
+    }
+
+    // The recorded checksum is asserted on replay.
+    if options.emit_repro.is_some() && (options.kernels.is_some() || options.compare_impls || options.backend == Backend::Gpu
+        || options.dtype != InputType::U8 || options.operator == Operator::Directional || options.pipeline.is_some()
+        || options.scale_report > 0 || options.pyramid > 0 || options.sizes.len() > 1) {
+        panic!("--emit-repro is only supported for a normal Dx/Dy run of one size");
+    }
+
+    // A cache hit would report the timings of the run that stored it.
+    if !options.cache_states.is_empty() && options.cache_dir.is_some() {
+        panic!("--cache-state cannot be combined with --cache-dir");
+    }
+
+    if options.llc_size.is_some() && !options.cache_states.contains(&CacheState::Cold) {
+        panic!("--llc-size sizes the eviction of --cache-state cold (or both) and requires it");
+    }
+
+    if options.cache_states.contains(&CacheState::Cold) && options.llc_size.is_none() {
+        options.llc_size = Some(detect_llc_size().unwrap_or(DEFAULT_LLC_SIZE));
+    }
+
+    if options.scale_json.is_some() && options.scale_report == 0 {
+        panic!("--scale-json writes the --scale-report and requires it");
+    }
+
+    // The report runs the built-in kernel on every level and prints only its
+    // own table.
+    if options.scale_report > 0 && (options.kernel.is_some() || options.arith.is_some() || options.pad.is_some() || options.conv_mode.is_some() || options.kernels.is_some()
+        || options.layout == Layout::ColMajor || options.dy_block_cols.is_some() || options.threads.is_some() || options.backend == Backend::Gpu
+        || options.operator != Operator::FirstDerivative || options.rows_range.is_some() || options.cols_range.is_some()
+        || options.dtype == InputType::Q8_8 || options.pyramid > 0 || options.compare_impls || options.profile_phases || options.verify
+        || options.check_overflow || options.crop_output || options.magnitude_l1 || options.u8_output || options.output_dtype.is_some() || options.zero_crossings
+        || options.percentiles.is_some() || options.top_k.is_some() || options.hog.is_some() || options.harris.is_some()
+        || options.adaptive_threshold.is_some() || options.rolling.is_some() || !options.assert_min_max.is_empty()
+        || options.assert_checksum.is_some() || options.output_csv.is_some() || options.output_pgm.is_some() || options.output_bin.is_some()
+        || options.output_dir.is_some() || options.output_heatmap.is_some() || options.output_rle.is_some() || options.output_stream.is_some()
+        || options.save_hist.is_some() || options.cache_dir.is_some() || options.lut.is_some() || options.ascii || options.ascii_input) {
+        panic!("--scale-report replaces the Dx/Dy run and only supports the input options, --scale-json and --accumulate");
+    }
+
+    // The stages replace the Dx/Dy run, so none of its settings apply.
+    let configures_run: bool = options.kernel.is_some() || options.arith.is_some() || options.pad.is_some() || options.conv_mode.is_some() || options.kernels.is_some()
+        || options.layout == Layout::ColMajor || options.dy_block_cols.is_some() || options.threads.is_some() || options.backend == Backend::Gpu
+        || options.operator != Operator::FirstDerivative || options.rows_range.is_some() || options.cols_range.is_some()
+        || options.dtype == InputType::Q8_8 || options.pyramid > 0 || options.compare_impls || options.profile_phases || options.verify
+        || options.check_overflow || options.crop_output || options.magnitude_l1 || options.u8_output || options.output_dtype.is_some() || options.zero_crossings
+        || options.percentiles.is_some() || options.top_k.is_some() || options.hog.is_some() || options.harris.is_some()
+        || options.adaptive_threshold.is_some() || options.rolling.is_some() || !options.assert_min_max.is_empty()
+        || options.assert_checksum.is_some() || options.scale_report > 0 || options.dtype == InputType::U4;
+    let writes_outputs: bool = options.output_csv.is_some() || options.output_pgm.is_some() || options.output_bin.is_some()
+        || options.output_dir.is_some() || options.output_heatmap.is_some() || options.output_rle.is_some() || options.output_stream.is_some()
+        || options.save_hist.is_some();
+
+    // The packed kernels are the built-in ones, timed on their own.
+    if options.dtype == InputType::U4 && (options.kernel.is_some() || options.arith.is_some() || options.pad.is_some() || options.conv_mode.is_some()
+        || options.kernels.is_some() || options.layout == Layout::ColMajor || options.dy_block_cols.is_some() || options.threads.is_some()
+        || options.backend == Backend::Gpu || options.operator != Operator::FirstDerivative || options.rows_range.is_some()
+        || options.cols_range.is_some() || options.pyramid > 0 || options.compare_impls || options.profile_phases || options.verify
+        || options.check_overflow || options.crop_output || options.magnitude_l1 || options.u8_output || options.output_dtype.is_some()
+        || options.zero_crossings || options.percentiles.is_some() || options.top_k.is_some() || options.hog.is_some()
+        || options.harris.is_some() || options.adaptive_threshold.is_some() || options.rolling.is_some() || !options.assert_min_max.is_empty()
+        || options.assert_checksum.is_some() || options.scale_report > 0 || options.pipeline.is_some() || options.rotate_input > 0
+        || options.resize.is_some() || options.equalize || options.sizes.len() > 1 || options.ascii || options.ascii_input
+        || options.cache_dir.is_some() || options.output_pgm.is_some()
+        || options.output_bin.is_some() || options.output_dir.is_some() || options.output_heatmap.is_some() || options.output_rle.is_some()
+        || options.output_stream.is_some() || options.save_hist.is_some() || options.lut.is_some()) {
+        panic!("--dtype u4 supports only --seed, --input, the timing options and --output-csv");
+    }
+
+    // The four directions are timed and reported on their own; only their
+    // maximum is written.
+    if options.operator == Operator::Directional && (options.kernel.is_some() || options.arith.is_some() || options.pad.is_some() || options.conv_mode.is_some()
+        || options.kernels.is_some() || options.layout == Layout::ColMajor || options.dy_block_cols.is_some() || options.threads.is_some()
+        || options.backend == Backend::Gpu || options.rows_range.is_some() || options.cols_range.is_some() || options.dtype != InputType::U8
+        || options.pyramid > 0 || options.compare_impls || options.profile_phases || options.verify || options.check_overflow
+        || options.crop_output || options.magnitude_l1 || options.u8_output || options.output_dtype.is_some() || options.zero_crossings
+        || options.percentiles.is_some() || options.top_k.is_some() || options.hog.is_some() || options.harris.is_some()
+        || options.adaptive_threshold.is_some() || options.rolling.is_some() || !options.assert_min_max.is_empty()
+        || options.assert_checksum.is_some() || options.scale_report > 0 || options.pipeline.is_some() || options.output_bin.is_some()
+        || options.output_dir.is_some() || options.output_heatmap.is_some() || options.output_rle.is_some() || options.output_stream.is_some()
+        || options.save_hist.is_some() || options.cache_dir.is_some() || options.lut.is_some() || options.ascii) {
+        panic!("--operator directional replaces the Dx/Dy run and only supports the input options, timing options, --output-csv and --output-pgm");
+    }
+
+    if options.match_template.is_some() && (options.kernel.is_some() || options.arith.is_some() || options.pad.is_some() || options.conv_mode.is_some()
+        || options.kernels.is_some() || options.layout == Layout::ColMajor || options.dy_block_cols.is_some() || options.threads.is_some()
+        || options.backend == Backend::Gpu || options.operator != Operator::FirstDerivative || options.rows_range.is_some() || options.cols_range.is_some()
+        || options.dtype != InputType::U8 || options.pyramid > 0 || options.compare_impls || options.profile_phases || options.verify
+        || options.check_overflow || options.crop_output || options.magnitude_l1 || options.u8_output || options.output_dtype.is_some() || options.zero_crossings
+        || options.percentiles.is_some() || options.hog.is_some() || options.harris.is_some() || options.adaptive_threshold.is_some()
+        || options.rolling.is_some() || !options.assert_min_max.is_empty() || options.assert_checksum.is_some() || options.scale_report > 0
+        || options.pipeline.is_some() || options.output_pgm.is_some() || options.output_bin.is_some() || options.output_dir.is_some()
+        || options.output_heatmap.is_some() || options.output_rle.is_some() || options.output_stream.is_some() || options.save_hist.is_some()
+        || options.cache_dir.is_some() || options.lut.is_some() || options.ascii || !options.cache_states.is_empty() || options.emit_repro.is_some()) {
+        panic!("--match-template replaces the Dx/Dy run and only supports the input options, timing options, --top-k and --output-csv");
+    }
+
+    if options.time_strips.is_some() && (configures_run || options.dtype != InputType::U8 || options.pipeline.is_some()
+        || options.match_template.is_some() || options.output_pgm.is_some() || options.output_bin.is_some() || options.output_dir.is_some()
+        || options.output_rle.is_some() || options.output_stream.is_some() || options.save_hist.is_some() || options.cache_dir.is_some()
+        || options.lut.is_some() || options.ascii || !options.cache_states.is_empty() || options.emit_repro.is_some()) {
+        panic!("--time-strips replaces the Dx/Dy run and only supports the input options, timing options, --output-csv and --output-heatmap");
+    }
+
+    if options.pipeline.is_some() && (configures_run || writes_outputs) {
+        panic!("--pipeline replaces the Dx/Dy run and only supports the input options, timing options, --lut and --ascii");
+    }
+
+    if options.cache_dir.is_some() && (options.kernels.is_some() || options.compare_impls || options.backend == Backend::Gpu
+        || options.dtype == InputType::Q8_8 || options.pipeline.is_some()) {
+        panic!("--cache-dir is only supported for a normal Dx/Dy run");
+    }
+
+    return options;
+}
+
+pub fn is_switch(flag: &str) -> bool {
+    return SWITCHES.contains(&flag);
+}
+
+// Sets or clears the option behind a flag that takes no value.
+pub fn apply_switch(options: &mut Options, flag: &str, on: bool) {
+    match flag {
+        "--crop-output" => options.crop_output = on,
+        "--magnitude-l1" => options.magnitude_l1 = on,
+        "--u8-output" => options.u8_output = on,
+        "--compare-impls" => options.compare_impls = on,
+        "--ascii" => options.ascii = on,
+        "--ascii-input" => options.ascii_input = on,
+        "--zero-crossings" => options.zero_crossings = on,
+        "--check-overflow" => options.check_overflow = on,
+        "--profile-phases" => options.profile_phases = on,
+        "--verify" => options.verify = on,
+        "--equalize" => options.equalize = on,
+        "--pin-threads" => options.pin_threads = on,
+        _ => panic!("{} is not a switch", flag),
+    }
+}
+
+// Whether the switch flag is on in options.
+pub fn switch_enabled(options: &Options, flag: &str) -> bool {
+    return match flag {
+        "--crop-output" => options.crop_output,
+        "--magnitude-l1" => options.magnitude_l1,
+        "--u8-output" => options.u8_output,
+        "--compare-impls" => options.compare_impls,
+        "--ascii" => options.ascii,
+        "--ascii-input" => options.ascii_input,
+        "--zero-crossings" => options.zero_crossings,
+        "--check-overflow" => options.check_overflow,
+        "--profile-phases" => options.profile_phases,
+        "--verify" => options.verify,
+        "--equalize" => options.equalize,
+        "--pin-threads" => options.pin_threads,
+        _ => panic!("{} is not a switch", flag),
+    };
+}
+
+// Applies a flag that takes a value. Returns false if the flag is unknown.
+pub fn apply_value(options: &mut Options, flag: &str, value: &str) -> bool {
+    match flag {
+        "--rows" => options.rows = Some(value.parse().expect("Invalid rows argument")),
+        "--cols" => options.cols = Some(value.parse().expect("Invalid cols argument")),
+        "--size" => options.sizes = parse_size_spec(value).unwrap_or_else(|err| panic!("Invalid --size: {}", err)),
+        "--seed" => options.seed = Some(value.parse().expect("Invalid --seed argument")),
+        "--input" => options.input = Some(value.to_string()),
+        "--dtype" => {
+            options.dtype = match value {
+                "u8" => InputType::U8,
+                "q8.8" => InputType::Q8_8,
+                "u4" => InputType::U4,
+                _ => panic!("Unknown --dtype {}, expected u8, q8.8 or u4", value),
+            };
+        }
+        "--output-csv" => options.output_csv = Some(value.to_string()),
+        "--output-pgm" => options.output_pgm = Some(value.to_string()),
+        "--output-bin" => options.output_bin = Some(value.to_string()),
+        "--output-dir" => options.output_dir = Some(value.to_string()),
+        "--cache-dir" => options.cache_dir = Some(value.to_string()),
+        "--output-heatmap" => options.output_heatmap = Some(value.to_string()),
+        "--output-rle" => options.output_rle = Some(value.to_string()),
+        "--output-stream" => options.output_stream = Some(value.to_string()),
+        "--save-hist" => options.save_hist = Some(value.to_string()),
+        "--hist-bins" => options.hist_bins = Some(value.parse().ok().filter(|&bins| bins > 0).expect("Invalid --hist-bins argument")),
+        "--lut" => options.lut = Some(value.to_string()),
+        "--output-dtype" => {
+            options.output_dtype = Some(OutputDType::parse(value).unwrap_or_else(|| panic!("Unknown --output-dtype {}, expected i16, i32, f32 or u8", value)));
+        }
+        "--rotate-input" => {
+            let degrees: usize = value.parse().expect("Invalid --rotate-input argument");
+
+            if !degrees.is_multiple_of(90) {
+                panic!("--rotate-input must be a multiple of 90 degrees");
+            }
+
+            options.rotate_input = degrees % 360;
+        }
+        "--pyramid" => options.pyramid = value.parse().expect("Invalid --pyramid argument"),
+        "--scale-report" => options.scale_report = value.parse().expect("Invalid --scale-report argument"),
+        "--scale-json" => options.scale_json = Some(value.to_string()),
+        "--accumulate" => {
+            options.accumulate = Some(Accumulator::parse(value).unwrap_or_else(|| panic!("Unknown --accumulate {}, expected f64 or f32", value)));
+        }
+        "--ascii-width" => options.ascii_width = Some(value.parse().expect("Invalid --ascii-width argument")),
+        "--percentiles" => {
+            options.percentiles = Some(value.split(',').map(|p| p.trim().parse().expect("Invalid percentile")).collect());
+        }
+        "--top-k" => options.top_k = Some(value.parse().expect("Invalid --top-k argument")),
+        "--hog" => options.hog = Some(value.parse().expect("Invalid --hog argument")),
+        "--harris" => options.harris = Some(parse_harris(value)),
+        "--adaptive-threshold" => {
+            let fail = || -> ! { panic!("Invalid --adaptive-threshold {}, expected RADIUS:OFFSET", value) };
+            options.adaptive_threshold = match value.split_once(':') {
+                Some((radius, offset)) => Some((radius.trim().parse().unwrap_or_else(|_| fail()), offset.trim().parse().unwrap_or_else(|_| fail()))),
+                None => fail(),
+            };
+        }
+        "--components" => {
+            options.components = match value {
+                "4" => Some(Connectivity::Four),
+                "8" => Some(Connectivity::Eight),
+                _ => panic!("Invalid --components {}, expected 4 or 8", value),
+            };
+        }
+        "--rolling" => options.rolling = Some(parse_rolling(value)),
+        "--hog-bins" => options.hog_bins = Some(value.parse().expect("Invalid --hog-bins argument")),
+        "--resize" => options.resize = Some(parse_dims(value)),
+        "--operator" => {
+            options.operator = match value {
+                "first-derivative" => Operator::FirstDerivative,
+                "second-derivative" => Operator::SecondDerivative,
+                "directional" => Operator::Directional,
+                _ => panic!("Unknown --operator {}, expected first-derivative, second-derivative or directional", value),
+            };
+        }
+        "--kernel" => options.kernel = Some(parse_kernel(value)),
+        "--kernels" => options.kernels = Some(value.split(';').map(parse_kernel).collect()),
+        "--pipeline" => options.pipeline = Some(parse_pipeline(value).unwrap_or_else(|err| panic!("Invalid --pipeline \"{}\": {}", value, err))),
+        "--conv-mode" => {
+            options.conv_mode = Some(ConvMode::parse(value).unwrap_or_else(|| panic!("Unknown --conv-mode {}, expected convolution or cross-correlation", value)));
+        }
+        "--arith" => {
+            options.arith = Some(ArithPolicy::parse(value).unwrap_or_else(|| panic!("Unknown --arith policy {}", value)));
+        }
+        "--rows-range" => options.rows_range = Some(parse_range(value).unwrap_or_else(|| panic!("Invalid --rows-range {}, expected START..END", value))),
+        "--cols-range" => options.cols_range = Some(parse_range(value).unwrap_or_else(|| panic!("Invalid --cols-range {}, expected START..END", value))),
+        "--pad" => options.pad = Some(value.parse().expect("Invalid --pad argument")),
+        "--check-overflow-limit" => {
+            options.check_overflow_limit = Some(value.parse().expect("Invalid --check-overflow-limit argument"));
+        }
+        "--backend" => {
+            options.backend = match value {
+                "cpu" => Backend::Cpu,
+                "gpu" => Backend::Gpu,
+                _ => panic!("Unknown --backend {}, expected cpu or gpu", value),
+            };
+        }
+        "--layout" => {
+            options.layout = match value {
+                "row" => Layout::RowMajor,
+                "col" => Layout::ColMajor,
+                _ => panic!("Unknown --layout {}, expected row or col", value),
+            };
+        }
+        "--dy-block-cols" => options.dy_block_cols = Some(value.parse().expect("Invalid --dy-block-cols argument")),
+        "--warmup" => options.warmup = Some(Warmup::parse(value).unwrap_or_else(|| panic!("Invalid --warmup {}, expected a count or auto", value))),
+        "--max-bench-time" => {
+            options.max_bench_time = Some(parse_duration(value).unwrap_or_else(|| panic!("Invalid --max-bench-time {}", value)));
+        }
+        "--threads" => options.threads = Some(parse_threads(value)),
+        "--assert-min-max" => {
+            // A later bound for the same output replaces the earlier one, so
+            // the command line overrides the config file.
+            for assertion in value.split(',').map(parse_range_assertion) {
+                options.assert_min_max.retain(|existing| existing.output != assertion.output);
+                options.assert_min_max.push(assertion);
+            }
+        }
+        "--assert-checksum" => {
+            let hex: &str = value.strip_prefix("0x").unwrap_or(value);
+            options.assert_checksum = Some(u64::from_str_radix(hex, 16).unwrap_or_else(|_| panic!("Invalid --assert-checksum {}, expected a hex checksum", value)));
+        }
+        "--timeout" => options.timeout = Some(parse_duration(value).unwrap_or_else(|| panic!("Invalid --timeout {}", value))),
+        "--max-memory" => options.max_memory = Some(parse_bytes(value).unwrap_or_else(|| panic!("Invalid --max-memory {}, expected bytes such as 512M or 8G", value))),
+        "--match-template" => options.match_template = Some(value.to_string()),
+        "--emit-repro" => options.emit_repro = Some(value.to_string()),
+        "--time-strips" => {
+            options.time_strips = Some(value.parse().ok().filter(|&rows: &usize| rows > 0)
+                .unwrap_or_else(|| panic!("Invalid --time-strips {}, expected rows per strip of at least 1", value)));
+        }
+        "--cache-state" => {
+            // Warm first for both, so the main timings stay the usual ones.
+            options.cache_states = match value {
+                "both" => vec![CacheState::Warm, CacheState::Cold],
+                state => vec![CacheState::parse(state).unwrap_or_else(|| panic!("Unknown --cache-state {}, expected cold, warm or both", state))],
+            };
+        }
+        "--llc-size" => {
+            options.llc_size = Some(parse_bytes(value).and_then(|bytes| usize::try_from(bytes).ok())
+                .unwrap_or_else(|| panic!("Invalid --llc-size {}, expected bytes such as 32M", value)));
+        }
+        _ => return false,
+    }
+
+    return true;
+}
+
+// Returns the argument following a flag that takes a value.
+fn flag_value<'a>(iter: &mut impl Iterator<Item = &'a String>, flag: &str) -> &'a str {
+    return match iter.next() {
+        Some(value) => value.trim(),
+        None => panic!("{} requires a value", flag),
+    };
+}
+
+// Parses dimensions written as ROWSxCOLS, e.g. 1024x768.
+fn parse_dims(value: &str) -> (usize, usize) {
+    return match value.split_once(['x', 'X']) {
+        Some((rows, cols)) => (
+            rows.trim().parse().expect("Invalid rows in dimensions"),
+            cols.trim().parse().expect("Invalid cols in dimensions"),
+        ),
+        None => panic!("Dimensions must be written as ROWSxCOLS, got {}", value),
+    };
+}
+
+// Parses a non-empty range written START..END, e.g. 40000..41000.
+fn parse_range(value: &str) -> Option<(usize, usize)> {
+    let (start, end) = value.split_once("..")?;
+    let (start, end): (usize, usize) = (start.trim().parse().ok()?, end.trim().parse().ok()?);
+
+    return (start < end).then_some((start, end));
+}
+
+// Parses a --max-memory or --llc-size value: a positive number of bytes, optionally
+// followed by K, M, G or T for powers of 1024 (KiB, KB and so on are read the
+// same way), e.g. 512M or 8GiB.
+fn parse_bytes(value: &str) -> Option<u128> {
+    let value: &str = value.trim();
+    let split: usize = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let count: u128 = value[..split].parse().ok()?;
+    let unit: String = value[split..].trim().to_ascii_uppercase();
+    let prefix: &str = unit.strip_suffix("IB").or_else(|| unit.strip_suffix('B')).unwrap_or(&unit);
+    let power: u32 = match prefix {
+        "" => 0,
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        _ => return None,
+    };
+
+    return count.checked_mul(1 << (10 * power)).filter(|&bytes| bytes > 0);
+}
+
+// Parses a --size value: a comma separated list of sizes, each written
+// ROWSxCOLS (x or X) or as a single N for an N x N matrix, e.g. "1024x768",
+// "4096" or "256, 512 x 128". Every dimension must be a positive integer.
+pub(crate) fn parse_size_spec(spec: &str) -> Result<Vec<(usize, usize)>, String> {
+    return spec.split(',').map(|token| {
+        let token: &str = token.trim();
+
+        if token.is_empty() {
+            return Err(format!("empty size in '{}'", spec.trim()));
+        }
+
+        let dims: Vec<usize> = token.split(['x', 'X']).map(|part| match part.trim().parse::<usize>() {
+            _ if part.trim().is_empty() => Err(format!("size '{}' is missing a dimension", token)),
+            Ok(0) => Err(format!("size '{}' has a zero dimension", token)),
+            Ok(dim) => Ok(dim),
+            Err(_) => Err(format!("'{}' in size '{}' is not a positive integer", part.trim(), token)),
+        }).collect::<Result<_, _>>()?;
+
+        return match dims[..] {
+            [size] => Ok((size, size)),
+            [rows, cols] => Ok((rows, cols)),
+            _ => Err(format!("size '{}' must be N or ROWSxCOLS", token)),
+        };
+    }).collect();
+}
+
+// Parses one --assert-min-max value, e.g. dx:-255:255.
+fn parse_range_assertion(value: &str) -> RangeAssertion {
+    let fail = || -> ! { panic!("Invalid --assert-min-max {}, expected dx:MIN:MAX or dy:MIN:MAX", value.trim()) };
+    let [name, min, max] = value.trim().split(':').collect::<Vec<&str>>()[..] else { fail() };
+    let output: &'static str = match name.trim() {
+        "dx" => "dx",
+        "dy" => "dy",
+        _ => fail(),
+    };
+    let (min, max): (i64, i64) = match (min.trim().parse(), max.trim().parse()) {
+        (Ok(min), Ok(max)) if min <= max => (min, max),
+        _ => fail(),
+    };
+
+    return RangeAssertion { output, min, max };
+}
+
+// Parses --harris parameters, e.g. "k=0.05,radius=3" or "k=0.05 radius=3".
+// Omitted ones keep their defaults.
+fn parse_harris(value: &str) -> HarrisParams {
+    let mut params: HarrisParams = HarrisParams::default();
+
+    for param in value.split([',', ' ']).map(|param| param.trim()).filter(|param| !param.is_empty()) {
+        match param.split_once('=') {
+            Some(("k", k)) => params.k = k.trim().parse().unwrap_or_else(|_| panic!("Invalid --harris k {}", k)),
+            Some(("radius", radius)) => params.radius = radius.trim().parse().unwrap_or_else(|_| panic!("Invalid --harris radius {}", radius)),
+            _ => panic!("Invalid --harris parameter {}, expected k=K or radius=R", param),
+        }
+    }
+
+    return params;
+}
+
+// Parses a --rolling value, max:WINDOW or min:WINDOW with an optional :input
+// to apply it to the input rather than |Dx|.
+fn parse_rolling(value: &str) -> Rolling {
+    let fail = || -> ! { panic!("Invalid --rolling {}, expected max:WINDOW or min:WINDOW, optionally followed by :input", value) };
+    let parts: Vec<&str> = value.split(':').map(|part| part.trim()).collect();
+
+    let (extreme, window, input) = match parts[..] {
+        [extreme, window] => (extreme, window, false),
+        [extreme, window, "input"] => (extreme, window, true),
+        _ => fail(),
+    };
+    let max: bool = match extreme {
+        "max" => true,
+        "min" => false,
+        _ => fail(),
+    };
+    let window: usize = match window.parse() {
+        Ok(window) if window > 0 => window,
+        _ => fail(),
+    };
+
+    return Rolling { max, window, input };
+}
+
+// Parses a comma separated list of kernel weights, e.g. -1,0,1.
+fn parse_kernel(value: &str) -> Vec<i32> {
+    return value.split(',').map(|weight| weight.trim().parse().expect("Invalid kernel weight")).collect();
+}
+
+#[cfg(feature = "parallel")]
+fn parse_threads(value: &str) -> usize {
+    return value.parse().ok().filter(|&threads| threads > 0).unwrap_or_else(|| panic!("Invalid --threads {}, expected a positive count", value));
+}
+
+// Refused rather than ignored, so a serial build is never mistaken for one
+// that used the requested threads.
+#[cfg(not(feature = "parallel"))]
+fn parse_threads(_value: &str) -> usize {
+    panic!("--threads is not available: this build has the parallel feature disabled and runs serially");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dimensions_take_either_case_of_x() {
+        assert_eq!(parse_dims("640x480"), (640, 480));
+        assert_eq!(parse_dims("640X480"), (640, 480));
+        assert_eq!(parse_dims(" 3 x 5 "), (3, 5));
+    }
+
+    #[test]
+    #[should_panic(expected = "Dimensions must be written as ROWSxCOLS")]
+    fn dimensions_need_a_separator() {
+        parse_dims("640*480");
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn threads_takes_a_positive_count() {
+        assert_eq!(parse_threads("4"), 4);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    #[should_panic(expected = "Invalid --threads 0, expected a positive count")]
+    fn threads_rejects_zero() {
+        parse_threads("0");
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    #[test]
+    #[should_panic(expected = "--threads is not available: this build has the parallel feature disabled and runs serially")]
+    fn threads_is_refused_without_the_parallel_feature() {
+        parse_threads("4");
+    }
+
+    #[test]
+    fn size_specs_take_either_case_of_x_and_whitespace() {
+        assert_eq!(parse_size_spec("512x256"), Ok(vec![(512, 256)]));
+        assert_eq!(parse_size_spec("512X256"), Ok(vec![(512, 256)]));
+        assert_eq!(parse_size_spec("  512 x 256 "), Ok(vec![(512, 256)]));
+        assert_eq!(parse_size_spec("4096"), Ok(vec![(4096, 4096)]));
+    }
+
+    #[test]
+    fn size_specs_take_comma_lists() {
+        assert_eq!(parse_size_spec("256, 512 x 128,1x1"), Ok(vec![(256, 256), (512, 128), (1, 1)]));
+        assert_eq!(parse_size_spec("8,8"), Ok(vec![(8, 8), (8, 8)]));
+    }
+
+    #[test]
+    fn size_spec_errors_name_the_offending_token() {
+        let error = |spec: &str| -> String { parse_size_spec(spec).unwrap_err() };
+
+        assert_eq!(error("512x0"), "size '512x0' has a zero dimension");
+        assert_eq!(error("64, 0"), "size '0' has a zero dimension");
+        assert_eq!(error("64,abcx3"), "'abc' in size 'abcx3' is not a positive integer");
+        assert_eq!(error("-4x4"), "'-4' in size '-4x4' is not a positive integer");
+        assert_eq!(error("3x"), "size '3x' is missing a dimension");
+        assert_eq!(error("2x3x4"), "size '2x3x4' must be N or ROWSxCOLS");
+        assert_eq!(error("8,,8"), "empty size in '8,,8'");
+        assert_eq!(error(""), "empty size in ''");
+    }
+
+    fn args(flags: &[&str]) -> Vec<String> {
+        return ["matician-coding-challenge"].iter().chain(flags).map(|flag| flag.to_string()).collect();
+    }
+
+    #[test]
+    fn accumulate_is_accepted_on_any_run() {
+        assert_eq!(parse_args(&args(&["--accumulate", "f32"])).accumulate, Some(Accumulator::F32));
+        assert_eq!(parse_args(&args(&["--accumulate", "f64", "--scale-report", "3"])).accumulate, Some(Accumulator::F64));
+        assert_eq!(parse_args(&args(&["--accumulate", "f32", "--output-dir", "out"])).accumulate, Some(Accumulator::F32));
+        assert_eq!(parse_args(&args(&[])).accumulate, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown --accumulate f16, expected f64 or f32")]
+    fn accumulate_takes_f64_or_f32() {
+        parse_args(&args(&["--accumulate", "f16"]));
+    }
+}
@@ -0,0 +1,91 @@
+use crate::error::{check_len, DimError};
+
+// How to_heatmap_rgb maps values to colors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMap {
+    // Blue through white to red for signed data such as Dx and Dy: the
+    // minimum is full blue, zero is white and the maximum is full red.
+    // Negative and positive values are scaled separately, so both extremes
+    // reach full color even when the range is not symmetric.
+    Diverging,
+    // Black through red and yellow to white for magnitudes, from the minimum
+    // to the maximum.
+    Sequential,
+}
+
+// Colors a rows x cols matrix, returning rows * cols RGB triples in
+// row-major order.
+pub fn to_heatmap_rgb(data: &[i16], rows: usize, cols: usize, map: ColorMap) -> Result<Vec<u8>, DimError> {
+    check_len(data.len(), rows, cols)?;
+
+    let min: i16 = data.iter().copied().min().unwrap_or(0);
+    let max: i16 = data.iter().copied().max().unwrap_or(0);
+    let mut rgb: Vec<u8> = Vec::with_capacity(data.len() * 3);
+
+    for value in data {
+        rgb.extend_from_slice(&color(*value, min, max, map));
+    }
+
+    return Ok(rgb);
+}
+
+// The color of value in a matrix whose values range over min..=max.
+pub fn color(value: i16, min: i16, max: i16, map: ColorMap) -> [u8; 3] {
+    return match map {
+        ColorMap::Diverging => {
+            if value < 0 {
+                // Fades from white at zero to blue at min.
+                let fade: u8 = 255 - scale(value as f32 / min as f32);
+                [fade, fade, 255]
+            } else if value > 0 {
+                let fade: u8 = 255 - scale(value as f32 / max as f32);
+                [255, fade, fade]
+            } else {
+                [255, 255, 255]
+            }
+        }
+        ColorMap::Sequential => {
+            let t: f32 = if max > min { (value as f32 - min as f32) / (max as f32 - min as f32) } else { 0.0 };
+            [scale(3.0 * t), scale(3.0 * t - 1.0), scale(3.0 * t - 2.0)]
+        }
+    };
+}
+
+// Maps 0.0..=1.0 onto 0..=255, clamping values outside that range.
+fn scale(t: f32) -> u8 {
+    return (t.clamp(0.0, 1.0) * 255.0).round() as u8;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diverging_map_runs_blue_white_red() {
+        assert_eq!(color(-200, -200, 50, ColorMap::Diverging), [0, 0, 255]);
+        assert_eq!(color(0, -200, 50, ColorMap::Diverging), [255, 255, 255]);
+        assert_eq!(color(50, -200, 50, ColorMap::Diverging), [255, 0, 0]);
+        // Each side is scaled by its own extreme.
+        assert_eq!(color(-100, -200, 50, ColorMap::Diverging), [127, 127, 255]);
+        assert_eq!(color(25, -200, 50, ColorMap::Diverging), [255, 127, 127]);
+    }
+
+    #[test]
+    fn sequential_map_runs_black_to_white() {
+        assert_eq!(color(10, 10, 40, ColorMap::Sequential), [0, 0, 0]);
+        assert_eq!(color(20, 10, 40, ColorMap::Sequential), [255, 0, 0]);
+        assert_eq!(color(30, 10, 40, ColorMap::Sequential), [255, 255, 0]);
+        assert_eq!(color(40, 10, 40, ColorMap::Sequential), [255, 255, 255]);
+        // A constant matrix is all black.
+        assert_eq!(color(7, 7, 7, ColorMap::Sequential), [0, 0, 0]);
+    }
+
+    #[test]
+    fn heatmap_colors_every_element_in_order() {
+        let rgb: Vec<u8> = to_heatmap_rgb(&[i16::MIN, 0, i16::MAX, 0], 2, 2, ColorMap::Diverging).unwrap();
+
+        assert_eq!(rgb, vec![0, 0, 255, 255, 255, 255, 255, 0, 0, 255, 255, 255]);
+        assert_eq!(to_heatmap_rgb(&[], 0, 0, ColorMap::Sequential), Ok(vec![]));
+        assert!(to_heatmap_rgb(&[1, 2, 3], 2, 2, ColorMap::Diverging).is_err());
+    }
+}
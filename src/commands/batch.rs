@@ -0,0 +1,118 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use rmm::io::write_bin;
+use rmm::kernels::{compute_dx_into, compute_dy_into};
+use rmm::matrix::fill_randomized_seeded;
+use rmm::pipeline::{run_pipelined, run_sequential, StageTimes, DEFAULT_CAPACITY};
+use rmm::pool::BufferPool;
+
+// Seed of the first matrix unless --seed is given; matrix i uses seed + i.
+const DEFAULT_SEED: u64 = 0;
+
+// Usage: batch R C --count N --out-dir DIR [--seed S] [--pipeline] [--workers W]
+//
+// Generates N seeded R x C matrices, runs Dx and Dy on each and writes them
+// to DIR as {i}_dx.bin and {i}_dy.bin. By default every matrix is generated,
+// computed and written before the next one starts. With --pipeline the
+// stages overlap: one thread generates, W workers (default: one per core)
+// run the kernels and the main thread writes, in the same order and with the
+// same bytes as the sequential run. --pipeline needs the parallel feature.
+// The time each stage spent busy is reported, so the bottleneck is visible.
+pub fn run(args: &[String]) {
+    let mut dims: Vec<usize> = Vec::new();
+    let mut count: Option<usize> = None;
+    let mut out_dir: Option<PathBuf> = None;
+    let mut seed: u64 = DEFAULT_SEED;
+    let mut pipeline: bool = false;
+    let mut workers: usize = thread::available_parallelism().map_or(1, |cores| cores.get());
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--count" => count = Some(iter.next().expect("--count requires a value").trim().parse().expect("Invalid --count argument")),
+            "--out-dir" => out_dir = Some(PathBuf::from(iter.next().expect("--out-dir requires a value").trim())),
+            "--seed" => seed = iter.next().expect("--seed requires a value").trim().parse().expect("Invalid --seed argument"),
+            "--pipeline" => pipeline = true,
+            "--workers" => {
+                workers = iter.next().expect("--workers requires a value").trim().parse().expect("Invalid --workers argument");
+            }
+            flag if flag.starts_with("--") => panic!("Unknown flag {}", flag),
+            value => dims.push(value.trim().parse().expect("Invalid matrix dimension")),
+        }
+    }
+
+    let (rows, cols) = match dims[..] {
+        [rows, cols] => (rows, cols),
+        _ => panic!("batch requires R C"),
+    };
+    let count: usize = count.expect("batch requires --count");
+
+    if pipeline && !cfg!(feature = "parallel") {
+        panic!("--pipeline is not available: this build has the parallel feature disabled and runs serially");
+    }
+
+    let out_dir: PathBuf = out_dir.expect("batch requires --out-dir");
+
+    fs::create_dir_all(&out_dir).unwrap_or_else(|err| panic!("Failed to create {}: {}", out_dir.display(), err));
+
+    // Buffers cycle through the stages: inputs go back to their pool once the
+    // kernels are done with them, outputs once they are written. Enough are
+    // kept for every item that can be in flight at once.
+    let in_flight: usize = 2 * DEFAULT_CAPACITY + workers + 1;
+    let inputs: Mutex<BufferPool<u8>> = Mutex::new(BufferPool::new(in_flight));
+    let outputs: Mutex<BufferPool<i16>> = Mutex::new(BufferPool::new(2 * in_flight));
+
+    let generate = |index: usize| {
+        let mut arr: Vec<u8> = inputs.lock().expect("input pool poisoned").take(rows * cols);
+        fill_randomized_seeded(&mut arr, seed.wrapping_add(index as u64));
+        return arr;
+    };
+    let compute = |arr: Vec<u8>| {
+        let (mut dx, mut dy) = {
+            let mut pool = outputs.lock().expect("output pool poisoned");
+            (pool.take(rows * (cols + 2)), pool.take((rows + 2) * cols))
+        };
+
+        compute_dx_into(&arr, rows, cols, &mut dx).expect("Input has unexpected dimensions");
+        compute_dy_into(&arr, rows, cols, &mut dy).expect("Input has unexpected dimensions");
+        inputs.lock().expect("input pool poisoned").give(arr);
+        return (dx, dy);
+    };
+    let write = |index: usize, (dx, dy): (Vec<i16>, Vec<i16>)| -> io::Result<()> {
+        write_bin(&out_dir.join(format!("{}_dx.bin", index)), &dx, rows, cols + 2)?;
+        write_bin(&out_dir.join(format!("{}_dy.bin", index)), &dy, rows + 2, cols)?;
+
+        let mut pool = outputs.lock().expect("output pool poisoned");
+        pool.give(dx);
+        pool.give(dy);
+        return Ok(());
+    };
+
+    let times: StageTimes = if pipeline {
+        run_pipelined(count, workers, DEFAULT_CAPACITY, generate, compute, write)
+    } else {
+        run_sequential(count, generate, compute, write)
+    }.unwrap_or_else(|err| panic!("Failed to write to {}: {}", out_dir.display(), err));
+
+    print_times(&times, count, rows, cols, if pipeline { workers } else { 1 }, &out_dir);
+}
+
+fn print_times(times: &StageTimes, count: usize, rows: usize, cols: usize, workers: usize, out_dir: &Path) {
+    println!("=== Batch {} x {}x{} -> {} ({} worker{}) ===", count, rows, cols, out_dir.display(), workers,
+             if workers == 1 { "" } else { "s" });
+
+    for (name, busy) in [("generate", times.generate), ("compute", times.compute), ("write", times.write)] {
+        println!("{:<9} busy: {:?} ({:.0}% of wall)", name, busy, share(busy, times.wall) * 100.0);
+    }
+
+    println!("{:<9} {:?}", "wall", times.wall);
+}
+
+// Fraction of total taken by part, 0 when total is zero.
+fn share(part: Duration, total: Duration) -> f64 {
+    return if total.is_zero() { 0.0 } else { part.as_secs_f64() / total.as_secs_f64() };
+}
@@ -0,0 +1,631 @@
+use std::fs::OpenOptions;
+use std::hint::black_box;
+use std::mem::size_of;
+use std::io::{ErrorKind, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::process;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use rmm::arith::ArithPolicy;
+use rmm::conv::{convolve_cols, convolve_cols_const, convolve_rows, convolve_rows_const, CENTRAL_DIFFERENCE};
+use rmm::kernels::{compute_dx, compute_dx_into, compute_dx_mapped, compute_dx_strided, compute_dy, compute_dy_blocked, compute_dy_into, compute_dy_mapped,
+                   compute_dy_strided, DY_BLOCK_THRESHOLD};
+use rmm::matmul::{matmul_blocked, matmul_f32, simd_available, DEFAULT_BLOCK_SIZE};
+use rmm::matrix::construct_randomized_matrix_seeded;
+use rmm::stats::{approx_eq, checksum, fingerprint, CHECKSUM_BASIS};
+use rmm::throughput::{dx_bytes_moved, dy_bytes_moved, Throughput};
+use rmm::timing::{measure, parse_duration, TimingConfig, TimingReport, Warmup};
+use super::matmul::TOLERANCE;
+
+// Seed of the input matrix unless --seed is given, so that runs logged on
+// different days benchmark the same data.
+const DEFAULT_SEED: u64 = 0;
+
+// Timed runs per kernel unless --iterations is given.
+const DEFAULT_ITERATIONS: usize = 10;
+
+// Shape bench wide runs unless R C is given, the 100 x 2,000,000 case
+// column-blocked Dy was written for.
+const WIDE_DIMS: (usize, usize) = (100, 2_000_000);
+
+// Square sizes bench gemm multiplies unless sizes are given.
+const GEMM_SIZES: [usize; 3] = [512, 1024, 2048];
+
+// Shape and calls per run of bench small unless given: 16 x 16, called in a
+// loop as the library API is, so that the per-call overhead shows.
+const SMALL_DIMS: (usize, usize) = (16, 16);
+const SMALL_CALLS: usize = 100_000;
+
+// Shape bench const runs unless N or R C is given.
+const CONST_DIMS: (usize, usize) = (1024, 1024);
+
+// Timed runs per size of bench gemm unless --iterations is given; a scalar
+// 2048 product takes seconds.
+const GEMM_ITERATIONS: usize = 3;
+
+// Columns of the --log CSV, written once when the file is created.
+const LOG_HEADER: &str = "timestamp,version,rows,cols,seed,kernel,variant,threads,iterations,discarded,median_ns,elements_per_s,gb_per_s,checksum,input_fingerprint";
+
+// Implementation the kernels run with in this build.
+#[cfg(feature = "unsafe-fast")]
+const VARIANT: &str = "unchecked";
+#[cfg(not(feature = "unsafe-fast"))]
+const VARIANT: &str = "safe";
+
+// A kernel being timed: its name, a closure making one timed run and
+// returning the output, and the bytes one call moves.
+type Kernel<'a> = (&'static str, Box<dyn FnMut() -> Vec<i16> + 'a>, usize);
+
+// An implementation timed by compare: its name and a closure making one
+// timed run and returning the output.
+type Variant<'a, T> = (&'static str, Box<dyn FnMut() -> T + 'a>);
+
+// Command line of a bench run.
+struct BenchArgs {
+    rows: usize,
+    cols: usize,
+    seed: u64,
+    calls: usize,
+    log: Option<String>,
+    timing: TimingConfig,
+}
+
+// Usage: bench N | R C [--seed S] [--iterations I] [--warmup K|auto] [--max-bench-time T] [--calls C] [--log PATH]
+//        bench cache N | R C [--seed S] [--iterations I] [--warmup K|auto] [--max-bench-time T]
+//
+// Times Dx and Dy on a seeded R x C (or N x N) input, reporting the median of
+// I runs, the resulting throughput and a checksum of the output. --warmup discards K runs first, or
+// with auto keeps running until the last I runs are stable or T (default
+// 10s) has passed. With --log the results are appended to
+// a CSV file, one row per kernel, so performance can be tracked across runs.
+//
+// With --calls every run makes C calls in a tight loop and the median is
+// reported per call, for sizes too small to time one call at a time. The
+// _into kernels reusing one buffer ("dx-into", "dy-into") and the generic
+// loops that small sizes otherwise bypass ("dx-generic", "dy-generic") are
+// then timed as well.
+//
+// bench cache times the kernels on the input as is and through a row map,
+// see run_cache. The subcommands below compare implementations of one
+// kernel, each printing the speedup over the first and exiting with status 1
+// if any result differs from it:
+//
+//        bench wide [R C] [--seed S] [--iterations I] [...]
+//
+//        bench loops N | R C [--seed S] [--iterations I] [...]
+//        bench gemm [N ...] [--seed S] [--iterations I] [--block-size B]
+//        bench small [N | R C] [--calls C] [--seed S] [--iterations I] [...]
+//        bench const [N | R C] [--seed S] [--iterations I] [...]
+//
+// bench wide times Dy on a wide matrix (100 x 2,000,000 by default) in one
+// panel and in the panels compute_dy picks, see run_wide. bench loops times
+// the per-row chunks_exact loops of the kernels against loops indexing every
+// element, see run_loops. bench gemm times the f32 products, see run_gemm.
+// bench small times the fixed-size paths of small matrices, see run_small.
+// bench const times the const-generic convolutions against the kernels, see
+// run_const.
+pub fn run(args: &[String]) {
+    match args.first().map(String::as_str) {
+        Some("cache") => return run_cache(parse_args(&args[1..])),
+        Some("wide") => return run_wide(parse_args_with(&args[1..], Some(WIDE_DIMS), 1)),
+        Some("loops") => return run_loops(parse_args(&args[1..])),
+        Some("gemm") => return run_gemm(&args[1..]),
+        Some("small") => return run_small(parse_args_with(&args[1..], Some(SMALL_DIMS), SMALL_CALLS)),
+        Some("const") => return run_const(parse_args_with(&args[1..], Some(CONST_DIMS), 1)),
+        _ => {}
+    }
+
+    let BenchArgs { rows, cols, seed, calls, log, timing } = parse_args(args);
+    let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, seed);
+    let input_fingerprint: u64 = fingerprint(&arr, rows, cols).expect("generated input");
+    let (dx_bytes, dy_bytes): (usize, usize) = (dx_bytes_moved(rows, cols, 2, size_of::<i16>()), dy_bytes_moved(rows, cols, 2, size_of::<i16>()));
+    let mut dx_out: Vec<i16> = vec![0; rows * (cols + 2)];
+    let mut dy_out: Vec<i16> = vec![0; (rows + 2) * cols];
+    let arr: &[u8] = &arr;
+    let mut kernels: Vec<Kernel> = vec![("dx", Box::new(move || repeat(calls, || compute_dx(arr, rows, cols).data)), dx_bytes)];
+
+    if calls > 1 {
+        kernels.push(("dx-into", Box::new(move || {
+            repeat(calls, || compute_dx_into(arr, rows, cols, &mut dx_out).expect("generated input"));
+            dx_out.clone()
+        }), dx_bytes));
+        kernels.push(("dx-generic", Box::new(move || repeat(calls, || compute_dx_strided(arr, rows, cols, cols).expect("generated input"))), dx_bytes));
+    }
+
+    kernels.push(("dy", Box::new(move || repeat(calls, || compute_dy(arr, rows, cols).data)), dy_bytes));
+
+    if calls > 1 {
+        kernels.push(("dy-into", Box::new(move || {
+            repeat(calls, || compute_dy_into(arr, rows, cols, &mut dy_out).expect("generated input"));
+            dy_out.clone()
+        }), dy_bytes));
+        kernels.push(("dy-generic", Box::new(move || repeat(calls, || compute_dy_strided(arr, rows, cols, cols).expect("generated input"))), dy_bytes));
+    }
+
+    let timestamp: u64 = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+    let mut lines: String = String::new();
+
+    match calls {
+        1 => println!("=== Bench {}x{} seed {} ({} iterations, median shown) ===", rows, cols, seed, timing.samples),
+        _ => println!("=== Bench {}x{} seed {} ({} iterations of {} calls, median per call shown) ===", rows, cols, seed, timing.samples, calls),
+    }
+
+    println!("Input fingerprint: {:016x}", input_fingerprint);
+
+    for (name, mut kernel, bytes) in kernels {
+        let (out, report): (Vec<i16>, TimingReport) = measure(&timing, &mut kernel);
+        let checksum: u64 = checksum(CHECKSUM_BASIS, &out);
+        let median: Duration = report.median() / calls as u32;
+        let rate: Throughput = Throughput::new(rows * cols, bytes, median);
+
+        println!("{:<10} {:<10} median: {:?} ({:.1} Melem/s, {:.2} GB/s) cv: {:.3} discarded: {}{} checksum: {:016x}", name, VARIANT,
+                 median, rate.elements_per_sec / 1e6, rate.gb_per_sec, report.cv(), report.discarded,
+                 if report.steady { "" } else { " (not steady)" }, checksum);
+
+        lines.push_str(&format!("{},{},{},{},{},{},{},{},{},{},{},{:.0},{:.3},{:016x},{:016x}\n", timestamp, env!("CARGO_PKG_VERSION"),
+                                rows, cols, seed, name, VARIANT, 1, report.samples.len(), report.discarded, median.as_nanos(),
+                                rate.elements_per_sec, rate.gb_per_sec, checksum, input_fingerprint));
+    }
+
+    if let Some(path) = log {
+        append_log(&path, &lines).unwrap_or_else(|err| panic!("Failed to append to {}: {}", path, err));
+    }
+}
+
+// Times compute_dx and compute_dy three ways on the same seeded input: on the
+// matrix itself, through an identity row map (the cost of the indirection
+// alone) and through a row map shuffled with the seed (the same data and
+// work, but every row a jump away from the last). The shuffled/identity
+// ratio is the slowdown due to locality. The mapped results are checked
+// against the plain ones, Dx after unpermuting its rows and Dy against the
+// shuffled matrix copied out; a mismatch exits with status 1.
+fn run_cache(args: BenchArgs) {
+    let BenchArgs { rows, cols, seed, calls, log, timing } = args;
+
+    if calls > 1 || log.is_some() {
+        panic!("bench cache does not support --calls or --log");
+    }
+
+    let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, seed);
+    let identity: Vec<usize> = (0..rows).collect();
+    let mut shuffled: Vec<usize> = identity.clone();
+    shuffled.shuffle(&mut ChaCha12Rng::seed_from_u64(seed));
+
+    let copied: Vec<u8> = shuffled.iter().flat_map(|&row| arr[row * cols..(row + 1) * cols].iter().copied()).collect();
+    let (dx_bytes, dy_bytes): (usize, usize) = (dx_bytes_moved(rows, cols, 2, size_of::<i16>()), dy_bytes_moved(rows, cols, 2, size_of::<i16>()));
+    let mut mismatches: Vec<String> = Vec::new();
+
+    println!("=== Bench cache {}x{} seed {} ({} iterations, median shown) ===", rows, cols, seed, timing.samples);
+
+    // Dx rows of the shuffled view are rows of plain Dx; put them back.
+    let (dx, dx_report) = measure(&timing, || compute_dx(&arr, rows, cols).data);
+    let (dx_identity, dx_identity_report) = measure(&timing, || compute_dx_mapped(&arr, rows, cols, &identity).expect("generated input"));
+    let (dx_shuffled, dx_shuffled_report) = measure(&timing, || compute_dx_mapped(&arr, rows, cols, &shuffled).expect("generated input"));
+    let mut unpermuted: Vec<i16> = vec![0; dx.len()];
+
+    for (out_row, &row) in dx_shuffled.chunks_exact(cols + 2).zip(&shuffled) {
+        unpermuted[row * (cols + 2)..(row + 1) * (cols + 2)].copy_from_slice(out_row);
+    }
+
+    print_cache("dx", [&dx_report, &dx_identity_report, &dx_shuffled_report], rows * cols, dx_bytes);
+    check_cache("dx identity", &dx, &dx_identity, &mut mismatches);
+    check_cache("dx shuffled", &dx, &unpermuted, &mut mismatches);
+
+    let (dy, dy_report) = measure(&timing, || compute_dy(&arr, rows, cols).data);
+    let (dy_identity, dy_identity_report) = measure(&timing, || compute_dy_mapped(&arr, rows, cols, &identity).expect("generated input"));
+    let (dy_shuffled, dy_shuffled_report) = measure(&timing, || compute_dy_mapped(&arr, rows, cols, &shuffled).expect("generated input"));
+
+    print_cache("dy", [&dy_report, &dy_identity_report, &dy_shuffled_report], rows * cols, dy_bytes);
+    check_cache("dy identity", &dy, &dy_identity, &mut mismatches);
+    check_cache("dy shuffled", &compute_dy(&copied, rows, cols).data, &dy_shuffled, &mut mismatches);
+
+    if !mismatches.is_empty() {
+        for mismatch in &mismatches {
+            eprintln!("{}", mismatch);
+        }
+
+        process::exit(1);
+    }
+
+    println!("Mapped results match the plain kernels");
+}
+
+// compute_dy on a matrix wider than DY_BLOCK_THRESHOLD, with all columns in
+// one panel ("dy-unblocked") and in the DY_BLOCK_COLS panels compute_dy uses
+// ("dy-blocked"). Unblocked, the two input rows and the output row each
+// stream cols elements apart, past L2 on wide shapes; a panel keeps all three
+// cache resident.
+fn run_wide(args: BenchArgs) {
+    let BenchArgs { rows, cols, seed, calls, log, timing } = args;
+
+    if calls > 1 || log.is_some() {
+        panic!("bench wide does not support --calls or --log");
+    }
+
+    let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, seed);
+    let arr: &[u8] = &arr;
+
+    println!("=== Bench wide {}x{} seed {} ({} iterations, median shown) ===", rows, cols, seed, timing.samples);
+
+    if cols <= DY_BLOCK_THRESHOLD {
+        println!("Note: {} columns is within DY_BLOCK_THRESHOLD ({}), so compute_dy does not block", cols, DY_BLOCK_THRESHOLD);
+    }
+
+    let variants: Vec<Variant<Vec<i16>>> = vec![
+        ("dy-unblocked", Box::new(move || compute_dy_blocked(arr, rows, cols, cols, cols).expect("generated input"))),
+        ("dy-blocked", Box::new(move || compute_dy(arr, rows, cols).data)),
+    ];
+
+    let bytes: usize = dy_bytes_moved(rows, cols, 2, size_of::<i16>());
+
+    if !compare(variants, &timing, 1, rows * cols, bytes, |a, b| a == b) {
+        process::exit(1);
+    }
+}
+
+// The kernels' generic loops, which work on per-row subslices through
+// chunks_exact ("dx-chunks", "dy-chunks"), against loops computing
+// row * cols + col and bounds checking every access, as the kernels did
+// before ("dx-indexed", "dy-indexed"). The strided entry points are timed so
+// that small sizes do not take the fixed-size fast paths instead.
+fn run_loops(args: BenchArgs) {
+    let BenchArgs { rows, cols, seed, calls, log, timing } = args;
+
+    if calls > 1 || log.is_some() {
+        panic!("bench loops does not support --calls or --log");
+    }
+
+    let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, seed);
+    let arr: &[u8] = &arr;
+
+    println!("=== Bench loops {}x{} seed {} ({} iterations, median shown) ===", rows, cols, seed, timing.samples);
+
+    let dx: Vec<Variant<Vec<i16>>> = vec![
+        ("dx-indexed", Box::new(move || dx_indexed(arr, rows, cols))),
+        ("dx-chunks", Box::new(move || compute_dx_strided(arr, rows, cols, cols).expect("generated input"))),
+    ];
+    let dy: Vec<Variant<Vec<i16>>> = vec![
+        ("dy-indexed", Box::new(move || dy_indexed(arr, rows, cols))),
+        ("dy-chunks", Box::new(move || compute_dy_strided(arr, rows, cols, cols).expect("generated input"))),
+    ];
+
+    let dx_agree: bool = compare(dx, &timing, 1, rows * cols, dx_bytes_moved(rows, cols, 2, size_of::<i16>()), |a, b| a == b);
+    let dy_agree: bool = compare(dy, &timing, 1, rows * cols, dy_bytes_moved(rows, cols, 2, size_of::<i16>()), |a, b| a == b);
+
+    if !dx_agree || !dy_agree {
+        process::exit(1);
+    }
+}
+
+// The [-1, 0, 1] convolutions three ways on a 1024 x 1024 matrix by default:
+// the hand-written kernels ("dx", "dy"), convolve_rows_const and
+// convolve_cols_const with CENTRAL_DIFFERENCE, which the compiler unrolls
+// like them ("dx-const", "dy-const"), and the runtime-slice convolve_rows and
+// convolve_cols looping over the taps ("dx-slice", "dy-slice"), all into i16
+// with wrapping arithmetic as the kernels use. The const speedup should be
+// close to 1.
+fn run_const(args: BenchArgs) {
+    let BenchArgs { rows, cols, seed, calls, log, timing } = args;
+
+    if calls > 1 || log.is_some() {
+        panic!("bench const does not support --calls or --log");
+    }
+
+    let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, seed);
+    let arr: &[u8] = &arr;
+
+    println!("=== Bench const {}x{} seed {} ({} iterations, median shown) ===", rows, cols, seed, timing.samples);
+
+    let dx: Vec<Variant<Vec<i16>>> = vec![
+        ("dx", Box::new(move || compute_dx(arr, rows, cols).data)),
+        ("dx-const", Box::new(move || convolve_rows_const::<u8, i16, 3>(arr, rows, cols, CENTRAL_DIFFERENCE, ArithPolicy::Wrapping).expect("generated input"))),
+        ("dx-slice", Box::new(move || convolve_rows::<u8, i16>(arr, rows, cols, &CENTRAL_DIFFERENCE, ArithPolicy::Wrapping).expect("generated input"))),
+    ];
+    let dy: Vec<Variant<Vec<i16>>> = vec![
+        ("dy", Box::new(move || compute_dy(arr, rows, cols).data)),
+        ("dy-const", Box::new(move || convolve_cols_const::<u8, i16, 3>(arr, rows, cols, CENTRAL_DIFFERENCE, ArithPolicy::Wrapping).expect("generated input"))),
+        ("dy-slice", Box::new(move || convolve_cols::<u8, i16>(arr, rows, cols, &CENTRAL_DIFFERENCE, ArithPolicy::Wrapping).expect("generated input"))),
+    ];
+
+    let dx_agree: bool = compare(dx, &timing, 1, rows * cols, dx_bytes_moved(rows, cols, 2, size_of::<i16>()), |a, b| a == b);
+    let dy_agree: bool = compare(dy, &timing, 1, rows * cols, dy_bytes_moved(rows, cols, 2, size_of::<i16>()), |a, b| a == b);
+
+    if !dx_agree || !dy_agree {
+        process::exit(1);
+    }
+}
+
+// The kernels on a small matrix (16 x 16 by default) called C times per run
+// (100,000 by default): the generic loops ("dx-generic"), which sizes up to
+// SMALL_MAX bypass, then the fixed-size paths the public functions dispatch
+// to, allocating ("dx") and reusing one output buffer ("dx-into").
+fn run_small(args: BenchArgs) {
+    let BenchArgs { rows, cols, seed, calls, log, timing } = args;
+
+    if log.is_some() {
+        panic!("bench small does not support --log");
+    }
+
+    let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, seed);
+    let arr: &[u8] = &arr;
+    let mut dx_out: Vec<i16> = vec![0; rows * (cols + 2)];
+    let mut dy_out: Vec<i16> = vec![0; (rows + 2) * cols];
+
+    println!("=== Bench small {}x{} seed {} ({} iterations of {} calls, median per call shown) ===", rows, cols, seed, timing.samples, calls);
+
+    let dx: Vec<Variant<Vec<i16>>> = vec![
+        ("dx-generic", Box::new(move || repeat(calls, || compute_dx_strided(arr, rows, cols, cols).expect("generated input")))),
+        ("dx", Box::new(move || repeat(calls, || compute_dx(arr, rows, cols).data))),
+        ("dx-into", Box::new(move || {
+            repeat(calls, || compute_dx_into(arr, rows, cols, &mut dx_out).expect("generated input"));
+            dx_out.clone()
+        })),
+    ];
+    let dy: Vec<Variant<Vec<i16>>> = vec![
+        ("dy-generic", Box::new(move || repeat(calls, || compute_dy_strided(arr, rows, cols, cols).expect("generated input")))),
+        ("dy", Box::new(move || repeat(calls, || compute_dy(arr, rows, cols).data))),
+        ("dy-into", Box::new(move || {
+            repeat(calls, || compute_dy_into(arr, rows, cols, &mut dy_out).expect("generated input"));
+            dy_out.clone()
+        })),
+    ];
+
+    let dx_agree: bool = compare(dx, &timing, calls, rows * cols, dx_bytes_moved(rows, cols, 2, size_of::<i16>()), |a, b| a == b);
+    let dy_agree: bool = compare(dy, &timing, calls, rows * cols, dy_bytes_moved(rows, cols, 2, size_of::<i16>()), |a, b| a == b);
+
+    if !dx_agree || !dy_agree {
+        process::exit(1);
+    }
+}
+
+// matmul_blocked ("scalar") against matmul_f32 ("fma"), which runs the
+// AVX2 + FMA micro kernel under the same tiling, on seeded N x N matrices
+// for every size (512, 1024 and 2048 by default). The sums are reassociated,
+// so the results are compared with approx_eq rather than exactly.
+fn run_gemm(args: &[String]) {
+    let mut sizes: Vec<usize> = Vec::new();
+    let mut seed: u64 = DEFAULT_SEED;
+    let mut iterations: usize = GEMM_ITERATIONS;
+    let mut block_size: usize = DEFAULT_BLOCK_SIZE;
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--seed" => seed = iter.next().expect("--seed requires a value").trim().parse().expect("Invalid --seed argument"),
+            "--iterations" => {
+                iterations = iter.next().expect("--iterations requires a value").trim().parse()
+                    .expect("Invalid --iterations argument");
+            }
+            "--block-size" => {
+                block_size = iter.next().expect("--block-size requires a value").trim().parse()
+                    .expect("Invalid --block-size argument");
+            }
+            flag if flag.starts_with("--") => panic!("Unknown flag {}", flag),
+            value => sizes.push(value.trim().parse().expect("Invalid matrix size")),
+        }
+    }
+
+    if sizes.is_empty() {
+        sizes = GEMM_SIZES.to_vec();
+    }
+
+    let timing: TimingConfig = TimingConfig { samples: iterations.max(1), window: iterations.max(1), ..TimingConfig::default() };
+    let mut agree: bool = true;
+
+    if !simd_available() {
+        println!("Note: this CPU lacks AVX2 + FMA, so fma runs the scalar code");
+    }
+
+    for n in sizes {
+        let mut rng: ChaCha12Rng = ChaCha12Rng::seed_from_u64(seed);
+        let a: Vec<f32> = (0..n * n).map(|_| rng.gen::<f32>()).collect();
+        let b: Vec<f32> = (0..n * n).map(|_| rng.gen::<f32>()).collect();
+        let (a, b): (&[f32], &[f32]) = (&a, &b);
+
+        println!("=== Bench gemm {}x{} seed {} block {} ({} iterations, median shown) ===", n, n, seed, block_size, timing.samples);
+
+        let variants: Vec<Variant<Vec<f32>>> = vec![
+            ("scalar", Box::new(move || matmul_blocked(a, n, n, b, n, n, block_size).expect("square operands"))),
+            ("fma", Box::new(move || matmul_f32(a, n, n, b, n, n, block_size).expect("square operands"))),
+        ];
+
+        // Elements of C, and the bytes of A, B and C.
+        agree &= compare(variants, &timing, 1, n * n, 3 * n * n * size_of::<f32>(), |x, y| approx_eq(x, y, TOLERANCE));
+    }
+
+    if !agree {
+        process::exit(1);
+    }
+}
+
+// Dx with the interior loop indexing every element as row * cols + col, the
+// baseline of bench loops. The border columns are computed as in the
+// kernels, so only the interior loop differs.
+fn dx_indexed(arr: &[u8], rows: usize, cols: usize) -> Vec<i16> {
+    let new_cols: usize = cols + 2;
+    let mut dx: Vec<i16> = vec![0; rows * new_cols];
+    let at = |row: usize, col: usize| -> i16 { if col < cols { arr[row * cols + col] as i16 } else { 0 } };
+
+    for row in 0..rows {
+        for col in [0, 1, cols, cols + 1] {
+            let left: i16 = if col >= 2 { at(row, col - 2) } else { 0 };
+            dx[row * new_cols + col] = left - at(row, col);
+        }
+
+        for col in 0..cols.saturating_sub(2) {
+            dx[row * new_cols + 2 + col] = arr[row * cols + col] as i16 - arr[row * cols + col + 2] as i16;
+        }
+    }
+
+    return dx;
+}
+
+// Dy with the interior loop indexing every element, see dx_indexed.
+fn dy_indexed(arr: &[u8], rows: usize, cols: usize) -> Vec<i16> {
+    let mut dy: Vec<i16> = vec![0; (rows + 2) * cols];
+    let at = |row: usize, col: usize| -> i16 { if row < rows { arr[row * cols + col] as i16 } else { 0 } };
+
+    for row in [0, 1, rows, rows + 1] {
+        for col in 0..cols {
+            let above: i16 = if row >= 2 { at(row - 2, col) } else { 0 };
+            dy[row * cols + col] = above - at(row, col);
+        }
+    }
+
+    for row in 0..rows.saturating_sub(2) {
+        for col in 0..cols {
+            dy[(row + 2) * cols + col] = arr[row * cols + col] as i16 - arr[(row + 2) * cols + col] as i16;
+        }
+    }
+
+    return dy;
+}
+
+// Times every variant, printing its median per call, its throughput over
+// elements the bytes moved per call and its speedup over the first variant,
+// whose output the others are checked against with same. Returns whether
+// they all agreed.
+fn compare<T>(variants: Vec<Variant<T>>, timing: &TimingConfig, calls: usize, elements: usize, bytes: usize,
+              same: impl Fn(&T, &T) -> bool) -> bool {
+    let mut baseline: Option<(&str, T, Duration)> = None;
+    let mut agree: bool = true;
+
+    for (name, mut variant) in variants {
+        let (out, report): (T, TimingReport) = measure(timing, &mut variant);
+        let median: Duration = report.median() / calls as u32;
+        let rate: Throughput = Throughput::new(elements, bytes, median);
+        let (speedup, matches): (f64, bool) = match &baseline {
+            Some((_, expected, base)) => (base.as_secs_f64() / median.as_secs_f64().max(f64::MIN_POSITIVE), same(expected, &out)),
+            None => (1.0, true),
+        };
+
+        println!("{:<14} median: {:?} ({:.1} Melem/s, {:.2} GB/s) cv: {:.3} speedup: {:.2}x{}", name, median,
+                 rate.elements_per_sec / 1e6, rate.gb_per_sec, report.cv(), speedup, if matches { "" } else { " MISMATCH" });
+
+        if let Some((first, _, _)) = &baseline {
+            if !matches {
+                eprintln!("{} differs from {}", name, first);
+                agree = false;
+            }
+        } else {
+            baseline = Some((name, out, median));
+        }
+    }
+
+    if agree {
+        println!("All results match {}", baseline.map_or("", |(first, _, _)| first));
+    }
+
+    return agree;
+}
+
+// One line per access pattern, with the slowdown of the shuffled run.
+fn print_cache(name: &str, [plain, identity, shuffled]: [&TimingReport; 3], elements: usize, bytes: usize) {
+    for (pattern, report) in [("sequential", plain), ("identity", identity), ("shuffled", shuffled)] {
+        let rate: Throughput = Throughput::new(elements, bytes, report.median());
+
+        println!("{:<4} {:<10} median: {:?} ({:.1} Melem/s, {:.2} GB/s) cv: {:.3}", name, pattern, report.median(),
+                 rate.elements_per_sec / 1e6, rate.gb_per_sec, report.cv());
+    }
+
+    let ratio = |report: &TimingReport| shuffled.median().as_secs_f64() / report.median().as_secs_f64().max(f64::MIN_POSITIVE);
+
+    println!("{:<4} slowdown   {:.2}x vs sequential, {:.2}x vs identity", name, ratio(plain), ratio(identity));
+}
+
+fn check_cache(what: &str, expected: &[i16], found: &[i16], mismatches: &mut Vec<String>) {
+    if let Some(index) = expected.iter().zip(found).position(|(a, b)| a != b) {
+        mismatches.push(format!("{}: element {} is {}, expected {}", what, index, found[index], expected[index]));
+    } else if expected.len() != found.len() {
+        mismatches.push(format!("{}: {} elements, expected {}", what, found.len(), expected.len()));
+    }
+}
+
+fn parse_args(args: &[String]) -> BenchArgs {
+    return parse_args_with(args, None, 1);
+}
+
+// parse_args for subcommands that run default_dims when no N or R C is given,
+// and make default_calls calls per run unless --calls is given.
+fn parse_args_with(args: &[String], default_dims: Option<(usize, usize)>, default_calls: usize) -> BenchArgs {
+    let mut dims: Vec<usize> = Vec::new();
+    let mut seed: u64 = DEFAULT_SEED;
+    let mut iterations: usize = DEFAULT_ITERATIONS;
+    let mut calls: usize = default_calls;
+    let mut log: Option<String> = None;
+    let mut timing: TimingConfig = TimingConfig::default();
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--seed" => seed = iter.next().expect("--seed requires a value").trim().parse().expect("Invalid --seed argument"),
+            "--iterations" => {
+                iterations = iter.next().expect("--iterations requires a value").trim().parse()
+                    .expect("Invalid --iterations argument");
+            }
+            "--warmup" => {
+                let value: &str = iter.next().expect("--warmup requires a value").trim();
+                timing.warmup = Warmup::parse(value).unwrap_or_else(|| panic!("Invalid --warmup {}, expected a count or auto", value));
+            }
+            "--max-bench-time" => {
+                let value: &str = iter.next().expect("--max-bench-time requires a value").trim();
+                timing.max_time = parse_duration(value).unwrap_or_else(|| panic!("Invalid --max-bench-time {}", value));
+            }
+            "--calls" => calls = iter.next().expect("--calls requires a value").trim().parse().expect("Invalid --calls argument"),
+            "--log" => log = Some(iter.next().expect("--log requires a value").trim().to_string()),
+            flag if flag.starts_with("--") => panic!("Unknown flag {}", flag),
+            value => dims.push(value.trim().parse().expect("Invalid matrix dimension")),
+        }
+    }
+
+    let (rows, cols) = match (&dims[..], default_dims) {
+        ([size], _) => (*size, *size),
+        ([rows, cols], _) => (*rows, *cols),
+        ([], Some(dims)) => dims,
+        _ => panic!("bench requires either N or R C"),
+    };
+
+    timing.samples = iterations.max(1);
+    timing.window = iterations.max(1);
+
+    return BenchArgs { rows, cols, seed, calls: calls.max(1), log, timing };
+}
+
+// Calls f calls times, returning the last result; the earlier ones are kept
+// opaque to the optimizer so every call is really made.
+fn repeat<T>(calls: usize, mut f: impl FnMut() -> T) -> T {
+    for _ in 1..calls {
+        black_box(f());
+    }
+
+    return f();
+}
+
+// Appends lines to the CSV log at path, writing the header first if this
+// call creates the file. Each call issues a single write to a file opened in
+// append mode, so concurrent runs append whole rows rather than interleaving.
+fn append_log(path: &str, lines: &str) -> std::io::Result<()> {
+    let (mut file, contents) = match OpenOptions::new().append(true).create_new(true).open(path) {
+        Ok(file) => (file, format!("{}\n{}", LOG_HEADER, lines)),
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => (OpenOptions::new().append(true).open(path)?, lines.to_string()),
+        Err(err) => return Err(err),
+    };
+
+    return file.write_all(contents.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indexed_baselines_match_the_kernels() {
+        for (rows, cols) in [(0, 0), (1, 1), (2, 3), (5, 1), (7, 40), (33, 17)] {
+            let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, 1);
+
+            assert_eq!(dx_indexed(&arr, rows, cols), compute_dx(&arr, rows, cols).data);
+            assert_eq!(dy_indexed(&arr, rows, cols), compute_dy(&arr, rows, cols).data);
+        }
+    }
+}
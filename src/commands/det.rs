@@ -0,0 +1,22 @@
+use std::time::Instant;
+use rmm::linalg::determinant;
+
+// Usage: det N
+//
+// Computes the determinant of a random N x N matrix with entries uniform in
+// [-1, 1) via LU decomposition.
+pub fn run(args: &[String]) {
+    if args.len() != 1 {
+        panic!("det requires exactly one argument: N");
+    }
+
+    let n: usize = args[0].trim().parse().expect("Invalid N argument");
+    let a: Vec<f64> = (0..n * n).map(|_| rand::random::<f64>() * 2.0 - 1.0).collect();
+
+    let start = Instant::now();
+    let det: f64 = determinant(&a, n).expect("Matrix has unexpected dimensions");
+    let duration = start.elapsed();
+
+    println!("=== Determinant of {}x{} ===", n, n);
+    println!("det: {:e} duration: {:?}", det, duration);
+}
@@ -0,0 +1,73 @@
+use std::path::Path;
+use std::process;
+use rmm::io::{read_matrix, StoredMatrix};
+use rmm::stats::{error_metrics, ErrorMetrics};
+
+// Mismatches listed individually before the rest are only counted.
+const SHOWN_MISMATCHES: usize = 10;
+
+// Exit statuses, following diff(1): 0 identical, 1 different, 2 the inputs
+// cannot be compared (unreadable, or different shapes or element types).
+const EXIT_DIFFERENT: i32 = 1;
+const EXIT_INCOMPARABLE: i32 = 2;
+
+// Usage: diff A B
+//
+// Compares two matrices saved with --output-bin or --output-csv (the formats
+// may be mixed) and reports the number of differing elements, the largest
+// absolute difference and where it is, the RMSE and the first few
+// mismatches.
+pub fn run(args: &[String]) {
+    if args.len() != 2 {
+        eprintln!("diff requires exactly two arguments: A B");
+        process::exit(EXIT_INCOMPARABLE);
+    }
+
+    let a: StoredMatrix = load(&args[0]);
+    let b: StoredMatrix = load(&args[1]);
+
+    if (a.rows, a.cols) != (b.rows, b.cols) {
+        eprintln!("shape mismatch: {} is {}x{}, {} is {}x{}", args[0], a.rows, a.cols, args[1], b.rows, b.cols);
+        process::exit(EXIT_INCOMPARABLE);
+    }
+
+    // CSV files carry no element type, so only two binary files can disagree.
+    if let (Some(a_dtype), Some(b_dtype)) = (a.dtype, b.dtype) {
+        if a_dtype != b_dtype {
+            eprintln!("element type mismatch: {} is {}, {} is {}", args[0], a_dtype, args[1], b_dtype);
+            process::exit(EXIT_INCOMPARABLE);
+        }
+    }
+
+    let mismatches: Vec<(usize, i64, i64)> = a.values.iter().zip(&b.values).enumerate()
+        .filter(|(_, (x, y))| x != y).map(|(index, (x, y))| (index, *x, *y)).collect();
+
+    if mismatches.is_empty() {
+        println!("identical: {}x{}", a.rows, a.cols);
+        return;
+    }
+
+    let a_values: Vec<f64> = a.values.iter().map(|&value| value as f64).collect();
+    let b_values: Vec<f64> = b.values.iter().map(|&value| value as f64).collect();
+    let metrics: ErrorMetrics = error_metrics(&a_values, &b_values).expect("shapes were checked");
+
+    println!("{} of {} elements differ, max absolute difference: {} at ({}, {}), rmse: {:.6}", metrics.mismatches, a.values.len(),
+             metrics.max_abs, metrics.max_abs_index / a.cols, metrics.max_abs_index % a.cols, metrics.rmse);
+
+    for (index, x, y) in mismatches.iter().take(SHOWN_MISMATCHES) {
+        println!("  ({}, {}): {} vs {}", index / a.cols, index % a.cols, x, y);
+    }
+
+    if mismatches.len() > SHOWN_MISMATCHES {
+        println!("  ... and {} more", mismatches.len() - SHOWN_MISMATCHES);
+    }
+
+    process::exit(EXIT_DIFFERENT);
+}
+
+fn load(path: &str) -> StoredMatrix {
+    return read_matrix(Path::new(path)).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        process::exit(EXIT_INCOMPARABLE);
+    });
+}
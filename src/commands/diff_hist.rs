@@ -0,0 +1,71 @@
+use std::path::Path;
+use std::process;
+use rmm::histogram::{compare_histograms, read_hist, HistDiff, Histogram};
+
+// Largest distance accepted unless --threshold is given.
+const DEFAULT_THRESHOLD: f64 = 0.01;
+
+// Exit statuses, as for diff: 0 within the threshold, 1 beyond it, 2 the
+// histograms cannot be compared (unreadable, or different bins or ranges).
+const EXIT_DRIFTED: i32 = 1;
+const EXIT_INCOMPARABLE: i32 = 2;
+
+// Usage: diff-hist A B [--threshold T]
+//
+// Compares two value histograms written by --save-hist, e.g. the dx.hist of
+// two runs, as distributions: prints their chi-squared and earth mover's
+// distances (see rmm::histogram::HistDiff, both between 0 and 1) and exits
+// with status 1 if either is above T (default 0.01). Exact values may change
+// from run to run while the distribution stays put; this catches the runs
+// where it does not.
+pub fn run(args: &[String]) {
+    let mut paths: Vec<&str> = Vec::new();
+    let mut threshold: f64 = DEFAULT_THRESHOLD;
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--threshold" => {
+                threshold = iter.next().expect("--threshold requires a value").trim().parse().ok().filter(|value: &f64| *value >= 0.0)
+                    .expect("Invalid --threshold argument");
+            }
+            flag if flag.starts_with("--") => panic!("Unknown flag {}", flag),
+            path => paths.push(path.trim()),
+        }
+    }
+
+    let [a_path, b_path] = paths[..] else {
+        eprintln!("diff-hist requires exactly two histogram files: A B");
+        process::exit(EXIT_INCOMPARABLE);
+    };
+
+    let (a, b): (Histogram, Histogram) = (load(a_path), load(b_path));
+
+    if (a.min, a.max) != (b.min, b.max) {
+        eprintln!("range mismatch: {} covers {}..={}, {} covers {}..={}", a_path, a.min, a.max, b_path, b.min, b.max);
+        process::exit(EXIT_INCOMPARABLE);
+    }
+
+    let diff: HistDiff = compare_histograms(&a.counts, &b.counts).unwrap_or_else(|err| {
+        eprintln!("{} and {}: {}", a_path, b_path, err);
+        process::exit(EXIT_INCOMPARABLE);
+    });
+
+    println!("=== Histogram drift {} vs {} ({} bins) ===", a_path, b_path, a.counts.len());
+    println!("samples: {} vs {}", a.counts.iter().sum::<u64>(), b.counts.iter().sum::<u64>());
+    println!("chi-squared: {:.6} earth mover's: {:.6} threshold: {}", diff.chi_squared, diff.earth_movers, threshold);
+
+    if diff.chi_squared > threshold || diff.earth_movers > threshold {
+        println!("Distributions drifted");
+        process::exit(EXIT_DRIFTED);
+    }
+
+    println!("Distributions match");
+}
+
+fn load(path: &str) -> Histogram {
+    return read_hist(Path::new(path)).unwrap_or_else(|err| {
+        eprintln!("Failed to read {}: {}", path, err);
+        process::exit(EXIT_INCOMPARABLE);
+    });
+}
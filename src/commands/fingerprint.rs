@@ -0,0 +1,22 @@
+use std::path::Path;
+use rmm::stats::fingerprint;
+use crate::input::load_input;
+
+// Usage: fingerprint FILE...
+//
+// Prints the input fingerprint of every matrix file, as a normal run reports
+// it for its input, so saved matrices (e.g. the input.bin of a run artifact)
+// can be matched with the runs that used them. Files are read as --input
+// reads them, so every value must be a u8.
+pub fn run(args: &[String]) {
+    if args.is_empty() {
+        panic!("fingerprint requires at least one matrix file");
+    }
+
+    for path in args {
+        let (arr, rows, cols) = load_input(Path::new(path.trim()));
+        let value: u64 = fingerprint(&arr, rows, cols).expect("Matrix has unexpected dimensions");
+
+        println!("{:016x}  {} ({}x{})", value, path.trim(), rows, cols);
+    }
+}
@@ -0,0 +1,110 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use rmm::io::{write_bin, BIN_MAGIC};
+use rmm::kernels::{compute_dx, compute_dy};
+use rmm::matrix::{construct_randomized_matrix_seeded, Matrix};
+use rmm::stats::fingerprint;
+
+// Where the expected outputs are committed, relative to the repository root.
+const DEFAULT_DIR: &str = "tests/golden";
+
+// (seed, rows, cols) of every golden case. Small enough to commit, and
+// covering a single element, non-square shapes and sizes that are not a
+// multiple of the kernels' inner loop width.
+const CASES: [(u64, usize, usize); 5] = [(1, 1, 1), (2, 7, 5), (3, 16, 16), (4, 5, 33), (5, 40, 17)];
+
+// File in the golden directory pinning the input fingerprint of every case,
+// one "seed{S}_{R}x{C} FINGERPRINT" line each.
+const FINGERPRINTS_FILE: &str = "fingerprints.txt";
+
+// Setting this environment variable to 1 has the same effect as --bless.
+const BLESS_VAR: &str = "RMM_BLESS";
+
+// Usage: golden [--bless] [--dir DIR]
+//
+// Regenerates Dx and Dy for the fixed seeds and sizes in CASES and
+// byte-compares them with the binary files in DIR (tests/golden by
+// default), exiting with status 1 if any differ or are missing. This pins the
+// exact numerical output, so an optimization that changes results fails
+// loudly. The inputs' fingerprints are checked against FINGERPRINTS_FILE the
+// same way, which pins the generator. With --bless (or RMM_BLESS=1) the files are rewritten instead,
+// which is how intentional changes are recorded.
+pub fn run(args: &[String]) {
+    let mut bless: bool = env::var(BLESS_VAR).is_ok_and(|value| value == "1");
+    let mut dir: PathBuf = PathBuf::from(DEFAULT_DIR);
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--bless" => bless = true,
+            "--dir" => dir = PathBuf::from(iter.next().expect("--dir requires a value").trim()),
+            other => panic!("Unknown argument {}", other),
+        }
+    }
+
+    let scratch: PathBuf = env::temp_dir().join(format!("rmm-golden-{}", process::id()));
+    let target: &Path = if bless { &dir } else { &scratch };
+    let mut failures: usize = 0;
+    let mut fingerprints: String = String::new();
+
+    fs::create_dir_all(target).unwrap_or_else(|err| panic!("Failed to create {}: {}", target.display(), err));
+
+    for (seed, rows, cols) in CASES {
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, seed);
+        fingerprints.push_str(&format!("seed{}_{}x{} {:016x}\n", seed, rows, cols, fingerprint(&arr, rows, cols).expect("generated input")));
+        let outputs: [(&str, Matrix<i16>); 2] = [("dx", compute_dx(&arr, rows, cols)), ("dy", compute_dy(&arr, rows, cols))];
+
+        for (name, output) in outputs {
+            let file: String = format!("seed{}_{}x{}_{}.bin", seed, rows, cols, name);
+            let generated: PathBuf = target.join(&file);
+
+            write_bin(&generated, &output.data, output.rows, output.cols).unwrap_or_else(|err| panic!("Failed to write {}: {}", generated.display(), err));
+
+            if bless {
+                println!("blessed {}", generated.display());
+                continue;
+            }
+
+            let expected: PathBuf = dir.join(&file);
+            let status: &str = match fs::read(&expected) {
+                Ok(bytes) if !bytes.starts_with(BIN_MAGIC) => "not a binary matrix",
+                Ok(bytes) if bytes == fs::read(&generated).expect("generated file") => "ok",
+                Ok(_) => "MISMATCH",
+                Err(_) => "MISSING",
+            };
+
+            if status != "ok" {
+                failures += 1;
+            }
+
+            println!("{:<28} {}", file, status);
+        }
+    }
+
+    if bless {
+        let generated: PathBuf = dir.join(FINGERPRINTS_FILE);
+        fs::write(&generated, &fingerprints).unwrap_or_else(|err| panic!("Failed to write {}: {}", generated.display(), err));
+        println!("blessed {}", generated.display());
+    } else {
+        let status: &str = match fs::read_to_string(dir.join(FINGERPRINTS_FILE)) {
+            Ok(expected) if expected == fingerprints => "ok",
+            Ok(_) => "MISMATCH",
+            Err(_) => "MISSING",
+        };
+
+        if status != "ok" {
+            failures += 1;
+        }
+
+        println!("{:<28} {}", FINGERPRINTS_FILE, status);
+    }
+
+    let _ = fs::remove_dir_all(&scratch);
+
+    if failures > 0 {
+        eprintln!("{} golden file(s) differ from {}; rerun with --bless if the change is intended", failures, dir.display());
+        process::exit(1);
+    }
+}
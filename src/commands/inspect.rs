@@ -0,0 +1,52 @@
+use std::path::Path;
+use std::process;
+use rmm::stream::{inspect_stream, StreamInfo, StreamStatus};
+
+// Usage: inspect FILE...
+//
+// Prints the header and footer of streaming-format files written with
+// --output-stream: format version, element type, shape, and either the
+// checksum of a finished file or, for one whose writer stopped midway, how
+// many complete rows are recoverable. Exits with status 1 if any file is
+// truncated or not a valid stream file.
+pub fn run(args: &[String]) {
+    if args.is_empty() {
+        panic!("inspect requires at least one stream file");
+    }
+
+    let mut failed: bool = false;
+
+    for path in args {
+        let path: &str = path.trim();
+
+        match inspect_stream(Path::new(path)) {
+            Ok(info) => {
+                failed |= matches!(info.status, StreamStatus::Truncated { .. });
+                print_info(path, &info);
+            }
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn print_info(path: &str, info: &StreamInfo) {
+    println!("{}: stream format version {}, {}x{} {}", path, info.version, info.rows, info.cols, info.dtype);
+
+    match info.status {
+        StreamStatus::Complete { checksum } => println!("  complete: {} elements, checksum {:016x} verified", info.rows * info.cols, checksum),
+        StreamStatus::Truncated { complete_rows, partial_bytes } => {
+            println!("  truncated: footer missing, {} of {} rows complete and recoverable", complete_rows, info.rows);
+
+            if partial_bytes > 0 {
+                println!("  {} bytes of row {} written", partial_bytes, complete_rows);
+            }
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use rmm::banded::{matmul_banded_dense, BandedMatrix};
+use rmm::error::DimError;
+use rmm::matmul::{autotune_block_size, chain_order, matmul, matmul_blocked, matmul_blocked_cancellable, matmul_bt, matmul_chain, matmul_f32};
+use rmm::matmul::{simd_available, ChainPlan};
+use rmm::matmul::{AUTOTUNE_SIZE, BLOCK_SIZE_CANDIDATES, DEFAULT_BLOCK_SIZE};
+use rmm::ops::transpose;
+use rmm::sparse::CsrMatrix;
+use rmm::stats::approx_eq;
+use rmm::timing::parse_duration;
+use crate::cli::parse_size_spec;
+use crate::timeout;
+
+// Relative tolerance used to compare products whose summation order differs.
+pub(crate) const TOLERANCE: f32 = 1e-4;
+
+// Fraction of A kept non-zero by --sparse unless --density is given.
+const DEFAULT_DENSITY: f32 = 0.05;
+
+// Product implementations selectable with --gemm-variant.
+const VARIANTS: [&str; 5] = ["naive", "blocked", "bt", "simd", "blas"];
+
+// Usage: matmul M K N [--block-size B | --autotune] [--gemm-variant naive|blocked|bt|simd|blas|all]
+//                      [--sparse [--density D]] [--pattern tridiagonal|banded:W|lower|upper] [--timeout T]
+//        matmul N [...] for square matrices
+//        matmul --chain RxC,RxC,... [--explain]
+//
+// Multiplies random f32 matrices with the selected implementations (all by
+// default), reporting each time and whether the results agree with the
+// first one run. --sparse zeroes all but a fraction D (default 0.05) of A,
+// then also times converting it to CSR and the sparse-dense product.
+// --pattern zeroes A outside a band (W diagonals either side of the main
+// one for banded:W, one for tridiagonal) or a triangle, then also times
+// converting it to a BandedMatrix and the banded-dense product, whose work
+// shrinks with the band width rather than with K.
+// --autotune picks the block size by calibration, caching the
+// choice; an explicit --block-size always wins. --timeout stops the run
+// with exit status 124 once T has passed.
+//
+// --chain multiplies random i64 matrices of the given shapes (each one's
+// columns matching the next one's rows) in the cheapest association order,
+// and again left to right, reporting both costs in scalar multiplications,
+// both times and whether the products agree. --explain also prints the cost
+// table of the order search.
+pub fn run(args: &[String]) {
+    let mut dims: Vec<usize> = Vec::new();
+    let mut block_size: Option<usize> = None;
+    let mut autotune: bool = false;
+    let mut variants: Vec<&str> = VARIANTS.to_vec();
+    let mut sparse: bool = false;
+    let mut density: f32 = DEFAULT_DENSITY;
+    let mut pattern: Option<Pattern> = None;
+    let mut chain: Option<Vec<(usize, usize)>> = None;
+    let mut explain: bool = false;
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--block-size" => {
+                block_size = Some(iter.next().expect("--block-size requires a value").trim().parse()
+                    .expect("Invalid --block-size argument"));
+            }
+            "--autotune" => autotune = true,
+            "--chain" => {
+                let value: &str = iter.next().expect("--chain requires a value");
+                chain = Some(parse_size_spec(value).unwrap_or_else(|err| panic!("Invalid --chain: {}", err)));
+            }
+            "--explain" => explain = true,
+            "--timeout" => {
+                let value: &str = iter.next().expect("--timeout requires a value").trim();
+                timeout::arm(parse_duration(value).unwrap_or_else(|| panic!("Invalid --timeout {}", value)));
+            }
+            "--sparse" => sparse = true,
+            "--pattern" => {
+                let value: &str = iter.next().expect("--pattern requires a value").trim();
+                pattern = Some(Pattern::parse(value).unwrap_or_else(|| {
+                    panic!("Unknown --pattern {}, expected tridiagonal, banded:W, lower or upper", value)
+                }));
+            }
+            "--density" => {
+                density = iter.next().expect("--density requires a value").trim().parse()
+                    .expect("Invalid --density argument");
+            }
+            "--gemm-variant" => {
+                let value: &str = iter.next().expect("--gemm-variant requires a value").trim();
+                variants = match VARIANTS.iter().find(|variant| **variant == value) {
+                    Some(variant) => vec![*variant],
+                    None if value == "all" => VARIANTS.to_vec(),
+                    None => panic!("Unknown --gemm-variant {}", value),
+                };
+            }
+            flag if flag.starts_with("--") => panic!("Unknown flag {}", flag),
+            value => dims.push(value.trim().parse().expect("Invalid matrix dimension")),
+        }
+    }
+
+    if let Some(shapes) = chain {
+        if !dims.is_empty() {
+            panic!("--chain takes the matrix shapes from its value, not M K N");
+        }
+
+        run_chain(&shapes, explain);
+        return;
+    }
+
+    if explain {
+        panic!("--explain is only supported with --chain");
+    }
+
+    let (m, k, n) = match dims[..] {
+        [size] => (size, size, size),
+        [m, k, n] => (m, k, n),
+        _ => panic!("matmul requires either N or M K N"),
+    };
+
+    let block_size: usize = match block_size {
+        Some(block_size) => block_size,
+        None if autotune => tuned_block_size(),
+        None => DEFAULT_BLOCK_SIZE,
+    };
+
+    let a: Vec<f32> = (0..m * k).map(|i| {
+        let value: f32 = rand::random::<f32>();
+        let outside: bool = pattern.is_some_and(|pattern| !pattern.contains(i / k, i % k));
+        if outside || (sparse && rand::random::<f32>() >= density) { 0.0 } else { value }
+    }).collect();
+    let b: Vec<f32> = (0..k * n).map(|_| rand::random::<f32>()).collect();
+    let mut reference: Option<Vec<f32>> = None;
+
+    println!("=== Matmul {}x{} * {}x{} (block size {}) ===", m, k, k, n, block_size);
+
+    for variant in variants {
+        if let Some(reason) = skip_reason(variant) {
+            println!("{:<24} skipped: {}", variant, reason);
+            continue;
+        }
+
+        let (c, duration) = timed(|| multiply(variant, &a, &b, m, k, n, block_size).expect("Operands have unexpected dimensions"));
+        let label: String = if variant == "simd" && !simd_available() { "simd (scalar fallback)".to_string() } else { variant.to_string() };
+
+        match &reference {
+            Some(expected) => println!("{:<24} duration: {:?} matches: {}", label, duration, approx_eq(expected, &c, TOLERANCE)),
+            None => {
+                println!("{:<24} duration: {:?}", label, duration);
+                reference = Some(c);
+            }
+        }
+    }
+
+    if sparse {
+        let (csr, convert_duration) = timed(|| CsrMatrix::from_dense(&a, m, k, 0.0).expect("Operand has unexpected dimensions"));
+        let (c, duration) = timed(|| csr.spmm(&b, k, n).expect("Operands have unexpected dimensions"));
+
+        println!("sparse A: nnz {} ({:.2}% dense), CSR conversion: {:?}",
+                 csr.nnz(), 100.0 * csr.nnz() as f64 / (m * k).max(1) as f64, convert_duration);
+
+        match &reference {
+            Some(expected) => println!("{:<24} duration: {:?} matches: {}", "csr spmm", duration, approx_eq(expected, &c, TOLERANCE)),
+            None => println!("{:<24} duration: {:?}", "csr spmm", duration),
+        }
+    }
+
+    if pattern.is_some() {
+        let (banded, convert_duration) = timed(|| BandedMatrix::from_dense(&a, m, k).expect("Operand has unexpected dimensions"));
+        let (c, duration) = timed(|| matmul_banded_dense(&banded, &b, k, n).expect("Operands have unexpected dimensions"));
+
+        println!("banded A: bandwidths {} lower, {} upper ({} of {} columns per row), conversion: {:?}",
+                 banded.bandwidth_lower, banded.bandwidth_upper, banded.width().min(k), k, convert_duration);
+
+        match &reference {
+            Some(expected) => println!("{:<24} duration: {:?} matches: {}", "banded matmul", duration, approx_eq(expected, &c, TOLERANCE)),
+            None => println!("{:<24} duration: {:?}", "banded matmul", duration),
+        }
+    }
+}
+
+// Shape of the non-zeros --pattern leaves in A.
+#[derive(Clone, Copy)]
+enum Pattern {
+    // Entries at most this many diagonals from the main one.
+    Banded(usize),
+    Lower,
+    Upper,
+}
+
+impl Pattern {
+    fn parse(value: &str) -> Option<Pattern> {
+        return match value.split_once(':') {
+            None if value == "tridiagonal" => Some(Pattern::Banded(1)),
+            None if value == "lower" => Some(Pattern::Lower),
+            None if value == "upper" => Some(Pattern::Upper),
+            Some(("banded", width)) => width.trim().parse().ok().map(Pattern::Banded),
+            _ => None,
+        };
+    }
+
+    fn contains(&self, row: usize, col: usize) -> bool {
+        return match self {
+            Pattern::Banded(width) => row.abs_diff(col) <= *width,
+            Pattern::Lower => col <= row,
+            Pattern::Upper => col >= row,
+        };
+    }
+}
+
+// The --chain mode of run.
+fn run_chain(shapes: &[(usize, usize)], explain: bool) {
+    for pair in shapes.windows(2) {
+        if pair[0].1 != pair[1].0 {
+            panic!("--chain shapes {}x{} and {}x{} cannot be multiplied", pair[0].0, pair[0].1, pair[1].0, pair[1].1);
+        }
+    }
+
+    let dims: Vec<usize> = std::iter::once(shapes[0].0).chain(shapes.iter().map(|&(_, cols)| cols)).collect();
+    let plan: ChainPlan = chain_order(&dims);
+    let left_to_right: u64 = dims.windows(2).skip(1).map(|pair| dims[0] as u64 * pair[0] as u64 * pair[1] as u64).sum();
+
+    // Entries in -1..=1 keep long chains of products far from overflowing i64.
+    let matrices: Vec<Vec<i64>> = shapes.iter().map(|&(rows, cols)| (0..rows * cols).map(|_| rand::random::<u8>() as i64 % 3 - 1).collect())
+        .collect();
+    let operands: Vec<(&[i64], usize, usize)> = matrices.iter().zip(shapes).map(|(data, &(rows, cols))| (data.as_slice(), rows, cols)).collect();
+
+    println!("=== Matmul chain of {} matrices, {}x{} result ===", shapes.len(), dims[0], dims[dims.len() - 1]);
+
+    if explain {
+        print_cost_table(&plan);
+    }
+
+    println!("optimal order: {} ({} multiplications)", plan.parenthesize(), plan.total_cost());
+    println!("left to right: {} multiplications ({:.2}x the optimal)", left_to_right, left_to_right as f64 / plan.total_cost().max(1) as f64);
+
+    let (optimal, optimal_duration) = timed(|| matmul_chain(&operands).expect("Operands have unexpected dimensions"));
+    let (naive, naive_duration) = timed(|| operands[1..].iter().fold(matrices[0].clone(), |product, &(data, rows, cols)| {
+        matmul_blocked(&product, dims[0], rows, data, rows, cols, DEFAULT_BLOCK_SIZE).expect("Operands have unexpected dimensions")
+    }));
+
+    println!("{:<24} duration: {:?}", "optimal order", optimal_duration);
+    println!("{:<24} duration: {:?} matches: {}", "left to right", naive_duration, optimal == naive);
+}
+
+// Prints the minimum cost of every sub-chain Ai..Aj, row i and column j, with
+// the split the order search chose for it.
+fn print_cost_table(plan: &ChainPlan) {
+    let n: usize = plan.cost.len();
+    let cells: Vec<Vec<String>> = (0..n).map(|i| (0..n).map(|j| match j {
+        _ if j < i => String::new(),
+        _ if j == i => "0".to_string(),
+        _ => format!("{} @{}", plan.cost[i][j], plan.split[i][j] + 1),
+    }).collect()).collect();
+    let width: usize = cells.iter().flatten().map(String::len).max().unwrap_or(0).max(4);
+
+    println!("cost table (multiplications @ last matrix of the left factor):");
+    println!("{:>5} {}", "", (1..=n).map(|j| format!("{:>width$}", format!("A{}", j))).collect::<Vec<String>>().join(" "));
+
+    for (i, row) in cells.iter().enumerate() {
+        println!("{:>5} {}", format!("A{}", i + 1), row.iter().map(|cell| format!("{:>width$}", cell)).collect::<Vec<String>>().join(" "));
+    }
+}
+
+// Why a variant cannot run in this build or on this machine, if it cannot.
+fn skip_reason(variant: &str) -> Option<String> {
+    if variant != "blas" {
+        return None;
+    }
+
+    #[cfg(feature = "blas")]
+    {
+        return if rmm::blas::blas_available() { None } else { Some(rmm::blas::BlasError::Unavailable.to_string()) };
+    }
+
+    #[cfg(not(feature = "blas"))]
+    {
+        return Some("built without the blas feature".to_string());
+    }
+}
+
+// Computes a * b with the named implementation. The bt variant includes the
+// time to transpose b.
+fn multiply(variant: &str, a: &[f32], b: &[f32], m: usize, k: usize, n: usize, block_size: usize) -> Result<Vec<f32>, DimError> {
+    return match variant {
+        "naive" => matmul(a, m, k, b, k, n),
+        "blocked" => match timeout::token() {
+            Some(cancel) => Ok(timeout::or_exit(matmul_blocked_cancellable(a, m, k, b, k, n, block_size, cancel), "blocked matmul")),
+            None => matmul_blocked(a, m, k, b, k, n, block_size),
+        },
+        "bt" => matmul_bt(a, m, k, &transpose(b, k, n)?, n, k),
+        #[cfg(feature = "blas")]
+        "blas" => Ok(rmm::blas::matmul_blas(a, m, k, b, k, n).expect("BLAS backend failed")),
+        _ => matmul_f32(a, m, k, b, k, n, block_size),
+    };
+}
+
+// Returns the block size cached by an earlier --autotune run, or calibrates
+// and caches one. Failing to write the cache only costs a recalibration next
+// time, so it is reported but not fatal.
+fn tuned_block_size() -> usize {
+    let path: Option<PathBuf> = cache_file();
+
+    if let Some(cached) = path.as_ref().and_then(|path| fs::read_to_string(path).ok()) {
+        if let Ok(block_size) = cached.trim().parse::<usize>() {
+            println!("Using cached block size {}", block_size);
+            return block_size;
+        }
+    }
+
+    let block_size: usize = autotune_block_size(&BLOCK_SIZE_CANDIDATES, AUTOTUNE_SIZE);
+    println!("Calibrated block size {}", block_size);
+
+    if let Some(path) = path {
+        let written = path.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| fs::write(&path, block_size.to_string()));
+
+        if let Err(err) = written {
+            eprintln!("Could not cache block size in {}: {}", path.display(), err);
+        }
+    }
+
+    return block_size;
+}
+
+// Location of the cached block size: $XDG_CACHE_HOME/rmm/block_size, falling
+// back to ~/.cache or %LOCALAPPDATA%.
+fn cache_file() -> Option<PathBuf> {
+    let base: PathBuf = env::var_os("XDG_CACHE_HOME").map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))?;
+
+    return Some(base.join("rmm").join("block_size"));
+}
+
+fn timed<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let value: T = f();
+
+    return (value, start.elapsed());
+}
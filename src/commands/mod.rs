@@ -0,0 +1,15 @@
+// Subcommands of the binary. A normal run (rows and columns as positional
+// arguments) is handled by main.rs itself.
+
+pub mod batch;
+pub mod bench;
+pub mod det;
+pub mod diff;
+pub mod diff_hist;
+pub mod fingerprint;
+pub mod golden;
+pub mod inspect;
+pub mod matmul;
+pub mod self_test;
+pub mod soak;
+pub mod verify_run;
@@ -0,0 +1,316 @@
+use std::process;
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
+use rmm::bits::{compute_dx_bits, compute_dy_bits, BitMatrix};
+#[cfg(feature = "gpu")]
+use rmm::gpu::GpuContext;
+use rmm::kernels::*;
+#[cfg(feature = "ffi")]
+use rmm::ffi::{rmm_compute_dx_into, rmm_compute_dy_into, rmm_dx_output_len, rmm_dy_output_len, RmmStatus, RMM_LEN_OVERFLOW};
+#[cfg(feature = "ffi")]
+use rmm::memstats;
+use rmm::matmul::{matmul_f32, simd_available, DEFAULT_BLOCK_SIZE};
+use rmm::matrix::construct_randomized_matrix_seeded;
+use rmm::nibble::{compute_dx_u4, compute_dy_u4, U4Matrix};
+
+// (seed, rows, cols) of the inputs every check runs on: a single element,
+// matrices narrower and shorter than the kernel, the small sizes compute_dx
+// specializes, and shapes just past them or not a multiple of any loop width.
+const CASES: [(u64, usize, usize); 7] = [(1, 1, 1), (2, 1, 5), (3, 2, 3), (4, 16, 16), (5, 7, 33), (6, 40, 17), (7, 33, 70)];
+
+// Runs a check on one rows x cols input, returning why its output is wrong
+// if it is.
+type CheckFn = Box<dyn Fn(&[u8], usize, usize) -> Result<(), String>>;
+
+// One kernel or mode under test, run on every case. A check that cannot run
+// in this build or on this machine has a skip reason instead.
+struct Check {
+    name: &'static str,
+    run: Result<CheckFn, String>,
+}
+
+// Usage: self-test
+//
+// A quick check of the build on the machine it runs on: runs every kernel and
+// backend available here on a handful of small seeded matrices (CASES),
+// comparing each with a naive reference written out here, and prints one line
+// per check: ok, FAIL with the first wrong element, or skipped with the
+// reason (e.g. a missing feature, CPU extension or GPU). Exits with status 1
+// if any check fails. The modes and options themselves are covered by the
+// unit and integration tests.
+pub fn run(args: &[String]) {
+    if !args.is_empty() {
+        panic!("self-test takes no arguments");
+    }
+
+    let start: Instant = Instant::now();
+    let checks: Vec<Check> = checks();
+    let mut failed: usize = 0;
+
+    println!("=== Self-test ({} cases) ===", CASES.len());
+
+    for check in &checks {
+        let run = match &check.run {
+            Ok(run) => run,
+            Err(reason) => {
+                println!("{:<14} skipped  {}", check.name, reason);
+                continue;
+            }
+        };
+
+        let failure: Option<String> = CASES.iter().find_map(|&(seed, rows, cols)| {
+            let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, seed);
+            run(&arr, rows, cols).err().map(|reason| format!("{}x{} seed {}: {}", rows, cols, seed, reason))
+        });
+
+        match failure {
+            None => println!("{:<14} ok", check.name),
+            Some(reason) => {
+                println!("{:<14} FAIL     {}", check.name, reason);
+                failed += 1;
+            }
+        }
+    }
+
+    let elapsed: Duration = start.elapsed();
+
+    match failed {
+        0 => println!("All {} checks passed or were skipped in {:?}", checks.len(), elapsed),
+        _ => {
+            println!("{} of {} checks failed in {:?}", failed, checks.len(), elapsed);
+            process::exit(1);
+        }
+    }
+}
+
+fn checks() -> Vec<Check> {
+    let check = |name: &'static str, run: fn(&[u8], usize, usize) -> Result<(), String>| Check { name, run: Ok(Box::new(run)) };
+
+    return vec![
+        check("scalar", |arr, rows, cols| {
+            return both(arr, rows, cols, &dim(compute_dx_safe(arr, rows, cols))?, &dim(compute_dy_safe(arr, rows, cols))?);
+        }),
+        unchecked(),
+        check("default", |arr, rows, cols| {
+            return both(arr, rows, cols, &compute_dx(arr, rows, cols).data, &compute_dy(arr, rows, cols).data);
+        }),
+        check("into", |arr, rows, cols| {
+            // Buffers holding garbage, which must be overwritten everywhere.
+            let mut dx: Vec<i16> = vec![i16::MIN; rows * (cols + 2)];
+            let mut dy: Vec<i16> = vec![i16::MAX; (rows + 2) * cols];
+            dim(compute_dxdy_into(arr, rows, cols, &mut dx, &mut dy))?;
+
+            return both(arr, rows, cols, &dx, &dy);
+        }),
+        check("strided", |arr, rows, cols| {
+            // The matrix embedded in a wider buffer, three filler columns per row.
+            let stride: usize = cols + 3;
+            let mut wide: Vec<u8> = vec![u8::MAX; rows * stride];
+
+            for (row, src) in arr.chunks_exact(cols).enumerate() {
+                wide[row * stride..row * stride + cols].copy_from_slice(src);
+            }
+
+            return both(arr, rows, cols, &dim(compute_dx_strided(&wide, rows, cols, stride))?, &dim(compute_dy_strided(&wide, rows, cols, stride))?);
+        }),
+        check("blocked", |arr, rows, cols| {
+            compare("Dy", &naive_dy(arr, rows, cols, 2), &dim(compute_dy_blocked(arr, rows, cols, cols, 3))?, cols)?;
+            return compare("Dy", &naive_dy(arr, rows, cols, 2), &dim(compute_dy_blocked(arr, rows, cols, cols, 1))?, cols);
+        }),
+        parallel(),
+        check("cancellable", |arr, rows, cols| {
+            let cancel: AtomicBool = AtomicBool::new(false);
+            let dx: Vec<i16> = compute_dx_cancellable(arr, rows, cols, &cancel).map_err(|err| err.to_string())?;
+            let dy: Vec<i16> = compute_dy_cancellable(arr, rows, cols, &cancel).map_err(|err| err.to_string())?;
+
+            return both(arr, rows, cols, &dx, &dy);
+        }),
+        check("profiled", |arr, rows, cols| {
+            return both(arr, rows, cols, &dim(compute_dx_profiled(arr, rows, cols))?.0, &dim(compute_dy_profiled(arr, rows, cols))?.0);
+        }),
+        check("bits", |arr, rows, cols| {
+            // The low bit of every element as a 0/1 matrix.
+            let mask: Vec<u8> = arr.iter().map(|value| value & 1).collect();
+            let packed: BitMatrix = dim(BitMatrix::from_u8(&mask, rows, cols))?;
+
+            compare("Dx", &naive_dx(&mask, rows, cols, 2), &compute_dx_bits(&packed), cols + 2)?;
+            return compare("Dy", &naive_dy(&mask, rows, cols, 2), &compute_dy_bits(&packed), cols);
+        }),
+        check("u4", |arr, rows, cols| {
+            // The high nibble of every element, packed and unpacked again
+            // before the normal kernels run on it.
+            let scaled: Vec<u8> = arr.iter().map(|value| value >> 4).collect();
+            let packed: U4Matrix = dim(U4Matrix::pack(&scaled, rows, cols))?;
+            let unpacked: Vec<u8> = packed.unpack();
+
+            if unpacked != scaled {
+                return Err("unpack does not return the packed values".to_string());
+            }
+
+            let widen = |data: Vec<i16>| data.iter().map(|&value| value as i32).collect::<Vec<i32>>();
+
+            compare("Dx", &widen(compute_dx(&unpacked, rows, cols).data), &compute_dx_u4(&packed), cols + 2)?;
+            return compare("Dy", &widen(compute_dy(&unpacked, rows, cols).data), &compute_dy_u4(&packed), cols);
+        }),
+        ffi(),
+        Check {
+            name: "matmul-simd",
+            run: match simd_available() {
+                true => Ok(Box::new(|arr: &[u8], rows: usize, cols: usize| {
+                    // arr times its transpose. Small integers sum exactly in
+                    // f32, so any summation order gives the same product.
+                    let a: Vec<f32> = arr.iter().map(|&value| (value % 16) as f32).collect();
+                    let a_t: Vec<f32> = (0..cols * rows).map(|index| a[(index % rows) * cols + index / rows]).collect();
+                    let product: Vec<f32> = dim(matmul_f32(&a, rows, cols, &a_t, cols, rows, DEFAULT_BLOCK_SIZE))?;
+                    let expected: Vec<i32> = (0..rows * rows).map(|index| {
+                        let (i, j): (usize, usize) = (index / rows, index % rows);
+                        (0..cols).map(|k| a[i * cols + k] as i32 * a[j * cols + k] as i32).sum()
+                    }).collect();
+
+                    return compare("product", &expected, &product.iter().map(|&value| value as i32).collect::<Vec<i32>>(), rows);
+                })),
+                false => Err("AVX2 and FMA not available on this CPU".to_string()),
+            },
+        },
+        gpu(),
+    ];
+}
+
+#[cfg(feature = "unsafe-fast")]
+fn unchecked() -> Check {
+    return Check { name: "unchecked", run: Ok(Box::new(|arr: &[u8], rows: usize, cols: usize| {
+        return both(arr, rows, cols, &dim(compute_dx_unchecked(arr, rows, cols))?, &dim(compute_dy_unchecked(arr, rows, cols))?);
+    })) };
+}
+
+#[cfg(not(feature = "unsafe-fast"))]
+fn unchecked() -> Check {
+    return Check { name: "unchecked", run: Err("built without the unsafe-fast feature".to_string()) };
+}
+
+#[cfg(feature = "ffi")]
+fn ffi() -> Check {
+    return Check { name: "ffi", run: Ok(Box::new(|arr: &[u8], rows: usize, cols: usize| {
+        let (dx_len, dy_len): (usize, usize) = (rmm_dx_output_len(rows, cols), rmm_dy_output_len(rows, cols));
+        // One element more than needed: only the first dx_len are written.
+        let (mut dx, mut dy): (Vec<i16>, Vec<i16>) = (vec![7; dx_len + 1], vec![7; dy_len]);
+
+        // The writes into the caller's buffers allocate nothing (counted in
+        // mem-stats builds).
+        memstats::reset();
+        // SAFETY: arr, dx and dy are live buffers of the lengths passed.
+        let status: [RmmStatus; 2] = unsafe { [
+            rmm_compute_dx_into(arr.as_ptr(), rows, cols, dx.as_mut_ptr(), dx.len()),
+            rmm_compute_dy_into(arr.as_ptr(), rows, cols, dy.as_mut_ptr(), dy.len()),
+        ] };
+        let allocations: usize = memstats::snapshot().alloc_count;
+
+        if status != [RmmStatus::Ok; 2] || allocations != 0 {
+            return Err(format!("status {:?} after {} allocations", status, allocations));
+        }
+
+        if dx[dx_len] != 7 {
+            return Err("Dx written past its length".to_string());
+        }
+
+        compare("Dx", &compute_dx(arr, rows, cols).data.iter().map(|&value| value as i32).collect::<Vec<i32>>(), &dx[..dx_len], cols + 2)?;
+        compare("Dy", &compute_dy(arr, rows, cols).data.iter().map(|&value| value as i32).collect::<Vec<i32>>(), &dy, cols)?;
+
+        // SAFETY: every call fails its checks before touching a buffer.
+        let failures: [RmmStatus; 4] = unsafe { [
+            rmm_compute_dx_into(std::ptr::null(), rows, cols, dx.as_mut_ptr(), dx.len()),
+            rmm_compute_dy_into(arr.as_ptr(), rows, cols, std::ptr::null_mut(), dy.len()),
+            rmm_compute_dy_into(arr.as_ptr(), rows, cols, dy.as_mut_ptr(), dy_len - 1),
+            rmm_compute_dx_into(arr.as_ptr(), usize::MAX / 2, 3, dx.as_mut_ptr(), dx.len()),
+        ] };
+        let expected: [RmmStatus; 4] = [RmmStatus::NullPointer, RmmStatus::NullPointer, RmmStatus::BufferTooSmall, RmmStatus::DimensionOverflow];
+
+        if failures != expected || rmm_dx_output_len(usize::MAX, 1) != RMM_LEN_OVERFLOW {
+            return Err(format!("status {:?}, expected {:?}", failures, expected));
+        }
+
+        return Ok(());
+    })) };
+}
+
+#[cfg(not(feature = "ffi"))]
+fn ffi() -> Check {
+    return Check { name: "ffi", run: Err("built without the ffi feature".to_string()) };
+}
+
+#[cfg(feature = "parallel")]
+fn parallel() -> Check {
+    // More threads than some cases have rows.
+    const THREADS: usize = 3;
+
+    return Check { name: "parallel", run: Ok(Box::new(|arr: &[u8], rows: usize, cols: usize| {
+        return both(arr, rows, cols, &dim(compute_dx_par(arr, rows, cols, THREADS))?, &dim(compute_dy_par(arr, rows, cols, THREADS))?);
+    })) };
+}
+
+#[cfg(not(feature = "parallel"))]
+fn parallel() -> Check {
+    return Check { name: "parallel", run: Err("built without the parallel feature".to_string()) };
+}
+
+#[cfg(feature = "gpu")]
+fn gpu() -> Check {
+    return Check { name: "gpu", run: match GpuContext::new() {
+        Ok(context) => Ok(Box::new(move |arr: &[u8], rows: usize, cols: usize| {
+            let (dx, _) = context.compute_dx(arr, rows, cols).map_err(|err| err.to_string())?;
+            let (dy, _) = context.compute_dy(arr, rows, cols).map_err(|err| err.to_string())?;
+
+            compare("Dx", &naive_dx(arr, rows, cols, 2), &dx, cols + 2)?;
+            return compare("Dy", &naive_dy(arr, rows, cols, 2), &dy, cols);
+        })),
+        Err(err) => Err(format!("no usable GPU: {}", err)),
+    } };
+}
+
+#[cfg(not(feature = "gpu"))]
+fn gpu() -> Check {
+    return Check { name: "gpu", run: Err("built without the gpu feature".to_string()) };
+}
+
+// Checks a Dx and a Dy with the usual padding of 2 against the references.
+fn both(arr: &[u8], rows: usize, cols: usize, dx: &[i16], dy: &[i16]) -> Result<(), String> {
+    compare("Dx", &naive_dx(arr, rows, cols, 2), dx, cols + 2)?;
+    return compare("Dy", &naive_dy(arr, rows, cols, 2), dy, cols);
+}
+
+// Names the first element of found, a matrix cols wide, that differs from
+// expected.
+fn compare<T: Copy + Into<f64>>(what: &str, expected: &[i32], found: &[T], cols: usize) -> Result<(), String> {
+    if found.len() != expected.len() {
+        return Err(format!("{} has {} elements, expected {}", what, found.len(), expected.len()));
+    }
+
+    return match expected.iter().zip(found).position(|(&expected, &found)| expected as f64 != found.into()) {
+        None => Ok(()),
+        Some(index) => Err(format!("{} differs at ({}, {}): expected {}, got {}", what, index / cols.max(1), index % cols.max(1),
+                                   expected[index], found[index].into())),
+    };
+}
+
+fn dim<T, E: ToString>(result: Result<T, E>) -> Result<T, String> {
+    return result.map_err(|err| err.to_string());
+}
+
+// [-1, 0, 1] along the rows with pad output columns of padding, one element
+// at a time: output column j is centered on input column j + 1 - pad / 2, so
+// it is input column j - 1 - pad / 2 minus input column j + 1 - pad / 2,
+// either of which may lie outside the matrix and count as 0.
+fn naive_dx(arr: &[u8], rows: usize, cols: usize, pad: usize) -> Vec<i32> {
+    let at = |row: usize, col: isize| if (0..cols as isize).contains(&col) { arr[row * cols + col as usize] as i32 } else { 0 };
+    let offset: isize = 1 - (pad / 2) as isize;
+
+    return (0..rows).flat_map(|row| (0..cols + pad).map(move |col| at(row, col as isize + offset - 2) - at(row, col as isize + offset))).collect();
+}
+
+// naive_dx along the columns.
+fn naive_dy(arr: &[u8], rows: usize, cols: usize, pad: usize) -> Vec<i32> {
+    let at = |row: isize, col: usize| if (0..rows as isize).contains(&row) { arr[row as usize * cols + col] as i32 } else { 0 };
+    let offset: isize = 1 - (pad / 2) as isize;
+
+    return (0..rows + pad).flat_map(|row| (0..cols).map(move |col| at(row as isize + offset - 2, col) - at(row as isize + offset, col))).collect();
+}
\ No newline at end of file
@@ -0,0 +1,300 @@
+use std::fmt::Display;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+#[cfg(feature = "unsafe-fast")]
+use rmm::kernels::{compute_dx_unchecked, compute_dy_unchecked};
+#[cfg(feature = "parallel")]
+use rmm::kernels::{compute_dx_par, compute_dy_par};
+use rmm::kernels::{compute_dx_into, compute_dx_safe, compute_dy_into, compute_dy_safe};
+use rmm::matrix::fill_randomized_seeded_par;
+use rmm::memstats;
+use rmm::stats::{checksum, checksum_bytes, CHECKSUM_BASIS};
+use rmm::timing::parse_duration;
+
+// Seed of the input unless --seed is given.
+const DEFAULT_SEED: u64 = 0;
+
+// How often a progress line is printed unless --report-every is given.
+const DEFAULT_REPORT_EVERY: Duration = Duration::from_secs(60);
+
+// Differing elements listed per divergence; the rest are only counted.
+const LISTED_MISMATCHES: usize = 10;
+
+// Computes Dx and Dy of a rows x cols input with threads threads into the
+// two buffers, which already have the right lengths.
+type VariantFn = fn(&[u8], usize, usize, usize, &mut Vec<i16>, &mut Vec<i16>);
+
+// One kernel implementation under soak. threads is what it runs with, 1 for
+// the serial ones.
+struct Variant {
+    name: &'static str,
+    threads: usize,
+    run: VariantFn,
+}
+
+// The results of a variant on the first iteration, which every later
+// iteration must reproduce.
+struct Baseline {
+    dx: Vec<i16>,
+    dy: Vec<i16>,
+    dx_checksum: u64,
+    dy_checksum: u64,
+}
+
+// Usage: soak --duration D --size N [--seed S] [--threads T] [--report-every D]
+//
+// Hunts for nondeterminism. Regenerates the same seeded N x N input (with the
+// parallel generator on T threads, default all cores) and runs every kernel
+// variant of this build on it over and over for D (e.g. 1h, 10m, 30s),
+// comparing checksums of the input and of each Dx and Dy with those of the
+// first iteration. The first iteration of every variant is also compared
+// with the "default" one. Each divergence is printed as it is seen, with the
+// iteration, variant, thread count and the first differing indices, and a
+// progress line is printed every --report-every (default 1m). Exits with
+// status 1 at the end if anything diverged.
+//
+// All buffers are allocated before the first iteration and reused, so memory
+// stays flat however long the soak runs; the variants without an _into form
+// allocate a result that is freed again before the next one (mem-stats
+// builds print the live heap in the progress lines). At least two iterations
+// run, so a short --duration makes a quick smoke test.
+pub fn run(args: &[String]) {
+    let (size, duration, seed, threads, report_every) = parse_args(args);
+    let variants: Vec<Variant> = variants(threads);
+    let (dx_len, dy_len): (usize, usize) = (size * (size + 2), (size + 2) * size);
+
+    let mut input: Vec<u8> = vec![0; size * size];
+    let mut dx: Vec<i16> = vec![0; dx_len];
+    let mut dy: Vec<i16> = vec![0; dy_len];
+
+    println!("=== Soak {}x{} for {:?}, seed {}, {} threads ===", size, size, duration, seed, threads);
+    println!("variants: {}", variants.iter().map(|variant| format!("{} (threads {})", variant.name, variant.threads)).collect::<Vec<String>>().join(", "));
+
+    fill_randomized_seeded_par(&mut input, seed, threads);
+    let reference_input: Vec<u8> = input.clone();
+    let input_checksum: u64 = checksum_bytes(CHECKSUM_BASIS, &input);
+
+    let baselines: Vec<Baseline> = variants.iter().map(|variant| baseline(variant, &input, size, &mut dx, &mut dy)).collect();
+
+    let mut divergences: usize = 0;
+
+    // The variants must agree with each other before they can be held to
+    // their own first results.
+    for (variant, baseline) in variants.iter().zip(&baselines).skip(1) {
+        for (what, expected, found, cols) in [("Dx", &baselines[0].dx, &baseline.dx, size + 2), ("Dy", &baselines[0].dy, &baseline.dy, size)] {
+            if expected != found {
+                report(1, (variant.name, variant.threads), &format!("{} against default", what), expected, found, cols);
+                divergences += 1;
+            }
+        }
+    }
+
+    let start: Instant = Instant::now();
+    let mut last_report: Instant = start;
+    let mut iteration: usize = 1;
+
+    while iteration < 2 || start.elapsed() < duration {
+        iteration += 1;
+
+        fill_randomized_seeded_par(&mut input, seed, threads);
+
+        if checksum_bytes(CHECKSUM_BASIS, &input) != input_checksum {
+            report(iteration, ("generator", threads), "input", &reference_input, &input, size);
+            divergences += 1;
+        }
+
+        for (variant, baseline) in variants.iter().zip(&baselines) {
+            divergences += check(iteration, variant, baseline, &input, size, &mut dx, &mut dy);
+        }
+
+        if last_report.elapsed() >= report_every {
+            last_report = Instant::now();
+            print_progress(iteration, start.elapsed(), divergences);
+        }
+    }
+
+    print_progress(iteration, start.elapsed(), divergences);
+
+    if divergences > 0 {
+        println!("FAIL: {} divergences in {} iterations", divergences, iteration);
+        process::exit(1);
+    }
+
+    println!("ok: {} iterations of {} variants reproduced the first one", iteration, variants.len());
+}
+
+// Runs variant on the size x size input into dx and dy and records the
+// results it must reproduce from now on.
+fn baseline(variant: &Variant, input: &[u8], size: usize, dx: &mut Vec<i16>, dy: &mut Vec<i16>) -> Baseline {
+    (variant.run)(input, size, size, variant.threads, dx, dy);
+
+    return Baseline { dx_checksum: checksum(CHECKSUM_BASIS, dx), dy_checksum: checksum(CHECKSUM_BASIS, dy), dx: dx.clone(), dy: dy.clone() };
+}
+
+// Runs variant again and reports every result that differs from baseline,
+// returning how many did (0, 1 or 2).
+fn check(iteration: usize, variant: &Variant, baseline: &Baseline, input: &[u8], size: usize, dx: &mut Vec<i16>, dy: &mut Vec<i16>) -> usize {
+    let mut divergences: usize = 0;
+
+    (variant.run)(input, size, size, variant.threads, dx, dy);
+
+    if checksum(CHECKSUM_BASIS, dx) != baseline.dx_checksum {
+        report(iteration, (variant.name, variant.threads), "Dx", &baseline.dx, dx, size + 2);
+        divergences += 1;
+    }
+
+    if checksum(CHECKSUM_BASIS, dy) != baseline.dy_checksum {
+        report(iteration, (variant.name, variant.threads), "Dy", &baseline.dy, dy, size);
+        divergences += 1;
+    }
+
+    return divergences;
+}
+
+// Every kernel variant of this build, "default" first.
+fn variants(threads: usize) -> Vec<Variant> {
+    #[cfg(not(feature = "parallel"))]
+    let _ = threads;
+
+    return vec![
+        Variant { name: "default", threads: 1, run: |arr, rows, cols, _, dx, dy| {
+            compute_dx_into(arr, rows, cols, dx).expect("buffers match the input");
+            compute_dy_into(arr, rows, cols, dy).expect("buffers match the input");
+        } },
+        Variant { name: "scalar", threads: 1, run: |arr, rows, cols, _, dx, dy| {
+            *dx = compute_dx_safe(arr, rows, cols).expect("generated input");
+            *dy = compute_dy_safe(arr, rows, cols).expect("generated input");
+        } },
+        #[cfg(feature = "unsafe-fast")]
+        Variant { name: "unchecked", threads: 1, run: |arr, rows, cols, _, dx, dy| {
+            *dx = compute_dx_unchecked(arr, rows, cols).expect("generated input");
+            *dy = compute_dy_unchecked(arr, rows, cols).expect("generated input");
+        } },
+        #[cfg(feature = "parallel")]
+        Variant { name: "parallel", threads, run: |arr, rows, cols, threads, dx, dy| {
+            *dx = compute_dx_par(arr, rows, cols, threads).expect("generated input");
+            *dy = compute_dy_par(arr, rows, cols, threads).expect("generated input");
+        } },
+    ];
+}
+
+// Prints one divergence, see describe.
+fn report<T: PartialEq + Display>(iteration: usize, source: (&str, usize), what: &str, expected: &[T], found: &[T], cols: usize) {
+    println!("{}", describe(iteration, source, what, expected, found, cols));
+}
+
+// One divergence of what, computed by source (a name and thread count): how
+// many elements of found, a matrix cols wide, differ from expected, and the
+// first LISTED_MISMATCHES of them.
+fn describe<T: PartialEq + Display>(iteration: usize, source: (&str, usize), what: &str, expected: &[T], found: &[T], cols: usize) -> String {
+    let differing: Vec<usize> = (0..expected.len().min(found.len())).filter(|&index| expected[index] != found[index]).collect();
+    let listed: Vec<String> = differing.iter().take(LISTED_MISMATCHES).map(|&index| {
+        format!("({}, {}) expected {} got {}", index / cols.max(1), index % cols.max(1), expected[index], found[index])
+    }).collect();
+
+    return format!("DIVERGENCE iteration {} variant {} threads {} {}: {} of {} elements differ: {}{}", iteration, source.0, source.1, what,
+                   differing.len(), expected.len(), listed.join(", "), if differing.len() > LISTED_MISMATCHES { ", ..." } else { "" });
+}
+
+fn print_progress(iteration: usize, elapsed: Duration, divergences: usize) {
+    let heap: String = match cfg!(feature = "mem-stats") {
+        true => format!(", heap {} bytes", memstats::snapshot().current_bytes),
+        false => String::new(),
+    };
+
+    println!("iteration {} after {:.1?}: {} divergences{}", iteration, elapsed, divergences, heap);
+}
+
+fn parse_args(args: &[String]) -> (usize, Duration, u64, usize, Duration) {
+    let mut size: Option<usize> = None;
+    let mut duration: Option<Duration> = None;
+    let mut seed: u64 = DEFAULT_SEED;
+    let mut threads: usize = thread::available_parallelism().map_or(1, |cores| cores.get());
+    let mut report_every: Duration = DEFAULT_REPORT_EVERY;
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().unwrap_or_else(|| panic!("{} requires a value", arg)).trim();
+
+        match arg.as_str() {
+            "--size" => size = Some(value().parse().expect("Invalid --size argument")),
+            "--duration" => {
+                let value: &str = value();
+                duration = Some(parse_duration(value).unwrap_or_else(|| panic!("Invalid --duration {}", value)));
+            }
+            "--seed" => seed = value().parse().expect("Invalid --seed argument"),
+            "--threads" => threads = value().parse().expect("Invalid --threads argument"),
+            "--report-every" => {
+                let value: &str = value();
+                report_every = parse_duration(value).unwrap_or_else(|| panic!("Invalid --report-every {}", value));
+            }
+            other => panic!("Unknown soak argument {}", other),
+        }
+    }
+
+    let size: usize = size.expect("soak requires --size N");
+    let duration: Duration = duration.expect("soak requires --duration D, e.g. 1h or 30s");
+
+    return (size, duration, seed, threads.max(1), report_every);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmm::matrix::construct_randomized_matrix_seeded;
+
+    fn buffers(size: usize) -> (Vec<i16>, Vec<i16>) {
+        return (vec![0; size * (size + 2)], vec![0; (size + 2) * size]);
+    }
+
+    #[test]
+    fn every_variant_reproduces_the_default_and_itself() {
+        let size: usize = 9;
+        let input: Vec<u8> = construct_randomized_matrix_seeded(size, size, 3);
+        let (mut dx, mut dy): (Vec<i16>, Vec<i16>) = buffers(size);
+        let variants: Vec<Variant> = variants(4);
+        let reference: Baseline = baseline(&variants[0], &input, size, &mut dx, &mut dy);
+
+        for variant in &variants {
+            assert_eq!(check(2, variant, &reference, &input, size, &mut dx, &mut dy), 0, "variant {}", variant.name);
+        }
+    }
+
+    #[test]
+    fn a_diverging_variant_is_counted_once_per_result() {
+        let size: usize = 6;
+        let input: Vec<u8> = construct_randomized_matrix_seeded(size, size, 5);
+        let (mut dx, mut dy): (Vec<i16>, Vec<i16>) = buffers(size);
+        let reference: Baseline = baseline(&variants(1)[0], &input, size, &mut dx, &mut dy);
+        // The default kernels with one Dx element and the last Dy element off
+        // by one, standing in for a nondeterministic variant.
+        let variant: Variant = Variant { name: "faulty", threads: 1, run: |arr, rows, cols, _, dx, dy| {
+            compute_dx_into(arr, rows, cols, dx).unwrap();
+            compute_dy_into(arr, rows, cols, dy).unwrap();
+            dx[cols + 2 + 3] += 1;
+            *dy.last_mut().unwrap() -= 1;
+        } };
+
+        assert_eq!(check(2, &variant, &reference, &input, size, &mut dx, &mut dy), 2);
+        // The faulty results are left in the buffers for the report.
+        assert_eq!(dx[size + 2 + 3], reference.dx[size + 2 + 3] + 1);
+    }
+
+    #[test]
+    fn a_divergence_lists_the_first_differing_indices() {
+        let expected: Vec<i16> = vec![0; 8];
+        let mut found: Vec<i16> = expected.clone();
+        found[5] = 7;
+
+        assert_eq!(describe(3, ("parallel", 4), "Dx", &expected, &found, 4),
+                   "DIVERGENCE iteration 3 variant parallel threads 4 Dx: 1 of 8 elements differ: (1, 1) expected 0 got 7");
+
+        let found: Vec<i16> = vec![1; 12];
+        let line: String = describe(2, ("scalar", 1), "Dy", &[0i16; 12], &found, 3);
+
+        assert!(line.contains(": 12 of 12 elements differ: (0, 0) expected 0 got 1,"), "{}", line);
+        assert!(line.contains("(3, 0) expected 0 got 1, ..."), "{}", line);
+        assert_eq!(line.matches("expected").count(), LISTED_MISMATCHES);
+    }
+}
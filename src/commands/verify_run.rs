@@ -0,0 +1,83 @@
+use std::fs;
+use std::path::Path;
+use std::process;
+use serde_json::Value;
+use crate::artifact::{gradient_stages, Stage};
+use crate::cli::{self, Options};
+use crate::{build_input, compute_gradients, Gradients, Input};
+
+// Usage: verify-run DIR
+//
+// Reproduces the run artifact in DIR: parses its recorded command line,
+// regenerates the input from the recorded seed (or reads the recorded input
+// file), reruns the pipeline and compares the checksum of every stage with
+// the one in manifest.json. Prints one line per stage and exits with status 1
+// if any differs, naming the first stage that diverged; later stages usually
+// differ only because an earlier one did.
+pub fn run(args: &[String]) {
+    let [dir] = args else {
+        panic!("verify-run requires exactly one run artifact directory");
+    };
+
+    let path = Path::new(dir.trim()).join("manifest.json");
+    let text: String = fs::read_to_string(&path).unwrap_or_else(|err| panic!("Failed to read {}: {}", path.display(), err));
+    let manifest: Value = serde_json::from_str(&text).unwrap_or_else(|err| panic!("Invalid manifest {}: {}", path.display(), err));
+
+    let recorded: Vec<(String, u64)> = manifest["pipeline"].as_array()
+        .unwrap_or_else(|| panic!("{} has no pipeline; it was written by a version without verify-run support", path.display()))
+        .iter()
+        .map(|stage| {
+            let name: &str = stage["stage"].as_str().expect("Pipeline stage without a name");
+            let checksum: u64 = stage["checksum"].as_str().and_then(|hex| u64::from_str_radix(hex, 16).ok())
+                .unwrap_or_else(|| panic!("Pipeline stage {} has no valid checksum", name));
+            (name.to_string(), checksum)
+        })
+        .collect();
+
+    let args: Vec<String> = manifest["args"].as_array().expect("Manifest has no args").iter()
+        .map(|arg| arg.as_str().expect("Manifest args must be strings").to_string())
+        .collect();
+
+    println!("Artifact version {}, verifying with {}", manifest["version"].as_str().unwrap_or("unknown"), env!("CARGO_PKG_VERSION"));
+
+    let stages: Vec<Stage> = rerun(&args, manifest["seed"].as_u64());
+    let mut diverged: Option<&str> = None;
+
+    for (name, expected) in &recorded {
+        match stages.iter().find(|stage| stage.name == name) {
+            Some(stage) if stage.checksum == *expected => println!("{:<10} ok        {:016x}  {}", name, expected, stage.description),
+            Some(stage) => {
+                println!("{:<10} MISMATCH  expected {:016x}, got {:016x}  {}", name, expected, stage.checksum, stage.description);
+                diverged = diverged.or(Some(name));
+            }
+            None => {
+                println!("{:<10} MISSING   not produced by this version", name);
+                diverged = diverged.or(Some(name));
+            }
+        }
+    }
+
+    match diverged {
+        Some(name) => {
+            println!("Run diverged at stage {}", name);
+            process::exit(1);
+        }
+        None => println!("Run reproduced: all {} stages match", recorded.len()),
+    }
+}
+
+// Reruns the recorded command line with the recorded seed, writing nothing,
+// and returns the stages it went through.
+fn rerun(args: &[String], seed: Option<u64>) -> Vec<Stage> {
+    let mut options: Options = cli::parse_args(args);
+    options.seed = seed;
+    options.output_dir = None;
+    options.cache_dir = None;
+
+    let Input { arr, rows, cols, mut stages } = build_input(&options, seed, true);
+    let gradients: Gradients = compute_gradients(&arr, rows, cols, &options);
+
+    stages.extend(gradient_stages(&options, &gradients));
+
+    return stages;
+}
@@ -0,0 +1,298 @@
+use crate::error::{check_len, DimError};
+use crate::index::flat;
+
+// Which neighbours of an element belong to the same component.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Connectivity {
+    // The elements above, below, left and right.
+    Four,
+    // Those and the four diagonal neighbours.
+    #[default]
+    Eight,
+}
+
+// Size and bounding box of one component, from component_stats. The box is
+// height x width with its top-left element at (row, col).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ComponentStats {
+    pub label: u32,
+    pub pixels: usize,
+    pub row: usize,
+    pub col: usize,
+    pub height: usize,
+    pub width: usize,
+}
+
+// Labels the connected components of the non-zero elements of a rows x cols
+// mask with the classic two-pass algorithm: the first pass hands out
+// provisional labels and records which ones touch in a union-find forest,
+// the second replaces each by its final label. Returns the labels, 0 for
+// background and 1..=count numbered in the order components are first met
+// in row-major order, and the number of components. Masks with more
+// elements than a u32 can label are rejected.
+pub fn label_components(mask: &[u8], rows: usize, cols: usize, connectivity: Connectivity) -> Result<(Vec<u32>, usize), DimError> {
+    check_len(mask.len(), rows, cols)?;
+
+    if mask.len() >= u32::MAX as usize {
+        return Err(DimError::Mismatch { what: "labelled element count", expected: u32::MAX as usize - 1, found: mask.len() });
+    }
+
+    let mut labels: Vec<u32> = vec![0; rows * cols];
+    // parent[label] of every provisional label; entry 0 is the background.
+    let mut parent: Vec<u32> = vec![0];
+
+    for row in 0..rows {
+        for col in 0..cols {
+            let index: usize = flat(row, col, cols);
+
+            if mask[index] == 0 {
+                continue;
+            }
+
+            // Neighbours already visited: left, then above-left, above and
+            // above-right for eight-connectivity.
+            let mut neighbours: [u32; 4] = [0; 4];
+
+            if col > 0 {
+                neighbours[0] = labels[index - 1];
+            }
+
+            if row > 0 {
+                neighbours[1] = labels[index - cols];
+
+                if connectivity == Connectivity::Eight {
+                    if col > 0 {
+                        neighbours[2] = labels[index - cols - 1];
+                    }
+
+                    if col + 1 < cols {
+                        neighbours[3] = labels[index - cols + 1];
+                    }
+                }
+            }
+
+            let label: u32 = match neighbours.iter().copied().filter(|&label| label != 0).min() {
+                Some(label) => label,
+                None => {
+                    parent.push(parent.len() as u32);
+                    (parent.len() - 1) as u32
+                }
+            };
+
+            for &neighbour in neighbours.iter().filter(|&&neighbour| neighbour != 0) {
+                union(&mut parent, label, neighbour);
+            }
+
+            labels[index] = label;
+        }
+    }
+
+    // Final labels of the roots, in the order their components are first
+    // met; provisional labels grow in row-major order, so walking them in
+    // increasing order meets every root before the labels pointing to it.
+    let mut resolved: Vec<u32> = vec![0; parent.len()];
+    let mut count: usize = 0;
+
+    for label in 1..parent.len() {
+        let root: usize = find(&mut parent, label as u32) as usize;
+
+        if resolved[root] == 0 {
+            count += 1;
+            resolved[root] = count as u32;
+        }
+
+        resolved[label] = resolved[root];
+    }
+
+    for label in labels.iter_mut() {
+        *label = resolved[*label as usize];
+    }
+
+    return Ok((labels, count));
+}
+
+// Pixel count and bounding box of every component of labels as returned by
+// label_components, indexed by label - 1.
+pub fn component_stats(labels: &[u32], rows: usize, cols: usize, count: usize) -> Result<Vec<ComponentStats>, DimError> {
+    check_len(labels.len(), rows, cols)?;
+
+    // (pixels, first row, first col, last row, last col) of every label.
+    let mut extents: Vec<(usize, usize, usize, usize, usize)> = vec![(0, usize::MAX, usize::MAX, 0, 0); count];
+
+    for (index, &label) in labels.iter().enumerate() {
+        if label == 0 {
+            continue;
+        }
+
+        let Some(extent) = extents.get_mut(label as usize - 1) else {
+            return Err(DimError::Mismatch { what: "component label", expected: count, found: label as usize });
+        };
+        let (row, col): (usize, usize) = (index / cols, index % cols);
+
+        *extent = (extent.0 + 1, extent.1.min(row), extent.2.min(col), extent.3.max(row), extent.4.max(col));
+    }
+
+    return Ok(extents.iter().enumerate().map(|(index, &(pixels, first_row, first_col, last_row, last_col))| match pixels {
+        0 => ComponentStats { label: index as u32 + 1, pixels, row: 0, col: 0, height: 0, width: 0 },
+        _ => ComponentStats {
+            label: index as u32 + 1, pixels, row: first_row, col: first_col,
+            height: last_row - first_row + 1, width: last_col - first_col + 1,
+        },
+    }).collect());
+}
+
+// Root of label's tree, halving the path on the way.
+fn find(parent: &mut [u32], mut label: u32) -> u32 {
+    while parent[label as usize] != label {
+        parent[label as usize] = parent[parent[label as usize] as usize];
+        label = parent[label as usize];
+    }
+
+    return label;
+}
+
+// Joins the trees of a and b under the smaller root, so a root is always the
+// smallest label of its component.
+fn union(parent: &mut [u32], a: u32, b: u32) {
+    let (a, b): (u32, u32) = (find(parent, a), find(parent, b));
+    parent[a.max(b) as usize] = a.min(b);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::construct_randomized_matrix_seeded;
+
+    // Labels by flood fill from each unlabelled element in row-major order,
+    // which numbers components in the order label_components promises.
+    fn flood_fill(mask: &[u8], rows: usize, cols: usize, connectivity: Connectivity) -> (Vec<u32>, usize) {
+        let mut labels: Vec<u32> = vec![0; rows * cols];
+        let mut count: usize = 0;
+        let offsets: &[(isize, isize)] = match connectivity {
+            Connectivity::Four => &[(-1, 0), (1, 0), (0, -1), (0, 1)],
+            Connectivity::Eight => &[(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)],
+        };
+
+        for start in 0..rows * cols {
+            if mask[start] == 0 || labels[start] != 0 {
+                continue;
+            }
+
+            count += 1;
+            labels[start] = count as u32;
+            let mut pending: Vec<usize> = vec![start];
+
+            while let Some(index) = pending.pop() {
+                for &(dr, dc) in offsets {
+                    let (row, col): (isize, isize) = ((index / cols) as isize + dr, (index % cols) as isize + dc);
+
+                    if row < 0 || col < 0 || row >= rows as isize || col >= cols as isize {
+                        continue;
+                    }
+
+                    let next: usize = flat(row as usize, col as usize, cols);
+                    if mask[next] != 0 && labels[next] == 0 {
+                        labels[next] = count as u32;
+                        pending.push(next);
+                    }
+                }
+            }
+        }
+
+        return (labels, count);
+    }
+
+    fn parse(picture: &[&str]) -> (Vec<u8>, usize, usize) {
+        let mask: Vec<u8> = picture.iter().flat_map(|line| line.bytes().map(|byte| (byte == b'#') as u8)).collect();
+
+        return (mask, picture.len(), picture[0].len());
+    }
+
+    fn count(picture: &[&str], connectivity: Connectivity) -> usize {
+        let (mask, rows, cols) = parse(picture);
+
+        return label_components(&mask, rows, cols, connectivity).unwrap().1;
+    }
+
+    #[test]
+    fn diagonals_join_only_with_eight_connectivity() {
+        let picture: [&str; 5] = [
+            "#...#",
+            ".#.#.",
+            "..#..",
+            ".....",
+            "##.##",
+        ];
+
+        assert_eq!(count(&picture, Connectivity::Four), 7);
+        assert_eq!(count(&picture, Connectivity::Eight), 3);
+    }
+
+    #[test]
+    fn components_on_the_border_are_counted() {
+        // Every corner and edge holds a component; the left column and top
+        // row wrap around the top-left corner.
+        let picture: [&str; 6] = [
+            "####..#",
+            "#......",
+            "#.....#",
+            ".......",
+            ".....#.",
+            "#.#.#.#",
+        ];
+
+        assert_eq!(count(&picture, Connectivity::Four), 8);
+        // The bottom-right corner and its neighbour on the bottom row touch
+        // the element between them above only diagonally.
+        assert_eq!(count(&picture, Connectivity::Eight), 6);
+
+        for picture in [&["#"][..], &["###"], &["#", "#", "#"], &["#.#.#"], &["#", ".", "#"]] {
+            let (mask, rows, cols) = parse(picture);
+            let expected: usize = flood_fill(&mask, rows, cols, Connectivity::Four).1;
+
+            assert_eq!(count(picture, Connectivity::Four), expected, "{:?}", picture);
+            assert_eq!(count(picture, Connectivity::Eight), expected, "{:?}", picture);
+        }
+    }
+
+    #[test]
+    fn u_shapes_merge_into_one_label() {
+        // The two arms get different provisional labels that the bottom row
+        // joins, so the resolved labels must all be 1.
+        let (mask, rows, cols) = parse(&["#.#.#", "#.#.#", "#####"]);
+
+        for connectivity in [Connectivity::Four, Connectivity::Eight] {
+            let (labels, count) = label_components(&mask, rows, cols, connectivity).unwrap();
+
+            assert_eq!(count, 1);
+            assert!(labels.iter().zip(&mask).all(|(&label, &value)| label == value as u32));
+        }
+    }
+
+    #[test]
+    fn labels_match_a_flood_fill() {
+        for (seed, (rows, cols)) in [(0, 0), (1, 1), (1, 30), (30, 1), (9, 13), (64, 64)].into_iter().enumerate() {
+            // About half foreground, so components are many and tangled.
+            let mask: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, seed as u64).iter().map(|&value| (value < 128) as u8).collect();
+
+            for connectivity in [Connectivity::Four, Connectivity::Eight] {
+                assert_eq!(label_components(&mask, rows, cols, connectivity).unwrap(), flood_fill(&mask, rows, cols, connectivity),
+                           "{}x{} {:?}", rows, cols, connectivity);
+            }
+        }
+    }
+
+    #[test]
+    fn stats_give_sizes_and_boxes() {
+        let (mask, rows, cols) = parse(&["##..", "..#.", "...#", "#..#"]);
+        let (labels, count) = label_components(&mask, rows, cols, Connectivity::Eight).unwrap();
+
+        assert_eq!(count, 2);
+        assert_eq!(component_stats(&labels, rows, cols, count).unwrap(), vec![
+            ComponentStats { label: 1, pixels: 5, row: 0, col: 0, height: 4, width: 4 },
+            ComponentStats { label: 2, pixels: 1, row: 3, col: 0, height: 1, width: 1 },
+        ]);
+        assert_eq!(component_stats(&labels, rows, cols, 1), Err(DimError::Mismatch { what: "component label", expected: 1, found: 2 }));
+        assert_eq!(label_components(&mask, 3, 3, Connectivity::Four), Err(DimError::LengthMismatch { expected: 9, found: 16 }));
+    }
+}
@@ -0,0 +1,125 @@
+use std::fs;
+use toml::{Table, Value};
+use crate::cli::{apply_switch, apply_value, is_switch, Options};
+
+// Applies the settings of a TOML config file to options.
+//
+// Every key is the name of a command line flag without the leading dashes
+// (underscores are accepted in place of dashes), so anything the command line
+// can do can also go in the file, e.g.
+//
+//     rows = 512
+//     cols = 512
+//     seed = 42
+//     kernel = [-1, 0, 1]
+//     crop-output = true
+//
+// Flags that take no value are set with booleans. Unknown keys are reported
+// as warnings rather than silently ignored.
+pub fn apply_config(options: &mut Options, path: &str) {
+    for (key, value) in &read_config(path) {
+        let flag: String = flag_name(key);
+
+        if is_switch(&flag) {
+            match value {
+                Value::Boolean(on) => apply_switch(options, &flag, *on),
+                _ => panic!("Config key {} in {} must be true or false", key, path),
+            }
+        } else if !apply_value(options, &flag, &value_to_arg(value)) {
+            eprintln!("warning: unknown key '{}' in {}", key, path);
+        }
+    }
+}
+
+// The settings of a config file as the command line flags they stand for,
+// e.g. ["--rows", "512", "--crop-output"]. Switches set to false are left
+// out, being off anyway, and so are unknown keys.
+pub fn config_args(path: &str) -> Vec<String> {
+    let mut args: Vec<String> = Vec::new();
+
+    for (key, value) in &read_config(path) {
+        let flag: String = flag_name(key);
+
+        match value {
+            Value::Boolean(true) if is_switch(&flag) => args.push(flag),
+            Value::Boolean(false) if is_switch(&flag) => {}
+            _ if apply_value(&mut Options::default(), &flag, &value_to_arg(value)) => {
+                args.push(flag);
+                args.push(value_to_arg(value));
+            }
+            _ => {}
+        }
+    }
+
+    return args;
+}
+
+fn read_config(path: &str) -> Table {
+    let text: String = fs::read_to_string(path).unwrap_or_else(|err| panic!("Failed to read config {}: {}", path, err));
+
+    return text.parse().unwrap_or_else(|err| panic!("Invalid config {}: {}", path, err));
+}
+
+// The flag a config key sets.
+fn flag_name(key: &str) -> String {
+    return format!("--{}", key.replace('_', "-"));
+}
+
+// Renders a config value the way the same setting is written on the command
+// line. Arrays become comma separated lists, and arrays of arrays (such as
+// kernels) semicolon separated lists of those.
+fn value_to_arg(value: &Value) -> String {
+    return match value {
+        Value::String(text) => text.trim().to_string(),
+        Value::Array(items) => {
+            let separator: &str = if items.iter().all(Value::is_array) && !items.is_empty() { ";" } else { "," };
+            items.iter().map(value_to_arg).collect::<Vec<String>>().join(separator)
+        }
+        other => other.to_string(),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::parse_args;
+    use rmm::arith::ArithPolicy;
+
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/run.toml");
+
+    fn args(extra: &[&str]) -> Vec<String> {
+        return ["matician-coding-challenge", "--config", FIXTURE].iter().chain(extra).map(|arg| arg.to_string()).collect();
+    }
+
+    #[test]
+    fn example_config_gives_its_settings() {
+        let options: Options = parse_args(&args(&[]));
+
+        assert_eq!((options.rows, options.cols), (Some(64), Some(48)));
+        assert_eq!(options.seed, Some(42));
+        assert_eq!(options.kernel, Some(vec![-1, 0, 1]));
+        assert_eq!(options.arith, Some(ArithPolicy::Checked));
+        assert!(options.crop_output && options.magnitude_l1);
+        assert_eq!(options.output_csv.as_deref(), Some("target/run-output"));
+    }
+
+    #[test]
+    fn command_line_flags_override_the_config() {
+        let options: Options = parse_args(&args(&["32", "16", "--seed", "7", "--arith", "saturating"]));
+
+        assert_eq!((options.rows, options.cols), (Some(32), Some(16)));
+        assert_eq!(options.seed, Some(7));
+        assert_eq!(options.arith, Some(ArithPolicy::Saturating));
+        assert_eq!(options.kernel, Some(vec![-1, 0, 1]));
+    }
+
+    #[test]
+    fn config_args_render_known_keys_as_flags() {
+        let path: std::path::PathBuf = std::env::temp_dir().join(format!("rmm-config-{}.toml", std::process::id()));
+        fs::write(&path, "seed = 3\nkernel = [1, 2, 1]\ncrop_output = true\nverify = false\nsede = 4\n").unwrap();
+        let rendered: Vec<String> = config_args(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(rendered, vec!["--crop-output", "--kernel", "1,2,1", "--seed", "3"]);
+    }
+}
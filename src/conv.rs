@@ -0,0 +1,702 @@
+use std::ops::Range;
+use crate::arith::{store, ArithPolicy, Narrow, Widen};
+use crate::error::{check_len, ArithError, DimError};
+use crate::index::{border_ranges, flat, padded_dims, padding_offset, row_range, Axis, BorderRanges, Padding};
+use crate::kernels::SECOND_DIFFERENCE;
+use crate::matrix::{Layout, Matrix};
+
+// How a kernel is slid over the input. Convolution flips it, the way
+// compute_dx and every function below apply it: out[j] = sum(kernel[i] *
+// arr[j - i]). Cross-correlation does not: out[j] = sum(kernel[i] *
+// arr[j - (len - 1) + i]), which is the convolution with the reversed kernel
+// at the same alignment. The two agree on symmetric kernels and mirror each
+// other on any other, e.g. [-1, 0, 1] gives compute_dx negated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConvMode {
+    #[default]
+    Convolution,
+    CrossCorrelation,
+}
+
+impl ConvMode {
+    pub fn parse(value: &str) -> Option<ConvMode> {
+        return match value {
+            "convolution" => Some(ConvMode::Convolution),
+            "cross-correlation" | "correlation" => Some(ConvMode::CrossCorrelation),
+            _ => None,
+        };
+    }
+
+    // The name parse takes.
+    pub fn name(self) -> &'static str {
+        return match self {
+            ConvMode::Convolution => "convolution",
+            ConvMode::CrossCorrelation => "cross-correlation",
+        };
+    }
+
+    // The kernel whose convolution applies kernel in this mode: kernel
+    // itself, or reversed for cross-correlation. Lets any of the functions
+    // below run in either mode.
+    pub fn taps(self, kernel: &[i32]) -> Vec<i32> {
+        return match self {
+            ConvMode::Convolution => kernel.to_vec(),
+            ConvMode::CrossCorrelation => kernel.iter().rev().copied().collect(),
+        };
+    }
+}
+
+// A 1D kernel, checked once when it is built, that convolves the rows or the
+// columns of a matrix in any layout. apply_rows and apply_cols are
+// convolve_rows and convolve_cols; the result is always row-major.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Kernel {
+    taps: Vec<i32>,
+}
+
+impl Kernel {
+    pub fn new(taps: Vec<i32>) -> Result<Kernel, DimError> {
+        check_kernel(&taps)?;
+
+        return Ok(Kernel { taps });
+    }
+
+    pub fn taps(&self) -> &[i32] {
+        return &self.taps;
+    }
+
+    // rows x (cols + taps - 1).
+    pub fn apply_rows<I: Widen, O: Narrow>(&self, matrix: &Matrix<I>, policy: ArithPolicy) -> Result<Matrix<O>, ArithError> {
+        let input: Matrix<I> = matrix.to_layout(Layout::RowMajor);
+        let data: Vec<O> = convolve_rows(&input.data, input.rows, input.cols, &self.taps, policy)?;
+
+        return Ok(Matrix { data, rows: input.rows, cols: input.cols + self.taps.len() - 1, layout: Layout::RowMajor });
+    }
+
+    // (rows + taps - 1) x cols.
+    pub fn apply_cols<I: Widen, O: Narrow>(&self, matrix: &Matrix<I>, policy: ArithPolicy) -> Result<Matrix<O>, ArithError> {
+        let input: Matrix<I> = matrix.to_layout(Layout::RowMajor);
+        let data: Vec<O> = convolve_cols(&input.data, input.rows, input.cols, &self.taps, policy)?;
+
+        return Ok(Matrix { data, rows: input.rows + self.taps.len() - 1, cols: input.cols, layout: Layout::RowMajor });
+    }
+}
+
+// Convolves every row of a rows x cols matrix with a 1D kernel.
+//
+// Like compute_dx, the input is zero padded by kernel.len() - 1 on both sides
+// and the full convolution is returned, so the result is
+// rows x (cols + kernel.len() - 1). The kernel is flipped, i.e.
+// out[j] = sum(kernel[i] * arr[j - i]), which makes convolve_rows with
+// [-1, 0, 1] equal to compute_dx. Sums are accumulated in i64 and converted
+// to the output type under policy.
+pub fn convolve_rows<I: Widen, O: Narrow>(arr: &[I], rows: usize, cols: usize, kernel: &[i32],
+                                          policy: ArithPolicy) -> Result<Vec<O>, ArithError> {
+    check_kernel(kernel)?;
+
+    return convolve_rows_padded(arr, rows, cols, kernel, kernel.len() - 1, policy);
+}
+
+// convolve_rows with pad output columns of padding instead of
+// kernel.len() - 1, so the result is rows x (cols + pad). Column pad / 2 + c
+// is centered on input column c, as in the full convolution: a smaller pad
+// drops outer columns (pad 0 gives the "same" convolution) and a larger one
+// adds columns beyond the reach of the kernel, which are 0.
+//
+// A kernel of even length N has no center tap; output column c of the
+// full convolution would be centered between two inputs, so the kept columns
+// start at (N - 1) / 2 - pad / 2, rounding down. With pad 0 output c then
+// reads inputs c - N / 2 ..= c + N / 2 - 1, one more to the left of c than to
+// the right: [a, b] gives a * arr[c] + b * arr[c - 1].
+pub fn convolve_rows_padded<I: Widen, O: Narrow>(arr: &[I], rows: usize, cols: usize, kernel: &[i32], pad: usize,
+                                                 policy: ArithPolicy) -> Result<Vec<O>, ArithError> {
+    check_len(arr.len(), rows, cols)?;
+    check_kernel(kernel)?;
+
+    let (_, new_cols): (usize, usize) = padded_dims(rows, cols, kernel.len(), Axis::Cols, Padding::Explicit(pad));
+    let offset: isize = padding_offset(kernel.len(), pad);
+    let mut out: Vec<O> = vec![O::default(); rows * new_cols];
+
+    for row in 0..rows {
+        for col in 0..new_cols {
+            let full: isize = col as isize + offset;
+            let mut sum: i64 = 0;
+
+            for (i, weight) in kernel.iter().enumerate() {
+                let src: isize = full - i as isize;
+
+                if src >= 0 && (src as usize) < cols {
+                    sum += *weight as i64 * arr[flat(row, src as usize, cols)].widen();
+                }
+            }
+
+            store(&mut out, flat(row, col, new_cols), sum, policy)?;
+        }
+    }
+
+    return Ok(out);
+}
+
+// Convolves every column of a rows x cols matrix with a 1D kernel. The
+// vertical counterpart of convolve_rows; the result is
+// (rows + kernel.len() - 1) x cols and [-1, 0, 1] reproduces compute_dy.
+pub fn convolve_cols<I: Widen, O: Narrow>(arr: &[I], rows: usize, cols: usize, kernel: &[i32],
+                                          policy: ArithPolicy) -> Result<Vec<O>, ArithError> {
+    check_kernel(kernel)?;
+
+    return convolve_cols_padded(arr, rows, cols, kernel, kernel.len() - 1, policy);
+}
+
+// The vertical counterpart of convolve_rows_padded: (rows + pad) x cols, with
+// row pad / 2 + r centered on input row r. Even kernels lean up by one row,
+// as they lean left in convolve_rows_padded.
+pub fn convolve_cols_padded<I: Widen, O: Narrow>(arr: &[I], rows: usize, cols: usize, kernel: &[i32], pad: usize,
+                                                 policy: ArithPolicy) -> Result<Vec<O>, ArithError> {
+    check_len(arr.len(), rows, cols)?;
+    check_kernel(kernel)?;
+
+    let (new_rows, _): (usize, usize) = padded_dims(rows, cols, kernel.len(), Axis::Rows, Padding::Explicit(pad));
+    let offset: isize = padding_offset(kernel.len(), pad);
+    let mut out: Vec<O> = vec![O::default(); new_rows * cols];
+
+    for row in 0..new_rows {
+        let full: isize = row as isize + offset;
+
+        for col in 0..cols {
+            let mut sum: i64 = 0;
+
+            for (i, weight) in kernel.iter().enumerate() {
+                let src: isize = full - i as isize;
+
+                if src >= 0 && (src as usize) < rows {
+                    sum += *weight as i64 * arr[flat(src as usize, col, cols)].widen();
+                }
+            }
+
+            store(&mut out, flat(row, col, cols), sum, policy)?;
+        }
+    }
+
+    return Ok(out);
+}
+
+// The built-in kernels convolve_rows_specialized and convolve_cols_specialized
+// send down the const path, along with the SECOND_DIFFERENCE of compute_dxx:
+// the central difference of compute_dx, the Sobel smoothing and the Scharr
+// smoothing.
+pub const CENTRAL_DIFFERENCE: [i32; 3] = [-1, 0, 1];
+pub const BINOMIAL_SMOOTH: [i32; 3] = [1, 2, 1];
+pub const SCHARR_SMOOTH: [i32; 3] = [3, 10, 3];
+
+// convolve_rows_padded for a kernel of N weights, the same results for the
+// same kernel. The length is known at compile time, so the loop over the
+// weights unrolls, and callers passing a constant kernel (as the
+// specialized dispatch does) let the optimizer fold the weights in and drop
+// the zero ones. Output columns whose window lies inside the row skip the
+// bounds tests; only the pad + N - 1 border columns of each row keep them.
+#[inline(always)]
+pub fn convolve_rows_padded_const<I: Widen, O: Narrow, const N: usize>(arr: &[I], rows: usize, cols: usize, kernel: [i32; N], pad: usize,
+                                                                       policy: ArithPolicy) -> Result<Vec<O>, ArithError> {
+    check_len(arr.len(), rows, cols)?;
+    check_kernel(&kernel)?;
+
+    let (_, new_cols): (usize, usize) = padded_dims(rows, cols, N, Axis::Cols, Padding::Explicit(pad));
+    let offset: isize = padding_offset(N, pad);
+    let mut out: Vec<O> = vec![O::default(); rows * new_cols];
+
+    // Output columns first..end have every tap on an input column.
+    let ranges: BorderRanges = border_ranges(cols, N, Padding::Explicit(pad));
+    let (first, end): (usize, usize) = (ranges.interior.start, ranges.interior.end);
+
+    for row in 0..rows {
+        let line: &[I] = &arr[row_range(row, cols)];
+        let border = |col: usize| border_sum(&kernel, col as isize + offset, |src| line[src].widen(), cols);
+
+        for col in 0..first {
+            store(&mut out, flat(row, col, new_cols), border(col), policy)?;
+        }
+
+        // Interior column first + k reads src[k..k + N].
+        let src: &[I] = match first < end {
+            true => &line[(first as isize + offset) as usize + 1 - N..(end as isize + offset) as usize],
+            false => &[],
+        };
+        let slots: &mut [O] = &mut out[flat(row, first, new_cols)..flat(row, end, new_cols)];
+
+        // Only Checked can fail, and it has to stop at the first overflow;
+        // the other policies are matched once per row so the optimizer sees
+        // a constant one and can vectorize the loop.
+        match policy {
+            ArithPolicy::Checked => for (k, col) in (first..end).enumerate() {
+                store(&mut out, flat(row, col, new_cols), window_sum(&kernel, src, k), policy)?;
+            },
+            ArithPolicy::Wrapping => for (k, slot) in slots.iter_mut().enumerate() {
+                *slot = O::narrow(window_sum(&kernel, src, k), ArithPolicy::Wrapping).unwrap_or_default();
+            },
+            ArithPolicy::Saturating => for (k, slot) in slots.iter_mut().enumerate() {
+                *slot = O::narrow(window_sum(&kernel, src, k), ArithPolicy::Saturating).unwrap_or_default();
+            },
+        }
+
+        for col in end..new_cols {
+            store(&mut out, flat(row, col, new_cols), border(col), policy)?;
+        }
+    }
+
+    return Ok(out);
+}
+
+// convolve_rows_padded_const with the full padding of N - 1, as convolve_rows.
+#[inline(always)]
+pub fn convolve_rows_const<I: Widen, O: Narrow, const N: usize>(arr: &[I], rows: usize, cols: usize, kernel: [i32; N],
+                                                                policy: ArithPolicy) -> Result<Vec<O>, ArithError> {
+    return convolve_rows_padded_const(arr, rows, cols, kernel, N.saturating_sub(1), policy);
+}
+
+// The vertical counterpart of convolve_rows_padded_const. Output rows whose
+// taps all lie inside the input combine N whole input rows, element by
+// element, with no bounds tests.
+#[inline(always)]
+pub fn convolve_cols_padded_const<I: Widen, O: Narrow, const N: usize>(arr: &[I], rows: usize, cols: usize, kernel: [i32; N], pad: usize,
+                                                                       policy: ArithPolicy) -> Result<Vec<O>, ArithError> {
+    check_len(arr.len(), rows, cols)?;
+    check_kernel(&kernel)?;
+
+    let (new_rows, _): (usize, usize) = padded_dims(rows, cols, N, Axis::Rows, Padding::Explicit(pad));
+    let offset: isize = padding_offset(N, pad);
+    let interior: Range<usize> = border_ranges(rows, N, Padding::Explicit(pad)).interior;
+    let mut out: Vec<O> = vec![O::default(); new_rows * cols];
+
+    for row in 0..new_rows {
+        let full: isize = row as isize + offset;
+
+        if !interior.contains(&row) {
+            for col in 0..cols {
+                let sum: i64 = border_sum(&kernel, full, |src| arr[flat(src, col, cols)].widen(), rows);
+                store(&mut out, flat(row, col, cols), sum, policy)?;
+            }
+
+            continue;
+        }
+
+        // Input row full - i for weight i.
+        let sources: [&[I]; N] = std::array::from_fn(|i| &arr[row_range(full as usize - i, cols)]);
+        let sum = |col: usize| -> i64 {
+            let mut sum: i64 = 0;
+
+            for i in 0..N {
+                sum += kernel[i] as i64 * sources[i][col].widen();
+            }
+
+            return sum;
+        };
+
+        // As in convolve_rows_padded_const.
+        match policy {
+            ArithPolicy::Checked => for col in 0..cols {
+                store(&mut out, flat(row, col, cols), sum(col), policy)?;
+            },
+            ArithPolicy::Wrapping => for (col, slot) in out[row_range(row, cols)].iter_mut().enumerate() {
+                *slot = O::narrow(sum(col), ArithPolicy::Wrapping).unwrap_or_default();
+            },
+            ArithPolicy::Saturating => for (col, slot) in out[row_range(row, cols)].iter_mut().enumerate() {
+                *slot = O::narrow(sum(col), ArithPolicy::Saturating).unwrap_or_default();
+            },
+        }
+    }
+
+    return Ok(out);
+}
+
+// convolve_cols_padded_const with the full padding of N - 1, as convolve_cols.
+#[inline(always)]
+pub fn convolve_cols_const<I: Widen, O: Narrow, const N: usize>(arr: &[I], rows: usize, cols: usize, kernel: [i32; N],
+                                                                policy: ArithPolicy) -> Result<Vec<O>, ArithError> {
+    return convolve_cols_padded_const(arr, rows, cols, kernel, N.saturating_sub(1), policy);
+}
+
+// convolve_rows_padded in either mode, routing the built-in kernels (once
+// flipped to their convolution taps) through convolve_rows_padded_const with
+// the kernel as a constant. Any other kernel, e.g. one given with --kernel,
+// takes the runtime-slice path.
+pub fn convolve_rows_specialized<I: Widen, O: Narrow>(arr: &[I], rows: usize, cols: usize, kernel: &[i32], pad: usize, mode: ConvMode,
+                                                      policy: ArithPolicy) -> Result<Vec<O>, ArithError> {
+    let taps: Vec<i32> = mode.taps(kernel);
+    let kernel: &[i32] = &taps;
+
+    return match kernel {
+        [-1, 0, 1] => convolve_rows_padded_const(arr, rows, cols, CENTRAL_DIFFERENCE, pad, policy),
+        [1, 2, 1] => convolve_rows_padded_const(arr, rows, cols, BINOMIAL_SMOOTH, pad, policy),
+        [1, -2, 1] => convolve_rows_padded_const(arr, rows, cols, SECOND_DIFFERENCE, pad, policy),
+        [3, 10, 3] => convolve_rows_padded_const(arr, rows, cols, SCHARR_SMOOTH, pad, policy),
+        _ => convolve_rows_padded(arr, rows, cols, kernel, pad, policy),
+    };
+}
+
+// The vertical counterpart of convolve_rows_specialized.
+pub fn convolve_cols_specialized<I: Widen, O: Narrow>(arr: &[I], rows: usize, cols: usize, kernel: &[i32], pad: usize, mode: ConvMode,
+                                                      policy: ArithPolicy) -> Result<Vec<O>, ArithError> {
+    let taps: Vec<i32> = mode.taps(kernel);
+    let kernel: &[i32] = &taps;
+
+    return match kernel {
+        [-1, 0, 1] => convolve_cols_padded_const(arr, rows, cols, CENTRAL_DIFFERENCE, pad, policy),
+        [1, 2, 1] => convolve_cols_padded_const(arr, rows, cols, BINOMIAL_SMOOTH, pad, policy),
+        [1, -2, 1] => convolve_cols_padded_const(arr, rows, cols, SECOND_DIFFERENCE, pad, policy),
+        [3, 10, 3] => convolve_cols_padded_const(arr, rows, cols, SCHARR_SMOOTH, pad, policy),
+        _ => convolve_cols_padded(arr, rows, cols, kernel, pad, policy),
+    };
+}
+
+// The weights of kernel times the N elements of src from start on, the last
+// one under the first weight.
+#[inline(always)]
+fn window_sum<I: Widen, const N: usize>(kernel: &[i32; N], src: &[I], start: usize) -> i64 {
+    let mut sum: i64 = 0;
+
+    for i in 0..N {
+        sum += kernel[i] as i64 * src[start + N - 1 - i].widen();
+    }
+
+    return sum;
+}
+
+// The sum of the weights of kernel times the elements full - i of a line of
+// len elements that lie inside it, read with value: the bounds-tested loop
+// of the border outputs.
+#[inline(always)]
+fn border_sum(kernel: &[i32], full: isize, value: impl Fn(usize) -> i64, len: usize) -> i64 {
+    let mut sum: i64 = 0;
+
+    for (i, weight) in kernel.iter().enumerate() {
+        let src: isize = full - i as isize;
+
+        if src >= 0 && (src as usize) < len {
+            sum += *weight as i64 * value(src as usize);
+        }
+    }
+
+    return sum;
+}
+
+// Convolves every row with each of several kernels in one pass over arr, so
+// the input is streamed from memory once however many kernels there are.
+// Output k is convolve_rows with kernels[k] into i32 under
+// ArithPolicy::Wrapping, i.e. rows x (cols + kernels[k].len() - 1).
+pub fn convolve_rows_multi<I: Widen>(arr: &[I], rows: usize, cols: usize,
+                                     kernels: &[&[i32]]) -> Result<Vec<Vec<i32>>, DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    let mut outs: Vec<Vec<i32>> = Vec::with_capacity(kernels.len());
+
+    for kernel in kernels {
+        check_kernel(kernel)?;
+        let (out_rows, out_cols): (usize, usize) = padded_dims(rows, cols, kernel.len(), Axis::Cols, Padding::Full);
+        outs.push(vec![0; out_rows * out_cols]);
+    }
+
+    // Each input element is scattered into every output it contributes to.
+    for row in 0..rows {
+        for col in 0..cols {
+            let value: i32 = arr[flat(row, col, cols)].widen() as i32;
+
+            for (kernel, out) in kernels.iter().zip(outs.iter_mut()) {
+                let start: usize = flat(row, col, cols + kernel.len() - 1);
+
+                for (sum, weight) in out[start..start + kernel.len()].iter_mut().zip(kernel.iter()) {
+                    *sum = sum.wrapping_add(weight.wrapping_mul(value));
+                }
+            }
+        }
+    }
+
+    return Ok(outs);
+}
+
+// The vertical counterpart of convolve_rows_multi: output k equals
+// convolve_cols with kernels[k], (rows + kernels[k].len() - 1) x cols.
+pub fn convolve_cols_multi<I: Widen>(arr: &[I], rows: usize, cols: usize,
+                                     kernels: &[&[i32]]) -> Result<Vec<Vec<i32>>, DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    let mut outs: Vec<Vec<i32>> = Vec::with_capacity(kernels.len());
+
+    for kernel in kernels {
+        check_kernel(kernel)?;
+        let (out_rows, out_cols): (usize, usize) = padded_dims(rows, cols, kernel.len(), Axis::Rows, Padding::Full);
+        outs.push(vec![0; out_rows * out_cols]);
+    }
+
+    for row in 0..rows {
+        for col in 0..cols {
+            let value: i32 = arr[flat(row, col, cols)].widen() as i32;
+
+            for (kernel, out) in kernels.iter().zip(outs.iter_mut()) {
+                for (i, weight) in kernel.iter().enumerate() {
+                    let index: usize = flat(row + i, col, cols);
+                    out[index] = out[index].wrapping_add(weight.wrapping_mul(value));
+                }
+            }
+        }
+    }
+
+    return Ok(outs);
+}
+
+// The 3x3 Sobel derivatives of a rows x cols matrix, (gx, gy), both rows x
+// cols: the [-1, 0, 1] difference along one axis after a [1, 2, 1] smoothing
+// along the other, each a "same" convolution with zero padding. Signs follow
+// compute_dx and compute_dy, so gx at (r, c) weighs column c - 1 positively.
+// Every value is within +-4 * 255 and the i16 results never overflow.
+pub fn sobel(arr: &[u8], rows: usize, cols: usize) -> Result<(Vec<i16>, Vec<i16>), DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    let fits = |result: Result<Vec<i16>, ArithError>| result.expect("Sobel sums fit an i16");
+    let smooth_cols: Vec<i16> = fits(convolve_cols_padded_const(arr, rows, cols, BINOMIAL_SMOOTH, 0, ArithPolicy::Checked));
+    let smooth_rows: Vec<i16> = fits(convolve_rows_padded_const(arr, rows, cols, BINOMIAL_SMOOTH, 0, ArithPolicy::Checked));
+    let gx: Vec<i16> = fits(convolve_rows_padded_const(&smooth_cols, rows, cols, CENTRAL_DIFFERENCE, 0, ArithPolicy::Checked));
+    let gy: Vec<i16> = fits(convolve_cols_padded_const(&smooth_rows, rows, cols, CENTRAL_DIFFERENCE, 0, ArithPolicy::Checked));
+
+    return Ok((gx, gy));
+}
+
+pub(crate) fn check_kernel(kernel: &[i32]) -> Result<(), DimError> {
+    if kernel.is_empty() {
+        return Err(DimError::Mismatch { what: "kernel length", expected: 1, found: 0 });
+    }
+
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // out[c] = sum(kernel[i] * arr[c + shift - i]) along one row, zero outside.
+    fn naive_row(arr: &[i32], kernel: &[i32], shift: isize) -> Vec<i32> {
+        return (0..arr.len() as isize).map(|c| {
+            kernel.iter().enumerate().map(|(i, weight)| {
+                let src: isize = c + shift - i as isize;
+                if src >= 0 && (src as usize) < arr.len() { weight * arr[src as usize] } else { 0 }
+            }).sum()
+        }).collect();
+    }
+
+    #[test]
+    fn same_convolution_of_an_even_kernel_leans_left() {
+        let arr: Vec<i32> = vec![1, 10, 100, 1000, 10000];
+
+        let two: Vec<i32> = convolve_rows_padded(&arr, 1, 5, &[1, 2], 0, ArithPolicy::Checked).unwrap();
+        assert_eq!(two, vec![1, 12, 120, 1200, 12000]);
+        assert_eq!(two, naive_row(&arr, &[1, 2], 0));
+
+        // N = 4 covers c - 2 ..= c + 1, so out[0] = 1 * arr[1] + 2 * arr[0].
+        let four: Vec<i32> = convolve_rows_padded(&arr, 1, 5, &[1, 2, 3, 4], 0, ArithPolicy::Checked).unwrap();
+        assert_eq!(four, naive_row(&arr, &[1, 2, 3, 4], 1));
+        assert_eq!(four[0], 12);
+    }
+
+    #[test]
+    fn same_convolution_is_the_full_one_cropped_by_the_padding_offset() {
+        let arr: Vec<i32> = vec![3, -1, 4, -1, 5, -9];
+
+        for kernel in [vec![1, 2], vec![1, 2, 3], vec![1, -2, 3, -4], vec![5, 4, 3, 2, 1, 0]] {
+            let full: Vec<i32> = convolve_rows(&arr, 1, 6, &kernel, ArithPolicy::Checked).unwrap();
+
+            for pad in 0..kernel.len() {
+                let padded: Vec<i32> = convolve_rows_padded(&arr, 1, 6, &kernel, pad, ArithPolicy::Checked).unwrap();
+                let start: usize = (kernel.len() - 1) / 2 - pad / 2;
+                assert_eq!(padded, full[start..start + 6 + pad], "kernel {:?} pad {}", kernel, pad);
+            }
+        }
+    }
+
+    #[test]
+    fn columns_of_an_even_kernel_lean_up() {
+        let arr: Vec<i32> = vec![1, 10, 100, 1000];
+        let same: Vec<i32> = convolve_cols_padded(&arr, 4, 1, &[1, 2], 0, ArithPolicy::Checked).unwrap();
+
+        assert_eq!(same, convolve_rows_padded::<i32, i32>(&arr, 1, 4, &[1, 2], 0, ArithPolicy::Checked).unwrap());
+        assert_eq!(same, vec![1, 12, 120, 1200]);
+    }
+
+    // Every built-in kernel, as convolve_rows_specialized and
+    // convolve_cols_specialized route them.
+    const PRESETS: [[i32; 3]; 4] = [CENTRAL_DIFFERENCE, BINOMIAL_SMOOTH, SECOND_DIFFERENCE, SCHARR_SMOOTH];
+    const POLICIES: [ArithPolicy; 3] = [ArithPolicy::Wrapping, ArithPolicy::Saturating, ArithPolicy::Checked];
+
+    #[test]
+    fn const_paths_match_the_runtime_ones_for_every_preset() {
+        for (seed, (rows, cols)) in [(1, 1), (1, 2), (2, 1), (3, 3), (5, 9), (17, 6)].into_iter().enumerate() {
+            let arr: Vec<u8> = crate::matrix::construct_randomized_matrix_seeded(rows, cols, seed as u64);
+
+            for kernel in PRESETS {
+                for pad in 0..=4 {
+                    for policy in POLICIES {
+                        let what: String = format!("{:?} pad {} {:?} on {}x{}", kernel, pad, policy, rows, cols);
+
+                        let dx: Result<Vec<i32>, ArithError> = convolve_rows_padded(&arr, rows, cols, &kernel, pad, policy);
+                        let dy: Result<Vec<i32>, ArithError> = convolve_cols_padded(&arr, rows, cols, &kernel, pad, policy);
+                        assert_eq!(convolve_rows_padded_const(&arr, rows, cols, kernel, pad, policy), dx, "rows {}", what);
+                        assert_eq!(convolve_cols_padded_const(&arr, rows, cols, kernel, pad, policy), dy, "cols {}", what);
+
+                        // Into i16, where Scharr on u8 can overflow, so the
+                        // policies differ.
+                        let dx: Result<Vec<i16>, ArithError> = convolve_rows_padded(&arr, rows, cols, &kernel, pad, policy);
+                        let dy: Result<Vec<i16>, ArithError> = convolve_cols_padded(&arr, rows, cols, &kernel, pad, policy);
+                        assert_eq!(convolve_rows_padded_const(&arr, rows, cols, kernel, pad, policy), dx, "rows {}", what);
+                        assert_eq!(convolve_cols_padded_const(&arr, rows, cols, kernel, pad, policy), dy, "cols {}", what);
+                    }
+                }
+
+                let full: Vec<i32> = convolve_rows(&arr, rows, cols, &kernel, ArithPolicy::Checked).unwrap();
+                assert_eq!(convolve_rows_const::<u8, i32, 3>(&arr, rows, cols, kernel, ArithPolicy::Checked).unwrap(), full);
+                let full: Vec<i32> = convolve_cols(&arr, rows, cols, &kernel, ArithPolicy::Checked).unwrap();
+                assert_eq!(convolve_cols_const::<u8, i32, 3>(&arr, rows, cols, kernel, ArithPolicy::Checked).unwrap(), full);
+            }
+        }
+    }
+
+    #[test]
+    fn checked_const_paths_report_the_first_overflow() {
+        // Scharr on i32 values far past i16: both paths must stop at the
+        // same output index.
+        let arr: Vec<i32> = (0..48).map(|index| (index * 7919 % 97) * 1000 - 40_000).collect();
+
+        for kernel in PRESETS {
+            for pad in [0, 2, 4] {
+                let runtime: Result<Vec<i16>, ArithError> = convolve_rows_padded(&arr, 6, 8, &kernel, pad, ArithPolicy::Checked);
+                assert!(matches!(runtime, Err(ArithError::Overflow { .. })), "{:?}", kernel);
+                assert_eq!(convolve_rows_padded_const(&arr, 6, 8, kernel, pad, ArithPolicy::Checked), runtime);
+
+                let runtime: Result<Vec<i16>, ArithError> = convolve_cols_padded(&arr, 6, 8, &kernel, pad, ArithPolicy::Checked);
+                assert_eq!(convolve_cols_padded_const(&arr, 6, 8, kernel, pad, ArithPolicy::Checked), runtime);
+            }
+        }
+    }
+
+    #[test]
+    fn the_dispatch_matches_the_runtime_path_in_both_modes() {
+        let arr: Vec<u8> = crate::matrix::construct_randomized_matrix_seeded(7, 10, 3);
+
+        // The presets, a preset reversed (Dx negated, not a preset unless
+        // it is symmetric) and kernels that are no preset at all.
+        let kernels: [&[i32]; 7] = [&CENTRAL_DIFFERENCE, &BINOMIAL_SMOOTH, &SECOND_DIFFERENCE, &SCHARR_SMOOTH, &[1, 0, -1], &[1, 1], &[2, -1, 0, 5]];
+
+        for kernel in kernels {
+            for mode in [ConvMode::Convolution, ConvMode::CrossCorrelation] {
+                let taps: Vec<i32> = mode.taps(kernel);
+
+                for pad in [0, 1, kernel.len() - 1] {
+                    let dx: Vec<i32> = convolve_rows_padded(&arr, 7, 10, &taps, pad, ArithPolicy::Wrapping).unwrap();
+                    let dy: Vec<i32> = convolve_cols_padded(&arr, 7, 10, &taps, pad, ArithPolicy::Wrapping).unwrap();
+
+                    assert_eq!(convolve_rows_specialized::<u8, i32>(&arr, 7, 10, kernel, pad, mode, ArithPolicy::Wrapping).unwrap(), dx, "{:?} {:?}", kernel, mode);
+                    assert_eq!(convolve_cols_specialized::<u8, i32>(&arr, 7, 10, kernel, pad, mode, ArithPolicy::Wrapping).unwrap(), dy, "{:?} {:?}", kernel, mode);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn const_dx_matches_compute_dx() {
+        for (rows, cols) in [(1, 1), (4, 9), (32, 33)] {
+            let arr: Vec<u8> = crate::matrix::construct_randomized_matrix_seeded(rows, cols, 8);
+
+            assert_eq!(convolve_rows_const::<u8, i16, 3>(&arr, rows, cols, CENTRAL_DIFFERENCE, ArithPolicy::Wrapping).unwrap(),
+                       crate::kernels::compute_dx(&arr, rows, cols).data);
+            assert_eq!(convolve_cols_const::<u8, i16, 3>(&arr, rows, cols, CENTRAL_DIFFERENCE, ArithPolicy::Wrapping).unwrap(),
+                       crate::kernels::compute_dy(&arr, rows, cols).data);
+        }
+    }
+
+    #[test]
+    fn cross_correlation_mirrors_convolution_on_an_asymmetric_kernel() {
+        let kernel: [i32; 3] = [1, 2, 0];
+        let run = |arr: &[u8], rows: usize, cols: usize, mode: ConvMode| -> (Vec<i32>, Vec<i32>) {
+            return (convolve_rows_specialized::<u8, i32>(arr, rows, cols, &kernel, 2, mode, ArithPolicy::Checked).unwrap(),
+                    convolve_cols_specialized::<u8, i32>(arr, rows, cols, &kernel, 2, mode, ArithPolicy::Checked).unwrap());
+        };
+
+        // By hand: the convolution flips the kernel, the correlation does not.
+        assert_eq!(run(&[1, 2, 3], 1, 3, ConvMode::Convolution).0, vec![1, 4, 7, 6, 0]);
+        assert_eq!(run(&[1, 2, 3], 1, 3, ConvMode::CrossCorrelation).0, vec![0, 2, 5, 8, 3]);
+        assert_eq!(run(&[1, 2, 3], 3, 1, ConvMode::CrossCorrelation).1, vec![0, 2, 5, 8, 3]);
+
+        // The correlation of a matrix is the convolution of the mirrored
+        // matrix, mirrored back.
+        let (rows, cols): (usize, usize) = (6, 9);
+        let arr: Vec<u8> = crate::matrix::construct_randomized_matrix_seeded(rows, cols, 4);
+        let mirror_cols: Vec<u8> = arr.chunks(cols).flat_map(|row| row.iter().rev()).copied().collect();
+        let mirror_rows: Vec<u8> = arr.chunks(cols).rev().flatten().copied().collect();
+        let (correlated_rows, correlated_cols): (Vec<i32>, Vec<i32>) = run(&arr, rows, cols, ConvMode::CrossCorrelation);
+
+        assert_eq!(run(&mirror_cols, rows, cols, ConvMode::Convolution).0.chunks(cols + 2).flat_map(|row| row.iter().rev()).copied().collect::<Vec<i32>>(),
+                   correlated_rows);
+        assert_eq!(run(&mirror_rows, rows, cols, ConvMode::Convolution).1.chunks(cols).rev().flatten().copied().collect::<Vec<i32>>(),
+                   correlated_cols);
+
+        // The default is today's convolution, and correlating [-1, 0, 1]
+        // negates compute_dx.
+        assert_eq!(run(&arr, rows, cols, ConvMode::default()), (convolve_rows_padded(&arr, rows, cols, &kernel, 2, ArithPolicy::Checked).unwrap(),
+                                                                convolve_cols_padded(&arr, rows, cols, &kernel, 2, ArithPolicy::Checked).unwrap()));
+
+        let dx: Vec<i16> = crate::kernels::compute_dx(&arr, rows, cols).data;
+        let correlated: Vec<i16> = convolve_rows_specialized(&arr, rows, cols, &CENTRAL_DIFFERENCE, 2, ConvMode::CrossCorrelation, ArithPolicy::Checked).unwrap();
+        assert_eq!(convolve_rows_specialized::<u8, i16>(&arr, rows, cols, &CENTRAL_DIFFERENCE, 2, ConvMode::default(), ArithPolicy::Checked).unwrap(), dx);
+        assert_eq!(correlated, dx.iter().map(|value| -value).collect::<Vec<i16>>());
+    }
+
+    #[test]
+    fn a_kernel_bank_equals_separate_convolutions() {
+        // Lengths 1 to 5, and weights large enough that the i32 sums wrap.
+        let kernels: [&[i32]; 5] = [&[3], &[1, -1], &[-1, 0, 1], &[1, 4, 6, 4, 1], &[i32::MAX, 2, i32::MIN]];
+
+        for (rows, cols) in [(1, 1), (3, 8), (17, 12)] {
+            let arr: Vec<u8> = crate::matrix::construct_randomized_matrix_seeded(rows, cols, 13);
+            let by_rows: Vec<Vec<i32>> = convolve_rows_multi(&arr, rows, cols, &kernels).unwrap();
+            let by_cols: Vec<Vec<i32>> = convolve_cols_multi(&arr, rows, cols, &kernels).unwrap();
+
+            for (k, kernel) in kernels.iter().enumerate() {
+                assert_eq!(by_rows[k], convolve_rows::<u8, i32>(&arr, rows, cols, kernel, ArithPolicy::Wrapping).unwrap(), "{}x{} kernel {:?}", rows, cols, kernel);
+                assert_eq!(by_cols[k], convolve_cols::<u8, i32>(&arr, rows, cols, kernel, ArithPolicy::Wrapping).unwrap(), "{}x{} kernel {:?}", rows, cols, kernel);
+            }
+        }
+
+        // Signed inputs too.
+        let arr: Vec<i16> = (0..20).map(|value| value * 997 - 9000).collect();
+
+        assert_eq!(convolve_rows_multi(&arr, 4, 5, &kernels[2..4]).unwrap()[1], convolve_rows::<i16, i32>(&arr, 4, 5, kernels[3], ArithPolicy::Wrapping).unwrap());
+        assert_eq!(convolve_cols_multi(&arr, 4, 5, &kernels[2..4]).unwrap()[1], convolve_cols::<i16, i32>(&arr, 4, 5, kernels[3], ArithPolicy::Wrapping).unwrap());
+        // An empty bank gives no outputs; bad operands are errors.
+        assert_eq!(convolve_rows_multi::<u8>(&[1, 2], 1, 2, &[]).unwrap(), Vec::<Vec<i32>>::new());
+        assert!(convolve_rows_multi::<u8>(&[1, 2, 3], 1, 2, &kernels).is_err());
+        assert!(convolve_cols_multi::<u8>(&[1, 2], 1, 2, &[&[1], &[]]).is_err());
+    }
+
+    #[test]
+    fn padding_width_two_reproduces_compute_dx_and_compute_dy() {
+        for (rows, cols) in [(1, 1), (2, 7), (9, 4), (16, 23)] {
+            let arr: Vec<u8> = crate::matrix::construct_randomized_matrix_seeded(rows, cols, 6);
+            let dx: Vec<i16> = crate::kernels::compute_dx(&arr, rows, cols).data;
+            let dy: Vec<i16> = crate::kernels::compute_dy(&arr, rows, cols).data;
+            let rows_with = |pad: usize| convolve_rows_padded::<u8, i16>(&arr, rows, cols, &CENTRAL_DIFFERENCE, pad, ArithPolicy::Checked).unwrap();
+            let cols_with = |pad: usize| convolve_cols_padded::<u8, i16>(&arr, rows, cols, &CENTRAL_DIFFERENCE, pad, ArithPolicy::Checked).unwrap();
+
+            // Explicitly, and as derived from the kernel length.
+            assert_eq!(rows_with(2), dx, "{}x{}", rows, cols);
+            assert_eq!(cols_with(2), dy, "{}x{}", rows, cols);
+            assert_eq!(convolve_rows::<u8, i16>(&arr, rows, cols, &CENTRAL_DIFFERENCE, ArithPolicy::Checked).unwrap(), dx);
+            assert_eq!(convolve_cols::<u8, i16>(&arr, rows, cols, &CENTRAL_DIFFERENCE, ArithPolicy::Checked).unwrap(), dy);
+
+            // Pad 1 drops the first padding column (row), pad 0 both, and pad
+            // 4 adds a zero column (row) on either side.
+            assert_eq!(rows_with(1), dx.chunks(cols + 2).flat_map(|row| &row[1..]).copied().collect::<Vec<i16>>());
+            assert_eq!(cols_with(1), dy[cols..].to_vec());
+            assert_eq!(rows_with(0), crate::ops::crop_dx_padding(&dx, rows, cols).unwrap());
+            assert_eq!(cols_with(0), crate::ops::crop_dy_padding(&dy, rows, cols).unwrap());
+            assert_eq!(rows_with(4), dx.chunks(cols + 2).flat_map(|row| [&[0][..], row, &[0]].concat()).collect::<Vec<i16>>());
+            assert_eq!(cols_with(4), [vec![0; cols], dy.clone(), vec![0; cols]].concat());
+        }
+    }
+}
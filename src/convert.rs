@@ -0,0 +1,39 @@
+use crate::error::DimError;
+
+// Takes the absolute value of every element and saturates it into 0..=255,
+// giving a u8 image that can be viewed directly. Values whose magnitude is
+// above 255 (possible with user kernels, not with [-1, 0, 1]) clamp to 255
+// instead of wrapping.
+pub fn to_abs_u8(data: &[i16]) -> Vec<u8> {
+    return data.iter().map(|value| value.unsigned_abs().min(u8::MAX as u16) as u8).collect();
+}
+
+// Linearly maps data onto 0..=255 so that its minimum becomes 0 and its
+// maximum 255. A constant input maps to all zeros.
+pub fn normalize_u8<T: Copy + Into<f64>>(data: &[T]) -> Vec<u8> {
+    let mut out: Vec<u8> = vec![0; data.len()];
+    normalize_u8_into(data, &mut out).expect("out has the length of data");
+
+    return out;
+}
+
+// normalize_u8 writing into out, which must be as long as data.
+pub fn normalize_u8_into<T: Copy + Into<f64>>(data: &[T], out: &mut [u8]) -> Result<(), DimError> {
+    if out.len() != data.len() {
+        return Err(DimError::Mismatch { what: "output length", expected: data.len(), found: out.len() });
+    }
+
+    let min: f64 = data.iter().map(|value| (*value).into()).fold(f64::INFINITY, f64::min);
+    let max: f64 = data.iter().map(|value| (*value).into()).fold(f64::NEG_INFINITY, f64::max);
+
+    if max <= min {
+        out.fill(0);
+        return Ok(());
+    }
+
+    for (value, &x) in out.iter_mut().zip(data) {
+        *value = ((x.into() - min) * 255.0 / (max - min)).round() as u8;
+    }
+
+    return Ok(());
+}
@@ -0,0 +1,196 @@
+use std::fmt;
+
+// Element type results can be converted to before they are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputDType {
+    U8,
+    // What the kernels produce, so no conversion happens.
+    #[default]
+    I16,
+    I32,
+    F32,
+}
+
+impl OutputDType {
+    pub fn parse(value: &str) -> Option<OutputDType> {
+        return match value {
+            "u8" => Some(OutputDType::U8),
+            "i16" => Some(OutputDType::I16),
+            "i32" => Some(OutputDType::I32),
+            "f32" => Some(OutputDType::F32),
+            _ => None,
+        };
+    }
+}
+
+impl fmt::Display for OutputDType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}", match self {
+            OutputDType::U8 => "u8",
+            OutputDType::I16 => "i16",
+            OutputDType::I32 => "i32",
+            OutputDType::F32 => "f32",
+        });
+    }
+}
+
+// Converted values, in the type convert_dtype was asked for.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Values {
+    U8(Vec<u8>),
+    I16(Vec<i16>),
+    I32(Vec<i32>),
+    F32(Vec<f32>),
+}
+
+impl Values {
+    pub fn dtype(&self) -> OutputDType {
+        return match self {
+            Values::U8(_) => OutputDType::U8,
+            Values::I16(_) => OutputDType::I16,
+            Values::I32(_) => OutputDType::I32,
+            Values::F32(_) => OutputDType::F32,
+        };
+    }
+
+    pub fn len(&self) -> usize {
+        return match self {
+            Values::U8(data) => data.len(),
+            Values::I16(data) => data.len(),
+            Values::I32(data) => data.len(),
+            Values::F32(data) => data.len(),
+        };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+}
+
+// Element types convert_dtype converts between. For every pair:
+//
+//   - to the same type, or to a wider integer type, values are unchanged;
+//   - integers to f32 are exact up to 2^24 in magnitude (all of u8 and i16)
+//     and round to the nearest f32 beyond that;
+//   - integers to a narrower integer type saturate at its bounds, e.g. an
+//     i32 of 40000 becomes the i16 32767;
+//   - f32 to an integer type rounds to the nearest integer, ties away from
+//     zero (2.5 becomes 3, -2.5 becomes -3), then saturates; NaN becomes 0;
+//   - anything to u8 takes the absolute value first, saturating into
+//     0..=255 as to_abs_u8 does for --u8-output, so the result can be viewed
+//     as an image whatever the sign of the gradient.
+pub trait Element: Copy {
+    fn to_u8(self) -> u8;
+    fn to_i16(self) -> i16;
+    fn to_i32(self) -> i32;
+    fn to_f32(self) -> f32;
+}
+
+macro_rules! impl_integer_element {
+    ($($t:ty),*) => {
+        $(impl Element for $t {
+            fn to_u8(self) -> u8 {
+                return (self as i64).unsigned_abs().min(u8::MAX as u64) as u8;
+            }
+
+            fn to_i16(self) -> i16 {
+                return (self as i64).clamp(i16::MIN as i64, i16::MAX as i64) as i16;
+            }
+
+            fn to_i32(self) -> i32 {
+                return (self as i64).clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+            }
+
+            fn to_f32(self) -> f32 {
+                return self as f32;
+            }
+        })*
+    };
+}
+
+impl_integer_element!(u8, i16, i32);
+
+impl Element for f32 {
+    // Casts from a float saturate and map NaN to 0, which is the rule above
+    // once the value is rounded.
+    fn to_u8(self) -> u8 {
+        return self.abs().round() as u8;
+    }
+
+    fn to_i16(self) -> i16 {
+        return self.round() as i16;
+    }
+
+    fn to_i32(self) -> i32 {
+        return self.round() as i32;
+    }
+
+    fn to_f32(self) -> f32 {
+        return self;
+    }
+}
+
+// Converts data to the element type to, following the rules at Element.
+pub fn convert_dtype<T: Element>(data: &[T], to: OutputDType) -> Values {
+    return match to {
+        OutputDType::U8 => Values::U8(data.iter().map(|value| value.to_u8()).collect()),
+        OutputDType::I16 => Values::I16(data.iter().map(|value| value.to_i16()).collect()),
+        OutputDType::I32 => Values::I32(data.iter().map(|value| value.to_i32()).collect()),
+        OutputDType::F32 => Values::F32(data.iter().map(|value| value.to_f32()).collect()),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The i16 inputs every table converts: both signs, the u8 bound and the
+    // largest |Dx| the central difference gives.
+    const INPUTS: [i16; 5] = [-510, -1, 0, 255, 510];
+
+    #[test]
+    fn i16_converts_to_every_dtype() {
+        assert_eq!(convert_dtype(&INPUTS, OutputDType::U8), Values::U8(vec![255, 1, 0, 255, 255]));
+        assert_eq!(convert_dtype(&INPUTS, OutputDType::I16), Values::I16(INPUTS.to_vec()));
+        assert_eq!(convert_dtype(&INPUTS, OutputDType::I32), Values::I32(vec![-510, -1, 0, 255, 510]));
+        assert_eq!(convert_dtype(&INPUTS, OutputDType::F32), Values::F32(vec![-510.0, -1.0, 0.0, 255.0, 510.0]));
+    }
+
+    #[test]
+    fn widenings_are_lossless() {
+        let extremes: [i16; 4] = [i16::MIN, -1, 1, i16::MAX];
+
+        assert_eq!(convert_dtype(&extremes, OutputDType::I32), Values::I32(vec![-32768, -1, 1, 32767]));
+        assert_eq!(convert_dtype(&extremes, OutputDType::F32), Values::F32(vec![-32768.0, -1.0, 1.0, 32767.0]));
+        assert_eq!(convert_dtype(&[0u8, 255], OutputDType::I16), Values::I16(vec![0, 255]));
+        assert_eq!(convert_dtype(&[1i32 << 24], OutputDType::F32), Values::F32(vec![16_777_216.0]));
+    }
+
+    #[test]
+    fn narrowings_saturate() {
+        assert_eq!(convert_dtype(&[40_000i32, -40_000, 32_767, -32_768], OutputDType::I16), Values::I16(vec![32_767, -32_768, 32_767, -32_768]));
+        assert_eq!(convert_dtype(&[i32::MIN, i32::MAX], OutputDType::U8), Values::U8(vec![255, 255]));
+        assert_eq!(convert_dtype(&[1e10f32, -1e10, f32::NAN], OutputDType::I32), Values::I32(vec![i32::MAX, i32::MIN, 0]));
+        assert_eq!(convert_dtype(&[-300.0f32, f32::NAN], OutputDType::U8), Values::U8(vec![255, 0]));
+    }
+
+    #[test]
+    fn f32_ties_round_away_from_zero() {
+        let ties: [f32; 6] = [2.5, -2.5, 0.5, -0.5, 1.4999, 254.5];
+
+        assert_eq!(convert_dtype(&ties, OutputDType::I16), Values::I16(vec![3, -3, 1, -1, 1, 255]));
+        assert_eq!(convert_dtype(&ties, OutputDType::I32), Values::I32(vec![3, -3, 1, -1, 1, 255]));
+        assert_eq!(convert_dtype(&ties, OutputDType::U8), Values::U8(vec![3, 3, 1, 1, 1, 255]));
+    }
+
+    #[test]
+    fn dtypes_parse_their_names() {
+        for dtype in [OutputDType::U8, OutputDType::I16, OutputDType::I32, OutputDType::F32] {
+            assert_eq!(OutputDType::parse(&dtype.to_string()), Some(dtype));
+            assert_eq!(convert_dtype::<i16>(&[], dtype).dtype(), dtype);
+        }
+
+        assert_eq!(OutputDType::parse("i8"), None);
+        assert_eq!(OutputDType::default(), OutputDType::I16);
+    }
+}
@@ -0,0 +1,161 @@
+use std::error::Error;
+use std::fmt;
+
+// Error returned when matrix dimensions passed to an operation do not agree
+// with each other or with the data they describe.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DimError {
+    // The slice length does not match rows * cols.
+    LengthMismatch { expected: usize, found: usize },
+    // A rectangle of height x width at (row, col) does not fit inside a
+    // rows x cols matrix.
+    OutOfRange { rows: usize, cols: usize, row: usize, col: usize, height: usize, width: usize },
+    // Two operands disagree on a dimension they must share, e.g. the row
+    // count of matrices concatenated side by side.
+    Mismatch { what: &'static str, expected: usize, found: usize },
+    // A strided view needs at least `needed` elements but the buffer holds
+    // only `found`.
+    BufferTooShort { needed: usize, found: usize },
+    // rows x cols elements, or rows rows whose starts are cols apart for a
+    // strided view, are more than a usize can count.
+    TooLarge { rows: usize, cols: usize },
+}
+
+impl fmt::Display for DimError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            DimError::LengthMismatch { expected, found } => {
+                write!(f, "expected {} elements but found {}", expected, found)
+            }
+            DimError::OutOfRange { rows, cols, row, col, height, width } => write!(
+                f,
+                "{}x{} region at ({}, {}) does not fit inside a {}x{} matrix",
+                height, width, row, col, rows, cols
+            ),
+            DimError::Mismatch { what, expected, found } => {
+                write!(f, "{} mismatch: expected {} but found {}", what, expected, found)
+            }
+            DimError::BufferTooShort { needed, found } => {
+                write!(f, "buffer holds {} elements but at least {} are needed", found, needed)
+            }
+            DimError::TooLarge { rows, cols } => write!(f, "a {}x{} matrix has more elements than fit in a usize", rows, cols),
+        };
+    }
+}
+
+impl Error for DimError {}
+
+// Error returned by integer operations that run under an ArithPolicy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArithError {
+    Dim(DimError),
+    // The value computed for output element `index` does not fit the output
+    // type and the policy was Checked.
+    Overflow { index: usize, value: i64 },
+}
+
+impl fmt::Display for ArithError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            ArithError::Dim(err) => write!(f, "{}", err),
+            ArithError::Overflow { index, value } => {
+                write!(f, "overflow at output index {}: {} does not fit the output type", index, value)
+            }
+        };
+    }
+}
+
+impl Error for ArithError {}
+
+impl From<DimError> for ArithError {
+    fn from(err: DimError) -> ArithError {
+        return ArithError::Dim(err);
+    }
+}
+
+// Error returned by the cancellable variants of long-running operations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CancelError {
+    Dim(DimError),
+    // The cancel token was set after `completed` of `total` rows were done.
+    // Partial results are discarded.
+    Cancelled { completed: usize, total: usize },
+}
+
+impl fmt::Display for CancelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            CancelError::Dim(err) => write!(f, "{}", err),
+            CancelError::Cancelled { completed, total } => write!(f, "cancelled after {} of {} rows", completed, total),
+        };
+    }
+}
+
+impl Error for CancelError {}
+
+impl From<DimError> for CancelError {
+    fn from(err: DimError) -> CancelError {
+        return CancelError::Dim(err);
+    }
+}
+
+// rows * cols, or TooLarge when the product overflows. Plain multiplication
+// wraps in release builds, so a wrapped product could pass a length check
+// for a buffer far smaller than the indices the kernels then compute.
+pub fn checked_elements(rows: usize, cols: usize) -> Result<usize, DimError> {
+    return rows.checked_mul(cols).ok_or(DimError::TooLarge { rows, cols });
+}
+
+// Checks that a slice holds exactly rows * cols elements.
+pub fn check_len(len: usize, rows: usize, cols: usize) -> Result<(), DimError> {
+    let expected: usize = checked_elements(rows, cols)?;
+
+    if len != expected {
+        return Err(DimError::LengthMismatch { expected, found: len });
+    }
+
+    return Ok(());
+}
+
+// Checks that a buffer can hold a rows x cols matrix whose rows start stride
+// elements apart.
+pub fn check_strided(len: usize, rows: usize, cols: usize, stride: usize) -> Result<(), DimError> {
+    if stride < cols {
+        return Err(DimError::Mismatch { what: "stride", expected: cols, found: stride });
+    }
+
+    // (rows - 1) * stride + cols, checked like check_len.
+    let needed: usize = match rows.checked_sub(1) {
+        None => 0,
+        Some(last) => last.checked_mul(stride).and_then(|start| start.checked_add(cols))
+            .ok_or(DimError::TooLarge { rows, cols: stride })?,
+    };
+
+    if len < needed {
+        return Err(DimError::BufferTooShort { needed, found: len });
+    }
+
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_len_rejects_a_wrapping_product() {
+        assert_eq!(check_len(6, 2, 3), Ok(()));
+        assert_eq!(check_len(5, 2, 3), Err(DimError::LengthMismatch { expected: 6, found: 5 }));
+        assert_eq!(check_len(0, 1 << 32, 1 << 32), Err(DimError::TooLarge { rows: 1 << 32, cols: 1 << 32 }));
+        assert_eq!(check_len(0, usize::MAX, 0), Ok(()));
+    }
+
+    #[test]
+    fn check_strided_rejects_a_wrapping_extent() {
+        assert_eq!(check_strided(10, 3, 2, 4), Ok(()));
+        assert_eq!(check_strided(9, 3, 2, 4), Err(DimError::BufferTooShort { needed: 10, found: 9 }));
+        assert_eq!(check_strided(0, 0, 2, 4), Ok(()));
+        assert_eq!(check_strided(1, 3, 1, 1 << 63), Err(DimError::TooLarge { rows: 3, cols: 1 << 63 }));
+        assert_eq!(check_strided(1, 2, usize::MAX, usize::MAX), Err(DimError::TooLarge { rows: 2, cols: usize::MAX }));
+    }
+}
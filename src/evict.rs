@@ -0,0 +1,172 @@
+use std::fs;
+use std::hint::black_box;
+use std::path::Path;
+
+// Bytes of a cache line, the stride the eviction buffer is walked with.
+const CACHE_LINE: usize = 64;
+
+// Last-level cache size assumed when it cannot be detected: larger than that
+// of most desktop and many server parts, so eviction errs on the thorough side.
+pub const DEFAULT_LLC_SIZE: usize = 32 << 20;
+
+// Where the timed runs find their input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheState {
+    // Out of the caches: evicted before every run, as on the first run over
+    // freshly loaded data.
+    Cold,
+    // In the caches as far as it fits: touched once before the first run.
+    Warm,
+}
+
+impl CacheState {
+    pub fn parse(name: &str) -> Option<CacheState> {
+        return match name {
+            "cold" => Some(CacheState::Cold),
+            "warm" => Some(CacheState::Warm),
+            _ => None,
+        };
+    }
+
+    pub fn name(self) -> &'static str {
+        return match self {
+            CacheState::Cold => "cold",
+            CacheState::Warm => "warm",
+        };
+    }
+}
+
+// Size of the largest cache of CPU 0, the last-level cache on the machines
+// we run on, read from sysfs. None off Linux or if no size can be read.
+pub fn detect_llc_size() -> Option<usize> {
+    let entries = fs::read_dir(Path::new("/sys/devices/system/cpu/cpu0/cache")).ok()?;
+
+    return entries.filter_map(|entry| {
+        let text: String = fs::read_to_string(entry.ok()?.path().join("size")).ok()?;
+        parse_cache_size(&text)
+    }).max();
+}
+
+// Parses a sysfs cache size such as "48K", "2048K" or "32M".
+fn parse_cache_size(text: &str) -> Option<usize> {
+    let text: &str = text.trim();
+    let split: usize = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let value: usize = text[..split].parse().ok()?;
+
+    let scale: usize = match &text[split..] {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return None,
+    };
+
+    return value.checked_mul(scale);
+}
+
+// True if Evictor::evict flushes the data's lines rather than streaming over
+// its buffer.
+pub const FLUSHES: bool = cfg!(all(feature = "cache-flush", target_arch = "x86_64"));
+
+// Evicts data from the CPU caches between timed runs by streaming over a
+// buffer of twice the last-level cache size, so that nothing read before is
+// left in it. With the cache-flush feature on x86-64 the data's lines are
+// flushed with clflush instead, which is quicker and does not depend on the
+// cache size being right.
+pub struct Evictor {
+    buffer: Vec<u8>,
+}
+
+impl Evictor {
+    // An evictor for a last-level cache of llc_size bytes.
+    pub fn new(llc_size: usize) -> Evictor {
+        return Evictor { buffer: vec![0; buffer_len(llc_size)] };
+    }
+
+    // Bytes streamed over by each evict (unless it flushes, see FLUSHES).
+    pub fn bytes(&self) -> usize {
+        return self.buffer.len();
+    }
+
+    // Makes sure none of data is cached.
+    #[cfg(all(feature = "cache-flush", target_arch = "x86_64"))]
+    pub fn evict<T>(&mut self, data: &[T]) {
+        use std::arch::x86_64::{_mm_clflush, _mm_mfence};
+        use std::mem::size_of_val;
+
+        let bytes: usize = size_of_val(data);
+        let start: *const u8 = data.as_ptr().cast();
+
+        for offset in (0..bytes).step_by(CACHE_LINE) {
+            // SAFETY: clflush (SSE2, part of x86-64) only needs a readable
+            // address, and every offset is inside data.
+            unsafe { _mm_clflush(start.add(offset)) };
+        }
+
+        // SAFETY: mfence has no preconditions.
+        unsafe { _mm_mfence() };
+    }
+
+    #[cfg(not(all(feature = "cache-flush", target_arch = "x86_64")))]
+    pub fn evict<T>(&mut self, _data: &[T]) {
+        // Writing each line makes its old contents write back and go, even on
+        // caches that keep lines which are only read.
+        for line in self.buffer.chunks_mut(CACHE_LINE) {
+            line[0] = line[0].wrapping_add(1);
+        }
+
+        black_box(&mut self.buffer);
+    }
+}
+
+// Bytes of the eviction buffer for a cache of llc_size bytes.
+pub fn buffer_len(llc_size: usize) -> usize {
+    return llc_size.max(CACHE_LINE).saturating_mul(2);
+}
+
+// Reads one byte of every cache line of data, bringing as much of it into the
+// caches as fits.
+pub fn touch(data: &[u8]) {
+    let sum: u8 = data.iter().step_by(CACHE_LINE).fold(0, |sum, &value| sum.wrapping_add(value));
+
+    black_box(sum);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sysfs_cache_sizes_parse() {
+        assert_eq!(parse_cache_size("48K\n"), Some(48 << 10));
+        assert_eq!(parse_cache_size("2048K"), Some(2048 << 10));
+        assert_eq!(parse_cache_size("32M"), Some(32 << 20));
+        assert_eq!(parse_cache_size("512"), Some(512));
+        assert_eq!(parse_cache_size("K"), None);
+        assert_eq!(parse_cache_size("48 kB"), None);
+    }
+
+    #[test]
+    fn the_buffer_is_twice_the_cache() {
+        assert_eq!(Evictor::new(64 << 10).bytes(), 128 << 10);
+        assert_eq!(buffer_len(DEFAULT_LLC_SIZE), 64 << 20);
+        assert_eq!(buffer_len(0), CACHE_LINE * 2);
+        assert_eq!(buffer_len(usize::MAX), usize::MAX);
+
+        // Evicting and touching leave the data alone.
+        let data: Vec<u8> = (0..=255).collect();
+        let mut evictor: Evictor = Evictor::new(1 << 10);
+        evictor.evict(&data);
+        touch(&data);
+        assert_eq!(data, (0..=255).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn cache_states_round_trip_through_their_names() {
+        for state in [CacheState::Cold, CacheState::Warm] {
+            assert_eq!(CacheState::parse(state.name()), Some(state));
+        }
+
+        assert_eq!(CacheState::parse("both"), None);
+    }
+}
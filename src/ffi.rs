@@ -0,0 +1,103 @@
+use std::mem::size_of;
+use std::slice;
+use crate::error::DimError;
+use crate::kernels::{compute_dx_into, compute_dy_into};
+
+// C interface to the [-1, 0, 1] kernels, declared in include/rmm.h. The
+// caller owns every buffer: it asks rmm_dx_output_len / rmm_dy_output_len
+// how many elements to allocate, allocates them once on its side (e.g. as a
+// NumPy array) and passes them in, and the results are written there
+// directly, with no allocation or copy on the Rust side.
+//
+// The library is built as an rlib; to get a shared library for C, build it
+// with
+//
+//   cargo rustc --lib --release --features ffi --crate-type cdylib
+
+// Outcome of an rmm_* call (RMM_OK and so on in C). Nothing is written
+// unless it is Ok.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RmmStatus {
+    Ok = 0,
+    // The input or the output pointer is NULL.
+    NullPointer = 1,
+    // out_len is less than the output length of the dimensions.
+    BufferTooSmall = 2,
+    // rows * cols, or the output length, does not fit a size_t (or is too
+    // large for any buffer).
+    DimensionOverflow = 3,
+}
+
+// compute_dx_into or compute_dy_into.
+type IntoKernel = fn(&[u8], usize, usize, &mut [i16]) -> Result<(), DimError>;
+
+// Output length rmm_dx_output_len and rmm_dy_output_len return when the
+// dimensions overflow.
+pub const RMM_LEN_OVERFLOW: usize = usize::MAX;
+
+// Elements of the Dx of a rows x cols input, rows * (cols + 2), or
+// RMM_LEN_OVERFLOW if that does not fit a buffer.
+#[no_mangle]
+pub extern "C" fn rmm_dx_output_len(rows: usize, cols: usize) -> usize {
+    return checked_lens(rows, cols, (0, 2)).map_or(RMM_LEN_OVERFLOW, |(_, out_len)| out_len);
+}
+
+// Elements of the Dy of a rows x cols input, (rows + 2) * cols, or
+// RMM_LEN_OVERFLOW if that does not fit a buffer.
+#[no_mangle]
+pub extern "C" fn rmm_dy_output_len(rows: usize, cols: usize) -> usize {
+    return checked_lens(rows, cols, (2, 0)).map_or(RMM_LEN_OVERFLOW, |(_, out_len)| out_len);
+}
+
+// Writes the Dx of the rows x cols u8 matrix at arr to the first
+// rmm_dx_output_len(rows, cols) elements of out, which holds out_len.
+//
+// Safety: arr must point to rows * cols readable bytes and out to out_len
+// writable i16s, neither overlapping the other, for the duration of the call.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn rmm_compute_dx_into(arr: *const u8, rows: usize, cols: usize, out: *mut i16, out_len: usize) -> RmmStatus {
+    return run_into(arr, rows, cols, out, out_len, (0, 2), compute_dx_into);
+}
+
+// rmm_compute_dx_into for Dy, whose length is rmm_dy_output_len(rows, cols).
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn rmm_compute_dy_into(arr: *const u8, rows: usize, cols: usize, out: *mut i16, out_len: usize) -> RmmStatus {
+    return run_into(arr, rows, cols, out, out_len, (2, 0), compute_dy_into);
+}
+
+// The validation both kernels share; pad is the (rows, cols) the output has
+// beyond the input.
+unsafe fn run_into(arr: *const u8, rows: usize, cols: usize, out: *mut i16, out_len: usize, pad: (usize, usize), kernel: IntoKernel) -> RmmStatus {
+    if arr.is_null() || out.is_null() {
+        return RmmStatus::NullPointer;
+    }
+
+    let Some((in_len, needed)) = checked_lens(rows, cols, pad) else {
+        return RmmStatus::DimensionOverflow;
+    };
+
+    if out_len < needed {
+        return RmmStatus::BufferTooSmall;
+    }
+
+    // SAFETY: the caller guarantees the pointers cover in_len and out_len >=
+    // needed elements and do not overlap, and both lengths fit an isize.
+    let (arr, out): (&[u8], &mut [i16]) = unsafe { (slice::from_raw_parts(arr, in_len), slice::from_raw_parts_mut(out, needed)) };
+
+    return match kernel(arr, rows, cols, out) {
+        Ok(()) => RmmStatus::Ok,
+        // Not reachable: the lengths were derived from the dimensions.
+        Err(_) => RmmStatus::DimensionOverflow,
+    };
+}
+
+// (rows * cols, (rows + pad.0) * (cols + pad.1)) if both fit a slice of i16.
+fn checked_lens(rows: usize, cols: usize, pad: (usize, usize)) -> Option<(usize, usize)> {
+    let in_len: usize = rows.checked_mul(cols)?;
+    let out_len: usize = rows.checked_add(pad.0)?.checked_mul(cols.checked_add(pad.1)?)?;
+
+    return (out_len <= isize::MAX as usize / size_of::<i16>() && in_len <= isize::MAX as usize).then_some((in_len, out_len));
+}
@@ -0,0 +1,267 @@
+use crate::error::{check_len, checked_elements, DimError};
+use crate::index::{flat, padded_dims, row_range, Axis, Padding};
+use crate::matrix::{Layout, Matrix};
+
+// Spreads the values of a matrix over the whole 0..=255 range by histogram
+// equalization: value v maps to round(255 * (cdf(v) - cdf_min) / (n -
+// cdf_min)), where cdf(v) counts the elements <= v, cdf_min is the count of
+// the smallest value present and n the element count. The smallest value
+// thus becomes 0 and the largest 255, and values that were crowded together
+// are pulled apart. The order of values is kept. A matrix with a single
+// distinct value (or none) is returned unchanged, as there is nothing to
+// spread.
+pub fn equalize_histogram(arr: &[u8], rows: usize, cols: usize) -> Result<Vec<u8>, DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    let mut cdf: [usize; 256] = [0; 256];
+
+    for &value in arr {
+        cdf[value as usize] += 1;
+    }
+
+    for value in 1..256 {
+        cdf[value] += cdf[value - 1];
+    }
+
+    let cdf_min: usize = cdf.iter().copied().find(|&count| count > 0).unwrap_or(0);
+    let spread: usize = arr.len() - cdf_min;
+
+    if spread == 0 {
+        return Ok(arr.to_vec());
+    }
+
+    let map: Vec<u8> = cdf.iter().map(|&count| ((count.saturating_sub(cdf_min) * 255 + spread / 2) / spread) as u8).collect();
+
+    return Ok(arr.iter().map(|&value| map[value as usize]).collect());
+}
+
+// Builds a normalized 1D Gaussian kernel with radius ceil(3 * sigma).
+pub fn gaussian_kernel(sigma: f32) -> Vec<f32> {
+    let radius: usize = (3.0 * sigma).ceil().max(0.0) as usize;
+    let mut kernel: Vec<f32> = Vec::with_capacity(2 * radius + 1);
+
+    for i in 0..2 * radius + 1 {
+        let x: f32 = i as f32 - radius as f32;
+        kernel.push((-(x * x) / (2.0 * sigma * sigma)).exp());
+    }
+
+    let sum: f32 = kernel.iter().sum();
+
+    return kernel.iter().map(|weight| weight / sum).collect();
+}
+
+// Blurs a matrix with a separable Gaussian of the given standard deviation.
+// Borders are handled by replicating the edge element, and the result is
+// rounded to the nearest integer. A non-positive sigma returns the input.
+pub fn gaussian_blur(arr: &[u8], rows: usize, cols: usize, sigma: f32) -> Vec<u8> {
+    if sigma <= 0.0 || rows == 0 || cols == 0 {
+        return arr.to_vec();
+    }
+
+    let kernel: Vec<f32> = gaussian_kernel(sigma);
+    let radius: isize = (kernel.len() / 2) as isize;
+    let mut horizontal: Vec<f32> = vec![0.0; rows * cols];
+
+    // Blur along rows first
+    for row in 0..rows {
+        for col in 0..cols {
+            let mut sum: f32 = 0.0;
+
+            for (i, weight) in kernel.iter().enumerate() {
+                let src_col: usize = (col as isize + i as isize - radius).clamp(0, cols as isize - 1) as usize;
+                sum += weight * arr[flat(row, src_col, cols)] as f32;
+            }
+
+            horizontal[flat(row, col, cols)] = sum;
+        }
+    }
+
+    // Then along columns of the row-blurred intermediate
+    let mut out: Vec<u8> = vec![0; rows * cols];
+
+    for row in 0..rows {
+        for col in 0..cols {
+            let mut sum: f32 = 0.0;
+
+            for (i, weight) in kernel.iter().enumerate() {
+                let src_row: usize = (row as isize + i as isize - radius).clamp(0, rows as isize - 1) as usize;
+                sum += weight * horizontal[flat(src_row, col, cols)];
+            }
+
+            out[flat(row, col, cols)] = sum.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+
+    return out;
+}
+
+// Blurs a matrix with the 3x3 kernel [1, 2, 1]^T [1, 2, 1] / 16, i.e. the
+// [1, 2, 1] / 4 smoothing along rows and then columns. Borders are handled by
+// replicating the edge element, as gaussian_blur does. The exact integer sum
+// is divided once and rounded half up, (sum + 8) / 16, so no rounding happens
+// between the two passes and the result always fits a u8.
+pub fn blur3(arr: &[u8], rows: usize, cols: usize) -> Result<Vec<u8>, DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    let at = |row: isize, col: isize| -> u16 {
+        let row: usize = row.clamp(0, rows as isize - 1) as usize;
+        let col: usize = col.clamp(0, cols as isize - 1) as usize;
+        arr[flat(row, col, cols)] as u16
+    };
+    let mut out: Vec<u8> = Vec::with_capacity(arr.len());
+
+    for row in 0..rows as isize {
+        for col in 0..cols as isize {
+            let mut sum: u16 = 0;
+
+            for (i, row_weight) in BLUR3_TAPS.iter().enumerate() {
+                for (j, col_weight) in BLUR3_TAPS.iter().enumerate() {
+                    sum += row_weight * col_weight * at(row + i as isize - 1, col + j as isize - 1);
+                }
+            }
+
+            out.push(((sum + 8) / 16) as u8);
+        }
+    }
+
+    return Ok(out);
+}
+
+// compute_dx of blur3 of the input, fused into one pass for matrices too
+// large to hold a blurred copy next to the input. Only the row-smoothed
+// values of the three input rows the current output row needs are kept, in a
+// rolling window reused from row to row, plus one blurred row: O(cols)
+// working memory besides the rows x (cols + 2) output. The result is
+// identical to compute_dx(&blur3(arr, rows, cols)?, rows, cols).
+pub fn blur3_then_dx(arr: &[u8], rows: usize, cols: usize) -> Result<Matrix<i16>, DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    let (_, new_cols): (usize, usize) = padded_dims(rows, cols, 3, Axis::Cols, Padding::Full);
+    let mut dx: Vec<i16> = vec![0; checked_elements(rows, new_cols)?];
+
+    if cols == 0 {
+        return Ok(Matrix { data: dx, rows, cols: new_cols, layout: Layout::RowMajor });
+    }
+
+    // window[i % 3] holds input row i smoothed along the row.
+    let mut window: [Vec<u16>; 3] = [vec![0; cols], vec![0; cols], vec![0; cols]];
+    let mut blurred: Vec<u8> = vec![0; cols];
+
+    for row in 0..rows.min(2) {
+        smooth_row(&arr[row_range(row, cols)], &mut window[row]);
+    }
+
+    for row in 0..rows {
+        if row >= 1 && row + 1 < rows {
+            smooth_row(&arr[row_range(row + 1, cols)], &mut window[(row + 1) % 3]);
+        }
+
+        // Rows past the border replicate the edge rows.
+        let above: &[u16] = &window[row.saturating_sub(1) % 3];
+        let middle: &[u16] = &window[row % 3];
+        let below: &[u16] = &window[(row + 1).min(rows - 1) % 3];
+
+        for (col, value) in blurred.iter_mut().enumerate() {
+            *value = ((above[col] + 2 * middle[col] + below[col] + 8) / 16) as u8;
+        }
+
+        // Output column c is blurred column c - 2 minus blurred column c.
+        let out: &mut [i16] = &mut dx[row_range(row, new_cols)];
+
+        for (col, value) in out.iter_mut().enumerate() {
+            let left: i16 = if col >= 2 { blurred[col - 2] as i16 } else { 0 };
+            let right: i16 = blurred.get(col).map_or(0, |&value| value as i16);
+            *value = left - right;
+        }
+    }
+
+    return Ok(Matrix { data: dx, rows, cols: new_cols, layout: Layout::RowMajor });
+}
+
+// Weights of the [1, 2, 1] smoothing, before the division by 4.
+const BLUR3_TAPS: [u16; 3] = [1, 2, 1];
+
+// One row smoothed with [1, 2, 1], replicating the edge elements, without
+// dividing: every value is at most 4 * 255.
+fn smooth_row(src: &[u8], out: &mut [u16]) {
+    let last: usize = src.len() - 1;
+
+    for (col, value) in out.iter_mut().enumerate() {
+        *value = src[col.saturating_sub(1)] as u16 + 2 * src[col] as u16 + src[(col + 1).min(last)] as u16;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernels::compute_dx;
+    use crate::matrix::construct_randomized_matrix_seeded;
+
+    #[test]
+    fn blur3_then_dx_matches_compute_dx_of_blur3() {
+        for (rows, cols) in [(0, 0), (0, 4), (4, 0), (1, 1), (1, 9), (9, 1), (2, 2), (3, 3), (37, 53)] {
+            let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, (rows * 100 + cols) as u64);
+            let blurred: Vec<u8> = blur3(&arr, rows, cols).unwrap();
+
+            assert_eq!(blur3_then_dx(&arr, rows, cols).unwrap(), compute_dx(&blurred, rows, cols), "{}x{}", rows, cols);
+        }
+
+        assert!(blur3_then_dx(&[1, 2, 3], 2, 2).is_err());
+    }
+
+    // Besides the output, the fused pass keeps a few rows of working memory:
+    // more columns need more of it, more rows do not.
+    #[cfg(feature = "mem-stats")]
+    #[test]
+    fn blur3_then_dx_working_memory_grows_with_cols_only() {
+        use crate::memstats::{thread_reset, thread_snapshot};
+
+        let extra = |rows: usize, cols: usize| -> usize {
+            let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, 7);
+
+            thread_reset();
+            let base: usize = thread_snapshot().current_bytes;
+            let dx: Matrix<i16> = blur3_then_dx(&arr, rows, cols).unwrap();
+            let output: usize = dx.data.len() * std::mem::size_of::<i16>();
+
+            return thread_snapshot().peak_bytes - base - output;
+        };
+
+        let small: usize = extra(16, 256);
+        assert_eq!(extra(1024, 256), small);
+        assert!(small < 16 * 256, "{} bytes", small);
+        assert!(extra(16, 4096) >= 16 * small, "{} bytes", extra(16, 4096));
+    }
+
+    #[test]
+    fn equalizing_a_uniform_histogram_is_the_identity() {
+        // Every value the same number of times, in a scrambled order.
+        for copies in [1, 2, 5] {
+            let arr: Vec<u8> = (0..256 * copies).map(|index| (index * 97 % 256) as u8).collect();
+
+            assert_eq!(equalize_histogram(&arr, copies, 256).unwrap(), arr, "{} copies", copies);
+        }
+    }
+
+    #[test]
+    fn equalization_stretches_to_the_full_range_and_keeps_the_order() {
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(13, 21, 4).iter().map(|&value| 100 + value / 8).collect();
+        let equalized: Vec<u8> = equalize_histogram(&arr, 13, 21).unwrap();
+
+        assert_eq!(*equalized.iter().min().unwrap(), 0);
+        assert_eq!(*equalized.iter().max().unwrap(), 255);
+
+        // Sorted by input value, the outputs never decrease.
+        let mut pairs: Vec<(u8, u8)> = arr.iter().copied().zip(equalized.iter().copied()).collect();
+        pairs.sort();
+
+        assert!(pairs.windows(2).all(|pair| pair[0].1 <= pair[1].1), "{:?}", pairs);
+
+        // By hand: cdf 1, 3, 4 over the values 10, 20, 30.
+        assert_eq!(equalize_histogram(&[20, 10, 30, 20], 2, 2).unwrap(), vec![170, 0, 255, 170]);
+        // One distinct value, or none, is left alone.
+        assert_eq!(equalize_histogram(&[9; 6], 2, 3).unwrap(), vec![9; 6]);
+        assert_eq!(equalize_histogram(&[], 0, 0).unwrap(), Vec::<u8>::new());
+        assert!(equalize_histogram(&[1, 2, 3], 2, 2).is_err());
+    }
+}
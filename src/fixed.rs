@@ -0,0 +1,235 @@
+use std::fmt;
+use std::ops::{Add, Neg, Sub};
+use crate::arith::{ArithPolicy, Narrow, Widen};
+
+// Fractional bits of Fixed16 (Q8.8) and Fixed32 (Q16.16).
+pub const FIXED16_FRAC_BITS: u32 = 8;
+pub const FIXED32_FRAC_BITS: u32 = 16;
+
+// A Q8.8 fixed-point number: an i16 holding value * 256, so it covers
+// -128 to 127.99609375 in steps of 1/256 exactly. Ordering and equality are
+// those of the raw value, which makes get_min and get_max work unchanged.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed16(pub i16);
+
+// A Q16.16 fixed-point number, the accumulator convolutions of Fixed16 data
+// are written into. Every Fixed16 converts to it exactly.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed32(pub i32);
+
+impl Fixed16 {
+    pub fn from_raw(raw: i16) -> Fixed16 {
+        return Fixed16(raw);
+    }
+
+    pub fn raw(self) -> i16 {
+        return self.0;
+    }
+
+    // The Fixed16 equal to value, or None if value is out of range or not a
+    // multiple of 1/256. Never rounds.
+    pub fn from_f64(value: f64) -> Option<Fixed16> {
+        let scaled: f64 = value * (1 << FIXED16_FRAC_BITS) as f64;
+
+        if scaled.fract() != 0.0 || scaled < i16::MIN as f64 || scaled > i16::MAX as f64 {
+            return None;
+        }
+
+        return Some(Fixed16(scaled as i16));
+    }
+
+    pub fn to_f64(self) -> f64 {
+        return self.0 as f64 / (1 << FIXED16_FRAC_BITS) as f64;
+    }
+
+    // The Fixed16 nearest to value, halves rounding away from zero, so off
+    // by at most 2^-9. Values past the range saturate to -128 or
+    // 127.99609375, and NaN gives 0.
+    pub fn from_f32(value: f32) -> Fixed16 {
+        let scaled: f32 = (value * (1 << FIXED16_FRAC_BITS) as f32).round();
+
+        return Fixed16(scaled.clamp(i16::MIN as f32, i16::MAX as f32) as i16);
+    }
+
+    // Exact, as every Fixed16 has at most 16 significant bits.
+    pub fn to_f32(self) -> f32 {
+        return self.0 as f32 / (1 << FIXED16_FRAC_BITS) as f32;
+    }
+
+    // The same value in Q16.16.
+    pub fn widen(self) -> Fixed32 {
+        return Fixed32((self.0 as i32) << (FIXED32_FRAC_BITS - FIXED16_FRAC_BITS));
+    }
+}
+
+impl Fixed32 {
+    pub fn from_raw(raw: i32) -> Fixed32 {
+        return Fixed32(raw);
+    }
+
+    pub fn raw(self) -> i32 {
+        return self.0;
+    }
+
+    pub fn to_f64(self) -> f64 {
+        return self.0 as f64 / (1u32 << FIXED32_FRAC_BITS) as f64;
+    }
+}
+
+// Sums and differences wrap like the i16 and i32 the kernels produce.
+macro_rules! impl_fixed_ops {
+    ($($t:ident),*) => {
+        $(impl Add for $t {
+            type Output = $t;
+
+            fn add(self, other: $t) -> $t {
+                return $t(self.0.wrapping_add(other.0));
+            }
+        }
+
+        impl Sub for $t {
+            type Output = $t;
+
+            fn sub(self, other: $t) -> $t {
+                return $t(self.0.wrapping_sub(other.0));
+            }
+        }
+
+        impl Neg for $t {
+            type Output = $t;
+
+            fn neg(self) -> $t {
+                return $t(self.0.wrapping_neg());
+            }
+        })*
+    };
+}
+
+impl_fixed_ops!(Fixed16, Fixed32);
+
+// Both types widen to their raw value in Q16.16 units, so a convolution of
+// Fixed16 data with integer weights accumulates exact Q16.16 sums.
+impl Widen for Fixed16 {
+    fn widen(self) -> i64 {
+        return Fixed16::widen(self).0 as i64;
+    }
+}
+
+impl Widen for Fixed32 {
+    fn widen(self) -> i64 {
+        return self.0 as i64;
+    }
+}
+
+// The accumulator is already in Q16.16 units; only the range is checked.
+impl Narrow for Fixed32 {
+    fn narrow(value: i64, policy: ArithPolicy) -> Option<Fixed32> {
+        return i32::narrow(value, policy).map(Fixed32);
+    }
+}
+
+impl fmt::Display for Fixed16 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}", format_fixed(self.0 as i64, FIXED16_FRAC_BITS));
+    }
+}
+
+impl fmt::Display for Fixed32 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}", format_fixed(self.0 as i64, FIXED32_FRAC_BITS));
+    }
+}
+
+// The exact decimal value of raw / 2^frac_bits, e.g. 12.5 or -0.00390625,
+// with no trailing zeros. Binary fractions always have a finite decimal
+// expansion of at most frac_bits digits, so nothing is rounded.
+fn format_fixed(raw: i64, frac_bits: u32) -> String {
+    let magnitude: u64 = raw.unsigned_abs();
+    let mask: u64 = (1 << frac_bits) - 1;
+    let mut text: String = format!("{}{}", if raw < 0 { "-" } else { "" }, magnitude >> frac_bits);
+    let mut frac: u64 = magnitude & mask;
+
+    if frac != 0 {
+        text.push('.');
+    }
+
+    while frac != 0 {
+        frac *= 10;
+        text.push(char::from(b'0' + (frac >> frac_bits) as u8));
+        frac &= mask;
+    }
+
+    return text;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conv::{convolve_cols, convolve_rows, CENTRAL_DIFFERENCE};
+
+    #[test]
+    fn exact_values_convert_exactly() {
+        for value in [0.5, -1.25, 0.0, 12.5, -128.0, 127.99609375, 0.00390625] {
+            let fixed: Fixed16 = Fixed16::from_f64(value).unwrap();
+
+            assert_eq!(fixed.to_f64(), value);
+            assert_eq!(Fixed16::from_f32(value as f32), fixed);
+            assert_eq!(fixed.widen().to_f64(), value);
+        }
+
+        assert_eq!(Fixed16::from_f64(0.5), Some(Fixed16(128)));
+        assert_eq!(Fixed16::from_f64(-1.25), Some(Fixed16(-320)));
+        assert_eq!(Fixed16::from_f64(0.001), None);
+        assert_eq!(Fixed16::from_f64(128.0), None);
+        assert_eq!(Fixed16(-320).widen(), Fixed32(-81_920));
+    }
+
+    #[test]
+    fn rounding_is_off_by_at_most_half_a_step() {
+        for index in -129_000..129_000 {
+            let value: f32 = index as f32 / 1013.0;
+            let error: f32 = (Fixed16::from_f32(value).to_f32() - value).abs();
+
+            assert!(error <= 1.0 / 512.0, "{} came back {} off", value, error);
+        }
+
+        assert_eq!(Fixed16::from_f32(1.0 / 512.0), Fixed16(1));
+        assert_eq!(Fixed16::from_f32(-1.0 / 512.0), Fixed16(-1));
+    }
+
+    #[test]
+    fn out_of_range_values_saturate() {
+        for (value, raw) in [(127.996, i16::MAX), (128.0, i16::MAX), (1e9, i16::MAX), (f32::INFINITY, i16::MAX),
+                             (-128.0, i16::MIN), (-128.1, i16::MIN), (f32::NEG_INFINITY, i16::MIN), (f32::NAN, 0)] {
+            assert_eq!(Fixed16::from_f32(value), Fixed16(raw), "{}", value);
+        }
+
+        assert_eq!(Fixed16(i16::MAX).to_f32(), 128.0 - 1.0 / 256.0);
+        assert_eq!(Fixed16(i16::MAX).to_string(), "127.99609375");
+        assert_eq!(Fixed16(i16::MIN).to_string(), "-128");
+    }
+
+    #[test]
+    fn the_q8_8_kernel_matches_the_f32_one() {
+        let (rows, cols): (usize, usize) = (9, 13);
+        let values: Vec<f32> = (0..rows * cols).map(|index| ((index * 37 % 251) as f32 - 125.0) * 0.51).collect();
+        let arr: Vec<Fixed16> = values.iter().map(|&value| Fixed16::from_f32(value)).collect();
+        let exact: Vec<f32> = arr.iter().map(|value| value.to_f32()).collect();
+        let ulp: f64 = 1.0 / (1u32 << FIXED32_FRAC_BITS) as f64;
+
+        // The f32 kernel on the same values: out[c] = in[c - 2] - in[c].
+        let at = |row: usize, col: isize| if (0..cols as isize).contains(&col) { exact[row * cols + col as usize] } else { 0.0 };
+        let expected_dx: Vec<f32> = (0..rows).flat_map(|row| (0..cols as isize + 2).map(move |col| at(row, col - 2) - at(row, col))).collect();
+
+        let dx: Vec<Fixed32> = convolve_rows(&arr, rows, cols, &CENTRAL_DIFFERENCE, ArithPolicy::Checked).unwrap();
+        assert_eq!(dx.len(), expected_dx.len());
+        for (fixed, float) in dx.iter().zip(&expected_dx) {
+            assert!((fixed.to_f64() - *float as f64).abs() <= ulp, "{} against {}", fixed, float);
+        }
+
+        // Dy of a single column is Dx of the same values as a row.
+        let column: Vec<Fixed32> = convolve_cols(&arr[..rows], rows, 1, &CENTRAL_DIFFERENCE, ArithPolicy::Checked).unwrap();
+        let row: Vec<Fixed32> = convolve_rows(&arr[..rows], 1, rows, &CENTRAL_DIFFERENCE, ArithPolicy::Checked).unwrap();
+        assert_eq!(column, row);
+    }
+}
@@ -0,0 +1,319 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use wgpu::util::DeviceExt;
+use crate::error::{check_len, DimError};
+
+// Threads per workgroup of both shaders.
+const WORKGROUP_SIZE: u32 = 256;
+
+// Largest number of workgroups along one dispatch dimension guaranteed by
+// WebGPU. Larger dispatches are folded into a second dimension.
+const MAX_GROUPS_PER_DIM: u32 = 65535;
+
+// The [-1, 0, 1] convolutions of compute_dx and compute_dy, one thread per
+// output element. The u8 input is packed four to a u32 word.
+const SHADER: &str = r#"
+struct Dims {
+    rows: u32,
+    cols: u32,
+    out_rows: u32,
+    out_cols: u32,
+    threads_x: u32,
+}
+
+@group(0) @binding(0) var<storage, read> input: array<u32>;
+@group(0) @binding(1) var<storage, read_write> output: array<i32>;
+@group(0) @binding(2) var<uniform> dims: Dims;
+
+// Element (row, col) of the input, or 0 in the padding around it.
+fn at(row: i32, col: i32) -> i32 {
+    if (row < 0 || col < 0 || row >= i32(dims.rows) || col >= i32(dims.cols)) {
+        return 0;
+    }
+    let index: u32 = u32(row) * dims.cols + u32(col);
+    return i32((input[index / 4u] >> (8u * (index % 4u))) & 0xffu);
+}
+
+@compute @workgroup_size(256)
+fn dx(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index: u32 = id.y * dims.threads_x + id.x;
+    if (index >= dims.out_rows * dims.out_cols) {
+        return;
+    }
+    let row: i32 = i32(index / dims.out_cols);
+    let col: i32 = i32(index % dims.out_cols);
+    output[index] = at(row, col - 2) - at(row, col);
+}
+
+@compute @workgroup_size(256)
+fn dy(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index: u32 = id.y * dims.threads_x + id.x;
+    if (index >= dims.out_rows * dims.out_cols) {
+        return;
+    }
+    let row: i32 = i32(index / dims.out_cols);
+    let col: i32 = i32(index % dims.out_cols);
+    output[index] = at(row - 2, col) - at(row, col);
+}
+"#;
+
+// Error returned by the GPU backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GpuError {
+    Dim(DimError),
+    // No adapter was found, e.g. no GPU or no driver.
+    NoAdapter,
+    // The adapter was found but a device could not be created or used.
+    Device(String),
+    // The output needs more bytes than one storage buffer may hold.
+    TooLarge { needed: u64, limit: u64 },
+}
+
+impl fmt::Display for GpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            GpuError::Dim(err) => write!(f, "{}", err),
+            GpuError::NoAdapter => write!(f, "no GPU adapter found"),
+            GpuError::Device(message) => write!(f, "GPU device error: {}", message),
+            GpuError::TooLarge { needed, limit } => {
+                write!(f, "result needs {} bytes but the GPU allows buffers of at most {}", needed, limit)
+            }
+        };
+    }
+}
+
+impl Error for GpuError {}
+
+impl From<DimError> for GpuError {
+    fn from(err: DimError) -> GpuError {
+        return GpuError::Dim(err);
+    }
+}
+
+// Time spent in each phase of a GPU kernel run. Transfers usually dominate.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GpuTimings {
+    pub upload: Duration,
+    pub compute: Duration,
+    pub download: Duration,
+}
+
+// Names and backends of every adapter wgpu can see, for diagnostics.
+pub fn adapters() -> Vec<String> {
+    let instance: wgpu::Instance = wgpu::Instance::new(wgpu::InstanceDescriptor::new_without_display_handle_from_env());
+    let adapters: Vec<wgpu::Adapter> = pollster::block_on(instance.enumerate_adapters(wgpu::Backends::all()));
+
+    return adapters.iter().map(|adapter| {
+        let info: wgpu::AdapterInfo = adapter.get_info();
+        format!("{} ({:?}, {:?})", info.name, info.backend, info.device_type)
+    }).collect();
+}
+
+// A device with both kernels compiled, reusable across calls.
+pub struct GpuContext {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    dx_pipeline: wgpu::ComputePipeline,
+    dy_pipeline: wgpu::ComputePipeline,
+    adapter_name: String,
+}
+
+impl GpuContext {
+    // Opens the default (preferably high-performance) adapter.
+    pub fn new() -> Result<GpuContext, GpuError> {
+        let instance: wgpu::Instance = wgpu::Instance::new(wgpu::InstanceDescriptor::new_without_display_handle_from_env());
+        let options: wgpu::RequestAdapterOptions = wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        };
+        let adapter: wgpu::Adapter = pollster::block_on(instance.request_adapter(&options)).map_err(|_| GpuError::NoAdapter)?;
+
+        // Ask for the adapter's own limits so large matrices are not cut off
+        // at the conservative defaults.
+        let descriptor: wgpu::DeviceDescriptor = wgpu::DeviceDescriptor {
+            label: Some("rmm"),
+            required_limits: adapter.limits(),
+            ..Default::default()
+        };
+        let (device, queue) = pollster::block_on(adapter.request_device(&descriptor))
+            .map_err(|err| GpuError::Device(err.to_string()))?;
+
+        let module: wgpu::ShaderModule = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("rmm kernels"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let pipeline = |entry_point: &str| device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(entry_point),
+            layout: None,
+            module: &module,
+            entry_point: Some(entry_point),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        let dx_pipeline: wgpu::ComputePipeline = pipeline("dx");
+        let dy_pipeline: wgpu::ComputePipeline = pipeline("dy");
+
+        return Ok(GpuContext { device, queue, dx_pipeline, dy_pipeline, adapter_name: adapter.get_info().name });
+    }
+
+    pub fn adapter_name(&self) -> &str {
+        return &self.adapter_name;
+    }
+
+    // compute_dx on the GPU. The rows x (cols + 2) result equals compute_dx
+    // widened to i32.
+    pub fn compute_dx(&self, arr: &[u8], rows: usize, cols: usize) -> Result<(Vec<i32>, GpuTimings), GpuError> {
+        return self.run(&self.dx_pipeline, arr, rows, cols, rows, cols + 2);
+    }
+
+    // compute_dy on the GPU. The (rows + 2) x cols result equals compute_dy
+    // widened to i32.
+    pub fn compute_dy(&self, arr: &[u8], rows: usize, cols: usize) -> Result<(Vec<i32>, GpuTimings), GpuError> {
+        return self.run(&self.dy_pipeline, arr, rows, cols, rows + 2, cols);
+    }
+
+    fn run(&self, pipeline: &wgpu::ComputePipeline, arr: &[u8], rows: usize, cols: usize, out_rows: usize,
+           out_cols: usize) -> Result<(Vec<i32>, GpuTimings), GpuError> {
+        check_len(arr.len(), rows, cols)?;
+
+        let out_len: usize = out_rows * out_cols;
+        let out_bytes: u64 = (out_len * 4) as u64;
+        let limits: wgpu::Limits = self.device.limits();
+        let limit: u64 = limits.max_storage_buffer_binding_size.min(limits.max_buffer_size);
+
+        if out_bytes > limit || arr.len() as u64 > limit || out_len > u32::MAX as usize {
+            return Err(GpuError::TooLarge { needed: out_bytes, limit });
+        }
+
+        if out_len == 0 {
+            return Ok((Vec::new(), GpuTimings::default()));
+        }
+
+        let groups: u32 = (out_len as u32).div_ceil(WORKGROUP_SIZE);
+        let groups_x: u32 = groups.min(MAX_GROUPS_PER_DIM);
+        let groups_y: u32 = groups.div_ceil(groups_x);
+        let dims: [u32; 8] = [rows as u32, cols as u32, out_rows as u32, out_cols as u32, groups_x * WORKGROUP_SIZE, 0, 0, 0];
+
+        // Upload: pack the input into words, padding the last one.
+        let upload_start = Instant::now();
+        let mut packed: Vec<u8> = arr.to_vec();
+        packed.resize(arr.len().div_ceil(4).max(1) * 4, 0);
+
+        let input: wgpu::Buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("input"),
+            contents: &packed,
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let uniforms: wgpu::Buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("dims"),
+            contents: &dims.iter().flat_map(|value| value.to_le_bytes()).collect::<Vec<u8>>(),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let output: wgpu::Buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("output"),
+            size: out_bytes,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback: wgpu::Buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size: out_bytes,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group: wgpu::BindGroup = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: input.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: output.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: uniforms.as_entire_binding() },
+            ],
+        });
+        self.queue.submit([]);
+        self.wait()?;
+        let upload: Duration = upload_start.elapsed();
+
+        // Compute.
+        let compute_start = Instant::now();
+        let mut encoder: wgpu::CommandEncoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut pass: wgpu::ComputePass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(groups_x, groups_y, 1);
+        }
+        self.queue.submit([encoder.finish()]);
+        self.wait()?;
+        let compute: Duration = compute_start.elapsed();
+
+        // Download.
+        let download_start = Instant::now();
+        let mut encoder: wgpu::CommandEncoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_buffer_to_buffer(&output, 0, &readback, 0, out_bytes);
+        self.queue.submit([encoder.finish()]);
+
+        let (sender, receiver) = mpsc::channel();
+        readback.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.wait()?;
+        receiver.recv().map_err(|err| GpuError::Device(err.to_string()))?.map_err(|err| GpuError::Device(err.to_string()))?;
+
+        let result: Vec<i32> = readback.slice(..).get_mapped_range().map_err(|err| GpuError::Device(err.to_string()))?
+            .chunks_exact(4).map(|bytes| i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])).collect();
+        readback.unmap();
+        let download: Duration = download_start.elapsed();
+
+        return Ok((result, GpuTimings { upload, compute, download }));
+    }
+
+    fn wait(&self) -> Result<(), GpuError> {
+        return self.device.poll(wgpu::PollType::wait_indefinitely()).map(|_| ()).map_err(|err| GpuError::Device(err.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernels;
+    use crate::matrix::construct_randomized_matrix_seeded;
+
+    // The context, or None (reported, so a skipped run is visible) when the
+    // machine has no usable adapter.
+    fn context() -> Option<GpuContext> {
+        return match GpuContext::new() {
+            Ok(context) => Some(context),
+            Err(err) => {
+                eprintln!("skipping the GPU test: {}", err);
+                None
+            }
+        };
+    }
+
+    #[test]
+    fn gpu_kernels_match_the_cpu() {
+        let Some(context) = context() else { return };
+
+        // 4100 x 4102 outputs need more than MAX_GROUPS_PER_DIM workgroups,
+        // so the last case folds the dispatch into a second dimension.
+        for (seed, (rows, cols)) in [(1, 1), (1, 7), (9, 1), (3, 5), (64, 255), (257, 129), (4100, 4100)].into_iter().enumerate() {
+            let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, seed as u64);
+            let widen = |data: Vec<i16>| -> Vec<i32> { data.into_iter().map(i32::from).collect() };
+
+            assert!(context.compute_dx(&arr, rows, cols).unwrap().0 == widen(kernels::compute_dx(&arr, rows, cols).data), "Dx {}x{}", rows, cols);
+            assert!(context.compute_dy(&arr, rows, cols).unwrap().0 == widen(kernels::compute_dy(&arr, rows, cols).data), "Dy {}x{}", rows, cols);
+        }
+    }
+
+    #[test]
+    fn gpu_kernels_check_their_input() {
+        let Some(context) = context() else { return };
+
+        assert_eq!(context.compute_dx(&[1, 2, 3], 2, 2).unwrap_err(), GpuError::Dim(DimError::LengthMismatch { expected: 4, found: 3 }));
+        assert_eq!(context.compute_dy(&[], 0, 4).unwrap().0, vec![0; 8]);
+        assert_eq!(context.compute_dx(&[], 0, 4).unwrap().0, Vec::<i32>::new());
+    }
+}
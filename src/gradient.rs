@@ -0,0 +1,384 @@
+use crate::error::DimError;
+use crate::index::flat;
+use crate::integral::box_mean;
+
+// Dx is rows x (cols + 2) and Dy is (rows + 2) x cols, so combining them
+// element-wise needs both cropped to the rows x cols region centered on the
+// input. Element (r, c) of that region is dx[r][c + 1] and dy[r + 1][c].
+// Inputs that were already cropped (e.g. with crop_dx_padding) are accepted
+// as-is. Returns the column count and column offset to read Dx with and the
+// row offset to read Dy with.
+fn aligned_layout(dx: &[i16], dy: &[i16], rows: usize, cols: usize) -> Result<(usize, usize, usize), DimError> {
+    let dx_layout: (usize, usize) = if dx.len() == rows * (cols + 2) {
+        (cols + 2, 1)
+    } else if dx.len() == rows * cols {
+        (cols, 0)
+    } else {
+        return Err(DimError::LengthMismatch { expected: rows * (cols + 2), found: dx.len() });
+    };
+
+    let dy_offset: usize = if dy.len() == (rows + 2) * cols {
+        1
+    } else if dy.len() == rows * cols {
+        0
+    } else {
+        return Err(DimError::LengthMismatch { expected: (rows + 2) * cols, found: dy.len() });
+    };
+
+    return Ok((dx_layout.0, dx_layout.1, dy_offset));
+}
+
+// Inverts compute_dx: reconstructs the rows x cols matrix whose Dx is dx,
+// given its first column. dx may be the full rows x (cols + 2) Dx or one
+// cropped to rows x cols (see crop_dx_padding).
+//
+// Aligned column c of Dx is input column c - 1 minus input column c + 1, so
+// the columns split into two independent chains, even and odd, each a
+// running difference: column c + 1 is column c - 1 minus Dx column c. The odd
+// chain starts from the zero padding left of the matrix, the even one from
+// first_col, which is why it is needed. The result is exact for any Dx
+// computed from a u8 matrix; i32 leaves room for inputs that were not.
+pub fn integrate_dx(dx: &[i16], rows: usize, cols: usize, first_col: &[u8]) -> Result<Vec<i32>, DimError> {
+    let (dx_cols, offset): (usize, usize) = match dx.len() {
+        len if len == rows * (cols + 2) => (cols + 2, 1),
+        len if len == rows * cols => (cols, 0),
+        len => return Err(DimError::LengthMismatch { expected: rows * (cols + 2), found: len }),
+    };
+
+    if first_col.len() != rows {
+        return Err(DimError::Mismatch { what: "first column length", expected: rows, found: first_col.len() });
+    }
+
+    let mut out: Vec<i32> = vec![0; rows * cols];
+
+    for (row, out_row) in out.chunks_exact_mut(cols.max(1)).take(rows).enumerate() {
+        let gradient: &[i16] = &dx[row * dx_cols + offset..row * dx_cols + offset + cols];
+        out_row[0] = first_col[row] as i32;
+
+        for col in 0..cols.saturating_sub(1) {
+            let before: i32 = if col > 0 { out_row[col - 1] } else { 0 };
+            out_row[col + 1] = before - gradient[col] as i32;
+        }
+    }
+
+    return Ok(out);
+}
+
+// Inverts compute_dy given the first row of the matrix, see integrate_dx. dy
+// may be the full (rows + 2) x cols Dy or one cropped to rows x cols.
+pub fn integrate_dy(dy: &[i16], rows: usize, cols: usize, first_row: &[u8]) -> Result<Vec<i32>, DimError> {
+    let offset: usize = match dy.len() {
+        len if len == (rows + 2) * cols => 1,
+        len if len == rows * cols => 0,
+        len => return Err(DimError::LengthMismatch { expected: (rows + 2) * cols, found: len }),
+    };
+
+    if first_row.len() != cols {
+        return Err(DimError::Mismatch { what: "first row length", expected: cols, found: first_row.len() });
+    }
+
+    let mut out: Vec<i32> = vec![0; rows * cols];
+
+    if rows > 0 {
+        for (value, &x) in out.iter_mut().zip(first_row) {
+            *value = x as i32;
+        }
+    }
+
+    for row in 0..rows.saturating_sub(1) {
+        for col in 0..cols {
+            let before: i32 = if row > 0 { out[flat(row - 1, col, cols)] } else { 0 };
+            out[flat(row + 1, col, cols)] = before - dy[flat(row + offset, col, cols)] as i32;
+        }
+    }
+
+    return Ok(out);
+}
+
+// Applies f to every aligned (dx, dy) pair of the common rows x cols region.
+fn map_aligned<T>(dx: &[i16], dy: &[i16], rows: usize, cols: usize, f: impl Fn(i16, i16) -> T) -> Result<Vec<T>, DimError> {
+    let (dx_cols, dx_col_offset, dy_row_offset) = aligned_layout(dx, dy, rows, cols)?;
+    let mut out: Vec<T> = Vec::with_capacity(rows * cols);
+
+    for row in 0..rows {
+        for col in 0..cols {
+            let gx: i16 = dx[row * dx_cols + col + dx_col_offset];
+            let gy: i16 = dy[flat(row + dy_row_offset, col, cols)];
+            out.push(f(gx, gy));
+        }
+    }
+
+    return Ok(out);
+}
+
+// map_aligned writing into out, which must hold rows * cols elements.
+fn map_aligned_into<T>(dx: &[i16], dy: &[i16], rows: usize, cols: usize, out: &mut [T], f: impl Fn(i16, i16) -> T) -> Result<(), DimError> {
+    let (dx_cols, dx_col_offset, dy_row_offset) = aligned_layout(dx, dy, rows, cols)?;
+
+    if out.len() != rows * cols {
+        return Err(DimError::Mismatch { what: "output length", expected: rows * cols, found: out.len() });
+    }
+
+    for (row, out_row) in out.chunks_exact_mut(cols.max(1)).take(rows).enumerate() {
+        for (col, value) in out_row.iter_mut().enumerate() {
+            *value = f(dx[flat(row, col + dx_col_offset, dx_cols)], dy[flat(row + dy_row_offset, col, cols)]);
+        }
+    }
+
+    return Ok(());
+}
+
+// Euclidean gradient magnitude sqrt(dx^2 + dy^2) over the common region.
+pub fn magnitude(dx: &[i16], dy: &[i16], rows: usize, cols: usize) -> Result<Vec<f32>, DimError> {
+    return map_aligned(dx, dy, rows, cols, |gx, gy| (gx as f32).hypot(gy as f32));
+}
+
+// magnitude writing into out, which must hold rows * cols elements, so a
+// caller running it every frame can reuse one buffer.
+pub fn magnitude_into(dx: &[i16], dy: &[i16], rows: usize, cols: usize, out: &mut [f32]) -> Result<(), DimError> {
+    return map_aligned_into(dx, dy, rows, cols, out, |gx, gy| (gx as f32).hypot(gy as f32));
+}
+
+// L1 gradient magnitude |dx| + |dy| over the common region. With the
+// [-1, 0, 1] kernel each term is at most 255, so the maximum is 510; larger
+// kernels and --arith saturate/wrap can reach i16::MIN in both, whose sum
+// saturates at u16::MAX rather than wrapping.
+pub fn abs_gradient(dx: &[i16], dy: &[i16], rows: usize, cols: usize) -> Result<Vec<u16>, DimError> {
+    return map_aligned(dx, dy, rows, cols, |gx, gy| gx.unsigned_abs().saturating_add(gy.unsigned_abs()));
+}
+
+// Histogram of gradient orientations per cell, as used by HOG descriptors.
+//
+// The common rows x cols region is divided into cell_size x cell_size cells,
+// in row-major order; cells along the bottom and right edges are smaller if
+// cell_size does not divide the dimensions. Each element votes with its
+// Euclidean magnitude into one of bins equal-width orientation bins covering
+// unsigned gradients, 0 to 180 degrees, so a gradient and its negation land
+// in the same bin. Bin b covers [b, b + 1) * 180 / bins degrees.
+pub fn orientation_histogram(dx: &[i16], dy: &[i16], rows: usize, cols: usize, cell_size: usize,
+                             bins: usize) -> Result<Vec<Vec<f32>>, DimError> {
+    if cell_size == 0 {
+        return Err(DimError::Mismatch { what: "cell size", expected: 1, found: 0 });
+    }
+
+    if bins == 0 {
+        return Err(DimError::Mismatch { what: "bin count", expected: 1, found: 0 });
+    }
+
+    let votes: Vec<(f32, usize)> = map_aligned(dx, dy, rows, cols, |gx, gy| {
+        let degrees: f32 = (gy as f32).atan2(gx as f32).to_degrees().rem_euclid(180.0);
+        let bin: usize = ((degrees * bins as f32 / 180.0) as usize).min(bins - 1);
+        ((gx as f32).hypot(gy as f32), bin)
+    })?;
+
+    let cell_cols: usize = cols.div_ceil(cell_size);
+    let mut cells: Vec<Vec<f32>> = vec![vec![0.0; bins]; rows.div_ceil(cell_size) * cell_cols];
+
+    for row in 0..rows {
+        for col in 0..cols {
+            let (weight, bin) = votes[flat(row, col, cols)];
+            cells[(row / cell_size) * cell_cols + col / cell_size][bin] += weight;
+        }
+    }
+
+    return Ok(cells);
+}
+
+// The Ixx, Iyy and Ixy fields of a structure tensor, each rows x cols.
+pub type TensorFields = (Vec<f32>, Vec<f32>, Vec<f32>);
+
+// Smoothed structure tensor of the gradient: the products Dx * Dx, Dy * Dy
+// and Dx * Dy over the common rows x cols region, each averaged over the
+// (2 * window_radius + 1)^2 window around every element with box_mean.
+pub fn structure_tensor(dx: &[i16], dy: &[i16], rows: usize, cols: usize,
+                        window_radius: usize) -> Result<TensorFields, DimError> {
+    let products: Vec<(f32, f32, f32)> = map_aligned(dx, dy, rows, cols, |gx, gy| {
+        let (gx, gy): (f32, f32) = (gx as f32, gy as f32);
+        (gx * gx, gy * gy, gx * gy)
+    })?;
+
+    let smooth = |pick: fn(&(f32, f32, f32)) -> f32| box_mean(&products.iter().map(pick).collect::<Vec<f32>>(), rows, cols, window_radius);
+
+    return Ok((smooth(|p| p.0)?, smooth(|p| p.1)?, smooth(|p| p.2)?));
+}
+
+// Harris corner response det - k * trace^2 of the structure tensor at every
+// element. Corners, where the gradient is strong in two directions, give
+// large positive values; edges negative ones and flat regions about zero.
+// k is usually between 0.04 and 0.06.
+pub fn harris_response(ixx: &[f32], iyy: &[f32], ixy: &[f32], k: f32) -> Result<Vec<f32>, DimError> {
+    for field in [iyy, ixy] {
+        if field.len() != ixx.len() {
+            return Err(DimError::Mismatch { what: "structure tensor length", expected: ixx.len(), found: field.len() });
+        }
+    }
+
+    return Ok(ixx.iter().zip(iyy).zip(ixy).map(|((&xx, &yy), &xy)| {
+        let trace: f32 = xx + yy;
+        xx * yy - xy * xy - k * trace * trace
+    }).collect());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernels::{compute_dx, compute_dy};
+
+    #[test]
+    fn abs_gradient_saturates_instead_of_wrapping() {
+        let dx: Vec<i16> = vec![i16::MIN, i16::MIN, i16::MAX, -1];
+        let dy: Vec<i16> = vec![i16::MIN, 1, i16::MAX, 1];
+
+        assert_eq!(abs_gradient(&dx, &dy, 2, 2).unwrap(), vec![u16::MAX, 32769, 65534, 2]);
+    }
+
+    #[test]
+    fn abs_gradient_reaches_510_with_the_central_difference() {
+        let arr: Vec<u8> = vec![0, 0, 255, 0, 0, 255, 255, 255, 255];
+        let (dx, dy) = (compute_dx(&arr, 3, 3), compute_dy(&arr, 3, 3));
+        let magnitude: Vec<u16> = abs_gradient(&dx.data, &dy.data, 3, 3).unwrap();
+
+        assert_eq!(magnitude[4], 510);
+        assert_eq!(magnitude.iter().max(), Some(&510));
+    }
+
+    #[test]
+    fn abs_gradient_aligns_padded_and_cropped_layouts() {
+        let arr: Vec<u8> = (0..16).map(|value| (value * value) as u8).collect();
+        let (dx, dy) = (compute_dx(&arr, 4, 4), compute_dy(&arr, 4, 4));
+        let padded: Vec<u16> = abs_gradient(&dx.data, &dy.data, 4, 4).unwrap();
+
+        // Element (row, col) of the input pairs Dx (row, col + 1) with Dy (row + 1, col).
+        let expected: Vec<u16> = (0..16).map(|i| {
+            let (row, col) = (i / 4, i % 4);
+            dx.data[row * 6 + col + 1].unsigned_abs() + dy.data[(row + 1) * 4 + col].unsigned_abs()
+        }).collect();
+        assert_eq!(padded, expected);
+
+        let dx_cropped: Vec<i16> = (0..16).map(|i| dx.data[(i / 4) * 6 + i % 4 + 1]).collect();
+        let dy_cropped: Vec<i16> = dy.data[4..20].to_vec();
+        assert_eq!(abs_gradient(&dx_cropped, &dy_cropped, 4, 4).unwrap(), expected);
+    }
+
+    #[test]
+    fn abs_gradient_rejects_unexpected_lengths() {
+        assert!(abs_gradient(&[0; 5], &[0; 4], 2, 2).is_err());
+        assert!(abs_gradient(&[0; 4], &[0; 7], 2, 2).is_err());
+    }
+
+    // Random inputs of the edge sizes, including empty ones and single rows
+    // and columns.
+    fn inputs() -> Vec<(Vec<u8>, usize, usize)> {
+        return [(0, 0), (0, 3), (3, 0), (1, 1), (1, 7), (7, 1), (2, 2), (31, 17)].iter()
+            .map(|&(rows, cols)| (crate::matrix::construct_randomized_matrix_seeded(rows, cols, (rows * 64 + cols) as u64), rows, cols))
+            .collect();
+    }
+
+    #[test]
+    fn integrate_dx_inverts_compute_dx() {
+        for (arr, rows, cols) in inputs() {
+            let original: Vec<i32> = arr.iter().map(|&value| value as i32).collect();
+            let first_col: Vec<u8> = (0..rows).map(|row| arr.get(flat(row, 0, cols)).copied().unwrap_or(0)).collect();
+            let dx: Vec<i16> = compute_dx(&arr, rows, cols).data;
+            let cropped: Vec<i16> = crate::ops::crop_dx_padding(&dx, rows, cols).unwrap();
+
+            assert_eq!(integrate_dx(&dx, rows, cols, &first_col).unwrap(), original, "{}x{}", rows, cols);
+            assert_eq!(integrate_dx(&cropped, rows, cols, &first_col).unwrap(), original, "cropped {}x{}", rows, cols);
+        }
+
+        assert!(integrate_dx(&[0; 7], 2, 2, &[0, 0]).is_err());
+        assert!(integrate_dx(&[0; 8], 2, 2, &[0]).is_err());
+    }
+
+    #[test]
+    fn integrate_dy_inverts_compute_dy() {
+        for (arr, rows, cols) in inputs() {
+            let original: Vec<i32> = arr.iter().map(|&value| value as i32).collect();
+            let first_row: Vec<u8> = if rows == 0 { vec![0; cols] } else { arr[..cols].to_vec() };
+            let dy: Vec<i16> = compute_dy(&arr, rows, cols).data;
+            let cropped: Vec<i16> = crate::ops::crop_dy_padding(&dy, rows, cols).unwrap();
+
+            assert_eq!(integrate_dy(&dy, rows, cols, &first_row).unwrap(), original, "{}x{}", rows, cols);
+            assert_eq!(integrate_dy(&cropped, rows, cols, &first_row).unwrap(), original, "cropped {}x{}", rows, cols);
+        }
+
+        assert!(integrate_dy(&[0; 7], 2, 2, &[0, 0]).is_err());
+        assert!(integrate_dy(&[0; 8], 2, 2, &[0]).is_err());
+    }
+
+    #[test]
+    fn pure_horizontal_and_vertical_gradients_fill_one_bin() {
+        // Cropped 5x6 fields, cells of 2: 3 x 3 cells, the last row of cells
+        // one element high.
+        let (rows, cols): (usize, usize) = (5, 6);
+        let alternating: Vec<i16> = (0..rows * cols).map(|index| if index % 3 == 0 { -3 } else { 3 }).collect();
+        let zeros: Vec<i16> = vec![0; rows * cols];
+        let cell_weights: [f32; 9] = [12.0, 12.0, 12.0, 12.0, 12.0, 12.0, 6.0, 6.0, 6.0];
+
+        // Horizontal gradients, of either sign, are 0 degrees.
+        let horizontal: Vec<Vec<f32>> = orientation_histogram(&alternating, &zeros, rows, cols, 2, 9).unwrap();
+        // Vertical ones are 90 degrees, bin 4 of 9 ([80, 100)).
+        let vertical: Vec<Vec<f32>> = orientation_histogram(&zeros, &alternating, rows, cols, 2, 9).unwrap();
+
+        assert_eq!(horizontal.len(), 9);
+
+        for (cell, weight) in cell_weights.iter().enumerate() {
+            let mut expected: Vec<f32> = vec![0.0; 9];
+
+            expected[0] = *weight;
+            assert_eq!(horizontal[cell], expected, "cell {}", cell);
+
+            expected.swap(0, 4);
+            assert_eq!(vertical[cell], expected, "cell {}", cell);
+        }
+
+        // With 4 bins vertical gradients start bin 2, and 45 degrees, dx = dy,
+        // starts bin 1 and votes with the Euclidean magnitude.
+        assert_eq!(orientation_histogram(&zeros, &alternating, rows, cols, 6, 4).unwrap(), vec![vec![0.0, 0.0, 90.0, 0.0]]);
+        assert_eq!(orientation_histogram(&[4; 4], &[4; 4], 2, 2, 2, 4).unwrap(), vec![vec![0.0, 4.0 * 32f32.sqrt(), 0.0, 0.0]]);
+    }
+
+    #[test]
+    fn a_horizontal_ramp_votes_horizontally_away_from_the_border() {
+        // Row r of the ramp is r-independent, so off the top and bottom rows,
+        // which see the zero padding, Dy is 0 and every vote is horizontal.
+        let (rows, cols): (usize, usize) = (6, 8);
+        let arr: Vec<u8> = (0..rows * cols).map(|index| (index % cols * 20 + 5) as u8).collect();
+        let (dx, dy): (Vec<i16>, Vec<i16>) = (compute_dx(&arr, rows, cols).data, compute_dy(&arr, rows, cols).data);
+        let cells: Vec<Vec<f32>> = orientation_histogram(&dx, &dy, rows, cols, 1, 6).unwrap();
+
+        for row in 1..rows - 1 {
+            for col in 0..cols {
+                let cell: &[f32] = &cells[flat(row, col, cols)];
+
+                assert!(cell[0] > 0.0 && cell[1..].iter().all(|&vote| vote == 0.0), "({}, {}): {:?}", row, col, cell);
+            }
+        }
+
+        assert!(orientation_histogram(&dx, &dy, rows, cols, 0, 6).is_err());
+        assert!(orientation_histogram(&dx, &dy, rows, cols, 2, 0).is_err());
+    }
+
+    #[test]
+    fn harris_separates_corners_edges_and_flat_regions() {
+        // A bright square filling the bottom right of a 24x24 image: its
+        // corner is at (12, 12) and its left edge runs down column 12.
+        let size: usize = 24;
+        let arr: Vec<u8> = (0..size * size).map(|index| if index / size >= 12 && index % size >= 12 { 200 } else { 0 }).collect();
+        let (dx, dy): (Vec<i16>, Vec<i16>) = (compute_dx(&arr, size, size).data, compute_dy(&arr, size, size).data);
+        let (ixx, iyy, ixy): TensorFields = structure_tensor(&dx, &dy, size, size, 2).unwrap();
+        let response: Vec<f32> = harris_response(&ixx, &iyy, &ixy, 0.05).unwrap();
+        let at = |row: usize, col: usize| response[flat(row, col, size)];
+
+        assert!(at(12, 12) > 0.0, "corner {}", at(12, 12));
+        // Along the edges only one gradient direction is present.
+        assert!(at(17, 12) <= 0.0, "vertical edge {}", at(17, 12));
+        assert!(at(12, 17) <= 0.0, "horizontal edge {}", at(12, 17));
+        assert_eq!(at(5, 5), 0.0);
+        // The corner is the strongest response away from the image border.
+        let interior_max: f32 = (3..size - 3).flat_map(|row| (3..size - 3).map(move |col| (row, col))).map(|(row, col)| at(row, col)).fold(f32::MIN, f32::max);
+        assert!((11..=13).any(|row| (11..=13).any(|col| at(row, col) == interior_max)), "max {}", interior_max);
+
+        assert!(harris_response(&ixx, &iyy[1..], &ixy, 0.05).is_err());
+    }
+}
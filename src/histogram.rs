@@ -0,0 +1,192 @@
+use std::fs;
+use std::path::Path;
+use crate::error::DimError;
+use crate::io::invalid_data;
+
+// Range of the histograms --save-hist writes: that of Dx and Dy of a u8
+// input. Values outside it, from other kernels, are counted in the end bins.
+pub const HIST_MIN: i32 = -255;
+pub const HIST_MAX: i32 = 255;
+
+// Bins --save-hist uses unless --hist-bins is given: one per value.
+pub const DEFAULT_HIST_BINS: usize = 511;
+
+// First line of a .hist file.
+const HIST_MAGIC: &str = "rmm-hist 1";
+
+// Counts of values in min..=max, split into counts.len() bins of equal width
+// (the last ones one value narrower when the range does not divide evenly).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Histogram {
+    pub min: i32,
+    pub max: i32,
+    pub counts: Vec<u64>,
+}
+
+// Histogram of data over min..=max in bins bins; values outside the range
+// count in the first or last bin. bins must be at least 1 and min at most max.
+pub fn value_histogram(data: &[i16], min: i32, max: i32, bins: usize) -> Histogram {
+    assert!(bins > 0 && min <= max, "a histogram needs at least one bin and min <= max");
+
+    let width: i64 = (max - min) as i64 + 1;
+    let mut counts: Vec<u64> = vec![0; bins];
+
+    for &value in data {
+        let offset: i64 = (value as i32).clamp(min, max) as i64 - min as i64;
+        counts[(offset * bins as i64 / width) as usize] += 1;
+    }
+
+    return Histogram { min, max, counts };
+}
+
+// Distances between two histograms, compared as distributions so that the
+// total counts may differ. Both are 0 for identical shapes and at most 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HistDiff {
+    // Half the sum over bins of (p - q)^2 / (p + q), p and q the fractions
+    // of each histogram in the bin: sensitive to any change of shape.
+    pub chi_squared: f64,
+    // Earth mover's distance, the sum of |P - Q| over the cumulative
+    // fractions, divided by bins - 1 so that moving everything from the
+    // first bin to the last is 1: how far the distribution shifted.
+    pub earth_movers: f64,
+}
+
+// Compares histograms with the same number of bins. An empty histogram is
+// at distance 0 from another empty one and 1 from anything else.
+pub fn compare_histograms(a: &[u64], b: &[u64]) -> Result<HistDiff, DimError> {
+    if a.len() != b.len() {
+        return Err(DimError::Mismatch { what: "histogram bins", expected: a.len(), found: b.len() });
+    }
+
+    let (a_total, b_total): (u64, u64) = (a.iter().sum(), b.iter().sum());
+
+    if a_total == 0 || b_total == 0 {
+        let distance: f64 = if a_total == b_total { 0.0 } else { 1.0 };
+        return Ok(HistDiff { chi_squared: distance, earth_movers: distance });
+    }
+
+    let mut chi_squared: f64 = 0.0;
+    let mut earth_movers: f64 = 0.0;
+    let (mut a_cumulative, mut b_cumulative): (f64, f64) = (0.0, 0.0);
+
+    for (&a_count, &b_count) in a.iter().zip(b) {
+        let (p, q): (f64, f64) = (a_count as f64 / a_total as f64, b_count as f64 / b_total as f64);
+
+        if p + q > 0.0 {
+            chi_squared += (p - q) * (p - q) / (p + q);
+        }
+
+        a_cumulative += p;
+        b_cumulative += q;
+        earth_movers += (a_cumulative - b_cumulative).abs();
+    }
+
+    return Ok(HistDiff { chi_squared: chi_squared / 2.0, earth_movers: earth_movers / (a.len() - 1).max(1) as f64 });
+}
+
+// Writes a histogram as text: HIST_MAGIC, then "min M", "max M" and
+// "bins N" lines, then one count per line from the lowest bin up.
+pub fn write_hist(path: &Path, histogram: &Histogram) -> std::io::Result<()> {
+    let mut text: String = format!("{}\nmin {}\nmax {}\nbins {}\n", HIST_MAGIC, histogram.min, histogram.max, histogram.counts.len());
+
+    for count in &histogram.counts {
+        text.push_str(&format!("{}\n", count));
+    }
+
+    return fs::write(path, text);
+}
+
+// Reads a histogram written by write_hist.
+pub fn read_hist(path: &Path) -> std::io::Result<Histogram> {
+    let text: String = fs::read_to_string(path)?;
+    let mut lines = text.lines().map(str::trim);
+
+    if lines.next() != Some(HIST_MAGIC) {
+        return Err(invalid_data(&format!("not a histogram file (expected \"{}\" first)", HIST_MAGIC)));
+    }
+
+    let mut field = |name: &str| -> std::io::Result<i64> {
+        return lines.next().and_then(|line| line.strip_prefix(name)).and_then(|value| value.trim().parse().ok())
+            .ok_or_else(|| invalid_data(&format!("missing or invalid \"{}\" line", name)));
+    };
+
+    let (min, max, bins): (i64, i64, i64) = (field("min")?, field("max")?, field("bins")?);
+    let counts: Vec<u64> = lines.filter(|line| !line.is_empty())
+        .map(|line| line.parse().map_err(|_| invalid_data(&format!("invalid count \"{}\"", line))))
+        .collect::<std::io::Result<Vec<u64>>>()?;
+
+    if counts.len() as i64 != bins {
+        return Err(invalid_data(&format!("{} bins declared but {} counts found", bins, counts.len())));
+    }
+
+    let range = |value: i64| i32::try_from(value).map_err(|_| invalid_data(&format!("range bound {} is out of range", value)));
+
+    return Ok(Histogram { min: range(min)?, max: range(max)?, counts });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The histogram of a ramp of values centered on center.
+    fn ramp(center: i16, bins: usize) -> Histogram {
+        let data: Vec<i16> = (0..2000).map(|index| center + (index % 101) as i16 - 50).collect();
+
+        return value_histogram(&data, HIST_MIN, HIST_MAX, bins);
+    }
+
+    #[test]
+    fn values_are_binned_and_clamped() {
+        let histogram: Histogram = value_histogram(&[-300, -255, -1, 0, 0, 254, 255, 1000], -255, 255, 511);
+
+        assert_eq!(histogram.counts.iter().sum::<u64>(), 8);
+        assert_eq!((histogram.counts[0], histogram.counts[254], histogram.counts[255], histogram.counts[510]), (2, 1, 2, 2));
+        assert_eq!(value_histogram(&[-255, 0, 255], -255, 255, 2).counts, vec![2, 1]);
+    }
+
+    #[test]
+    fn identical_histograms_do_not_drift() {
+        let histogram: Histogram = ramp(0, DEFAULT_HIST_BINS);
+        let doubled: Vec<u64> = histogram.counts.iter().map(|count| 2 * count).collect();
+
+        assert_eq!(compare_histograms(&histogram.counts, &histogram.counts).unwrap(), HistDiff { chi_squared: 0.0, earth_movers: 0.0 });
+        // Only the shape counts, not the number of samples.
+        assert_eq!(compare_histograms(&histogram.counts, &doubled).unwrap(), HistDiff { chi_squared: 0.0, earth_movers: 0.0 });
+        assert_eq!(compare_histograms(&[0, 0], &[0, 0]).unwrap(), HistDiff { chi_squared: 0.0, earth_movers: 0.0 });
+    }
+
+    #[test]
+    fn a_shifted_distribution_drifts() {
+        let diff: HistDiff = compare_histograms(&ramp(0, DEFAULT_HIST_BINS).counts, &ramp(20, DEFAULT_HIST_BINS).counts).unwrap();
+
+        // 20 of 510 bin widths is about 0.039 of earth mover's distance.
+        assert!(diff.earth_movers > 0.01 && (diff.earth_movers - 20.0 / 510.0).abs() < 1e-9, "{:?}", diff);
+        assert!(diff.chi_squared > 0.01 && diff.chi_squared <= 1.0, "{:?}", diff);
+
+        // Disjoint distributions are as far apart as chi-squared goes.
+        assert_eq!(compare_histograms(&[5, 0], &[0, 3]).unwrap(), HistDiff { chi_squared: 1.0, earth_movers: 1.0 });
+        assert_eq!(compare_histograms(&[5, 0], &[0, 0]).unwrap(), HistDiff { chi_squared: 1.0, earth_movers: 1.0 });
+    }
+
+    #[test]
+    fn mismatched_bins_are_an_error() {
+        assert_eq!(compare_histograms(&ramp(0, 511).counts, &ramp(0, 64).counts), Err(DimError::Mismatch { what: "histogram bins", expected: 511, found: 64 }));
+    }
+
+    #[test]
+    fn hist_files_round_trip() {
+        let path: std::path::PathBuf = std::env::temp_dir().join(format!("rmm-histogram-{}-round-trip.hist", std::process::id()));
+        let histogram: Histogram = ramp(-7, 32);
+
+        write_hist(&path, &histogram).unwrap();
+        assert_eq!(read_hist(&path).unwrap(), histogram);
+
+        fs::write(&path, "rmm-hist 1\nmin -255\nmax 255\nbins 3\n1\n2\n").unwrap();
+        assert!(read_hist(&path).unwrap_err().to_string().contains("3 bins declared but 2 counts found"));
+        fs::write(&path, "not a histogram\n").unwrap();
+        assert!(read_hist(&path).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,300 @@
+use std::ops::Range;
+
+// Index arithmetic shared by the kernels, the convolutions and the writers:
+// where an element of a flat row-major matrix lives, the shape a padded
+// convolution gives, and which of its outputs reach into the padding.
+
+// The dimension a 1D kernel runs along: Cols for a horizontal kernel such as
+// that of compute_dx, which pads the columns, Rows for a vertical one such as
+// that of compute_dy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Axis {
+    Rows,
+    Cols,
+}
+
+// Elements of padding a convolution's output has along its axis, beyond the
+// input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Padding {
+    // kernel_len - 1, the full convolution, as compute_dx and compute_dy.
+    Full,
+    // None, so the output is shaped as the input ("same").
+    Same,
+    // This many elements, as convolve_rows_padded takes.
+    Explicit(usize),
+}
+
+impl Padding {
+    // The elements of padding for a kernel of kernel_len taps.
+    pub fn pad(self, kernel_len: usize) -> usize {
+        return match self {
+            Padding::Full => kernel_len.saturating_sub(1),
+            Padding::Same => 0,
+            Padding::Explicit(pad) => pad,
+        };
+    }
+}
+
+// Position of element (row, col) in a row-major matrix cols wide (or with
+// rows starting cols elements apart).
+#[inline(always)]
+pub fn flat(row: usize, col: usize, cols: usize) -> usize {
+    return row * cols + col;
+}
+
+// The positions of row row of a row-major matrix cols wide, flat(row, 0,
+// cols)..flat(row + 1, 0, cols), for slicing one row out.
+#[inline(always)]
+pub fn row_range(row: usize, cols: usize) -> Range<usize> {
+    return flat(row, 0, cols)..flat(row + 1, 0, cols);
+}
+
+// The positions of rows start..end, see row_range.
+#[inline(always)]
+pub fn rows_range(start: usize, end: usize, cols: usize) -> Range<usize> {
+    return flat(start, 0, cols)..flat(end, 0, cols);
+}
+
+// (rows, cols) of the output of a kernel of kernel_len taps run along axis of
+// a rows x cols matrix with the given padding.
+pub fn padded_dims(rows: usize, cols: usize, kernel_len: usize, axis: Axis, padding: Padding) -> (usize, usize) {
+    let pad: usize = padding.pad(kernel_len);
+
+    return match axis {
+        Axis::Rows => (rows + pad, cols),
+        Axis::Cols => (rows, cols + pad),
+    };
+}
+
+// How far output index j of a convolution with pad elements of padding is
+// from index j of the full one: output j is full index j + offset. Both
+// halves round down, so an even kernel_len leans towards lower indices.
+pub fn padding_offset(kernel_len: usize, pad: usize) -> isize {
+    return ((kernel_len - 1) / 2) as isize - (pad / 2) as isize;
+}
+
+// The outputs along the axis of a padded convolution, in order, split by
+// whether the kernel reaches the padding of the input for them: leading and
+// trailing do, interior does not. Together they are 0..len + pad for an input
+// len long; interior is empty when the input is shorter than the kernel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BorderRanges {
+    pub leading: Range<usize>,
+    pub interior: Range<usize>,
+    pub trailing: Range<usize>,
+}
+
+impl BorderRanges {
+    // leading followed by trailing, the outputs that need the padding.
+    pub fn border(&self) -> impl Iterator<Item = usize> {
+        return self.leading.clone().chain(self.trailing.clone());
+    }
+}
+
+// The BorderRanges of a kernel of kernel_len taps (at least 1) along an input
+// len long with the given padding. Output j reads inputs j + offset -
+// kernel_len + 1 ..= j + offset, offset being padding_offset, so it is
+// interior when those are all inside 0..len.
+pub fn border_ranges(len: usize, kernel_len: usize, padding: Padding) -> BorderRanges {
+    let pad: usize = padding.pad(kernel_len);
+    let out_len: usize = len + pad;
+    let offset: isize = padding_offset(kernel_len, pad);
+    let clamp = |j: isize| -> usize { j.clamp(0, out_len as isize) as usize };
+
+    let start: usize = clamp(kernel_len as isize - 1 - offset);
+    let end: usize = clamp(len as isize - offset).max(start);
+
+    return BorderRanges { leading: 0..start, interior: start..end, trailing: end..out_len };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every Padding of a kernel_len kernel, up to 6 elements explicitly.
+    fn paddings() -> Vec<Padding> {
+        let mut paddings: Vec<Padding> = vec![Padding::Full, Padding::Same];
+        paddings.extend((0..=6).map(Padding::Explicit));
+
+        return paddings;
+    }
+
+    // The outputs of border_ranges by definition, one at a time: output j,
+    // full index j + offset, reads inputs j + offset - kernel_len + 1 ..=
+    // j + offset. It is leading when it reads before the input, trailing when
+    // it reads past the end but not before, and interior otherwise.
+    fn naive_kinds(len: usize, kernel_len: usize, pad: usize) -> Vec<char> {
+        let offset: isize = ((kernel_len - 1) / 2) as isize - (pad / 2) as isize;
+
+        return (0..(len + pad) as isize).map(|j| {
+            let (first, last): (isize, isize) = (j + offset - kernel_len as isize + 1, j + offset);
+
+            return if first < 0 {
+                'l'
+            } else if last >= len as isize {
+                't'
+            } else {
+                assert!((first..=last).all(|input| (0..len as isize).contains(&input)));
+                'i'
+            };
+        }).collect();
+    }
+
+    #[test]
+    fn flat_counts_row_major_positions() {
+        for rows in 0..=6 {
+            for cols in 0..=6 {
+                let mut expected: usize = 0;
+
+                for row in 0..rows {
+                    for col in 0..cols {
+                        assert_eq!(flat(row, col, cols), expected, "({}, {}) of {}x{}", row, col, rows, cols);
+                        expected += 1;
+                    }
+                }
+
+                // Rows of a wider buffer start stride elements apart.
+                for stride in cols..=cols + 3 {
+                    for row in 0..rows {
+                        for col in 0..cols {
+                            assert_eq!(flat(row, col, stride), row * stride + col);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn row_ranges_cover_each_row_once() {
+        for rows in 0..=6 {
+            for cols in 0..=6 {
+                let mut positions: Vec<usize> = Vec::new();
+
+                for row in 0..rows {
+                    let range: Range<usize> = row_range(row, cols);
+                    assert_eq!(range.len(), cols);
+                    assert!(range.clone().all(|index| index / cols.max(1) == row));
+                    positions.extend(range);
+                }
+                assert_eq!(positions, (0..rows * cols).collect::<Vec<usize>>());
+
+                for start in 0..=rows {
+                    for end in start..=rows {
+                        assert_eq!(rows_range(start, end, cols).collect::<Vec<usize>>(), (start..end).flat_map(|row| row_range(row, cols)).collect::<Vec<usize>>());
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn paddings_give_their_elements() {
+        for kernel_len in 0..=6 {
+            assert_eq!(Padding::Full.pad(kernel_len), if kernel_len == 0 { 0 } else { kernel_len - 1 });
+            assert_eq!(Padding::Same.pad(kernel_len), 0);
+
+            for pad in 0..=6 {
+                assert_eq!(Padding::Explicit(pad).pad(kernel_len), pad);
+            }
+        }
+    }
+
+    #[test]
+    fn padded_dims_grow_only_the_axis() {
+        for rows in 0..=6 {
+            for cols in 0..=6 {
+                for kernel_len in 1..=6 {
+                    for padding in paddings() {
+                        let pad: usize = match padding {
+                            Padding::Full => kernel_len - 1,
+                            Padding::Same => 0,
+                            Padding::Explicit(pad) => pad,
+                        };
+
+                        assert_eq!(padded_dims(rows, cols, kernel_len, Axis::Rows, padding), (rows + pad, cols));
+                        assert_eq!(padded_dims(rows, cols, kernel_len, Axis::Cols, padding), (rows, cols + pad));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn border_ranges_match_the_definition() {
+        for len in 0..=6 {
+            for kernel_len in 1..=6 {
+                for padding in paddings() {
+                    let pad: usize = padding.pad(kernel_len);
+                    let ranges: BorderRanges = border_ranges(len, kernel_len, padding);
+                    let kinds: Vec<char> = (0..len + pad).map(|j| {
+                        return if ranges.leading.contains(&j) { 'l' } else if ranges.interior.contains(&j) { 'i' } else { 't' };
+                    }).collect();
+                    let what: String = format!("len {} kernel_len {} {:?}", len, kernel_len, padding);
+
+                    assert_eq!(kinds, naive_kinds(len, kernel_len, pad), "{}", what);
+
+                    // In order and covering the outputs exactly.
+                    assert_eq!(ranges.leading.start, 0, "{}", what);
+                    assert_eq!(ranges.leading.end, ranges.interior.start, "{}", what);
+                    assert_eq!(ranges.interior.end, ranges.trailing.start, "{}", what);
+                    assert_eq!(ranges.trailing.end, len + pad, "{}", what);
+                    assert_eq!(ranges.border().collect::<Vec<usize>>(), (0..len + pad).filter(|&j| kinds[j] != 'i').collect::<Vec<usize>>());
+
+                    if len < kernel_len {
+                        assert!(ranges.interior.is_empty(), "{}", what);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn the_full_convolution_has_every_border_the_kernel_reaches() {
+        // With the full padding only the first and last kernel_len - 1 outputs
+        // reach the padding, and same padding of an odd kernel centres it.
+        for len in 0..=6 {
+            for kernel_len in 1..=6 {
+                let full: BorderRanges = border_ranges(len, kernel_len, Padding::Full);
+
+                if len >= kernel_len {
+                    assert_eq!(full, BorderRanges { leading: 0..kernel_len - 1, interior: kernel_len - 1..len, trailing: len..len + kernel_len - 1 });
+                }
+                if kernel_len % 2 == 1 && len >= kernel_len {
+                    let half: usize = kernel_len / 2;
+                    assert_eq!(border_ranges(len, kernel_len, Padding::Same), BorderRanges { leading: 0..half, interior: half..len - half, trailing: len - half..len });
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn padded_outputs_are_the_full_ones_shifted() {
+        // Output j of a padded convolution is full index j + offset, or zero
+        // where that is outside the full output.
+        for len in 1..=6 {
+            let arr: Vec<u8> = (0..len as u8).map(|value| value * 37 + 11).collect();
+
+            for kernel_len in 1..=6 {
+                let kernel: Vec<i32> = (0..kernel_len as i32).map(|tap| tap * tap - 3 * tap + 1).collect();
+                let full: Vec<i32> = crate::conv::convolve_rows(&arr, 1, len, &kernel, crate::arith::ArithPolicy::Checked).unwrap();
+
+                for pad in 0..=6 {
+                    let padded: Vec<i32> = crate::conv::convolve_rows_padded(&arr, 1, len, &kernel, pad, crate::arith::ArithPolicy::Checked).unwrap();
+                    let offset: isize = padding_offset(kernel_len, pad);
+
+                    assert_eq!(offset, ((kernel_len - 1) / 2) as isize - (pad / 2) as isize);
+                    assert_eq!(padded.len(), padded_dims(1, len, kernel_len, Axis::Cols, Padding::Explicit(pad)).1);
+
+                    for (j, &value) in padded.iter().enumerate() {
+                        let index: isize = j as isize + offset;
+                        let expected: i32 = if (0..full.len() as isize).contains(&index) { full[index as usize] } else { 0 };
+
+                        assert_eq!(value, expected, "output {} of len {} kernel_len {} pad {}", j, len, kernel_len, pad);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use std::error::Error;
+use std::fmt;
+use std::io::ErrorKind;
+use std::path::Path;
+use rmm::io::{read_matrix, StoredMatrix};
+use rmm::mtx::{read_matrix_market, MarketMatrix};
+use crate::cli::Options;
+
+// The input of a run once the dimensions on the command line and those of
+// --input are reconciled, before --rotate-input, --resize and --equalize.
+pub(crate) struct ResolvedInput {
+    // The matrix read from --input, or None if one is generated.
+    pub arr: Option<Vec<u8>>,
+    // Shape of arr, or of the matrix to generate.
+    pub rows: usize,
+    pub cols: usize,
+}
+
+// Why the input of a run could not be resolved.
+#[derive(Debug)]
+pub(crate) enum InputError {
+    // Nothing to generate from and no file to read.
+    MissingDimensions,
+    // Rows or columns were given and neither the file nor the --resize
+    // target, if any, has them. Either is None when only the other was given.
+    DimensionMismatch { path: String, given: (Option<usize>, Option<usize>), loaded: (usize, usize), resize: Option<(usize, usize)> },
+    Read { path: String, error: std::io::Error },
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dim = |value: Option<usize>| value.map_or("?".to_string(), |value| value.to_string());
+
+        return match self {
+            InputError::MissingDimensions => write!(f, "2 arguments required: rows columns (or --size ROWSxCOLS, or --input FILE)"),
+            InputError::DimensionMismatch { path, given, loaded, resize } => {
+                write!(f, "{} is {}x{} but {}x{} was given; ", path, loaded.0, loaded.1, dim(given.0), dim(given.1))?;
+
+                match (resize, given) {
+                    (Some((rows, cols)), _) => write!(f, "with --resize the dimensions must be its target {}x{}", rows, cols),
+                    (None, (Some(rows), Some(cols))) => write!(f, "drop them to use the file's, or add --resize {}x{} to resize it", rows, cols),
+                    (None, _) => write!(f, "drop it to use the file's"),
+                }
+            }
+            InputError::Read { path, error } => write!(f, "Failed to read {}: {}", path, error),
+        };
+    }
+}
+
+impl Error for InputError {}
+
+// Reconciles the dimensions given on the command line (positional, --rows,
+// --cols or --size) with --input, for every run that reads or generates a
+// u8 input:
+//
+//   - without --input, both dimensions are required and are what is
+//     generated;
+//   - with --input alone, the dimensions are the file's;
+//   - dimensions given as well must be the file's. To run on a file at
+//     another size they must be the --resize target, which resizes it.
+pub(crate) fn resolve(options: &Options) -> Result<ResolvedInput, InputError> {
+    let Some(path) = &options.input else {
+        return match (options.rows, options.cols) {
+            (Some(rows), Some(cols)) => Ok(ResolvedInput { arr: None, rows, cols }),
+            _ => Err(InputError::MissingDimensions),
+        };
+    };
+
+    let (arr, rows, cols): (Vec<u8>, usize, usize) = read_u8(Path::new(path))
+        .map_err(|error| InputError::Read { path: path.clone(), error })?;
+    check_dims(options, (rows, cols))?;
+
+    return Ok(ResolvedInput { arr: Some(arr), rows, cols });
+}
+
+// The rule resolve applies to a file of shape loaded, for inputs read some
+// other way (such as --dtype q8.8).
+pub(crate) fn check_dims(options: &Options, loaded: (usize, usize)) -> Result<(), InputError> {
+    let given: (Option<usize>, Option<usize>) = (options.rows, options.cols);
+    let matches = |target: (usize, usize)| given.0.is_none_or(|rows| rows == target.0) && given.1.is_none_or(|cols| cols == target.1);
+
+    if matches(loaded) || options.resize.is_some_and(matches) {
+        return Ok(());
+    }
+
+    return Err(InputError::DimensionMismatch { path: options.input.clone().unwrap_or_default(), given, loaded, resize: options.resize });
+}
+
+// Reads a u8 matrix, dispatching on the extension: .mtx is Matrix Market
+// (array or coordinate), anything else goes to read_matrix. Every value must
+// be a whole number from 0 to 255.
+pub(crate) fn read_u8(path: &Path) -> std::io::Result<(Vec<u8>, usize, usize)> {
+    let (values, rows, cols): (Vec<f64>, usize, usize) = if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("mtx")) {
+        let matrix: MarketMatrix = read_matrix_market(path)?;
+        (matrix.to_dense(), matrix.rows(), matrix.cols())
+    } else {
+        let matrix: StoredMatrix = read_matrix(path)?;
+        (matrix.values.iter().map(|&value| value as f64).collect(), matrix.rows, matrix.cols)
+    };
+
+    let data: Vec<u8> = values.iter().map(|&value| match value.fract() == 0.0 && (0.0..=255.0).contains(&value) {
+        true => Ok(value as u8),
+        false => Err(std::io::Error::new(ErrorKind::InvalidData, format!("input value {} is not a u8", value))),
+    }).collect::<std::io::Result<Vec<u8>>>()?;
+
+    return Ok((data, rows, cols));
+}
+
+// read_u8 for commands that take files only, panicking if one cannot be read.
+pub(crate) fn load_input(path: &Path) -> (Vec<u8>, usize, usize) {
+    return read_u8(path).unwrap_or_else(|err| panic!("Failed to read {}: {}", path.display(), err));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::parse_args;
+    use rmm::io::{write_bin, write_csv};
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        return std::env::temp_dir().join(format!("rmm-input-{}-{}", std::process::id(), name));
+    }
+
+    fn options(args: &[&str]) -> Options {
+        let mut all: Vec<String> = vec!["matician-coding-challenge".to_string()];
+        all.extend(args.iter().map(|arg| arg.to_string()));
+
+        return parse_args(&all);
+    }
+
+    // Writes a 3x4 CSV input.
+    fn fixture(name: &str) -> PathBuf {
+        let path: PathBuf = temp_path(name);
+        write_csv(&path, &(0..12u8).collect::<Vec<u8>>(), 3, 4).unwrap();
+
+        return path;
+    }
+
+    fn mismatch(args: &[&str]) -> String {
+        let err: InputError = resolve(&options(args)).err().expect("a dimension mismatch");
+        assert!(matches!(err, InputError::DimensionMismatch { .. }), "{:?}", err);
+
+        return err.to_string();
+    }
+
+    #[test]
+    fn without_a_file_both_dimensions_are_required() {
+        let resolved: ResolvedInput = resolve(&options(&["5", "7"])).unwrap();
+        assert_eq!((resolved.arr, resolved.rows, resolved.cols), (None, 5, 7));
+
+        let sized: ResolvedInput = resolve(&options(&["--size", "2x9"])).unwrap();
+        assert_eq!((sized.rows, sized.cols), (2, 9));
+
+        for args in [&[][..], &["--rows", "5"], &["--cols", "5"]] {
+            assert!(matches!(resolve(&options(args)), Err(InputError::MissingDimensions)), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn a_file_alone_gives_its_dimensions() {
+        let fixture: PathBuf = fixture("alone.csv");
+        let resolved: ResolvedInput = resolve(&options(&["--input", fixture.to_str().unwrap()])).unwrap();
+
+        assert_eq!((resolved.rows, resolved.cols), (3, 4));
+        assert_eq!(resolved.arr, Some((0..12).collect()));
+        std::fs::remove_file(&fixture).unwrap();
+    }
+
+    #[test]
+    fn given_dimensions_must_match_the_file() {
+        let fixture: PathBuf = fixture("matching.csv");
+
+        for args in [&["3", "4"][..], &["--rows", "3"], &["--cols", "4"], &["--size", "3x4"]] {
+            let mut all: Vec<&str> = vec!["--input", fixture.to_str().unwrap()];
+            all.extend_from_slice(args);
+            let resolved: ResolvedInput = resolve(&options(&all)).unwrap();
+
+            assert_eq!((resolved.rows, resolved.cols), (3, 4), "{:?}", args);
+        }
+
+        std::fs::remove_file(&fixture).unwrap();
+    }
+
+    #[test]
+    fn mismatched_dimensions_show_both() {
+        let fixture: PathBuf = fixture("mismatched.csv");
+        let path: &str = fixture.to_str().unwrap();
+
+        assert_eq!(mismatch(&["--input", path, "4", "3"]),
+                   format!("{} is 3x4 but 4x3 was given; drop them to use the file's, or add --resize 4x3 to resize it", path));
+        assert_eq!(mismatch(&["--input", path, "--cols", "5"]), format!("{} is 3x4 but ?x5 was given; drop it to use the file's", path));
+        std::fs::remove_file(&fixture).unwrap();
+    }
+
+    #[test]
+    fn resize_reconciles_mismatched_dimensions() {
+        let fixture: PathBuf = fixture("resize.csv");
+        let path: &str = fixture.to_str().unwrap();
+
+        // The file keeps its own shape; it is resized to the target later.
+        for args in [&["8", "6", "--resize", "8x6"][..], &["--resize", "8x6"], &["--rows", "8", "--resize", "8x6"], &["3", "4", "--resize", "8x6"]] {
+            let mut all: Vec<&str> = vec!["--input", path];
+            all.extend_from_slice(args);
+            let resolved: ResolvedInput = resolve(&options(&all)).unwrap();
+
+            assert_eq!((resolved.rows, resolved.cols), (3, 4), "{:?}", args);
+        }
+
+        assert_eq!(mismatch(&["--input", path, "8", "7", "--resize", "8x6"]),
+                   format!("{} is 3x4 but 8x7 was given; with --resize the dimensions must be its target 8x6", path));
+        std::fs::remove_file(&fixture).unwrap();
+    }
+
+    #[test]
+    fn unreadable_and_non_u8_files_are_errors() {
+        let missing: PathBuf = temp_path("missing.csv");
+        let err: InputError = resolve(&options(&["--input", missing.to_str().unwrap()])).err().expect("a read error");
+        assert!(matches!(&err, InputError::Read { error, .. } if error.kind() == ErrorKind::NotFound), "{:?}", err);
+
+        let wide: PathBuf = temp_path("wide.bin");
+        write_bin(&wide, &[1i16, 256, 3, 4], 2, 2).unwrap();
+        let err: InputError = resolve(&options(&["--input", wide.to_str().unwrap()])).err().expect("a read error");
+        assert!(err.to_string().ends_with("input value 256 is not a u8"), "{}", err);
+        std::fs::remove_file(&wide).unwrap();
+
+        let market: PathBuf = temp_path("input.mtx");
+        std::fs::write(&market, "%%MatrixMarket matrix coordinate integer general\n2 3 2\n1 2 7\n2 3 255\n").unwrap();
+        assert_eq!(read_u8(&market).unwrap(), (vec![0, 7, 0, 0, 0, 255], 2, 3));
+        std::fs::remove_file(&market).unwrap();
+    }
+}
@@ -0,0 +1,194 @@
+use crate::error::{check_len, DimError};
+use crate::index::flat;
+
+// A summed-area table: sums[r * (cols + 1) + c] is the sum of every element
+// above and to the left of (r, c), so the sum over any rectangle takes four
+// lookups however large it is. Sums are kept in f64, which holds the sum of
+// a whole matrix of u8, i16 or f32 values without losing precision in
+// practice.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IntegralImage {
+    pub rows: usize,
+    pub cols: usize,
+    sums: Vec<f64>,
+}
+
+impl IntegralImage {
+    pub fn new<T: Copy + Into<f64>>(arr: &[T], rows: usize, cols: usize) -> Result<IntegralImage, DimError> {
+        check_len(arr.len(), rows, cols)?;
+
+        let stride: usize = cols + 1;
+        let mut sums: Vec<f64> = vec![0.0; (rows + 1) * stride];
+
+        for row in 0..rows {
+            let mut row_sum: f64 = 0.0;
+
+            for col in 0..cols {
+                row_sum += arr[flat(row, col, cols)].into();
+                sums[flat(row + 1, col + 1, stride)] = sums[flat(row, col + 1, stride)] + row_sum;
+            }
+        }
+
+        return Ok(IntegralImage { rows, cols, sums });
+    }
+
+    // Sum of the elements in rows r0..r1 and columns c0..c1 (ends
+    // excluded). Panics if the rectangle does not fit inside the matrix.
+    pub fn sum(&self, r0: usize, c0: usize, r1: usize, c1: usize) -> f64 {
+        assert!(r0 <= r1 && r1 <= self.rows && c0 <= c1 && c1 <= self.cols,
+                "rows {}..{}, columns {}..{} outside the {}x{} matrix", r0, r1, c0, c1, self.rows, self.cols);
+
+        let stride: usize = self.cols + 1;
+
+        return self.sums[flat(r1, c1, stride)] - self.sums[flat(r0, c1, stride)] - self.sums[flat(r1, c0, stride)] + self.sums[flat(r0, c0, stride)];
+    }
+
+    // Sum of the (2 * radius + 1)^2 window centered on (row, col), with the
+    // matrix extended by replicating its edge elements wherever the window
+    // reaches past the border. Still O(1): the part of the window outside
+    // the matrix is made of copies of the edge rows, columns and corners,
+    // each summed once and weighted by how often it repeats.
+    pub fn replicate_sum(&self, row: usize, col: usize, radius: usize) -> f64 {
+        assert!(row < self.rows && col < self.cols, "({}, {}) outside the {}x{} matrix", row, col, self.rows, self.cols);
+
+        let row_spans: [(f64, usize, usize); 3] = replicate_spans(row, radius, self.rows);
+        let col_spans: [(f64, usize, usize); 3] = replicate_spans(col, radius, self.cols);
+        let mut total: f64 = 0.0;
+
+        for (row_weight, r0, r1) in row_spans {
+            for (col_weight, c0, c1) in col_spans {
+                if row_weight != 0.0 && col_weight != 0.0 {
+                    total += row_weight * col_weight * self.sum(r0, c0, r1, c1);
+                }
+            }
+        }
+
+        return total;
+    }
+}
+
+// The window position - radius..=position + radius over 0..len, clamped, as
+// (weight, start, end) spans: the first index repeated for the part before
+// 0, the indices inside, and the last index repeated for the part past len.
+fn replicate_spans(position: usize, radius: usize, len: usize) -> [(f64, usize, usize); 3] {
+    let before: usize = radius.saturating_sub(position);
+    let after: usize = (position + radius).saturating_sub(len - 1);
+
+    return [
+        (before as f64, 0, 1),
+        (1.0, position.saturating_sub(radius), (position + radius + 1).min(len)),
+        (after as f64, len - 1, len),
+    ];
+}
+
+// Mean of the (2 * radius + 1)^2 window around every element of a rows x cols
+// matrix, i.e. a box blur, in O(1) per element. Borders replicate the edge
+// elements, as gaussian_blur does, so every window has the same weight.
+pub fn box_mean<T: Copy + Into<f64>>(arr: &[T], rows: usize, cols: usize, radius: usize) -> Result<Vec<f32>, DimError> {
+    let integral: IntegralImage = IntegralImage::new(arr, rows, cols)?;
+    let side: f64 = (2 * radius + 1) as f64;
+    let area: f64 = side * side;
+    let mut out: Vec<f32> = Vec::with_capacity(rows * cols);
+
+    for row in 0..rows {
+        for col in 0..cols {
+            out.push((integral.replicate_sum(row, col, radius) / area) as f32);
+        }
+    }
+
+    return Ok(out);
+}
+
+// Value adaptive_threshold gives elements above their local threshold.
+pub const FOREGROUND: u8 = 255;
+
+// Binarizes a rows x cols matrix against its local means: an element becomes
+// FOREGROUND if it is greater than offset plus the mean of the
+// (2 * radius + 1)^2 window around it, and 0 otherwise. Unlike a global
+// threshold this follows illumination that varies across the matrix. The
+// means are those of box_mean, replicating the edge elements where the
+// window reaches past the border, and are computed exactly in f64.
+pub fn adaptive_threshold<T: Copy + Into<f64>>(arr: &[T], rows: usize, cols: usize, window_radius: usize,
+                                               offset: f64) -> Result<Vec<u8>, DimError> {
+    let integral: IntegralImage = IntegralImage::new(arr, rows, cols)?;
+    let side: f64 = (2 * window_radius + 1) as f64;
+    let area: f64 = side * side;
+    let mut out: Vec<u8> = Vec::with_capacity(rows * cols);
+
+    for row in 0..rows {
+        for col in 0..cols {
+            let mean: f64 = integral.replicate_sum(row, col, window_radius) / area;
+            out.push(if arr[flat(row, col, cols)].into() > mean + offset { FOREGROUND } else { 0 });
+        }
+    }
+
+    return Ok(out);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::construct_randomized_matrix_seeded;
+
+    #[test]
+    fn rectangle_sums_match_brute_force() {
+        let (rows, cols): (usize, usize) = (7, 9);
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, 13);
+        let integral: IntegralImage = IntegralImage::new(&arr, rows, cols).unwrap();
+
+        // Every rectangle, empty ones included.
+        for r0 in 0..=rows {
+            for r1 in r0..=rows {
+                for c0 in 0..=cols {
+                    for c1 in c0..=cols {
+                        let expected: u32 = (r0..r1).flat_map(|row| (c0..c1).map(move |col| (row, col)))
+                            .map(|(row, col)| arr[flat(row, col, cols)] as u32).sum();
+
+                        assert_eq!(integral.sum(r0, c0, r1, c1), expected as f64, "rows {}..{}, cols {}..{}", r0, r1, c0, c1);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn replicated_windows_match_brute_force() {
+        let (rows, cols): (usize, usize) = (5, 6);
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, 8);
+        let integral: IntegralImage = IntegralImage::new(&arr, rows, cols).unwrap();
+
+        for radius in [0, 1, 2, 7] {
+            for row in 0..rows {
+                for col in 0..cols {
+                    let clamp = |value: isize, len: usize| value.clamp(0, len as isize - 1) as usize;
+                    let span = |center: usize| center as isize - radius as isize..=center as isize + radius as isize;
+                    let expected: u32 = span(row).flat_map(|r| span(col).map(move |c| (r, c)))
+                        .map(|(r, c)| arr[flat(clamp(r, rows), clamp(c, cols), cols)] as u32).sum();
+
+                    assert_eq!(integral.replicate_sum(row, col, radius), expected as f64, "({}, {}) radius {}", row, col, radius);
+                }
+            }
+        }
+
+        assert_eq!(box_mean(&[6u8; 12], 3, 4, 2).unwrap(), vec![6.0; 12]);
+    }
+
+    #[test]
+    fn adaptive_threshold_splits_a_step_by_its_local_mean() {
+        // A 10 | 200 step: near the step the local mean lies between the two
+        // levels, so only the bright side is foreground; away from it every
+        // window is flat and nothing exceeds its mean.
+        let (rows, cols): (usize, usize) = (4, 12);
+        let arr: Vec<u8> = (0..rows * cols).map(|index| if index % cols < 6 { 10 } else { 200 }).collect();
+        let binary: Vec<u8> = adaptive_threshold(&arr, rows, cols, 2, 0.0).unwrap();
+        let expected_row: Vec<u8> = vec![0, 0, 0, 0, 0, 0, FOREGROUND, FOREGROUND, 0, 0, 0, 0];
+
+        for row in binary.chunks(cols) {
+            assert_eq!(row, &expected_row[..]);
+        }
+
+        // A large enough offset leaves nothing above the threshold.
+        assert!(adaptive_threshold(&arr, rows, cols, 2, 255.0).unwrap().iter().all(|&value| value == 0));
+        assert!(adaptive_threshold(&arr, rows, cols + 1, 2, 0.0).is_err());
+    }
+}
@@ -0,0 +1,300 @@
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{BufWriter, Error, ErrorKind, Write};
+use std::path::Path;
+use crate::index::flat;
+use crate::stream::{parse_stream, STREAM_MAGIC};
+
+// Writes a rows x cols u8 matrix as a binary (P5) PGM image.
+pub fn write_pgm(path: &Path, data: &[u8], rows: usize, cols: usize) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    write!(writer, "P5\n{} {}\n255\n", cols, rows)?;
+    writer.write_all(&data[..rows * cols])?;
+
+    return writer.flush();
+}
+
+// Reads a binary (P5) PGM image with a maximum value of at most 255, as
+// written by write_pgm or most image tools. Returns the pixels in row-major
+// order with the row and column counts. Comments in the header are skipped.
+pub fn read_pgm(path: &Path) -> std::io::Result<(Vec<u8>, usize, usize)> {
+    let bytes: Vec<u8> = fs::read(path)?;
+
+    if !bytes.starts_with(b"P5") {
+        return Err(invalid_data("not a binary (P5) PGM image"));
+    }
+
+    // Width, height and maximum value, each followed by whitespace; the one
+    // after the maximum value is the last header byte.
+    let mut fields: [usize; 3] = [0; 3];
+    let mut pos: usize = 2;
+
+    for field in fields.iter_mut() {
+        loop {
+            match bytes.get(pos) {
+                Some(b'#') => pos += bytes[pos..].iter().position(|&byte| byte == b'\n').unwrap_or(bytes.len() - pos),
+                Some(byte) if byte.is_ascii_whitespace() => pos += 1,
+                _ => break,
+            }
+        }
+
+        let digits: usize = bytes[pos..].iter().take_while(|byte| byte.is_ascii_digit()).count();
+        *field = std::str::from_utf8(&bytes[pos..pos + digits]).ok().and_then(|text| text.parse().ok())
+            .ok_or_else(|| invalid_data("truncated or invalid PGM header"))?;
+        pos += digits;
+    }
+
+    let [cols, rows, max_value] = fields;
+
+    if max_value == 0 || max_value > 255 {
+        return Err(invalid_data(&format!("maximum value {} is not 1..=255", max_value)));
+    }
+
+    let body: &[u8] = bytes.get(pos + 1..).unwrap_or(&[]);
+
+    if rows.checked_mul(cols) != Some(body.len()) {
+        return Err(invalid_data(&format!("{}x{} image but {} pixel bytes", rows, cols, body.len())));
+    }
+
+    return Ok((body.to_vec(), rows, cols));
+}
+
+// Writes rows x cols RGB triples, e.g. from to_heatmap_rgb, as a binary (P6)
+// PPM image.
+pub fn write_ppm(path: &Path, rgb: &[u8], rows: usize, cols: usize) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    write!(writer, "P6\n{} {}\n255\n", cols, rows)?;
+    writer.write_all(&rgb[..rows * cols * 3])?;
+
+    return writer.flush();
+}
+
+// Writes a rows x cols matrix as CSV, one matrix row per line.
+pub fn write_csv<T: Display>(path: &Path, data: &[T], rows: usize, cols: usize) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    for row in 0..rows {
+        for col in 0..cols {
+            if col > 0 {
+                write!(writer, ",")?;
+            }
+            write!(writer, "{}", data[flat(row, col, cols)])?;
+        }
+        writeln!(writer)?;
+    }
+
+    return writer.flush();
+}
+
+// Binary matrix format (.bin), little-endian throughout:
+//
+//     offset  size  field
+//     0       4     magic "RMMB"
+//     4       1     format version, currently 1
+//     5       1     element type, see DType
+//     6       2     reserved, zero
+//     8       8     rows (u64)
+//     16      8     cols (u64)
+//     24            rows * cols elements in row-major order
+pub const BIN_MAGIC: &[u8; 4] = b"RMMB";
+pub const BIN_VERSION: u8 = 1;
+const BIN_HEADER_LEN: usize = 24;
+
+// Element type of a stored matrix.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DType {
+    U8 = 0,
+    I16 = 1,
+    U16 = 2,
+    I32 = 3,
+    F32 = 4,
+}
+
+impl DType {
+    pub(crate) fn from_code(code: u8) -> Option<DType> {
+        return [DType::U8, DType::I16, DType::U16, DType::I32, DType::F32].into_iter().find(|dtype| *dtype as u8 == code);
+    }
+
+    pub fn size(self) -> usize {
+        return match self {
+            DType::U8 => 1,
+            DType::I16 | DType::U16 => 2,
+            DType::I32 | DType::F32 => 4,
+        };
+    }
+}
+
+impl fmt::Display for DType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}", match self {
+            DType::U8 => "u8",
+            DType::I16 => "i16",
+            DType::U16 => "u16",
+            DType::I32 => "i32",
+            DType::F32 => "f32",
+        });
+    }
+}
+
+// Element types that can be written in the binary format.
+pub trait BinElement: Copy {
+    const DTYPE: DType;
+
+    fn write_le(self, out: &mut Vec<u8>);
+}
+
+macro_rules! impl_bin_element {
+    ($($t:ty => $dtype:expr),*) => {
+        $(impl BinElement for $t {
+            const DTYPE: DType = $dtype;
+
+            fn write_le(self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+        })*
+    };
+}
+
+impl_bin_element!(u8 => DType::U8, i16 => DType::I16, u16 => DType::U16, i32 => DType::I32, f32 => DType::F32);
+
+// A matrix read back by read_matrix. Values are widened to i64 whatever the
+// stored type; dtype is None for CSV files, which carry no type.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoredMatrix {
+    pub dtype: Option<DType>,
+    pub rows: usize,
+    pub cols: usize,
+    pub values: Vec<i64>,
+}
+
+// Writes a rows x cols matrix in the binary format.
+pub fn write_bin<T: BinElement + Copy>(path: &Path, data: &[T], rows: usize, cols: usize) -> std::io::Result<()> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(BIN_HEADER_LEN + rows * cols * T::DTYPE.size());
+
+    bytes.extend_from_slice(BIN_MAGIC);
+    bytes.extend_from_slice(&[BIN_VERSION, T::DTYPE as u8, 0, 0]);
+    bytes.extend_from_slice(&(rows as u64).to_le_bytes());
+    bytes.extend_from_slice(&(cols as u64).to_le_bytes());
+
+    for value in &data[..rows * cols] {
+        value.write_le(&mut bytes);
+    }
+
+    return fs::write(path, bytes);
+}
+
+// Reads a matrix written by write_bin, write_csv or a StreamWriter. Files
+// starting with the binary or stream magic are read as such, anything else
+// as CSV.
+pub fn read_matrix(path: &Path) -> std::io::Result<StoredMatrix> {
+    let bytes: Vec<u8> = fs::read(path)?;
+
+    if bytes.starts_with(BIN_MAGIC) {
+        return parse_bin(&bytes);
+    }
+
+    if bytes.starts_with(STREAM_MAGIC) {
+        return parse_stream(&bytes);
+    }
+
+    let text: String = String::from_utf8(bytes).map_err(|_| invalid_data("neither a binary matrix nor CSV text"))?;
+
+    return parse_csv(&text);
+}
+
+fn parse_bin(bytes: &[u8]) -> std::io::Result<StoredMatrix> {
+    if bytes.len() < BIN_HEADER_LEN {
+        return Err(invalid_data("truncated header"));
+    }
+
+    if bytes[4] != BIN_VERSION {
+        return Err(invalid_data(&format!("unsupported format version {}", bytes[4])));
+    }
+
+    let dtype: DType = DType::from_code(bytes[5]).ok_or_else(|| invalid_data(&format!("unknown element type {}", bytes[5])))?;
+    let rows: usize = u64::from_le_bytes(bytes[8..16].try_into().expect("8 bytes")) as usize;
+    let cols: usize = u64::from_le_bytes(bytes[16..24].try_into().expect("8 bytes")) as usize;
+    let body: &[u8] = &bytes[BIN_HEADER_LEN..];
+
+    if rows.checked_mul(cols).and_then(|len| len.checked_mul(dtype.size())) != Some(body.len()) {
+        return Err(invalid_data(&format!("{}x{} {} matrix but {} data bytes", rows, cols, dtype, body.len())));
+    }
+
+    return Ok(StoredMatrix { dtype: Some(dtype), rows, cols, values: decode_values(dtype, body) });
+}
+
+// The little-endian elements of type dtype in bytes, widened to i64. f32
+// values are rounded to the nearest integer; every f32 this tool writes holds
+// one, converted from an integer result.
+pub(crate) fn decode_values(dtype: DType, bytes: &[u8]) -> Vec<i64> {
+    return bytes.chunks_exact(dtype.size()).map(|chunk| match dtype {
+        DType::U8 => chunk[0] as i64,
+        DType::I16 => i16::from_le_bytes([chunk[0], chunk[1]]) as i64,
+        DType::U16 => u16::from_le_bytes([chunk[0], chunk[1]]) as i64,
+        DType::I32 => i32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) as i64,
+        DType::F32 => f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]).round() as i64,
+    }).collect();
+}
+
+fn parse_csv(text: &str) -> std::io::Result<StoredMatrix> {
+    let mut values: Vec<i64> = Vec::new();
+    let mut rows: usize = 0;
+    let mut cols: usize = 0;
+
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        let row: Vec<i64> = line.split(',').map(|field| field.trim().parse::<i64>())
+            .collect::<Result<_, _>>().map_err(|_| invalid_data(&format!("invalid number on line {}", rows + 1)))?;
+
+        if rows > 0 && row.len() != cols {
+            return Err(invalid_data(&format!("line {} has {} fields, expected {}", rows + 1, row.len(), cols)));
+        }
+
+        cols = row.len();
+        rows += 1;
+        values.extend(row);
+    }
+
+    return Ok(StoredMatrix { dtype: None, rows, cols, values });
+}
+
+pub(crate) fn invalid_data(message: &str) -> Error {
+    return Error::new(ErrorKind::InvalidData, message.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // A file name for one test under the system temp directory.
+    fn temp_path(name: &str) -> PathBuf {
+        return std::env::temp_dir().join(format!("rmm-io-{}-{}", std::process::id(), name));
+    }
+
+    #[test]
+    fn ppm_has_a_p6_header_and_the_rgb_bytes() {
+        let path: PathBuf = temp_path("heatmap.ppm");
+        let rgb: Vec<u8> = (0..2 * 3 * 3).map(|value| value as u8 * 10).collect();
+        write_ppm(&path, &rgb, 2, 3).unwrap();
+        let bytes: Vec<u8> = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // Width before height, then one byte per channel.
+        let header: &[u8] = b"P6\n3 2\n255\n";
+        assert_eq!(&bytes[..header.len()], header);
+        assert_eq!(&bytes[header.len()..], &rgb[..]);
+    }
+
+    #[test]
+    fn pgm_round_trips() {
+        let path: PathBuf = temp_path("image.pgm");
+        let pixels: Vec<u8> = (0..12).map(|value| value as u8 * 21).collect();
+        write_pgm(&path, &pixels, 3, 4).unwrap();
+        let read = read_pgm(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(read.unwrap(), (pixels, 3, 4));
+    }
+}
@@ -0,0 +1,1335 @@
+#[cfg(feature = "unsafe-fast")]
+use crate::access::Unchecked;
+use crate::access::{Access, Checked, Fast};
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
+#[cfg(feature = "parallel")]
+use std::thread;
+use crate::cancel::{check_cancel, row_chunks};
+use crate::error::{check_len, check_strided, checked_elements, CancelError, DimError};
+use crate::index::{border_ranges, flat, padded_dims, row_range, rows_range, Axis, BorderRanges, Padding};
+use crate::matrix::{Layout, Matrix};
+
+// Border condition assumption:
+// To calculate convolutions, we apply a padding of size 2. Thus, when
+// calculating the result of a convolution with [-1, 0, 1] applied
+// horizontally, we use padding of size 2 on the left and right side of the
+// randomized matrix. Size 2 is kernel.len() - 1 for these 3-tap kernels; the
+// generic convolutions in conv.rs derive the padding from the kernel length
+// the same way, or take it explicitly (see convolve_rows_padded).
+
+// Calculates convolution of 2D matrix arr and [-1, 0, 1] (applied horizontally).
+// By applying horizontally, [-1, 0, 1] is treated as the 1x3 matrix
+// [[-1, 0, 1]]. The result is a row-major rows x (cols + 2) matrix.
+pub fn compute_dx(arr: &[u8], rows: usize, cols: usize) -> Matrix<i16> {
+    let (out_rows, out_cols): (usize, usize) = dx_dims(rows, cols);
+    let mut data: Vec<i16> = vec![0; dx_len(rows, cols).expect("Matrix has unexpected dimensions")];
+    compute_dx_into(arr, rows, cols, &mut data).expect("Matrix has unexpected dimensions");
+
+    return Matrix { data, rows: out_rows, cols: out_cols, layout: Layout::RowMajor };
+}
+
+// Calculates convolution of 2D matrix arr and [-1, 0, 1] (applied vertically).
+// By applying vertically, [-1, 0, 1] is treated as the 3x1 matrix
+// [[-1], [0], [1]]. The result is a row-major (rows + 2) x cols matrix.
+pub fn compute_dy(arr: &[u8], rows: usize, cols: usize) -> Matrix<i16> {
+    let (out_rows, out_cols): (usize, usize) = dy_dims(rows, cols);
+    let mut data: Vec<i16> = vec![0; dy_len(rows, cols).expect("Matrix has unexpected dimensions")];
+    compute_dy_into(arr, rows, cols, &mut data).expect("Matrix has unexpected dimensions");
+
+    return Matrix { data, rows: out_rows, cols: out_cols, layout: Layout::RowMajor };
+}
+
+// Shape of the Dx of a rows x cols matrix, rows x (cols + 2).
+fn dx_dims(rows: usize, cols: usize) -> (usize, usize) {
+    return padded_dims(rows, cols, 3, Axis::Cols, Padding::Full);
+}
+
+// Shape of the Dy of a rows x cols matrix, (rows + 2) x cols.
+fn dy_dims(rows: usize, cols: usize) -> (usize, usize) {
+    return padded_dims(rows, cols, 3, Axis::Rows, Padding::Full);
+}
+
+// Elements of the Dx of a rows x cols matrix, or TooLarge if they overflow.
+// Every output is sized through this (or dy_len) before the Fast loops write
+// it, so a wrapped size cannot leave them writing past the end.
+fn dx_len(rows: usize, cols: usize) -> Result<usize, DimError> {
+    let (out_rows, out_cols): (usize, usize) = dx_dims(rows, cols);
+
+    return checked_elements(out_rows, out_cols);
+}
+
+// Elements of the Dy of a rows x cols matrix, see dx_len.
+fn dy_len(rows: usize, cols: usize) -> Result<usize, DimError> {
+    let (out_rows, out_cols): (usize, usize) = dy_dims(rows, cols);
+
+    return checked_elements(out_rows, out_cols);
+}
+
+// compute_dx returning only the data, as it did before the result carried
+// its shape.
+#[deprecated(note = "use compute_dx, whose result carries its shape")]
+pub fn compute_dx_vec(arr: &[u8], rows: usize, cols: usize) -> Vec<i16> {
+    return compute_dx(arr, rows, cols).data;
+}
+
+#[deprecated(note = "use compute_dy, whose result carries its shape")]
+pub fn compute_dy_vec(arr: &[u8], rows: usize, cols: usize) -> Vec<i16> {
+    return compute_dy(arr, rows, cols).data;
+}
+
+// compute_dx on a rows x cols matrix whose rows start stride elements apart
+// in arr, i.e. element (row, col) lives at row * stride + col. This allows
+// running the kernel on a sub-rectangle of a larger buffer without copying it.
+// The output is a compact rows x (cols + 2) matrix. Unlike compute_dx, this
+// always runs the generic loops, whatever the size.
+pub fn compute_dx_strided(arr: &[u8], rows: usize, cols: usize, stride: usize) -> Result<Vec<i16>, DimError> {
+    return dx_impl::<Fast>(arr, rows, cols, stride);
+}
+
+// compute_dx writing into out instead of allocating, so a caller running the
+// kernel repeatedly can reuse one buffer. out must hold exactly
+// rows * (cols + 2) elements; its previous contents do not matter.
+pub fn compute_dx_into(arr: &[u8], rows: usize, cols: usize, out: &mut [i16]) -> Result<(), DimError> {
+    check_len(arr.len(), rows, cols)?;
+    check_out_len(out.len(), dx_len(rows, cols)?)?;
+
+    if !dx_small(arr, rows, cols, out) {
+        dx_fill::<Fast>(arr, rows, cols, cols, out);
+    }
+
+    return Ok(());
+}
+
+// compute_dx that always uses bounds-checked indexing, regardless of the
+// unsafe-fast feature.
+pub fn compute_dx_safe(arr: &[u8], rows: usize, cols: usize) -> Result<Vec<i16>, DimError> {
+    return dx_impl::<Checked>(arr, rows, cols, cols);
+}
+
+// compute_dx using unchecked indexing after validating arr's length.
+#[cfg(feature = "unsafe-fast")]
+pub fn compute_dx_unchecked(arr: &[u8], rows: usize, cols: usize) -> Result<Vec<i16>, DimError> {
+    return dx_impl::<Unchecked>(arr, rows, cols, cols);
+}
+
+fn dx_impl<A: Access>(arr: &[u8], rows: usize, cols: usize, stride: usize) -> Result<Vec<i16>, DimError> {
+    check_strided(arr.len(), rows, cols, stride)?;
+
+    let mut dx: Vec<i16> = vec![0; dx_len(rows, cols)?];
+    dx_fill::<A>(arr, rows, cols, stride, &mut dx);
+
+    return Ok(dx);
+}
+
+// Writes every element of the rows x (cols + 2) Dx into dx, whatever it held
+// before.
+fn dx_fill<A: Access>(arr: &[u8], rows: usize, cols: usize, stride: usize, dx: &mut [i16]) {
+    let ranges: BorderRanges = border_ranges(cols, 3, Padding::Full);
+
+    dx_border::<A>(arr, rows, cols, stride, &ranges, dx);
+    dx_interior::<A>(arr, cols, stride, &ranges, dx);
+}
+
+// Computes the columns of Dx where padding is used: the first 2 and last 2,
+// or all of them for inputs narrower than the kernel. Output column col is
+// input column col - 2 minus input column col, either of which may be
+// padding. For optimization, the 0 of [-1, 0, 1] is ignored.
+fn dx_border<A: Access>(arr: &[u8], rows: usize, cols: usize, stride: usize, ranges: &BorderRanges, dx: &mut [i16]) {
+    let new_cols: usize = cols + 2;
+
+    for row in 0..rows {
+        for col in ranges.border() {
+            let left: i16 = if col >= 2 { A::get(arr, flat(row, col - 2, stride)) as i16 } else { 0 };
+            let right: i16 = if col < cols { A::get(arr, flat(row, col, stride)) as i16 } else { 0 };
+            A::set(dx, flat(row, col, new_cols), left - right);
+        }
+    }
+}
+
+// Computes the inner columns of Dx. Once again, for optimization, the 0 of
+// [-1, 0, 1] is ignored. Working on per-row subslices lets the compiler drop
+// the bounds checks.
+fn dx_interior<A: Access>(arr: &[u8], cols: usize, stride: usize, ranges: &BorderRanges, dx: &mut [i16]) {
+    let (start, end): (usize, usize) = (ranges.interior.start, ranges.interior.end);
+
+    if start < end {
+        for (row, out) in dx.chunks_exact_mut(cols + 2).enumerate() {
+            let src: &[u8] = A::slice(arr, flat(row, 0, stride), flat(row, cols, stride));
+            diff_into(A::slice_mut(out, start, end), &src[..end - 2], &src[start..]);
+        }
+    }
+}
+
+// Matrices wider than this many columns are processed by compute_dy in
+// vertical panels of DY_BLOCK_COLS columns.
+pub const DY_BLOCK_THRESHOLD: usize = 65536;
+
+// Width of the vertical panels used for wide matrices. A panel's input rows
+// and output rows then stay cache resident while they are reused, instead of
+// each pass over a row streaming megabytes.
+pub const DY_BLOCK_COLS: usize = 4096;
+
+// compute_dy on a strided rows x cols matrix, see compute_dx_strided. The
+// output is a compact (rows + 2) x cols matrix. Matrices wider than
+// DY_BLOCK_THRESHOLD are processed in panels of DY_BLOCK_COLS columns.
+pub fn compute_dy_strided(arr: &[u8], rows: usize, cols: usize, stride: usize) -> Result<Vec<i16>, DimError> {
+    return compute_dy_blocked(arr, rows, cols, stride, dy_block_cols(cols));
+}
+
+// compute_dy processing the matrix in vertical panels of block_cols columns.
+// The result is identical to the unblocked computation for any block width;
+// block_cols >= cols processes the whole matrix as one panel.
+pub fn compute_dy_blocked(arr: &[u8], rows: usize, cols: usize, stride: usize,
+                          block_cols: usize) -> Result<Vec<i16>, DimError> {
+    return dy_impl::<Fast>(arr, rows, cols, stride, block_cols);
+}
+
+// compute_dy writing into out, see compute_dx_into. out must hold exactly
+// (rows + 2) * cols elements.
+pub fn compute_dy_into(arr: &[u8], rows: usize, cols: usize, out: &mut [i16]) -> Result<(), DimError> {
+    check_len(arr.len(), rows, cols)?;
+    check_out_len(out.len(), dy_len(rows, cols)?)?;
+
+    if !dy_small(arr, rows, cols, out) {
+        dy_fill::<Fast>(arr, rows, cols, cols, dy_block_cols(cols), out);
+    }
+
+    return Ok(());
+}
+
+// compute_dx_into and compute_dy_into in one call, for callers that need
+// both gradients of every frame. Both outputs are checked before either is
+// written.
+pub fn compute_dxdy_into(arr: &[u8], rows: usize, cols: usize, dx: &mut [i16], dy: &mut [i16]) -> Result<(), DimError> {
+    check_len(arr.len(), rows, cols)?;
+    check_out_len(dx.len(), dx_len(rows, cols)?)?;
+    check_out_len(dy.len(), dy_len(rows, cols)?)?;
+    compute_dx_into(arr, rows, cols, dx)?;
+
+    return compute_dy_into(arr, rows, cols, dy);
+}
+
+fn check_out_len(found: usize, expected: usize) -> Result<(), DimError> {
+    if found != expected {
+        return Err(DimError::Mismatch { what: "output length", expected, found });
+    }
+
+    return Ok(());
+}
+
+// compute_dx on the rows x cols view whose row i is row row_map[i] of arr,
+// read through the index instead of copied. row_map may repeat or skip rows,
+// but must hold rows indices below rows. Row i of the result is row
+// row_map[i] of compute_dx(arr), so the same work is done in a different
+// memory order; bench cache uses this to measure how much the kernels rely
+// on sequential access.
+pub fn compute_dx_mapped(arr: &[u8], rows: usize, cols: usize, row_map: &[usize]) -> Result<Vec<i16>, DimError> {
+    check_row_map(arr.len(), rows, cols, row_map)?;
+
+    let mut dx: Vec<i16> = vec![0; dx_len(rows, cols)?];
+
+    for (out, &row) in dx.chunks_exact_mut(cols + 2).zip(row_map) {
+        dx_fill::<Fast>(&arr[row_range(row, cols)], 1, cols, cols, out);
+    }
+
+    return Ok(dx);
+}
+
+// compute_dy on the row_map view, see compute_dx_mapped. Vertically adjacent
+// rows of the view are generally far apart in arr, so unlike compute_dx_mapped
+// the result is not a reordering of compute_dy(arr); it is compute_dy of the
+// view copied out.
+pub fn compute_dy_mapped(arr: &[u8], rows: usize, cols: usize, row_map: &[usize]) -> Result<Vec<i16>, DimError> {
+    check_row_map(arr.len(), rows, cols, row_map)?;
+
+    let mut dy: Vec<i16> = vec![0; dy_len(rows, cols)?];
+    let view = |index: usize| &arr[row_range(row_map[index], cols)];
+
+    // Output row r is view row r - 2 minus view row r.
+    for (row, out) in dy.chunks_exact_mut(cols.max(1)).enumerate().take(rows + 2) {
+        match (row.checked_sub(2), row < rows) {
+            (Some(above), true) => diff_into(out, view(above), view(row)),
+            (Some(above), false) => out.iter_mut().zip(view(above)).for_each(|(o, &value)| *o = value as i16),
+            (None, true) => out.iter_mut().zip(view(row)).for_each(|(o, &value)| *o = -(value as i16)),
+            (None, false) => {}
+        }
+    }
+
+    return Ok(dy);
+}
+
+fn check_row_map(len: usize, rows: usize, cols: usize, row_map: &[usize]) -> Result<(), DimError> {
+    check_len(len, rows, cols)?;
+
+    if row_map.len() != rows {
+        return Err(DimError::Mismatch { what: "row map length", expected: rows, found: row_map.len() });
+    }
+
+    if let Some(&row) = row_map.iter().find(|&&row| row >= rows) {
+        return Err(DimError::OutOfRange { rows, cols, row, col: 0, height: 1, width: cols });
+    }
+
+    return Ok(());
+}
+
+// compute_dy that always uses bounds-checked indexing, regardless of the
+// unsafe-fast feature.
+pub fn compute_dy_safe(arr: &[u8], rows: usize, cols: usize) -> Result<Vec<i16>, DimError> {
+    return dy_impl::<Checked>(arr, rows, cols, cols, dy_block_cols(cols));
+}
+
+// compute_dy using unchecked indexing after validating arr's length.
+#[cfg(feature = "unsafe-fast")]
+pub fn compute_dy_unchecked(arr: &[u8], rows: usize, cols: usize) -> Result<Vec<i16>, DimError> {
+    return dy_impl::<Unchecked>(arr, rows, cols, cols, dy_block_cols(cols));
+}
+
+fn dy_block_cols(cols: usize) -> usize {
+    return if cols > DY_BLOCK_THRESHOLD { DY_BLOCK_COLS } else { cols };
+}
+
+fn dy_impl<A: Access>(arr: &[u8], rows: usize, cols: usize, stride: usize,
+                      block_cols: usize) -> Result<Vec<i16>, DimError> {
+    check_strided(arr.len(), rows, cols, stride)?;
+
+    let mut dy: Vec<i16> = vec![0; dy_len(rows, cols)?];
+    dy_fill::<A>(arr, rows, cols, stride, block_cols, &mut dy);
+
+    return Ok(dy);
+}
+
+// Writes every element of the (rows + 2) x cols Dy into dy, whatever it held
+// before.
+fn dy_fill<A: Access>(arr: &[u8], rows: usize, cols: usize, stride: usize, block_cols: usize, dy: &mut [i16]) {
+    let ranges: BorderRanges = border_ranges(rows, 3, Padding::Full);
+
+    for c0 in (0..cols).step_by(block_cols.max(1)) {
+        dy_panel::<A>(arr, rows, cols, stride, c0, (c0 + block_cols.max(1)).min(cols), &ranges, dy);
+    }
+}
+
+// Computes columns c0..c1 of Dy into dy.
+#[allow(clippy::too_many_arguments)]
+fn dy_panel<A: Access>(arr: &[u8], rows: usize, cols: usize, stride: usize, c0: usize, c1: usize, ranges: &BorderRanges, dy: &mut [i16]) {
+    dy_border::<A>(arr, rows, cols, stride, c0, c1, ranges, dy);
+    dy_inner::<A>(arr, cols, stride, c0, c1, 0, ranges.interior.len(), dy);
+}
+
+// Computes columns c0..c1 of the rows of Dy where padding is used: the first
+// 2 and last 2, or all of them for inputs shorter than the kernel. Output row
+// row is input row row - 2 minus input row row, either of which may be
+// padding. For optimization, the 0 of [-1, 0, 1] is ignored.
+#[allow(clippy::too_many_arguments)]
+fn dy_border<A: Access>(arr: &[u8], rows: usize, cols: usize, stride: usize, c0: usize, c1: usize, ranges: &BorderRanges, dy: &mut [i16]) {
+    for row in ranges.border() {
+        for col in c0..c1 {
+            let above: i16 = if row >= 2 { A::get(arr, flat(row - 2, col, stride)) as i16 } else { 0 };
+            let below: i16 = if row < rows { A::get(arr, flat(row, col, stride)) as i16 } else { 0 };
+            A::set(dy, flat(row, col, cols), above - below);
+        }
+    }
+}
+
+// Computes columns c0..c1 of the inner Dy rows r0 + 2..r1 + 2, i.e. the
+// differences of input rows r and r + 2 for r in r0..r1. Once again, for
+// optimization, the 0 of [-1, 0, 1] is ignored.
+#[allow(clippy::too_many_arguments)]
+fn dy_inner<A: Access>(arr: &[u8], cols: usize, stride: usize, c0: usize, c1: usize, r0: usize, r1: usize, dy: &mut [i16]) {
+    for row in r0..r1 {
+        let above: &[u8] = A::slice(arr, flat(row, c0, stride), flat(row, c1, stride));
+        let below: &[u8] = A::slice(arr, flat(row + 2, c0, stride), flat(row + 2, c1, stride));
+        diff_into(A::slice_mut(dy, flat(row + 2, c0, cols), flat(row + 2, c1, cols)), above, below);
+    }
+}
+
+// Matrices with at most this many rows and columns go through kernels
+// specialized for their column count in compute_dx, compute_dy and their
+// _into variants. At such sizes the generic loops spend most of their time on
+// setup and the border branches; with the width a constant every row becomes
+// straight-line code. The results are identical.
+pub const SMALL_MAX: usize = 32;
+
+// Calls $kernel::<N> for cols == N in 1..=SMALL_MAX, or evaluates to false
+// for any other width.
+macro_rules! small_dispatch {
+    ($kernel:ident, $cols:expr, $($arg:expr),*) => {
+        small_dispatch!(@arms $kernel, $cols, ($($arg),*), 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16
+                        17 18 19 20 21 22 23 24 25 26 27 28 29 30 31 32)
+    };
+    (@arms $kernel:ident, $cols:expr, $args:tt, $($n:literal)*) => {
+        match $cols {
+            $($n => small_dispatch!(@call $kernel, $n, $args),)*
+            _ => false,
+        }
+    };
+    (@call $kernel:ident, $n:literal, ($($arg:expr),*)) => {{
+        $kernel::<$n>($($arg),*);
+        true
+    }};
+}
+
+// Writes Dx of a small matrix into out with the kernel for its width, or
+// returns false if the matrix is larger than SMALL_MAX either way (or
+// empty). arr and out must have checked lengths.
+fn dx_small(arr: &[u8], rows: usize, cols: usize, out: &mut [i16]) -> bool {
+    if rows > SMALL_MAX {
+        return false;
+    }
+
+    return small_dispatch!(dx_small_fill, cols, arr, out);
+}
+
+// Dx of a matrix COLS wide: output column col is input column col - 2 minus
+// input column col, either of which may be padding.
+fn dx_small_fill<const COLS: usize>(arr: &[u8], dx: &mut [i16]) {
+    for (src, out) in arr.chunks_exact(COLS).zip(dx.chunks_exact_mut(COLS + 2)) {
+        let src: &[u8; COLS] = src.try_into().expect("chunks are COLS long");
+
+        for (col, value) in out[..COLS + 2].iter_mut().enumerate() {
+            let left: i16 = if col >= 2 { src[col - 2] as i16 } else { 0 };
+            let right: i16 = if col < COLS { src[col] as i16 } else { 0 };
+            *value = left - right;
+        }
+    }
+}
+
+// dx_small for Dy.
+fn dy_small(arr: &[u8], rows: usize, cols: usize, out: &mut [i16]) -> bool {
+    if rows > SMALL_MAX {
+        return false;
+    }
+
+    return small_dispatch!(dy_small_fill, cols, arr, rows, out);
+}
+
+// Dy of a rows x COLS matrix: output row row is input row row - 2 minus input
+// row row, either of which may be padding.
+fn dy_small_fill<const COLS: usize>(arr: &[u8], rows: usize, dy: &mut [i16]) {
+    let row_of = |row: usize| -> &[u8; COLS] { arr[row_range(row, COLS)].try_into().expect("rows are COLS long") };
+
+    for (row, out) in dy.chunks_exact_mut(COLS).enumerate() {
+        let out: &mut [i16; COLS] = out.try_into().expect("chunks are COLS long");
+
+        match (row.checked_sub(2), row < rows) {
+            (Some(above), true) => {
+                let (above, below): (&[u8; COLS], &[u8; COLS]) = (row_of(above), row_of(row));
+
+                for col in 0..COLS {
+                    out[col] = above[col] as i16 - below[col] as i16;
+                }
+            }
+            (Some(above), false) => {
+                for (value, &x) in out.iter_mut().zip(row_of(above)) {
+                    *value = x as i16;
+                }
+            }
+            (None, true) => {
+                for (value, &x) in out.iter_mut().zip(row_of(row)) {
+                    *value = -(x as i16);
+                }
+            }
+            (None, false) => out.fill(0),
+        }
+    }
+}
+
+// compute_dx that checks cancel every CANCEL_CHECK_ROWS rows and gives up
+// with CancelError::Cancelled once it is set. The result is identical to
+// compute_dx.
+pub fn compute_dx_cancellable(arr: &[u8], rows: usize, cols: usize, cancel: &AtomicBool) -> Result<Vec<i16>, CancelError> {
+    check_len(arr.len(), rows, cols)?;
+
+    let mut dx: Vec<i16> = Vec::with_capacity(dx_len(rows, cols)?);
+
+    // Rows of Dx only depend on the same input row, so chunks are independent.
+    for (r0, r1) in row_chunks(rows) {
+        check_cancel(cancel, r0, rows)?;
+        dx.extend(dx_impl::<Fast>(&arr[rows_range(r0, r1, cols)], r1 - r0, cols, cols)?);
+    }
+
+    return Ok(dx);
+}
+
+// compute_dy that checks cancel every CANCEL_CHECK_ROWS rows, see
+// compute_dx_cancellable. The result is identical to compute_dy.
+pub fn compute_dy_cancellable(arr: &[u8], rows: usize, cols: usize, cancel: &AtomicBool) -> Result<Vec<i16>, CancelError> {
+    check_len(arr.len(), rows, cols)?;
+
+    let mut dy: Vec<i16> = vec![0; dy_len(rows, cols)?];
+
+    if rows == 0 {
+        return Ok(dy);
+    }
+
+    let block_cols: usize = dy_block_cols(cols).max(1);
+    dy_border::<Fast>(arr, rows, cols, cols, 0, cols, &border_ranges(rows, 3, Padding::Full), &mut dy);
+
+    for (r0, r1) in row_chunks(rows.saturating_sub(2)) {
+        check_cancel(cancel, r0, rows)?;
+
+        for c0 in (0..cols).step_by(block_cols) {
+            dy_inner::<Fast>(arr, cols, cols, c0, (c0 + block_cols).min(cols), r0, r1, &mut dy);
+        }
+    }
+
+    return Ok(dy);
+}
+
+// compute_dx split into bands of rows computed on up to threads threads.
+// Rows of Dx are independent, so the result is identical to compute_dx.
+// Without the parallel feature this runs on the calling thread.
+pub fn compute_dx_par(arr: &[u8], rows: usize, cols: usize, threads: usize) -> Result<Vec<i16>, DimError> {
+    return compute_dx_par_with(arr, rows, cols, threads, |_| {});
+}
+
+// compute_dx_par calling on_start(band index) on each worker thread before it
+// touches its band, e.g. to pin the worker to a core. Band i covers input
+// rows i * rows.div_ceil(threads) onwards, the same rows
+// construct_randomized_matrix_seeded_bands generates on worker i.
+#[cfg(feature = "parallel")]
+pub fn compute_dx_par_with<F: Fn(usize) + Sync>(arr: &[u8], rows: usize, cols: usize, threads: usize, on_start: F) -> Result<Vec<i16>, DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    if rows == 0 || threads <= 1 {
+        return dx_impl::<Fast>(arr, rows, cols, cols);
+    }
+
+    let band: usize = rows.div_ceil(threads);
+    let mut dx: Vec<i16> = vec![0; dx_len(rows, cols)?];
+    let on_start: &F = &on_start;
+
+    thread::scope(|scope| {
+        for (index, out) in dx.chunks_mut(band * (cols + 2)).enumerate() {
+            let r0: usize = index * band;
+            let r1: usize = (r0 + band).min(rows);
+
+            scope.spawn(move || {
+                on_start(index);
+                out.copy_from_slice(&dx_impl::<Fast>(&arr[rows_range(r0, r1, cols)], r1 - r0, cols, cols).expect("band has checked dimensions"));
+            });
+        }
+    });
+
+    return Ok(dx);
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn compute_dx_par_with<F: Fn(usize) + Sync>(arr: &[u8], rows: usize, cols: usize, _threads: usize, _on_start: F) -> Result<Vec<i16>, DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    return dx_impl::<Fast>(arr, rows, cols, cols);
+}
+
+// compute_dy with the inner rows split into bands computed on up to threads
+// threads. The result is identical to compute_dy. Without the parallel
+// feature this runs on the calling thread.
+pub fn compute_dy_par(arr: &[u8], rows: usize, cols: usize, threads: usize) -> Result<Vec<i16>, DimError> {
+    return compute_dy_par_with(arr, rows, cols, threads, |_| {});
+}
+
+// compute_dy_par calling on_start(band index) on each worker thread first, as
+// compute_dx_par_with does. Bands are rows.div_ceil(threads) rows long like
+// those of Dx, so band i reads the input rows worker i generated plus the two
+// below them.
+#[cfg(feature = "parallel")]
+pub fn compute_dy_par_with<F: Fn(usize) + Sync>(arr: &[u8], rows: usize, cols: usize, threads: usize, on_start: F) -> Result<Vec<i16>, DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    let inner: usize = rows.saturating_sub(2);
+
+    if inner == 0 || cols == 0 || threads <= 1 {
+        return dy_impl::<Fast>(arr, rows, cols, cols, dy_block_cols(cols));
+    }
+
+    let band: usize = rows.div_ceil(threads);
+    let mut dy: Vec<i16> = vec![0; dy_len(rows, cols)?];
+    let on_start: &F = &on_start;
+
+    dy_border::<Fast>(arr, rows, cols, cols, 0, cols, &border_ranges(rows, 3, Padding::Full), &mut dy);
+
+    // Output row r + 2 is input row r minus input row r + 2.
+    thread::scope(|scope| {
+        for (index, out) in dy[rows_range(2, inner + 2, cols)].chunks_mut(band * cols).enumerate() {
+            let r0: usize = index * band;
+
+            scope.spawn(move || {
+                on_start(index);
+
+                for (offset, out_row) in out.chunks_mut(cols).enumerate() {
+                    let row: usize = r0 + offset;
+                    diff_into(out_row, &arr[row_range(row, cols)], &arr[row_range(row + 2, cols)]);
+                }
+            });
+        }
+    });
+
+    return Ok(dy);
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn compute_dy_par_with<F: Fn(usize) + Sync>(arr: &[u8], rows: usize, cols: usize, _threads: usize, _on_start: F) -> Result<Vec<i16>, DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    return dy_impl::<Fast>(arr, rows, cols, cols, dy_block_cols(cols));
+}
+
+// A rectangle of a matrix: rows row..row + rows and columns col..col + cols.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Region {
+    pub row: usize,
+    pub col: usize,
+    pub rows: usize,
+    pub cols: usize,
+}
+
+// Rows row_start..row_start + row_count of compute_dx, computed from those
+// input rows only, so a band of a huge matrix costs only the band. The
+// result is row_count x (cols + 2).
+pub fn compute_dx_rows(arr: &[u8], rows: usize, cols: usize, row_start: usize, row_count: usize) -> Result<Matrix<i16>, DimError> {
+    return compute_dx_region(arr, rows, cols, Region { row: row_start, col: 0, rows: row_count, cols });
+}
+
+// The rows of compute_dy that belong to input rows row_start..row_start +
+// row_count: output rows row_start..row_start + row_count + 2, i.e. the band
+// with the same padding compute_dy gives a whole matrix, (row_count + 2) x
+// cols. Output row r is input row r - 2 minus input row r, so only the band
+// and up to two context rows above and below it are read. Every element
+// equals the one compute_dy gives for the whole matrix.
+pub fn compute_dy_rows(arr: &[u8], rows: usize, cols: usize, row_start: usize, row_count: usize) -> Result<Matrix<i16>, DimError> {
+    return compute_dy_region(arr, rows, cols, Region { row: row_start, col: 0, rows: row_count, cols });
+}
+
+// The columns of compute_dx that belong to input columns col_start..col_start
+// + col_count, rows x (col_count + 2), reading up to two context columns on
+// either side; see compute_dy_rows.
+pub fn compute_dx_cols(arr: &[u8], rows: usize, cols: usize, col_start: usize, col_count: usize) -> Result<Matrix<i16>, DimError> {
+    return compute_dx_region(arr, rows, cols, Region { row: 0, col: col_start, rows, cols: col_count });
+}
+
+// Columns col_start..col_start + col_count of compute_dy, (rows + 2) x
+// col_count. Columns of Dy are independent, so no context is read.
+pub fn compute_dy_cols(arr: &[u8], rows: usize, cols: usize, col_start: usize, col_count: usize) -> Result<Matrix<i16>, DimError> {
+    return compute_dy_region(arr, rows, cols, Region { row: 0, col: col_start, rows, cols: col_count });
+}
+
+// compute_dx restricted to region: rows region.row.. and columns region.col..
+// region.col + region.cols + 2 of the whole matrix's Dx, i.e. the region with
+// its usual padding, region.rows x (region.cols + 2). Only the region and up
+// to two context columns on either side are read.
+pub fn compute_dx_region(arr: &[u8], rows: usize, cols: usize, region: Region) -> Result<Matrix<i16>, DimError> {
+    check_region(arr, rows, cols, region)?;
+
+    let out_cols: usize = region.cols + 2;
+
+    if region.rows == 0 {
+        return Ok(Matrix { data: Vec::new(), rows: 0, cols: out_cols, layout: Layout::RowMajor });
+    }
+
+    let first: usize = region.col.saturating_sub(2);
+    let last: usize = (region.col + region.cols + 2).min(cols);
+    // Output columns first..last + 2 of the whole matrix.
+    let context: Vec<i16> = compute_dx_strided(&arr[flat(region.row, first, cols)..], region.rows, last - first, cols)?;
+    let skip: usize = region.col - first;
+    let data: Vec<i16> = context.chunks(last - first + 2).flat_map(|row| row[skip..skip + out_cols].iter().copied()).collect();
+
+    return Ok(Matrix { data, rows: region.rows, cols: out_cols, layout: Layout::RowMajor });
+}
+
+// compute_dy restricted to region: rows region.row..region.row + region.rows
+// + 2 and columns region.col.. of the whole matrix's Dy, (region.rows + 2) x
+// region.cols. Only the region and up to two context rows above and below it
+// are read.
+pub fn compute_dy_region(arr: &[u8], rows: usize, cols: usize, region: Region) -> Result<Matrix<i16>, DimError> {
+    check_region(arr, rows, cols, region)?;
+
+    let out_rows: usize = region.rows + 2;
+
+    if region.cols == 0 {
+        return Ok(Matrix { data: Vec::new(), rows: out_rows, cols: 0, layout: Layout::RowMajor });
+    }
+
+    let first: usize = region.row.saturating_sub(2);
+    let last: usize = (region.row + region.rows + 2).min(rows);
+    // Output rows first..last + 2 of the whole matrix.
+    let context: Vec<i16> = compute_dy_strided(&arr[flat(first, region.col, cols).min(arr.len())..], last - first, region.cols, cols)?;
+    let skip: usize = region.row - first;
+    let data: Vec<i16> = context[skip * region.cols..(skip + out_rows) * region.cols].to_vec();
+
+    return Ok(Matrix { data, rows: out_rows, cols: region.cols, layout: Layout::RowMajor });
+}
+
+fn check_region(arr: &[u8], rows: usize, cols: usize, region: Region) -> Result<(), DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    if region.row + region.rows > rows || region.col + region.cols > cols {
+        return Err(DimError::OutOfRange { rows, cols, row: region.row, col: region.col, height: region.rows, width: region.cols });
+    }
+
+    return Ok(());
+}
+
+// The second difference kernel of compute_dxx and compute_dyy.
+pub const SECOND_DIFFERENCE: [i32; 3] = [1, -2, 1];
+
+// Calculates the convolution of arr and [1, -2, 1] applied horizontally, the
+// second derivative along the rows, with the same padding as compute_dx: the
+// result is a row-major rows x (cols + 2) matrix. Values lie in -510..=510,
+// so i16 still holds them exactly. Sized through dx_len; a DimError if arr is
+// not rows x cols or the output would not fit.
+pub fn compute_dxx(arr: &[u8], rows: usize, cols: usize) -> Result<Matrix<i16>, DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    let (_, new_cols): (usize, usize) = dx_dims(rows, cols);
+    let mut dxx: Vec<i16> = vec![0; dx_len(rows, cols)?];
+
+    for (row, out) in dxx.chunks_exact_mut(new_cols).enumerate() {
+        let src: &[u8] = &arr[row_range(row, cols)];
+
+        // The first 2 and last 2 columns, where padding is used.
+        for col in border_ranges(cols, 3, Padding::Full).border() {
+            out[col] = second_difference_at(|index| src[index], cols, col);
+        }
+
+        if cols > 2 {
+            second_diff_into(&mut out[2..cols], &src[..cols - 2], &src[1..cols - 1], &src[2..]);
+        }
+    }
+
+    return Ok(Matrix { data: dxx, rows, cols: new_cols, layout: Layout::RowMajor });
+}
+
+// Calculates the convolution of arr and [1, -2, 1] applied vertically, see
+// compute_dxx. The result is a row-major (rows + 2) x cols matrix, sized
+// through dy_len.
+pub fn compute_dyy(arr: &[u8], rows: usize, cols: usize) -> Result<Matrix<i16>, DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    let (new_rows, _): (usize, usize) = dy_dims(rows, cols);
+    let mut dyy: Vec<i16> = vec![0; dy_len(rows, cols)?];
+
+    // The first 2 and last 2 rows, where padding is used.
+    for row in border_ranges(rows, 3, Padding::Full).border() {
+        for col in 0..cols {
+            dyy[flat(row, col, cols)] = second_difference_at(|index| arr[flat(index, col, cols)], rows, row);
+        }
+    }
+
+    for row in 2..rows {
+        second_diff_into(&mut dyy[row_range(row, cols)], &arr[row_range(row - 2, cols)], &arr[row_range(row - 1, cols)], &arr[row_range(row, cols)]);
+    }
+
+    return Ok(Matrix { data: dyy, rows: new_rows, cols, layout: Layout::RowMajor });
+}
+
+// Output j of the zero padded [1, -2, 1] convolution of the len values get
+// returns.
+fn second_difference_at(get: impl Fn(usize) -> u8, len: usize, j: usize) -> i16 {
+    return SECOND_DIFFERENCE.iter().enumerate().filter(|&(i, _)| j >= i && j - i < len)
+        .map(|(i, weight)| *weight as i16 * get(j - i) as i16).sum();
+}
+
+// Writes a[i] - 2 * b[i] + c[i] into out[i]. The slices must have equal lengths.
+fn second_diff_into(out: &mut [i16], a: &[u8], b: &[u8], c: &[u8]) {
+    for (((o, x), y), z) in out.iter_mut().zip(a).zip(b).zip(c) {
+        *o = *x as i16 - 2 * *y as i16 + *z as i16;
+    }
+}
+
+// The directional differences below all use the "same" output mode: the
+// result is rows x cols and element (r, c) is centered on input element
+// (r, c), with zero padding outside the matrix. Along the axes that is Dx
+// and Dy with their padding cropped (see ops::crop_dx_padding and
+// crop_dy_padding), so all four directions line up element for element.
+// Each weighs the neighbour behind its direction positively, as Dx does the
+// left one: step (dr, dc) gives arr[r - dr][c - dc] - arr[r + dr][c + dc].
+const SAME_DX: (isize, isize) = (0, 1);
+const SAME_DY: (isize, isize) = (1, 0);
+const SAME_D45: (isize, isize) = (-1, 1);
+const SAME_D135: (isize, isize) = (-1, -1);
+
+// The difference along the 45 degree diagonal, towards the upper right: the
+// 3x3 stencil [[0, 0, -1], [0, 0, 0], [1, 0, 0]], i.e.
+// arr[r + 1][c - 1] - arr[r - 1][c + 1], as a rows x cols matrix. The two
+// neighbours are sqrt(2) further apart than those of Dx and Dy; the
+// responses are not rescaled for it.
+pub fn compute_d45(arr: &[u8], rows: usize, cols: usize) -> Matrix<i16> {
+    return same_difference(arr, rows, cols, SAME_D45);
+}
+
+// The difference along the 135 degree diagonal, towards the upper left: the
+// stencil [[-1, 0, 0], [0, 0, 0], [0, 0, 1]], i.e.
+// arr[r + 1][c + 1] - arr[r - 1][c - 1], as a rows x cols matrix.
+pub fn compute_d135(arr: &[u8], rows: usize, cols: usize) -> Matrix<i16> {
+    return same_difference(arr, rows, cols, SAME_D135);
+}
+
+// The largest absolute response of every element over the four directions,
+// Dx, Dy, D45 and D135 in the "same" mode, as a rows x cols matrix. Edges at
+// any multiple of 45 degrees give their full contrast, up to 255.
+pub fn max_directional_gradient(arr: &[u8], rows: usize, cols: usize) -> Matrix<u16> {
+    let mut data: Vec<u16> = vec![0; rows * cols];
+
+    for step in [SAME_DX, SAME_DY, SAME_D45, SAME_D135] {
+        for (out, value) in data.iter_mut().zip(same_difference(arr, rows, cols, step).data) {
+            *out = (*out).max(value.unsigned_abs());
+        }
+    }
+
+    return Matrix { data, rows, cols, layout: Layout::RowMajor };
+}
+
+// arr[r - dr][c - dc] - arr[r + dr][c + dc] for every element, 0 outside.
+fn same_difference(arr: &[u8], rows: usize, cols: usize, (dr, dc): (isize, isize)) -> Matrix<i16> {
+    check_len(arr.len(), rows, cols).expect("Matrix has unexpected dimensions");
+
+    let at = |row: isize, col: isize| -> i16 {
+        if row < 0 || col < 0 || row >= rows as isize || col >= cols as isize {
+            return 0;
+        }
+
+        return arr[flat(row as usize, col as usize, cols)] as i16;
+    };
+
+    let mut data: Vec<i16> = vec![0; rows * cols];
+
+    for (index, out) in data.iter_mut().enumerate() {
+        let (row, col): (isize, isize) = ((index / cols) as isize, (index % cols) as isize);
+        *out = at(row - dr, col - dc) - at(row + dr, col + dc);
+    }
+
+    return Matrix { data, rows, cols, layout: Layout::RowMajor };
+}
+
+// Time spent in each phase of one compute_dx or compute_dy run: allocating
+// the zeroed output, the padded border and the interior. The allocation is
+// usually cheap even for large outputs because the zeroed pages are only
+// mapped when first written, which is then counted in the other phases.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KernelTimings {
+    pub alloc: Duration,
+    pub border: Duration,
+    pub interior: Duration,
+}
+
+impl KernelTimings {
+    pub fn total(&self) -> Duration {
+        return self.alloc + self.border + self.interior;
+    }
+}
+
+// compute_dx with each phase timed, for profiling. The result is identical to
+// compute_dx; the unprofiled kernels take no timestamps.
+pub fn compute_dx_profiled(arr: &[u8], rows: usize, cols: usize) -> Result<(Vec<i16>, KernelTimings), DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    let mut timings: KernelTimings = KernelTimings::default();
+    let start: Instant = Instant::now();
+    let mut dx: Vec<i16> = vec![0; dx_len(rows, cols)?];
+    timings.alloc = start.elapsed();
+
+    // Without columns the output is all padding, already zero.
+    if cols > 0 {
+        let ranges: BorderRanges = border_ranges(cols, 3, Padding::Full);
+
+        let start: Instant = Instant::now();
+        dx_border::<Fast>(arr, rows, cols, cols, &ranges, &mut dx);
+        timings.border = start.elapsed();
+
+        let start: Instant = Instant::now();
+        dx_interior::<Fast>(arr, cols, cols, &ranges, &mut dx);
+        timings.interior = start.elapsed();
+    }
+
+    return Ok((dx, timings));
+}
+
+// compute_dy with each phase timed, see compute_dx_profiled. Wide matrices
+// are processed in the same panels as compute_dy, with the phase times summed
+// over all panels.
+pub fn compute_dy_profiled(arr: &[u8], rows: usize, cols: usize) -> Result<(Vec<i16>, KernelTimings), DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    let mut timings: KernelTimings = KernelTimings::default();
+    let start: Instant = Instant::now();
+    let mut dy: Vec<i16> = vec![0; dy_len(rows, cols)?];
+    timings.alloc = start.elapsed();
+
+    if rows > 0 {
+        let block_cols: usize = dy_block_cols(cols).max(1);
+        let ranges: BorderRanges = border_ranges(rows, 3, Padding::Full);
+
+        for c0 in (0..cols).step_by(block_cols) {
+            let c1: usize = (c0 + block_cols).min(cols);
+
+            let start: Instant = Instant::now();
+            dy_border::<Fast>(arr, rows, cols, cols, c0, c1, &ranges, &mut dy);
+            timings.border += start.elapsed();
+
+            let start: Instant = Instant::now();
+            dy_inner::<Fast>(arr, cols, cols, c0, c1, 0, ranges.interior.len(), &mut dy);
+            timings.interior += start.elapsed();
+        }
+    }
+
+    return Ok((dy, timings));
+}
+
+// Number of elements handled per iteration of diff_into's main loop.
+const DIFF_LANES: usize = 16;
+
+// Writes a[i] - b[i] into out[i]. The slices must have equal lengths. The
+// main loop works on fixed-size chunks, which the compiler vectorizes, and
+// the remainder is handled by a scalar tail.
+fn diff_into(out: &mut [i16], a: &[u8], b: &[u8]) {
+    let mut out_chunks = out.chunks_exact_mut(DIFF_LANES);
+    let mut a_chunks = a.chunks_exact(DIFF_LANES);
+    let mut b_chunks = b.chunks_exact(DIFF_LANES);
+
+    for ((out_chunk, a_chunk), b_chunk) in (&mut out_chunks).zip(&mut a_chunks).zip(&mut b_chunks) {
+        for ((o, x), y) in out_chunk.iter_mut().zip(a_chunk).zip(b_chunk) {
+            *o = *x as i16 - *y as i16;
+        }
+    }
+
+    for ((o, x), y) in out_chunks.into_remainder().iter_mut().zip(a_chunks.remainder()).zip(b_chunks.remainder()) {
+        *o = *x as i16 - *y as i16;
+    }
+}
+
+// Layout-aware compute_dx. The result is stored in the same layout as the
+// input. A col-major rows x cols buffer is the row-major buffer of the
+// transposed matrix, and Dx of a matrix is Dy of its transpose, so col-major
+// inputs are handled by compute_dy on the same buffer. This needs no extra
+// copy and costs the same as the row-major case.
+pub fn compute_dx_matrix(arr: &Matrix<u8>) -> Matrix<i16> {
+    let data: Vec<i16> = match arr.layout {
+        Layout::RowMajor => compute_dx(&arr.data, arr.rows, arr.cols).data,
+        Layout::ColMajor => compute_dy(&arr.data, arr.cols, arr.rows).data,
+    };
+
+    return Matrix { data, rows: arr.rows, cols: arr.cols + 2, layout: arr.layout };
+}
+
+// Layout-aware compute_dy, see compute_dx_matrix.
+pub fn compute_dy_matrix(arr: &Matrix<u8>) -> Matrix<i16> {
+    let data: Vec<i16> = match arr.layout {
+        Layout::RowMajor => compute_dy(&arr.data, arr.rows, arr.cols).data,
+        Layout::ColMajor => compute_dx(&arr.data, arr.cols, arr.rows).data,
+    };
+
+    return Matrix { data, rows: arr.rows + 2, cols: arr.cols, layout: arr.layout };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::construct_randomized_matrix_seeded;
+
+    #[test]
+    fn col_major_kernels_match_row_major() {
+        for (rows, cols) in [(1, 1), (1, 6), (6, 1), (4, 9), (9, 4), (16, 16)] {
+            let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, 7);
+            let row_major: Matrix<u8> = Matrix::new(arr, rows, cols, Layout::RowMajor).unwrap();
+            let col_major: Matrix<u8> = row_major.to_layout(Layout::ColMajor);
+
+            let (dx, dy) = (compute_dx_matrix(&col_major), compute_dy_matrix(&col_major));
+            assert_eq!(dx.layout, Layout::ColMajor);
+            assert_eq!(dx.to_layout(Layout::RowMajor), compute_dx_matrix(&row_major));
+            assert_eq!(dy.to_layout(Layout::RowMajor), compute_dy_matrix(&row_major));
+            assert_eq!(compute_dx_matrix(&row_major).data, compute_dx(&row_major.data, rows, cols).data);
+        }
+    }
+
+    // In release builds (3 - 1) * 2^63 + 1 used to wrap to 1, which let the
+    // unsafe-fast loops read element 2^63 of a one element buffer.
+    #[test]
+    fn strided_kernels_reject_an_overflowing_extent() {
+        let expected: DimError = DimError::TooLarge { rows: 3, cols: 1usize << 63 };
+
+        assert_eq!(compute_dx_strided(&[7u8], 3, 1, 1usize << 63), Err(expected.clone()));
+        assert_eq!(compute_dy_strided(&[7u8], 3, 1, 1usize << 63), Err(expected));
+    }
+
+    #[test]
+    fn kernels_reject_sizes_that_overflow() {
+        assert_eq!(compute_dx_safe(&[0; 4], 1 << 32, 1 << 32), Err(DimError::TooLarge { rows: 1 << 32, cols: 1 << 32 }));
+        assert!(compute_dy_strided(&[0; 4], 1 << 32, 1 << 32, 1 << 32).is_err());
+
+        // No input elements, but 2^63 + 1 rows of 2 padding columns to write.
+        let rows: usize = (1usize << 63) + 1;
+        assert_eq!(compute_dx_into(&[], rows, 0, &mut []), Err(DimError::TooLarge { rows, cols: 2 }));
+    }
+
+    #[test]
+    fn strided_kernels_match_a_compacted_copy() {
+        let (stride, height): (usize, usize) = (11, 9);
+        let buffer: Vec<u8> = construct_randomized_matrix_seeded(height, stride, 5);
+
+        // Every sub-rectangle starting at (2, 3), read in place.
+        for rows in 0..=height - 2 {
+            for cols in 0..=stride - 3 {
+                let start: usize = flat(2, 3, stride);
+                let view: &[u8] = &buffer[start..start + if rows == 0 { 0 } else { (rows - 1) * stride + cols }];
+                let compact: Vec<u8> = (0..rows * cols).map(|i| buffer[start + flat(i / cols, i % cols, stride)]).collect();
+
+                assert_eq!(compute_dx_strided(view, rows, cols, stride).unwrap(), compute_dx(&compact, rows, cols).data);
+                assert_eq!(compute_dy_strided(view, rows, cols, stride).unwrap(), compute_dy(&compact, rows, cols).data);
+            }
+        }
+    }
+
+    #[test]
+    fn strided_kernels_check_the_buffer() {
+        assert_eq!(compute_dx_strided(&[0; 9], 2, 5, 4), Err(DimError::Mismatch { what: "stride", expected: 5, found: 4 }));
+        assert_eq!(compute_dx_strided(&[0; 9], 2, 5, 5), Err(DimError::BufferTooShort { needed: 10, found: 9 }));
+        assert_eq!(compute_dy_strided(&[0; 9], 2, 5, 5), Err(DimError::BufferTooShort { needed: 10, found: 9 }));
+    }
+
+    #[cfg(feature = "unsafe-fast")]
+    #[test]
+    fn unchecked_kernels_match_the_checked_ones() {
+        for (rows, cols) in [(0, 0), (1, 1), (3, 1), (1, 3), (7, 13), (40, 300)] {
+            let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, 9);
+
+            assert_eq!(compute_dx_unchecked(&arr, rows, cols), compute_dx_safe(&arr, rows, cols));
+            assert_eq!(compute_dy_unchecked(&arr, rows, cols), compute_dy_safe(&arr, rows, cols));
+        }
+
+        assert_eq!(compute_dx_unchecked(&[0; 4], 1 << 32, 1 << 32), Err(DimError::TooLarge { rows: 1 << 32, cols: 1 << 32 }));
+        assert_eq!(compute_dy_unchecked(&[0; 4], 1 << 32, 1 << 32), Err(DimError::TooLarge { rows: 1 << 32, cols: 1 << 32 }));
+    }
+
+    // Both builds: the threaded kernels, or their serial fallbacks, must give
+    // compute_dx and compute_dy for any thread count.
+    #[test]
+    fn par_kernels_match_the_serial_ones() {
+        for (rows, cols) in [(0, 5), (1, 1), (2, 7), (3, 1), (17, 23), (100, 64)] {
+            let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, 11);
+
+            for threads in [1, 2, 3, 8, 200] {
+                assert_eq!(compute_dx_par(&arr, rows, cols, threads).unwrap(), compute_dx(&arr, rows, cols).data, "Dx {}x{} {} threads", rows, cols, threads);
+                assert_eq!(compute_dy_par(&arr, rows, cols, threads).unwrap(), compute_dy(&arr, rows, cols).data, "Dy {}x{} {} threads", rows, cols, threads);
+            }
+        }
+
+        assert_eq!(compute_dx_par(&[1, 2, 3], 2, 2, 4), Err(DimError::LengthMismatch { expected: 4, found: 3 }));
+        assert_eq!(compute_dy_par(&[1, 2, 3], 2, 2, 4), Err(DimError::LengthMismatch { expected: 4, found: 3 }));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn par_kernels_start_every_band_on_its_own_thread() {
+        use std::sync::Mutex;
+
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(10, 4, 3);
+        let started: Mutex<Vec<(usize, thread::ThreadId)>> = Mutex::new(Vec::new());
+        let record = |band: usize| started.lock().unwrap().push((band, thread::current().id()));
+
+        compute_dx_par_with(&arr, 10, 4, 3, record).unwrap();
+        let mut bands: Vec<(usize, thread::ThreadId)> = std::mem::take(&mut *started.lock().unwrap());
+        bands.sort_by_key(|(band, _)| *band);
+
+        assert_eq!(bands.iter().map(|(band, _)| *band).collect::<Vec<usize>>(), vec![0, 1, 2]);
+        assert!(bands.iter().all(|(_, id)| *id != thread::current().id()));
+
+        // Dy splits the 8 inner rows into bands of rows.div_ceil(3) = 4.
+        compute_dy_par_with(&arr, 10, 4, 3, record).unwrap();
+        assert_eq!(started.lock().unwrap().len(), 2);
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    #[test]
+    fn serial_fallback_never_starts_a_worker() {
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(10, 4, 3);
+        let on_start = |band: usize| panic!("band {} started without the parallel feature", band);
+
+        assert_eq!(compute_dx_par_with(&arr, 10, 4, 3, on_start).unwrap(), compute_dx(&arr, 10, 4).data);
+        assert_eq!(compute_dy_par_with(&arr, 10, 4, 3, on_start).unwrap(), compute_dy(&arr, 10, 4).data);
+    }
+
+    #[test]
+    fn second_differences_of_a_quadratic_ramp_are_constant() {
+        // c * c along the axis; its second difference is 2 wherever the
+        // kernel does not reach the padding.
+        for len in [3, 4, 9, 15] {
+            let row: Vec<u8> = (0..len).map(|c| (c * c) as u8).collect();
+            let dxx: Matrix<i16> = compute_dxx(&row.repeat(3), 3, len).unwrap();
+            let dyy: Matrix<i16> = compute_dyy(&row.iter().flat_map(|&value| [value; 2]).collect::<Vec<u8>>(), len, 2).unwrap();
+
+            assert_eq!((dxx.rows, dxx.cols, dyy.rows, dyy.cols), (3, len + 2, len + 2, 2));
+            for out in dxx.data.chunks_exact(len + 2) {
+                assert!(out[2..len].iter().all(|&value| value == 2), "{:?}", out);
+                // The border is the zero padded convolution.
+                assert_eq!(out[..2], [0, 1]);
+            }
+            assert!(dyy.data[4..2 * len].iter().all(|&value| value == 2), "{:?}", dyy.data);
+        }
+    }
+
+    #[test]
+    fn second_differences_report_bad_dimensions() {
+        assert_eq!(compute_dxx(&[0; 5], 2, 3), Err(DimError::LengthMismatch { expected: 6, found: 5 }));
+        assert_eq!(compute_dyy(&[0; 7], 2, 3), Err(DimError::LengthMismatch { expected: 6, found: 7 }));
+
+        // No input elements, but 2^63 + 1 rows of 2 padding columns to write.
+        let rows: usize = (1usize << 63) + 1;
+        assert_eq!(compute_dxx(&[], rows, 0), Err(DimError::TooLarge { rows, cols: 2 }));
+    }
+
+    #[test]
+    fn mapped_kernels_match_the_view_copied_out() {
+        for (rows, cols) in [(1, 1), (2, 5), (9, 7)] {
+            let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, 6);
+            let row_map: Vec<usize> = (0..rows).map(|row| (row * 5 + 3) % rows).collect();
+            let view: Vec<u8> = row_map.iter().flat_map(|&row| arr[row_range(row, cols)].to_vec()).collect();
+
+            assert_eq!(compute_dx_mapped(&arr, rows, cols, &row_map).unwrap(), compute_dx(&view, rows, cols).data);
+            assert_eq!(compute_dy_mapped(&arr, rows, cols, &row_map).unwrap(), compute_dy(&view, rows, cols).data);
+        }
+
+        assert_eq!(compute_dy_mapped(&[0; 6], 2, 3, &[0]), Err(DimError::Mismatch { what: "row map length", expected: 2, found: 1 }));
+        assert!(matches!(compute_dy_mapped(&[0; 6], 2, 3, &[0, 2]), Err(DimError::OutOfRange { row: 2, .. })));
+    }
+
+    // Sizes the band and region tests cut up, including single rows and
+    // columns and widths the Dy column blocking splits.
+    const BAND_SIZES: [(usize, usize); 6] = [(1, 1), (1, 7), (6, 1), (5, 9), (17, 23), (40, 300)];
+
+    #[test]
+    fn row_bands_equal_the_rows_of_the_full_kernels() {
+        for (rows, cols) in BAND_SIZES {
+            let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, (rows * cols) as u64);
+            let (dx, dy): (Vec<i16>, Vec<i16>) = (compute_dx(&arr, rows, cols).data, compute_dy(&arr, rows, cols).data);
+
+            for start in 0..=rows {
+                for count in 0..=rows - start {
+                    let band_dx: Matrix<i16> = compute_dx_rows(&arr, rows, cols, start, count).unwrap();
+                    let band_dy: Matrix<i16> = compute_dy_rows(&arr, rows, cols, start, count).unwrap();
+
+                    assert_eq!((band_dx.rows, band_dx.cols), (count, cols + 2));
+                    assert_eq!(band_dx.data, dx[flat(start, 0, cols + 2)..flat(start + count, 0, cols + 2)], "Dx rows {}..{} of {}x{}", start, start + count, rows, cols);
+                    assert_eq!((band_dy.rows, band_dy.cols), (count + 2, cols));
+                    assert_eq!(band_dy.data, dy[flat(start, 0, cols)..flat(start + count + 2, 0, cols)], "Dy rows {}..{} of {}x{}", start, start + count, rows, cols);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn row_bands_must_lie_inside_the_matrix() {
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(4, 5, 1);
+
+        assert_eq!(compute_dx_rows(&arr, 4, 5, 3, 2), Err(DimError::OutOfRange { rows: 4, cols: 5, row: 3, col: 0, height: 2, width: 5 }));
+        assert!(compute_dy_rows(&arr, 4, 5, 5, 0).is_err());
+        assert!(compute_dx_rows(&arr, 4, 4, 0, 1).is_err());
+    }
+
+    #[test]
+    fn regions_equal_the_slice_of_the_full_kernels() {
+        for (rows, cols) in BAND_SIZES {
+            let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, (rows + cols) as u64);
+            let (dx, dy): (Vec<i16>, Vec<i16>) = (compute_dx(&arr, rows, cols).data, compute_dy(&arr, rows, cols).data);
+            // Every start and length along the short axes, a sample of them
+            // along the longer ones.
+            let spans = |len: usize| -> Vec<(usize, usize)> {
+                let step: usize = len / 10 + 1;
+                return (0..=len).step_by(step).flat_map(|start| (0..=len - start).step_by(step).map(move |count| (start, count))).collect();
+            };
+
+            for (row, height) in spans(rows) {
+                for (col, width) in spans(cols) {
+                    let region: Region = Region { row, col, rows: height, cols: width };
+                    let region_dx: Matrix<i16> = compute_dx_region(&arr, rows, cols, region).unwrap();
+                    let region_dy: Matrix<i16> = compute_dy_region(&arr, rows, cols, region).unwrap();
+                    let expected_dx: Vec<i16> = (row..row + height).flat_map(|r| dx[flat(r, col, cols + 2)..flat(r, col + width + 2, cols + 2)].to_vec()).collect();
+                    let expected_dy: Vec<i16> = (row..row + height + 2).flat_map(|r| dy[flat(r, col, cols)..flat(r, col + width, cols)].to_vec()).collect();
+
+                    assert_eq!((region_dx.rows, region_dx.cols, region_dx.data), (height, width + 2, expected_dx), "Dx {:?} of {}x{}", region, rows, cols);
+                    assert_eq!((region_dy.rows, region_dy.cols, region_dy.data), (height + 2, width, expected_dy), "Dy {:?} of {}x{}", region, rows, cols);
+                }
+            }
+
+            // The column bands are the full-height regions.
+            for (col, width) in spans(cols) {
+                let region: Region = Region { row: 0, col, rows, cols: width };
+
+                assert_eq!(compute_dx_cols(&arr, rows, cols, col, width).unwrap(), compute_dx_region(&arr, rows, cols, region).unwrap());
+                assert_eq!(compute_dy_cols(&arr, rows, cols, col, width).unwrap(), compute_dy_region(&arr, rows, cols, region).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn regions_must_lie_inside_the_matrix() {
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(4, 5, 1);
+
+        assert!(compute_dx_cols(&arr, 4, 5, 4, 2).is_err());
+        assert!(compute_dy_cols(&arr, 4, 5, 6, 0).is_err());
+        assert!(compute_dx_region(&arr, 4, 5, Region { row: 1, col: 1, rows: 4, cols: 1 }).is_err());
+        assert!(compute_dy_region(&arr, 4, 5, Region { row: 0, col: 0, rows: 0, cols: 6 }).is_err());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn results_carry_their_padded_shapes() {
+        for (rows, cols) in [(0, 0), (0, 4), (4, 0), (1, 1), (3, 5), (6, 2)] {
+            let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, 21);
+            let (dx, dy): (Matrix<i16>, Matrix<i16>) = (compute_dx(&arr, rows, cols), compute_dy(&arr, rows, cols));
+
+            assert_eq!((dx.rows, dx.cols, dx.layout, dx.data.len()), (rows, cols + 2, Layout::RowMajor, rows * (cols + 2)), "{}x{}", rows, cols);
+            assert_eq!((dy.rows, dy.cols, dy.layout, dy.data.len()), (rows + 2, cols, Layout::RowMajor, (rows + 2) * cols), "{}x{}", rows, cols);
+
+            // The deprecated wrappers return the same data without the shape.
+            assert_eq!(compute_dx_vec(&arr, rows, cols), dx.data);
+            assert_eq!(compute_dy_vec(&arr, rows, cols), dy.data);
+
+            // Cropping the padding leaves rows x cols in both.
+            assert_eq!(crate::ops::crop_dx_padding(&dx.data, rows, cols).unwrap().len(), rows * cols);
+            assert_eq!(crate::ops::crop_dy_padding(&dy.data, rows, cols).unwrap().len(), rows * cols);
+
+            // Either layout reports the logical shape.
+            for layout in [Layout::RowMajor, Layout::ColMajor] {
+                let matrix: Matrix<u8> = Matrix::new(arr.clone(), rows, cols, Layout::RowMajor).unwrap().to_layout(layout);
+                let (dx, dy): (Matrix<i16>, Matrix<i16>) = (compute_dx_matrix(&matrix), compute_dy_matrix(&matrix));
+
+                assert_eq!((dx.rows, dx.cols, dx.layout), (rows, cols + 2, layout));
+                assert_eq!((dy.rows, dy.cols, dy.layout), (rows + 2, cols, layout));
+            }
+        }
+    }
+
+    #[test]
+    fn diagonal_operators_respond_to_a_rotated_ramp() {
+        // A ramp rising by 10 per step towards the lower right, rotated a
+        // quarter turn so that it rises towards the lower left.
+        let (rows, cols): (usize, usize) = (8, 11);
+        let ramp: Vec<u8> = (0..rows * cols).map(|index| (10 * (index / cols + index % cols)) as u8).collect();
+        let (rotated, new_rows, new_cols) = crate::ops::rotate90_cw(&ramp, rows, cols).unwrap();
+        let interior = |matrix: &Matrix<i16>| -> Vec<i16> {
+            return (1..new_rows - 1).flat_map(|row| (1..new_cols - 1).map(move |col| (row, col))).map(|(row, col)| matrix.data[flat(row, col, new_cols)]).collect();
+        };
+        let count: usize = (new_rows - 2) * (new_cols - 2);
+
+        // Off the zero padded border D45 sees the full 4 steps, D135 none,
+        // and Dx and Dy half as much each.
+        assert_eq!(interior(&compute_d45(&rotated, new_rows, new_cols)), vec![40; count]);
+        assert_eq!(interior(&compute_d135(&rotated, new_rows, new_cols)), vec![0; count]);
+        assert_eq!(interior(&same_difference(&rotated, new_rows, new_cols, SAME_DX)), vec![20; count]);
+        assert_eq!(interior(&same_difference(&rotated, new_rows, new_cols, SAME_DY)), vec![-20; count]);
+
+        let strongest: Matrix<u16> = max_directional_gradient(&rotated, new_rows, new_cols);
+        assert_eq!((strongest.rows, strongest.cols), (new_rows, new_cols));
+
+        for row in 1..new_rows - 1 {
+            for col in 1..new_cols - 1 {
+                assert_eq!(strongest.data[flat(row, col, new_cols)], 40);
+            }
+        }
+
+        // Unrotated, the ramp rises along the 135 degree diagonal instead.
+        let d135: Matrix<i16> = compute_d135(&ramp, rows, cols);
+        assert_eq!(d135.data[flat(3, 4, cols)], 40);
+        assert_eq!(compute_d45(&ramp, rows, cols).data[flat(3, 4, cols)], 0);
+    }
+
+    #[test]
+    fn a_quarter_turn_permutes_the_four_directions() {
+        // With rotate90_cw: Dx of the rotated matrix is -Dy of the original
+        // rotated, Dy is Dx, D45 is D135 and D135 is -D45, borders included.
+        let (rows, cols): (usize, usize) = (7, 12);
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, 19);
+        let (rotated, new_rows, new_cols) = crate::ops::rotate90_cw(&arr, rows, cols).unwrap();
+        let turn = |matrix: Matrix<i16>, sign: i16| -> Vec<i16> {
+            return crate::ops::rotate90_cw(&matrix.data, rows, cols).unwrap().0.iter().map(|value| sign * value).collect();
+        };
+        let rotated_step = |step: (isize, isize)| same_difference(&rotated, new_rows, new_cols, step).data;
+
+        assert_eq!(rotated_step(SAME_DX), turn(same_difference(&arr, rows, cols, SAME_DY), -1));
+        assert_eq!(rotated_step(SAME_DY), turn(same_difference(&arr, rows, cols, SAME_DX), 1));
+        assert_eq!(compute_d45(&rotated, new_rows, new_cols).data, turn(compute_d135(&arr, rows, cols), 1));
+        assert_eq!(compute_d135(&rotated, new_rows, new_cols).data, turn(compute_d45(&arr, rows, cols), -1));
+        assert_eq!(max_directional_gradient(&rotated, new_rows, new_cols).data,
+                   crate::ops::rotate90_cw(&max_directional_gradient(&arr, rows, cols).data, rows, cols).unwrap().0);
+        // In the "same" mode Dx and Dy are the cropped full ones.
+        assert_eq!(same_difference(&arr, rows, cols, SAME_DX).data, crate::ops::crop_dx_padding(&compute_dx(&arr, rows, cols).data, rows, cols).unwrap());
+    }
+
+    // arr[r][c - 1] - arr[r][c + 1] one element at a time, rows x (cols + 2)
+    // with output column j centered on input column j - 1, 0 outside.
+    fn naive_dx(arr: &[u8], rows: usize, cols: usize) -> Vec<i16> {
+        let at = |row: usize, col: isize| if (0..cols as isize).contains(&col) { arr[flat(row, col as usize, cols)] as i16 } else { 0 };
+
+        return (0..rows).flat_map(|row| (0..cols as isize + 2).map(move |col| at(row, col - 2) - at(row, col))).collect();
+    }
+
+    // naive_dx along the columns, (rows + 2) x cols.
+    fn naive_dy(arr: &[u8], rows: usize, cols: usize) -> Vec<i16> {
+        let at = |row: isize, col: usize| if (0..rows as isize).contains(&row) { arr[flat(row as usize, col, cols)] as i16 } else { 0 };
+
+        return (0..rows as isize + 2).flat_map(|row| (0..cols).map(move |col| at(row - 2, col) - at(row, col))).collect();
+    }
+
+    #[test]
+    fn every_implementation_matches_the_naive_reference() {
+        // A single element, matrices narrower and shorter than the kernel,
+        // the small sizes compute_dx specializes and shapes just past them.
+        for (seed, rows, cols) in [(1, 1, 1), (2, 1, 5), (3, 2, 3), (4, 16, 16), (5, 7, 33), (6, 40, 17), (7, 33, 70)] {
+            let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, seed);
+            let (dx, dy): (Vec<i16>, Vec<i16>) = (naive_dx(&arr, rows, cols), naive_dy(&arr, rows, cols));
+            let what: String = format!("{}x{}", rows, cols);
+
+            assert_eq!((compute_dx(&arr, rows, cols).data, compute_dy(&arr, rows, cols).data), (dx.clone(), dy.clone()), "default {}", what);
+            assert_eq!((compute_dx_safe(&arr, rows, cols).unwrap(), compute_dy_safe(&arr, rows, cols).unwrap()), (dx.clone(), dy.clone()), "safe {}", what);
+            #[cfg(feature = "unsafe-fast")]
+            assert_eq!((compute_dx_unchecked(&arr, rows, cols).unwrap(), compute_dy_unchecked(&arr, rows, cols).unwrap()), (dx.clone(), dy.clone()),
+                       "unchecked {}", what);
+            #[cfg(feature = "parallel")]
+            assert_eq!((compute_dx_par(&arr, rows, cols, 3).unwrap(), compute_dy_par(&arr, rows, cols, 3).unwrap()), (dx.clone(), dy.clone()),
+                       "parallel {}", what);
+
+            // Buffers holding garbage, which must be overwritten everywhere.
+            let (mut into_dx, mut into_dy): (Vec<i16>, Vec<i16>) = (vec![i16::MIN; dx.len()], vec![i16::MAX; dy.len()]);
+            compute_dxdy_into(&arr, rows, cols, &mut into_dx, &mut into_dy).unwrap();
+            assert_eq!((into_dx, into_dy), (dx.clone(), dy.clone()), "into {}", what);
+
+            // Embedded in a wider buffer, three filler columns per row.
+            let stride: usize = cols + 3;
+            let wide: Vec<u8> = (0..rows * stride).map(|index| if index % stride < cols { arr[flat(index / stride, index % stride, cols)] } else { u8::MAX }).collect();
+            assert_eq!((compute_dx_strided(&wide, rows, cols, stride).unwrap(), compute_dy_strided(&wide, rows, cols, stride).unwrap()),
+                       (dx.clone(), dy.clone()), "strided {}", what);
+
+            for block_cols in [1, 3, cols] {
+                assert_eq!(compute_dy_blocked(&arr, rows, cols, cols, block_cols).unwrap(), dy, "blocks of {} {}", block_cols, what);
+            }
+
+            let cancel: AtomicBool = AtomicBool::new(false);
+            assert_eq!((compute_dx_cancellable(&arr, rows, cols, &cancel).unwrap(), compute_dy_cancellable(&arr, rows, cols, &cancel).unwrap()),
+                       (dx.clone(), dy.clone()), "cancellable {}", what);
+            assert_eq!((compute_dx_profiled(&arr, rows, cols).unwrap().0, compute_dy_profiled(&arr, rows, cols).unwrap().0), (dx, dy),
+                       "profiled {}", what);
+        }
+    }
+
+    #[test]
+    fn a_diagonal_edge_is_seen_most_by_the_operator_across_it() {
+        // 0 on and below the main diagonal, 255 above. Away from the borders
+        // D45, across the edge, sees the step on four diagonals where Dx and
+        // Dy see it on two, and D135, along it, sees nothing.
+        const EDGE: usize = 32;
+        let edge: Vec<u8> = Matrix::from_fn(EDGE, EDGE, |row, col| if col > row { u8::MAX } else { 0 }).data;
+        let total = |data: &[i16]| -> i64 {
+            return (0..EDGE * EDGE).filter(|index| (2..EDGE - 2).contains(&(index / EDGE)) && (2..EDGE - 2).contains(&(index % EDGE)))
+                .map(|index| data[index].unsigned_abs() as i64).sum();
+        };
+
+        let dx: i64 = total(&crate::ops::crop_dx_padding(&compute_dx(&edge, EDGE, EDGE).data, EDGE, EDGE).unwrap());
+        let dy: i64 = total(&crate::ops::crop_dy_padding(&compute_dy(&edge, EDGE, EDGE).data, EDGE, EDGE).unwrap());
+        let d45: i64 = total(&compute_d45(&edge, EDGE, EDGE).data);
+
+        // The edge is symmetric about the diagonal, so Dx and Dy see the same.
+        assert_eq!(dx, dy);
+        assert!(d45 as f64 >= 1.9 * dx.max(dy) as f64, "|D45| {} |Dx| {} |Dy| {}", d45, dx, dy);
+        assert_eq!(total(&compute_d135(&edge, EDGE, EDGE).data), 0);
+    }
+}
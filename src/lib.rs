@@ -0,0 +1,66 @@
+// Library half of the matrix tool. The binary in main.rs only parses the
+// command line and reports results; every operation on matrices lives in the
+// modules below so it can be reused outside of the CLI.
+//
+// Matrices are stored as flat row-major slices together with their row and
+// column counts, i.e. element (row, col) lives at row * cols + col.
+
+#![allow(clippy::needless_return)]
+
+mod access;
+pub mod arith;
+pub mod banded;
+pub mod bits;
+#[cfg(feature = "blas")]
+pub mod blas;
+pub mod cancel;
+pub mod color;
+pub mod components;
+pub mod conv;
+pub mod convert;
+pub mod convert_dtype;
+pub mod error;
+pub mod evict;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod filters;
+pub mod fixed;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+pub mod gradient;
+pub mod histogram;
+pub mod index;
+pub mod integral;
+pub mod io;
+pub mod kernels;
+pub mod linalg;
+pub mod matmul;
+pub mod matrix;
+pub mod memstats;
+pub mod mtx;
+pub mod nibble;
+pub mod numa;
+pub mod ops;
+pub mod overflow;
+pub mod pipeline;
+pub mod pool;
+pub mod postprocess;
+pub mod print;
+pub mod pyramid;
+pub mod realtime;
+pub mod resize;
+pub mod rle;
+pub mod sparse;
+pub mod stages;
+pub mod stats;
+pub mod stream;
+pub mod template;
+pub mod throughput;
+pub mod tiles;
+pub mod timing;
+pub mod window;
+
+// Counts the allocations of the unit tests that measure heap usage.
+#[cfg(all(test, feature = "mem-stats"))]
+#[global_allocator]
+static ALLOCATOR: memstats::CountingAllocator = memstats::CountingAllocator;
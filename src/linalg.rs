@@ -0,0 +1,194 @@
+use std::error::Error;
+use std::fmt;
+use crate::error::{check_len, DimError};
+
+// Error returned by the LU decomposition: the matrix is singular, or a is not
+// n x n to begin with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SingularError {
+    Dim(DimError),
+    // No usable pivot was left for this column, so the matrix is singular
+    // (to within rounding).
+    Singular { column: usize },
+}
+
+impl fmt::Display for SingularError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            SingularError::Dim(err) => write!(f, "{}", err),
+            SingularError::Singular { column } => write!(f, "matrix is singular (no pivot in column {})", column),
+        };
+    }
+}
+
+impl Error for SingularError {}
+
+impl From<DimError> for SingularError {
+    fn from(err: DimError) -> SingularError {
+        return SingularError::Dim(err);
+    }
+}
+
+// Pivots smaller than this times the largest absolute entry count as zero.
+const PIVOT_TOLERANCE: f64 = 1e-12;
+
+// LU decomposition of the n x n matrix a with partial pivoting, PA = LU.
+//
+// Returns the factors packed into one n x n matrix, with the unit lower
+// triangle L below the diagonal and U on and above it, and the permutation,
+// where perm[i] is the row of a that ended up in row i.
+pub fn lu_decompose(a: &[f64], n: usize) -> Result<(Vec<f64>, Vec<usize>), SingularError> {
+    check_len(a.len(), n, n)?;
+
+    let mut lu: Vec<f64> = a.to_vec();
+    let mut perm: Vec<usize> = (0..n).collect();
+    let scale: f64 = a.iter().fold(0.0, |max: f64, value| max.max(value.abs()));
+
+    for col in 0..n {
+        // Pick the row with the largest entry in this column as the pivot
+        let mut pivot_row: usize = col;
+
+        for row in col + 1..n {
+            if lu[row * n + col].abs() > lu[pivot_row * n + col].abs() {
+                pivot_row = row;
+            }
+        }
+
+        let pivot: f64 = lu[pivot_row * n + col];
+
+        if pivot.abs() <= PIVOT_TOLERANCE * scale || pivot == 0.0 {
+            return Err(SingularError::Singular { column: col });
+        }
+
+        if pivot_row != col {
+            for k in 0..n {
+                lu.swap(col * n + k, pivot_row * n + k);
+            }
+            perm.swap(col, pivot_row);
+        }
+
+        // Eliminate the column below the pivot, storing the multipliers in L
+        for row in col + 1..n {
+            let factor: f64 = lu[row * n + col] / pivot;
+            lu[row * n + col] = factor;
+
+            for k in col + 1..n {
+                lu[row * n + k] -= factor * lu[col * n + k];
+            }
+        }
+    }
+
+    return Ok((lu, perm));
+}
+
+// Determinant of the n x n matrix a, computed from its LU decomposition as
+// the product of U's diagonal times the sign of the permutation. Singular
+// matrices have determinant 0.
+pub fn determinant(a: &[f64], n: usize) -> Result<f64, DimError> {
+    let (lu, perm) = match lu_decompose(a, n) {
+        Ok(decomposition) => decomposition,
+        Err(SingularError::Singular { .. }) => return Ok(0.0),
+        Err(SingularError::Dim(err)) => return Err(err),
+    };
+
+    let mut det: f64 = if permutation_is_odd(&perm) { -1.0 } else { 1.0 };
+
+    for i in 0..n {
+        det *= lu[i * n + i];
+    }
+
+    return Ok(det);
+}
+
+// Whether perm is an odd permutation, found by counting the cycles.
+fn permutation_is_odd(perm: &[usize]) -> bool {
+    let mut visited: Vec<bool> = vec![false; perm.len()];
+    let mut swaps: usize = 0;
+
+    for start in 0..perm.len() {
+        let mut i: usize = start;
+        let mut cycle_len: usize = 0;
+
+        while !visited[i] {
+            visited[i] = true;
+            i = perm[i];
+            cycle_len += 1;
+        }
+
+        swaps += cycle_len.saturating_sub(1);
+    }
+
+    return swaps % 2 == 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matmul::matmul;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha12Rng;
+
+    fn random(n: usize, seed: u64) -> Vec<f64> {
+        let mut rng: ChaCha12Rng = ChaCha12Rng::seed_from_u64(seed);
+
+        return (0..n * n).map(|_| rng.gen_range(-1.0..1.0)).collect();
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        return (a - b).abs() <= 1e-9 * a.abs().max(b.abs()).max(1.0);
+    }
+
+    #[test]
+    fn determinant_of_the_identity_is_one() {
+        for n in 1..8 {
+            let identity: Vec<f64> = (0..n * n).map(|i| if i / n == i % n { 1.0 } else { 0.0 }).collect();
+
+            assert_eq!(determinant(&identity, n), Ok(1.0));
+        }
+    }
+
+    #[test]
+    fn determinant_of_a_known_3x3() {
+        // 2(0 * 6 - 5 * 7) - 1(1 * 6 - 5 * 4) + 3(1 * 7 - 0 * 4) = -70 + 14 + 21.
+        let a: [f64; 9] = [2.0, 1.0, 3.0, 1.0, 0.0, 5.0, 4.0, 7.0, 6.0];
+        assert!(close(determinant(&a, 3).unwrap(), -35.0));
+
+        // Swapping two rows negates it.
+        let swapped: [f64; 9] = [1.0, 0.0, 5.0, 2.0, 1.0, 3.0, 4.0, 7.0, 6.0];
+        assert!(close(determinant(&swapped, 3).unwrap(), 35.0));
+    }
+
+    #[test]
+    fn determinant_is_multiplicative() {
+        for (seed, n) in [(0, 2), (1, 3), (2, 4), (3, 5), (4, 8)] {
+            let (a, b): (Vec<f64>, Vec<f64>) = (random(n, seed), random(n, seed + 100));
+            let ab: Vec<f64> = matmul(&a, n, n, &b, n, n).unwrap();
+
+            assert!(close(determinant(&ab, n).unwrap(), determinant(&a, n).unwrap() * determinant(&b, n).unwrap()), "n = {}", n);
+        }
+    }
+
+    #[test]
+    fn lu_decompose_reconstructs_the_permuted_matrix() {
+        let n: usize = 6;
+        let a: Vec<f64> = random(n, 7);
+        let (lu, perm) = lu_decompose(&a, n).unwrap();
+
+        for i in 0..n {
+            for j in 0..n {
+                let sum: f64 = (0..=i.min(j)).map(|k| if k == i { lu[k * n + j] } else { lu[i * n + k] * lu[k * n + j] }).sum();
+                assert!(close(sum, a[perm[i] * n + j]), "({}, {})", i, j);
+            }
+        }
+    }
+
+    #[test]
+    fn singular_matrices_are_an_error() {
+        let a: [f64; 9] = [1.0, 2.0, 3.0, 2.0, 4.0, 6.0, 1.0, 0.0, 1.0];
+
+        assert!(matches!(lu_decompose(&a, 3), Err(SingularError::Singular { .. })));
+        assert_eq!(lu_decompose(&[0.0; 4], 2), Err(SingularError::Singular { column: 0 }));
+        assert_eq!(determinant(&a, 3), Ok(0.0));
+        assert!(matches!(lu_decompose(&[1.0; 5], 2), Err(SingularError::Dim(_))));
+    }
+}
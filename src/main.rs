@@ -2,186 +2,1638 @@
 // Description: For your convenience, matrices can be printed
 //              out by uncommenting print statements.
 //
-//              Border condition assumption:
-//              To calculate convolutions, we apply a padding
-//              of size 2. Thus, when calculating the result
-//              of a convolution with [-1, 0, 1] applied
-//              horizontally, we use padding of size 2 on the
-//              left and right side of the randomized matrix.
+//              The kernels themselves live in the rmm library
+//              (see src/lib.rs); this binary only parses the
+//              command line and reports results.
+
+#![allow(clippy::needless_return)]
+
+mod artifact;
+mod budget;
+mod cache;
+mod cli;
+mod commands;
+mod config;
+mod input;
+mod repro;
+mod timeout;
 
 use std::env;
-use std::time::Instant;
-use rand;
+use std::hint::black_box;
+use std::fs;
+use std::mem::size_of;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use serde_json::{json, Value};
+use cli::{Backend, InputType, Operator, Options, Rolling};
+use input::ResolvedInput;
+use rmm::arith::{ArithPolicy, Widen};
+use rmm::color::{color, to_heatmap_rgb, ColorMap};
+use rmm::components::{component_stats, label_components, ComponentStats, Connectivity};
+use rmm::conv::{convolve_cols, convolve_cols_multi, convolve_cols_padded, convolve_cols_specialized, convolve_rows, convolve_rows_multi, convolve_rows_padded,
+                convolve_rows_specialized, ConvMode};
+use rmm::error::DimError;
+use rmm::evict::{self, touch, CacheState, Evictor};
+use rmm::convert::{normalize_u8, to_abs_u8};
+use rmm::convert_dtype::{convert_dtype, Element, OutputDType, Values};
+use rmm::filters::equalize_histogram;
+use rmm::fixed::{Fixed16, Fixed32};
+#[cfg(feature = "gpu")]
+use rmm::gpu::GpuContext;
+use rmm::gradient::{abs_gradient, harris_response, orientation_histogram, structure_tensor};
+use rmm::histogram::{value_histogram, write_hist, DEFAULT_HIST_BINS, HIST_MAX, HIST_MIN};
+use rmm::index::{row_range, rows_range};
+use rmm::integral::{adaptive_threshold, FOREGROUND};
+use rmm::io::{read_matrix, write_bin, write_csv, write_pgm, write_ppm, BinElement, StoredMatrix};
+#[cfg(feature = "unsafe-fast")]
+use rmm::kernels::{compute_dx_unchecked, compute_dy_unchecked};
+use rmm::kernels::{compute_dx_safe, compute_dy_safe};
+use rmm::kernels::{compute_d135, compute_d45, compute_dx, compute_dx_cancellable, compute_dx_into, compute_dx_matrix, compute_dx_par,
+                   compute_dx_par_with, compute_dx_profiled, compute_dx_region, compute_dxx, compute_dy, compute_dy_blocked, compute_dy_cancellable, compute_dy_into, compute_dy_matrix,
+                   compute_dy_par, compute_dy_par_with, compute_dy_profiled, compute_dy_region, compute_dyy, max_directional_gradient, KernelTimings, Region,
+                   SECOND_DIFFERENCE};
+#[cfg(feature = "mem-stats")]
+use rmm::memstats::CountingAllocator;
+use rmm::memstats::{self, MemStats};
+use rmm::matrix::{construct_randomized_matrix, construct_randomized_matrix_cancellable, construct_randomized_matrix_seeded,
+                  construct_randomized_matrix_seeded_bands, construct_randomized_matrix_seeded_par, Layout, Matrix};
+use rmm::nibble::{compute_dx_u4, compute_dy_u4, construct_randomized_u4_seeded, U4Matrix};
+use rmm::numa::{Placement, Topology};
+use rmm::ops::{crop, crop_dx_padding, crop_dy_padding, rotate90_cw};
+use rmm::overflow::{check_cols_overflow, check_rows_overflow, OverflowReport, DEFAULT_FINDING_LIMIT};
+use rmm::pool::BufferPool;
+use rmm::postprocess::{Identity, Lut256, PostProcess};
+use rmm::print::render_ascii;
+use rmm::pyramid::{build_pyramid, scale_report_with, GradientSummary, ScaleLevel};
+use rmm::resize::resize_bilinear;
+use rmm::rle::{rle_encode, write_rle, RleFormat, Run};
+use rmm::stages::{run_stages_with, Image, Stage};
+use rmm::stream::StreamWriter;
+use rmm::template::{match_dims, match_template, match_template_normalized};
+use rmm::tiles::{process_tiles, time_strips, StripTiming};
+use rmm::stats::{argmax, checksum, count_nonzero, fingerprint, count_zero_crossings_cols, count_zero_crossings_rows, error_metrics, get_max, get_min, get_sum,
+                 percentiles, top_k_abs_par, top_k_f32, Accumulator, ErrorMetrics, CHECKSUM_BASIS};
+use rmm::throughput::{dx_bytes_moved, dx_bytes_moved_u4, dy_bytes_moved, dy_bytes_moved_u4, Throughput};
+use rmm::window::{rolling_max_rows, rolling_min_rows};
+use rmm::timing::{measure, measure_pooled, measure_prepared, TimingConfig, TimingReport, DEFAULT_WINDOW};
+
+// Dx and Dy of one input matrix together with how long each took.
+struct Gradients {
+    // Labels of the two results, "Dx" and "Dy" or "Dxx" and "Dyy" for the
+    // second derivative.
+    names: [&'static str; 2],
+    // Their shapes depend on the kernel and on whether padding was cropped.
+    dx: Matrix<i16>,
+    dy: Matrix<i16>,
+    dx_timing: TimingReport,
+    dy_timing: TimingReport,
+    // Input elements and modelled bytes moved by each kernel pass.
+    elements: usize,
+    dx_bytes: usize,
+    dy_bytes: usize,
+    // |Dx| + |Dy| over the rows x cols input region, when requested.
+    magnitude_l1: Option<Vec<u16>>,
+    // Orientation histogram per cell, when requested.
+    hog: Option<Vec<Vec<f32>>>,
+    // The adaptive threshold of the L1 magnitude, rows x cols, when requested.
+    threshold: Option<Vec<u8>>,
+    // Every component of the threshold mask, with --components.
+    components: Option<Vec<ComponentStats>>,
+    // The --rolling extreme of |Dx| (shaped like Dx) or of the input, when
+    // requested.
+    rolling: Option<Matrix<u16>>,
+    // Strongest Harris corner responses as (response, row, col), when
+    // requested.
+    harris: Option<Vec<(f32, usize, usize)>>,
+    // Heap usage of the run so far, in builds with the mem-stats feature.
+    memory: Option<MemStats>,
+    // Elements of Dx and Dy that overflowed, with --check-overflow.
+    overflow: Option<[OverflowReport; 2]>,
+    // Per-phase times of the Dx and Dy kernels, with --profile-phases.
+    phases: Option<[KernelTimings; 2]>,
+    // Errors of Dx and Dy against the scalar reference, with --verify.
+    verify: Option<[ErrorMetrics; 2]>,
+    // Whether Dx and Dy came from --cache-dir, with the timings of the run
+    // that stored them, or were stored there.
+    cache: Option<cache::Outcome>,
+    // (state, Dx timing, Dy timing) for every --cache-state, the first being
+    // the main timings.
+    cache_timings: Vec<(CacheState, TimingReport, TimingReport)>,
+}
+
+// Counts allocations so a run can report the memory it needed.
+#[cfg(feature = "mem-stats")]
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// Percentiles printed for Dx and Dy unless --percentiles is given.
+const DEFAULT_PERCENTILES: [f64; 3] = [1.0, 50.0, 99.0];
+
+// Bins of the orientation histogram unless --hog-bins is given.
+const DEFAULT_HOG_BINS: usize = 9;
+
+// Harris corners listed unless --top-k is given.
+const DEFAULT_HARRIS_CORNERS: usize = 10;
+
+// Largest --components listed.
+const LARGEST_COMPONENTS: usize = 10;
+
+// Distribution the elements of a generated input are drawn from.
+pub(crate) const GENERATED_DISTRIBUTION: &str = "uniform u8";
+
+// Rows of a stream file produced and post-processed at a time.
+const STREAM_STRIP_ROWS: usize = 64;
+
+// Exit status of a run that violated --assert-min-max or --assert-checksum.
+const EXIT_ASSERTION_FAILED: i32 = 1;
 
 fn main() {
     let args: Vec<_> = env::args().collect();
 
-    if args.len() < 3 {
-        panic!("2 arguments required: rows columns");
+    match args.get(1).map(|arg| arg.as_str()) {
+        Some("matmul") => return commands::matmul::run(&args[2..]),
+        Some("bench") => return commands::bench::run(&args[2..]),
+        Some("det") => return commands::det::run(&args[2..]),
+        Some("batch") => return commands::batch::run(&args[2..]),
+        Some("diff") => return commands::diff::run(&args[2..]),
+        Some("diff-hist") => return commands::diff_hist::run(&args[2..]),
+        Some("golden") => return commands::golden::run(&args[2..]),
+        Some("fingerprint") => return commands::fingerprint::run(&args[2..]),
+        Some("inspect") => return commands::inspect::run(&args[2..]),
+        Some("verify-run") => return commands::verify_run::run(&args[2..]),
+        Some("self-test") => return commands::self_test::run(&args[2..]),
+        Some("soak") => return commands::soak::run(&args[2..]),
+        _ => {}
+    }
+
+    let mut options: Options = cli::parse_args(&args);
+
+    if let Some(repro) = &options.replay {
+        println!("Replaying {} (recorded by version {})", repro.path, repro.version);
+        repro::enforce(repro, &repro::mismatches(repro));
+    }
+
+    if let Some(limit) = options.timeout {
+        timeout::arm(limit);
+    }
+    if options.dtype == InputType::Q8_8 {
+        run_fixed(&options);
+        return;
+    }
+
+    if options.dtype == InputType::U4 {
+        run_u4(&options);
+        return;
+    }
+
+    // Several --size values are run one after another with the same flags,
+    // each labelled with its size.
+    if options.sizes.len() > 1 {
+        for (rows, cols) in options.sizes.clone() {
+            (options.rows, options.cols) = (Some(rows), Some(cols));
+            println!("=== Size {}x{} ===", rows, cols);
+            run(&options, &args);
+        }
+
+        return;
+    }
+
+    run(&options, &args);
+}
+
+// Generates or reads the input matrix and runs whatever options ask for on it.
+fn run(options: &Options, args: &[String]) {
+    if let Some(dir) = &options.output_dir {
+        artifact::check_target(Path::new(dir));
     }
 
-    let rows: usize = args[1].trim().parse().expect("Invalid rows argument");
-    let cols: usize = args[2].trim().parse().expect("Invalid cols argument");
+    // An artifact records its seed, and pinned workers generate their own
+    // rows of a seeded input, so one is picked if none was given. A matrix
+    // read from a file has none.
+    let seed: Option<u64> = match options.input {
+        Some(_) => None,
+        None => options.seed.or_else(|| (options.output_dir.is_some() || options.emit_repro.is_some() || placement(options).is_some()).then(rand::random)),
+    };
 
-    let arr: Vec<u8> = construct_randomized_matrix(rows, cols);
+    let Input { arr, rows, cols, stages } = build_input(options, seed, options.output_dir.is_some());
+
+    if let Some(repro) = &options.replay {
+        repro::enforce(repro, &repro::setting_mismatches(repro, &repro::resolved(options, seed, rows, cols)));
+    }
 
     // println!("=== Original matrix ===");
-    // print_2d_array_u8(&arr, rows, cols);
+    // rmm::print::print_2d_array_u8(&arr, rows, cols);
 
-    // compute Dy
-    let dy_start = Instant::now();
-    let dy: Vec<i16> = compute_dy(&arr, rows, cols);
-    let dy_duration = dy_start.elapsed();
-    // println!("=== Dy ===");
-    // print_2d_array_i16(&dy, rows + 2, cols);
+    if options.ascii_input {
+        println!("=== Input ===");
+        print!("{}", render_ascii(&arr, rows, cols, ascii_width(options)));
+    }
 
-    // Compute Dx
-    let dx_start = Instant::now();
-    let dx: Vec<i16> = compute_dx(&arr, rows, cols);
-    let dx_duration = dx_start.elapsed();
+    if options.compare_impls {
+        compare_impls(&arr, rows, cols);
+        return;
+    }
+
+    // Without a usable GPU the run continues on the CPU.
+    if options.backend == Backend::Gpu && run_gpu(&arr, rows, cols) {
+        return;
+    }
+
+    if let Some(kernels) = &options.kernels {
+        run_kernel_bank(&arr, rows, cols, kernels, options);
+        return;
+    }
+
+    if let Some(stages) = &options.pipeline {
+        run_pipeline(&arr, rows, cols, stages, options);
+        return;
+    }
+
+    if options.scale_report > 0 {
+        run_scale_report(&arr, rows, cols, options);
+        return;
+    }
+
+    if options.operator == Operator::Directional {
+        run_directional(&arr, rows, cols, options);
+        return;
+    }
+
+    if let Some(path) = &options.match_template {
+        run_match_template(&arr, rows, cols, Path::new(path), options);
+        return;
+    }
+
+    if let Some(strip_rows) = options.time_strips {
+        run_time_strips(&arr, rows, cols, strip_rows, options);
+        return;
+    }
+
+    if options.pyramid > 0 {
+        for (level, (level_arr, level_rows, level_cols)) in build_pyramid(&arr, rows, cols, options.pyramid).iter().enumerate() {
+            let gradients: Gradients = compute_gradients(level_arr, *level_rows, *level_cols, options);
+
+            println!("=== Level {} ({}x{}) ===", level, level_rows, level_cols);
+            print_results(&gradients, options);
+        }
+
+        return;
+    }
+
+    let gradients: Gradients = compute_gradients(&arr, rows, cols, options);
+    let input_fingerprint: u64 = fingerprint(&arr, rows, cols).expect("Input has unexpected dimensions");
+
+    // println!("=== Dy ===");
+    // rmm::print::print_2d_array_i16(&gradients.dy.data, gradients.dy.rows, gradients.dy.cols);
     // println!("=== Dx ===");
-    // print_2d_array_i16(&dx, rows, cols + 2);
+    // rmm::print::print_2d_array_i16(&gradients.dx.data, gradients.dx.rows, gradients.dx.cols);
+
+    write_outputs(&gradients, options).expect("Failed to write results");
+
+    if let Some(dir) = &options.output_dir {
+        let run: artifact::Run = artifact::Run { args, options, seed, input: &arr, rows, cols, fingerprint: input_fingerprint, stages: &stages };
+        artifact::write_artifact(Path::new(dir), &run, &gradients).expect("Failed to write run artifact");
+    }
 
     println!("=== Results ===");
-    println!("Dx min: {} max: {} duration: {:?}", get_min(&dx), get_max(&dx), dx_duration);
-    println!("Dy min: {} max: {} duration: {:?}", get_min(&dy), get_max(&dy), dy_duration);
+    println!("Input fingerprint: {:016x} ({})", input_fingerprint, describe_source(options, seed, rows, cols));
+
+    // Runs on the generic path say how they applied the kernel.
+    if options.kernel.is_some() || options.arith.is_some() || options.pad.is_some() || options.conv_mode.is_some() {
+        println!("Kernel: {:?} ({})", options.kernel.as_deref().unwrap_or(&[-1, 0, 1]), options.conv_mode.unwrap_or_default().name());
+    }
+    print_results(&gradients, options);
+
+    if options.ascii {
+        // Dx has as many rows and Dy as many columns as the input region,
+        // also for a band.
+        let (rows, cols): (usize, usize) = (gradients.dx.rows, gradients.dy.cols);
+        let magnitude: Vec<u16> = abs_gradient(&gradients.dx.data, &gradients.dy.data, rows, cols).expect("Dx and Dy have unexpected dimensions");
+
+        println!("=== L1 gradient magnitude ===");
+        print!("{}", render_ascii(&normalize_u8(&magnitude), rows, cols, ascii_width(options)));
+    }
+
+    if let Some(path) = &options.emit_repro {
+        let result: u64 = checksum(checksum(CHECKSUM_BASIS, &gradients.dx.data), &gradients.dy.data);
+
+        repro::write(path, args, repro::resolved(options, seed, rows, cols), result).unwrap_or_else(|err| panic!("Failed to write {}: {}", path, err));
+        println!("Repro written to {}; replay it with --repro {}", path, path);
+    }
+
+    check_assertions(&gradients, options);
 }
 
-// Constructs a matrix of specified dimensions with random non-negative values
-fn construct_randomized_matrix(rows: usize, cols: usize) -> Vec<u8> {
-    let mut arr: Vec<u8> = vec![0; rows * cols];
+// The matrix a run works on, and with tracing the steps that produced it.
+pub(crate) struct Input {
+    pub(crate) arr: Vec<u8>,
+    pub(crate) rows: usize,
+    pub(crate) cols: usize,
+    pub(crate) stages: Vec<artifact::Stage>,
+}
+
+// Reads --input or generates the input from seed, then applies --rotate-input,
+// --resize and --equalize. With trace, each of those steps is recorded with the
+// fingerprint of the matrix it produced, for run artifacts.
+pub(crate) fn build_input(options: &Options, seed: Option<u64>, trace: bool) -> Input {
+    let ResolvedInput { arr: loaded, mut rows, mut cols } = input::resolve(options).unwrap_or_else(|err| panic!("{}", err));
+
+    // Checked before the input is generated, or after it is read and before
+    // anything else.
+    budget::enforce(options, rows, cols);
+
+    let mut stages: Vec<artifact::Stage> = Vec::new();
+    let mut record = |name: &'static str, description: String, arr: &[u8], rows: usize, cols: usize| if trace {
+        let fingerprint: u64 = fingerprint(arr, rows, cols).expect("Input has unexpected dimensions");
+        stages.push(artifact::Stage { name, description, checksum: fingerprint });
+    };
+
+    let mut arr: Vec<u8> = match (loaded, timeout::token(), seed) {
+        (Some(data), _, _) => data,
+        (None, Some(cancel), seed) => timeout::or_exit(construct_randomized_matrix_cancellable(rows, cols, seed, cancel), "generation"),
+        // Seeded generation gives the same matrix on any number of threads.
+        (None, None, Some(seed)) => match (options.threads, placement(options)) {
+            (Some(threads), Some(placement)) => construct_randomized_matrix_seeded_bands(rows, cols, seed, threads, |worker| pin_worker(placement, worker)),
+            (Some(threads), None) => construct_randomized_matrix_seeded_par(rows, cols, seed, threads),
+            (None, _) => construct_randomized_matrix_seeded(rows, cols, seed),
+        },
+        (None, None, None) => construct_randomized_matrix(rows, cols),
+    };
+
+    let source: String = describe_source(options, seed, rows, cols);
+    record(if options.input.is_some() { "load" } else { "generate" }, source, &arr, rows, cols);
 
-    for row in 0..rows {
-        for col in 0..cols {
-            arr[row * cols + col] = rand::random();
+    // Rotations other than 90 degrees are repeated quarter turns.
+    if options.rotate_input > 0 {
+        for _ in 0..options.rotate_input / 90 {
+            (arr, rows, cols) = rotate90_cw(&arr, rows, cols).expect("Input has unexpected dimensions");
         }
+
+        record("rotate", format!("{} degrees clockwise", options.rotate_input), &arr, rows, cols);
+    }
+
+    if let Some((new_rows, new_cols)) = options.resize {
+        arr = resize_bilinear(&arr, rows, cols, new_rows, new_cols).expect("Input has unexpected dimensions");
+        (rows, cols) = (new_rows, new_cols);
+        record("resize", format!("bilinear to {}x{}", rows, cols), &arr, rows, cols);
     }
 
-    return arr;
+    if options.equalize {
+        arr = equalize_histogram(&arr, rows, cols).expect("Input has unexpected dimensions");
+        record("equalize", "histogram equalization".to_string(), &arr, rows, cols);
+    }
+
+    return Input { arr, rows, cols, stages };
 }
 
-// Calculates convolution of 2D matrix arr and [-1, 0, 1] (applied horizontally).
-// By applying horizontally, [-1, 0, 1] is treated as the 1x3 matrix
-// [[-1, 0, 1]].
-fn compute_dx(arr: &Vec<u8>, rows: usize, cols: usize) -> Vec<i16> {
-    let new_cols: usize = cols + 2;
-    let mut dx: Vec<i16> = vec![0; rows * new_cols];
+// Where --pin-threads puts the --threads workers, decided on first use. None,
+// after saying why once, when they run unpinned: on a single NUMA node
+// pinning would only constrain the scheduler, so the run is left as it is.
+fn placement(options: &Options) -> Option<&'static Placement> {
+    static PLACEMENT: OnceLock<Option<Placement>> = OnceLock::new();
 
-    // Compute values of first 2 and last 2 columns, where padding is used. For optimization, the 0
-    // of [-1, 0, 1] is ignored.
-    for row in 0..rows {
-        dx[row * new_cols + new_cols - 1] = arr[row * cols + cols - 1] as i16;
-        dx[row * new_cols] = -1 * arr[row * cols] as i16;
+    let threads: usize = options.threads.filter(|_| options.pin_threads)?;
 
-        if cols > 1 {
-            dx[row * new_cols + new_cols - 2] = arr[row * cols + cols - 2] as i16;
-            dx[row * new_cols + 1] = -1 * arr[row * cols + 1] as i16;
+    return PLACEMENT.get_or_init(|| {
+        if !cfg!(feature = "numa") {
+            eprintln!("--pin-threads: built without the numa feature, threads are not pinned");
+            return None;
         }
+
+        let topology: Topology = Topology::detect();
+
+        if !topology.is_numa() {
+            eprintln!("--pin-threads: single NUMA node, threads are not pinned");
+            return None;
+        }
+
+        let placement: Placement = Placement::new(&topology, threads);
+        eprintln!("--pin-threads: {} threads over {} NUMA nodes on CPUs {:?}", threads, topology.nodes.len(), placement.cpus);
+
+        return Some(placement);
+    }).as_ref();
+}
+
+// Pins the calling worker thread; one that cannot be pinned still runs.
+fn pin_worker(placement: &Placement, worker: usize) {
+    if let Err(err) = placement.pin(worker) {
+        eprintln!("--pin-threads: could not pin worker {}: {}", worker, err);
     }
+}
 
-    // Compute inner values of resulting matrix. Once again, for optimization, the 0 of [-1, 0, 1]
-    // is ignored.
-    if cols > 2 {
-        for row in 0..rows {
-            for col in 0..cols - 2 {
-                dx[row * new_cols + 2 + col] = arr[row * cols + col] as i16 - arr[row * cols + col + 2] as i16;
+// Checks the results against --assert-min-max and --assert-checksum. Every
+// violation is described on stderr before exiting with EXIT_ASSERTION_FAILED.
+fn check_assertions(gradients: &Gradients, options: &Options) {
+    let mut passed: Vec<String> = Vec::new();
+    let mut failures: Vec<String> = Vec::new();
+
+    for assertion in &options.assert_min_max {
+        let data: &[i16] = if assertion.output == "dx" { &gradients.dx.data } else { &gradients.dy.data };
+        let (min, max): (i64, i64) = (get_min(data) as i64, get_max(data) as i64);
+        let expected: String = format!("{}:{}:{}", assertion.output, assertion.min, assertion.max);
+
+        if min < assertion.min || max > assertion.max {
+            failures.push(format!("{}: expected min/max within {}..={}, observed {}..={}", expected, assertion.min, assertion.max, min, max));
+        } else {
+            passed.push(expected);
+        }
+    }
+
+    if let Some(expected) = options.assert_checksum {
+        let observed: u64 = checksum(checksum(CHECKSUM_BASIS, &gradients.dx.data), &gradients.dy.data);
+
+        if observed != expected {
+            failures.push(format!("checksum: expected {:016x}, observed {:016x}", expected, observed));
+        } else {
+            passed.push(format!("checksum {:016x}", observed));
+        }
+    }
+
+    if failures.is_empty() {
+        if !passed.is_empty() {
+            println!("Assertions passed: {}", passed.join(", "));
+        }
+
+        return;
+    }
+
+    for failure in &failures {
+        eprintln!("assertion failed: {}", failure);
+    }
+
+    process::exit(EXIT_ASSERTION_FAILED);
+}
+
+// How the input was produced, reported next to its fingerprint.
+fn describe_source(options: &Options, seed: Option<u64>, rows: usize, cols: usize) -> String {
+    let origin: String = match (&options.input, seed) {
+        (Some(path), _) => format!("read from {}", path),
+        (None, Some(seed)) => format!("seed {}, {}", seed, GENERATED_DISTRIBUTION),
+        (None, None) => format!("unseeded, {}", GENERATED_DISTRIBUTION),
+    };
+
+    return format!("{}x{}, {}", rows, cols, origin);
+}
+
+// Width of --ascii renderings: --ascii-width, else the terminal width from
+// $COLUMNS, else 80 characters.
+fn ascii_width(options: &Options) -> usize {
+    return options.ascii_width
+        .or_else(|| env::var("COLUMNS").ok().and_then(|columns| columns.trim().parse().ok()))
+        .unwrap_or(80);
+}
+
+// Writes Dx/Dy to the CSV and PGM directories requested on the command line,
+// mapped through the --lut table if there is one. PGM images are always u8,
+// so they hold |Dx| and |Dy| saturated to 255. The CSV, binary and stream
+// files hold the --output-dtype conversion.
+fn write_outputs(gradients: &Gradients, options: &Options) -> std::io::Result<()> {
+    let lut: Option<Lut256> = load_lut(options);
+    let hook: &dyn PostProcess = match &lut {
+        Some(lut) => lut,
+        None => &Identity,
+    };
+
+    for (label, output) in [(gradients.names[0], &gradients.dx), (gradients.names[1], &gradients.dy)] {
+        let name: String = label.to_lowercase();
+        let (rows, cols): (usize, usize) = (output.rows, output.cols);
+        let processed: Option<Vec<i16>> = lut.as_ref().map(|lut| {
+            let mut data: Vec<i16> = output.data.clone();
+            lut.apply(&mut data, rows, cols);
+            data
+        });
+        let data: &[i16] = processed.as_deref().unwrap_or(&output.data);
+        let values: Values = convert_dtype(data, options.output_dtype.unwrap_or_default());
+
+        if let Some(dir) = &options.output_csv {
+            fs::create_dir_all(dir)?;
+            let path: PathBuf = Path::new(dir).join(format!("{}.csv", name));
+
+            if options.u8_output {
+                write_csv(&path, &to_abs_u8(data), rows, cols)?;
+            } else {
+                write_csv_values(&path, &values, rows, cols)?;
             }
         }
+
+        if let Some(dir) = &options.output_pgm {
+            fs::create_dir_all(dir)?;
+            write_pgm(&Path::new(dir).join(format!("{}.pgm", name)), &to_abs_u8(data), rows, cols)?;
+        }
+
+        if let Some(dir) = &options.output_bin {
+            fs::create_dir_all(dir)?;
+            write_bin_values(&Path::new(dir).join(format!("{}.bin", name)), &values, rows, cols)?;
+        }
+
+        if let Some(dir) = &options.output_stream {
+            fs::create_dir_all(dir)?;
+            let path: PathBuf = Path::new(dir).join(format!("{}.rmms", name));
+            let raw: &[i16] = &output.data;
+
+            match options.output_dtype.unwrap_or_default() {
+                OutputDType::U8 => write_stream(&path, raw, rows, cols, hook, i16::to_u8)?,
+                OutputDType::I16 => write_stream(&path, raw, rows, cols, hook, |value| value)?,
+                OutputDType::I32 => write_stream(&path, raw, rows, cols, hook, i16::to_i32)?,
+                OutputDType::F32 => write_stream(&path, raw, rows, cols, hook, i16::to_f32)?,
+            }
+        }
+
+        // The distribution of the gradients themselves, before --lut.
+        if let Some(dir) = &options.save_hist {
+            fs::create_dir_all(dir)?;
+            let bins: usize = options.hist_bins.unwrap_or(DEFAULT_HIST_BINS);
+            write_hist(&Path::new(dir).join(format!("{}.hist", name)), &value_histogram(&output.data, HIST_MIN, HIST_MAX, bins))?;
+        }
+
+        if let Some(dir) = &options.output_heatmap {
+            fs::create_dir_all(dir)?;
+            let rgb: Vec<u8> = to_heatmap_rgb(data, rows, cols, ColorMap::Diverging).expect("Result has unexpected dimensions");
+            write_ppm(&Path::new(dir).join(format!("{}.ppm", name)), &rgb, rows, cols)?;
+        }
     }
 
-    return dx;
+    // The magnitude covers the input region, whichever shapes Dx/Dy have.
+    if let Some(dir) = &options.output_heatmap {
+        let (rows, cols) = (gradients.dx.rows, gradients.dy.cols);
+
+        if let Ok(magnitude) = abs_gradient(&gradients.dx.data, &gradients.dy.data, rows, cols) {
+            let magnitude: Vec<i16> = magnitude.iter().map(|value| (*value).min(i16::MAX as u16) as i16).collect();
+            let rgb: Vec<u8> = to_heatmap_rgb(&magnitude, rows, cols, ColorMap::Sequential).expect("Magnitude has unexpected dimensions");
+            write_ppm(&Path::new(dir).join("magnitude.ppm"), &rgb, rows, cols)?;
+        }
+    }
+
+    // --magnitude-l1 exports as Dx/Dy do; the PGM is normalized to 0..255.
+    if let Some(magnitude) = &gradients.magnitude_l1 {
+        let (rows, cols) = (gradients.dx.rows, gradients.dy.cols);
+
+        if let Some(dir) = &options.output_csv {
+            fs::create_dir_all(dir)?;
+            write_csv(&Path::new(dir).join("magnitude_l1.csv"), magnitude, rows, cols)?;
+        }
+
+        if let Some(dir) = &options.output_pgm {
+            fs::create_dir_all(dir)?;
+            write_pgm(&Path::new(dir).join("magnitude_l1.pgm"), &normalize_u8(magnitude), rows, cols)?;
+        }
+
+        if let Some(dir) = &options.output_bin {
+            fs::create_dir_all(dir)?;
+            write_bin(&Path::new(dir).join("magnitude_l1.bin"), magnitude, rows, cols)?;
+        }
+    }
+
+    if let Some(binary) = &gradients.threshold {
+        let (rows, cols) = (gradients.dx.rows, gradients.dy.cols);
+
+        if let Some(dir) = &options.output_csv {
+            write_csv(&Path::new(dir).join("threshold.csv"), binary, rows, cols)?;
+        }
+
+        if let Some(dir) = &options.output_pgm {
+            write_pgm(&Path::new(dir).join("threshold.pgm"), binary, rows, cols)?;
+        }
+
+        if let Some(dir) = &options.output_bin {
+            write_bin(&Path::new(dir).join("threshold.bin"), binary, rows, cols)?;
+        }
+
+        if let Some(dir) = &options.output_rle {
+            let runs: Vec<Run> = rle_encode(binary, rows, cols).unwrap_or_else(|err| panic!("Threshold RLE: {}", err));
+
+            fs::create_dir_all(dir)?;
+            write_rle(&Path::new(dir).join("threshold.rle"), &runs, rows, cols, RleFormat::Binary)?;
+            write_rle(&Path::new(dir).join("threshold.rle.txt"), &runs, rows, cols, RleFormat::Text)?;
+        }
+    }
+
+    if let Some(rolling) = &gradients.rolling {
+        if let Some(dir) = &options.output_csv {
+            write_csv(&Path::new(dir).join("rolling.csv"), &rolling.data, rolling.rows, rolling.cols)?;
+        }
+
+        if let Some(dir) = &options.output_bin {
+            write_bin(&Path::new(dir).join("rolling.bin"), &rolling.data, rolling.rows, rolling.cols)?;
+        }
+    }
+
+    // One line per cell, one column per bin.
+    if let (Some(dir), Some(cells)) = (&options.output_csv, &gradients.hog) {
+        let bins: usize = cells.first().map_or(0, |cell| cell.len());
+        write_csv(&Path::new(dir).join("hog.csv"), &cells.concat(), cells.len(), bins)?;
+    }
+
+    return Ok(());
 }
 
-// Calculates convolution of 2D matrix arr and [-1, 0, 1] (applied vertically).
-// By applying vertically, [-1, 0, 1] is treated as the 3x1 matrix
-// [[-1], [0], [1]].
-fn compute_dy(arr: &Vec<u8>, rows: usize, cols: usize) -> Vec<i16> {
-    let new_rows: usize = rows + 2;
-    let mut dy: Vec<i16> = vec![0; cols * new_rows];
+fn write_csv_values(path: &Path, values: &Values, rows: usize, cols: usize) -> std::io::Result<()> {
+    return match values {
+        Values::U8(data) => write_csv(path, data, rows, cols),
+        Values::I16(data) => write_csv(path, data, rows, cols),
+        Values::I32(data) => write_csv(path, data, rows, cols),
+        Values::F32(data) => write_csv(path, data, rows, cols),
+    };
+}
 
-    // Compute values of first 2 and last 2 rows, where padding is used. For optimization, the 0 of
-    // [-1, 0, 1] is ignored.
-    for col in 0..cols {
-        dy[cols * new_rows - col - 1] = arr[cols * rows - col - 1] as i16;
-        dy[col] = -1 * arr[col] as i16;
+pub(crate) fn write_bin_values(path: &Path, values: &Values, rows: usize, cols: usize) -> std::io::Result<()> {
+    return match values {
+        Values::U8(data) => write_bin(path, data, rows, cols),
+        Values::I16(data) => write_bin(path, data, rows, cols),
+        Values::I32(data) => write_bin(path, data, rows, cols),
+        Values::F32(data) => write_bin(path, data, rows, cols),
+    };
+}
 
-        if rows > 1 {
-            dy[cols * (new_rows - 1) - col - 1] = arr[cols * (rows - 1) - col - 1] as i16;
-            dy[cols + col] = -1 * arr[cols + col] as i16;
+// Writes data to a stream file a row at a time, as a producer would: every
+// STREAM_STRIP_ROWS rows are post-processed by hook as they come, then each
+// element is converted to T.
+fn write_stream<T: BinElement>(path: &Path, data: &[i16], rows: usize, cols: usize, hook: &dyn PostProcess,
+                               convert: impl Fn(i16) -> T) -> std::io::Result<()> {
+    let mut writer: StreamWriter<T> = StreamWriter::create(path, rows, cols)?;
+    let mut strip: Vec<i16> = Vec::with_capacity(STREAM_STRIP_ROWS * cols);
+    let mut row_out: Vec<T> = Vec::with_capacity(cols);
+
+    for first in (0..rows).step_by(STREAM_STRIP_ROWS) {
+        let count: usize = STREAM_STRIP_ROWS.min(rows - first);
+
+        strip.clear();
+        strip.extend_from_slice(&data[rows_range(first, first + count, cols)]);
+        hook.apply(&mut strip, count, cols);
+
+        for row in 0..count {
+            row_out.clear();
+            row_out.extend(strip[row_range(row, cols)].iter().map(|&value| convert(value)));
+            writer.write_row(&row_out)?;
         }
     }
 
-    // Compute inner values of resulting matrix. Once again, for optimization, the 0 of [-1, 0, 1]
-    // is ignored.
-    if rows > 2 {
-        for row in 0..rows - 2 {
-            for col in 0..cols {
-                dy[(row + 2) * cols + col] = arr[row * cols + col] as i16 - arr[(row + 2) * cols + col] as i16;
-            }
+    return writer.finish();
+}
+
+// Runs both kernels on arr, timing each, and applies the requested
+// post-processing to the results.
+pub(crate) fn compute_gradients(arr: &[u8], rows: usize, cols: usize, options: &Options) -> Gradients {
+    // The generic convolution is only used when a kernel, overflow policy,
+    // padding or mode is requested; the fixed [-1, 0, 1] kernels are the
+    // default. Built-in kernels given that way still take the const path.
+    let generic: bool = options.kernel.is_some() || options.arith.is_some() || options.pad.is_some() || options.conv_mode.is_some();
+    let second: bool = options.operator == Operator::SecondDerivative;
+    let kernel: &[i32] = if second { &SECOND_DIFFERENCE } else { options.kernel.as_deref().unwrap_or(&[-1, 0, 1]) };
+    let pad: usize = options.pad.unwrap_or(kernel.len() - 1);
+    let policy: ArithPolicy = options.arith.unwrap_or_default();
+    let mode: ConvMode = options.conv_mode.unwrap_or_default();
+    // What the checks below convolve with to get the same results.
+    let taps: Vec<i32> = mode.taps(kernel);
+
+    // Col-major runs convert the input up front and the results back to
+    // row-major afterwards, outside of the timed region.
+    let input: Option<Matrix<u8>> = match options.layout {
+        Layout::RowMajor => None,
+        Layout::ColMajor => Some(Matrix::new(arr.to_vec(), rows, cols, Layout::RowMajor)
+            .expect("Input has unexpected dimensions").to_layout(Layout::ColMajor)),
+    };
+
+    let timing: TimingConfig = timing_config(options);
+
+    // The plain kernels write into pooled buffers, so repeated timed runs do
+    // not allocate a fresh output each time.
+    // A band of rows or columns (or the rectangle where they cross) is
+    // computed as a matrix of its own size.
+    let region: Option<Region> = (options.rows_range.is_some() || options.cols_range.is_some()).then(|| {
+        let (row, row_end) = options.rows_range.unwrap_or((0, rows));
+        let (col, col_end) = options.cols_range.unwrap_or((0, cols));
+
+        if row_end > rows {
+            panic!("--rows-range {}..{} is outside the {}-row input", row, row_end, rows);
+        }
+
+        if col_end > cols {
+            panic!("--cols-range {}..{} is outside the {}-column input", col, col_end, cols);
+        }
+
+        Region { row, col, rows: row_end - row, cols: col_end - col }
+    });
+
+    // Shapes of the results as the kernels return them, before any cropping.
+    let (out_rows, out_cols): (usize, usize) = region.map_or((rows, cols), |region| (region.rows, region.cols));
+    let (dx_shape, dy_shape): ((usize, usize), (usize, usize)) = ((out_rows, out_cols + pad), (out_rows + pad, out_cols));
+
+    let entry: Option<cache::Entry> = options.cache_dir.as_ref().map(|dir| {
+        cache::Entry::new(Path::new(dir), fingerprint(arr, rows, cols).expect("Input has unexpected dimensions"), rows, cols, options)
+    });
+    let hit: Option<cache::Hit> = entry.as_ref().and_then(|entry| entry.load(dx_shape, dy_shape));
+    let cached: bool = hit.is_some();
+    let (cached_dx, cached_dy) = match hit {
+        Some(hit) => (Some((hit.dx, hit.dx_timing)), Some((hit.dy, hit.dy_timing))),
+        None => (None, None),
+    };
+
+    let plain: bool = !generic && !second && region.is_none() && input.is_none() && options.dy_block_cols.is_none() && options.threads.is_none()
+        && timeout::token().is_none();
+    let mut pool: BufferPool<i16> = BufferPool::default();
+
+    // The kernels are timed below with prepare run before each timed run, to
+    // put their input in the state --cache-state asks for.
+    let time_dy = |pool: &mut BufferPool<i16>, prepare: &mut dyn FnMut()| -> (Vec<i16>, TimingReport) {
+        if plain {
+            return measure_pooled(&timing, pool, (rows + 2) * cols, prepare, |out| {
+                compute_dy_into(arr, rows, cols, out).expect("Input has unexpected dimensions")
+            });
         }
+
+        return measure_prepared(&timing, prepare, || if generic {
+            convolve_cols_specialized(arr, rows, cols, kernel, pad, mode, policy).unwrap_or_else(|err| panic!("Dy: {}", err))
+        } else if second {
+            compute_dyy(arr, rows, cols).expect("Input has unexpected dimensions").data
+        } else if let Some(region) = region {
+            compute_dy_region(arr, rows, cols, region).expect("Region is inside the input").data
+        } else if let Some(matrix) = &input {
+            compute_dy_matrix(matrix).data
+        } else if let Some(block_cols) = options.dy_block_cols {
+            compute_dy_blocked(arr, rows, cols, cols, block_cols).expect("Input has unexpected dimensions")
+        } else if let Some(threads) = options.threads {
+            match placement(options) {
+                Some(placement) => compute_dy_par_with(arr, rows, cols, threads, |worker| pin_worker(placement, worker)),
+                None => compute_dy_par(arr, rows, cols, threads),
+            }.expect("Input has unexpected dimensions")
+        } else if let Some(cancel) = timeout::token() {
+            timeout::or_exit(compute_dy_cancellable(arr, rows, cols, cancel), "Dy")
+        } else {
+            compute_dy(arr, rows, cols).data
+        });
+    };
+
+    let time_dx = |pool: &mut BufferPool<i16>, prepare: &mut dyn FnMut()| -> (Vec<i16>, TimingReport) {
+        if plain {
+            return measure_pooled(&timing, pool, rows * (cols + 2), prepare, |out| {
+                compute_dx_into(arr, rows, cols, out).expect("Input has unexpected dimensions")
+            });
+        }
+
+        return measure_prepared(&timing, prepare, || if generic {
+            convolve_rows_specialized(arr, rows, cols, kernel, pad, mode, policy).unwrap_or_else(|err| panic!("Dx: {}", err))
+        } else if second {
+            compute_dxx(arr, rows, cols).expect("Input has unexpected dimensions").data
+        } else if let Some(region) = region {
+            compute_dx_region(arr, rows, cols, region).expect("Region is inside the input").data
+        } else if let Some(matrix) = &input {
+            compute_dx_matrix(matrix).data
+        } else if let Some(threads) = options.threads {
+            match placement(options) {
+                Some(placement) => compute_dx_par_with(arr, rows, cols, threads, |worker| pin_worker(placement, worker)),
+                None => compute_dx_par(arr, rows, cols, threads),
+            }.expect("Input has unexpected dimensions")
+        } else if let Some(cancel) = timeout::token() {
+            timeout::or_exit(compute_dx_cancellable(arr, rows, cols, cancel), "Dx")
+        } else {
+            compute_dx(arr, rows, cols).data
+        });
+    };
+
+    // What the kernels read: the col-major copy if there is one.
+    let kernel_input: &[u8] = input.as_ref().map_or(arr, |matrix| &matrix.data);
+    let mut evictor: Option<Evictor> = options.llc_size.map(Evictor::new);
+    let state: Option<CacheState> = options.cache_states.first().copied();
+
+    // compute Dy
+    let (mut dy, dy_timing) = match cached_dy {
+        Some(cached) => cached,
+        None => time_dy(&mut pool, &mut prepare_input(state, kernel_input, &mut evictor)),
+    };
+
+    // Compute Dx
+    let (mut dx, dx_timing) = match cached_dx {
+        Some(cached) => cached,
+        None => time_dx(&mut pool, &mut prepare_input(state, kernel_input, &mut evictor)),
+    };
+
+    // With --cache-state both the other state is timed too, after the main
+    // timings, which are those of the first state.
+    let mut cache_timings: Vec<(CacheState, TimingReport, TimingReport)> = Vec::new();
+
+    for (index, &state) in options.cache_states.iter().enumerate() {
+        if index == 0 {
+            cache_timings.push((state, dx_timing.clone(), dy_timing.clone()));
+            continue;
+        }
+
+        let (_, dy_report) = time_dy(&mut pool, &mut prepare_input(Some(state), kernel_input, &mut evictor));
+        let (_, dx_report) = time_dx(&mut pool, &mut prepare_input(Some(state), kernel_input, &mut evictor));
+        cache_timings.push((state, dx_report, dy_report));
+    }
+
+    // Cached results are already row-major.
+    if options.layout == Layout::ColMajor && !cached {
+        dx = Matrix::new(dx, rows, cols + 2, Layout::ColMajor).expect("Dx has unexpected dimensions")
+            .to_layout(Layout::RowMajor).data;
+        dy = Matrix::new(dy, rows + 2, cols, Layout::ColMajor).expect("Dy has unexpected dimensions")
+            .to_layout(Layout::RowMajor).data;
     }
 
-    return dy;
+    let cache: Option<cache::Outcome> = entry.map(|entry| if cached {
+        cache::Outcome::Hit(entry.dir().to_path_buf())
+    } else {
+        entry.store((&dx, dx_shape.0, dx_shape.1), (&dy, dy_shape.0, dy_shape.1), &dx_timing, &dy_timing)
+    });
+
+    // Profiled separately so the timed runs above take no extra timestamps.
+    let phases: Option<[KernelTimings; 2]> = options.profile_phases.then(|| [
+        fastest_phases(|| compute_dx_profiled(arr, rows, cols).expect("Input has unexpected dimensions").1),
+        fastest_phases(|| compute_dy_profiled(arr, rows, cols).expect("Input has unexpected dimensions").1),
+    ]);
+
+    // A rolling extreme of the input covers all of it, also for a region.
+    let input_shape: (usize, usize) = (rows, cols);
+    let (rows, cols): (usize, usize) = region.map_or((rows, cols), |region| (region.rows, region.cols));
+
+    // Checked against the full results, before any cropping.
+    let overflow: Option<[OverflowReport; 2]> = options.check_overflow.then(|| check_overflow(arr, rows, cols, &taps, &dx, &dy, options));
+
+    let dx_bytes: usize = dx_bytes_moved(rows, cols, pad, size_of::<i16>());
+    let dy_bytes: usize = dy_bytes_moved(rows, cols, pad, size_of::<i16>());
+    let (dx, dy): (Matrix<i16>, Matrix<i16>) = shape_outputs(dx, dy, rows, cols, pad, options.crop_output);
+    let verify: Option<[ErrorMetrics; 2]> = options.verify.then(|| verify_outputs(arr, rows, cols, &taps, &dx, &dy, options));
+
+    // The threshold runs on the L1 magnitude, so it is computed once for both.
+    let l1: Option<Vec<u16>> = (options.magnitude_l1 || options.adaptive_threshold.is_some())
+        .then(|| abs_gradient(&dx.data, &dy.data, rows, cols).expect("Dx and Dy have unexpected dimensions"));
+
+    let threshold: Option<Vec<u8>> = options.adaptive_threshold.zip(l1.as_ref()).map(|((radius, offset), magnitude)| {
+        adaptive_threshold(magnitude, rows, cols, radius, offset).expect("Magnitude has unexpected dimensions")
+    });
+
+    let magnitude_l1: Option<Vec<u16>> = l1.filter(|_| options.magnitude_l1);
+
+    let components: Option<Vec<ComponentStats>> = threshold.as_ref().zip(options.components).map(|(binary, connectivity)| {
+        let (labels, count) = label_components(binary, rows, cols, connectivity).unwrap_or_else(|err| panic!("Components: {}", err));
+        component_stats(&labels, rows, cols, count).expect("Labels have unexpected dimensions")
+    });
+
+    let rolling: Option<Matrix<u16>> = options.rolling.map(|rolling| {
+        let (source, rows, cols): (Vec<u16>, usize, usize) = if rolling.input {
+            (arr.iter().map(|&value| value as u16).collect(), input_shape.0, input_shape.1)
+        } else {
+            (dx.data.iter().map(|value| value.unsigned_abs()).collect(), dx.rows, dx.cols)
+        };
+        let data: Vec<u16> = if rolling.max {
+            rolling_max_rows(&source, rows, cols, rolling.window)
+        } else {
+            rolling_min_rows(&source, rows, cols, rolling.window)
+        }.expect("Rolling source has unexpected dimensions");
+
+        Matrix::new(data, rows, cols, Layout::RowMajor).expect("Rolling result has unexpected dimensions")
+    });
+
+    let hog: Option<Vec<Vec<f32>>> = options.hog.map(|cell_size| {
+        orientation_histogram(&dx.data, &dy.data, rows, cols, cell_size, options.hog_bins.unwrap_or(DEFAULT_HOG_BINS))
+            .unwrap_or_else(|err| panic!("Orientation histogram: {}", err))
+    });
+
+    let harris: Option<Vec<(f32, usize, usize)>> = options.harris.map(|params| {
+        let (ixx, iyy, ixy) = structure_tensor(&dx.data, &dy.data, rows, cols, params.radius)
+            .unwrap_or_else(|err| panic!("Structure tensor: {}", err));
+        let response: Vec<f32> = harris_response(&ixx, &iyy, &ixy, params.k).expect("Structure tensor fields differ in length");
+
+        top_k_f32(&response, rows, cols, options.top_k.unwrap_or(DEFAULT_HARRIS_CORNERS)).expect("Response has unexpected dimensions")
+    });
+
+    let names: [&'static str; 2] = if second { ["Dxx", "Dyy"] } else { ["Dx", "Dy"] };
+
+    return Gradients { names, dx, dy, dx_timing, dy_timing, elements: rows * cols, dx_bytes, dy_bytes, magnitude_l1, threshold,
+                      components, rolling, hog, harris, memory: memory_stats(), overflow, phases,
+                      verify, cache, cache_timings };
+}
+
+// What runs before each timed run of a kernel reading input to put it in
+// state: nothing without --cache-state, a touch of the input before the first
+// run for warm and an eviction before every run for cold.
+fn prepare_input<'a>(state: Option<CacheState>, input: &'a [u8], evictor: &'a mut Option<Evictor>) -> impl FnMut() + 'a {
+    let mut touched: bool = false;
+
+    return move || match state {
+        Some(CacheState::Warm) if !touched => {
+            touch(input);
+            touched = true;
+        }
+        Some(CacheState::Cold) => evictor.as_mut().expect("Cold runs have an evictor").evict(input),
+        _ => {}
+    };
+}
+
+fn print_results(gradients: &Gradients, options: &Options) {
+    let ps: &[f64] = options.percentiles.as_deref().unwrap_or(&DEFAULT_PERCENTILES);
+    let zero_crossings: bool = options.zero_crossings;
+
+    let dx_rate: Throughput = Throughput::new(gradients.elements, gradients.dx_bytes, gradients.dx_timing.median());
+    let dy_rate: Throughput = Throughput::new(gradients.elements, gradients.dy_bytes, gradients.dy_timing.median());
+
+    let (dx, dy): (&[i16], &[i16]) = (&gradients.dx.data, &gradients.dy.data);
+    let [dx_name, dy_name] = gradients.names;
+
+    println!("{} min: {} max: {} sum: {} nonzero: {} duration: {} {}", dx_name, get_min(dx), get_max(dx), get_sum(dx), count_nonzero(dx),
+             describe_timing(&gradients.dx_timing), describe_throughput(&dx_rate));
+    println!("{} min: {} max: {} sum: {} nonzero: {} duration: {} {}", dy_name, get_min(dy), get_max(dy), get_sum(dy), count_nonzero(dy),
+             describe_timing(&gradients.dy_timing), describe_throughput(&dy_rate));
+
+    for (state, dx_report, dy_report) in &gradients.cache_timings {
+        println!("Cache {}: {} {} {} {}{}", state.name(), dx_name, describe_timing(dx_report), dy_name, describe_timing(dy_report),
+                 describe_cache_state(*state, options));
+    }
+
+    match &gradients.cache {
+        Some(cache::Outcome::Hit(dir)) => println!("{}/{} cached in {}; durations are from the run that stored them", dx_name, dy_name, dir.display()),
+        Some(cache::Outcome::Stored(dir)) => println!("{}/{} computed and stored in {}", dx_name, dy_name, dir.display()),
+        Some(cache::Outcome::Unstored(reason)) => println!("{}/{} computed but not cached: {}", dx_name, dy_name, reason),
+        None => {}
+    }
+
+    if let Some(magnitude) = &gradients.magnitude_l1 {
+        println!("L1 magnitude min: {} max: {}", get_min(magnitude), get_max(magnitude));
+    }
+
+    if let (Some(binary), Some((radius, offset))) = (&gradients.threshold, options.adaptive_threshold) {
+        let foreground: usize = binary.iter().filter(|&&value| value == FOREGROUND).count();
+        println!("Adaptive threshold (radius {}, offset {}): {} of {} elements foreground", radius, offset, foreground, binary.len());
+    }
+
+    if let (Some(components), Some(connectivity)) = (&gradients.components, options.components) {
+        print_components(components, connectivity);
+    }
+
+    if let (Some(result), Some(rolling)) = (&gradients.rolling, options.rolling) {
+        print_rolling(result, rolling, dx_name);
+    }
+
+    if let Some(memory) = &gradients.memory {
+        println!("Memory peak_rss_estimate: {} bytes alloc_count: {}", memory.peak_bytes, memory.alloc_count);
+    }
+
+    if let Some([dx_report, dy_report]) = &gradients.overflow {
+        print_overflow(dx_name, dx_report);
+        print_overflow(dy_name, dy_report);
+    }
+
+    if let Some([dx_metrics, dy_metrics]) = &gradients.verify {
+        println!("{} verify: {}", dx_name, describe_error(dx_metrics, gradients.dx.cols));
+        println!("{} verify: {}", dy_name, describe_error(dy_metrics, gradients.dy.cols));
+    }
+
+    if let Some(phases) = &gradients.phases {
+        for (name, timings) in [("Dx", phases[0]), ("Dy", phases[1])] {
+            let share = |phase: Duration| 100.0 * phase.as_secs_f64() / timings.total().as_secs_f64().max(f64::MIN_POSITIVE);
+            println!("{} phases: alloc {:.1}% border {:.1}% interior {:.1}% (alloc {:?} border {:?} interior {:?})", name,
+                     share(timings.alloc), share(timings.border), share(timings.interior), timings.alloc, timings.border, timings.interior);
+        }
+    }
+
+    for (name, data) in [(dx_name, dx), (dy_name, dy)] {
+        let values: Vec<String> = percentiles(data, ps).iter().zip(ps).map(|(value, p)| format!("p{}: {}", p, value)).collect();
+        println!("{} {}", name, values.join(" "));
+    }
+
+    if let Some(k) = options.top_k {
+        for (name, output) in [(dx_name, &gradients.dx), (dy_name, &gradients.dy)] {
+            let strongest: Vec<String> = top_k_abs_par(&output.data, output.rows, output.cols, k, options.threads.unwrap_or(1)).expect("Result has unexpected dimensions").iter()
+                .map(|(value, row, col)| format!("{} at ({}, {})", value, row, col)).collect();
+            println!("{} top {}: {}", name, k, strongest.join(", "));
+        }
+    }
+
+    if zero_crossings {
+        for (name, output) in [(dx_name, &gradients.dx), (dy_name, &gradients.dy)] {
+            let (data, rows, cols): (&[i16], usize, usize) = (&output.data, output.rows, output.cols);
+            let along_rows: usize = count_zero_crossings_rows(data, rows, cols).expect("Result has unexpected dimensions").iter().sum();
+            let along_cols: usize = count_zero_crossings_cols(data, rows, cols).expect("Result has unexpected dimensions").iter().sum();
+
+            println!("{} zero crossings along rows: {} along columns: {} total: {}", name, along_rows, along_cols, along_rows + along_cols);
+        }
+    }
+
+    if let (Some(corners), Some(params)) = (&gradients.harris, &options.harris) {
+        let listed: Vec<String> = corners.iter().map(|(response, row, col)| format!("{:.1} at ({}, {})", response, row, col)).collect();
+        println!("Harris corners (k={}, radius={}) top {}: {}", params.k, params.radius, corners.len(), listed.join(", "));
+    }
+
+    if let Some(cells) = &gradients.hog {
+        let bins: usize = cells.first().map_or(0, |cell| cell.len());
+        let totals: Vec<f32> = (0..bins).map(|bin| cells.iter().map(|cell| cell[bin]).sum()).collect();
+        let strongest: usize = (0..bins).max_by(|a, b| totals[*a].total_cmp(&totals[*b])).unwrap_or(0);
+
+        println!("Orientation histogram: {} cells x {} bins, strongest bin: {} ({:.0} to {:.0} degrees)", cells.len(), bins,
+                 strongest, strongest as f32 * 180.0 / bins as f32, (strongest + 1) as f32 * 180.0 / bins as f32);
+    }
+}
+
+// Prints the number of components and the largest ones, biggest first and
+// in label order among equals.
+fn print_components(components: &[ComponentStats], connectivity: Connectivity) {
+    let mut largest: Vec<&ComponentStats> = components.iter().collect();
+    largest.sort_by_key(|component| std::cmp::Reverse(component.pixels));
+
+    let listed: Vec<String> = largest.iter().take(LARGEST_COMPONENTS).map(|component| {
+        format!("{} px at ({}, {}) {}x{}", component.pixels, component.row, component.col, component.height, component.width)
+    }).collect();
+    let neighbours: usize = if connectivity == Connectivity::Four { 4 } else { 8 };
+
+    println!("Components ({}-connected): {}", neighbours, components.len());
+
+    if !listed.is_empty() {
+        println!("Largest components: {}", listed.join(", "));
+    }
+}
+
+// Summarises a --rolling result: its range and where its maximum is. For a
+// rolling minimum of |Dx| that lies in the horizontal streak of window
+// elements whose weakest gradient is the strongest.
+fn print_rolling(result: &Matrix<u16>, rolling: Rolling, dx_name: &str) {
+    let source: String = if rolling.input { "the input".to_string() } else { format!("|{}|", dx_name) };
+    let label: String = format!("Rolling {}:{} of {}", if rolling.max { "max" } else { "min" }, rolling.window, source);
+
+    // The first of equal maxima, in row-major order.
+    match argmax(&result.data, 1) {
+        Some(index) => println!("{} min: {} max: {} at ({}, {})", label, get_min(&result.data), result.data[index], index / result.cols,
+                                index % result.cols),
+        None => println!("{} is empty", label),
+    }
+}
+
+// Wraps the Dx and Dy a kernel of pad + 1 weights produced for a rows x cols
+// input with their shapes, cropped to the central rows x cols region (which
+// starts pad / 2 elements in) when crop is set.
+fn shape_outputs<T: Copy>(dx: Vec<T>, dy: Vec<T>, rows: usize, cols: usize, pad: usize, crop_output: bool) -> (Matrix<T>, Matrix<T>) {
+    let dx: Matrix<T> = Matrix::new(dx, rows, cols + pad, Layout::RowMajor).expect("Dx has unexpected dimensions");
+    let dy: Matrix<T> = Matrix::new(dy, rows + pad, cols, Layout::RowMajor).expect("Dy has unexpected dimensions");
+
+    if !crop_output {
+        return (dx, dy);
+    }
+
+    return (
+        Matrix::new(crop(&dx.data, dx.rows, dx.cols, 0, pad / 2, rows, cols).expect("Dx has unexpected dimensions"), rows, cols, Layout::RowMajor)
+            .expect("Cropped Dx has unexpected dimensions"),
+        Matrix::new(crop(&dy.data, dy.rows, dy.cols, pad / 2, 0, rows, cols).expect("Dy has unexpected dimensions"), rows, cols, Layout::RowMajor)
+            .expect("Cropped Dy has unexpected dimensions"),
+    );
+}
+
+// Error metrics of Dx and Dy against the scalar convolution into i32, padded,
+// shaped and cropped the same way.
+fn verify_outputs(arr: &[u8], rows: usize, cols: usize, kernel: &[i32], dx: &Matrix<i16>, dy: &Matrix<i16>,
+                  options: &Options) -> [ErrorMetrics; 2] {
+    let pad: usize = options.pad.unwrap_or(kernel.len() - 1);
+    let reference_dx: Vec<i32> = convolve_rows_padded(arr, rows, cols, kernel, pad, ArithPolicy::Wrapping)
+        .unwrap_or_else(|err| panic!("Dx reference: {}", err));
+    let reference_dy: Vec<i32> = convolve_cols_padded(arr, rows, cols, kernel, pad, ArithPolicy::Wrapping)
+        .unwrap_or_else(|err| panic!("Dy reference: {}", err));
+    let (reference_dx, reference_dy) = shape_outputs(reference_dx, reference_dy, rows, cols, pad, options.crop_output);
+
+    return [
+        error_metrics(&reference_dx.data, &dx.data).expect("Dx has unexpected dimensions"),
+        error_metrics(&reference_dy.data, &dy.data).expect("Dy has unexpected dimensions"),
+    ];
+}
+
+// "ok" for identical results, otherwise the metrics; positions are (row, col)
+// in a matrix of the given width, the earliest in row-major order.
+fn describe_error(metrics: &ErrorMetrics, cols: usize) -> String {
+    let Some(first) = metrics.first_mismatch else {
+        return "ok".to_string();
+    };
+
+    return format!("MISMATCH {} elements differ, first at ({}, {}), max abs error: {} at ({}, {}), rmse: {:.6}", metrics.mismatches,
+                   first / cols.max(1), first % cols.max(1), metrics.max_abs, metrics.max_abs_index / cols.max(1),
+                   metrics.max_abs_index % cols.max(1), metrics.rmse);
+}
+
+// Recomputes Dx and Dy exactly and compares them with the results the
+// kernels produced.
+fn check_overflow<I: Widen, O: Widen>(arr: &[I], rows: usize, cols: usize, kernel: &[i32], dx: &[O], dy: &[O],
+                                      options: &Options) -> [OverflowReport; 2] {
+    let limit: usize = options.check_overflow_limit.unwrap_or(DEFAULT_FINDING_LIMIT);
+
+    return [
+        check_rows_overflow(arr, rows, cols, kernel, dx, limit).unwrap_or_else(|err| panic!("Dx overflow check: {}", err)),
+        check_cols_overflow(arr, rows, cols, kernel, dy, limit).unwrap_or_else(|err| panic!("Dy overflow check: {}", err)),
+    ];
+}
+
+// One line per overflowed element, with the products that were summed.
+// Positions are in the uncropped output and values are raw integers.
+fn print_overflow(name: &str, report: &OverflowReport) {
+    println!("{} overflow check: {} of {} elements overflowed{}", name, report.total, report.checked,
+             if report.total > report.findings.len() { format!(", first {} shown", report.findings.len()) } else { String::new() });
+
+    for finding in &report.findings {
+        let terms: Vec<String> = finding.operands.iter().map(|(weight, value)| format!("{}*{}", weight, value)).collect();
+        println!("  ({}, {}) index {}: got {} expected {} = {}", finding.row, finding.col, finding.index, finding.fast, finding.exact,
+                 terms.join(" + "));
+    }
+}
+
+// Timing of the kernels in a normal run: a single run unless --warmup asks
+// for warm-up, in which case the median of the measured runs is reported.
+fn timing_config(options: &Options) -> TimingConfig {
+    let mut config: TimingConfig = TimingConfig::default();
+
+    if let Some(warmup) = options.warmup {
+        config.warmup = warmup;
+        config.samples = DEFAULT_WINDOW;
+    }
+
+    if let Some(max_time) = options.max_bench_time {
+        config.max_time = max_time;
+    }
+
+    return config;
+}
+
+// How the input was put in state for the timed runs.
+fn describe_cache_state(state: CacheState, options: &Options) -> String {
+    return match (state, options.llc_size) {
+        (CacheState::Warm, _) => " (input touched once before timing)".to_string(),
+        (CacheState::Cold, _) if evict::FLUSHES => " (input flushed with clflush before every run)".to_string(),
+        (CacheState::Cold, Some(llc_size)) => format!(" (evicted before every run by streaming {} for a {} last-level cache)",
+                                                      budget::format_bytes(evict::buffer_len(llc_size) as u128), budget::format_bytes(llc_size as u128)),
+        (CacheState::Cold, None) => String::new(),
+    };
+}
+
+// The median duration, followed by the warm-up details when there are any.
+fn describe_timing(report: &TimingReport) -> String {
+    let median: String = format!("{:?}", report.median());
+
+    if report.samples.len() == 1 && report.discarded == 0 {
+        return median;
+    }
+
+    return format!("{} (median of {}, cv {:.3}, {} warm-up runs discarded{})", median, report.samples.len(), report.cv(),
+                   report.discarded, if report.steady { "" } else { ", no steady state before time limit" });
+}
+
+// Applies every kernel horizontally and vertically, both as a single-pass
+// kernel bank and as separate passes, reporting the time and bandwidth of
+// each approach and the range of every output.
+fn run_kernel_bank(arr: &[u8], rows: usize, cols: usize, kernels: &[Vec<i32>], options: &Options) {
+    let kernels: Vec<&[i32]> = kernels.iter().map(|kernel| kernel.as_slice()).collect();
+    let timing: TimingConfig = timing_config(options);
+    let policy: ArithPolicy = ArithPolicy::Wrapping;
+
+    let (row_outs, row_timing) = measure(&timing, || convolve_rows_multi(arr, rows, cols, &kernels).unwrap_or_else(|err| panic!("Rows: {}", err)));
+    let (col_outs, col_timing) = measure(&timing, || convolve_cols_multi(arr, rows, cols, &kernels).unwrap_or_else(|err| panic!("Cols: {}", err)));
+    let (_, separate_row_timing) = measure(&timing, || kernels.iter().map(|kernel| {
+        convolve_rows::<u8, i32>(arr, rows, cols, kernel, policy).unwrap_or_else(|err| panic!("Rows: {}", err))
+    }).collect::<Vec<Vec<i32>>>());
+    let (_, separate_col_timing) = measure(&timing, || kernels.iter().map(|kernel| {
+        convolve_cols::<u8, i32>(arr, rows, cols, kernel, policy).unwrap_or_else(|err| panic!("Cols: {}", err))
+    }).collect::<Vec<Vec<i32>>>());
+
+    // A bank reads the input once; separate passes read it once per kernel.
+    let row_written: usize = row_outs.iter().map(|out| out.len() * size_of::<i32>()).sum();
+    let col_written: usize = col_outs.iter().map(|out| out.len() * size_of::<i32>()).sum();
+    let separate_reads: usize = kernels.len() * rows * cols;
+
+    println!("=== Kernel bank ({} kernels) ===", kernels.len());
+
+    for (index, kernel) in kernels.iter().enumerate() {
+        println!("{:?} rows min: {} max: {} cols min: {} max: {}", kernel, get_min(&row_outs[index]), get_max(&row_outs[index]),
+                 get_min(&col_outs[index]), get_max(&col_outs[index]));
+    }
+
+    let runs: [(&str, &TimingReport, usize); 4] = [
+        ("bank rows", &row_timing, rows * cols + row_written),
+        ("bank cols", &col_timing, rows * cols + col_written),
+        ("separate rows", &separate_row_timing, separate_reads + row_written),
+        ("separate cols", &separate_col_timing, separate_reads + col_written),
+    ];
+
+    for (name, report, bytes) in runs {
+        let rate: Throughput = Throughput::new(rows * cols, bytes, report.median());
+        println!("{:<14} duration: {} {}", name, describe_timing(report), describe_throughput(&rate));
+    }
+}
+
+// Runs the --pipeline stages on the input, reporting the time the whole chain
+// takes and the range of its result.
+fn run_pipeline(arr: &[u8], rows: usize, cols: usize, stages: &[Stage], options: &Options) {
+    let timing: TimingConfig = timing_config(options);
+    let lut: Option<Lut256> = load_lut(options);
+    let hook: &dyn PostProcess = match &lut {
+        Some(lut) => lut,
+        None => &Identity,
+    };
+    let (image, report) = measure(&timing, || run_stages_with(stages, arr, rows, cols, hook).expect("Input has unexpected dimensions"));
+    let spec: Vec<String> = stages.iter().map(|stage| stage.to_string()).collect();
+
+    println!("=== Pipeline {} ===", spec.join(" | "));
+
+    match &image {
+        Image::U8(data) => println!("u8 result min: {} max: {} nonzero: {}", get_min(data), get_max(data), count_nonzero(data)),
+        Image::Gradients(dx, dy) => println!("Gradients Dx min: {} max: {} Dy min: {} max: {}", get_min(dx), get_max(dx), get_min(dy), get_max(dy)),
+        Image::F32(data) => println!("f32 result min: {} max: {}", data.iter().copied().fold(f32::INFINITY, f32::min),
+                                     data.iter().copied().fold(f32::NEG_INFINITY, f32::max)),
+    }
+
+    println!("duration: {}", describe_timing(&report));
+
+    if options.ascii {
+        // u8 results are drawn as they are, anything else stretched to u8.
+        let pixels: Vec<u8> = match image {
+            Image::U8(data) => data,
+            Image::Gradients(dx, dy) => normalize_u8(&abs_gradient(&dx, &dy, rows, cols).expect("Gradients have unexpected dimensions")),
+            Image::F32(data) => normalize_u8(&data),
+        };
+
+        println!("=== Pipeline result ===");
+        print!("{}", render_ascii(&pixels, rows, cols, ascii_width(options)));
+    }
+}
+
+// Prints the --scale-report table, one line per pyramid level, and writes it
+// to --scale-json if given.
+fn run_scale_report(arr: &[u8], rows: usize, cols: usize, options: &Options) {
+    let accumulator: Accumulator = options.accumulate.unwrap_or_default();
+    let report: Vec<ScaleLevel> = scale_report_with(arr, rows, cols, options.scale_report, accumulator).expect("Input has unexpected dimensions");
+
+    println!("=== Scale report ({} levels, {} accumulation) ===", report.len(), accumulator.name());
+    println!("{:>5} {:>11} {:>6} {:>6} {:>9} {:>9} {:>6} {:>6} {:>9} {:>9} {:>9} {:>7}", "level", "size", "dx min", "dx max", "dx std",
+             "dx |mean|", "dy min", "dy max", "dy std", "dy |mean|", "|grad|", "ratio");
+
+    for level in &report {
+        println!("{:>5} {:>11} {:>6} {:>6} {:>9.3} {:>9.3} {:>6} {:>6} {:>9.3} {:>9.3} {:>9.3} {:>7}", level.level,
+                 format!("{}x{}", level.rows, level.cols), level.dx.min, level.dx.max, level.dx.stddev, level.dx.mean_abs, level.dy.min,
+                 level.dy.max, level.dy.stddev, level.dy.mean_abs, level.mean_abs_gradient,
+                 level.ratio.map_or("-".to_string(), |ratio| format!("{:.3}", ratio)));
+    }
+
+    if let Some(path) = &options.scale_json {
+        let summary = |summary: &GradientSummary| json!({
+            "min": summary.min,
+            "max": summary.max,
+            "stddev": summary.stddev,
+            "mean_abs": summary.mean_abs,
+        });
+        let levels: Vec<Value> = report.iter().map(|level| json!({
+            "level": level.level,
+            "rows": level.rows,
+            "cols": level.cols,
+            "dx": summary(&level.dx),
+            "dy": summary(&level.dy),
+            "mean_abs_gradient": level.mean_abs_gradient,
+            "ratio": level.ratio,
+        })).collect();
+        let text: String = serde_json::to_string_pretty(&json!({ "input": { "rows": rows, "cols": cols }, "accumulator": accumulator.name(), "levels": levels }))
+            .expect("JSON values always serialize");
+
+        fs::write(path, text + "\n").unwrap_or_else(|err| panic!("Failed to write {}: {}", path, err));
+    }
+}
+
+// Runs the four directional differences of --operator directional and their
+// per-element maximum on arr, timing each, and writes the maximum as
+// directional.csv and directional.pgm with --output-csv and --output-pgm.
+// All are rows x cols, aligned with the input.
+fn run_directional(arr: &[u8], rows: usize, cols: usize, options: &Options) {
+    let timing: TimingConfig = timing_config(options);
+    let crop = |result: Result<Vec<i16>, DimError>| result.expect("Result has unexpected dimensions");
+
+    let directions: [(&str, Vec<i16>, TimingReport); 4] = [
+        ("Dx", measure(&timing, || crop(crop_dx_padding(&compute_dx(arr, rows, cols).data, rows, cols)))),
+        ("Dy", measure(&timing, || crop(crop_dy_padding(&compute_dy(arr, rows, cols).data, rows, cols)))),
+        ("D45", measure(&timing, || compute_d45(arr, rows, cols).data)),
+        ("D135", measure(&timing, || compute_d135(arr, rows, cols).data)),
+    ].map(|(name, (data, report))| (name, data, report));
+    let (strongest, strongest_timing) = measure(&timing, || max_directional_gradient(arr, rows, cols));
+
+    println!("=== Directional gradients ({}x{}, same mode) ===", rows, cols);
+
+    for (name, data, report) in &directions {
+        println!("{} min: {} max: {} sum: {} nonzero: {} duration: {}", name, get_min(data), get_max(data), get_sum(data), count_nonzero(data),
+                 describe_timing(report));
+    }
+
+    let data: &[u16] = &strongest.data;
+    println!("Max |D| min: {} max: {} sum: {} nonzero: {} duration: {}", get_min(data), get_max(data), get_sum(data), count_nonzero(data),
+             describe_timing(&strongest_timing));
+
+    let write = |dir: &str, file: &str, write: &dyn Fn(&Path) -> std::io::Result<()>| {
+        fs::create_dir_all(dir).and_then(|_| write(&Path::new(dir).join(file))).unwrap_or_else(|err| panic!("Failed to write results: {}", err));
+    };
+
+    if let Some(dir) = &options.output_csv {
+        write(dir, "directional.csv", &|path| write_csv(path, data, rows, cols));
+    }
+
+    if let Some(dir) = &options.output_pgm {
+        let pixels: Vec<u8> = data.iter().map(|&value| value.min(u8::MAX as u16) as u8).collect();
+        write(dir, "directional.pgm", &|path| write_pgm(path, &pixels, rows, cols));
+    }
 }
 
-// Utility used to print vector of unsigned char
-fn print_2d_array_u8(arr: &[u8], rows: usize, cols: usize) {
-    println!("Array of size {}x{}\nRaw values:", rows, cols);
+// Slides the --match-template template over the input and reports where it
+// matches best, by the raw dot product and by normalized cross-correlation,
+// listing --top-k of the latter.
+fn run_match_template(arr: &[u8], rows: usize, cols: usize, path: &Path, options: &Options) {
+    let stored: StoredMatrix = read_matrix(path).unwrap_or_else(|err| panic!("Failed to read --match-template {}: {}", path.display(), err));
+    let (trows, tcols): (usize, usize) = (stored.rows, stored.cols);
+    let template: Vec<i32> = stored.values.iter().map(|&value| {
+        i32::try_from(value).unwrap_or_else(|_| panic!("{}: template value {} does not fit an i32", path.display(), value))
+    }).collect();
+    let (out_rows, out_cols): (usize, usize) = match_dims(arr, rows, cols, &template, trows, tcols)
+        .unwrap_or_else(|err| panic!("--match-template: {}", err));
 
-    for i in 0..rows*cols {
-        print!("{},", arr[i]);
+    let timing: TimingConfig = timing_config(options);
+    let (raw, raw_timing) = measure(&timing, || match_template(arr, rows, cols, &template, trows, tcols).expect("Template fits the input"));
+    let (normalized, normalized_timing) = measure(&timing, || {
+        match_template_normalized(arr, rows, cols, &template, trows, tcols).expect("Template fits the input")
+    });
+
+    println!("=== Template match ({}x{} template {} over {}x{}, {}x{} placements) ===", trows, tcols, path.display(), rows, cols, out_rows, out_cols);
+
+    if let Some(peak) = argmax(&raw, 1) {
+        println!("Dot product peak: {} at ({}, {}) duration: {}", raw[peak], peak / out_cols, peak % out_cols, describe_timing(&raw_timing));
     }
 
-    println!("\nFormatted values:");
+    let best: Vec<(f32, usize, usize)> = top_k_f32(&normalized, out_rows, out_cols, options.top_k.unwrap_or(1)).expect("Result has unexpected dimensions");
+
+    if let Some((value, row, col)) = best.first() {
+        println!("Normalized peak: {:.6} at ({}, {}) duration: {}", value, row, col, describe_timing(&normalized_timing));
+    }
 
-    for row in 0.. rows {
-        print!("[");
-        for col in 0.. cols {
-            print!("{},", arr[row * cols + col]);
+    if options.top_k.is_some() {
+        for (rank, (value, row, col)) in best.iter().enumerate() {
+            println!("  {:>3}. {:.6} at ({}, {})", rank + 1, value, row, col);
         }
-        println!("]");
     }
 
-    println!("]");
-    return;
+    if let Some(dir) = &options.output_csv {
+        fs::create_dir_all(dir).and_then(|_| write_csv(&Path::new(dir).join("match.csv"), &raw, out_rows, out_cols))
+            .and_then(|_| write_csv(&Path::new(dir).join("match_normalized.csv"), &normalized, out_rows, out_cols))
+            .unwrap_or_else(|err| panic!("Failed to write results: {}", err));
+    }
 }
 
-// Utility used to print vector of 16-bit ints
-fn print_2d_array_i16(arr: &[i16], rows: usize, cols: usize) {
-    println!("Array of size {}x{}\nRaw values:", rows, cols);
+// Times the built-in kernels strip by strip over strips of strip_rows rows,
+// reporting the overhead of the timing against the same strips untimed and
+// writing the durations with --output-csv and --output-heatmap.
+fn run_time_strips(arr: &[u8], rows: usize, cols: usize, strip_rows: usize, options: &Options) {
+    let timing: TimingConfig = timing_config(options);
+    let (strips, timed) = measure(&timing, || time_strips(arr, rows, cols, strip_rows).expect("Input has unexpected dimensions"));
+    let (_, untimed) = measure(&timing, || {
+        process_tiles(arr, rows, cols, strip_rows, cols.max(1), |tile| {
+            black_box(tile.compute_dx());
+            black_box(tile.compute_dy());
+        }).expect("Input has unexpected dimensions")
+    });
+
+    let dx: Duration = strips.iter().map(|strip| strip.dx).sum();
+    let dy: Duration = strips.iter().map(|strip| strip.dy).sum();
+    // The pass the strips were taken from, the last one measured.
+    let pass: Duration = timed.samples.last().copied().unwrap_or_default();
+    let overhead: f64 = (timed.median().as_secs_f64() / untimed.median().as_secs_f64().max(f64::MIN_POSITIVE) - 1.0) * 100.0;
 
-    for i in 0..rows*cols {
-        print!("{},", arr[i]);
+    println!("=== Strip timings ({} strips of {} rows over {}x{}) ===", strips.len(), strip_rows, rows, cols);
+    println!("Dx: {:?} Dy: {:?} over the strips, {:?} of a {:?} pass", dx, dy, dx + dy, pass);
+    println!("Timed passes: {} untimed: {} overhead: {:.1}%", describe_timing(&timed), describe_timing(&untimed), overhead);
+
+    if let Some(slowest) = strips.iter().max_by_key(|strip| strip.dx + strip.dy) {
+        println!("Slowest strip: rows {}..{} Dx: {:?} Dy: {:?}", slowest.row, slowest.row + slowest.rows, slowest.dx, slowest.dy);
+    }
+
+    // One row per strip: its first row, its rows and the Dx and Dy nanoseconds.
+    if let Some(dir) = &options.output_csv {
+        let table: Vec<u128> = strips.iter().flat_map(|strip| [strip.row as u128, strip.rows as u128, strip.dx.as_nanos(), strip.dy.as_nanos()]).collect();
+
+        fs::create_dir_all(dir).and_then(|_| write_csv(&Path::new(dir).join("strips.csv"), &table, strips.len(), 4))
+            .unwrap_or_else(|err| panic!("Failed to write results: {}", err));
+    }
+
+    if let Some(dir) = &options.output_heatmap {
+        let rgb: Vec<u8> = strip_heatmap(&strips);
+
+        fs::create_dir_all(dir).and_then(|_| write_ppm(&Path::new(dir).join("strips.ppm"), &rgb, strips.len(), 2))
+            .unwrap_or_else(|err| panic!("Failed to write results: {}", err));
     }
+}
+
+// A strips x 2 image of the strip timings, Dx on the left and Dy on the
+// right of each strip's row, from black for no time to white for the slowest.
+fn strip_heatmap(strips: &[StripTiming]) -> Vec<u8> {
+    let nanos: Vec<u128> = strips.iter().flat_map(|strip| [strip.dx.as_nanos(), strip.dy.as_nanos()]).collect();
+    let max: u128 = nanos.iter().copied().max().unwrap_or(0).max(1);
+    let scaled: Vec<i16> = nanos.iter().map(|&value| (value * i16::MAX as u128 / max) as i16).collect();
 
-    println!("\nFormatted values:");
+    let mut rgb: Vec<u8> = Vec::with_capacity(scaled.len() * 3);
 
-    for row in 0.. rows {
-        print!("[");
-        for col in 0.. cols {
-            print!("{},", arr[row * cols + col]);
+    for value in scaled {
+        rgb.extend_from_slice(&color(value, 0, i16::MAX, ColorMap::Sequential));
+    }
+
+    return rgb;
+}
+
+fn describe_throughput(rate: &Throughput) -> String {
+    return format!("({:.1} Melem/s, {:.2} GB/s)", rate.elements_per_sec / 1e6, rate.gb_per_sec);
+}
+
+// Kernel implementation compared by --compare-impls.
+struct KernelImpl {
+    name: &'static str,
+    dx: Option<KernelFn>,
+    dy: Option<KernelFn>,
+    // Why the implementation is missing from this build.
+    skip_reason: &'static str,
+}
+
+type KernelFn = fn(&[u8], usize, usize) -> Result<Vec<i16>, DimError>;
+
+// Number of timed runs per implementation; the fastest one is reported.
+const COMPARE_RUNS: usize = 5;
+
+// Times every kernel implementation compiled into this build on arr and
+// checks that they agree with the safe implementation.
+fn compare_impls(arr: &[u8], rows: usize, cols: usize) {
+    let impls: [KernelImpl; 2] = [
+        KernelImpl { name: "safe", dx: Some(compute_dx_safe), dy: Some(compute_dy_safe), skip_reason: "" },
+        #[cfg(feature = "unsafe-fast")]
+        KernelImpl { name: "unchecked", dx: Some(compute_dx_unchecked), dy: Some(compute_dy_unchecked), skip_reason: "" },
+        #[cfg(not(feature = "unsafe-fast"))]
+        KernelImpl { name: "unchecked", dx: None, dy: None, skip_reason: "built without the unsafe-fast feature" },
+    ];
+
+    let reference_dx: Vec<i16> = compute_dx_safe(arr, rows, cols).expect("Input has unexpected dimensions");
+    let reference_dy: Vec<i16> = compute_dy_safe(arr, rows, cols).expect("Input has unexpected dimensions");
+
+    println!("=== Implementations ({} runs each, best shown) ===", COMPARE_RUNS);
+
+    for kernel_impl in &impls {
+        let (dx_fn, dy_fn) = match (kernel_impl.dx, kernel_impl.dy) {
+            (Some(dx_fn), Some(dy_fn)) => (dx_fn, dy_fn),
+            _ => {
+                println!("{:<10} skipped: {}", kernel_impl.name, kernel_impl.skip_reason);
+                continue;
+            }
+        };
+
+        let (dx, dx_duration) = best_of(COMPARE_RUNS, || dx_fn(arr, rows, cols).expect("Input has unexpected dimensions"));
+        let (dy, dy_duration) = best_of(COMPARE_RUNS, || dy_fn(arr, rows, cols).expect("Input has unexpected dimensions"));
+        let matches: bool = dx == reference_dx && dy == reference_dy;
+
+        println!("{:<10} Dx: {:?} Dy: {:?} {}", kernel_impl.name, dx_duration, dy_duration,
+                 if matches { "ok" } else { "MISMATCH" });
+    }
+}
+
+// Runs the built-in kernels through the GPU backend and checks them against
+// the CPU. Returns false, after saying why, when no GPU can be used.
+#[cfg(feature = "gpu")]
+fn run_gpu(arr: &[u8], rows: usize, cols: usize) -> bool {
+    let context: GpuContext = match GpuContext::new() {
+        Ok(context) => context,
+        Err(err) => {
+            let adapters: Vec<String> = rmm::gpu::adapters();
+
+            eprintln!("GPU backend unavailable ({}), falling back to the CPU", err);
+            eprintln!("adapters found: {}", if adapters.is_empty() { "none".to_string() } else { adapters.join(", ") });
+            return false;
         }
-        println!("]")
+    };
+
+    let (dx, dx_timings) = context.compute_dx(arr, rows, cols).unwrap_or_else(|err| panic!("Dx: {}", err));
+    let (dy, dy_timings) = context.compute_dy(arr, rows, cols).unwrap_or_else(|err| panic!("Dy: {}", err));
+    let dx_metrics: ErrorMetrics = error_metrics(&compute_dx(arr, rows, cols).data, &dx).expect("GPU Dx has unexpected dimensions");
+    let dy_metrics: ErrorMetrics = error_metrics(&compute_dy(arr, rows, cols).data, &dy).expect("GPU Dy has unexpected dimensions");
+
+    println!("=== GPU ({}) ===", context.adapter_name());
+
+    for (name, timings, metrics, out_cols) in [("Dx", dx_timings, dx_metrics, cols + 2), ("Dy", dy_timings, dy_metrics, cols)] {
+        println!("{} upload: {:?} compute: {:?} download: {:?} {}", name, timings.upload, timings.compute, timings.download,
+                 describe_error(&metrics, out_cols));
     }
 
-    println!("]");
-    return;
+    return true;
+}
+
+#[cfg(not(feature = "gpu"))]
+fn run_gpu(_arr: &[u8], _rows: usize, _cols: usize) -> bool {
+    eprintln!("GPU backend unavailable (built without the gpu feature), falling back to the CPU");
+    return false;
 }
 
-fn get_min(matrix: &Vec<i16>) -> i16 {
-    let value = matrix.iter().min();
+// Convolves a Q8.8 input read from --input, accumulating exactly into Q16.16,
+// and reports the results as decimal numbers.
+fn run_fixed(options: &Options) {
+    let path: &Path = Path::new(options.input.as_deref().expect("--dtype q8.8 requires --input"));
+    let stored: StoredMatrix = read_matrix(path).unwrap_or_else(|err| panic!("Failed to read {}: {}", path.display(), err));
+    let (rows, cols) = (stored.rows, stored.cols);
+    input::check_dims(options, (rows, cols)).unwrap_or_else(|err| panic!("{}", err));
+    let arr: Vec<Fixed16> = stored.values.iter().map(|&value| {
+        Fixed16::from_raw(i16::try_from(value).unwrap_or_else(|_| panic!("{}: raw value {} does not fit Q8.8", path.display(), value)))
+    }).collect();
+
+    let kernel: &[i32] = options.kernel.as_deref().unwrap_or(&[-1, 0, 1]);
+    let policy: ArithPolicy = options.arith.unwrap_or_default();
+    let pad: usize = options.pad.unwrap_or(kernel.len() - 1);
+    let mode: ConvMode = options.conv_mode.unwrap_or_default();
+
+    let (dx, dx_timing) = measure(&timing_config(options), || {
+        convolve_rows_specialized::<Fixed16, Fixed32>(&arr, rows, cols, kernel, pad, mode, policy).unwrap_or_else(|err| panic!("Dx: {}", err))
+    });
+    let (dy, dy_timing) = measure(&timing_config(options), || {
+        convolve_cols_specialized::<Fixed16, Fixed32>(&arr, rows, cols, kernel, pad, mode, policy).unwrap_or_else(|err| panic!("Dy: {}", err))
+    });
+    let overflow: Option<[OverflowReport; 2]> = options.check_overflow.then(|| check_overflow(&arr, rows, cols, &mode.taps(kernel), &dx, &dy, options));
+    let (dx, dy): (Matrix<Fixed32>, Matrix<Fixed32>) = shape_outputs(dx, dy, rows, cols, pad, options.crop_output);
+
+    println!("=== Results (Q8.8 input, Q16.16 output) ===");
+    println!("Kernel: {:?} ({})", kernel, mode.name());
 
-    return match value {
-        Some(min) => *min,
-        None => 0
+    println!("Input min: {} max: {}", get_min(&arr), get_max(&arr));
+
+    // Values are in Q16.16 units, i.e. 65536 per 1.0.
+    if let Some([dx_report, dy_report]) = &overflow {
+        print_overflow("Dx", dx_report);
+        print_overflow("Dy", dy_report);
+    }
+
+    for (name, output, timing) in [("Dx", &dx, &dx_timing), ("Dy", &dy, &dy_timing)] {
+        let (data, out_rows, out_cols): (&[Fixed32], usize, usize) = (&output.data, output.rows, output.cols);
+
+        println!("{} min: {} max: {} nonzero: {} duration: {}", name, get_min(data), get_max(data), count_nonzero(data),
+                 describe_timing(timing));
+
+        if let Some(dir) = &options.output_csv {
+            fs::create_dir_all(dir).and_then(|_| write_csv(&Path::new(dir).join(format!("{}.csv", name.to_lowercase())), data, out_rows, out_cols))
+                .unwrap_or_else(|err| panic!("Failed to write results: {}", err));
+        }
+    }
+}
+
+// Runs the packed 4-bit kernels on --input or a seeded input scaled to 4 bits.
+// The throughput counts the packed input bytes, half those of a u8 input.
+fn run_u4(options: &Options) {
+    let matrix: U4Matrix = match input::resolve(options).unwrap_or_else(|err| panic!("{}", err)) {
+        ResolvedInput { arr: Some(arr), rows, cols } => {
+            U4Matrix::pack(&arr, rows, cols).unwrap_or_else(|err| panic!("{}: {}", options.input.as_deref().unwrap_or_default(), err))
+        }
+        ResolvedInput { arr: None, rows, cols } => construct_randomized_u4_seeded(rows, cols, options.seed.unwrap_or_else(rand::random)),
+    };
+    let (rows, cols) = (matrix.rows, matrix.cols);
+
+    let (dx, dx_timing) = measure(&timing_config(options), || compute_dx_u4(&matrix));
+    let (dy, dy_timing) = measure(&timing_config(options), || compute_dy_u4(&matrix));
+
+    println!("=== Results (u4 input, {} bytes packed) ===", matrix.bytes.len());
+
+    for (name, data, out_rows, out_cols, timing, bytes) in [
+        ("Dx", &dx, rows, cols + 2, &dx_timing, dx_bytes_moved_u4(rows, cols, 2, size_of::<i16>())),
+        ("Dy", &dy, rows + 2, cols, &dy_timing, dy_bytes_moved_u4(rows, cols, 2, size_of::<i16>())),
+    ] {
+        let rate: Throughput = Throughput::new(rows * cols, bytes, timing.median());
+
+        println!("{} min: {} max: {} sum: {} nonzero: {} duration: {} {}", name, get_min(data), get_max(data), get_sum(data), count_nonzero(data),
+                 describe_timing(timing), describe_throughput(&rate));
+
+        if let Some(dir) = &options.output_csv {
+            fs::create_dir_all(dir).and_then(|_| write_csv(&Path::new(dir).join(format!("{}.csv", name.to_lowercase())), data, out_rows, out_cols))
+                .unwrap_or_else(|err| panic!("Failed to write results: {}", err));
+        }
     }
 }
 
-fn get_max(matrix: &Vec<i16>) -> i16 {
-    let value = matrix.iter().max();
+// The --lut table, if one was given.
+fn load_lut(options: &Options) -> Option<Lut256> {
+    return options.lut.as_deref().map(|path| Lut256::load(Path::new(path)).unwrap_or_else(|err| panic!("Failed to read --lut {}: {}", path, err)));
+}
+
+// Heap usage since the run started, or None when the counting allocator is
+// not compiled in.
+fn memory_stats() -> Option<MemStats> {
+    return if cfg!(feature = "mem-stats") { Some(memstats::snapshot()) } else { None };
+}
+
+// Profiled runs per kernel for --profile-phases; the fastest one is reported.
+const PROFILE_RUNS: usize = 5;
+
+// The phase times of the fastest of PROFILE_RUNS profiled runs.
+fn fastest_phases(f: impl Fn() -> KernelTimings) -> KernelTimings {
+    return (0..PROFILE_RUNS).map(|_| f()).min_by_key(|timings| timings.total()).expect("at least one run");
+}
+
+// Runs f the given number of times and returns its last result together with
+// the fastest duration.
+fn best_of<T>(runs: usize, f: impl Fn() -> T) -> (T, Duration) {
+    let mut best: Duration = Duration::MAX;
+    let mut result: Option<T> = None;
 
-    return match value {
-        Some(max) => *max,
-        None => 0
+    for _ in 0..runs.max(1) {
+        let start = Instant::now();
+        let value: T = f();
+        best = best.min(start.elapsed());
+        result = Some(value);
     }
+
+    return (result.expect("at least one run"), best);
 }
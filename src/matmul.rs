@@ -0,0 +1,536 @@
+use std::borrow::Cow;
+use std::ops::{Add, AddAssign, Mul};
+use std::time::{Duration, Instant};
+use std::sync::atomic::AtomicBool;
+use crate::cancel::check_cancel;
+use crate::error::{check_len, CancelError, DimError};
+use crate::ops::transpose;
+
+// Element types the matrix products are defined for.
+pub trait Scalar: Copy + Default + PartialEq + Add<Output = Self> + Mul<Output = Self> + AddAssign {}
+
+impl Scalar for i32 {}
+impl Scalar for i64 {}
+impl Scalar for f32 {}
+impl Scalar for f64 {}
+
+// Tile size used by the blocked products when the caller has no preference.
+pub const DEFAULT_BLOCK_SIZE: usize = 64;
+
+// Checks that a is a_rows x a_cols, b is b_rows x b_cols and the two can be
+// multiplied.
+fn check_operands(a_len: usize, a_rows: usize, a_cols: usize, b_len: usize, b_rows: usize,
+                  b_cols: usize) -> Result<(), DimError> {
+    check_len(a_len, a_rows, a_cols)?;
+    check_len(b_len, b_rows, b_cols)?;
+
+    if a_cols != b_rows {
+        return Err(DimError::Mismatch { what: "inner dimension", expected: a_cols, found: b_rows });
+    }
+
+    return Ok(());
+}
+
+// Multiplies the a_rows x a_cols matrix a with the b_rows x b_cols matrix b,
+// giving an a_rows x b_cols matrix. The loops run in i-k-j order so the
+// innermost loop streams through rows of b and c.
+pub fn matmul<T: Scalar>(a: &[T], a_rows: usize, a_cols: usize, b: &[T], b_rows: usize,
+                         b_cols: usize) -> Result<Vec<T>, DimError> {
+    check_operands(a.len(), a_rows, a_cols, b.len(), b_rows, b_cols)?;
+
+    let mut c: Vec<T> = vec![T::default(); a_rows * b_cols];
+
+    for i in 0..a_rows {
+        for p in 0..a_cols {
+            let a_ip: T = a[i * a_cols + p];
+
+            for j in 0..b_cols {
+                c[i * b_cols + j] += a_ip * b[p * b_cols + j];
+            }
+        }
+    }
+
+    return Ok(c);
+}
+
+// Dense matrix times vector.
+pub fn matvec<T: Scalar>(a: &[T], a_rows: usize, a_cols: usize, x: &[T]) -> Result<Vec<T>, DimError> {
+    check_operands(a.len(), a_rows, a_cols, x.len(), x.len(), 1)?;
+
+    return Ok(a.chunks_exact(a_cols.max(1)).take(a_rows).map(|row| {
+        let mut sum: T = T::default();
+
+        for (value, x_value) in row.iter().zip(x) {
+            sum += *value * *x_value;
+        }
+
+        sum
+    }).collect());
+}
+
+// matmul computed in block_size x block_size tiles so the parts of a, b and
+// c being combined stay in cache.
+pub fn matmul_blocked<T: Scalar>(a: &[T], a_rows: usize, a_cols: usize, b: &[T], b_rows: usize,
+                                 b_cols: usize, block_size: usize) -> Result<Vec<T>, DimError> {
+    check_operands(a.len(), a_rows, a_cols, b.len(), b_rows, b_cols)?;
+
+    let mut c: Vec<T> = vec![T::default(); a_rows * b_cols];
+    let dims: GemmDims = GemmDims { m: a_rows, k: a_cols, n: b_cols };
+
+    for_each_tile(dims, block_size, |tile| update_tile_scalar(a, b, &mut c, dims, tile));
+
+    return Ok(c);
+}
+
+// matmul_blocked that checks cancel before every block row of the output and
+// gives up with CancelError::Cancelled once it is set.
+#[allow(clippy::too_many_arguments)]
+pub fn matmul_blocked_cancellable<T: Scalar>(a: &[T], a_rows: usize, a_cols: usize, b: &[T], b_rows: usize, b_cols: usize,
+                                             block_size: usize, cancel: &AtomicBool) -> Result<Vec<T>, CancelError> {
+    check_operands(a.len(), a_rows, a_cols, b.len(), b_rows, b_cols)?;
+
+    let mut c: Vec<T> = vec![T::default(); a_rows * b_cols];
+    let dims: GemmDims = GemmDims { m: a_rows, k: a_cols, n: b_cols };
+    let block: usize = block_size.max(1);
+
+    for i0 in (0..a_rows).step_by(block) {
+        check_cancel(cancel, i0, a_rows)?;
+
+        for p0 in (0..a_cols).step_by(block) {
+            for j0 in (0..b_cols).step_by(block) {
+                let tile: Tile = Tile {
+                    i0, i1: (i0 + block).min(a_rows),
+                    p0, p1: (p0 + block).min(a_cols),
+                    j0, j1: (j0 + block).min(b_cols),
+                };
+                update_tile_scalar(a, b, &mut c, dims, tile);
+            }
+        }
+    }
+
+    return Ok(c);
+}
+
+// Multiplies a (a_rows x a_cols) by B where the caller passes b_t = B
+// transposed (b_cols x b_rows). Every output element is then a dot product
+// of two contiguous rows, so both operands stream sequentially.
+pub fn matmul_bt<T: Scalar>(a: &[T], a_rows: usize, a_cols: usize, b_t: &[T], b_cols: usize,
+                            b_rows: usize) -> Result<Vec<T>, DimError> {
+    check_operands(a.len(), a_rows, a_cols, b_t.len(), b_rows, b_cols)?;
+
+    let mut c: Vec<T> = vec![T::default(); a_rows * b_cols];
+
+    for i in 0..a_rows {
+        let a_row: &[T] = &a[i * a_cols..(i + 1) * a_cols];
+
+        for j in 0..b_cols {
+            let b_row: &[T] = &b_t[j * b_rows..(j + 1) * b_rows];
+            let mut sum: T = T::default();
+
+            for (x, y) in a_row.iter().zip(b_row) {
+                sum += *x * *y;
+            }
+
+            c[i * b_cols + j] = sum;
+        }
+    }
+
+    return Ok(c);
+}
+
+// Below this many multiply-adds transposing B costs more than it saves.
+pub const BT_MIN_WORK: usize = 1 << 15;
+
+// Multiplies a by b, transposing b and using matmul_bt when the product is
+// large enough for that to pay off and falling back to matmul otherwise.
+pub fn matmul_transposed<T: Scalar>(a: &[T], a_rows: usize, a_cols: usize, b: &[T], b_rows: usize,
+                                    b_cols: usize) -> Result<Vec<T>, DimError> {
+    check_operands(a.len(), a_rows, a_cols, b.len(), b_rows, b_cols)?;
+
+    if a_rows * a_cols * b_cols < BT_MIN_WORK {
+        return matmul(a, a_rows, a_cols, b, b_rows, b_cols);
+    }
+
+    let b_t: Vec<T> = transpose(b, b_rows, b_cols)?;
+
+    return matmul_bt(a, a_rows, a_cols, &b_t, b_cols, b_rows);
+}
+
+// The cheapest order to multiply a chain of matrices A1 A2 ... An, where Ai
+// is dims[i - 1] x dims[i], found with the classic O(n^3) dynamic programme
+// over sub-chains. Tables are indexed from 0: cost[i][j] is the fewest scalar
+// multiplications that compute A(i+1) ... A(j+1), and split[i][j] the k whose
+// product (A(i+1) ... A(k+1)) (A(k+2) ... A(j+1)) achieves it. Entries with
+// i > j are unused and 0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChainPlan {
+    pub dims: Vec<usize>,
+    pub cost: Vec<Vec<u64>>,
+    pub split: Vec<Vec<usize>>,
+}
+
+impl ChainPlan {
+    // Scalar multiplications of the whole chain in this order.
+    pub fn total_cost(&self) -> u64 {
+        return self.cost.first().and_then(|row| row.last()).copied().unwrap_or(0);
+    }
+
+    // The order written out with 1-based names, e.g. "((A1 (A2 A3)) A4)".
+    pub fn parenthesize(&self) -> String {
+        return self.describe(0, self.cost.len().saturating_sub(1));
+    }
+
+    fn describe(&self, i: usize, j: usize) -> String {
+        if i == j {
+            return format!("A{}", i + 1);
+        }
+
+        let k: usize = self.split[i][j];
+        return format!("({} {})", self.describe(i, k), self.describe(k + 1, j));
+    }
+}
+
+// Plans the product of the chain whose matrix i is dims[i] x dims[i + 1].
+// Equal costs go to the smallest split, so the plan is deterministic. Fewer
+// than two dims describe no matrices and give empty tables.
+pub fn chain_order(dims: &[usize]) -> ChainPlan {
+    let n: usize = dims.len().saturating_sub(1);
+    let mut cost: Vec<Vec<u64>> = vec![vec![0; n]; n];
+    let mut split: Vec<Vec<usize>> = vec![vec![0; n]; n];
+
+    // Sub-chains by increasing length, so both halves are always solved.
+    for len in 2..=n {
+        for i in 0..=n - len {
+            let j: usize = i + len - 1;
+            cost[i][j] = u64::MAX;
+
+            for k in i..j {
+                let multiply: u64 = dims[i] as u64 * dims[k + 1] as u64 * dims[j + 1] as u64;
+                let total: u64 = cost[i][k].saturating_add(cost[k + 1][j]).saturating_add(multiply);
+
+                if total < cost[i][j] {
+                    cost[i][j] = total;
+                    split[i][j] = k;
+                }
+            }
+        }
+    }
+
+    return ChainPlan { dims: dims.to_vec(), cost, split };
+}
+
+// Multiplies a chain of (matrix, rows, cols) operands in the order
+// chain_order finds cheapest, each product with matmul_blocked. Matrix
+// products are associative, so the result is the left-to-right product;
+// for floats it can differ from it in the last bits, for integers it is
+// identical.
+pub fn matmul_chain<T: Scalar>(matrices: &[(&[T], usize, usize)]) -> Result<Vec<T>, DimError> {
+    let Some(&(_, first_rows, _)) = matrices.first() else {
+        return Err(DimError::Mismatch { what: "chain length", expected: 1, found: 0 });
+    };
+
+    for (index, &(data, rows, cols)) in matrices.iter().enumerate() {
+        check_len(data.len(), rows, cols)?;
+
+        if let Some(&(_, next_rows, _)) = matrices.get(index + 1) {
+            if cols != next_rows {
+                return Err(DimError::Mismatch { what: "inner dimension", expected: cols, found: next_rows });
+            }
+        }
+    }
+
+    let dims: Vec<usize> = std::iter::once(first_rows).chain(matrices.iter().map(|&(_, _, cols)| cols)).collect();
+    let plan: ChainPlan = chain_order(&dims);
+
+    return Ok(chain_product(matrices, &plan, 0, matrices.len() - 1)?.into_owned());
+}
+
+// The product of matrices i..=j in the order of plan.
+fn chain_product<'a, T: Scalar>(matrices: &[(&'a [T], usize, usize)], plan: &ChainPlan, i: usize,
+                                j: usize) -> Result<Cow<'a, [T]>, DimError> {
+    if i == j {
+        return Ok(Cow::Borrowed(matrices[i].0));
+    }
+
+    let k: usize = plan.split[i][j];
+    let left: Cow<[T]> = chain_product(matrices, plan, i, k)?;
+    let right: Cow<[T]> = chain_product(matrices, plan, k + 1, j)?;
+    let (rows, inner, cols): (usize, usize, usize) = (plan.dims[i], plan.dims[k + 1], plan.dims[j + 1]);
+
+    return Ok(Cow::Owned(matmul_blocked(&left, rows, inner, &right, inner, cols, DEFAULT_BLOCK_SIZE)?));
+}
+
+// Multiplies two f32 matrices. Uses the same tiling as matmul_blocked, with
+// an AVX2 + FMA register-blocked micro kernel for the interior of every tile
+// when the CPU supports it and scalar code otherwise. Results can differ
+// from the scalar product in the last bits since the summation order changes.
+pub fn matmul_f32(a: &[f32], a_rows: usize, a_cols: usize, b: &[f32], b_rows: usize, b_cols: usize,
+                  block_size: usize) -> Result<Vec<f32>, DimError> {
+    if !simd_available() {
+        return matmul_blocked(a, a_rows, a_cols, b, b_rows, b_cols, block_size);
+    }
+
+    check_operands(a.len(), a_rows, a_cols, b.len(), b_rows, b_cols)?;
+
+    let mut c: Vec<f32> = vec![0.0; a_rows * b_cols];
+    let dims: GemmDims = GemmDims { m: a_rows, k: a_cols, n: b_cols };
+
+    #[cfg(target_arch = "x86_64")]
+    for_each_tile(dims, block_size, |tile| {
+        // SAFETY: simd_available() confirmed AVX2 and FMA support.
+        unsafe { x86::update_tile(a, b, &mut c, dims, tile) };
+    });
+
+    return Ok(c);
+}
+
+// Tile sizes tried by autotune_block_size by default.
+pub const BLOCK_SIZE_CANDIDATES: [usize; 5] = [32, 48, 64, 96, 128];
+
+// Side length of the square matrices multiplied during calibration.
+pub const AUTOTUNE_SIZE: usize = 256;
+
+// Times matmul_f32 on random trial_size x trial_size matrices with every
+// candidate block size and returns the fastest. Each candidate gets a few
+// runs and its best time counts, so one noisy run does not decide. Meant to
+// be called once at startup; the whole calibration takes well under a second
+// at the default trial size.
+pub fn autotune_block_size(candidates: &[usize], trial_size: usize) -> usize {
+    const RUNS: usize = 3;
+
+    let a: Vec<f32> = (0..trial_size * trial_size).map(|_| rand::random::<f32>()).collect();
+    let b: Vec<f32> = (0..trial_size * trial_size).map(|_| rand::random::<f32>()).collect();
+    let mut best: (usize, Duration) = (DEFAULT_BLOCK_SIZE, Duration::MAX);
+
+    for &block_size in candidates {
+        for _ in 0..RUNS {
+            let start = Instant::now();
+            let _ = matmul_f32(&a, trial_size, trial_size, &b, trial_size, trial_size, block_size);
+            let elapsed: Duration = start.elapsed();
+
+            if elapsed < best.1 {
+                best = (block_size, elapsed);
+            }
+        }
+    }
+
+    return best.0;
+}
+
+// Whether matmul_f32 can use its SIMD micro kernel on this CPU.
+pub fn simd_available() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        return is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma");
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        return false;
+    }
+}
+
+// Dimensions of a product c (m x n) = a (m x k) * b (k x n).
+#[derive(Clone, Copy)]
+struct GemmDims {
+    m: usize,
+    k: usize,
+    n: usize,
+}
+
+// Half-open index ranges of one tile: rows of c, the shared dimension, and
+// columns of c.
+#[derive(Clone, Copy)]
+struct Tile {
+    i0: usize,
+    i1: usize,
+    p0: usize,
+    p1: usize,
+    j0: usize,
+    j1: usize,
+}
+
+fn for_each_tile(dims: GemmDims, block_size: usize, mut f: impl FnMut(Tile)) {
+    let block: usize = block_size.max(1);
+
+    for i0 in (0..dims.m).step_by(block) {
+        for p0 in (0..dims.k).step_by(block) {
+            for j0 in (0..dims.n).step_by(block) {
+                f(Tile {
+                    i0, i1: (i0 + block).min(dims.m),
+                    p0, p1: (p0 + block).min(dims.k),
+                    j0, j1: (j0 + block).min(dims.n),
+                });
+            }
+        }
+    }
+}
+
+// Adds the contribution of one tile to c.
+fn update_tile_scalar<T: Scalar>(a: &[T], b: &[T], c: &mut [T], dims: GemmDims, tile: Tile) {
+    for i in tile.i0..tile.i1 {
+        for p in tile.p0..tile.p1 {
+            let a_ip: T = a[i * dims.k + p];
+
+            for j in tile.j0..tile.j1 {
+                c[i * dims.n + j] += a_ip * b[p * dims.n + j];
+            }
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+    use super::{update_tile_scalar, GemmDims, Tile};
+
+    // Rows and columns of c computed by one micro kernel call.
+    const MR: usize = 8;
+    const NR: usize = 8;
+
+    // Adds the contribution of one tile to c. Full MR x NR blocks go through
+    // the micro kernel; the ragged right and bottom edges use scalar code.
+    #[target_feature(enable = "avx2,fma")]
+    pub(super) unsafe fn update_tile(a: &[f32], b: &[f32], c: &mut [f32], dims: GemmDims, tile: Tile) {
+        let full_i1: usize = tile.i0 + (tile.i1 - tile.i0) / MR * MR;
+        let full_j1: usize = tile.j0 + (tile.j1 - tile.j0) / NR * NR;
+
+        for i in (tile.i0..full_i1).step_by(MR) {
+            for j in (tile.j0..full_j1).step_by(NR) {
+                kernel_8x8(a, b, c, dims, i, j, tile.p0, tile.p1);
+            }
+        }
+
+        update_tile_scalar(a, b, c, dims, Tile { j0: full_j1, ..tile });
+        update_tile_scalar(a, b, c, dims, Tile { i0: full_i1, j1: full_j1, ..tile });
+    }
+
+    // c[i..i + 8][j..j + 8] += a[i..i + 8][p0..p1] * b[p0..p1][j..j + 8],
+    // keeping the 8x8 block of c in eight ymm registers.
+    #[target_feature(enable = "avx2,fma")]
+    #[allow(clippy::too_many_arguments)]
+    unsafe fn kernel_8x8(a: &[f32], b: &[f32], c: &mut [f32], dims: GemmDims, i: usize, j: usize, p0: usize, p1: usize) {
+        assert!((i + MR - 1) * dims.n + j + NR <= c.len());
+        assert!(p1 == p0 || ((p1 - 1) * dims.n + j + NR <= b.len() && (i + MR - 1) * dims.k + p1 <= a.len()));
+
+        let mut acc: [__m256; MR] = [_mm256_setzero_ps(); MR];
+        let c_ptr: *mut f32 = c.as_mut_ptr();
+
+        for (r, row) in acc.iter_mut().enumerate() {
+            *row = _mm256_loadu_ps(c_ptr.add((i + r) * dims.n + j));
+        }
+
+        for p in p0..p1 {
+            let b_row: __m256 = _mm256_loadu_ps(b.as_ptr().add(p * dims.n + j));
+
+            for (r, row) in acc.iter_mut().enumerate() {
+                let a_value: __m256 = _mm256_set1_ps(*a.get_unchecked((i + r) * dims.k + p));
+                *row = _mm256_fmadd_ps(a_value, b_row, *row);
+            }
+        }
+
+        for (r, row) in acc.iter().enumerate() {
+            _mm256_storeu_ps(c_ptr.add((i + r) * dims.n + j), *row);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::approx_eq;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha12Rng;
+
+    // Shapes (m, k, n) of the products tested, including vectors and the
+    // sizes large enough for matmul_transposed to take the transposing path.
+    const SHAPES: [(usize, usize, usize); 7] = [(1, 1, 1), (1, 7, 1), (3, 5, 2), (7, 1, 9), (17, 33, 5), (5, 33, 17), (64, 40, 70)];
+
+    fn random(len: usize, seed: u64) -> Vec<i64> {
+        let mut rng: ChaCha12Rng = ChaCha12Rng::seed_from_u64(seed);
+
+        return (0..len).map(|_| rng.gen_range(-50..50)).collect();
+    }
+
+    #[test]
+    fn matmul_bt_of_the_transpose_equals_matmul() {
+        for (seed, &(m, k, n)) in SHAPES.iter().enumerate() {
+            let a: Vec<i64> = random(m * k, seed as u64);
+            let b: Vec<i64> = random(k * n, seed as u64 + 100);
+            let expected: Vec<i64> = matmul(&a, m, k, &b, k, n).unwrap();
+            let b_t: Vec<i64> = transpose(&b, k, n).unwrap();
+
+            assert_eq!(matmul_bt(&a, m, k, &b_t, n, k).unwrap(), expected, "{}x{} times {}x{}", m, k, k, n);
+            assert_eq!(matmul_transposed(&a, m, k, &b, k, n).unwrap(), expected, "{}x{} times {}x{}", m, k, k, n);
+        }
+    }
+
+    #[test]
+    fn matmul_bt_checks_its_operands() {
+        assert!(matmul_bt(&[1i64; 6], 2, 3, &[1; 8], 4, 2).is_err());
+        assert!(matmul_bt(&[1i64; 6], 2, 3, &[1; 7], 2, 3).is_err());
+    }
+
+    #[test]
+    fn blocked_products_equal_matmul() {
+        for (seed, &(m, k, n)) in SHAPES.iter().enumerate() {
+            let a: Vec<i64> = random(m * k, seed as u64);
+            let b: Vec<i64> = random(k * n, seed as u64 + 100);
+            let expected: Vec<i64> = matmul(&a, m, k, &b, k, n).unwrap();
+
+            for block_size in [1, 4, 16, DEFAULT_BLOCK_SIZE] {
+                assert_eq!(matmul_blocked(&a, m, k, &b, k, n, block_size).unwrap(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn matmul_f32_matches_the_scalar_product() {
+        for &(m, k, n) in &SHAPES {
+            let to_f32 = |values: Vec<i64>| -> Vec<f32> { values.iter().map(|&value| value as f32 / 7.0).collect() };
+            let a: Vec<f32> = to_f32(random(m * k, 1));
+            let b: Vec<f32> = to_f32(random(k * n, 2));
+            let expected: Vec<f32> = matmul(&a, m, k, &b, k, n).unwrap();
+
+            assert!(approx_eq(&matmul_f32(&a, m, k, &b, k, n, 16).unwrap(), &expected, 1e-4));
+        }
+    }
+
+    #[test]
+    fn chain_order_finds_the_textbook_order() {
+        let plan: ChainPlan = chain_order(&[10, 30, 5, 60]);
+        assert_eq!(plan.parenthesize(), "((A1 A2) A3)");
+        assert_eq!(plan.total_cost(), 4500);
+
+        // Right to left would cost 27000.
+        assert_eq!(plan.cost[1][2], 30 * 5 * 60);
+
+        assert_eq!(chain_order(&[4, 7]).parenthesize(), "A1");
+        assert_eq!(chain_order(&[4, 7]).total_cost(), 0);
+        assert_eq!(chain_order(&[]).total_cost(), 0);
+    }
+
+    #[test]
+    fn matmul_chain_equals_the_left_to_right_product() {
+        let dims: [usize; 6] = [10, 30, 5, 60, 1, 8];
+        let operands: Vec<Vec<i64>> = dims.windows(2).enumerate().map(|(seed, pair)| random(pair[0] * pair[1], seed as u64)).collect();
+        let chain: Vec<(&[i64], usize, usize)> = operands.iter().zip(dims.windows(2)).map(|(data, pair)| (data.as_slice(), pair[0], pair[1])).collect();
+
+        let mut expected: Vec<i64> = operands[0].clone();
+        for (index, data) in operands.iter().enumerate().skip(1) {
+            expected = matmul(&expected, dims[0], dims[index], data, dims[index], dims[index + 1]).unwrap();
+        }
+
+        assert_eq!(matmul_chain(&chain).unwrap(), expected);
+        assert_eq!(matmul_chain(&chain[..1]).unwrap(), operands[0]);
+    }
+
+    #[test]
+    fn matmul_chain_checks_its_operands() {
+        let (a, b): (Vec<i64>, Vec<i64>) = (random(6, 1), random(8, 2));
+
+        assert_eq!(matmul_chain(&[(&a[..], 2, 3), (&b[..], 4, 2)]), Err(DimError::Mismatch { what: "inner dimension", expected: 3, found: 4 }));
+        assert!(matmul_chain(&[(&a[..], 2, 3), (&b[..], 3, 3)]).is_err());
+        assert!(matmul_chain::<i64>(&[]).is_err());
+    }
+}
@@ -0,0 +1,295 @@
+use std::sync::atomic::AtomicBool;
+#[cfg(feature = "parallel")]
+use std::thread;
+use crate::cancel::check_cancel;
+use crate::error::{check_len, CancelError, DimError};
+use crate::index::flat;
+use crate::ops::transpose;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+
+// Constructs a matrix of specified dimensions with random non-negative values
+pub fn construct_randomized_matrix(rows: usize, cols: usize) -> Vec<u8> {
+    let mut arr: Vec<u8> = vec![0; rows * cols];
+
+    for row in 0..rows {
+        for col in 0..cols {
+            arr[flat(row, col, cols)] = rand::random();
+        }
+    }
+
+    return arr;
+}
+
+// Constructs a matrix of specified dimensions with random non-negative
+// values. The same seed always produces the same matrix.
+//
+// The contract, which every seeded generator in this module keeps: the
+// matrix is the ChaCha12 keystream for seed (the generator behind
+// rand::rngs::StdRng, seeded with seed_from_u64), one byte per element in
+// row-major order. Each RNG_CHUNK bytes are generated independently from
+// (seed, chunk index) by seeking the stream to the chunk's start, so the
+// result does not depend on how the chunks are split across threads, and is
+// the same for the serial, parallel and cancellable variants.
+pub fn construct_randomized_matrix_seeded(rows: usize, cols: usize, seed: u64) -> Vec<u8> {
+    let mut arr: Vec<u8> = vec![0; rows * cols];
+
+    fill_randomized_seeded(&mut arr, seed);
+
+    return arr;
+}
+
+// construct_randomized_matrix_seeded generating chunks on up to threads
+// threads. The result is identical whatever the thread count.
+pub fn construct_randomized_matrix_seeded_par(rows: usize, cols: usize, seed: u64, threads: usize) -> Vec<u8> {
+    let mut arr: Vec<u8> = vec![0; rows * cols];
+
+    fill_randomized_seeded_par(&mut arr, seed, threads);
+
+    return arr;
+}
+
+// construct_randomized_matrix_seeded with the rows split into the bands
+// compute_dx_par_with and compute_dy_par_with use, band i generated on its own
+// worker after on_start(i) has run there. A worker pinned by on_start is thus
+// the first to touch the pages it later reads, which keeps them on its NUMA
+// node. The result is identical whatever the thread count.
+#[cfg(feature = "parallel")]
+pub fn construct_randomized_matrix_seeded_bands<F: Fn(usize) + Sync>(rows: usize, cols: usize, seed: u64, threads: usize,
+                                                                     on_start: F) -> Vec<u8> {
+    let mut arr: Vec<u8> = vec![0; rows * cols];
+
+    if arr.is_empty() || threads <= 1 {
+        fill_randomized_seeded(&mut arr, seed);
+        return arr;
+    }
+
+    let band: usize = rows.div_ceil(threads);
+    let on_start: &F = &on_start;
+
+    thread::scope(|scope| {
+        for (index, out) in arr.chunks_mut(band * cols).enumerate() {
+            scope.spawn(move || {
+                on_start(index);
+                fill_from(out, seed, index * band * cols);
+            });
+        }
+    });
+
+    return arr;
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn construct_randomized_matrix_seeded_bands<F: Fn(usize) + Sync>(rows: usize, cols: usize, seed: u64, _threads: usize,
+                                                                     _on_start: F) -> Vec<u8> {
+    return construct_randomized_matrix_seeded(rows, cols, seed);
+}
+
+// Bytes generated from one position of the seeded stream. A multiple of 64,
+// the ChaCha block size, so every chunk starts on a block boundary.
+pub const RNG_CHUNK: usize = 64 << 10;
+
+// Overwrites arr with the values construct_randomized_matrix_seeded would
+// return for a matrix of arr.len() elements.
+pub fn fill_randomized_seeded(arr: &mut [u8], seed: u64) {
+    for (index, chunk) in arr.chunks_mut(RNG_CHUNK).enumerate() {
+        fill_chunk(chunk, seed, index);
+    }
+}
+
+// fill_randomized_seeded with the chunks split into bands filled on up to
+// threads threads. Without the parallel feature this runs on the calling
+// thread.
+#[cfg(feature = "parallel")]
+pub fn fill_randomized_seeded_par(arr: &mut [u8], seed: u64, threads: usize) {
+    let chunks: usize = arr.len().div_ceil(RNG_CHUNK);
+
+    if chunks <= 1 || threads <= 1 {
+        return fill_randomized_seeded(arr, seed);
+    }
+
+    let band: usize = chunks.div_ceil(threads);
+
+    thread::scope(|scope| {
+        for (band_index, out) in arr.chunks_mut(band * RNG_CHUNK).enumerate() {
+            scope.spawn(move || {
+                for (offset, chunk) in out.chunks_mut(RNG_CHUNK).enumerate() {
+                    fill_chunk(chunk, seed, band_index * band + offset);
+                }
+            });
+        }
+    });
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn fill_randomized_seeded_par(arr: &mut [u8], seed: u64, _threads: usize) {
+    fill_randomized_seeded(arr, seed);
+}
+
+// Fills chunk with the seeded stream from byte index * RNG_CHUNK on.
+fn fill_chunk(chunk: &mut [u8], seed: u64, index: usize) {
+    let mut rng: ChaCha12Rng = ChaCha12Rng::seed_from_u64(seed);
+
+    // The word position counts 32-bit words.
+    rng.set_word_pos((index * RNG_CHUNK / 4) as u128);
+    rng.fill_bytes(chunk);
+}
+
+// Fills out with the seeded stream from byte start on, which unlike a chunk
+// need not start on a block boundary.
+#[cfg(feature = "parallel")]
+fn fill_from(out: &mut [u8], seed: u64, start: usize) {
+    let mut rng: ChaCha12Rng = ChaCha12Rng::seed_from_u64(seed);
+    // The stream is read a 32-bit word at a time, so a start inside a word
+    // takes the rest of that word first.
+    let skip: usize = start % 4;
+    let head: usize = if skip == 0 { 0 } else { (4 - skip).min(out.len()) };
+
+    rng.set_word_pos((start / 4) as u128);
+
+    if head > 0 {
+        let mut word: [u8; 4] = [0; 4];
+        rng.fill_bytes(&mut word);
+        out[..head].copy_from_slice(&word[skip..skip + head]);
+    }
+
+    rng.fill_bytes(&mut out[head..]);
+}
+
+// Bytes generated between two checks of the cancel token, a whole number of
+// RNG chunks.
+const GENERATE_CHUNK: usize = 16 * RNG_CHUNK;
+
+// construct_randomized_matrix (seed None) or
+// construct_randomized_matrix_seeded that checks cancel after every
+// GENERATE_CHUNK bytes. A seeded result is identical to the non-cancellable
+// one; without a seed a random one is used.
+pub fn construct_randomized_matrix_cancellable(rows: usize, cols: usize, seed: Option<u64>,
+                                               cancel: &AtomicBool) -> Result<Vec<u8>, CancelError> {
+    let seed: u64 = seed.unwrap_or_else(rand::random);
+    let mut arr: Vec<u8> = vec![0; rows * cols];
+
+    for (index, block) in arr.chunks_mut(GENERATE_CHUNK).enumerate() {
+        check_cancel(cancel, index * GENERATE_CHUNK / cols.max(1), rows)?;
+
+        for (offset, chunk) in block.chunks_mut(RNG_CHUNK).enumerate() {
+            fill_chunk(chunk, seed, index * (GENERATE_CHUNK / RNG_CHUNK) + offset);
+        }
+    }
+
+    return Ok(arr);
+}
+
+// Order in which the elements of a matrix are stored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Layout {
+    // Element (row, col) at row * cols + col.
+    #[default]
+    RowMajor,
+    // Element (row, col) at col * rows + row, as produced by Fortran.
+    ColMajor,
+}
+
+// A matrix whose data is stored in the given layout.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Matrix<T> {
+    pub data: Vec<T>,
+    pub rows: usize,
+    pub cols: usize,
+    pub layout: Layout,
+}
+
+impl<T: Copy> Matrix<T> {
+    pub fn new(data: Vec<T>, rows: usize, cols: usize, layout: Layout) -> Result<Matrix<T>, DimError> {
+        check_len(data.len(), rows, cols)?;
+
+        return Ok(Matrix { data, rows, cols, layout });
+    }
+
+    // A row-major rows x cols matrix whose element (row, col) is f(row, col),
+    // called in row-major order.
+    pub fn from_fn(rows: usize, cols: usize, mut f: impl FnMut(usize, usize) -> T) -> Matrix<T> {
+        let data: Vec<T> = (0..rows * cols).map(|index| f(index / cols, index % cols)).collect();
+
+        return Matrix { data, rows, cols, layout: Layout::RowMajor };
+    }
+
+    // Position of element (row, col) in data.
+    pub fn index(&self, row: usize, col: usize) -> usize {
+        return match self.layout {
+            Layout::RowMajor => row * self.cols + col,
+            Layout::ColMajor => col * self.rows + row,
+        };
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> T {
+        return self.data[self.index(row, col)];
+    }
+
+    // Returns the same logical matrix stored in the requested layout. Changing
+    // layout is a transpose of the underlying buffer and allocates a copy.
+    pub fn to_layout(&self, layout: Layout) -> Matrix<T> {
+        if layout == self.layout {
+            return self.clone();
+        }
+
+        // A row-major rows x cols buffer read as col-major is its transpose,
+        // and vice versa, so either direction is one transpose.
+        let (buffer_rows, buffer_cols) = match self.layout {
+            Layout::RowMajor => (self.rows, self.cols),
+            Layout::ColMajor => (self.cols, self.rows),
+        };
+        let data: Vec<T> = transpose(&self.data, buffer_rows, buffer_cols).expect("Matrix data has unexpected length");
+
+        return Matrix { data, rows: self.rows, cols: self.cols, layout };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_follows_the_layout() {
+        let row_major: Matrix<u8> = Matrix::new((0..6).collect(), 2, 3, Layout::RowMajor).unwrap();
+        let col_major: Matrix<u8> = Matrix::new(vec![0, 3, 1, 4, 2, 5], 2, 3, Layout::ColMajor).unwrap();
+
+        for row in 0..2 {
+            for col in 0..3 {
+                assert_eq!(row_major.get(row, col), col_major.get(row, col));
+            }
+        }
+
+        assert_eq!(col_major.index(1, 0), 1);
+        assert_eq!(col_major.index(0, 1), 2);
+        assert!(Matrix::new(vec![0u8; 5], 2, 3, Layout::ColMajor).is_err());
+    }
+
+    #[test]
+    fn to_layout_keeps_the_logical_matrix() {
+        let matrix: Matrix<u8> = Matrix::from_fn(3, 5, |row, col| (row * 10 + col) as u8);
+        let col_major: Matrix<u8> = matrix.to_layout(Layout::ColMajor);
+
+        assert_eq!(col_major.layout, Layout::ColMajor);
+        assert_eq!(col_major.data[..4], [0, 10, 20, 1]);
+        assert_eq!(col_major.to_layout(Layout::RowMajor), matrix);
+        assert_eq!(matrix.to_layout(Layout::RowMajor), matrix);
+    }
+
+    // Both builds: banded and threaded generation give the seeded stream
+    // whatever the thread count.
+    #[test]
+    fn par_generation_matches_the_seeded_stream() {
+        for (rows, cols) in [(0, 3), (1, 1), (7, 5), (3, RNG_CHUNK + 13), (9, 1000)] {
+            let expected: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, 5);
+
+            for threads in [1, 2, 3, 8] {
+                assert!(construct_randomized_matrix_seeded_bands(rows, cols, 5, threads, |_| {}) == expected, "bands {}x{} {} threads", rows, cols, threads);
+
+                let mut filled: Vec<u8> = vec![0; rows * cols];
+                fill_randomized_seeded_par(&mut filled, 5, threads);
+                assert!(filled == expected, "fill {}x{} {} threads", rows, cols, threads);
+            }
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Heap usage counters, only updated when CountingAllocator is installed as
+// the global allocator (the binary does so with the mem-stats feature).
+static ALLOC_COUNT: AtomicUsize = AtomicUsize::new(0);
+static CURRENT_BYTES: AtomicUsize = AtomicUsize::new(0);
+static PEAK_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    // The same counters for the allocations of one thread, so that a
+    // measurement is not disturbed by other threads, such as tests running in
+    // parallel. Const initialized, so reading them never allocates.
+    static THREAD_STATS: Cell<MemStats> = const { Cell::new(MemStats { alloc_count: 0, peak_bytes: 0, current_bytes: 0 }) };
+}
+
+// Heap usage since start or the last reset.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemStats {
+    // Allocations and reallocations made.
+    pub alloc_count: usize,
+    // Most bytes live at any one time. Only heap memory is counted, so this
+    // is an estimate of the peak RSS rather than the real thing.
+    pub peak_bytes: usize,
+    // Bytes live right now.
+    pub current_bytes: usize,
+}
+
+// The system allocator, counting every allocation and tracking the peak
+// number of live bytes. Install it with
+//
+//     #[global_allocator]
+//     static ALLOCATOR: CountingAllocator = CountingAllocator;
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr: *mut u8 = System.alloc(layout);
+
+        if !ptr.is_null() {
+            record_alloc(layout.size());
+        }
+
+        return ptr;
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr: *mut u8 = System.alloc_zeroed(layout);
+
+        if !ptr.is_null() {
+            record_alloc(layout.size());
+        }
+
+        return ptr;
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        record_dealloc(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr: *mut u8 = System.realloc(ptr, layout, new_size);
+
+        // On failure the old block is untouched and still counted.
+        if !new_ptr.is_null() {
+            record_dealloc(layout.size());
+            record_alloc(new_size);
+        }
+
+        return new_ptr;
+    }
+}
+
+fn record_alloc(size: usize) {
+    ALLOC_COUNT.fetch_add(1, Ordering::Relaxed);
+    let current: usize = CURRENT_BYTES.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK_BYTES.fetch_max(current, Ordering::Relaxed);
+
+    // Not available while the thread is being torn down.
+    let _ = THREAD_STATS.try_with(|stats| {
+        let mut thread: MemStats = stats.get();
+        thread.alloc_count += 1;
+        thread.current_bytes += size;
+        thread.peak_bytes = thread.peak_bytes.max(thread.current_bytes);
+        stats.set(thread);
+    });
+}
+
+fn record_dealloc(size: usize) {
+    CURRENT_BYTES.fetch_sub(size, Ordering::Relaxed);
+
+    // Memory allocated by another thread may be freed by this one, so the
+    // thread's count stops at zero.
+    let _ = THREAD_STATS.try_with(|stats| {
+        let mut thread: MemStats = stats.get();
+        thread.current_bytes = thread.current_bytes.saturating_sub(size);
+        stats.set(thread);
+    });
+}
+
+// Heap usage recorded so far. All zeros unless CountingAllocator is the
+// global allocator.
+pub fn snapshot() -> MemStats {
+    return MemStats {
+        alloc_count: ALLOC_COUNT.load(Ordering::Relaxed),
+        peak_bytes: PEAK_BYTES.load(Ordering::Relaxed),
+        current_bytes: CURRENT_BYTES.load(Ordering::Relaxed),
+    };
+}
+
+// Starts a new measurement: the allocation count goes back to zero and the
+// peak to what is live now.
+pub fn reset() {
+    ALLOC_COUNT.store(0, Ordering::Relaxed);
+    PEAK_BYTES.store(CURRENT_BYTES.load(Ordering::Relaxed), Ordering::Relaxed);
+}
+
+// snapshot for the allocations made by the calling thread only.
+pub fn thread_snapshot() -> MemStats {
+    return THREAD_STATS.with(Cell::get);
+}
+
+// reset for the calling thread's counters, see thread_snapshot.
+pub fn thread_reset() {
+    THREAD_STATS.with(|stats| {
+        let current: usize = stats.get().current_bytes;
+        stats.set(MemStats { alloc_count: 0, peak_bytes: current, current_bytes: current });
+    });
+}
+
+#[cfg(all(test, feature = "mem-stats"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_allocation_raises_the_peak_and_the_count() {
+        const N: usize = 1 << 20;
+
+        thread_reset();
+        reset();
+        let before: MemStats = thread_snapshot();
+        let buffer: Vec<u8> = Vec::with_capacity(N);
+        let during: MemStats = thread_snapshot();
+
+        assert_eq!(during.alloc_count, before.alloc_count + 1);
+        assert!(during.peak_bytes >= before.peak_bytes + N, "{:?} -> {:?}", before, during);
+        assert_eq!(during.current_bytes, before.current_bytes + N);
+        assert!(snapshot().peak_bytes >= N);
+
+        drop(buffer);
+        let after: MemStats = thread_snapshot();
+        assert_eq!(after.current_bytes, before.current_bytes);
+        assert_eq!(after.peak_bytes, during.peak_bytes);
+    }
+
+    #[test]
+    fn a_reallocation_counts_and_a_reset_starts_over() {
+        thread_reset();
+        let live: usize = thread_snapshot().current_bytes;
+        let mut buffer: Vec<u64> = Vec::with_capacity(16);
+        buffer.reserve_exact(1024);
+        let grown: MemStats = thread_snapshot();
+
+        assert_eq!(grown.alloc_count, 2);
+        assert_eq!(grown.current_bytes, live + buffer.capacity() * 8);
+
+        thread_reset();
+        assert_eq!(thread_snapshot(), MemStats { alloc_count: 0, peak_bytes: grown.current_bytes, current_bytes: grown.current_bytes });
+    }
+}
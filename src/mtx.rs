@@ -0,0 +1,415 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use crate::index::flat;
+use crate::io::invalid_data;
+use crate::sparse::CsrMatrix;
+
+// First token of every Matrix Market file.
+pub const MARKET_BANNER: &str = "%%MatrixMarket";
+
+// Type of the values in a Matrix Market file. Pattern files list positions
+// only; their entries read back as 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarketField {
+    Real,
+    Integer,
+    Pattern,
+}
+
+impl MarketField {
+    fn name(self) -> &'static str {
+        return match self {
+            MarketField::Real => "real",
+            MarketField::Integer => "integer",
+            MarketField::Pattern => "pattern",
+        };
+    }
+}
+
+// Which entries a file stores. Symmetric files store the lower triangle,
+// skew-symmetric files the strictly lower triangle; the rest is mirrored
+// (negated for skew-symmetric) on reading.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Symmetry {
+    General,
+    Symmetric,
+    SkewSymmetric,
+}
+
+// A matrix read from or written to a Matrix Market file. The array format
+// holds a dense matrix, stored row-major here although the file lists it
+// column by column. The coordinate format holds a sparse one.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MarketMatrix {
+    Array { field: MarketField, rows: usize, cols: usize, values: Vec<f64> },
+    Coordinate { field: MarketField, matrix: CsrMatrix<f64> },
+}
+
+impl MarketMatrix {
+    pub fn rows(&self) -> usize {
+        return match self {
+            MarketMatrix::Array { rows, .. } => *rows,
+            MarketMatrix::Coordinate { matrix, .. } => matrix.rows,
+        };
+    }
+
+    pub fn cols(&self) -> usize {
+        return match self {
+            MarketMatrix::Array { cols, .. } => *cols,
+            MarketMatrix::Coordinate { matrix, .. } => matrix.cols,
+        };
+    }
+
+    // The matrix as a dense row-major buffer, whichever format it came in.
+    pub fn to_dense(&self) -> Vec<f64> {
+        return match self {
+            MarketMatrix::Array { values, .. } => values.clone(),
+            MarketMatrix::Coordinate { matrix, .. } => matrix.to_dense(),
+        };
+    }
+}
+
+// Reads a Matrix Market file in the array or coordinate format. Only real,
+// integer and pattern fields are supported, with general, symmetric or
+// skew-symmetric storage; anything else is rejected with InvalidData.
+pub fn read_matrix_market(path: &Path) -> std::io::Result<MarketMatrix> {
+    let text: String = fs::read_to_string(path)?;
+
+    return parse_matrix_market(&text);
+}
+
+pub fn parse_matrix_market(text: &str) -> std::io::Result<MarketMatrix> {
+    let mut lines = text.lines().enumerate().map(|(index, line)| (index + 1, line));
+    let banner: &str = lines.next().map(|(_, line)| line).unwrap_or("");
+    let (coordinate, field, symmetry) = parse_banner(banner)?;
+
+    // Comments and blank lines may appear before and among the data.
+    let mut data = lines.filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('%'));
+    let (size_line, size) = data.next().ok_or_else(|| invalid_data("missing size line"))?;
+    let size: Vec<usize> = parse_fields(size, size_line)?;
+    // Counted before anything is allocated, so a size line claiming more
+    // entries than the file holds is rejected rather than trusted.
+    let data: Vec<(usize, &str)> = data.collect();
+
+    match (coordinate, &size[..]) {
+        (false, &[rows, cols]) => {
+            if symmetry != Symmetry::General && rows != cols {
+                return Err(invalid_data(&format!("{}x{} matrix cannot be symmetric", rows, cols)));
+            }
+
+            let len: usize = rows.checked_mul(cols)
+                .ok_or_else(|| invalid_data(&format!("{}x{} matrix has more elements than fit in memory", rows, cols)))?;
+            // For square symmetric storage len - rows is even: the elements
+            // off the diagonal, half of them stored.
+            let stored: usize = match symmetry {
+                Symmetry::General => len,
+                Symmetry::Symmetric => (len - rows) / 2 + rows,
+                Symmetry::SkewSymmetric => (len - rows) / 2,
+            };
+
+            if data.len() != stored {
+                return Err(invalid_data(&format!("size line declares {} entries but the file has {}", stored, data.len())));
+            }
+
+            let mut values: Vec<f64> = vec![0.0; len];
+            // Entries are listed column by column, only the stored triangle
+            // for symmetric storage.
+            let mut positions = (0..cols).flat_map(|col| (0..rows).map(move |row| (row, col))).filter(|&(row, col)| match symmetry {
+                Symmetry::General => true,
+                Symmetry::Symmetric => row >= col,
+                Symmetry::SkewSymmetric => row > col,
+            });
+
+            for (line_number, line) in data {
+                let (row, col) = positions.next().expect("one position per counted entry");
+                let [text] = line.split_whitespace().collect::<Vec<&str>>()[..] else {
+                    return Err(invalid_data(&format!("line {}: expected one value", line_number)));
+                };
+                let value: f64 = parse_value(text, field, line_number)?;
+
+                set_mirrored(&mut values, cols, row, col, value, symmetry);
+            }
+
+            return Ok(MarketMatrix::Array { field, rows, cols, values });
+        }
+        (true, &[rows, cols, nnz]) => {
+            if rows.checked_mul(cols).is_some_and(|len| nnz > len) {
+                return Err(invalid_data(&format!("size line declares {} entries in a {}x{} matrix", nnz, rows, cols)));
+            }
+
+            if data.len() != nnz {
+                return Err(invalid_data(&format!("size line declares {} entries but the file has {}", nnz, data.len())));
+            }
+
+            let mut entries: Vec<(usize, usize, f64)> = Vec::with_capacity(nnz);
+
+            for (line_number, line) in data {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                let expected: usize = if field == MarketField::Pattern { 2 } else { 3 };
+
+                if fields.len() != expected {
+                    return Err(invalid_data(&format!("line {}: expected {} fields, found {}", line_number, expected, fields.len())));
+                }
+
+                let index = |text: &str, limit: usize| text.parse::<usize>().ok().filter(|&index| index >= 1 && index <= limit);
+                let (row, col) = match (index(fields[0], rows), index(fields[1], cols)) {
+                    (Some(row), Some(col)) => (row - 1, col - 1),
+                    _ => return Err(invalid_data(&format!("line {}: position {} {} outside the {}x{} matrix", line_number,
+                                                          fields[0], fields[1], rows, cols))),
+                };
+                let value: f64 = match fields.get(2) {
+                    Some(text) => parse_value(text, field, line_number)?,
+                    None => 1.0,
+                };
+
+                entries.push((row, col, value));
+
+                if symmetry != Symmetry::General && row != col {
+                    entries.push((col, row, if symmetry == Symmetry::SkewSymmetric { -value } else { value }));
+                }
+            }
+
+            return Ok(MarketMatrix::Coordinate { field, matrix: csr_from_entries(rows, cols, entries)? });
+        }
+        _ => return Err(invalid_data(&format!("line {}: invalid size line", size_line))),
+    }
+}
+
+// Writes matrix with a general banner: the array format column by column,
+// the coordinate format one 1-based entry per line in row order.
+pub fn write_matrix_market(path: &Path, matrix: &MarketMatrix) -> std::io::Result<()> {
+    let mut text: String = String::new();
+
+    match matrix {
+        MarketMatrix::Array { field, rows, cols, values } => {
+            if *field == MarketField::Pattern {
+                return Err(invalid_data("the array format cannot hold a pattern matrix"));
+            }
+
+            writeln!(text, "{} matrix array {} general", MARKET_BANNER, field.name()).expect("writing to a String");
+            writeln!(text, "{} {}", rows, cols).expect("writing to a String");
+
+            for col in 0..*cols {
+                for row in 0..*rows {
+                    writeln!(text, "{}", format_value(values[flat(row, col, *cols)], *field)).expect("writing to a String");
+                }
+            }
+        }
+        MarketMatrix::Coordinate { field, matrix } => {
+            writeln!(text, "{} matrix coordinate {} general", MARKET_BANNER, field.name()).expect("writing to a String");
+            writeln!(text, "{} {} {}", matrix.rows, matrix.cols, matrix.nnz()).expect("writing to a String");
+
+            for row in 0..matrix.rows {
+                for i in matrix.row_ptr[row]..matrix.row_ptr[row + 1] {
+                    match field {
+                        MarketField::Pattern => writeln!(text, "{} {}", row + 1, matrix.col_idx[i] + 1),
+                        _ => writeln!(text, "{} {} {}", row + 1, matrix.col_idx[i] + 1, format_value(matrix.values[i], *field)),
+                    }.expect("writing to a String");
+                }
+            }
+        }
+    }
+
+    return fs::write(path, text);
+}
+
+// Parses "%%MatrixMarket matrix <format> <field> <symmetry>" into whether the
+// format is coordinate, the field and the symmetry. Keywords are matched
+// case-insensitively, as the format allows.
+fn parse_banner(line: &str) -> std::io::Result<(bool, MarketField, Symmetry)> {
+    let tokens: Vec<String> = line.split_whitespace().map(|token| token.to_lowercase()).collect();
+
+    if tokens.first().map(|token| token.as_str()) != Some(&MARKET_BANNER.to_lowercase()) {
+        return Err(invalid_data(&format!("missing {} banner", MARKET_BANNER)));
+    }
+
+    let [_, object, format, field, symmetry] = &tokens[..] else {
+        return Err(invalid_data("banner must be: %%MatrixMarket matrix <format> <field> <symmetry>"));
+    };
+
+    if object != "matrix" {
+        return Err(invalid_data(&format!("unsupported object '{}', expected matrix", object)));
+    }
+
+    let coordinate: bool = match format.as_str() {
+        "array" => false,
+        "coordinate" => true,
+        other => return Err(invalid_data(&format!("unsupported format '{}', expected array or coordinate", other))),
+    };
+    let field: MarketField = match field.as_str() {
+        "real" => MarketField::Real,
+        "integer" => MarketField::Integer,
+        "pattern" if coordinate => MarketField::Pattern,
+        other => return Err(invalid_data(&format!("unsupported field type '{}', expected real, integer{}", other,
+                                                  if coordinate { " or pattern" } else { "" }))),
+    };
+    let symmetry: Symmetry = match symmetry.as_str() {
+        "general" => Symmetry::General,
+        "symmetric" => Symmetry::Symmetric,
+        "skew-symmetric" => Symmetry::SkewSymmetric,
+        other => return Err(invalid_data(&format!("unsupported symmetry '{}', expected general, symmetric or skew-symmetric", other))),
+    };
+
+    return Ok((coordinate, field, symmetry));
+}
+
+fn parse_fields<T: std::str::FromStr>(line: &str, line_number: usize) -> std::io::Result<Vec<T>> {
+    return line.split_whitespace().map(|field| field.parse::<T>())
+        .collect::<Result<_, _>>().map_err(|_| invalid_data(&format!("line {}: invalid number", line_number)));
+}
+
+// Parses one value; integer files must hold whole numbers.
+fn parse_value(text: &str, field: MarketField, line_number: usize) -> std::io::Result<f64> {
+    let value: Option<f64> = match field {
+        MarketField::Integer => text.parse::<i64>().ok().map(|value| value as f64),
+        _ => text.parse::<f64>().ok(),
+    };
+
+    return value.ok_or_else(|| invalid_data(&format!("line {}: invalid {} value {}", line_number, field.name(), text)));
+}
+
+// Stores value at (row, col) of a row-major matrix and, for symmetric
+// storage, at its mirror position.
+fn set_mirrored(values: &mut [f64], cols: usize, row: usize, col: usize, value: f64, symmetry: Symmetry) {
+    values[flat(row, col, cols)] = value;
+
+    match symmetry {
+        Symmetry::General => {}
+        Symmetry::Symmetric => values[flat(col, row, cols)] = value,
+        Symmetry::SkewSymmetric => values[flat(col, row, cols)] = -value,
+    }
+}
+
+// Builds a CSR matrix from entries in any order. Repeated positions are
+// summed, as most Matrix Market readers do. The row pointers are the one
+// allocation the entries do not bound, so failing to make it is an error
+// rather than an abort.
+fn csr_from_entries(rows: usize, cols: usize, mut entries: Vec<(usize, usize, f64)>) -> std::io::Result<CsrMatrix<f64>> {
+    entries.sort_by_key(|&(row, col, _)| (row, col));
+
+    let too_many_rows = || invalid_data(&format!("{} rows are more than fit in memory", rows));
+    let mut row_ptr: Vec<usize> = Vec::new();
+    row_ptr.try_reserve_exact(rows.checked_add(1).ok_or_else(too_many_rows)?).map_err(|_| too_many_rows())?;
+    row_ptr.resize(rows + 1, 0);
+    let mut col_idx: Vec<usize> = Vec::with_capacity(entries.len());
+    let mut values: Vec<f64> = Vec::with_capacity(entries.len());
+    let mut last: Option<(usize, usize)> = None;
+
+    for (row, col, value) in entries {
+        if last == Some((row, col)) {
+            *values.last_mut().expect("a previous entry") += value;
+            continue;
+        }
+
+        col_idx.push(col);
+        values.push(value);
+        row_ptr[row + 1] += 1;
+        last = Some((row, col));
+    }
+
+    for row in 0..rows {
+        row_ptr[row + 1] += row_ptr[row];
+    }
+
+    return Ok(CsrMatrix { rows, cols, row_ptr, col_idx, values });
+}
+
+// Integers are written without a fractional part; reals in the shortest
+// form that reads back to the same value.
+fn format_value(value: f64, field: MarketField) -> String {
+    return match field {
+        MarketField::Integer => format!("{}", value as i64),
+        _ => format!("{}", value),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        return std::env::temp_dir().join(format!("rmm-mtx-{}-{}", std::process::id(), name));
+    }
+
+    // Writes matrix and reads it back.
+    fn round_trip(matrix: &MarketMatrix, name: &str) -> MarketMatrix {
+        let path: PathBuf = temp_path(name);
+        write_matrix_market(&path, matrix).unwrap();
+        let read: MarketMatrix = read_matrix_market(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        return read;
+    }
+
+    fn error(text: &str) -> String {
+        let err: std::io::Error = parse_matrix_market(text).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData, "{}", err);
+
+        return err.to_string();
+    }
+
+    #[test]
+    fn array_files_round_trip() {
+        let values: Vec<f64> = vec![1.5, -2.0, 0.0, 3.25, 1e-7, -4e12];
+        let real: MarketMatrix = MarketMatrix::Array { field: MarketField::Real, rows: 2, cols: 3, values: values.clone() };
+        let integer: MarketMatrix = MarketMatrix::Array { field: MarketField::Integer, rows: 3, cols: 2, values: vec![7.0, -1.0, 0.0, 42.0, 5.0, -9.0] };
+        let empty: MarketMatrix = MarketMatrix::Array { field: MarketField::Real, rows: 0, cols: 4, values: vec![] };
+
+        for (index, matrix) in [real, integer, empty].iter().enumerate() {
+            assert_eq!(&round_trip(matrix, &format!("array{}.mtx", index)), matrix);
+        }
+    }
+
+    #[test]
+    fn coordinate_files_round_trip() {
+        let dense: Vec<f64> = vec![0.0, 2.5, 0.0, 0.0, -1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 8.0];
+        let real: MarketMatrix = MarketMatrix::Coordinate { field: MarketField::Real, matrix: CsrMatrix::from_dense(&dense, 3, 4, 0.0).unwrap() };
+        let integers: Vec<f64> = dense.iter().map(|value| value.round()).collect();
+        let integer: MarketMatrix = MarketMatrix::Coordinate { field: MarketField::Integer, matrix: CsrMatrix::from_dense(&integers, 4, 3, 0.0).unwrap() };
+        let ones: Vec<f64> = dense.iter().map(|&value| if value != 0.0 { 1.0 } else { 0.0 }).collect();
+        let pattern: MarketMatrix = MarketMatrix::Coordinate { field: MarketField::Pattern, matrix: CsrMatrix::from_dense(&ones, 2, 6, 0.0).unwrap() };
+
+        for (index, matrix) in [real, integer, pattern].iter().enumerate() {
+            assert_eq!(&round_trip(matrix, &format!("coordinate{}.mtx", index)), matrix);
+        }
+    }
+
+    #[test]
+    fn symmetric_storage_is_mirrored() {
+        let array: MarketMatrix = parse_matrix_market("%%MatrixMarket matrix array real symmetric\n2 2\n1\n2\n3\n").unwrap();
+        assert_eq!(array.to_dense(), vec![1.0, 2.0, 2.0, 3.0]);
+
+        let skew: MarketMatrix = parse_matrix_market("%%MatrixMarket matrix coordinate integer skew-symmetric\n% comment\n2 2 1\n2 1 5\n").unwrap();
+        assert_eq!(skew.to_dense(), vec![0.0, -5.0, 5.0, 0.0]);
+    }
+
+    #[test]
+    fn bad_banners_are_rejected() {
+        assert!(error("").contains("missing %%MatrixMarket banner"));
+        assert!(error("2 2\n1\n2\n3\n4\n").contains("missing %%MatrixMarket banner"));
+        assert!(error("%%MatrixMarket matrix array real\n1 1\n1\n").contains("banner must be"));
+        assert!(error("%%MatrixMarket vector array real general\n1 1\n1\n").contains("unsupported object 'vector'"));
+        assert!(error("%%MatrixMarket matrix dense real general\n1 1\n1\n").contains("unsupported format 'dense'"));
+        assert!(error("%%MatrixMarket matrix coordinate complex general\n1 1 1\n1 1 1 0\n").contains("unsupported field type 'complex'"));
+        assert!(error("%%MatrixMarket matrix array pattern general\n1 1\n1\n").contains("unsupported field type 'pattern'"));
+        assert!(error("%%MatrixMarket matrix array real hermitian\n1 1\n1\n").contains("unsupported symmetry 'hermitian'"));
+        // Keywords are case-insensitive.
+        assert!(parse_matrix_market("%%matrixmarket MATRIX Array Real General\n1 1\n4\n").is_ok());
+    }
+
+    #[test]
+    fn size_lines_are_not_trusted() {
+        // Neither would fit in memory; both must fail before allocating.
+        assert!(error("%%MatrixMarket matrix array real general\n4294967296 4294967296\n1\n").contains("more elements than fit in memory"));
+        assert!(error(&format!("%%MatrixMarket matrix array real general\n{} 2\n1\n", usize::MAX)).contains("more elements than fit in memory"));
+        assert!(error("%%MatrixMarket matrix array real general\n100000 100000\n1\n2\n").contains("declares 10000000000 entries but the file has 2"));
+        assert!(error("%%MatrixMarket matrix coordinate real general\n1000000 1000000 999999999999\n1 1 1\n")
+                    .contains("declares 999999999999 entries but the file has 1"));
+        assert!(error("%%MatrixMarket matrix coordinate real general\n2 2 5\n1 1 1\n1 2 1\n2 1 1\n2 2 1\n1 1 1\n").contains("5 entries in a 2x2 matrix"));
+        assert!(error(&format!("%%MatrixMarket matrix coordinate real general\n{} 1 0\n", usize::MAX)).contains("more than fit in memory"));
+        assert!(error("%%MatrixMarket matrix array real symmetric\n2 2\n1\n2\n3\n4\n").contains("declares 3 entries but the file has 4"));
+        assert!(error("%%MatrixMarket matrix coordinate real general\n2 2 1\n3 1 1\n").contains("outside the 2x2 matrix"));
+    }
+}
@@ -0,0 +1,211 @@
+use crate::error::{check_len, DimError};
+use crate::index::row_range;
+use crate::matrix::construct_randomized_matrix_seeded;
+
+// Largest value a 4-bit element holds.
+pub const U4_MAX: u8 = 15;
+
+// A matrix of 4-bit values (0..=15) packed two per byte, half the memory of
+// a u8 matrix. Each row starts on a byte boundary: element (row, col) is the
+// low nibble of bytes[row * bytes_per_row + col / 2] for even col and the
+// high nibble for odd col. With an odd column count the high nibble of the
+// last byte of every row is unused and always 0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct U4Matrix {
+    pub rows: usize,
+    pub cols: usize,
+    pub bytes_per_row: usize,
+    pub bytes: Vec<u8>,
+}
+
+impl U4Matrix {
+    // A rows x cols matrix of zeros.
+    pub fn new(rows: usize, cols: usize) -> U4Matrix {
+        let bytes_per_row: usize = cols.div_ceil(2);
+
+        return U4Matrix { rows, cols, bytes_per_row, bytes: vec![0; rows * bytes_per_row] };
+    }
+
+    // Packs a u8 matrix. Every element must be at most U4_MAX; the first one
+    // that is not is reported.
+    pub fn pack(arr: &[u8], rows: usize, cols: usize) -> Result<U4Matrix, DimError> {
+        check_len(arr.len(), rows, cols)?;
+
+        if let Some(&value) = arr.iter().find(|&&value| value > U4_MAX) {
+            return Err(DimError::Mismatch { what: "largest 4-bit value", expected: U4_MAX as usize, found: value as usize });
+        }
+
+        let mut matrix: U4Matrix = U4Matrix::new(rows, cols);
+
+        for row in 0..rows {
+            let out: &mut [u8] = &mut matrix.bytes[row * matrix.bytes_per_row..(row + 1) * matrix.bytes_per_row];
+
+            for (byte, pair) in out.iter_mut().zip(arr[row_range(row, cols)].chunks(2)) {
+                *byte = pair[0] | pair.get(1).map_or(0, |&high| high << 4);
+            }
+        }
+
+        return Ok(matrix);
+    }
+
+    // Unpacks into a row-major u8 matrix.
+    pub fn unpack(&self) -> Vec<u8> {
+        let mut arr: Vec<u8> = Vec::with_capacity(self.rows * self.cols);
+
+        for row in 0..self.rows {
+            arr.extend((0..self.cols).map(|col| self.get(row, col)));
+        }
+
+        return arr;
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> u8 {
+        assert!(row < self.rows && col < self.cols, "({}, {}) outside the {}x{} matrix", row, col, self.rows, self.cols);
+
+        return self.bytes[row * self.bytes_per_row + col / 2] >> (4 * (col % 2)) & U4_MAX;
+    }
+
+    pub fn set(&mut self, row: usize, col: usize, value: u8) {
+        assert!(row < self.rows && col < self.cols, "({}, {}) outside the {}x{} matrix", row, col, self.rows, self.cols);
+        assert!(value <= U4_MAX, "{} does not fit in 4 bits", value);
+
+        let shift: usize = 4 * (col % 2);
+        let byte: &mut u8 = &mut self.bytes[row * self.bytes_per_row + col / 2];
+
+        *byte = *byte & !(U4_MAX << shift) | value << shift;
+    }
+
+    // The packed bytes of row.
+    fn row_bytes(&self, row: usize) -> &[u8] {
+        return &self.bytes[row * self.bytes_per_row..(row + 1) * self.bytes_per_row];
+    }
+}
+
+// The seeded matrix construct_randomized_matrix_seeded returns, scaled to
+// 4 bits by keeping the high nibble of every element, so the values stay
+// uniform over 0..=15.
+pub fn construct_randomized_u4_seeded(rows: usize, cols: usize, seed: u64) -> U4Matrix {
+    let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, seed).iter().map(|value| value >> 4).collect();
+
+    return U4Matrix::pack(&arr, rows, cols).expect("scaled values fit in 4 bits");
+}
+
+// The rows x (cols + 2) matrix compute_dx gives for matrix.unpack(), reading
+// the packed rows directly. Output column j is element j - 2 minus element j,
+// and byte k holds elements 2k and 2k + 1, so both output columns of a byte
+// come from the same nibble of bytes k - 1 and k. The unused nibble of an odd
+// row is 0, which is what the missing element past the end reads as anyway.
+pub fn compute_dx_u4(matrix: &U4Matrix) -> Vec<i16> {
+    let new_cols: usize = matrix.cols + 2;
+    let mut dx: Vec<i16> = vec![0; matrix.rows * new_cols];
+
+    for row in 0..matrix.rows {
+        let bytes: &[u8] = matrix.row_bytes(row);
+        let out: &mut [i16] = &mut dx[row_range(row, new_cols)];
+        let (first, last): (i16, i16) = (bytes.first().map_or(0, |&byte| byte as i16), bytes.last().map_or(0, |&byte| byte as i16));
+
+        // Output columns 0 and 1 see only byte 0, the pairs after them bytes
+        // k - 1 and k, and the last one or two only the last byte.
+        out[0] = -(first & U4_MAX as i16);
+        out[1] = -(first >> 4);
+
+        for (pair, window) in out[2..].chunks_exact_mut(2).zip(bytes.windows(2)) {
+            pair[0] = (window[0] & U4_MAX) as i16 - (window[1] & U4_MAX) as i16;
+            pair[1] = (window[0] >> 4) as i16 - (window[1] >> 4) as i16;
+        }
+
+        let tail: usize = 2 * matrix.bytes_per_row;
+
+        if matrix.bytes_per_row > 0 {
+            out[tail] = last & U4_MAX as i16;
+
+            if let Some(high) = out.get_mut(tail + 1) {
+                *high = last >> 4;
+            }
+        }
+    }
+
+    return dx;
+}
+
+// The (rows + 2) x cols matrix compute_dy gives for matrix.unpack(). Output
+// row r is input row r - 2 minus input row r, one packed byte of each at a
+// time.
+pub fn compute_dy_u4(matrix: &U4Matrix) -> Vec<i16> {
+    let cols: usize = matrix.cols;
+    let mut dy: Vec<i16> = vec![0; (matrix.rows + 2) * cols];
+    let zeros: Vec<u8> = vec![0; matrix.bytes_per_row];
+
+    for row in 0..matrix.rows + 2 {
+        let above: &[u8] = if row >= 2 { matrix.row_bytes(row - 2) } else { &zeros };
+        let below: &[u8] = if row < matrix.rows { matrix.row_bytes(row) } else { &zeros };
+        let out: &mut [i16] = &mut dy[row_range(row, cols)];
+        let mut pairs = out.chunks_exact_mut(2);
+
+        for (pair, (&a, &b)) in pairs.by_ref().zip(above.iter().zip(below)) {
+            pair[0] = (a & U4_MAX) as i16 - (b & U4_MAX) as i16;
+            pair[1] = (a >> 4) as i16 - (b >> 4) as i16;
+        }
+
+        // The low nibble of the last byte of an odd row.
+        if let [last] = pairs.into_remainder() {
+            *last = (above[cols / 2] & U4_MAX) as i16 - (below[cols / 2] & U4_MAX) as i16;
+        }
+    }
+
+    return dy;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernels::{compute_dx, compute_dy};
+
+    // Every 4-bit value, over and over, so each one lands on both nibbles.
+    fn ramp(rows: usize, cols: usize) -> Vec<u8> {
+        return (0..rows * cols).map(|index| (index * 7 % 16) as u8).collect();
+    }
+
+    #[test]
+    fn packing_round_trips_for_odd_widths() {
+        for (rows, cols) in [(1, 1), (3, 1), (2, 3), (5, 7), (4, 15), (3, 16), (0, 5), (5, 0)] {
+            let arr: Vec<u8> = ramp(rows, cols);
+            let matrix: U4Matrix = U4Matrix::pack(&arr, rows, cols).unwrap();
+
+            assert_eq!(matrix.bytes.len(), rows * cols.div_ceil(2));
+            assert_eq!(matrix.unpack(), arr, "{}x{}", rows, cols);
+
+            // The unused high nibble of an odd row stays 0.
+            if cols % 2 == 1 {
+                assert!(matrix.bytes.chunks(matrix.bytes_per_row).all(|row| row[row.len() - 1] >> 4 == 0));
+            }
+        }
+
+        assert_eq!(U4Matrix::pack(&[3, 16, 2], 1, 3), Err(DimError::Mismatch { what: "largest 4-bit value", expected: 15, found: 16 }));
+        assert!(U4Matrix::pack(&[1, 2, 3], 2, 2).is_err());
+    }
+
+    #[test]
+    fn set_changes_one_nibble() {
+        let mut matrix: U4Matrix = U4Matrix::pack(&ramp(2, 5), 2, 5).unwrap();
+        let mut expected: Vec<u8> = ramp(2, 5);
+
+        for (row, col, value) in [(0, 0, 15), (0, 1, 0), (1, 4, 9), (1, 3, 15)] {
+            matrix.set(row, col, value);
+            expected[row * 5 + col] = value;
+            assert_eq!(matrix.unpack(), expected);
+        }
+    }
+
+    #[test]
+    fn nibble_kernels_match_the_u8_ones() {
+        for (rows, cols) in [(1, 1), (1, 2), (2, 3), (7, 9), (16, 31), (8, 32)] {
+            for arr in [ramp(rows, cols), construct_randomized_u4_seeded(rows, cols, (rows * cols) as u64).unpack(), vec![U4_MAX; rows * cols]] {
+                let matrix: U4Matrix = U4Matrix::pack(&arr, rows, cols).unwrap();
+
+                assert_eq!(compute_dx_u4(&matrix), compute_dx(&arr, rows, cols).data, "Dx {}x{}", rows, cols);
+                assert_eq!(compute_dy_u4(&matrix), compute_dy(&arr, rows, cols).data, "Dy {}x{}", rows, cols);
+            }
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::thread;
+
+// Where Linux lists the NUMA nodes, one nodeN directory each.
+const NODE_DIR: &str = "/sys/devices/system/node";
+
+// The CPUs of every NUMA node of the machine.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Topology {
+    pub nodes: Vec<Vec<usize>>,
+}
+
+impl Topology {
+    // Reads the topology from sysfs. Machines that do not expose one, and
+    // other platforms, count as a single node holding every CPU.
+    pub fn detect() -> Topology {
+        return read_nodes(Path::new(NODE_DIR)).unwrap_or_else(|| {
+            let cpus: usize = thread::available_parallelism().map_or(1, |cpus| cpus.get());
+            Topology { nodes: vec![(0..cpus).collect()] }
+        });
+    }
+
+    pub fn is_numa(&self) -> bool {
+        return self.nodes.len() > 1;
+    }
+}
+
+// The nodes under dir with at least one CPU, in node order.
+fn read_nodes(dir: &Path) -> Option<Topology> {
+    let mut nodes: Vec<(usize, Vec<usize>)> = Vec::new();
+
+    for entry in fs::read_dir(dir).ok()?.flatten() {
+        let name: String = entry.file_name().to_string_lossy().into_owned();
+        let Some(index) = name.strip_prefix("node").and_then(|index| index.parse().ok()) else {
+            continue;
+        };
+        let cpus: Vec<usize> = parse_cpulist(fs::read_to_string(entry.path().join("cpulist")).ok()?.trim())?;
+
+        // Memory-only nodes have no CPUs to run workers on.
+        if !cpus.is_empty() {
+            nodes.push((index, cpus));
+        }
+    }
+
+    if nodes.is_empty() {
+        return None;
+    }
+
+    nodes.sort();
+
+    return Some(Topology { nodes: nodes.into_iter().map(|(_, cpus)| cpus).collect() });
+}
+
+// Parses a kernel CPU list such as "0-3,8,10-11". An empty list is valid and
+// holds no CPUs.
+pub fn parse_cpulist(list: &str) -> Option<Vec<usize>> {
+    let mut cpus: Vec<usize> = Vec::new();
+
+    for part in list.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (first, last): (usize, usize) = (first.trim().parse().ok()?, last.trim().parse().ok()?);
+
+                if first > last {
+                    return None;
+                }
+
+                cpus.extend(first..=last);
+            }
+            None => cpus.push(part.parse().ok()?),
+        }
+    }
+
+    return Some(cpus);
+}
+
+// The CPU each of a number of workers is pinned to. Workers are split into
+// contiguous runs, one per node and as even as possible, so that the bands of
+// rows the parallel kernels hand out in order land on one node each and
+// neighbouring bands share a node. Within a node the workers take its CPUs in
+// turn.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Placement {
+    pub cpus: Vec<usize>,
+    // Node of every worker.
+    pub nodes: Vec<usize>,
+}
+
+impl Placement {
+    pub fn new(topology: &Topology, workers: usize) -> Placement {
+        let count: usize = topology.nodes.len();
+        let mut cpus: Vec<usize> = Vec::with_capacity(workers);
+        let mut nodes: Vec<usize> = Vec::with_capacity(workers);
+
+        for worker in 0..workers {
+            let node: usize = worker * count / workers;
+            // First worker of the node, the smallest w with w * count / workers == node.
+            let first: usize = (node * workers).div_ceil(count);
+            let node_cpus: &[usize] = &topology.nodes[node];
+
+            cpus.push(node_cpus[(worker - first) % node_cpus.len()]);
+            nodes.push(node);
+        }
+
+        return Placement { cpus, nodes };
+    }
+
+    // Pins the calling thread to the CPU of worker. Workers beyond those the
+    // placement was made for wrap around.
+    pub fn pin(&self, worker: usize) -> std::io::Result<()> {
+        return pin_to_cpu(self.cpus[worker % self.cpus.len()]);
+    }
+}
+
+// Restricts the calling thread to cpu.
+#[cfg(all(feature = "numa", target_os = "linux"))]
+pub fn pin_to_cpu(cpu: usize) -> std::io::Result<()> {
+    // SAFETY: set is a plain bit set that outlives both calls, and cpu is
+    // checked against its capacity before CPU_SET writes to it.
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+
+        if cpu >= 8 * std::mem::size_of::<libc::cpu_set_t>() {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("CPU {} is beyond the affinity mask", cpu)));
+        }
+
+        libc::CPU_SET(cpu, &mut set);
+
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(Error::last_os_error());
+        }
+    }
+
+    return Ok(());
+}
+
+#[cfg(not(all(feature = "numa", target_os = "linux")))]
+pub fn pin_to_cpu(_cpu: usize) -> std::io::Result<()> {
+    return Err(Error::new(ErrorKind::Unsupported, "thread pinning needs the numa feature on Linux"));
+}
@@ -0,0 +1,461 @@
+use crate::error::{check_len, DimError};
+use crate::index::{flat, row_range};
+
+// An owned matrix together with its row and column counts.
+pub type Block<T> = (Vec<T>, usize, usize);
+
+// Copies the height x width region whose top-left corner is (r0, c0) out of a
+// rows x cols matrix.
+pub fn crop<T: Copy>(data: &[T], rows: usize, cols: usize, r0: usize, c0: usize, height: usize,
+                     width: usize) -> Result<Vec<T>, DimError> {
+    check_len(data.len(), rows, cols)?;
+
+    // A corner so far out that r0 + height wraps is out of range too.
+    let fits = |start: usize, len: usize, end: usize| start.checked_add(len).is_some_and(|last| last <= end);
+
+    if !fits(r0, height, rows) || !fits(c0, width, cols) {
+        return Err(DimError::OutOfRange { rows, cols, row: r0, col: c0, height, width });
+    }
+
+    let mut out: Vec<T> = Vec::with_capacity(height * width);
+
+    for row in r0..r0 + height {
+        out.extend_from_slice(&data[flat(row, c0, cols)..flat(row, c0 + width, cols)]);
+    }
+
+    return Ok(out);
+}
+
+// Strips the padding columns from a Dx result. dx is rows x (cols + 2) and
+// column c + 1 of dx is centered on column c of the input, so the central
+// rows x cols region lines up with the original matrix.
+pub fn crop_dx_padding(dx: &[i16], rows: usize, cols: usize) -> Result<Vec<i16>, DimError> {
+    return crop(dx, rows, cols + 2, 0, 1, rows, cols);
+}
+
+// Strips the padding rows from a Dy result. dy is (rows + 2) x cols and is
+// cropped to the central rows x cols region, as with crop_dx_padding.
+pub fn crop_dy_padding(dy: &[i16], rows: usize, cols: usize) -> Result<Vec<i16>, DimError> {
+    return crop(dy, rows + 2, cols, 1, 0, rows, cols);
+}
+
+// Places matrices side by side. Every part must have the same number of rows;
+// the result has that many rows and the sum of the parts' columns.
+pub fn hconcat<T: Copy>(parts: &[(&[T], usize, usize)]) -> Result<Vec<T>, DimError> {
+    if parts.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let rows: usize = parts[0].1;
+    let mut total_cols: usize = 0;
+
+    for &(data, part_rows, part_cols) in parts {
+        check_len(data.len(), part_rows, part_cols)?;
+
+        if part_rows != rows {
+            return Err(DimError::Mismatch { what: "row count", expected: rows, found: part_rows });
+        }
+
+        total_cols += part_cols;
+    }
+
+    let mut out: Vec<T> = Vec::with_capacity(rows * total_cols);
+
+    for row in 0..rows {
+        for &(data, _, part_cols) in parts {
+            out.extend_from_slice(&data[row * part_cols..(row + 1) * part_cols]);
+        }
+    }
+
+    return Ok(out);
+}
+
+// Stacks matrices on top of each other. Every part must have the same number
+// of columns; the result has that many columns and the sum of the parts' rows.
+pub fn vconcat<T: Copy>(parts: &[(&[T], usize, usize)]) -> Result<Vec<T>, DimError> {
+    if parts.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let cols: usize = parts[0].2;
+    let mut total_len: usize = 0;
+
+    for &(data, part_rows, part_cols) in parts {
+        check_len(data.len(), part_rows, part_cols)?;
+
+        if part_cols != cols {
+            return Err(DimError::Mismatch { what: "column count", expected: cols, found: part_cols });
+        }
+
+        total_len += data.len();
+    }
+
+    let mut out: Vec<T> = Vec::with_capacity(total_len);
+
+    for &(data, _, _) in parts {
+        out.extend_from_slice(data);
+    }
+
+    return Ok(out);
+}
+
+// Assembles a grid of sub-matrices into one matrix. blocks[i][j] is the block
+// in block row i and block column j; all blocks in a block row must share a
+// height and all blocks in a block column must share a width.
+pub fn from_blocks<T: Copy>(blocks: &[&[(&[T], usize, usize)]]) -> Result<Vec<T>, DimError> {
+    if blocks.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let widths: Vec<usize> = blocks[0].iter().map(|&(_, _, block_cols)| block_cols).collect();
+    let mut out: Vec<T> = Vec::new();
+
+    for block_row in blocks {
+        if block_row.len() != widths.len() {
+            return Err(DimError::Mismatch { what: "block count", expected: widths.len(), found: block_row.len() });
+        }
+
+        for (&(_, _, block_cols), &width) in block_row.iter().zip(&widths) {
+            if block_cols != width {
+                return Err(DimError::Mismatch { what: "block width", expected: width, found: block_cols });
+            }
+        }
+
+        // hconcat checks that the heights agree within the block row.
+        out.extend(hconcat(block_row)?);
+    }
+
+    return Ok(out);
+}
+
+// Splits a rows x cols matrix into a grid of blocks, the inverse of
+// from_blocks. block_rows lists the height of each block row and block_cols
+// the width of each block column; they must sum to rows and cols.
+pub fn split_blocks<T: Copy>(data: &[T], rows: usize, cols: usize, block_rows: &[usize],
+                             block_cols: &[usize]) -> Result<Vec<Vec<Block<T>>>, DimError> {
+    check_len(data.len(), rows, cols)?;
+
+    let total_rows: usize = block_rows.iter().sum();
+    let total_cols: usize = block_cols.iter().sum();
+
+    if total_rows != rows {
+        return Err(DimError::Mismatch { what: "sum of block heights", expected: rows, found: total_rows });
+    }
+
+    if total_cols != cols {
+        return Err(DimError::Mismatch { what: "sum of block widths", expected: cols, found: total_cols });
+    }
+
+    let mut grid: Vec<Vec<Block<T>>> = Vec::with_capacity(block_rows.len());
+    let mut r0: usize = 0;
+
+    for &height in block_rows {
+        let mut block_row: Vec<Block<T>> = Vec::with_capacity(block_cols.len());
+        let mut c0: usize = 0;
+
+        for &width in block_cols {
+            block_row.push((crop(data, rows, cols, r0, c0, height, width)?, height, width));
+            c0 += width;
+        }
+
+        grid.push(block_row);
+        r0 += height;
+    }
+
+    return Ok(grid);
+}
+
+// Mirrors a matrix left to right.
+pub fn flip_horizontal<T: Copy>(arr: &[T], rows: usize, cols: usize) -> Result<Vec<T>, DimError> {
+    let mut out: Vec<T> = arr.to_vec();
+    flip_horizontal_in_place(&mut out, rows, cols)?;
+
+    return Ok(out);
+}
+
+// Mirrors a matrix top to bottom.
+pub fn flip_vertical<T: Copy>(arr: &[T], rows: usize, cols: usize) -> Result<Vec<T>, DimError> {
+    let mut out: Vec<T> = arr.to_vec();
+    flip_vertical_in_place(&mut out, rows, cols)?;
+
+    return Ok(out);
+}
+
+// Mirrors a matrix left to right without allocating by reversing each row.
+pub fn flip_horizontal_in_place<T>(arr: &mut [T], rows: usize, cols: usize) -> Result<(), DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    if cols > 0 {
+        for row in arr.chunks_exact_mut(cols) {
+            row.reverse();
+        }
+    }
+
+    return Ok(());
+}
+
+// Mirrors a matrix top to bottom without allocating by swapping row pairs.
+pub fn flip_vertical_in_place<T>(arr: &mut [T], rows: usize, cols: usize) -> Result<(), DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    for row in 0..rows / 2 {
+        let (top, bottom) = arr.split_at_mut((rows - 1 - row) * cols);
+        top[row_range(row, cols)].swap_with_slice(&mut bottom[..cols]);
+    }
+
+    return Ok(());
+}
+
+// Side length of the square tiles used by transpose. Each tile is read and
+// written while it is hot in cache instead of striding over whole columns.
+const TRANSPOSE_BLOCK: usize = 32;
+
+// Transposes a rows x cols matrix into a cols x rows matrix, one
+// TRANSPOSE_BLOCK x TRANSPOSE_BLOCK tile at a time.
+pub fn transpose<T: Copy>(arr: &[T], rows: usize, cols: usize) -> Result<Vec<T>, DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    let mut out: Vec<T> = arr.to_vec();
+
+    for row_block in (0..rows).step_by(TRANSPOSE_BLOCK) {
+        for col_block in (0..cols).step_by(TRANSPOSE_BLOCK) {
+            for row in row_block..(row_block + TRANSPOSE_BLOCK).min(rows) {
+                for col in col_block..(col_block + TRANSPOSE_BLOCK).min(cols) {
+                    out[flat(col, row, rows)] = arr[flat(row, col, cols)];
+                }
+            }
+        }
+    }
+
+    return Ok(out);
+}
+
+// Rotates a matrix a quarter turn clockwise. The rows x cols input becomes a
+// cols x rows matrix, returned together with its new dimensions.
+pub fn rotate90_cw<T: Copy>(arr: &[T], rows: usize, cols: usize) -> Result<Block<T>, DimError> {
+    let mut out: Vec<T> = transpose(arr, rows, cols)?;
+    flip_horizontal_in_place(&mut out, cols, rows)?;
+
+    return Ok((out, cols, rows));
+}
+
+// Rotates a matrix a quarter turn counter-clockwise. The rows x cols input
+// becomes a cols x rows matrix, returned together with its new dimensions.
+pub fn rotate90_ccw<T: Copy>(arr: &[T], rows: usize, cols: usize) -> Result<Block<T>, DimError> {
+    let mut out: Vec<T> = transpose(arr, rows, cols)?;
+    flip_vertical_in_place(&mut out, cols, rows)?;
+
+    return Ok((out, cols, rows));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arith::ArithPolicy;
+    use crate::conv::{convolve_cols_padded, convolve_rows_padded, CENTRAL_DIFFERENCE};
+    use crate::kernels::{compute_dx, compute_dy};
+    use crate::matrix::construct_randomized_matrix_seeded;
+
+    // A borrowed matrix with its rows and cols, as the functions take them.
+    type Part<'a> = (&'a [i32], usize, usize);
+
+    #[test]
+    fn crop_copies_the_region() {
+        let data: Vec<i32> = (0..20).collect();
+
+        assert_eq!(crop(&data, 4, 5, 1, 2, 2, 3).unwrap(), vec![7, 8, 9, 12, 13, 14]);
+        assert_eq!(crop(&data, 4, 5, 0, 0, 4, 5).unwrap(), data);
+        assert_eq!(crop(&data, 4, 5, 4, 5, 0, 0).unwrap(), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn crop_rejects_rectangles_outside_the_matrix() {
+        let data: Vec<i32> = (0..20).collect();
+
+        assert_eq!(crop(&data, 4, 5, 3, 0, 2, 1), Err(DimError::OutOfRange { rows: 4, cols: 5, row: 3, col: 0, height: 2, width: 1 }));
+        assert!(crop(&data, 4, 5, 0, 1, 1, 5).is_err());
+        assert!(crop(&data, 4, 5, 0, 0, 1, 1).is_ok());
+    }
+
+    #[test]
+    fn crop_rejects_corners_that_wrap() {
+        let data: Vec<i32> = (0..20).collect();
+
+        assert!(crop(&data, 4, 5, usize::MAX, 0, 2, 1).is_err());
+        assert!(crop(&data, 4, 5, 0, 2, 1, usize::MAX).is_err());
+        assert!(crop(&data, 4, 5, 1, usize::MAX - 1, 1, 3).is_err());
+    }
+
+    #[test]
+    fn cropped_gradients_equal_the_same_convolution() {
+        for (rows, cols) in [(1, 1), (1, 2), (3, 1), (5, 7), (17, 40)] {
+            let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, (rows * cols) as u64);
+            let same_dx: Vec<i16> = convolve_rows_padded(&arr, rows, cols, &CENTRAL_DIFFERENCE, 0, ArithPolicy::Wrapping).unwrap();
+            let same_dy: Vec<i16> = convolve_cols_padded(&arr, rows, cols, &CENTRAL_DIFFERENCE, 0, ArithPolicy::Wrapping).unwrap();
+
+            assert_eq!(crop_dx_padding(&compute_dx(&arr, rows, cols).data, rows, cols).unwrap(), same_dx, "{}x{}", rows, cols);
+            assert_eq!(crop_dy_padding(&compute_dy(&arr, rows, cols).data, rows, cols).unwrap(), same_dy, "{}x{}", rows, cols);
+        }
+    }
+
+    #[test]
+    fn hconcat_places_parts_side_by_side() {
+        let (a, b, c): ([i32; 4], [i32; 2], [i32; 6]) = ([1, 2, 3, 4], [5, 6], [7, 8, 9, 10, 11, 12]);
+
+        assert_eq!(hconcat(&[(&a, 2, 2), (&b, 2, 1)]).unwrap(), vec![1, 2, 5, 3, 4, 6]);
+        assert_eq!(hconcat(&[(&a, 2, 2), (&b, 2, 1), (&c, 2, 3)]).unwrap(), vec![1, 2, 5, 7, 8, 9, 3, 4, 6, 10, 11, 12]);
+    }
+
+    #[test]
+    fn vconcat_stacks_parts() {
+        let (a, b, c): ([i32; 4], [i32; 2], [i32; 6]) = ([1, 2, 3, 4], [5, 6], [7, 8, 9, 10, 11, 12]);
+
+        assert_eq!(vconcat(&[(&a, 2, 2), (&b, 1, 2)]).unwrap(), vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(vconcat(&[(&a, 2, 2), (&b, 1, 2), (&c, 3, 2)]).unwrap(), (1..=12).collect::<Vec<i32>>());
+    }
+
+    #[test]
+    fn concat_rejects_mismatched_dimensions() {
+        let (a, b): ([i32; 4], [i32; 3]) = ([1, 2, 3, 4], [5, 6, 7]);
+
+        assert_eq!(hconcat(&[(&a, 2, 2), (&b, 3, 1)]), Err(DimError::Mismatch { what: "row count", expected: 2, found: 3 }));
+        assert_eq!(vconcat(&[(&a, 2, 2), (&b, 1, 3)]), Err(DimError::Mismatch { what: "column count", expected: 2, found: 3 }));
+        assert_eq!(hconcat(&[(&a, 2, 2), (&b, 2, 2)]), Err(DimError::LengthMismatch { expected: 4, found: 3 }));
+    }
+
+    #[test]
+    fn bright_half_next_to_dark_half_has_one_vertical_edge() {
+        let (dark, bright): (Vec<u8>, Vec<u8>) = (vec![0; 12], vec![200; 12]);
+        let image: Vec<u8> = hconcat(&[(&dark, 4, 3), (&bright, 4, 3)]).unwrap();
+        let dx: Vec<i16> = crop_dx_padding(&compute_dx(&image, 4, 6).data, 4, 6).unwrap();
+
+        // Only the columns either side of the edge differ, and the last
+        // column, which meets the zero padding.
+        for row in dx.chunks_exact(6) {
+            assert_eq!(row, [0, 0, -200, -200, 0, 200]);
+        }
+    }
+
+    #[test]
+    fn from_blocks_assembles_non_uniform_blocks() {
+        // A 3x5 matrix as a 2x2 grid: heights 1 and 2, widths 2 and 3.
+        let (a, b): ([i32; 2], [i32; 3]) = ([0, 1], [2, 3, 4]);
+        let (c, d): ([i32; 4], [i32; 6]) = ([5, 6, 10, 11], [7, 8, 9, 12, 13, 14]);
+        let top: [Part; 2] = [(&a, 1, 2), (&b, 1, 3)];
+        let bottom: [Part; 2] = [(&c, 2, 2), (&d, 2, 3)];
+
+        assert_eq!(from_blocks(&[&top, &bottom]).unwrap(), (0..15).collect::<Vec<i32>>());
+    }
+
+    #[test]
+    fn split_blocks_inverts_from_blocks() {
+        let data: Vec<i32> = (0..42).collect();
+        let grid: Vec<Vec<Block<i32>>> = split_blocks(&data, 6, 7, &[1, 3, 2], &[4, 0, 3]).unwrap();
+
+        assert_eq!(grid.len(), 3);
+        assert_eq!(grid[1][0], (vec![7, 8, 9, 10, 14, 15, 16, 17, 21, 22, 23, 24], 3, 4));
+        assert_eq!(grid[2][1], (vec![], 2, 0));
+
+        let rows: Vec<Vec<Part>> = grid.iter()
+            .map(|row| row.iter().map(|(block, rows, cols)| (block.as_slice(), *rows, *cols)).collect())
+            .collect();
+        let refs: Vec<&[Part]> = rows.iter().map(Vec::as_slice).collect();
+
+        assert_eq!(from_blocks(&refs).unwrap(), data);
+    }
+
+    #[test]
+    fn blocks_reject_inconsistent_shapes() {
+        let (a, b, c): ([i32; 2], [i32; 4], [i32; 3]) = ([0, 1], [2, 3, 4, 5], [6, 7, 8]);
+        // Heights 1 and 2 in one block row.
+        let uneven_height: [Part; 2] = [(&a, 1, 2), (&b, 2, 2)];
+        // Widths 2 then 3 in the same block column.
+        let (first, second): ([Part; 1], [Part; 1]) = ([(&a, 1, 2)], [(&c, 1, 3)]);
+
+        assert_eq!(from_blocks(&[&uneven_height]), Err(DimError::Mismatch { what: "row count", expected: 1, found: 2 }));
+        assert_eq!(from_blocks(&[&first, &second]), Err(DimError::Mismatch { what: "block width", expected: 2, found: 3 }));
+        let two_blocks: [Part; 2] = [(&a, 1, 2), (&c, 1, 3)];
+        assert_eq!(from_blocks(&[&two_blocks[..], &first]), Err(DimError::Mismatch { what: "block count", expected: 2, found: 1 }));
+        assert_eq!(split_blocks(&[0; 6], 2, 3, &[1, 2], &[3]), Err(DimError::Mismatch { what: "sum of block heights", expected: 2, found: 3 }));
+        assert_eq!(split_blocks(&[0; 6], 2, 3, &[2], &[2]), Err(DimError::Mismatch { what: "sum of block widths", expected: 3, found: 2 }));
+    }
+
+    #[test]
+    fn flips_are_involutions() {
+        for (rows, cols) in [(0, 3), (1, 1), (2, 5), (5, 2), (7, 9)] {
+            let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, 3);
+            let mut in_place: Vec<u8> = arr.clone();
+
+            assert_eq!(flip_horizontal(&flip_horizontal(&arr, rows, cols).unwrap(), rows, cols).unwrap(), arr);
+            assert_eq!(flip_vertical(&flip_vertical(&arr, rows, cols).unwrap(), rows, cols).unwrap(), arr);
+
+            flip_horizontal_in_place(&mut in_place, rows, cols).unwrap();
+            assert_eq!(in_place, flip_horizontal(&arr, rows, cols).unwrap());
+            flip_vertical_in_place(&mut in_place, rows, cols).unwrap();
+            assert_eq!(in_place, flip_vertical(&flip_horizontal(&arr, rows, cols).unwrap(), rows, cols).unwrap());
+        }
+
+        assert_eq!(flip_horizontal(&[1, 2, 3, 4, 5, 6], 2, 3).unwrap(), vec![3, 2, 1, 6, 5, 4]);
+        assert_eq!(flip_vertical(&[1, 2, 3, 4, 5, 6], 3, 2).unwrap(), vec![5, 6, 3, 4, 1, 2]);
+        assert!(flip_vertical_in_place(&mut [1, 2, 3], 2, 2).is_err());
+    }
+
+    #[test]
+    fn flipping_the_input_mirrors_and_negates_dx() {
+        for (rows, cols) in [(1, 1), (1, 2), (3, 4), (8, 33), (40, 17)] {
+            let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, (rows + cols) as u64);
+            let dx: Vec<i16> = compute_dx(&arr, rows, cols).data;
+            let flipped: Vec<i16> = compute_dx(&flip_horizontal(&arr, rows, cols).unwrap(), rows, cols).data;
+            let mirrored: Vec<i16> = flip_horizontal(&dx, rows, cols + 2).unwrap().iter().map(|value| -value).collect();
+
+            assert_eq!(flipped, mirrored, "{}x{}", rows, cols);
+        }
+    }
+
+    #[test]
+    fn rotations_turn_the_matrix() {
+        // 1 2 3      4 1      3 6
+        // 4 5 6  ->  5 2  or  2 5
+        //            6 3      1 4
+        assert_eq!(rotate90_cw(&[1, 2, 3, 4, 5, 6], 2, 3).unwrap(), (vec![4, 1, 5, 2, 6, 3], 3, 2));
+        assert_eq!(rotate90_ccw(&[1, 2, 3, 4, 5, 6], 2, 3).unwrap(), (vec![3, 6, 2, 5, 1, 4], 3, 2));
+
+        // Larger than a transpose tile, so several tiles and partial ones.
+        let (rows, cols): (usize, usize) = (45, 70);
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, 11);
+        let (turned, turned_rows, turned_cols) = rotate90_cw(&arr, rows, cols).unwrap();
+        let (back, back_rows, back_cols) = rotate90_ccw(&turned, turned_rows, turned_cols).unwrap();
+
+        assert_eq!((turned_rows, turned_cols), (cols, rows));
+        assert_eq!((back, back_rows, back_cols), (arr.clone(), rows, cols));
+
+        let mut full: Block<u8> = (arr.clone(), rows, cols);
+
+        for _ in 0..4 {
+            full = rotate90_cw(&full.0, full.1, full.2).unwrap();
+        }
+
+        assert_eq!(full, (arr, rows, cols));
+    }
+
+    #[test]
+    fn dx_of_the_rotated_input_is_the_rotated_dy() {
+        // Rotating clockwise turns the columns of A into the rows of B with
+        // the row order of A reversed, so the vertical difference of A read
+        // along B's rows comes out negated.
+        for (rows, cols) in [(1, 1), (1, 4), (4, 1), (6, 9), (40, 33)] {
+            let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, (rows * 31 + cols) as u64);
+            let (turned, turned_rows, turned_cols) = rotate90_cw(&arr, rows, cols).unwrap();
+            let dx: Vec<i16> = compute_dx(&turned, turned_rows, turned_cols).data;
+            let (dy, _, _) = rotate90_cw(&compute_dy(&arr, rows, cols).data, rows + 2, cols).unwrap();
+
+            assert_eq!(dx, dy.iter().map(|value| -value).collect::<Vec<i16>>(), "{}x{}", rows, cols);
+
+            // Counter-clockwise keeps the row order, so the sign stays.
+            let (turned, turned_rows, turned_cols) = rotate90_ccw(&arr, rows, cols).unwrap();
+            let (dy, _, _) = rotate90_ccw(&compute_dy(&arr, rows, cols).data, rows + 2, cols).unwrap();
+
+            assert_eq!(compute_dx(&turned, turned_rows, turned_cols).data, dy, "{}x{}", rows, cols);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use crate::arith::Widen;
+use crate::conv::check_kernel;
+use crate::error::{check_len, DimError};
+
+// Findings kept by a check unless another limit is given.
+pub const DEFAULT_FINDING_LIMIT: usize = 16;
+
+// An output element of a convolution whose value differs from the exact sum,
+// i.e. where the fast path wrapped (or saturated) instead of fitting.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OverflowFinding {
+    // Position in the output, which includes the padding.
+    pub index: usize,
+    pub row: usize,
+    pub col: usize,
+    // What the fast path produced and what it should have been, both as the
+    // widened integers the accumulator works with.
+    pub fast: i64,
+    pub exact: i128,
+    // The (weight, input value) pairs summed into the element, in kernel
+    // order; padding contributes none.
+    pub operands: Vec<(i32, i64)>,
+}
+
+// Result of checking one convolution output.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OverflowReport {
+    // The first findings in output order, at most the requested limit.
+    pub findings: Vec<OverflowFinding>,
+    // Every element that differs, including those past the limit.
+    pub total: usize,
+    // Elements compared.
+    pub checked: usize,
+}
+
+// Recomputes convolve_rows(arr, rows, cols, kernel) element by element in
+// exact arithmetic and compares it with fast, the output of the fast path,
+// reporting every element where the two disagree (at most limit of them in
+// detail). Slow by design: it is a diagnosis for new kernel and type
+// combinations, not something to run in production.
+pub fn check_rows_overflow<I: Widen, O: Widen>(arr: &[I], rows: usize, cols: usize, kernel: &[i32], fast: &[O],
+                                               limit: usize) -> Result<OverflowReport, DimError> {
+    check_kernel(kernel)?;
+    let new_cols: usize = cols + kernel.len() - 1;
+
+    return check(arr, rows, cols, (rows, new_cols), fast, limit, |row, col| {
+        kernel.iter().enumerate().filter(|&(i, _)| col >= i && col - i < cols).map(|(i, weight)| (*weight, row * cols + col - i)).collect()
+    });
+}
+
+// The vertical counterpart of check_rows_overflow, for convolve_cols.
+pub fn check_cols_overflow<I: Widen, O: Widen>(arr: &[I], rows: usize, cols: usize, kernel: &[i32], fast: &[O],
+                                               limit: usize) -> Result<OverflowReport, DimError> {
+    check_kernel(kernel)?;
+    let new_rows: usize = rows + kernel.len() - 1;
+
+    return check(arr, rows, cols, (new_rows, cols), fast, limit, |row, col| {
+        kernel.iter().enumerate().filter(|&(i, _)| row >= i && row - i < rows).map(|(i, weight)| (*weight, (row - i) * cols + col)).collect()
+    });
+}
+
+// Compares every element of the out_shape output fast with the exact sum of
+// the (weight, input index) pairs taps gives for it.
+fn check<I: Widen, O: Widen>(arr: &[I], rows: usize, cols: usize, out_shape: (usize, usize), fast: &[O], limit: usize,
+                             taps: impl Fn(usize, usize) -> Vec<(i32, usize)>) -> Result<OverflowReport, DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    let (out_rows, out_cols) = out_shape;
+
+    if fast.len() != out_rows * out_cols {
+        return Err(DimError::Mismatch { what: "output length", expected: out_rows * out_cols, found: fast.len() });
+    }
+
+    let mut report: OverflowReport = OverflowReport { checked: fast.len(), ..OverflowReport::default() };
+
+    for row in 0..out_rows {
+        for col in 0..out_cols {
+            let index: usize = row * out_cols + col;
+            let operands: Vec<(i32, i64)> = taps(row, col).into_iter().map(|(weight, input)| (weight, arr[input].widen())).collect();
+            // An i32 weight times an i64 value always fits an i128, and so
+            // does any realistic number of such products.
+            let exact: i128 = operands.iter().map(|&(weight, value)| weight as i128 * value as i128).sum();
+            let value: i64 = fast[index].widen();
+
+            if value as i128 == exact {
+                continue;
+            }
+
+            report.total += 1;
+
+            if report.findings.len() < limit {
+                report.findings.push(OverflowFinding { index, row, col, fast: value, exact, operands });
+            }
+        }
+    }
+
+    return Ok(report);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arith::ArithPolicy;
+    use crate::conv::{convolve_cols, convolve_rows, CENTRAL_DIFFERENCE};
+
+    #[test]
+    fn wrapped_elements_are_found_with_their_position() {
+        // out[c] = in[c - 2] - in[c]: -(-32768) and -32768 - 32767 do not
+        // fit an i16 and wrap, the other three outputs do.
+        let arr: Vec<i16> = vec![5, 6, 7, i16::MIN, 0, i16::MAX];
+        let fast: Vec<i16> = convolve_rows(&arr, 2, 3, &CENTRAL_DIFFERENCE, ArithPolicy::Wrapping).unwrap();
+        let report: OverflowReport = check_rows_overflow(&arr, 2, 3, &CENTRAL_DIFFERENCE, &fast, DEFAULT_FINDING_LIMIT).unwrap();
+
+        assert_eq!((report.total, report.checked), (2, 10));
+        assert_eq!(report.findings[0], OverflowFinding { index: 5, row: 1, col: 0, fast: -32768, exact: 32768, operands: vec![(-1, -32768)] });
+        assert_eq!(report.findings[1], OverflowFinding { index: 7, row: 1, col: 2, fast: 1, exact: -65535,
+                                                         operands: vec![(-1, 32767), (0, 0), (1, -32768)] });
+
+        // The limit caps the details, not the count.
+        let limited: OverflowReport = check_rows_overflow(&arr, 2, 3, &CENTRAL_DIFFERENCE, &fast, 1).unwrap();
+        assert_eq!((limited.total, limited.findings.len()), (2, 1));
+
+        // The same values down a column.
+        let fast: Vec<i16> = convolve_cols(&arr[3..], 3, 1, &CENTRAL_DIFFERENCE, ArithPolicy::Wrapping).unwrap();
+        let report: OverflowReport = check_cols_overflow(&arr[3..], 3, 1, &CENTRAL_DIFFERENCE, &fast, DEFAULT_FINDING_LIMIT).unwrap();
+        assert_eq!(report.findings.iter().map(|finding| (finding.row, finding.col)).collect::<Vec<(usize, usize)>>(), vec![(0, 0), (2, 0)]);
+    }
+
+    #[test]
+    fn a_clean_input_has_no_findings() {
+        let arr: Vec<i16> = crate::matrix::construct_randomized_matrix_seeded(9, 11, 3).iter().map(|&value| value as i16 * 100).collect();
+        let dx: Vec<i16> = convolve_rows(&arr, 9, 11, &CENTRAL_DIFFERENCE, ArithPolicy::Wrapping).unwrap();
+        let dy: Vec<i16> = convolve_cols(&arr, 9, 11, &CENTRAL_DIFFERENCE, ArithPolicy::Wrapping).unwrap();
+
+        assert_eq!(check_rows_overflow(&arr, 9, 11, &CENTRAL_DIFFERENCE, &dx, 4).unwrap(), OverflowReport { findings: vec![], total: 0, checked: 9 * 13 });
+        assert_eq!(check_cols_overflow(&arr, 9, 11, &CENTRAL_DIFFERENCE, &dy, 4).unwrap(), OverflowReport { findings: vec![], total: 0, checked: 11 * 11 });
+        assert!(check_rows_overflow(&arr, 9, 11, &CENTRAL_DIFFERENCE, &dy, 4).is_err());
+    }
+}
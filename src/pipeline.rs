@@ -0,0 +1,202 @@
+use std::io;
+#[cfg(feature = "parallel")]
+use std::{collections::BTreeMap, sync::atomic::{AtomicBool, Ordering}, sync::mpsc::{sync_channel, Receiver, SyncSender}, sync::Mutex, thread};
+use std::time::{Duration, Instant};
+
+// Items each channel may hold before its producer blocks. Small, so a fast
+// stage cannot run far ahead of a slow one and buffer the whole batch.
+pub const DEFAULT_CAPACITY: usize = 4;
+
+// Time each stage spent doing work (not waiting on a channel). Compute is
+// summed over all workers, so it can exceed the wall time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StageTimes {
+    pub generate: Duration,
+    pub compute: Duration,
+    pub write: Duration,
+    pub wall: Duration,
+}
+
+// Runs generate, compute and write for every item 0..count, one item after
+// the other. This is the reference the pipelined executor must agree with.
+pub fn run_sequential<T, U>(count: usize, generate: impl Fn(usize) -> T, compute: impl Fn(T) -> U,
+                            mut write: impl FnMut(usize, U) -> io::Result<()>) -> io::Result<StageTimes> {
+    let start = Instant::now();
+    let mut times: StageTimes = StageTimes::default();
+
+    for index in 0..count {
+        let item: T = timed(&mut times.generate, || generate(index));
+        let result: U = timed(&mut times.compute, || compute(item));
+
+        timed(&mut times.write, || write(index, result))?;
+    }
+
+    times.wall = start.elapsed();
+    return Ok(times);
+}
+
+// Runs the same stages concurrently: one thread generates, worker threads
+// compute and the calling thread writes, connected by bounded channels of
+// the given capacity. Results are written in index order, exactly as
+// run_sequential writes them. The first write error stops the pipeline.
+// Without the parallel feature this is run_sequential.
+#[cfg(feature = "parallel")]
+pub fn run_pipelined<T: Send, U: Send>(count: usize, workers: usize, capacity: usize,
+                                       generate: impl Fn(usize) -> T + Sync,
+                                       compute: impl Fn(T) -> U + Sync,
+                                       mut write: impl FnMut(usize, U) -> io::Result<()>) -> io::Result<StageTimes> {
+    let start = Instant::now();
+    let (item_sender, item_receiver) = sync_channel::<(usize, T)>(capacity.max(1));
+    let (result_sender, result_receiver) = sync_channel::<(usize, U)>(capacity.max(1));
+    // Workers take turns receiving; the lock is only held while waiting.
+    let item_receiver: Mutex<Receiver<(usize, T)>> = Mutex::new(item_receiver);
+    // Set by the writer when it gives up, so no further items are generated.
+    let stop: AtomicBool = AtomicBool::new(false);
+
+    let mut times: StageTimes = thread::scope(|scope| {
+        let generator = scope.spawn(|| {
+            let mut busy: Duration = Duration::ZERO;
+
+            for index in 0..count {
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                let item: T = timed(&mut busy, || generate(index));
+
+                if item_sender.send((index, item)).is_err() {
+                    break;
+                }
+            }
+
+            drop(item_sender);
+            return busy;
+        });
+
+        let computers: Vec<_> = (0..workers.max(1)).map(|_| {
+            let result_sender: SyncSender<(usize, U)> = result_sender.clone();
+            let item_receiver: &Mutex<Receiver<(usize, T)>> = &item_receiver;
+            let compute = &compute;
+
+            return scope.spawn(move || {
+                let mut busy: Duration = Duration::ZERO;
+
+                loop {
+                    let next = item_receiver.lock().expect("item channel poisoned").recv();
+                    let Ok((index, item)) = next else { break };
+                    let result: U = timed(&mut busy, || compute(item));
+
+                    if result_sender.send((index, result)).is_err() {
+                        break;
+                    }
+                }
+
+                return busy;
+            });
+        }).collect();
+
+        // Only the workers' clones may keep the result channel open.
+        drop(result_sender);
+
+        // Results arrive in completion order; hold early ones back until
+        // every item before them has been written.
+        let mut write_busy: Duration = Duration::ZERO;
+        let mut pending: BTreeMap<usize, U> = BTreeMap::new();
+        let mut next: usize = 0;
+        let mut outcome: io::Result<()> = Ok(());
+
+        'receive: for (index, result) in result_receiver.iter() {
+            pending.insert(index, result);
+
+            while let Some(result) = pending.remove(&next) {
+                if let Err(err) = timed(&mut write_busy, || write(next, result)) {
+                    outcome = Err(err);
+                    break 'receive;
+                }
+
+                next += 1;
+            }
+        }
+
+        // After a write error, unblock every stage still waiting to send:
+        // workers fail on the closed result channel, and the generator's
+        // remaining items are received and discarded.
+        if outcome.is_err() {
+            stop.store(true, Ordering::Relaxed);
+            drop(result_receiver);
+            item_receiver.lock().expect("item channel poisoned").iter().for_each(drop);
+        }
+
+        let generate_busy: Duration = generator.join().expect("generator thread panicked");
+        let compute_busy: Duration = computers.into_iter().map(|worker| worker.join().expect("worker thread panicked")).sum();
+
+        return outcome.map(|_| StageTimes { generate: generate_busy, compute: compute_busy, write: write_busy, wall: Duration::ZERO });
+    })?;
+
+    times.wall = start.elapsed();
+    return Ok(times);
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn run_pipelined<T, U>(count: usize, _workers: usize, _capacity: usize, generate: impl Fn(usize) -> T,
+                           compute: impl Fn(T) -> U, write: impl FnMut(usize, U) -> io::Result<()>) -> io::Result<StageTimes> {
+    return run_sequential(count, generate, compute, write);
+}
+
+// Runs f and adds its duration to busy.
+fn timed<R>(busy: &mut Duration, f: impl FnOnce() -> R) -> R {
+    let start = Instant::now();
+    let result: R = f();
+    *busy += start.elapsed();
+
+    return result;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Runs count items through run_pipelined, returning what was written in
+    // the order it was written.
+    fn pipelined(count: usize, workers: usize, capacity: usize) -> Vec<(usize, u64)> {
+        let mut written: Vec<(usize, u64)> = Vec::new();
+        run_pipelined(count, workers, capacity, |index| index as u64, |item| item * item + 1, |index, result| {
+            written.push((index, result));
+            return Ok(());
+        }).unwrap();
+
+        return written;
+    }
+
+    // Both builds: the pipelined executor, or its sequential fallback, writes
+    // exactly what run_sequential does.
+    #[test]
+    fn pipelined_writes_what_sequential_does() {
+        for count in [0, 1, 5, 100] {
+            let mut expected: Vec<(usize, u64)> = Vec::new();
+            run_sequential(count, |index| index as u64, |item| item * item + 1, |index, result| {
+                expected.push((index, result));
+                return Ok(());
+            }).unwrap();
+
+            for (workers, capacity) in [(1, 1), (3, 2), (8, DEFAULT_CAPACITY), (2, 0)] {
+                assert_eq!(pipelined(count, workers, capacity), expected, "{} items, {} workers", count, workers);
+            }
+        }
+    }
+
+    #[test]
+    fn a_write_error_stops_the_pipeline() {
+        let mut written: usize = 0;
+        let result: io::Result<StageTimes> = run_pipelined(1000, 4, 2, |index| index, |item| item, |index, _| {
+            if index == 3 {
+                return Err(io::Error::other("disk full"));
+            }
+            written += 1;
+            return Ok(());
+        });
+
+        assert_eq!(result.unwrap_err().to_string(), "disk full");
+        assert_eq!(written, 3);
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+
+// Buffers kept by a pool unless another cap is given.
+pub const DEFAULT_POOL_CAP: usize = 8;
+
+// Spare buffers of one element type, keyed by length, for loops that would
+// otherwise allocate a fresh output every round. Buffers are taken with
+// take, filled by an _into kernel and handed back with give once they are no
+// longer needed. At most cap buffers are kept; extra ones are dropped.
+pub struct BufferPool<T> {
+    buffers: HashMap<usize, Vec<Vec<T>>>,
+    cap: usize,
+    held: usize,
+}
+
+impl<T: Copy + Default> BufferPool<T> {
+    pub fn new(cap: usize) -> BufferPool<T> {
+        return BufferPool { buffers: HashMap::new(), cap, held: 0 };
+    }
+
+    // A buffer of exactly len elements. A reused buffer keeps whatever it
+    // held before, so the caller must overwrite every element.
+    pub fn take(&mut self, len: usize) -> Vec<T> {
+        if let Some(buffer) = self.buffers.get_mut(&len).and_then(|spare| spare.pop()) {
+            self.held -= 1;
+            return buffer;
+        }
+
+        return vec![T::default(); len];
+    }
+
+    // Returns a buffer for reuse by a later take of the same length.
+    pub fn give(&mut self, buffer: Vec<T>) {
+        if self.held >= self.cap {
+            return;
+        }
+
+        self.buffers.entry(buffer.len()).or_default().push(buffer);
+        self.held += 1;
+    }
+
+    // Drops every spare buffer, e.g. when the sizes being processed change.
+    pub fn clear(&mut self) {
+        self.buffers.clear();
+        self.held = 0;
+    }
+
+    // Number of spare buffers currently kept.
+    pub fn len(&self) -> usize {
+        return self.held;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.held == 0;
+    }
+}
+
+impl<T: Copy + Default> Default for BufferPool<T> {
+    fn default() -> BufferPool<T> {
+        return BufferPool::new(DEFAULT_POOL_CAP);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffers_are_reused_by_length() {
+        let mut pool: BufferPool<i16> = BufferPool::new(4);
+        let mut buffer: Vec<i16> = pool.take(10);
+        assert_eq!(buffer, vec![0; 10]);
+        buffer[3] = 7;
+        let address: *const i16 = buffer.as_ptr();
+        pool.give(buffer);
+        assert_eq!(pool.len(), 1);
+
+        // Another length gets a fresh buffer; the same length the old one,
+        // contents and all.
+        assert_eq!(pool.take(9), vec![0; 9]);
+        let reused: Vec<i16> = pool.take(10);
+        assert_eq!((reused.as_ptr(), reused[3]), (address, 7));
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn the_cap_limits_the_buffers_kept() {
+        let mut pool: BufferPool<u8> = BufferPool::new(3);
+
+        for len in 1..=5 {
+            pool.give(vec![0; len]);
+        }
+        assert_eq!(pool.len(), 3);
+
+        // The first three were kept, the rest dropped.
+        pool.take(3);
+        assert_eq!(pool.len(), 2);
+        pool.take(4);
+        assert_eq!(pool.len(), 2);
+
+        let mut none: BufferPool<u8> = BufferPool::new(0);
+        none.give(vec![1, 2]);
+        assert!(none.is_empty());
+        assert_eq!(BufferPool::<u8>::default().cap, DEFAULT_POOL_CAP);
+    }
+
+    #[test]
+    fn clear_drops_every_buffer() {
+        let mut pool: BufferPool<f32> = BufferPool::default();
+        pool.give(vec![1.0; 4]);
+        pool.give(vec![2.0; 4]);
+        pool.give(vec![3.0; 8]);
+
+        pool.clear();
+        assert!(pool.is_empty());
+        assert_eq!(pool.take(4), vec![0.0; 4]);
+
+        // Still usable, with its count starting over.
+        pool.give(vec![0.5; 2]);
+        assert_eq!(pool.len(), 1);
+    }
+
+    // A take/fill/give loop allocates once for the buffer and once or twice
+    // for the pool's bookkeeping, however many rounds it makes.
+    #[cfg(feature = "mem-stats")]
+    #[test]
+    fn a_loop_allocates_a_constant_number_of_times() {
+        use crate::memstats::{thread_reset, thread_snapshot};
+
+        let rounds = |count: usize| -> usize {
+            let mut pool: BufferPool<i16> = BufferPool::default();
+
+            thread_reset();
+            for round in 0..count {
+                let mut buffer: Vec<i16> = pool.take(4096);
+                buffer.fill(round as i16);
+                pool.give(buffer);
+            }
+
+            return thread_snapshot().alloc_count;
+        };
+
+        let hundred: usize = rounds(100);
+        assert!(hundred <= 3, "{} allocations", hundred);
+        assert_eq!(rounds(1), hundred);
+
+        // Without the pool, every round allocates.
+        thread_reset();
+        for round in 0..100 {
+            std::hint::black_box(vec![round as i16; 4096]);
+        }
+        assert_eq!(thread_snapshot().alloc_count, 100);
+    }
+}
@@ -0,0 +1,205 @@
+use std::path::Path;
+use crate::error::{check_len, DimError};
+use crate::io::{invalid_data, read_matrix, StoredMatrix};
+
+// A transfer function run on results after the convolution and before they
+// are exported, e.g. a log compression of the gradients. data is a row-major
+// rows x cols matrix, or in streaming mode one strip of rows of it at a time,
+// so a hook must treat every row alike: running it on a whole matrix and on
+// each strip of it in turn has to give the same result (see apply_strips).
+// Per-element hooks always do.
+pub trait PostProcess: Send + Sync {
+    fn apply(&self, data: &mut [i16], rows: usize, cols: usize);
+}
+
+// Leaves the results as they are.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Identity;
+
+impl PostProcess for Identity {
+    fn apply(&self, _data: &mut [i16], _rows: usize, _cols: usize) {}
+}
+
+// Entries of a Lut256 table, one for every value from -255 to 255.
+pub const LUT_LEN: usize = 511;
+
+// Maps every value v to table[v + 255]. Dx and Dy of a u8 input lie in
+// -255..=255; values beyond it, from other kernels, are clamped to it first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lut256 {
+    table: Vec<i16>,
+}
+
+impl Lut256 {
+    pub fn new(table: Vec<i16>) -> Result<Lut256, DimError> {
+        if table.len() != LUT_LEN {
+            return Err(DimError::Mismatch { what: "LUT entries", expected: LUT_LEN, found: table.len() });
+        }
+
+        return Ok(Lut256 { table });
+    }
+
+    // The table mapping v to f(v).
+    pub fn from_fn(f: impl FnMut(i16) -> i16) -> Lut256 {
+        return Lut256 { table: (-255..=255).map(f).collect() };
+    }
+
+    // Reads a table from any matrix file read_matrix accepts, e.g. a CSV
+    // with one entry per line. The file may have any shape as long as it
+    // holds LUT_LEN values in row-major order, the entry for -255 first, and
+    // every value fits an i16.
+    pub fn load(path: &Path) -> std::io::Result<Lut256> {
+        let stored: StoredMatrix = read_matrix(path)?;
+
+        let table: Vec<i16> = stored.values.iter().map(|&value| {
+            i16::try_from(value).map_err(|_| invalid_data(&format!("LUT value {} does not fit an i16", value)))
+        }).collect::<std::io::Result<Vec<i16>>>()?;
+
+        return Lut256::new(table).map_err(|err| invalid_data(&err.to_string()));
+    }
+
+    pub fn table(&self) -> &[i16] {
+        return &self.table;
+    }
+}
+
+impl PostProcess for Lut256 {
+    fn apply(&self, data: &mut [i16], _rows: usize, _cols: usize) {
+        for value in data.iter_mut() {
+            *value = self.table[((*value).clamp(-255, 255) + 255) as usize];
+        }
+    }
+}
+
+// Hooks run one after the other, in the order they were pushed.
+#[derive(Default)]
+pub struct Chain {
+    hooks: Vec<Box<dyn PostProcess>>,
+}
+
+impl Chain {
+    pub fn new() -> Chain {
+        return Chain::default();
+    }
+
+    pub fn push(&mut self, hook: Box<dyn PostProcess>) {
+        self.hooks.push(hook);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.hooks.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.hooks.is_empty();
+    }
+}
+
+impl PostProcess for Chain {
+    fn apply(&self, data: &mut [i16], rows: usize, cols: usize) {
+        for hook in &self.hooks {
+            hook.apply(data, rows, cols);
+        }
+    }
+}
+
+// Runs hook on a rows x cols matrix strip_rows rows at a time, the way a
+// streaming writer does as it produces the output. The last strip may be
+// shorter.
+pub fn apply_strips(hook: &dyn PostProcess, data: &mut [i16], rows: usize, cols: usize, strip_rows: usize) -> Result<(), DimError> {
+    check_len(data.len(), rows, cols)?;
+
+    if cols == 0 {
+        return Ok(());
+    }
+
+    for strip in data.chunks_mut(strip_rows.max(1) * cols) {
+        hook.apply(strip, strip.len() / cols, cols);
+    }
+
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Adds its amount to every element, saturating.
+    struct AddHook(i16);
+
+    impl PostProcess for AddHook {
+        fn apply(&self, data: &mut [i16], _rows: usize, _cols: usize) {
+            for value in data.iter_mut() {
+                *value = value.saturating_add(self.0);
+            }
+        }
+    }
+
+    // Doubles every element, so that it does not commute with AddHook.
+    struct DoubleHook;
+
+    impl PostProcess for DoubleHook {
+        fn apply(&self, data: &mut [i16], _rows: usize, _cols: usize) {
+            for value in data.iter_mut() {
+                *value = value.saturating_mul(2);
+            }
+        }
+    }
+
+    fn sample() -> Vec<i16> {
+        return vec![-255, -3, 0, 1, 7, 255, 400, -1000];
+    }
+
+    #[test]
+    fn hooks_run_in_registration_order() {
+        let mut add_then_double: Chain = Chain::new();
+        add_then_double.push(Box::new(AddHook(1)));
+        add_then_double.push(Box::new(DoubleHook));
+
+        let mut double_then_add: Chain = Chain::new();
+        double_then_add.push(Box::new(DoubleHook));
+        double_then_add.push(Box::new(AddHook(1)));
+
+        let (mut first, mut second): (Vec<i16>, Vec<i16>) = (sample(), sample());
+        add_then_double.apply(&mut first, 2, 4);
+        double_then_add.apply(&mut second, 2, 4);
+
+        assert_eq!(first, sample().iter().map(|value| (value + 1) * 2).collect::<Vec<i16>>());
+        assert_eq!(second, sample().iter().map(|value| value * 2 + 1).collect::<Vec<i16>>());
+        assert_eq!(add_then_double.len(), 2);
+    }
+
+    #[test]
+    fn hooks_apply_to_every_element_in_any_strips() {
+        let lut: Lut256 = Lut256::from_fn(|value| value / 2);
+        let expected: Vec<i16> = vec![-127, -1, 0, 0, 3, 127, 127, -127];
+
+        for strip_rows in [0, 1, 2, 3, 8] {
+            let mut data: Vec<i16> = sample();
+            apply_strips(&lut, &mut data, 4, 2, strip_rows).unwrap();
+            assert_eq!(data, expected, "{} rows per strip", strip_rows);
+        }
+
+        assert!(apply_strips(&lut, &mut sample(), 3, 3, 1).is_err());
+    }
+
+    #[test]
+    fn no_hooks_is_the_identity() {
+        let mut data: Vec<i16> = sample();
+
+        Chain::new().apply(&mut data, 2, 4);
+        assert_eq!(data, sample());
+        Identity.apply(&mut data, 2, 4);
+        assert_eq!(data, sample());
+        // The identity table too, up to the clamp of the last two values.
+        Lut256::from_fn(|value| value).apply(&mut data, 2, 4);
+        assert_eq!(data[..6], sample()[..6]);
+        assert!(Chain::new().is_empty());
+    }
+
+    #[test]
+    fn lut_tables_need_511_entries() {
+        assert_eq!(Lut256::new(vec![0; 510]), Err(DimError::Mismatch { what: "LUT entries", expected: LUT_LEN, found: 510 }));
+        assert_eq!(Lut256::new(vec![7; LUT_LEN]).unwrap().table(), &[7; LUT_LEN][..]);
+    }
+}
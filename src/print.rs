@@ -0,0 +1,119 @@
+use crate::convert::normalize_u8;
+use crate::index::flat;
+use crate::resize::resize_area;
+
+// Utility used to print vector of unsigned char
+pub fn print_2d_array_u8(arr: &[u8], rows: usize, cols: usize) {
+    println!("Array of size {}x{}\nRaw values:", rows, cols);
+
+    for value in &arr[..rows * cols] {
+        print!("{},", value);
+    }
+
+    println!("\nFormatted values:");
+
+    for row in 0.. rows {
+        print!("[");
+        for col in 0.. cols {
+            print!("{},", arr[flat(row, col, cols)]);
+        }
+        println!("]");
+    }
+
+    println!("]");
+    return;
+}
+
+// Utility used to print vector of 16-bit ints
+pub fn print_2d_array_i16(arr: &[i16], rows: usize, cols: usize) {
+    println!("Array of size {}x{}\nRaw values:", rows, cols);
+
+    for value in &arr[..rows * cols] {
+        print!("{},", value);
+    }
+
+    println!("\nFormatted values:");
+
+    for row in 0.. rows {
+        print!("[");
+        for col in 0.. cols {
+            print!("{},", arr[flat(row, col, cols)]);
+        }
+        println!("]")
+    }
+
+    println!("]");
+    return;
+}
+
+// Characters used by render_ascii, from lowest to highest value.
+pub const ASCII_RAMP: &[u8] = b" .:-=+*#%@";
+
+// Renders a matrix as ASCII art at most width characters wide, one line per
+// output row. The matrix is downsampled by area averaging (never upscaled),
+// with half as many lines as the aspect ratio suggests since terminal cells
+// are about twice as tall as they are wide, then stretched to the full ramp.
+pub fn render_ascii(arr: &[u8], rows: usize, cols: usize, width: usize) -> String {
+    if rows == 0 || cols == 0 || width == 0 {
+        return String::new();
+    }
+
+    let new_cols: usize = width.min(cols);
+    let new_rows: usize = ((rows * new_cols) as f64 / cols as f64 / 2.0).round().clamp(1.0, rows as f64) as usize;
+    let levels: Vec<u8> = normalize_u8(&resize_area(arr, rows, cols, new_rows, new_cols).expect("Matrix has unexpected dimensions"));
+    let mut text: String = String::with_capacity(new_rows * (new_cols + 1));
+
+    for line in levels.chunks(new_cols) {
+        for level in line {
+            text.push(ASCII_RAMP[*level as usize * ASCII_RAMP.len() / 256] as char);
+        }
+        text.push('\n');
+    }
+
+    return text;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two equal rows stepping evenly from 3 to 255, one step per ramp
+    // character.
+    fn steps(cols: usize) -> Vec<u8> {
+        let row: Vec<u8> = (0..cols).map(|col| (3 + col * 252 / (cols - 1)) as u8).collect();
+
+        return [row.clone(), row].concat();
+    }
+
+    #[test]
+    fn the_minimum_and_maximum_map_to_the_ends_of_the_ramp() {
+        // Two rows of ten halve to one line, and each step is one character.
+        assert_eq!(render_ascii(&steps(10), 2, 10, 80), " .:-=+*#%@\n");
+
+        // Downsampled to five columns the averages are still stretched over
+        // the whole ramp.
+        let text: String = render_ascii(&steps(10), 2, 10, 5);
+
+        assert_eq!(text.len(), 6);
+        assert!(text.starts_with(' ') && text.ends_with("@\n"), "{:?}", text);
+    }
+
+    #[test]
+    fn lines_follow_the_halved_aspect_ratio() {
+        let arr: Vec<u8> = crate::matrix::construct_randomized_matrix_seeded(40, 60, 2);
+        let text: String = render_ascii(&arr, 40, 60, 30);
+        let lines: Vec<&str> = text.lines().collect();
+
+        // 40 rows at half the column scale, halved again: 10 lines.
+        assert_eq!(lines.len(), 10);
+        assert!(lines.iter().all(|line| line.len() == 30 && line.bytes().all(|c| ASCII_RAMP.contains(&c))), "{}", text);
+        assert!(text.contains(' ') && text.contains('@'), "{}", text);
+    }
+
+    #[test]
+    fn constant_and_empty_inputs() {
+        assert_eq!(render_ascii(&[77; 12], 3, 4, 4), "    \n    \n");
+        assert_eq!(render_ascii(&[], 0, 4, 4), "");
+        assert_eq!(render_ascii(&[1, 2], 1, 2, 0), "");
+    }
+}
@@ -0,0 +1,232 @@
+use crate::error::{check_len, DimError};
+use crate::filters::gaussian_blur;
+use crate::gradient::abs_gradient;
+use crate::index::flat;
+use crate::kernels::{compute_dx, compute_dy};
+use crate::ops::{crop_dx_padding, crop_dy_padding, Block};
+use crate::stats::{float_stats_with, get_max, get_min, get_sum, Accumulator};
+
+// Standard deviation of the Gaussian applied before each downsampling step.
+pub const PYRAMID_SIGMA: f32 = 1.0;
+
+// Keeps every other row and column. Odd dimensions are rounded down, so a
+// 5x7 matrix becomes 2x3 and the last row/column is dropped.
+pub fn downsample2x(arr: &[u8], rows: usize, cols: usize) -> Block<u8> {
+    let new_rows: usize = rows / 2;
+    let new_cols: usize = cols / 2;
+    let mut out: Vec<u8> = Vec::with_capacity(new_rows * new_cols);
+
+    for row in 0..new_rows {
+        for col in 0..new_cols {
+            out.push(arr[flat(2 * row, 2 * col, cols)]);
+        }
+    }
+
+    return (out, new_rows, new_cols);
+}
+
+// Builds an image pyramid with up to `levels` levels. Level 0 is a copy of
+// the input and each following level is the previous one blurred with
+// PYRAMID_SIGMA and downsampled by 2 (rounding odd dimensions down). Building
+// stops early once another level would have a zero dimension.
+pub fn build_pyramid(arr: &[u8], rows: usize, cols: usize, levels: usize) -> Vec<Block<u8>> {
+    let mut pyramid: Vec<Block<u8>> = Vec::with_capacity(levels);
+
+    if levels == 0 {
+        return pyramid;
+    }
+
+    pyramid.push((arr.to_vec(), rows, cols));
+
+    while pyramid.len() < levels {
+        let (prev, prev_rows, prev_cols) = &pyramid[pyramid.len() - 1];
+
+        if prev_rows / 2 == 0 || prev_cols / 2 == 0 {
+            break;
+        }
+
+        let blurred: Vec<u8> = gaussian_blur(prev, *prev_rows, *prev_cols, PYRAMID_SIGMA);
+        let level: Block<u8> = downsample2x(&blurred, *prev_rows, *prev_cols);
+        pyramid.push(level);
+    }
+
+    return pyramid;
+}
+
+// Statistics of one gradient at one level of a scale report, over the rows x
+// cols region aligned with the level; the padding of the full Dx and Dy is
+// left out, since at coarse levels it would outweigh the interior. The
+// outermost rows and columns still see the zero padding of the kernel.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GradientSummary {
+    pub min: i16,
+    pub max: i16,
+    // Population standard deviation.
+    pub stddev: f64,
+    pub mean_abs: f64,
+}
+
+// One level of scale_report.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScaleLevel {
+    pub level: usize,
+    pub rows: usize,
+    pub cols: usize,
+    pub dx: GradientSummary,
+    pub dy: GradientSummary,
+    // Mean L1 gradient magnitude |Dx| + |Dy| over the level.
+    pub mean_abs_gradient: f64,
+    // mean_abs_gradient divided by that of the level before, None for level
+    // 0 or when the level before had no gradient at all. Content that
+    // survives blurring keeps its gradient while the level shrinks, so the
+    // ratio is near or above 1 for sharp edges and well below 1 for noise.
+    pub ratio: Option<f64>,
+}
+
+// Computes Dx and Dy at every level of build_pyramid(arr, rows, cols, levels)
+// and summarizes them, to show how much of the gradient energy of an input
+// lives at fine scales.
+pub fn scale_report(arr: &[u8], rows: usize, cols: usize, levels: usize) -> Result<Vec<ScaleLevel>, DimError> {
+    return scale_report_with(arr, rows, cols, levels, Accumulator::F64);
+}
+
+// scale_report with the standard deviations and mean magnitudes reduced in
+// accumulator, see rmm::stats::float_stats_with.
+pub fn scale_report_with(arr: &[u8], rows: usize, cols: usize, levels: usize, accumulator: Accumulator) -> Result<Vec<ScaleLevel>, DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    let mut report: Vec<ScaleLevel> = Vec::with_capacity(levels);
+
+    for (level, (level_arr, level_rows, level_cols)) in build_pyramid(arr, rows, cols, levels).into_iter().enumerate() {
+        let dx: Vec<i16> = crop_dx_padding(&compute_dx(&level_arr, level_rows, level_cols).data, level_rows, level_cols)?;
+        let dy: Vec<i16> = crop_dy_padding(&compute_dy(&level_arr, level_rows, level_cols).data, level_rows, level_cols)?;
+        let magnitude: Vec<u16> = abs_gradient(&dx, &dy, level_rows, level_cols)?;
+        let mean_abs_gradient: f64 = get_sum(&magnitude) as f64 / magnitude.len().max(1) as f64;
+        let ratio: Option<f64> = report.last().map(|previous: &ScaleLevel| previous.mean_abs_gradient)
+            .filter(|&previous| previous > 0.0).map(|previous| mean_abs_gradient / previous);
+
+        report.push(ScaleLevel { level, rows: level_rows, cols: level_cols, dx: summarize(&dx, accumulator), dy: summarize(&dy, accumulator), mean_abs_gradient, ratio });
+    }
+
+    return Ok(report);
+}
+
+fn summarize(data: &[i16], accumulator: Accumulator) -> GradientSummary {
+    if data.is_empty() {
+        return GradientSummary::default();
+    }
+
+    let values: Vec<f32> = data.iter().map(|&value| value as f32).collect();
+    let magnitudes: Vec<f32> = data.iter().map(|&value| value.unsigned_abs() as f32).collect();
+
+    return GradientSummary {
+        min: get_min(data),
+        max: get_max(data),
+        stddev: float_stats_with(&values, 1, accumulator).variance.sqrt(),
+        mean_abs: float_stats_with(&magnitudes, 1, accumulator).mean,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::construct_randomized_matrix_seeded;
+
+    #[test]
+    fn level_0_is_the_input_and_each_level_halves() {
+        let (rows, cols): (usize, usize) = (37, 100);
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, 11);
+        let pyramid: Vec<Block<u8>> = build_pyramid(&arr, rows, cols, 20);
+
+        assert_eq!(pyramid[0], (arr.clone(), rows, cols));
+
+        // 37x100, 18x50, 9x25, 4x12, 2x6, 1x3, and no level of 0 rows.
+        let dims: Vec<(usize, usize)> = pyramid.iter().map(|&(_, rows, cols)| (rows, cols)).collect();
+        assert_eq!(dims, vec![(37, 100), (18, 50), (9, 25), (4, 12), (2, 6), (1, 3)]);
+
+        for pair in pyramid.windows(2) {
+            let ((prev, prev_rows, prev_cols), (level, level_rows, level_cols)) = (&pair[0], &pair[1]);
+            let blurred: Vec<u8> = gaussian_blur(prev, *prev_rows, *prev_cols, PYRAMID_SIGMA);
+
+            assert_eq!(level.len(), level_rows * level_cols);
+            assert_eq!(downsample2x(&blurred, *prev_rows, *prev_cols), (level.clone(), *level_rows, *level_cols));
+        }
+
+        assert_eq!(build_pyramid(&arr, rows, cols, 3).len(), 3);
+        assert_eq!(&build_pyramid(&arr, rows, cols, 3)[..], &pyramid[..3]);
+    }
+
+    #[test]
+    fn square_pyramids_end_at_1x1() {
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(16, 16, 2);
+        let dims: Vec<(usize, usize)> = build_pyramid(&arr, 16, 16, 10).iter().map(|&(_, rows, cols)| (rows, cols)).collect();
+
+        assert_eq!(dims, vec![(16, 16), (8, 8), (4, 4), (2, 2), (1, 1)]);
+    }
+
+    #[test]
+    fn no_levels_and_single_rows() {
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(1, 9, 5);
+
+        assert!(build_pyramid(&arr, 1, 9, 0).is_empty());
+        assert_eq!(build_pyramid(&arr, 1, 9, 4), vec![(arr.clone(), 1, 9)]);
+        assert_eq!(build_pyramid(&arr, 9, 1, 4), vec![(arr.clone(), 9, 1)]);
+        assert_eq!(build_pyramid(&[], 0, 0, 2), vec![(vec![], 0, 0)]);
+    }
+
+    #[test]
+    fn downsampling_keeps_the_even_rows_and_columns() {
+        let arr: Vec<u8> = (0..35).collect();
+
+        assert_eq!(downsample2x(&arr, 5, 7), (vec![0, 2, 4, 14, 16, 18], 2, 3));
+        assert_eq!(downsample2x(&arr[..7], 1, 7), (vec![], 0, 3));
+    }
+
+    #[test]
+    fn scale_report_summarizes_every_pyramid_level() {
+        let (rows, cols): (usize, usize) = (37, 100);
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, 11);
+        let report: Vec<ScaleLevel> = scale_report(&arr, rows, cols, 4).unwrap();
+        let pyramid: Vec<Block<u8>> = build_pyramid(&arr, rows, cols, 4);
+
+        assert_eq!(report.len(), 4);
+        assert_eq!(report[0].ratio, None);
+
+        for (index, (level, (level_arr, level_rows, level_cols))) in report.iter().zip(&pyramid).enumerate() {
+            let dx: Vec<i16> = crop_dx_padding(&compute_dx(level_arr, *level_rows, *level_cols).data, *level_rows, *level_cols).unwrap();
+            let dy: Vec<i16> = crop_dy_padding(&compute_dy(level_arr, *level_rows, *level_cols).data, *level_rows, *level_cols).unwrap();
+            let l1: u64 = dx.iter().zip(&dy).map(|(&gx, &gy)| gx.unsigned_abs() as u64 + gy.unsigned_abs() as u64).sum();
+
+            assert_eq!((level.level, level.rows, level.cols), (index, *level_rows, *level_cols));
+            assert_eq!((level.dx.min, level.dx.max), (*dx.iter().min().unwrap(), *dx.iter().max().unwrap()));
+            assert_eq!((level.dy.min, level.dy.max), (*dy.iter().min().unwrap(), *dy.iter().max().unwrap()));
+            assert!((level.mean_abs_gradient - l1 as f64 / dx.len() as f64).abs() < 1e-9, "level {}", index);
+            assert!((level.dx.mean_abs - dx.iter().map(|&gx| gx.unsigned_abs() as f64).sum::<f64>() / dx.len() as f64).abs() < 1e-9);
+
+            if index > 0 {
+                assert_eq!(level.ratio, Some(level.mean_abs_gradient / report[index - 1].mean_abs_gradient));
+            }
+        }
+
+        // Blurring removes most of the gradient of noise.
+        assert!(report[1].ratio.unwrap() < 0.5, "{:?}", report[1].ratio);
+        assert!(scale_report(&arr, rows, cols + 1, 4).is_err());
+    }
+
+    #[test]
+    fn sharp_edges_keep_their_gradient_across_levels() {
+        // Vertical stripes 16 columns wide survive three halvings.
+        let (rows, cols): (usize, usize) = (64, 128);
+        let arr: Vec<u8> = (0..rows * cols).map(|index| if index % cols / 16 % 2 == 0 { 0 } else { 255 }).collect();
+        let report: Vec<ScaleLevel> = scale_report(&arr, rows, cols, 4).unwrap();
+
+        for level in &report[1..] {
+            assert!(level.ratio.unwrap() > 0.9, "level {}: {:?}", level.level, level.ratio);
+        }
+
+        // A flat zero input has no gradient, so no level has a ratio.
+        let flat: Vec<ScaleLevel> = scale_report(&[0; 64], 8, 8, 3).unwrap();
+
+        assert!(flat.iter().all(|level| level.mean_abs_gradient == 0.0 && level.ratio.is_none() && level.dx == GradientSummary::default()));
+    }
+}
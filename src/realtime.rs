@@ -0,0 +1,19 @@
+// The kernels a real-time caller can run on its hot path. Given buffers of
+// the right size, every function re-exported here
+//
+//   - performs no heap allocation, not even on error (DimError carries only
+//     numbers and static strings),
+//   - takes no locks and spawns no threads,
+//   - does work bounded by the size of its inputs.
+//
+// This is part of the API: a change that breaks it for any function below is
+// a breaking change. Everything else in the library may allocate, in
+// particular the functions returning a Vec or Matrix, the _par kernels and
+// the thread scheduler behind --threads, the pool and the pipeline, so a
+// caller that must not stall should take its kernels from this module only.
+// Buffers are sized once up front: Dx is rows x (cols + 2), Dy is (rows + 2)
+// x cols, and the magnitude and the normalized image are rows x cols.
+
+pub use crate::convert::normalize_u8_into;
+pub use crate::gradient::magnitude_into;
+pub use crate::kernels::{compute_dx_into, compute_dxdy_into, compute_dy_into};
@@ -0,0 +1,346 @@
+use std::fs;
+use std::process;
+use toml::{Table, Value};
+use rmm::kernels::SECOND_DIFFERENCE;
+use rmm::matrix::Layout;
+use rmm::timing::{TimingConfig, Warmup};
+use crate::cli::{self, switch_enabled, Backend, InputType, Operator, Options, SWITCHES};
+use crate::{config, timing_config};
+
+// Exit status of a replay refused because the repro file does not match this
+// build or machine, see mismatches.
+pub(crate) const EXIT_REPRO_MISMATCH: i32 = 4;
+
+// A run recorded by --emit-repro for --repro to replay:
+//
+//     version = "0.1.0"
+//     cpu-features = ["sse4.1", "avx", "avx2", "fma"]
+//     args = ["--size", "512", "--kernel", "1,2,1"]
+//
+//     [resolved]
+//     seed = 8471983373645178026
+//     rows = 512
+//     ...
+//
+//     [result]
+//     checksum = "9d2f0e4c5b61a873"
+//
+// args is the command line with any --config file written out as flags, so
+// the file stands on its own. resolved holds every setting with a default as
+// the run resolved it: the seed picked for an unseeded input and the detected
+// last-level cache size are replayed from it, and the rest must come out the
+// same from args, or a default changed. checksum covers Dx followed by Dy, as
+// for --assert-checksum.
+pub(crate) struct Repro {
+    pub path: String,
+    pub version: String,
+    pub cpu_features: Vec<String>,
+    pub args: Vec<String>,
+    pub resolved: Table,
+    pub checksum: u64,
+    // --force: replay even if mismatches finds something.
+    pub force: bool,
+}
+
+// Writes the repro file of a run with the command line args, the settings
+// resolved (see resolved) and Dx and Dy of the given checksum.
+pub(crate) fn write(path: &str, args: &[String], resolved: Table, checksum: u64) -> std::io::Result<()> {
+    let mut table: Table = Table::new();
+
+    table.insert("version".to_string(), Value::from(env!("CARGO_PKG_VERSION")));
+    table.insert("cpu-features".to_string(), Value::from(cpu_features()));
+    table.insert("args".to_string(), Value::from(recorded_args(args)));
+    table.insert("resolved".to_string(), Value::Table(resolved));
+    table.insert("result".to_string(), Value::Table(Table::from_iter([("checksum".to_string(), Value::from(format!("{:016x}", checksum)))])));
+
+    let text: String = toml::to_string(&table).map_err(|err| std::io::Error::other(err.to_string()))?;
+
+    return fs::write(path, format!("# Written by --emit-repro; replay with --repro {}\n{}", path, text));
+}
+
+// Reads a repro file written by write.
+pub(crate) fn read(path: &str, force: bool) -> Result<Repro, String> {
+    let text: String = fs::read_to_string(path).map_err(|err| format!("Failed to read {}: {}", path, err))?;
+    let mut table: Table = text.parse().map_err(|err| format!("Invalid repro file {}: {}", path, err))?;
+    let strings = |value: Option<Value>, key: &str| -> Result<Vec<String>, String> {
+        return value.and_then(|value| value.try_into().ok()).ok_or_else(|| format!("{}: {} must be a list of strings", path, key));
+    };
+
+    let version: String = table.remove("version").and_then(|value| value.as_str().map(str::to_string))
+        .ok_or_else(|| format!("{}: no version", path))?;
+    let cpu_features: Vec<String> = strings(table.remove("cpu-features"), "cpu-features")?;
+    let args: Vec<String> = strings(table.remove("args"), "args")?;
+    let resolved: Table = match table.remove("resolved") {
+        Some(Value::Table(resolved)) => resolved,
+        _ => return Err(format!("{}: no [resolved] table", path)),
+    };
+    let checksum: u64 = table.get("result").and_then(|result| result.get("checksum")).and_then(Value::as_str)
+        .and_then(|hex| u64::from_str_radix(hex, 16).ok()).ok_or_else(|| format!("{}: no valid [result] checksum", path))?;
+
+    return Ok(Repro { path: path.to_string(), version, cpu_features, args, resolved, checksum, force });
+}
+
+// The options of the run repro recorded.
+pub(crate) fn replay_options(repro: Repro) -> Options {
+    let mut args: Vec<String> = vec!["repro".to_string()];
+    args.extend(repro.args.iter().cloned());
+
+    let mut options: Options = cli::parse_args(&args);
+
+    options.seed = repro.resolved.get("seed").and_then(Value::as_integer).map(|seed| seed as u64).or(options.seed);
+    options.llc_size = repro.resolved.get("llc-size").and_then(Value::as_integer).map(|bytes| bytes as usize).or(options.llc_size);
+    options.assert_checksum = Some(repro.checksum);
+    options.replay = Some(repro);
+
+    return options;
+}
+
+// Why repro may not reproduce here: another crate version, or CPU features it
+// was recorded with that this machine lacks.
+pub(crate) fn mismatches(repro: &Repro) -> Vec<String> {
+    let mut found: Vec<String> = Vec::new();
+
+    if repro.version != env!("CARGO_PKG_VERSION") {
+        found.push(format!("recorded by version {}, this is {}", repro.version, env!("CARGO_PKG_VERSION")));
+    }
+
+    let here: Vec<&str> = cpu_features();
+    let missing: Vec<&str> = repro.cpu_features.iter().map(String::as_str).filter(|feature| !here.contains(feature)).collect();
+
+    if !missing.is_empty() {
+        found.push(format!("recorded on a CPU with {}, which this one lacks", missing.join(", ")));
+    }
+
+    return found;
+}
+
+// The settings of resolved, those of the replay, that differ from the
+// recorded ones.
+pub(crate) fn setting_mismatches(repro: &Repro, resolved: &Table) -> Vec<String> {
+    let mut found: Vec<String> = Vec::new();
+
+    for (key, value) in resolved {
+        match repro.resolved.get(key) {
+            Some(recorded) if recorded == value => {}
+            Some(recorded) => found.push(format!("{} resolves to {}, recorded as {}", key, value, recorded)),
+            None => found.push(format!("{} resolves to {}, not recorded", key, value)),
+        }
+    }
+
+    return found;
+}
+
+// Exits with EXIT_REPRO_MISMATCH, listing the mismatches found, unless there
+// are none or the replay was forced.
+pub(crate) fn enforce(repro: &Repro, found: &[String]) {
+    if found.is_empty() {
+        return;
+    }
+
+    for mismatch in found {
+        eprintln!("{}: {}", repro.path, mismatch);
+    }
+
+    if repro.force {
+        eprintln!("warning: replaying {} anyway (--force); the results may differ", repro.path);
+        return;
+    }
+
+    eprintln!("Refusing to replay {}; pass --force to replay it anyway.", repro.path);
+    process::exit(EXIT_REPRO_MISMATCH);
+}
+
+// Every setting of a run of options with a default, as resolved for the
+// rows x cols input it ran on, plus the seed it was generated from.
+pub(crate) fn resolved(options: &Options, seed: Option<u64>, rows: usize, cols: usize) -> Table {
+    let timing: TimingConfig = timing_config(options);
+    let mut table: Table = Table::new();
+    let mut set = |key: &str, value: Value| {
+        table.insert(key.to_string(), value);
+    };
+
+    if let Some(seed) = seed {
+        set("seed", Value::Integer(seed as i64));
+    }
+
+    set("rows", Value::Integer(rows as i64));
+    set("cols", Value::Integer(cols as i64));
+    set("dtype", Value::from(match options.dtype {
+        InputType::U8 => "u8",
+        InputType::Q8_8 => "q8.8",
+        InputType::U4 => "u4",
+    }));
+    set("operator", Value::from(match options.operator {
+        Operator::FirstDerivative => "first-derivative",
+        Operator::SecondDerivative => "second-derivative",
+        Operator::Directional => "directional",
+    }));
+
+    let kernel: Vec<i32> = match (options.operator, &options.kernel) {
+        (Operator::SecondDerivative, _) => SECOND_DIFFERENCE.to_vec(),
+        (_, Some(kernel)) => kernel.clone(),
+        (_, None) => vec![-1, 0, 1],
+    };
+    set("pad", Value::Integer(options.pad.unwrap_or(kernel.len() - 1) as i64));
+    set("kernel", Value::from(kernel.iter().map(|&tap| tap as i64).collect::<Vec<i64>>()));
+    set("arith", Value::from(format!("{:?}", options.arith.unwrap_or_default()).to_lowercase()));
+    set("conv-mode", Value::from(options.conv_mode.unwrap_or_default().name()));
+    set("layout", Value::from(if options.layout == Layout::ColMajor { "col" } else { "row" }));
+    set("backend", Value::from(if options.backend == Backend::Gpu { "gpu" } else { "cpu" }));
+    set("threads", Value::Integer(options.threads.unwrap_or(1) as i64));
+    set("output-dtype", Value::from(options.output_dtype.unwrap_or_default().to_string()));
+    set("accumulate", Value::from(options.accumulate.unwrap_or_default().name()));
+    set("warmup", match timing.warmup {
+        Warmup::Fixed(runs) => Value::Integer(runs as i64),
+        Warmup::Auto => Value::from("auto"),
+    });
+    set("samples", Value::Integer(timing.samples as i64));
+    set("max-bench-time", Value::from(format!("{:?}", timing.max_time)));
+    set("cache-state", Value::from(options.cache_states.iter().map(|state| state.name()).collect::<Vec<&str>>()));
+
+    if let Some(llc_size) = options.llc_size {
+        set("llc-size", Value::Integer(llc_size as i64));
+    }
+
+    for flag in SWITCHES {
+        set(&flag[2..], Value::Boolean(switch_enabled(options, flag)));
+    }
+
+    return table;
+}
+
+// The command line of a run, args[0] left out, with any --config file
+// written out as the flags it stands for and --emit-repro dropped.
+fn recorded_args(args: &[String]) -> Vec<String> {
+    let mut recorded: Vec<String> = Vec::new();
+    let mut iter = args[1..].iter();
+
+    // The config file applies first, wherever --config appears.
+    if let Some(index) = args.iter().position(|arg| arg == "--config") {
+        recorded.extend(config::config_args(args[index + 1].trim()));
+    }
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--config" | "--emit-repro" => {
+                iter.next();
+            }
+            _ => recorded.push(arg.clone()),
+        }
+    }
+
+    return recorded;
+}
+
+// The CPU features this machine has among those that decide which kernels a
+// run may use (see rmm::matmul::simd_available).
+pub(crate) fn cpu_features() -> Vec<&'static str> {
+    #[allow(unused_mut)]
+    let mut features: Vec<&'static str> = Vec::new();
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("sse4.1") {
+            features.push("sse4.1");
+        }
+
+        if is_x86_feature_detected!("avx") {
+            features.push("avx");
+        }
+
+        if is_x86_feature_detected!("avx2") {
+            features.push("avx2");
+        }
+
+        if is_x86_feature_detected!("fma") {
+            features.push("fma");
+        }
+
+        if is_x86_feature_detected!("avx512f") {
+            features.push("avx512f");
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    {
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            features.push("neon");
+        }
+    }
+
+    return features;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        return std::env::temp_dir().join(format!("rmm-repro-{}-{}", std::process::id(), name));
+    }
+
+    // A file written by write for a run of args reads back as written.
+    #[test]
+    fn written_files_read_back() {
+        let path: std::path::PathBuf = temp_path("round-trip.toml");
+        let path: &str = path.to_str().unwrap();
+        let args: Vec<String> = ["repro", "16", "12", "--seed", "3", "--emit-repro", path].iter().map(|arg| arg.to_string()).collect();
+        let resolved: Table = Table::from_iter([("rows".to_string(), Value::from(16)), ("seed".to_string(), Value::from(3))]);
+
+        write(path, &args, resolved.clone(), 0x0123_4567_89ab_cdef).unwrap();
+        let repro: Repro = read(path, false).unwrap();
+
+        assert_eq!(repro.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(repro.cpu_features, cpu_features());
+        assert_eq!(repro.args, ["16", "12", "--seed", "3"]);
+        assert_eq!(repro.resolved, resolved);
+        assert_eq!(repro.checksum, 0x0123_4567_89ab_cdef);
+        assert!(mismatches(&repro).is_empty());
+        assert!(setting_mismatches(&repro, &resolved).is_empty());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn mismatches_name_what_differs() {
+        let mut repro: Repro = Repro { path: "run.toml".to_string(), version: "0.0.0".to_string(), cpu_features: vec!["sse4.1".to_string(), "warp-drive".to_string()],
+                                       args: Vec::new(), resolved: Table::from_iter([("pad".to_string(), Value::from(2))]), checksum: 0, force: false };
+
+        let found: Vec<String> = mismatches(&repro);
+        assert_eq!(found[0], format!("recorded by version 0.0.0, this is {}", env!("CARGO_PKG_VERSION")));
+        assert_eq!(found.last().unwrap(), "recorded on a CPU with warp-drive, which this one lacks");
+
+        repro.version = env!("CARGO_PKG_VERSION").to_string();
+        repro.cpu_features = Vec::new();
+        assert!(mismatches(&repro).is_empty());
+
+        let replayed: Table = Table::from_iter([("pad".to_string(), Value::from(0)), ("rows".to_string(), Value::from(4))]);
+        assert_eq!(setting_mismatches(&repro, &replayed), ["pad resolves to 0, recorded as 2", "rows resolves to 4, not recorded"]);
+    }
+
+    #[test]
+    fn malformed_files_are_refused() {
+        let path: std::path::PathBuf = temp_path("malformed.toml");
+        let path: &str = path.to_str().unwrap();
+        let complete: &str = "version = \"0.1.0\"\ncpu-features = []\nargs = [\"4\"]\n[resolved]\nrows = 4\n[result]\nchecksum = \"00ff\"\n";
+
+        assert_eq!(read(path, false).err().unwrap().split(':').next().unwrap(), format!("Failed to read {}", path));
+
+        for (text, message) in [(complete.replace("version = \"0.1.0\"\n", ""), "no version"),
+                                (complete.replace("args = [\"4\"]", "args = [4]"), "args must be a list of strings"),
+                                (complete.replace("cpu-features = []\n", ""), "cpu-features must be a list of strings"),
+                                (complete.replace("[resolved]\nrows = 4\n", ""), "no [resolved] table"),
+                                (complete.replace("00ff", "xyz"), "no valid [result] checksum")] {
+            fs::write(path, text).unwrap();
+            assert_eq!(read(path, false).err(), Some(format!("{}: {}", path, message)));
+        }
+
+        fs::write(path, "version = ").unwrap();
+        assert!(read(path, false).err().unwrap().starts_with(&format!("Invalid repro file {}: ", path)));
+
+        fs::write(path, complete).unwrap();
+        assert_eq!(read(path, true).unwrap().checksum, 0xff);
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,130 @@
+use crate::error::{check_len, DimError};
+use crate::index::flat;
+
+// Resizes a matrix with bilinear interpolation.
+//
+// Sampling is pixel-center aligned: destination element (r, c) samples the
+// source at ((r + 0.5) * rows / new_rows - 0.5, (c + 0.5) * cols / new_cols - 0.5),
+// clamped to the source bounds. This keeps the image centered under both
+// upscaling and downscaling, and a 1-element source dimension simply
+// replicates that row or column. Results are rounded to the nearest integer.
+pub fn resize_bilinear(arr: &[u8], rows: usize, cols: usize, new_rows: usize, new_cols: usize) -> Result<Vec<u8>, DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    let mut out: Vec<u8> = vec![0; new_rows * new_cols];
+
+    if rows == 0 || cols == 0 {
+        return Ok(out);
+    }
+
+    let row_scale: f32 = rows as f32 / new_rows.max(1) as f32;
+    let col_scale: f32 = cols as f32 / new_cols.max(1) as f32;
+
+    for row in 0..new_rows {
+        let (r0, r1, row_frac) = sample_position(row, row_scale, rows);
+
+        for col in 0..new_cols {
+            let (c0, c1, col_frac) = sample_position(col, col_scale, cols);
+
+            let top: f32 = arr[flat(r0, c0, cols)] as f32 * (1.0 - col_frac) + arr[flat(r0, c1, cols)] as f32 * col_frac;
+            let bottom: f32 = arr[flat(r1, c0, cols)] as f32 * (1.0 - col_frac) + arr[flat(r1, c1, cols)] as f32 * col_frac;
+
+            out[flat(row, col, new_cols)] = (top * (1.0 - row_frac) + bottom * row_frac).round() as u8;
+        }
+    }
+
+    return Ok(out);
+}
+
+// Resizes a matrix by averaging, for each destination element, the block of
+// source elements it covers. Unlike resize_bilinear this takes every source
+// element into account, so large reductions do not skip over thin features.
+// When upscaling, each destination element covers (and copies) a single
+// source element. Results are rounded to the nearest integer.
+pub fn resize_area(arr: &[u8], rows: usize, cols: usize, new_rows: usize, new_cols: usize) -> Result<Vec<u8>, DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    let mut out: Vec<u8> = vec![0; new_rows * new_cols];
+
+    if rows == 0 || cols == 0 {
+        return Ok(out);
+    }
+
+    for row in 0..new_rows {
+        let (r0, r1) = covered_range(row, rows, new_rows);
+
+        for col in 0..new_cols {
+            let (c0, c1) = covered_range(col, cols, new_cols);
+            let sum: u64 = (r0..r1).map(|r| arr[flat(r, c0, cols)..flat(r, c1, cols)].iter().map(|value| *value as u64).sum::<u64>()).sum();
+            let count: u64 = ((r1 - r0) * (c1 - c0)) as u64;
+
+            out[flat(row, col, new_cols)] = ((sum + count / 2) / count) as u8;
+        }
+    }
+
+    return Ok(out);
+}
+
+// Source indices [start, end) covered by destination index when len
+// elements are resized to new_len; never empty.
+fn covered_range(index: usize, len: usize, new_len: usize) -> (usize, usize) {
+    let start: usize = (index * len / new_len).min(len - 1);
+    let end: usize = ((index + 1) * len / new_len).clamp(start + 1, len);
+
+    return (start, end);
+}
+
+// Maps a destination index to the two neighbouring source indices and the
+// weight of the second one.
+fn sample_position(index: usize, scale: f32, len: usize) -> (usize, usize, f32) {
+    let pos: f32 = ((index as f32 + 0.5) * scale - 0.5).clamp(0.0, (len - 1) as f32);
+    let lower: usize = pos.floor() as usize;
+    let upper: usize = (lower + 1).min(len - 1);
+
+    return (lower, upper, pos - lower as f32);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::construct_randomized_matrix_seeded;
+
+    #[test]
+    fn resizing_to_the_same_size_is_the_identity() {
+        for (rows, cols) in [(1, 1), (2, 3), (17, 40)] {
+            let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, 5);
+
+            assert_eq!(resize_bilinear(&arr, rows, cols, rows, cols).unwrap(), arr);
+            assert_eq!(resize_area(&arr, rows, cols, rows, cols).unwrap(), arr);
+        }
+    }
+
+    #[test]
+    fn upscaling_a_checkerboard_matches_hand_computed_values() {
+        // Destination index i samples source position (i + 0.5) / 2 - 0.5,
+        // i.e. 0 (clamped), 0.25, 0.75 and 1 (clamped) along each axis.
+        let checkerboard: [u8; 4] = [0, 255, 255, 0];
+        let expected: Vec<u8> = vec![
+            0, 64, 191, 255,
+            64, 96, 159, 191,
+            191, 159, 96, 64,
+            255, 191, 64, 0,
+        ];
+
+        assert_eq!(resize_bilinear(&checkerboard, 2, 2, 4, 4).unwrap(), expected);
+    }
+
+    #[test]
+    fn downscaling_and_single_element_sources_work() {
+        assert_eq!(resize_bilinear(&[10, 20, 30, 40], 1, 4, 1, 2).unwrap(), vec![15, 35]);
+        assert_eq!(resize_bilinear(&[7], 1, 1, 3, 2).unwrap(), vec![7; 6]);
+        assert_eq!(resize_bilinear(&[1, 2, 3], 3, 1, 5, 1).unwrap().len(), 5);
+        assert_eq!(resize_area(&[10, 20, 30, 40], 2, 2, 1, 1).unwrap(), vec![25]);
+    }
+
+    #[test]
+    fn short_buffers_are_rejected() {
+        assert_eq!(resize_bilinear(&[1, 2, 3], 2, 2, 4, 4), Err(DimError::LengthMismatch { expected: 4, found: 3 }));
+        assert_eq!(resize_area(&[1, 2, 3], 2, 2, 1, 1), Err(DimError::LengthMismatch { expected: 4, found: 3 }));
+    }
+}
@@ -0,0 +1,284 @@
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use crate::error::{check_len, checked_elements, DimError};
+use crate::integral::FOREGROUND;
+use crate::io::invalid_data;
+
+// A run of non-zero elements: the row-major index of its first element and
+// its length. Runs never cross a row boundary.
+pub type Run = (u32, u32);
+
+// Binary RLE format (.rle), little-endian throughout:
+//
+//     offset  size  field
+//     0       4     magic "RMMR"
+//     4       1     format version, currently 1
+//     5       3     reserved, zero
+//     8       8     rows (u64)
+//     16      8     cols (u64)
+//     24      8     number of runs (u64)
+//     32            the runs, start (u32) then length (u32)
+//
+// The text format holds the same fields: a "RLE ROWSxCOLS" line followed by
+// one "START LENGTH" line per run.
+pub const RLE_MAGIC: &[u8; 4] = b"RMMR";
+pub const RLE_VERSION: u8 = 1;
+const RLE_HEADER_LEN: usize = 32;
+
+// Encoding of an RLE file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RleFormat {
+    Text,
+    Binary,
+}
+
+// A mask read back by read_rle.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoredRle {
+    pub rows: usize,
+    pub cols: usize,
+    pub runs: Vec<Run>,
+}
+
+// The runs of non-zero elements of a rows x cols mask, in row-major order,
+// each ending at the end of its row at the latest. An all-zero mask has no
+// runs and an all-ones mask one per row. Masks with more elements than a u32
+// can index are rejected.
+pub fn rle_encode(mask: &[u8], rows: usize, cols: usize) -> Result<Vec<Run>, DimError> {
+    check_len(mask.len(), rows, cols)?;
+
+    if mask.len() > u32::MAX as usize {
+        return Err(DimError::Mismatch { what: "RLE element count", expected: u32::MAX as usize, found: mask.len() });
+    }
+
+    let mut runs: Vec<Run> = Vec::new();
+
+    for (row, line) in mask.chunks_exact(cols.max(1)).take(rows).enumerate() {
+        let mut start: Option<usize> = None;
+
+        for (col, &value) in line.iter().enumerate() {
+            match (start, value != 0) {
+                (None, true) => start = Some(col),
+                (Some(first), false) => {
+                    runs.push(((row * cols + first) as u32, (col - first) as u32));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(first) = start {
+            runs.push(((row * cols + first) as u32, (cols - first) as u32));
+        }
+    }
+
+    return Ok(runs);
+}
+
+// The rows x cols mask described by runs, FOREGROUND inside them and 0
+// elsewhere, so masks of 0 and FOREGROUND such as adaptive_threshold's
+// survive a round trip unchanged. A run that leaves its row is rejected.
+pub fn rle_decode(runs: &[Run], rows: usize, cols: usize) -> Result<Vec<u8>, DimError> {
+    let mut mask: Vec<u8> = vec![0; checked_elements(rows, cols)?];
+
+    for &run in runs {
+        check_run(run, rows, cols)?;
+        mask[run.0 as usize..run.0 as usize + run.1 as usize].fill(FOREGROUND);
+    }
+
+    return Ok(mask);
+}
+
+// Checks that a run lies inside one row of a rows x cols mask.
+fn check_run((start, len): Run, rows: usize, cols: usize) -> Result<(), DimError> {
+    let (start, len): (usize, usize) = (start as usize, len as usize);
+    // A matrix without columns has room for no run at all.
+    let (row, col): (usize, usize) = start.checked_div(cols).map_or((rows, 0), |row| (row, start % cols));
+
+    if row >= rows || col + len > cols {
+        return Err(DimError::OutOfRange { rows, cols, row, col, height: 1, width: len });
+    }
+
+    return Ok(());
+}
+
+// Writes the runs of a rows x cols mask in the given format.
+pub fn write_rle(path: &Path, runs: &[Run], rows: usize, cols: usize, format: RleFormat) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(fs::File::create(path)?);
+
+    match format {
+        RleFormat::Text => {
+            writeln!(writer, "RLE {}x{}", rows, cols)?;
+
+            for (start, len) in runs {
+                writeln!(writer, "{} {}", start, len)?;
+            }
+        }
+        RleFormat::Binary => {
+            writer.write_all(RLE_MAGIC)?;
+            writer.write_all(&[RLE_VERSION, 0, 0, 0])?;
+
+            for field in [rows, cols, runs.len()] {
+                writer.write_all(&(field as u64).to_le_bytes())?;
+            }
+
+            for (start, len) in runs {
+                writer.write_all(&start.to_le_bytes())?;
+                writer.write_all(&len.to_le_bytes())?;
+            }
+        }
+    }
+
+    return writer.flush();
+}
+
+// Reads a file written by write_rle in either format, checking that every
+// run fits its row.
+pub fn read_rle(path: &Path) -> std::io::Result<StoredRle> {
+    let bytes: Vec<u8> = fs::read(path)?;
+    let stored: StoredRle = if bytes.starts_with(RLE_MAGIC) {
+        parse_binary(&bytes)?
+    } else {
+        parse_text(&String::from_utf8(bytes).map_err(|_| invalid_data("neither binary nor text RLE"))?)?
+    };
+
+    for &run in &stored.runs {
+        check_run(run, stored.rows, stored.cols).map_err(|err| invalid_data(&err.to_string()))?;
+    }
+
+    return Ok(stored);
+}
+
+fn parse_binary(bytes: &[u8]) -> std::io::Result<StoredRle> {
+    if bytes.len() < RLE_HEADER_LEN {
+        return Err(invalid_data("truncated header"));
+    }
+
+    if bytes[4] != RLE_VERSION {
+        return Err(invalid_data(&format!("unsupported format version {}", bytes[4])));
+    }
+
+    let field = |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().expect("8 bytes")) as usize;
+    let (rows, cols, count): (usize, usize, usize) = (field(8), field(16), field(24));
+    let body: &[u8] = &bytes[RLE_HEADER_LEN..];
+
+    if count.checked_mul(8) != Some(body.len()) {
+        return Err(invalid_data(&format!("{} runs but {} data bytes", count, body.len())));
+    }
+
+    let word = |chunk: &[u8]| u32::from_le_bytes(chunk.try_into().expect("4 bytes"));
+    let runs: Vec<Run> = body.chunks_exact(8).map(|chunk| (word(&chunk[..4]), word(&chunk[4..]))).collect();
+
+    return Ok(StoredRle { rows, cols, runs });
+}
+
+fn parse_text(text: &str) -> std::io::Result<StoredRle> {
+    let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+
+    let (rows, cols): (usize, usize) = lines.next()
+        .and_then(|(_, header)| header.trim().strip_prefix("RLE "))
+        .and_then(|shape| shape.trim().split_once('x'))
+        .and_then(|(rows, cols)| Some((rows.trim().parse().ok()?, cols.trim().parse().ok()?)))
+        .ok_or_else(|| invalid_data("missing \"RLE ROWSxCOLS\" header"))?;
+
+    let runs: Vec<Run> = lines.map(|(index, line)| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+
+        return match fields[..] {
+            [start, len] => start.parse().ok().zip(len.parse().ok()),
+            _ => None,
+        }.ok_or_else(|| invalid_data(&format!("invalid run on line {}", index + 1)));
+    }).collect::<std::io::Result<_>>()?;
+
+    return Ok(StoredRle { rows, cols, runs });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::construct_randomized_matrix_seeded;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        return std::env::temp_dir().join(format!("rmm-rle-{}-{}", std::process::id(), name));
+    }
+
+    // Masks of 0 and FOREGROUND with runs of every length, including ones
+    // that start or end a row.
+    fn random_mask(rows: usize, cols: usize, seed: u64) -> Vec<u8> {
+        return construct_randomized_matrix_seeded(rows, cols, seed).iter().map(|&value| if value < 128 { FOREGROUND } else { 0 }).collect();
+    }
+
+    #[test]
+    fn masks_round_trip() {
+        for (seed, (rows, cols)) in [(0, 0), (1, 1), (1, 40), (40, 1), (7, 9), (64, 64)].into_iter().enumerate() {
+            let mask: Vec<u8> = random_mask(rows, cols, seed as u64);
+            let runs: Vec<Run> = rle_encode(&mask, rows, cols).unwrap();
+
+            assert_eq!(rle_decode(&runs, rows, cols).unwrap(), mask, "{}x{}", rows, cols);
+            assert!(runs.iter().all(|&(start, len)| len > 0 && (start as usize % cols.max(1)) + len as usize <= cols));
+        }
+    }
+
+    #[test]
+    fn runs_end_at_each_row() {
+        assert_eq!(rle_encode(&[0; 6], 2, 3).unwrap(), vec![]);
+        assert_eq!(rle_encode(&[1; 6], 2, 3).unwrap(), vec![(0, 3), (3, 3)]);
+        assert_eq!(rle_encode(&[0, 5, 0, 9, 9, 1, 7, 0, 3], 3, 3).unwrap(), vec![(1, 1), (3, 3), (6, 1), (8, 1)]);
+        assert_eq!(rle_encode(&[], 4, 0).unwrap(), vec![]);
+        // Any non-zero value is foreground, decoded as FOREGROUND.
+        assert_eq!(rle_decode(&[(1, 1), (3, 3)], 2, 3).unwrap(), vec![0, FOREGROUND, 0, FOREGROUND, FOREGROUND, FOREGROUND]);
+    }
+
+    #[test]
+    fn runs_must_stay_in_their_row() {
+        assert_eq!(rle_decode(&[(2, 2)], 2, 3), Err(DimError::OutOfRange { rows: 2, cols: 3, row: 0, col: 2, height: 1, width: 2 }));
+        assert_eq!(rle_decode(&[(6, 1)], 2, 3), Err(DimError::OutOfRange { rows: 2, cols: 3, row: 2, col: 0, height: 1, width: 1 }));
+        assert!(rle_decode(&[(0, 1)], 3, 0).is_err());
+        assert_eq!(rle_decode(&[], usize::MAX, 2), Err(DimError::TooLarge { rows: usize::MAX, cols: 2 }));
+        assert_eq!(rle_encode(&[1, 2, 3], 2, 2), Err(DimError::LengthMismatch { expected: 4, found: 3 }));
+    }
+
+    #[test]
+    fn files_round_trip_in_both_formats() {
+        let mask: Vec<u8> = random_mask(13, 17, 9);
+        let runs: Vec<Run> = rle_encode(&mask, 13, 17).unwrap();
+
+        for (format, name) in [(RleFormat::Text, "mask.txt"), (RleFormat::Binary, "mask.rle")] {
+            let path: PathBuf = temp_path(name);
+            write_rle(&path, &runs, 13, 17, format).unwrap();
+            let stored: StoredRle = read_rle(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+
+            assert_eq!(stored, StoredRle { rows: 13, cols: 17, runs: runs.clone() }, "{:?}", format);
+            assert_eq!(rle_decode(&stored.runs, stored.rows, stored.cols).unwrap(), mask);
+        }
+    }
+
+    #[test]
+    fn bad_files_are_rejected() {
+        let path: PathBuf = temp_path("bad.rle");
+        let read = |bytes: &[u8]| -> String {
+            fs::write(&path, bytes).unwrap();
+            let err: std::io::Error = read_rle(&path).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+            return err.to_string();
+        };
+
+        write_rle(&path, &[(0, 2), (4, 1)], 2, 3, RleFormat::Binary).unwrap();
+        let good: Vec<u8> = fs::read(&path).unwrap();
+
+        assert!(read(&good[..20]).contains("truncated header"));
+        assert!(read(&good[..good.len() - 1]).contains("2 runs but 15 data bytes"));
+        let mut version: Vec<u8> = good.clone();
+        version[4] = 9;
+        assert!(read(&version).contains("unsupported format version 9"));
+        assert!(read(b"RLE 2x3\n0 2\n2 2\n").contains("1x2 region at (0, 2) does not fit inside a 2x3 matrix"));
+        assert!(read(b"RLE 2x3\n0 two\n").contains("invalid run on line 2"));
+        assert!(read(b"2x3\n0 2\n").contains("missing \"RLE ROWSxCOLS\" header"));
+        assert!(read(&[0xff, 0xfe]).contains("neither binary nor text RLE"));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
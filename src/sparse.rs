@@ -0,0 +1,170 @@
+use std::ops::Neg;
+use crate::error::{check_len, DimError};
+use crate::index::flat;
+use crate::matmul::Scalar;
+
+// A sparse matrix in compressed sparse row form. The non-zeros of row r are
+// values[row_ptr[r]..row_ptr[r + 1]], in increasing column order, with their
+// columns in the same range of col_idx.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CsrMatrix<T> {
+    pub rows: usize,
+    pub cols: usize,
+    pub row_ptr: Vec<usize>,
+    pub col_idx: Vec<usize>,
+    pub values: Vec<T>,
+}
+
+impl<T: Scalar + PartialOrd + Neg<Output = T>> CsrMatrix<T> {
+    // Converts a dense row-major matrix, keeping the entries whose absolute
+    // value is greater than threshold. A threshold of zero keeps every
+    // non-zero.
+    pub fn from_dense(data: &[T], rows: usize, cols: usize, threshold: T) -> Result<CsrMatrix<T>, DimError> {
+        check_len(data.len(), rows, cols)?;
+
+        let mut row_ptr: Vec<usize> = Vec::with_capacity(rows + 1);
+        let mut col_idx: Vec<usize> = Vec::new();
+        let mut values: Vec<T> = Vec::new();
+
+        row_ptr.push(0);
+
+        for row in 0..rows {
+            for col in 0..cols {
+                let value: T = data[flat(row, col, cols)];
+
+                if value > threshold || value < -threshold {
+                    col_idx.push(col);
+                    values.push(value);
+                }
+            }
+
+            row_ptr.push(values.len());
+        }
+
+        return Ok(CsrMatrix { rows, cols, row_ptr, col_idx, values });
+    }
+}
+
+impl<T: Scalar> CsrMatrix<T> {
+    // Number of stored entries.
+    pub fn nnz(&self) -> usize {
+        return self.values.len();
+    }
+
+    pub fn to_dense(&self) -> Vec<T> {
+        let mut dense: Vec<T> = vec![T::default(); self.rows * self.cols];
+
+        for row in 0..self.rows {
+            for i in self.row_ptr[row]..self.row_ptr[row + 1] {
+                dense[row * self.cols + self.col_idx[i]] = self.values[i];
+            }
+        }
+
+        return dense;
+    }
+
+    // Sparse matrix times dense vector.
+    pub fn spmv(&self, x: &[T]) -> Result<Vec<T>, DimError> {
+        if x.len() != self.cols {
+            return Err(DimError::Mismatch { what: "vector length", expected: self.cols, found: x.len() });
+        }
+
+        let mut y: Vec<T> = vec![T::default(); self.rows];
+
+        for (row, out) in y.iter_mut().enumerate() {
+            for i in self.row_ptr[row]..self.row_ptr[row + 1] {
+                *out += self.values[i] * x[self.col_idx[i]];
+            }
+        }
+
+        return Ok(y);
+    }
+
+    // Sparse matrix times a dense b_rows x b_cols matrix, giving a dense
+    // rows x b_cols matrix. Each stored entry scales one row of b.
+    pub fn spmm(&self, b: &[T], b_rows: usize, b_cols: usize) -> Result<Vec<T>, DimError> {
+        check_len(b.len(), b_rows, b_cols)?;
+
+        if b_rows != self.cols {
+            return Err(DimError::Mismatch { what: "inner dimension", expected: self.cols, found: b_rows });
+        }
+
+        let mut c: Vec<T> = vec![T::default(); self.rows * b_cols];
+
+        for row in 0..self.rows {
+            let c_row: &mut [T] = &mut c[row * b_cols..(row + 1) * b_cols];
+
+            for i in self.row_ptr[row]..self.row_ptr[row + 1] {
+                let value: T = self.values[i];
+                let b_row: &[T] = &b[self.col_idx[i] * b_cols..(self.col_idx[i] + 1) * b_cols];
+
+                for (out, b_value) in c_row.iter_mut().zip(b_row) {
+                    *out += value * *b_value;
+                }
+            }
+        }
+
+        return Ok(c);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matmul::{matmul, matvec};
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha12Rng;
+
+    // A rows x cols matrix with about density of its entries non-zero.
+    fn random_sparse(rows: usize, cols: usize, density: f64, seed: u64) -> Vec<i64> {
+        let mut rng: ChaCha12Rng = ChaCha12Rng::seed_from_u64(seed);
+
+        return (0..rows * cols).map(|_| if rng.gen_bool(density) { rng.gen_range(-9..=9) } else { 0 }).collect();
+    }
+
+    #[test]
+    fn from_dense_round_trips() {
+        for (seed, (rows, cols, density)) in [(0, 0, 0.5), (1, 1, 1.0), (4, 7, 0.0), (9, 5, 0.05), (30, 40, 0.2), (12, 12, 1.0)].into_iter().enumerate() {
+            let dense: Vec<i64> = random_sparse(rows, cols, density, seed as u64);
+            let csr: CsrMatrix<i64> = CsrMatrix::from_dense(&dense, rows, cols, 0).unwrap();
+
+            assert_eq!(csr.nnz(), dense.iter().filter(|&&value| value != 0).count());
+            assert_eq!(csr.row_ptr.len(), rows + 1);
+            assert_eq!(csr.to_dense(), dense);
+        }
+    }
+
+    #[test]
+    fn from_dense_drops_entries_within_the_threshold() {
+        let csr: CsrMatrix<i64> = CsrMatrix::from_dense(&[1, -2, 3, 0, -3, 2], 2, 3, 2).unwrap();
+
+        assert_eq!(csr.row_ptr, vec![0, 1, 2]);
+        assert_eq!(csr.col_idx, vec![2, 1]);
+        assert_eq!(csr.values, vec![3, -3]);
+        assert_eq!(csr.to_dense(), vec![0, 0, 3, 0, -3, 0]);
+        assert!(CsrMatrix::from_dense(&[1i64; 5], 2, 3, 0).is_err());
+    }
+
+    #[test]
+    fn spmv_and_spmm_match_the_dense_products() {
+        for seed in 0..8 {
+            let (rows, cols, b_cols): (usize, usize, usize) = (13 + seed as usize, 29, 6);
+            let dense: Vec<i64> = random_sparse(rows, cols, 0.1, seed);
+            let x: Vec<i64> = random_sparse(cols, 1, 1.0, seed + 100);
+            let b: Vec<i64> = random_sparse(cols, b_cols, 0.7, seed + 200);
+            let csr: CsrMatrix<i64> = CsrMatrix::from_dense(&dense, rows, cols, 0).unwrap();
+
+            assert_eq!(csr.spmv(&x).unwrap(), matvec(&dense, rows, cols, &x).unwrap());
+            assert_eq!(csr.spmm(&b, cols, b_cols).unwrap(), matmul(&dense, rows, cols, &b, cols, b_cols).unwrap());
+        }
+    }
+
+    #[test]
+    fn products_check_their_operands() {
+        let csr: CsrMatrix<i64> = CsrMatrix::from_dense(&[1, 0, 0, 1], 2, 2, 0).unwrap();
+
+        assert!(csr.spmv(&[1, 2, 3]).is_err());
+        assert!(csr.spmm(&[1, 2, 3], 3, 1).is_err());
+        assert!(csr.spmm(&[1, 2, 3], 1, 2).is_err());
+    }
+}
@@ -0,0 +1,328 @@
+use std::error::Error;
+use std::fmt;
+use crate::conv::sobel;
+use crate::convert::normalize_u8;
+use crate::error::{check_len, DimError};
+use crate::filters::gaussian_blur;
+use crate::gradient::magnitude;
+use crate::integral::FOREGROUND;
+use crate::kernels::{compute_dx, compute_dy};
+use crate::ops::{crop_dx_padding, crop_dy_padding};
+use crate::postprocess::{Identity, PostProcess};
+
+// One operation of a pipeline such as "blur:1.5 | sobel | magnitude |
+// threshold:50 | normalize". Every stage keeps the rows x cols shape of the
+// input; only the element type changes from stage to stage.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+    // gaussian_blur with this sigma: u8 to u8.
+    Blur(f32),
+    // The 3x3 Sobel derivatives: u8 to gradients.
+    Sobel,
+    // Dx and Dy of the built-in [-1, 0, 1] kernels, cropped to rows x cols:
+    // u8 to gradients.
+    Gradient,
+    // Euclidean magnitude of the gradients: gradients to f32.
+    Magnitude,
+    // FOREGROUND where a value is greater than this and 0 elsewhere: u8 or
+    // f32 to u8.
+    Threshold(f64),
+    // normalize_u8: u8 or f32 to u8.
+    Normalize,
+}
+
+// Element type flowing between two stages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    U8,
+    Gradients,
+    F32,
+}
+
+// The result of a stage, rows x cols like the pipeline input.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Image {
+    U8(Vec<u8>),
+    // Horizontal and vertical derivatives.
+    Gradients(Vec<i16>, Vec<i16>),
+    F32(Vec<f32>),
+}
+
+// Why a pipeline specification was rejected, located by the stage (counted
+// from 1) and the column of the specification (counted from 1) where the
+// problem starts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PipelineError {
+    pub stage: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "stage {} at column {}: {}", self.stage, self.column, self.message);
+    }
+}
+
+impl Error for PipelineError {}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}", match self {
+            Kind::U8 => "u8",
+            Kind::Gradients => "gradients",
+            Kind::F32 => "f32",
+        });
+    }
+}
+
+// Stages print the way they are written in a specification, e.g. blur:1.5.
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Stage::Blur(sigma) => write!(f, "blur:{}", sigma),
+            Stage::Threshold(level) => write!(f, "threshold:{}", level),
+            _ => write!(f, "{}", self.name()),
+        };
+    }
+}
+
+impl Image {
+    pub fn kind(&self) -> Kind {
+        return match self {
+            Image::U8(_) => Kind::U8,
+            Image::Gradients(..) => Kind::Gradients,
+            Image::F32(_) => Kind::F32,
+        };
+    }
+}
+
+impl Stage {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Stage::Blur(_) => "blur",
+            Stage::Sobel => "sobel",
+            Stage::Gradient => "gradient",
+            Stage::Magnitude => "magnitude",
+            Stage::Threshold(_) => "threshold",
+            Stage::Normalize => "normalize",
+        };
+    }
+
+    // What the stage produces from input, or None if it does not accept it.
+    pub fn output(&self, input: Kind) -> Option<Kind> {
+        return match (self, input) {
+            (Stage::Blur(_), Kind::U8) => Some(Kind::U8),
+            (Stage::Sobel | Stage::Gradient, Kind::U8) => Some(Kind::Gradients),
+            (Stage::Magnitude, Kind::Gradients) => Some(Kind::F32),
+            (Stage::Threshold(_) | Stage::Normalize, Kind::U8 | Kind::F32) => Some(Kind::U8),
+            _ => None,
+        };
+    }
+
+    // Applies the stage to a rows x cols image of a kind it accepts.
+    fn apply(&self, image: Image, rows: usize, cols: usize) -> Result<Image, DimError> {
+        return Ok(match (self, image) {
+            (Stage::Blur(sigma), Image::U8(arr)) => Image::U8(gaussian_blur(&arr, rows, cols, *sigma)),
+            (Stage::Sobel, Image::U8(arr)) => {
+                let (gx, gy) = sobel(&arr, rows, cols)?;
+                Image::Gradients(gx, gy)
+            }
+            (Stage::Gradient, Image::U8(arr)) => {
+                let dx: Vec<i16> = crop_dx_padding(&compute_dx(&arr, rows, cols).data, rows, cols)?;
+                let dy: Vec<i16> = crop_dy_padding(&compute_dy(&arr, rows, cols).data, rows, cols)?;
+                Image::Gradients(dx, dy)
+            }
+            (Stage::Magnitude, Image::Gradients(dx, dy)) => Image::F32(magnitude(&dx, &dy, rows, cols)?),
+            (Stage::Threshold(level), Image::U8(arr)) => Image::U8(threshold(&arr, *level)),
+            (Stage::Threshold(level), Image::F32(arr)) => Image::U8(threshold(&arr, *level)),
+            (Stage::Normalize, Image::U8(arr)) => Image::U8(normalize_u8(&arr)),
+            (Stage::Normalize, Image::F32(arr)) => Image::U8(normalize_u8(&arr)),
+            (stage, image) => panic!("{} cannot take {} input; pipelines from parse_pipeline are checked", stage, image.kind()),
+        });
+    }
+}
+
+// Parses a pipeline specification: stages separated by |, each a name
+// optionally followed by :PARAMETER, e.g. "blur:1.5 | sobel | magnitude |
+// threshold:50 | normalize". The first stage receives the u8 input, and
+// every stage must accept the kind of image the one before it produces.
+pub fn parse_pipeline(spec: &str) -> Result<Vec<Stage>, PipelineError> {
+    let mut stages: Vec<Stage> = Vec::new();
+    let mut kind: Kind = Kind::U8;
+    let mut offset: usize = 0;
+
+    for (index, token) in spec.split('|').enumerate() {
+        let start: usize = offset + (token.len() - token.trim_start().len());
+        let fail = |at: usize, message: String| PipelineError { stage: index + 1, column: spec[..at].chars().count() + 1, message };
+        let text: &str = token.trim();
+        offset += token.len() + 1;
+
+        let (name, parameter) = match text.split_once(':') {
+            Some((name, parameter)) => {
+                let at: usize = start + name.len() + 1 + (parameter.len() - parameter.trim_start().len());
+                (name.trim_end(), Some((parameter.trim(), at)))
+            }
+            None => (text, None),
+        };
+
+        let stage: Stage = match (name, parameter) {
+            ("", _) => return Err(fail(start, "empty stage".to_string())),
+            ("blur", Some((sigma, at))) => match sigma.parse::<f32>() {
+                Ok(sigma) if sigma > 0.0 && sigma.is_finite() => Stage::Blur(sigma),
+                _ => return Err(fail(at, format!("invalid blur sigma \"{}\", expected a positive number", sigma))),
+            },
+            ("threshold", Some((level, at))) => match level.parse::<f64>() {
+                Ok(level) if level.is_finite() => Stage::Threshold(level),
+                _ => return Err(fail(at, format!("invalid threshold \"{}\", expected a number", level))),
+            },
+            ("blur", None) => return Err(fail(start, "blur needs a sigma, e.g. blur:1.5".to_string())),
+            ("threshold", None) => return Err(fail(start, "threshold needs a level, e.g. threshold:50".to_string())),
+            ("sobel" | "gradient" | "magnitude" | "normalize", Some((_, at))) => {
+                return Err(fail(at, format!("{} takes no parameter", name)));
+            }
+            ("sobel", None) => Stage::Sobel,
+            ("gradient", None) => Stage::Gradient,
+            ("magnitude", None) => Stage::Magnitude,
+            ("normalize", None) => Stage::Normalize,
+            _ => return Err(fail(start, format!("unknown stage \"{}\", expected blur, sobel, gradient, magnitude, threshold or normalize", name))),
+        };
+
+        kind = stage.output(kind).ok_or_else(|| fail(start, format!("{} cannot take {} input", stage.name(), kind)))?;
+        stages.push(stage);
+    }
+
+    return Ok(stages);
+}
+
+// Runs stages, as returned by parse_pipeline, on a rows x cols u8 matrix and
+// returns the last stage's result. An empty list returns the input.
+pub fn run_stages(stages: &[Stage], arr: &[u8], rows: usize, cols: usize) -> Result<Image, DimError> {
+    return run_stages_with(stages, arr, rows, cols, &Identity);
+}
+
+// run_stages with hook run on both derivatives right after every stage that
+// produces gradients, so later stages and the caller see the processed
+// values.
+pub fn run_stages_with(stages: &[Stage], arr: &[u8], rows: usize, cols: usize, hook: &dyn PostProcess) -> Result<Image, DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    let mut image: Image = Image::U8(arr.to_vec());
+
+    for stage in stages {
+        image = stage.apply(image, rows, cols)?;
+
+        if let Image::Gradients(dx, dy) = &mut image {
+            hook.apply(dx, rows, cols);
+            hook.apply(dy, rows, cols);
+        }
+    }
+
+    return Ok(image);
+}
+
+fn threshold<T: Copy + Into<f64>>(data: &[T], level: f64) -> Vec<u8> {
+    return data.iter().map(|&value| if value.into() > level { FOREGROUND } else { 0 }).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::construct_randomized_matrix_seeded;
+
+    const SHAPES: [(usize, usize); 4] = [(1, 1), (3, 7), (16, 16), (33, 20)];
+
+    fn run(spec: &str, arr: &[u8], rows: usize, cols: usize) -> Image {
+        return run_stages(&parse_pipeline(spec).unwrap(), arr, rows, cols).unwrap();
+    }
+
+    #[test]
+    fn two_stages_match_the_manual_calls() {
+        for (rows, cols) in SHAPES {
+            let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, 21);
+
+            let blurred: Vec<u8> = gaussian_blur(&arr, rows, cols, 1.5);
+            assert_eq!(run("blur:1.5 | normalize", &arr, rows, cols), Image::U8(normalize_u8(&blurred)), "{}x{}", rows, cols);
+
+            let dx: Vec<i16> = crop_dx_padding(&compute_dx(&arr, rows, cols).data, rows, cols).unwrap();
+            let dy: Vec<i16> = crop_dy_padding(&compute_dy(&arr, rows, cols).data, rows, cols).unwrap();
+            assert_eq!(run("gradient|magnitude", &arr, rows, cols), Image::F32(magnitude(&dx, &dy, rows, cols).unwrap()), "{}x{}", rows, cols);
+        }
+    }
+
+    #[test]
+    fn five_stages_match_the_manual_calls() {
+        for (rows, cols) in SHAPES {
+            let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, 22);
+
+            let blurred: Vec<u8> = gaussian_blur(&arr, rows, cols, 1.5);
+            let (gx, gy) = sobel(&blurred, rows, cols).unwrap();
+            let strength: Vec<f32> = magnitude(&gx, &gy, rows, cols).unwrap();
+            let edges: Vec<u8> = strength.iter().map(|&value| if value as f64 > 50.0 { FOREGROUND } else { 0 }).collect();
+
+            assert_eq!(run("blur:1.5 | sobel | magnitude | threshold:50 | normalize", &arr, rows, cols), Image::U8(normalize_u8(&edges)),
+                       "{}x{}", rows, cols);
+        }
+    }
+
+    #[test]
+    fn no_stages_return_the_input() {
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(4, 5, 1);
+
+        assert_eq!(run_stages(&[], &arr, 4, 5).unwrap(), Image::U8(arr.clone()));
+        assert_eq!(run_stages(&[Stage::Normalize], &arr, 5, 5), Err(DimError::LengthMismatch { expected: 25, found: 20 }));
+    }
+
+    #[test]
+    fn stages_parse_with_their_parameters() {
+        assert_eq!(parse_pipeline(" blur : 2 |sobel|magnitude| threshold:-1.5|normalize ").unwrap(),
+                   vec![Stage::Blur(2.0), Stage::Sobel, Stage::Magnitude, Stage::Threshold(-1.5), Stage::Normalize]);
+        assert_eq!(parse_pipeline("threshold:0").unwrap(), vec![Stage::Threshold(0.0)]);
+
+        let stages: Vec<Stage> = parse_pipeline("blur:1.5 | gradient | magnitude | threshold:50 | normalize").unwrap();
+        assert_eq!(stages.iter().map(|stage| stage.to_string()).collect::<Vec<String>>().join(" | "),
+                   "blur:1.5 | gradient | magnitude | threshold:50 | normalize");
+    }
+
+    #[test]
+    fn errors_give_the_stage_and_column() {
+        let error = |spec: &str| -> (usize, usize, String) {
+            let err: PipelineError = parse_pipeline(spec).unwrap_err();
+            return (err.stage, err.column, err.message);
+        };
+
+        assert_eq!(error("blur:1.5 | edges"), (2, 12, "unknown stage \"edges\", expected blur, sobel, gradient, magnitude, threshold or normalize".to_string()));
+        assert_eq!(error("blur:x"), (1, 6, "invalid blur sigma \"x\", expected a positive number".to_string()));
+        assert_eq!(error("blur:0"), (1, 6, "invalid blur sigma \"0\", expected a positive number".to_string()));
+        assert_eq!(error("sobel | threshold"), (2, 9, "threshold needs a level, e.g. threshold:50".to_string()));
+        assert_eq!(error("sobel:3"), (1, 7, "sobel takes no parameter".to_string()));
+        assert_eq!(error("sobel || magnitude"), (2, 8, "empty stage".to_string()));
+        assert_eq!(error("magnitude"), (1, 1, "magnitude cannot take u8 input".to_string()));
+        assert_eq!(error("sobel | normalize"), (2, 9, "normalize cannot take gradients input".to_string()));
+        assert_eq!(PipelineError { stage: 2, column: 9, message: "empty stage".to_string() }.to_string(), "stage 2 at column 9: empty stage");
+    }
+
+    #[test]
+    fn the_hook_runs_on_the_gradients_before_later_stages() {
+        let negate: crate::postprocess::Lut256 = crate::postprocess::Lut256::from_fn(|value| -value);
+        let zero: crate::postprocess::Lut256 = crate::postprocess::Lut256::from_fn(|_| 0);
+
+        for (rows, cols) in SHAPES {
+            let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, 23);
+            let pipeline: Vec<Stage> = parse_pipeline("gradient | magnitude").unwrap();
+            let dx: Vec<i16> = crop_dx_padding(&compute_dx(&arr, rows, cols).data, rows, cols).unwrap();
+            let dy: Vec<i16> = crop_dy_padding(&compute_dy(&arr, rows, cols).data, rows, cols).unwrap();
+            let negated = |data: &[i16]| data.iter().map(|value| -value).collect::<Vec<i16>>();
+
+            assert_eq!(run_stages_with(&pipeline[..1], &arr, rows, cols, &negate).unwrap(), Image::Gradients(negated(&dx), negated(&dy)),
+                       "{}x{}", rows, cols);
+            // The magnitude stage sees the processed gradients.
+            assert_eq!(run_stages_with(&pipeline, &arr, rows, cols, &zero).unwrap(), Image::F32(vec![0.0; rows * cols]), "{}x{}", rows, cols);
+            assert_eq!(run_stages_with(&pipeline, &arr, rows, cols, &negate).unwrap(), run("gradient | magnitude", &arr, rows, cols));
+        }
+
+        // Stages without gradients are left alone.
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(5, 6, 24);
+        assert_eq!(run_stages_with(&parse_pipeline("blur:1").unwrap(), &arr, 5, 6, &zero).unwrap(), run("blur:1", &arr, 5, 6));
+    }
+}
@@ -0,0 +1,808 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+#[cfg(feature = "parallel")]
+use std::thread;
+use crate::arith::Widen;
+use crate::error::{check_len, DimError};
+use crate::index::{flat, row_range};
+
+// Smallest element, or the default value for an empty matrix. Any element
+// is neutral for min, so the first one seeds the reduction.
+pub fn get_min<T: Ord + Copy + Default + Sync + Send>(matrix: &[T]) -> T {
+    return match matrix.first() {
+        Some(&first) => reduce(matrix, 1, first, T::min, T::min),
+        None => T::default(),
+    };
+}
+
+// Largest element, or the default value for an empty matrix.
+pub fn get_max<T: Ord + Copy + Default + Sync + Send>(matrix: &[T]) -> T {
+    return match matrix.first() {
+        Some(&first) => reduce(matrix, 1, first, T::max, T::max),
+        None => T::default(),
+    };
+}
+
+// Index of the smallest element, of the earliest one in row-major order if
+// several are equal, or None for an empty matrix. Up to threads threads each
+// take whole SUM_BLOCK blocks, and the block candidates are merged by the
+// same rule (a later block only wins with a strictly smaller value), so the
+// index is the one a serial scan finds whatever threads is.
+pub fn argmin<T: Ord + Copy + Sync + Send>(matrix: &[T], threads: usize) -> Option<usize> {
+    return arg_best(matrix, threads, |candidate, best| candidate < best);
+}
+
+// Index of the largest element, ties going to the earliest as in argmin.
+pub fn argmax<T: Ord + Copy + Sync + Send>(matrix: &[T], threads: usize) -> Option<usize> {
+    return arg_best(matrix, threads, |candidate, best| candidate > best);
+}
+
+// The first element no later one is better than, for argmin and argmax.
+fn arg_best<T: Copy + Sync + Send>(matrix: &[T], threads: usize, better: impl Fn(T, T) -> bool + Sync) -> Option<usize> {
+    let block = |block: &[T]| -> (T, usize) {
+        let mut best: usize = 0;
+
+        for index in 1..block.len() {
+            if better(block[index], block[best]) {
+                best = index;
+            }
+        }
+
+        return (block[best], best);
+    };
+
+    return map_chunks(matrix, SUM_BLOCK, threads, &block).into_iter().enumerate()
+        .map(|(number, (value, index))| (value, number * SUM_BLOCK + index))
+        .reduce(|earlier, later| if better(later.0, earlier.0) { later } else { earlier })
+        .map(|(_, index)| index);
+}
+
+// Sum of all elements, accumulated in i64 so that even 2^31 elements at the
+// i16 extremes cannot overflow. Fixed-point values are summed in Q16.16
+// units; see rmm::fixed.
+pub fn get_sum<T: Widen + Sync>(matrix: &[T]) -> i64 {
+    return reduce(matrix, 1, 0, |sum: i64, value| sum + value.widen(), |a, b| a + b);
+}
+
+// Sum of every row of a rows x cols matrix, accumulated like get_sum, with
+// the rows shared out among up to threads threads.
+pub fn row_sums<T: Widen + Sync>(matrix: &[T], rows: usize, cols: usize, threads: usize) -> Result<Vec<i64>, DimError> {
+    return reduce_rows(matrix, rows, cols, threads, 0, |sum: i64, value| sum + value.widen(), |a, b| a + b);
+}
+
+// Reduces data to one value: every SUM_BLOCK consecutive elements are folded
+// into a copy of init in index order, and the block results are then
+// combined with merge in index order too, merge(earlier, later). fold and
+// merge must agree, i.e. merging two partial results must give what folding
+// the later block onto the earlier result would, and init must be neutral
+// for merge. Threads (up to threads of them) only share out whole blocks,
+// so the result is the same whatever threads is, even for reductions that
+// are not associative such as float sums. An empty slice gives init.
+// Without the parallel feature this runs on the calling thread.
+pub fn reduce<T, A>(data: &[T], threads: usize, init: A, fold: impl Fn(A, T) -> A + Sync, merge: impl Fn(A, A) -> A + Sync) -> A
+    where T: Copy + Sync, A: Clone + Send + Sync {
+    let block = |block: &[T]| block.iter().fold(init.clone(), |acc, &value| fold(acc, value));
+
+    return map_chunks(data, SUM_BLOCK, threads, &block).into_iter().reduce(merge).unwrap_or(init);
+}
+
+// Reduces every row of a rows x cols matrix like reduce does the whole of
+// it: each row is folded in SUM_BLOCK blocks that are merged in index order,
+// and threads share out whole rows, so the results are independent of
+// threads. A matrix without columns gives init for every row.
+pub fn reduce_rows<T, A>(data: &[T], rows: usize, cols: usize, threads: usize, init: A, fold: impl Fn(A, T) -> A + Sync,
+                         merge: impl Fn(A, A) -> A + Sync) -> Result<Vec<A>, DimError> where T: Copy + Sync, A: Clone + Send + Sync {
+    check_len(data.len(), rows, cols)?;
+
+    if cols == 0 {
+        return Ok(vec![init; rows]);
+    }
+
+    let row = |line: &[T]| line.chunks(SUM_BLOCK).map(|block| block.iter().fold(init.clone(), |acc, &value| fold(acc, value)))
+        .reduce(&merge).unwrap_or_else(|| init.clone());
+
+    return Ok(map_chunks(data, cols, threads, &row));
+}
+
+pub fn count_nonzero<T: Copy + Default + PartialEq>(matrix: &[T]) -> usize {
+    return matrix.iter().filter(|value| **value != T::default()).count();
+}
+
+// The values at percentiles ps (0 to 100, clamped) of matrix, in the order
+// of ps. Percentile p is the element at index round(p / 100 * (n - 1)) of
+// the sorted data, so 0 gives the minimum and 100 the maximum. Computed
+// exactly with a counting pass over the value range instead of a sort. An
+// empty matrix gives 0 for every percentile, like get_min and get_max.
+pub fn percentiles(matrix: &[i16], ps: &[f64]) -> Vec<i16> {
+    if matrix.is_empty() {
+        return vec![0; ps.len()];
+    }
+
+    let min: i16 = get_min(matrix);
+    let mut counts: Vec<usize> = vec![0; (get_max(matrix) as i32 - min as i32) as usize + 1];
+
+    for value in matrix {
+        counts[(*value as i32 - min as i32) as usize] += 1;
+    }
+
+    return ps.iter().map(|p| {
+        let rank: usize = (p.clamp(0.0, 100.0) / 100.0 * (matrix.len() - 1) as f64).round() as usize;
+        let mut seen: usize = 0;
+
+        // The bucket holding the element at index rank of the sorted data.
+        for (offset, count) in counts.iter().enumerate() {
+            seen += count;
+
+            if seen > rank {
+                return (min as i32 + offset as i32) as i16;
+            }
+        }
+
+        unreachable!("rank {} is below the element count {}", rank, matrix.len());
+    }).collect();
+}
+
+// The k elements of largest absolute value as (value, row, col), strongest
+// first. Ties go to the element that comes first in row-major order, so the
+// result is deterministic. Keeps a heap of at most k candidates rather than
+// sorting the whole matrix; k larger than the matrix returns every element.
+pub fn top_k_abs(matrix: &[i16], rows: usize, cols: usize, k: usize) -> Result<Vec<(i16, usize, usize)>, DimError> {
+    return top_k_abs_par(matrix, rows, cols, k, 1);
+}
+
+// top_k_abs with up to threads threads, each keeping the k strongest of
+// whole SUM_BLOCK blocks. The block candidates are merged with the same
+// tie rule, so the result is exactly that of top_k_abs.
+pub fn top_k_abs_par(matrix: &[i16], rows: usize, cols: usize, k: usize, threads: usize) -> Result<Vec<(i16, usize, usize)>, DimError> {
+    return top_k_by(matrix, rows, cols, k, threads, |value| value.unsigned_abs());
+}
+
+// The k largest elements of an f32 matrix as (value, row, col), largest
+// first, e.g. the strongest corner responses. Ties are broken as in
+// top_k_abs; values are compared with total_cmp, so a NaN ranks above every
+// number.
+pub fn top_k_f32(matrix: &[f32], rows: usize, cols: usize, k: usize) -> Result<Vec<(f32, usize, usize)>, DimError> {
+    return top_k_by(matrix, rows, cols, k, 1, TotalF32);
+}
+
+// f32 ordered by total_cmp, so it can be kept in a heap.
+#[derive(Clone, Copy, PartialEq)]
+struct TotalF32(f32);
+
+impl Eq for TotalF32 {}
+
+impl PartialOrd for TotalF32 {
+    fn partial_cmp(&self, other: &TotalF32) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+impl Ord for TotalF32 {
+    fn cmp(&self, other: &TotalF32) -> Ordering {
+        return self.0.total_cmp(&other.0);
+    }
+}
+
+// The k elements with the largest key, strongest first, for top_k_abs and
+// top_k_f32. Every SUM_BLOCK block yields its own k strongest, which include
+// all of the overall k strongest that lie in it; sorting the candidates by
+// key and then index picks those.
+fn top_k_by<T: Copy + Sync, K: Ord + Send>(matrix: &[T], rows: usize, cols: usize, k: usize, threads: usize,
+                                           key: impl Fn(T) -> K + Sync) -> Result<Vec<(T, usize, usize)>, DimError> {
+    check_len(matrix.len(), rows, cols)?;
+
+    let block = |block: &[T]| -> Vec<(Reverse<K>, usize)> {
+        // The top of the heap is the weakest candidate: smallest key, and
+        // among equal keys the latest index.
+        let mut heap: BinaryHeap<(Reverse<K>, usize)> = BinaryHeap::with_capacity(k.min(block.len()) + 1);
+
+        for (index, value) in block.iter().enumerate() {
+            let strength: K = key(*value);
+
+            if heap.len() < k {
+                heap.push((Reverse(strength), index));
+            } else if heap.peek().is_some_and(|(Reverse(weakest), _)| strength > *weakest) {
+                heap.pop();
+                heap.push((Reverse(strength), index));
+            }
+        }
+
+        return heap.into_vec();
+    };
+
+    let mut candidates: Vec<(Reverse<K>, usize)> = map_chunks(matrix, SUM_BLOCK, threads, &block).into_iter().enumerate()
+        .flat_map(|(number, strongest)| strongest.into_iter().map(move |(strength, index)| (strength, number * SUM_BLOCK + index)))
+        .collect();
+    candidates.sort_unstable();
+    candidates.truncate(k);
+
+    return Ok(candidates.into_iter().map(|(_, index)| (matrix[index], index / cols, index % cols)).collect());
+}
+
+// Number of sign changes along each row of a rows x cols matrix.
+//
+// Zeros carry no sign and are skipped, so a crossing is counted between two
+// non-zero values of opposite sign however many zeros separate them:
+// +, 0, - counts once and +, 0, + not at all.
+pub fn count_zero_crossings_rows(matrix: &[i16], rows: usize, cols: usize) -> Result<Vec<usize>, DimError> {
+    check_len(matrix.len(), rows, cols)?;
+
+    return Ok((0..rows).map(|row| count_crossings(matrix[row_range(row, cols)].iter().copied())).collect());
+}
+
+// Number of sign changes down each column, with zeros handled as in
+// count_zero_crossings_rows.
+pub fn count_zero_crossings_cols(matrix: &[i16], rows: usize, cols: usize) -> Result<Vec<usize>, DimError> {
+    check_len(matrix.len(), rows, cols)?;
+
+    return Ok((0..cols).map(|col| count_crossings((0..rows).map(|row| matrix[flat(row, col, cols)]))).collect());
+}
+
+fn count_crossings(values: impl Iterator<Item = i16>) -> usize {
+    let mut crossings: usize = 0;
+    let mut previous: i16 = 0;
+
+    for sign in values.map(i16::signum).filter(|sign| *sign != 0) {
+        if previous != 0 && sign != previous {
+            crossings += 1;
+        }
+        previous = sign;
+    }
+
+    return crossings;
+}
+
+// Starting value of checksum: the FNV-1a offset basis.
+pub const CHECKSUM_BASIS: u64 = 0xcbf29ce484222325;
+
+// 64-bit FNV-1a hash of the little-endian bytes of data, to spot runs whose
+// results changed. Starts from hash, CHECKSUM_BASIS for a fresh checksum or
+// the previous result to checksum several matrices together.
+pub fn checksum(hash: u64, data: &[i16]) -> u64 {
+    return data.iter().fold(hash, |hash, value| checksum_bytes(hash, &value.to_le_bytes()));
+}
+
+// checksum of raw bytes, e.g. a u8 matrix.
+pub fn checksum_bytes(hash: u64, bytes: &[u8]) -> u64 {
+    let mut hash: u64 = hash;
+
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    return hash;
+}
+
+// Fingerprint of a rows x cols input matrix: the checksum of its dimensions
+// (as little-endian u64s) followed by its elements. Two runs with the same
+// fingerprint ran on the same input, however it was produced. Generation
+// parameters are not hashed, as a matrix saved to disk no longer carries
+// them; equal seeds and dimensions always give equal fingerprints anyway.
+pub fn fingerprint(arr: &[u8], rows: usize, cols: usize) -> Result<u64, DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    let dims: u64 = checksum_bytes(checksum_bytes(CHECKSUM_BASIS, &(rows as u64).to_le_bytes()), &(cols as u64).to_le_bytes());
+
+    return Ok(checksum_bytes(dims, arr));
+}
+
+// Whether a and b have the same length and every pair of elements agrees to
+// within rel_tol relative to the larger magnitude. Values close to zero are
+// compared with rel_tol as an absolute tolerance instead.
+pub fn approx_eq(a: &[f32], b: &[f32], rel_tol: f32) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    return a.iter().zip(b).all(|(x, y)| {
+        let scale: f32 = x.abs().max(y.abs()).max(1.0);
+        (x - y).abs() <= rel_tol * scale
+    });
+}
+
+// How far one matrix is from another of the same length, e.g. a float or GPU
+// result from the integer reference.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ErrorMetrics {
+    // Root mean square of the element-wise differences.
+    pub rmse: f64,
+    // Largest absolute difference and the first index where it occurs.
+    pub max_abs: f64,
+    pub max_abs_index: usize,
+    // Number of elements that are not exactly equal, and the lowest index of
+    // one, None if there are none.
+    pub mismatches: usize,
+    pub first_mismatch: Option<usize>,
+}
+
+// Error metrics of b against a, comparing the elements as f64 so that any
+// mix of i16, i32 and f32 can be measured. Empty inputs give all zeros; a NaN
+// counts as a mismatch but not towards max_abs, and makes rmse NaN.
+pub fn error_metrics<A: Copy + Into<f64>, B: Copy + Into<f64>>(a: &[A], b: &[B]) -> Result<ErrorMetrics, DimError> {
+    if a.len() != b.len() {
+        return Err(DimError::LengthMismatch { expected: a.len(), found: b.len() });
+    }
+
+    let mut metrics: ErrorMetrics = ErrorMetrics::default();
+    let mut sum_sq: f64 = 0.0;
+
+    for (index, (x, y)) in a.iter().zip(b).enumerate() {
+        let (x, y): (f64, f64) = ((*x).into(), (*y).into());
+        let diff: f64 = (x - y).abs();
+
+        if x != y {
+            metrics.mismatches += 1;
+            metrics.first_mismatch.get_or_insert(index);
+        }
+
+        if diff > metrics.max_abs {
+            metrics.max_abs = diff;
+            metrics.max_abs_index = index;
+        }
+
+        sum_sq += diff * diff;
+    }
+
+    if !a.is_empty() {
+        metrics.rmse = (sum_sq / a.len() as f64).sqrt();
+    }
+
+    return Ok(metrics);
+}
+
+// Elements in each partial result of reduce, reduce_rows and the float
+// reductions built on them. The blocks are fixed by the length of the data
+// alone, never by the number of threads.
+pub const SUM_BLOCK: usize = 4096;
+
+// Type of the running sums the f32 reductions keep.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Accumulator {
+    // f64, so that even hundreds of millions of terms lose nothing visible.
+    #[default]
+    F64,
+    // f32, as a single-precision loop would: faster on wide vectors, but a
+    // block sum stops taking in terms much smaller than itself. Kept for
+    // speed and accuracy comparisons.
+    F32,
+}
+
+impl Accumulator {
+    pub fn parse(value: &str) -> Option<Accumulator> {
+        return match value {
+            "f64" => Some(Accumulator::F64),
+            "f32" => Some(Accumulator::F32),
+            _ => None,
+        };
+    }
+
+    // The name parse takes.
+    pub fn name(self) -> &'static str {
+        return match self {
+            Accumulator::F64 => "f64",
+            Accumulator::F32 => "f32",
+        };
+    }
+}
+
+// Summary statistics of an f32 matrix, computed in f64.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FloatStats {
+    pub mean: f64,
+    // Population variance, the mean squared deviation from mean.
+    pub variance: f64,
+    // Square root of the sum of squares.
+    pub frobenius: f64,
+}
+
+// Sum of an f32 matrix in a fixed order: every SUM_BLOCK consecutive elements
+// are summed in index order, and the block sums are then added in index
+// order too. Threads (up to threads of them) only share out whole blocks, so
+// the result is bit-identical whatever threads is, unlike a reduction whose
+// order follows the thread split. Without the parallel feature this runs on
+// the calling thread.
+pub fn sum_f32(matrix: &[f32], threads: usize) -> f64 {
+    return sum_f32_with(matrix, threads, Accumulator::F64);
+}
+
+// sum_f32 keeping the block and running sums in accumulator, in the same
+// order whichever it is.
+pub fn sum_f32_with(matrix: &[f32], threads: usize, accumulator: Accumulator) -> f64 {
+    return match accumulator {
+        Accumulator::F64 => blocked_sum(matrix, threads, |value| value as f64),
+        Accumulator::F32 => blocked_sum_f32(matrix, threads, |value| value) as f64,
+    };
+}
+
+// Mean, variance and Frobenius norm of an f32 matrix, each reduced in the
+// order of sum_f32, so they are independent of threads as well. The variance
+// takes a second pass over the deviations from the mean rather than
+// subtracting the squared mean, which loses precision for data far from 0.
+// An empty matrix gives all zeros; NaNs propagate.
+pub fn float_stats(matrix: &[f32], threads: usize) -> FloatStats {
+    return float_stats_with(matrix, threads, Accumulator::F64);
+}
+
+// float_stats with every sum, and with Accumulator::F32 the mean and the
+// deviations as well, kept in accumulator.
+pub fn float_stats_with(matrix: &[f32], threads: usize, accumulator: Accumulator) -> FloatStats {
+    if matrix.is_empty() {
+        return FloatStats::default();
+    }
+
+    if accumulator == Accumulator::F32 {
+        let n: f32 = matrix.len() as f32;
+        let mean: f32 = blocked_sum_f32(matrix, threads, |value| value) / n;
+        let variance: f32 = blocked_sum_f32(matrix, threads, |value| (value - mean) * (value - mean)) / n;
+        let frobenius: f32 = blocked_sum_f32(matrix, threads, |value| value * value).sqrt();
+
+        return FloatStats { mean: mean as f64, variance: variance as f64, frobenius: frobenius as f64 };
+    }
+
+    let n: f64 = matrix.len() as f64;
+    let mean: f64 = blocked_sum(matrix, threads, |value| value as f64) / n;
+    let variance: f64 = blocked_sum(matrix, threads, |value| (value as f64 - mean) * (value as f64 - mean)) / n;
+    let frobenius: f64 = blocked_sum(matrix, threads, |value| value as f64 * value as f64).sqrt();
+
+    return FloatStats { mean, variance, frobenius };
+}
+
+// Sum of term over matrix in the fixed block order described at sum_f32.
+fn blocked_sum(matrix: &[f32], threads: usize, term: impl Fn(f32) -> f64 + Sync) -> f64 {
+    return reduce(matrix, threads, 0.0, |sum: f64, value| sum + term(value), |a, b| a + b);
+}
+
+// blocked_sum with f32 accumulators.
+fn blocked_sum_f32(matrix: &[f32], threads: usize, term: impl Fn(f32) -> f32 + Sync) -> f32 {
+    return reduce(matrix, threads, 0.0, |sum: f32, value| sum + term(value), |a, b| a + b);
+}
+
+// f applied to each chunk of chunk elements of data, in index order, with
+// contiguous runs of chunks handed to up to threads threads.
+#[cfg(feature = "parallel")]
+fn map_chunks<T: Sync, R: Send>(data: &[T], chunk: usize, threads: usize, f: &(impl Fn(&[T]) -> R + Sync)) -> Vec<R> {
+    let chunks: usize = data.len().div_ceil(chunk);
+
+    if threads <= 1 || chunks <= 1 {
+        return data.chunks(chunk).map(f).collect();
+    }
+
+    let run: usize = chunks.div_ceil(threads) * chunk;
+
+    return thread::scope(|scope| {
+        let handles: Vec<_> = data.chunks(run).map(|part| scope.spawn(move || part.chunks(chunk).map(f).collect::<Vec<R>>())).collect();
+
+        handles.into_iter().flat_map(|handle| handle.join().expect("reduction thread panicked")).collect()
+    });
+}
+
+#[cfg(not(feature = "parallel"))]
+fn map_chunks<T: Sync, R: Send>(data: &[T], chunk: usize, _threads: usize, f: &(impl Fn(&[T]) -> R + Sync)) -> Vec<R> {
+    return data.chunks(chunk).map(f).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::flat;
+    use crate::kernels::compute_dx;
+    use crate::matrix::construct_randomized_matrix_seeded;
+
+    #[test]
+    fn sums_of_all_positive_data_do_not_overflow() {
+        // 100,000 * 32,767 is well past i32::MAX.
+        let matrix: Vec<i16> = vec![i16::MAX; 100_000];
+
+        assert_eq!(get_sum(&matrix), 3_276_700_000);
+        assert_eq!(get_sum(&[255u8; 1000][..]), 255_000);
+        assert_eq!(count_nonzero(&matrix), 100_000);
+    }
+
+    #[test]
+    fn sums_of_mixed_sign_data() {
+        let matrix: Vec<i16> = vec![5, -3, 0, i16::MIN, i16::MAX, 0, -1, 2];
+
+        assert_eq!(get_sum(&matrix), 2);
+        assert_eq!(count_nonzero(&matrix), 6);
+        assert_eq!(row_sums(&matrix, 2, 4, 1).unwrap(), vec![-32766, 32768]);
+        assert_eq!(row_sums(&matrix, 2, 4, 4).unwrap(), vec![-32766, 32768]);
+        assert_eq!(get_sum::<i16>(&[]), 0);
+        assert!(row_sums(&matrix, 3, 3, 1).is_err());
+    }
+
+    #[test]
+    fn dx_sums_to_zero() {
+        // The kernel's weights sum to zero and the full convolution keeps
+        // every product, so each row of Dx does.
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(37, 53, 4);
+        let dx: Vec<i16> = compute_dx(&arr, 37, 53).data;
+
+        assert_eq!(get_sum(&dx), 0);
+        assert!(row_sums(&dx, 37, 55, 3).unwrap().iter().all(|&sum| sum == 0));
+        assert!(count_nonzero(&dx) > 0);
+    }
+
+    #[test]
+    fn zero_crossings_skip_runs_of_zeros() {
+        let rows: [&[i16]; 6] = [&[3, 0, -2], &[3, 0, 0, 0, 5], &[-1, 0, 0, 0, 0, 4, 0, -7], &[0, 0, 0], &[0, 0, 9, 0], &[]];
+
+        for (row, expected) in rows.iter().zip([1, 0, 2, 0, 0, 0]) {
+            assert_eq!(count_zero_crossings_rows(row, 1, row.len()).unwrap(), vec![expected], "{:?}", row);
+        }
+    }
+
+    #[test]
+    fn zero_crossings_of_alternating_data() {
+        let alternating: Vec<i16> = (0..4 * 6).map(|i| if (i / 6 + i % 6) % 2 == 0 { 1 } else { -1 }).collect();
+
+        assert_eq!(count_zero_crossings_rows(&alternating, 4, 6).unwrap(), vec![5; 4]);
+        assert_eq!(count_zero_crossings_cols(&alternating, 4, 6).unwrap(), vec![3; 6]);
+
+        // The columns of a matrix are the rows of its transpose.
+        let arr: Vec<i16> = compute_dx(&construct_randomized_matrix_seeded(9, 7, 2), 9, 7).data;
+        let transposed: Vec<i16> = crate::ops::transpose(&arr, 9, 9).unwrap();
+        assert_eq!(count_zero_crossings_cols(&arr, 9, 9).unwrap(), count_zero_crossings_rows(&transposed, 9, 9).unwrap());
+        assert!(count_zero_crossings_cols(&arr, 9, 8).is_err());
+    }
+
+    // Percentile p of data by sorting it, as percentiles defines it.
+    fn sorted_percentile(data: &[i16], p: f64) -> i16 {
+        let mut sorted: Vec<i16> = data.to_vec();
+        sorted.sort();
+
+        return sorted[(p.clamp(0.0, 100.0) / 100.0 * (sorted.len() - 1) as f64).round() as usize];
+    }
+
+    #[test]
+    fn percentiles_match_a_sorted_reference() {
+        let ps: [f64; 9] = [0.0, 1.0, 5.0, 25.0, 50.0, 75.0, 95.0, 99.0, 100.0];
+
+        for (seed, (rows, cols)) in [(1, 1), (1, 2), (13, 17), (64, 64)].into_iter().enumerate() {
+            let dx: Vec<i16> = compute_dx(&construct_randomized_matrix_seeded(rows, cols, seed as u64), rows, cols).data;
+            let expected: Vec<i16> = ps.iter().map(|&p| sorted_percentile(&dx, p)).collect();
+
+            assert_eq!(percentiles(&dx, &ps), expected, "{}x{}", rows, cols);
+        }
+    }
+
+    #[test]
+    fn percentile_edge_cases() {
+        let data: Vec<i16> = vec![i16::MAX, -4, 9, i16::MIN, 0];
+
+        assert_eq!(percentiles(&data, &[0.0, 100.0]), vec![i16::MIN, i16::MAX]);
+        assert_eq!(percentiles(&data, &[-5.0, 250.0]), vec![i16::MIN, i16::MAX]);
+        assert_eq!(percentiles(&data, &[50.0]), vec![0]);
+        assert_eq!(percentiles(&[], &[0.0, 50.0, 100.0]), vec![0, 0, 0]);
+        assert_eq!(percentiles(&data, &[]), Vec::<i16>::new());
+    }
+
+    // top_k_abs by sorting every element by descending magnitude, then index.
+    fn sorted_top_k(matrix: &[i16], cols: usize, k: usize) -> Vec<(i16, usize, usize)> {
+        let mut order: Vec<usize> = (0..matrix.len()).collect();
+        order.sort_by_key(|&index| (std::cmp::Reverse(matrix[index].unsigned_abs()), index));
+
+        return order.into_iter().take(k).map(|index| (matrix[index], index / cols, index % cols)).collect();
+    }
+
+    #[test]
+    fn top_k_finds_planted_extremes() {
+        let (rows, cols): (usize, usize) = (300, 500);
+        let mut matrix: Vec<i16> = vec![0; rows * cols];
+        for (index, value) in matrix.iter_mut().enumerate() {
+            *value = (index % 201) as i16 - 100;
+        }
+        // Spread over different SUM_BLOCK blocks; i16::MIN is the strongest.
+        matrix[flat(7, 3, cols)] = 20_000;
+        matrix[flat(150, 499, cols)] = i16::MIN;
+        matrix[flat(299, 0, cols)] = -20_000;
+        matrix[flat(0, 0, cols)] = 5_000;
+
+        assert_eq!(top_k_abs(&matrix, rows, cols, 4).unwrap(),
+                   vec![(i16::MIN, 150, 499), (20_000, 7, 3), (-20_000, 299, 0), (5_000, 0, 0)]);
+        for threads in [1, 2, 3, 8] {
+            assert_eq!(top_k_abs_par(&matrix, rows, cols, 50, threads).unwrap(), sorted_top_k(&matrix, cols, 50), "{} threads", threads);
+        }
+    }
+
+    #[test]
+    fn top_k_matches_a_sort_on_random_data() {
+        for (seed, (rows, cols, k)) in [(1, 1, 1), (9, 11, 5), (64, 64, 100), (300, 400, 17)].into_iter().enumerate() {
+            let dx: Vec<i16> = compute_dx(&construct_randomized_matrix_seeded(rows, cols, seed as u64), rows, cols).data;
+
+            assert_eq!(top_k_abs(&dx, rows, cols + 2, k).unwrap(), sorted_top_k(&dx, cols + 2, k), "{}x{} k={}", rows, cols, k);
+        }
+    }
+
+    #[test]
+    fn top_k_larger_than_the_matrix_returns_everything() {
+        let matrix: Vec<i16> = vec![3, -7, 0, 7, 1, -3];
+
+        assert_eq!(top_k_abs(&matrix, 2, 3, 100).unwrap(),
+                   vec![(-7, 0, 1), (7, 1, 0), (3, 0, 0), (-3, 1, 2), (1, 1, 1), (0, 0, 2)]);
+        assert_eq!(top_k_abs(&matrix, 2, 3, 0).unwrap(), vec![]);
+        assert_eq!(top_k_abs(&[], 0, 0, 5).unwrap(), vec![]);
+        assert_eq!(top_k_abs(&matrix, 2, 2, 1), Err(DimError::LengthMismatch { expected: 4, found: 6 }));
+    }
+
+    #[test]
+    fn top_k_f32_ranks_nan_first() {
+        let matrix: Vec<f32> = vec![1.0, f32::NAN, -2.0, 3.0];
+        let top: Vec<(f32, usize, usize)> = top_k_f32(&matrix, 2, 2, 10).unwrap();
+
+        assert!(top[0].0.is_nan());
+        assert_eq!(top[1..], [(3.0, 1, 1), (1.0, 0, 0), (-2.0, 1, 0)]);
+    }
+
+    #[test]
+    fn error_metrics_of_equal_inputs_are_zero() {
+        let a: Vec<i16> = vec![3, -7, i16::MAX, 0];
+
+        assert_eq!(error_metrics(&a, &a).unwrap(), ErrorMetrics::default());
+        assert_eq!(error_metrics::<i16, i32>(&[], &[]).unwrap(), ErrorMetrics::default());
+        // Mixed types compare by value.
+        assert_eq!(error_metrics(&a, &a.iter().map(|&value| value as f32).collect::<Vec<f32>>()).unwrap().mismatches, 0);
+    }
+
+    #[test]
+    fn error_metrics_measure_the_differences() {
+        let a: Vec<i32> = vec![10, 20, 30, 40, 50];
+        let b: Vec<i32> = vec![10, 23, 30, 36, 54];
+        let metrics: ErrorMetrics = error_metrics(&a, &b).unwrap();
+
+        // Differences 0, 3, 0, 4, 4: the first 4 is the max.
+        assert_eq!(metrics.mismatches, 3);
+        assert_eq!(metrics.first_mismatch, Some(1));
+        assert_eq!((metrics.max_abs, metrics.max_abs_index), (4.0, 3));
+        assert!((metrics.rmse - (41.0f64 / 5.0).sqrt()).abs() < 1e-12);
+
+        // Within i16, the difference can exceed i16::MAX without wrapping.
+        let extremes: ErrorMetrics = error_metrics(&[i16::MIN], &[i16::MAX]).unwrap();
+        assert_eq!((extremes.max_abs, extremes.rmse), (65535.0, 65535.0));
+    }
+
+    #[test]
+    fn error_metrics_count_nan_as_a_mismatch() {
+        let metrics: ErrorMetrics = error_metrics(&[1.0f32, 2.0, 3.0], &[1.0f32, f32::NAN, 5.0]).unwrap();
+
+        assert_eq!(metrics.mismatches, 2);
+        assert_eq!(metrics.first_mismatch, Some(1));
+        assert_eq!((metrics.max_abs, metrics.max_abs_index), (2.0, 2));
+        assert!(metrics.rmse.is_nan());
+    }
+
+    #[test]
+    fn error_metrics_need_equal_lengths() {
+        assert_eq!(error_metrics(&[1i16, 2, 3], &[1i16, 2]), Err(DimError::LengthMismatch { expected: 3, found: 2 }));
+        assert_eq!(error_metrics::<i16, f32>(&[], &[0.0]), Err(DimError::LengthMismatch { expected: 0, found: 1 }));
+    }
+
+    #[test]
+    fn reduce_rows_matches_a_serial_fold_of_each_row() {
+        // Rows longer than SUM_BLOCK are merged from several blocks.
+        for (rows, cols) in [(1, 1), (5, 7), (3, SUM_BLOCK + 5), (17, 2 * SUM_BLOCK)] {
+            let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, (rows + cols) as u64);
+            let expected: Vec<(u64, u8)> = arr.chunks(cols)
+                .map(|row| row.iter().fold((0, 0), |(sum, max): (u64, u8), &value| (sum + value as u64, max.max(value)))).collect();
+
+            for threads in [1, 2, 3, 8, 64] {
+                let found: Vec<(u64, u8)> = reduce_rows(&arr, rows, cols, threads, (0, 0), |(sum, max): (u64, u8), value: u8| (sum + value as u64, max.max(value)),
+                                                        |a, b| (a.0 + b.0, a.1.max(b.1))).unwrap();
+                assert_eq!(found, expected, "{}x{} on {} threads", rows, cols, threads);
+            }
+        }
+    }
+
+    #[test]
+    fn reduce_rows_does_not_depend_on_threads() {
+        // A float sum is not associative, so a different block split or
+        // merge order would show in the last bits.
+        let (rows, cols): (usize, usize) = (24, 3 * SUM_BLOCK + 17);
+        let data: Vec<f32> = construct_randomized_matrix_seeded(rows, cols, 9).iter().map(|&value| value as f32 / 7.0 + 1e-3).collect();
+        let sums = |threads: usize| -> Vec<u32> {
+            return reduce_rows(&data, rows, cols, threads, 0.0f32, |sum, value| sum + value, |a, b| a + b).unwrap().iter().map(|sum| sum.to_bits()).collect();
+        };
+
+        for threads in [2, 5, 8, 24, 100] {
+            assert_eq!(sums(threads), sums(1), "{} threads", threads);
+        }
+    }
+
+    #[test]
+    fn reduce_rows_of_empty_matrices() {
+        let fold = |sum: i64, value: i16| sum + value as i64;
+
+        assert_eq!(reduce_rows(&[], 0, 5, 4, 7, fold, |a, b| a + b).unwrap(), Vec::<i64>::new());
+        assert_eq!(reduce_rows(&[], 3, 0, 4, 7, fold, |a, b| a + b).unwrap(), vec![7; 3]);
+        assert_eq!(reduce_rows(&[], 0, 0, 1, 7, fold, |a, b| a + b).unwrap(), Vec::<i64>::new());
+        assert_eq!(reduce_rows(&[1, 2, 3], 2, 2, 1, 0, fold, |a, b| a + b), Err(DimError::LengthMismatch { expected: 4, found: 3 }));
+    }
+
+    // A seeded f32 matrix of both signs and many magnitudes, spread over
+    // dozens of SUM_BLOCK blocks.
+    fn float_matrix(rows: usize, cols: usize, seed: u64) -> Vec<f32> {
+        return construct_randomized_matrix_seeded(rows, cols, seed).iter().enumerate()
+            .map(|(index, &value)| (value as f32 - 127.5) * 10f32.powi((index % 7) as i32 - 3)).collect();
+    }
+
+    #[test]
+    fn float_sums_are_bit_identical_on_1_and_8_threads() {
+        let matrix: Vec<f32> = float_matrix(300, 500, 6);
+        let bits = |stats: FloatStats| -> [u64; 3] { [stats.mean.to_bits(), stats.variance.to_bits(), stats.frobenius.to_bits()] };
+
+        for accumulator in [Accumulator::F64, Accumulator::F32] {
+            assert_eq!(sum_f32_with(&matrix, 1, accumulator).to_bits(), sum_f32_with(&matrix, 8, accumulator).to_bits(), "{}", accumulator.name());
+            assert_eq!(bits(float_stats_with(&matrix, 1, accumulator)), bits(float_stats_with(&matrix, 8, accumulator)), "{}", accumulator.name());
+        }
+
+        assert_eq!(sum_f32(&matrix, 8).to_bits(), sum_f32(&matrix, 3).to_bits());
+        assert_eq!(float_stats(&[], 8), FloatStats::default());
+    }
+
+    #[test]
+    fn f64_accumulation_keeps_what_f32_drops() {
+        // Every SUM_BLOCK block is 2^24, SUM_BLOCK - 2 ones and -2^24. In
+        // f32, 2^24 + 1 rounds back to 2^24, so the ones all vanish and each
+        // block sums to 0; f64 holds the exact SUM_BLOCK - 2.
+        let big: f32 = (1u32 << 24) as f32;
+        let data: Vec<f32> = (0..4 * SUM_BLOCK).map(|index| match index % SUM_BLOCK {
+            0 => big,
+            offset if offset == SUM_BLOCK - 1 => -big,
+            _ => 1.0,
+        }).collect();
+        let exact_mean: f64 = (SUM_BLOCK - 2) as f64 / SUM_BLOCK as f64;
+        let error = |mean: f64| (mean - exact_mean).abs() / exact_mean;
+
+        for threads in [1, 3] {
+            assert!(error(float_stats_with(&data, threads, Accumulator::F64).mean) < 1e-12);
+            assert!(error(sum_f32_with(&data, threads, Accumulator::F64) / data.len() as f64) < 1e-12);
+            assert!(error(float_stats_with(&data, threads, Accumulator::F32).mean) > 1e-3);
+            assert!(error(sum_f32_with(&data, threads, Accumulator::F32) / data.len() as f64) > 1e-3);
+        }
+
+        // Ten million copies of 0.1 drift by about 5e-5 in f32, even in
+        // blocks; in f64 by nothing visible.
+        let tenths: Vec<f32> = vec![0.1; 10_000_000];
+        let exact: f64 = 0.1f32 as f64 * 1e7;
+        let error = |sum: f64| (sum - exact).abs() / exact;
+
+        assert!(error(sum_f32_with(&tenths, 4, Accumulator::F64)) < 1e-9);
+        assert!(error(sum_f32_with(&tenths, 4, Accumulator::F32)) > 1e-5);
+        assert_eq!(Accumulator::default(), Accumulator::F64);
+    }
+
+    #[test]
+    fn ties_go_to_the_first_index_in_row_major_order() {
+        // Several SUM_BLOCK blocks of one value, so every block offers a
+        // candidate as good as the first.
+        let (rows, cols): (usize, usize) = (40, 1000);
+        let equal: Vec<i16> = vec![-9; rows * cols];
+
+        for threads in [1, 2, 3, 8] {
+            assert_eq!((argmin(&equal, threads), argmax(&equal, threads)), (Some(0), Some(0)), "{} threads", threads);
+            assert_eq!(top_k_abs_par(&equal, rows, cols, 5, threads).unwrap(), vec![(-9, 0, 0), (-9, 0, 1), (-9, 0, 2), (-9, 0, 3), (-9, 0, 4)]);
+        }
+
+        // Equal extremes planted in later blocks first, then at the start of
+        // later rows: the earliest still wins, whatever thread took it.
+        let mut planted: Vec<i16> = construct_randomized_matrix_seeded(rows, cols, 3).iter().map(|&value| value as i16 - 128).collect();
+        for (row, col) in [(39, 999), (20, 0), (9, 512), (33, 7)] {
+            planted[flat(row, col, cols)] = i16::MAX;
+        }
+        planted[flat(30, 1, cols)] = i16::MIN;
+        planted[flat(12, 40, cols)] = i16::MIN;
+
+        let top: Vec<(i16, usize, usize)> = top_k_abs(&planted, rows, cols, 6).unwrap();
+        assert_eq!(top, vec![(i16::MIN, 12, 40), (i16::MIN, 30, 1), (i16::MAX, 9, 512), (i16::MAX, 20, 0), (i16::MAX, 33, 7), (i16::MAX, 39, 999)]);
+
+        for threads in [2, 3, 8, 64] {
+            assert_eq!(argmax(&planted, threads), Some(flat(9, 512, cols)), "{} threads", threads);
+            assert_eq!(argmin(&planted, threads), Some(flat(12, 40, cols)), "{} threads", threads);
+            assert_eq!(top_k_abs_par(&planted, rows, cols, 6, threads).unwrap(), top, "{} threads", threads);
+        }
+
+        let floats: Vec<f32> = vec![2.5; 3 * SUM_BLOCK];
+        assert_eq!(top_k_f32(&floats, 3, SUM_BLOCK, 2).unwrap(), vec![(2.5, 0, 0), (2.5, 0, 1)]);
+        assert_eq!(argmax::<u8>(&[], 4), None);
+    }
+}
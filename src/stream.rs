@@ -0,0 +1,352 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+use crate::io::{decode_values, invalid_data, BinElement, DType, StoredMatrix};
+use crate::stats::{checksum_bytes, CHECKSUM_BASIS};
+
+// Streaming matrix format (.rmms), written a row at a time by StreamWriter,
+// little-endian throughout:
+//
+//     offset  size  field
+//     0       4     magic "RMMS"
+//     4       1     format version, currently 1
+//     5       1     element type, see DType
+//     6       2     reserved, zero
+//     8       8     rows (u64)
+//     16      8     cols (u64)
+//     24            rows * cols elements in row-major order
+//
+// followed by a footer written only once every row is in:
+//
+//     0       4     magic "RMME"
+//     4       4     reserved, zero
+//     8       8     element count (u64)
+//     16      8     checksum_bytes of the element bytes (u64)
+//
+// A file whose writer died midway has a header but no footer, and its
+// complete rows can still be recovered.
+pub const STREAM_MAGIC: &[u8; 4] = b"RMMS";
+pub const STREAM_FOOTER_MAGIC: &[u8; 4] = b"RMME";
+pub const STREAM_VERSION: u8 = 1;
+const STREAM_HEADER_LEN: usize = 24;
+const STREAM_FOOTER_LEN: usize = 24;
+
+// Writes a rows x cols matrix of T to a stream file row by row.
+pub struct StreamWriter<T> {
+    writer: BufWriter<File>,
+    rows: usize,
+    cols: usize,
+    written_rows: usize,
+    hash: u64,
+    bytes: Vec<u8>,
+    element: PhantomData<T>,
+}
+
+impl<T: BinElement> StreamWriter<T> {
+    // Creates the file and writes its header.
+    pub fn create(path: &Path, rows: usize, cols: usize) -> std::io::Result<StreamWriter<T>> {
+        let mut writer: BufWriter<File> = BufWriter::new(File::create(path)?);
+
+        writer.write_all(STREAM_MAGIC)?;
+        writer.write_all(&[STREAM_VERSION, T::DTYPE as u8, 0, 0])?;
+        writer.write_all(&(rows as u64).to_le_bytes())?;
+        writer.write_all(&(cols as u64).to_le_bytes())?;
+
+        return Ok(StreamWriter { writer, rows, cols, written_rows: 0, hash: CHECKSUM_BASIS, bytes: Vec::with_capacity(cols * T::DTYPE.size()),
+                                 element: PhantomData });
+    }
+
+    // Appends the next row, which must hold cols elements.
+    pub fn write_row(&mut self, row: &[T]) -> std::io::Result<()> {
+        if row.len() != self.cols {
+            return Err(invalid_data(&format!("row of {} elements, expected {}", row.len(), self.cols)));
+        }
+
+        if self.written_rows == self.rows {
+            return Err(invalid_data(&format!("all {} rows are already written", self.rows)));
+        }
+
+        self.bytes.clear();
+
+        for value in row {
+            value.write_le(&mut self.bytes);
+        }
+
+        self.hash = checksum_bytes(self.hash, &self.bytes);
+        self.written_rows += 1;
+
+        return self.writer.write_all(&self.bytes);
+    }
+
+    // Writes the footer once every row is in. A writer dropped without
+    // finishing leaves a truncated file behind.
+    pub fn finish(mut self) -> std::io::Result<()> {
+        if self.written_rows != self.rows {
+            return Err(invalid_data(&format!("only {} of {} rows written", self.written_rows, self.rows)));
+        }
+
+        self.writer.write_all(STREAM_FOOTER_MAGIC)?;
+        self.writer.write_all(&[0; 4])?;
+        self.writer.write_all(&((self.rows * self.cols) as u64).to_le_bytes())?;
+        self.writer.write_all(&self.hash.to_le_bytes())?;
+
+        return self.writer.flush();
+    }
+}
+
+// Whether a stream file was finished.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamStatus {
+    // The footer is present and agrees with the data.
+    Complete { checksum: u64 },
+    // The footer is missing: the writer stopped after complete_rows whole
+    // rows (plus partial_bytes of the next one, if any).
+    Truncated { complete_rows: usize, partial_bytes: usize },
+}
+
+// What inspect_stream found in a stream file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamInfo {
+    pub version: u8,
+    pub dtype: DType,
+    pub rows: usize,
+    pub cols: usize,
+    pub status: StreamStatus,
+}
+
+// Reads the header and footer of a stream file, verifying the checksum of a
+// complete one. Files that are not stream files, or whose footer
+// contradicts the header or the data, are errors; a missing footer is not.
+pub fn inspect_stream(path: &Path) -> std::io::Result<StreamInfo> {
+    return Ok(parse(&fs::read(path)?)?.0);
+}
+
+// Reads a complete stream file. A truncated one is an error that says how
+// many rows recover_stream can still return.
+pub fn read_stream(path: &Path) -> std::io::Result<StoredMatrix> {
+    return parse_stream(&fs::read(path)?);
+}
+
+// Reads the complete rows of a stream file, all of them if it was finished.
+pub fn recover_stream(path: &Path) -> std::io::Result<StoredMatrix> {
+    let bytes: Vec<u8> = fs::read(path)?;
+    let (info, body) = parse(&bytes)?;
+    let rows: usize = match info.status {
+        StreamStatus::Complete { .. } => info.rows,
+        StreamStatus::Truncated { complete_rows, .. } => complete_rows,
+    };
+
+    return Ok(StoredMatrix { dtype: Some(info.dtype), rows, cols: info.cols,
+                             values: decode_values(info.dtype, &body[..rows * info.cols * info.dtype.size()]) });
+}
+
+pub(crate) fn parse_stream(bytes: &[u8]) -> std::io::Result<StoredMatrix> {
+    let (info, body) = parse(bytes)?;
+
+    if let StreamStatus::Truncated { complete_rows, .. } = info.status {
+        return Err(invalid_data(&format!("truncated stream: footer missing, {} of {} rows complete and recoverable", complete_rows, info.rows)));
+    }
+
+    return Ok(StoredMatrix { dtype: Some(info.dtype), rows: info.rows, cols: info.cols, values: decode_values(info.dtype, body) });
+}
+
+// The header information of a stream file and the element bytes it holds.
+fn parse(bytes: &[u8]) -> std::io::Result<(StreamInfo, &[u8])> {
+    if !bytes.starts_with(STREAM_MAGIC) {
+        return Err(invalid_data(&format!("not a stream file: magic {:?}, expected \"RMMS\"", String::from_utf8_lossy(&bytes[..bytes.len().min(4)]))));
+    }
+
+    if bytes.len() < STREAM_HEADER_LEN {
+        return Err(invalid_data("truncated header"));
+    }
+
+    if bytes[4] != STREAM_VERSION {
+        return Err(invalid_data(&format!("unsupported format version {}", bytes[4])));
+    }
+
+    let dtype: DType = DType::from_code(bytes[5]).ok_or_else(|| invalid_data(&format!("unknown element type {}", bytes[5])))?;
+    let field = |bytes: &[u8], offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().expect("8 bytes"));
+    let (rows, cols): (usize, usize) = (field(bytes, 8) as usize, field(bytes, 16) as usize);
+    let row_bytes: usize = cols.checked_mul(dtype.size()).ok_or_else(|| invalid_data("row size overflows"))?;
+    let data_bytes: usize = rows.checked_mul(row_bytes).ok_or_else(|| invalid_data("matrix size overflows"))?;
+    let rest: &[u8] = &bytes[STREAM_HEADER_LEN..];
+
+    // Everything up to a complete footer is a truncated file.
+    if rest.len() < data_bytes + STREAM_FOOTER_LEN {
+        let body: &[u8] = &rest[..rest.len().min(data_bytes)];
+        // Rows without columns are complete as soon as the header is.
+        let complete_rows: usize = body.len().checked_div(row_bytes).unwrap_or(rows);
+        let status: StreamStatus = StreamStatus::Truncated { complete_rows, partial_bytes: body.len() - complete_rows * row_bytes };
+
+        return Ok((StreamInfo { version: bytes[4], dtype, rows, cols, status }, body));
+    }
+
+    if rest.len() > data_bytes + STREAM_FOOTER_LEN {
+        return Err(invalid_data(&format!("{} bytes after the footer", rest.len() - data_bytes - STREAM_FOOTER_LEN)));
+    }
+
+    let (body, footer): (&[u8], &[u8]) = rest.split_at(data_bytes);
+
+    if !footer.starts_with(STREAM_FOOTER_MAGIC) {
+        return Err(invalid_data("invalid footer magic"));
+    }
+
+    let (elements, stored): (u64, u64) = (field(footer, 8), field(footer, 16));
+    let computed: u64 = checksum_bytes(CHECKSUM_BASIS, body);
+
+    if elements != (rows * cols) as u64 {
+        return Err(invalid_data(&format!("footer counts {} elements, header {}x{}", elements, rows, cols)));
+    }
+
+    if stored != computed {
+        return Err(invalid_data(&format!("checksum mismatch: footer {:016x}, data {:016x}", stored, computed)));
+    }
+
+    return Ok((StreamInfo { version: bytes[4], dtype, rows, cols, status: StreamStatus::Complete { checksum: stored } }, body));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::read_matrix;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        return std::env::temp_dir().join(format!("rmm-stream-{}-{}", std::process::id(), name));
+    }
+
+    // The rows x cols matrix with element (row, col) = row * 100 - col.
+    fn values(rows: usize, cols: usize) -> Vec<i16> {
+        return (0..rows * cols).map(|index| ((index / cols) * 100) as i16 - (index % cols) as i16).collect();
+    }
+
+    // Streams the first written rows of a rows x cols matrix, finishing the
+    // file only if every row is written.
+    fn write(path: &Path, rows: usize, cols: usize, written: usize) -> Vec<i16> {
+        let data: Vec<i16> = values(rows, cols);
+        let mut writer: StreamWriter<i16> = StreamWriter::create(path, rows, cols).unwrap();
+
+        for row in data.chunks_exact(cols.max(1)).take(written) {
+            writer.write_row(row).unwrap();
+        }
+
+        if written == rows {
+            writer.finish().unwrap();
+        }
+
+        return data;
+    }
+
+    fn error(path: &Path) -> String {
+        let err: std::io::Error = inspect_stream(path).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        return err.to_string();
+    }
+
+    #[test]
+    fn complete_files_read_back() {
+        let path: PathBuf = temp_path("complete.rmms");
+        let data: Vec<i16> = write(&path, 5, 7, 5);
+        let info: StreamInfo = inspect_stream(&path).unwrap();
+
+        let mut bytes: Vec<u8> = Vec::new();
+        data.iter().for_each(|value| value.write_le(&mut bytes));
+        assert_eq!(info, StreamInfo { version: STREAM_VERSION, dtype: DType::I16, rows: 5, cols: 7,
+                                      status: StreamStatus::Complete { checksum: checksum_bytes(CHECKSUM_BASIS, &bytes) } });
+
+        let expected: StoredMatrix = StoredMatrix { dtype: Some(DType::I16), rows: 5, cols: 7, values: data.iter().map(|&value| value as i64).collect() };
+        assert_eq!(read_stream(&path).unwrap(), expected);
+        assert_eq!(recover_stream(&path).unwrap(), expected);
+        assert_eq!(read_matrix(&path).unwrap(), expected);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn truncated_files_report_the_recoverable_rows() {
+        let path: PathBuf = temp_path("truncated.rmms");
+        let data: Vec<i16> = write(&path, 5, 7, 2);
+
+        assert_eq!(inspect_stream(&path).unwrap().status, StreamStatus::Truncated { complete_rows: 2, partial_bytes: 0 });
+        assert_eq!(read_stream(&path).unwrap_err().to_string(), "truncated stream: footer missing, 2 of 5 rows complete and recoverable");
+        assert_eq!(recover_stream(&path).unwrap().values, data[..14].iter().map(|&value| value as i64).collect::<Vec<i64>>());
+
+        // A complete file cut inside its third row, and one cut inside its
+        // footer, which has all rows but is still unfinished.
+        write(&path, 5, 7, 5);
+        let bytes: Vec<u8> = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..STREAM_HEADER_LEN + 2 * 14 + 5]).unwrap();
+        assert_eq!(inspect_stream(&path).unwrap().status, StreamStatus::Truncated { complete_rows: 2, partial_bytes: 5 });
+        assert_eq!(recover_stream(&path).unwrap().rows, 2);
+
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert_eq!(inspect_stream(&path).unwrap().status, StreamStatus::Truncated { complete_rows: 5, partial_bytes: 0 });
+        assert_eq!(recover_stream(&path).unwrap().values, data.iter().map(|&value| value as i64).collect::<Vec<i64>>());
+
+        // Just the header.
+        fs::write(&path, &bytes[..STREAM_HEADER_LEN]).unwrap();
+        assert_eq!(inspect_stream(&path).unwrap().status, StreamStatus::Truncated { complete_rows: 0, partial_bytes: 0 });
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn wrong_magic_is_rejected() {
+        let path: PathBuf = temp_path("magic.rmms");
+        write(&path, 3, 4, 3);
+        let mut bytes: Vec<u8> = fs::read(&path).unwrap();
+        bytes[..4].copy_from_slice(b"RMMB");
+        fs::write(&path, &bytes).unwrap();
+
+        assert_eq!(error(&path), "not a stream file: magic \"RMMB\", expected \"RMMS\"");
+        assert!(read_stream(&path).is_err());
+        fs::write(&path, b"RM").unwrap();
+        assert_eq!(error(&path), "not a stream file: magic \"RM\", expected \"RMMS\"");
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn damaged_files_are_rejected() {
+        let path: PathBuf = temp_path("damaged.rmms");
+        write(&path, 3, 4, 3);
+        let good: Vec<u8> = fs::read(&path).unwrap();
+        let damaged = |offset: usize, value: u8| -> String {
+            let mut bytes: Vec<u8> = good.clone();
+            bytes[offset] = value;
+            fs::write(&path, &bytes).unwrap();
+            return error(&path);
+        };
+
+        assert!(damaged(STREAM_HEADER_LEN + 3, 0x55).starts_with("checksum mismatch: footer "));
+        assert_eq!(damaged(4, 2), "unsupported format version 2");
+        assert_eq!(damaged(5, 99), "unknown element type 99");
+        assert_eq!(damaged(good.len() - STREAM_FOOTER_LEN, b'X'), "invalid footer magic");
+        assert_eq!(damaged(good.len() - 16, 13), "footer counts 13 elements, header 3x4");
+
+        let mut longer: Vec<u8> = good.clone();
+        longer.extend_from_slice(&[0; 3]);
+        fs::write(&path, &longer).unwrap();
+        assert_eq!(error(&path), "3 bytes after the footer");
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn the_writer_checks_its_rows() {
+        let path: PathBuf = temp_path("writer.rmms");
+        let mut writer: StreamWriter<i16> = StreamWriter::create(&path, 1, 3).unwrap();
+
+        assert_eq!(writer.write_row(&[1, 2]).unwrap_err().to_string(), "row of 2 elements, expected 3");
+        writer.write_row(&[1, 2, 3]).unwrap();
+        assert_eq!(writer.write_row(&[1, 2, 3]).unwrap_err().to_string(), "all 1 rows are already written");
+        writer.finish().unwrap();
+
+        let unfinished: StreamWriter<i16> = StreamWriter::create(&path, 2, 3).unwrap();
+        assert_eq!(unfinished.finish().unwrap_err().to_string(), "only 0 of 2 rows written");
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,155 @@
+use crate::arith::Widen;
+use crate::error::{check_len, DimError};
+use crate::integral::IntegralImage;
+
+// Template matching: a small trows x tcols template slid over every position
+// where it fits inside a rows x cols matrix. The results are
+// (rows - trows + 1) x (cols - tcols + 1), element (r, c) being the match of
+// the window whose top-left corner is (r, c). Unlike the kernels in conv, the
+// template is not flipped and may be as large as the matrix.
+
+// The sliding dot product: element (r, c) is the sum of
+// template[i][j] * arr[r + i][c + j] over the template, exact in i64.
+pub fn match_template<T: Widen>(arr: &[T], rows: usize, cols: usize, template: &[i32], trows: usize, tcols: usize) -> Result<Vec<i64>, DimError> {
+    let (out_rows, out_cols): (usize, usize) = match_dims(arr, rows, cols, template, trows, tcols)?;
+    let mut out: Vec<i64> = vec![0; out_rows * out_cols];
+
+    for row in 0..out_rows {
+        for col in 0..out_cols {
+            let mut sum: i64 = 0;
+
+            for t_row in 0..trows {
+                let window: &[T] = &arr[(row + t_row) * cols + col..][..tcols];
+                let taps: &[i32] = &template[t_row * tcols..][..tcols];
+
+                sum += window.iter().zip(taps).map(|(&value, &tap)| value.widen() * tap as i64).sum::<i64>();
+            }
+
+            out[row * out_cols + col] = sum;
+        }
+    }
+
+    return Ok(out);
+}
+
+// The zero-mean normalized cross-correlation, shaped as match_template: the
+// correlation coefficient of the template and each window, from -1 to 1. A
+// window that is the template up to brightness and contrast (scaled by a
+// positive factor plus an offset) scores 1. Where the window or the template
+// does not vary at all the coefficient is undefined and 0 is returned.
+//
+// The window sums and sums of squares come from integral images, so the
+// normalization adds O(1) per window to the dot products.
+pub fn match_template_normalized<T: Widen>(arr: &[T], rows: usize, cols: usize, template: &[i32], trows: usize,
+                                           tcols: usize) -> Result<Vec<f32>, DimError> {
+    let raw: Vec<i64> = match_template(arr, rows, cols, template, trows, tcols)?;
+    let (out_rows, out_cols): (usize, usize) = match_dims(arr, rows, cols, template, trows, tcols)?;
+    let n: f64 = (trows * tcols) as f64;
+
+    if n == 0.0 {
+        return Ok(vec![0.0; raw.len()]);
+    }
+
+    let values: Vec<f64> = arr.iter().map(|&value| value.widen() as f64).collect();
+    let squares: Vec<f64> = values.iter().map(|value| value * value).collect();
+    let (sums, square_sums): (IntegralImage, IntegralImage) = (IntegralImage::new(&values, rows, cols)?, IntegralImage::new(&squares, rows, cols)?);
+
+    // n times the variance of the template, and below of the window. For
+    // integer data n * variance is a whole number, so anything under 0.5 is
+    // rounding error on a flat window.
+    let t_sum: f64 = template.iter().map(|&tap| tap as f64).sum();
+    let t_spread: f64 = template.iter().map(|&tap| tap as f64 * tap as f64).sum::<f64>() - t_sum * t_sum / n;
+    let mut out: Vec<f32> = vec![0.0; raw.len()];
+
+    for row in 0..out_rows {
+        for col in 0..out_cols {
+            let sum: f64 = sums.sum(row, col, row + trows, col + tcols);
+            let spread: f64 = square_sums.sum(row, col, row + trows, col + tcols) - sum * sum / n;
+
+            if t_spread * n < 0.5 || spread * n < 0.5 {
+                continue;
+            }
+
+            let index: usize = row * out_cols + col;
+            let coefficient: f64 = (raw[index] as f64 - t_sum * sum / n) / (t_spread * spread).sqrt();
+
+            out[index] = coefficient.clamp(-1.0, 1.0) as f32;
+        }
+    }
+
+    return Ok(out);
+}
+
+// Shape of the results of matching a trows x tcols template in a rows x cols
+// matrix, after checking the lengths and that the template fits.
+pub fn match_dims<T>(arr: &[T], rows: usize, cols: usize, template: &[i32], trows: usize, tcols: usize) -> Result<(usize, usize), DimError> {
+    check_len(arr.len(), rows, cols)?;
+    check_len(template.len(), trows, tcols)?;
+
+    if trows > rows || tcols > cols {
+        return Err(DimError::OutOfRange { rows, cols, row: 0, col: 0, height: trows, width: tcols });
+    }
+
+    return Ok((rows - trows + 1, cols - tcols + 1));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::construct_randomized_matrix_seeded;
+
+    // The trows x tcols patch of arr whose top-left corner is (row, col).
+    fn patch(arr: &[u8], cols: usize, (row, col): (usize, usize), trows: usize, tcols: usize) -> Vec<i32> {
+        return (0..trows * tcols).map(|index| arr[(row + index / tcols) * cols + col + index % tcols] as i32).collect();
+    }
+
+    // Index and value of the largest score, the first one on ties.
+    fn best(scores: &[f32]) -> (usize, f32) {
+        return scores.iter().copied().enumerate().fold((0, f32::MIN), |best, (index, score)| if score > best.1 { (index, score) } else { best });
+    }
+
+    #[test]
+    fn a_patch_is_found_at_its_origin() {
+        let (rows, cols): (usize, usize) = (40, 57);
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, 21);
+
+        for (origin, trows, tcols) in [((0, 0), 5, 5), ((17, 30), 6, 9), ((35, 52), 5, 5), ((12, 0), 1, 57)] {
+            let template: Vec<i32> = patch(&arr, cols, origin, trows, tcols);
+            let out_cols: usize = cols - tcols + 1;
+            let (index, score): (usize, f32) = best(&match_template_normalized(&arr, rows, cols, &template, trows, tcols).unwrap());
+
+            assert_eq!((index / out_cols, index % out_cols), origin, "{}x{} patch", trows, tcols);
+            assert!((score - 1.0).abs() < 1e-5, "{}", score);
+
+            // Brightness and contrast do not change the match.
+            let brighter: Vec<i32> = template.iter().map(|&tap| 3 * tap + 40).collect();
+            let (index, score): (usize, f32) = best(&match_template_normalized(&arr, rows, cols, &brighter, trows, tcols).unwrap());
+            assert_eq!((index / out_cols, index % out_cols, (score - 1.0).abs() < 1e-5), (origin.0, origin.1, true));
+        }
+    }
+
+    #[test]
+    fn raw_scores_are_the_sliding_dot_product() {
+        let arr: Vec<u8> = vec![1, 2, 3, 4, 5, 6, 7, 8, 9];
+
+        assert_eq!(match_template(&arr, 3, 3, &[1, 0, 0, -1], 2, 2).unwrap(), vec![1 - 5, 2 - 6, 4 - 8, 5 - 9]);
+        assert_eq!(match_template(&arr, 3, 3, &[1; 9], 3, 3).unwrap(), vec![45]);
+        assert_eq!(match_template(&arr, 3, 3, &[2], 1, 1).unwrap(), arr.iter().map(|&value| 2 * value as i64).collect::<Vec<i64>>());
+
+        // A flat window or template has no defined coefficient.
+        assert_eq!(match_template_normalized(&[4u8; 9], 3, 3, &[1, 2, 3, 4], 2, 2).unwrap(), vec![0.0; 4]);
+        assert_eq!(match_template_normalized(&arr, 3, 3, &[7; 4], 2, 2).unwrap(), vec![0.0; 4]);
+    }
+
+    #[test]
+    fn a_template_larger_than_the_matrix_is_an_error() {
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(4, 6, 2);
+        let out_of_range = DimError::OutOfRange { rows: 4, cols: 6, row: 0, col: 0, height: 5, width: 2 };
+
+        assert_eq!(match_template(&arr, 4, 6, &[1; 10], 5, 2), Err(out_of_range.clone()));
+        assert_eq!(match_template_normalized(&arr, 4, 6, &[1; 10], 5, 2), Err(out_of_range));
+        assert!(match_template(&arr, 4, 6, &[1; 7], 1, 7).is_err());
+        assert!(match_template(&arr, 4, 6, &[1; 3], 2, 2).is_err());
+        assert_eq!(match_dims(&arr, 4, 6, &[1; 24], 4, 6), Ok((1, 1)));
+    }
+}
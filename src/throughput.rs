@@ -0,0 +1,97 @@
+use std::mem::size_of;
+use std::time::Duration;
+
+// Memory traffic model used to turn kernel durations into bandwidth figures.
+// Each input element is assumed to be read from memory once and each output
+// element written once; re-reads that hit the cache are not counted. For one
+// pass of a kernel of length k over a rows x cols u8 input, the output has
+// k - 1 elements of padding along the convolved axis, so
+//
+//     compute_dx: rows * cols + 2 * rows * (cols + 2) bytes
+//     compute_dy: rows * cols + 2 * (rows + 2) * cols bytes
+//
+// with i16 outputs (k = 3).
+
+// Bytes moved by a horizontal pass with pad elements of output padding per
+// row, reading u8 and writing out_size-byte elements.
+pub fn dx_bytes_moved(rows: usize, cols: usize, pad: usize, out_size: usize) -> usize {
+    return rows * cols * size_of::<u8>() + rows * (cols + pad) * out_size;
+}
+
+// Bytes moved by a vertical pass with pad rows of output padding.
+pub fn dy_bytes_moved(rows: usize, cols: usize, pad: usize, out_size: usize) -> usize {
+    return rows * cols * size_of::<u8>() + (rows + pad) * cols * out_size;
+}
+
+// dx_bytes_moved for a 4-bit input packed two elements per byte, see
+// rmm::nibble, so each row reads cols.div_ceil(2) bytes.
+pub fn dx_bytes_moved_u4(rows: usize, cols: usize, pad: usize, out_size: usize) -> usize {
+    return rows * cols.div_ceil(2) + rows * (cols + pad) * out_size;
+}
+
+// dy_bytes_moved for a packed 4-bit input.
+pub fn dy_bytes_moved_u4(rows: usize, cols: usize, pad: usize, out_size: usize) -> usize {
+    return rows * cols.div_ceil(2) + (rows + pad) * cols * out_size;
+}
+
+// Rates achieved by one timed kernel run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Throughput {
+    // Input elements processed per second.
+    pub elements_per_sec: f64,
+    // Estimated memory bandwidth in GB/s (10^9 bytes).
+    pub gb_per_sec: f64,
+}
+
+impl Throughput {
+    // Zero rates for a zero duration rather than infinities.
+    pub fn new(elements: usize, bytes: usize, duration: Duration) -> Throughput {
+        let seconds: f64 = duration.as_secs_f64();
+
+        if seconds == 0.0 {
+            return Throughput { elements_per_sec: 0.0, gb_per_sec: 0.0 };
+        }
+
+        return Throughput { elements_per_sec: elements as f64 / seconds, gb_per_sec: bytes as f64 / seconds / 1e9 };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_moved_match_the_documented_model() {
+        // 100x200 u8 input, i16 output with the 3-tap padding.
+        assert_eq!(dx_bytes_moved(100, 200, 2, 2), 100 * 200 + 2 * 100 * 202);
+        assert_eq!(dy_bytes_moved(100, 200, 2, 2), 100 * 200 + 2 * 102 * 200);
+        // Cropped f32 output.
+        assert_eq!(dx_bytes_moved(3, 5, 0, 4), 15 + 60);
+        assert_eq!(dy_bytes_moved(0, 5, 2, 4), 40);
+    }
+
+    #[test]
+    fn packed_inputs_read_half_a_byte_per_element() {
+        // Odd widths round the packed row up to a whole byte.
+        assert_eq!(dx_bytes_moved_u4(4, 7, 2, 2), 4 * 4 + 4 * 9 * 2);
+        assert_eq!(dy_bytes_moved_u4(4, 7, 2, 2), 4 * 4 + 6 * 7 * 2);
+        assert_eq!(dx_bytes_moved_u4(4, 8, 2, 2), dx_bytes_moved(4, 8, 2, 2) - 4 * 4);
+    }
+
+    #[test]
+    fn rates_are_per_second_and_in_gigabytes() {
+        let throughput: Throughput = Throughput::new(1_000_000, 3_000_000_000, Duration::from_millis(500));
+
+        assert_eq!(throughput, Throughput { elements_per_sec: 2_000_000.0, gb_per_sec: 6.0 });
+
+        let micro: Throughput = Throughput::new(250, 4000, Duration::from_micros(1));
+
+        assert!((micro.elements_per_sec - 2.5e8).abs() < 1e-3, "{:?}", micro);
+        assert!((micro.gb_per_sec - 4.0).abs() < 1e-12, "{:?}", micro);
+    }
+
+    #[test]
+    fn a_zero_duration_gives_zero_rates() {
+        assert_eq!(Throughput::new(10, 20, Duration::ZERO), Throughput { elements_per_sec: 0.0, gb_per_sec: 0.0 });
+    }
+}
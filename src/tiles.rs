@@ -0,0 +1,221 @@
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use crate::error::{check_len, DimError};
+use crate::index::{flat, row_range};
+
+// Elements of context a tile carries on every side: the reach of the 3-tap
+// [-1, 0, 1] kernels.
+pub const HALO: usize = 1;
+
+// One tile of a matrix as process_tiles hands it out: rows row..row + rows
+// and columns col..col + cols, plus HALO elements of context on every side.
+// Context outside the matrix is 0, the padding the kernels assume.
+pub struct Tile {
+    pub row: usize,
+    pub col: usize,
+    pub rows: usize,
+    pub cols: usize,
+    // (rows + 2 * HALO) x (cols + 2 * HALO), row-major.
+    halo: Vec<u8>,
+}
+
+impl Tile {
+    // Width of the tile with its halo.
+    fn stride(&self) -> usize {
+        return self.cols + 2 * HALO;
+    }
+
+    // Element (row, col) of the tile, counted from its top-left element;
+    // -1 and rows (or cols) reach into the halo.
+    pub fn get(&self, row: isize, col: isize) -> u8 {
+        let (r, c): (isize, isize) = (row + HALO as isize, col + HALO as isize);
+
+        assert!(r >= 0 && c >= 0 && (r as usize) < self.rows + 2 * HALO && (c as usize) < self.stride(),
+                "({}, {}) outside the {}x{} tile and its halo", row, col, self.rows, self.cols);
+
+        return self.halo[flat(r as usize, c as usize, self.stride())];
+    }
+
+    // Dx of the tile's elements, rows x cols: element (r, c) is the one
+    // compute_dx gives the whole matrix for input element (row + r, col + c),
+    // i.e. the tile's part of crop_dx_padding.
+    pub fn compute_dx(&self) -> Vec<i16> {
+        let stride: usize = self.stride();
+        let mut dx: Vec<i16> = Vec::with_capacity(self.rows * self.cols);
+
+        for r in 0..self.rows {
+            let line: &[u8] = &self.halo[row_range(r + HALO, stride)];
+            dx.extend(line.windows(3).map(|window| window[0] as i16 - window[2] as i16));
+        }
+
+        return dx;
+    }
+
+    // Dy of the tile's elements, rows x cols, the tile's part of
+    // crop_dy_padding.
+    pub fn compute_dy(&self) -> Vec<i16> {
+        let stride: usize = self.stride();
+        let mut dy: Vec<i16> = Vec::with_capacity(self.rows * self.cols);
+
+        for r in 0..self.rows {
+            let above: &[u8] = &self.halo[flat(r, HALO, stride)..flat(r, HALO + self.cols, stride)];
+            let below: &[u8] = &self.halo[flat(r + 2, HALO, stride)..flat(r + 2, HALO + self.cols, stride)];
+            dy.extend(above.iter().zip(below).map(|(&a, &b)| a as i16 - b as i16));
+        }
+
+        return dy;
+    }
+}
+
+// Walks a rows x cols matrix in tiles of tile_rows x tile_cols (smaller along
+// the bottom and right edges), row of tiles by row of tiles, calling f with
+// each tile and its halo and collecting the results in that order. Only one
+// tile and its halo are held besides arr, so working memory is bounded by the
+// tile size whatever the matrix size. Results computed from the halo, such
+// as Tile::compute_dx, are identical to the monolithic computation, so
+// stitching them back together with from_blocks (or hconcat and vconcat)
+// reproduces it exactly.
+pub fn process_tiles<T>(arr: &[u8], rows: usize, cols: usize, tile_rows: usize, tile_cols: usize,
+                        mut f: impl FnMut(&Tile) -> T) -> Result<Vec<T>, DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    if tile_rows == 0 || tile_cols == 0 {
+        return Err(DimError::Mismatch { what: "tile size", expected: 1, found: 0 });
+    }
+
+    let mut results: Vec<T> = Vec::with_capacity(rows.div_ceil(tile_rows) * cols.div_ceil(tile_cols));
+    let mut tile: Tile = Tile { row: 0, col: 0, rows: 0, cols: 0, halo: Vec::with_capacity((tile_rows + 2 * HALO) * (tile_cols + 2 * HALO)) };
+
+    for row in (0..rows).step_by(tile_rows) {
+        for col in (0..cols).step_by(tile_cols) {
+            (tile.row, tile.col) = (row, col);
+            (tile.rows, tile.cols) = (tile_rows.min(rows - row), tile_cols.min(cols - col));
+            fill_halo(arr, rows, cols, &mut tile);
+            results.push(f(&tile));
+        }
+    }
+
+    return Ok(results);
+}
+
+// Copies the tile and its halo out of arr, with zeros outside the matrix.
+fn fill_halo(arr: &[u8], rows: usize, cols: usize, tile: &mut Tile) {
+    let stride: usize = tile.stride();
+
+    tile.halo.clear();
+    tile.halo.resize((tile.rows + 2 * HALO) * stride, 0);
+
+    // Matrix columns first..last land at halo column first + HALO - tile.col.
+    let first: usize = tile.col.saturating_sub(HALO);
+    let last: usize = (tile.col + tile.cols + HALO).min(cols);
+    let offset: usize = first + HALO - tile.col;
+
+    for r in 0..tile.rows + 2 * HALO {
+        let Some(row) = (tile.row + r).checked_sub(HALO).filter(|&row| row < rows) else { continue };
+
+        tile.halo[flat(r, offset, stride)..flat(r, offset + last - first, stride)].copy_from_slice(&arr[flat(row, first, cols)..flat(row, last, cols)]);
+    }
+}
+
+// Time one horizontal strip took in each kernel, see time_strips.
+pub struct StripTiming {
+    // The strip's first row and its number of rows.
+    pub row: usize,
+    pub rows: usize,
+    pub dx: Duration,
+    pub dy: Duration,
+}
+
+// Walks a rows x cols matrix in horizontal strips of strip_rows rows (fewer
+// in the last one) with process_tiles, timing Tile::compute_dx and
+// Tile::compute_dy on each. Only the kernels are timed, not copying the strip
+// and its halo out of arr.
+pub fn time_strips(arr: &[u8], rows: usize, cols: usize, strip_rows: usize) -> Result<Vec<StripTiming>, DimError> {
+    return process_tiles(arr, rows, cols, strip_rows, cols.max(1), |tile| {
+        let start: Instant = Instant::now();
+        black_box(tile.compute_dx());
+        let dx: Duration = start.elapsed();
+
+        let start: Instant = Instant::now();
+        black_box(tile.compute_dy());
+        let dy: Duration = start.elapsed();
+
+        StripTiming { row: tile.row, rows: tile.rows, dx, dy }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernels::{compute_dx, compute_dy};
+    use crate::matrix::construct_randomized_matrix_seeded;
+    use crate::ops::{crop_dx_padding, crop_dy_padding, from_blocks};
+
+    // The per-tile results of f stitched back into one rows x cols matrix.
+    fn stitched(arr: &[u8], rows: usize, cols: usize, tile_rows: usize, tile_cols: usize, f: fn(&Tile) -> Vec<i16>) -> Vec<i16> {
+        let tiles: Vec<(Vec<i16>, usize, usize)> = process_tiles(arr, rows, cols, tile_rows, tile_cols, |tile| (f(tile), tile.rows, tile.cols)).unwrap();
+        let per_row: usize = cols.div_ceil(tile_cols).max(1);
+        let parts: Vec<Vec<(&[i16], usize, usize)>> = tiles.chunks(per_row)
+            .map(|row| row.iter().map(|(data, tile_rows, tile_cols)| (&data[..], *tile_rows, *tile_cols)).collect()).collect();
+
+        return from_blocks(&parts.iter().map(|row| &row[..]).collect::<Vec<&[(&[i16], usize, usize)]>>()).unwrap();
+    }
+
+    #[test]
+    fn stitched_tiles_equal_the_full_kernels() {
+        for (rows, cols) in [(1, 1), (7, 5), (16, 16), (23, 37)] {
+            let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, (rows * 31 + cols) as u64);
+            let dx: Vec<i16> = crop_dx_padding(&compute_dx(&arr, rows, cols).data, rows, cols).unwrap();
+            let dy: Vec<i16> = crop_dy_padding(&compute_dy(&arr, rows, cols).data, rows, cols).unwrap();
+
+            // Tiles that divide the matrix, that do not, single elements and
+            // tiles larger than the whole matrix.
+            for (tile_rows, tile_cols) in [(1, 1), (2, 3), (4, 4), (5, 7), (8, 16), (100, 100)] {
+                assert_eq!(stitched(&arr, rows, cols, tile_rows, tile_cols, Tile::compute_dx), dx, "Dx {}x{} in {}x{} tiles", rows, cols, tile_rows, tile_cols);
+                assert_eq!(stitched(&arr, rows, cols, tile_rows, tile_cols, Tile::compute_dy), dy, "Dy {}x{} in {}x{} tiles", rows, cols, tile_rows, tile_cols);
+            }
+        }
+    }
+
+    #[test]
+    fn tiles_cover_the_matrix_with_a_zero_halo() {
+        let arr: Vec<u8> = (1..=20).collect();
+        let origins: Vec<(usize, usize, usize, usize)> = process_tiles(&arr, 4, 5, 3, 2, |tile| (tile.row, tile.col, tile.rows, tile.cols)).unwrap();
+
+        assert_eq!(origins, vec![(0, 0, 3, 2), (0, 2, 3, 2), (0, 4, 3, 1), (3, 0, 1, 2), (3, 2, 1, 2), (3, 4, 1, 1)]);
+
+        // The halo holds the neighbours inside the matrix and 0 outside it.
+        let corners: Vec<[u8; 4]> = process_tiles(&arr, 4, 5, 3, 2, |tile| {
+            [tile.get(-1, -1), tile.get(0, 0), tile.get(tile.rows as isize, tile.cols as isize), tile.get(-1, tile.cols as isize)]
+        }).unwrap();
+        assert_eq!(corners[0], [0, 1, 18, 0]);
+        assert_eq!(corners[4], [12, 18, 0, 15]);
+
+        assert!(process_tiles(&arr, 4, 5, 0, 2, |_| ()).is_err());
+        assert!(process_tiles(&arr, 4, 4, 2, 2, |_| ()).is_err());
+        assert_eq!(process_tiles(&[], 0, 0, 2, 2, |_| ()).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn strips_cover_every_row_once() {
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(10, 4, 3);
+        let strips: Vec<(usize, usize)> = time_strips(&arr, 10, 4, 4).unwrap().iter().map(|strip| (strip.row, strip.rows)).collect();
+
+        assert_eq!(strips, vec![(0, 4), (4, 4), (8, 2)]);
+    }
+
+    #[test]
+    fn strip_durations_add_up_to_most_of_the_pass() {
+        // Large enough to time: everything but copying out the strips and
+        // halos is in the strips, which is well over a quarter of the pass.
+        const SIZE: usize = 512;
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(SIZE, SIZE, 9);
+        let start: std::time::Instant = std::time::Instant::now();
+        let strips: Vec<StripTiming> = time_strips(&arr, SIZE, SIZE, 64).unwrap();
+        let total: std::time::Duration = start.elapsed();
+        let sum: std::time::Duration = strips.iter().map(|strip| strip.dx + strip.dy).sum();
+
+        assert_eq!(strips.len(), SIZE / 64);
+        assert!(sum <= total && sum >= total / 4, "strip durations sum to {:?} of a {:?} pass", sum, total);
+    }
+}
@@ -0,0 +1,59 @@
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "parallel")]
+use std::thread;
+use std::time::Duration;
+use rmm::error::CancelError;
+
+// Exit status of a run stopped by --timeout, the same as timeout(1) uses.
+pub const EXIT_TIMEOUT: i32 = 124;
+
+// How long cancelled work may take to notice the token before the process
+// is stopped anyway. Only the generation, default kernels and blocked matmul
+// check the token; this covers every other path.
+#[cfg(feature = "parallel")]
+const GRACE: Duration = Duration::from_secs(2);
+
+static CANCEL: AtomicBool = AtomicBool::new(false);
+static ARMED: AtomicBool = AtomicBool::new(false);
+
+// Sets the cancel token once timeout has elapsed, and exits with
+// EXIT_TIMEOUT if the run is still going GRACE later.
+#[cfg(feature = "parallel")]
+pub fn arm(timeout: Duration) {
+    ARMED.store(true, Ordering::Relaxed);
+
+    thread::spawn(move || {
+        thread::sleep(timeout);
+        CANCEL.store(true, Ordering::Relaxed);
+
+        thread::sleep(GRACE);
+        eprintln!("timed out after {:?}", timeout);
+        process::exit(EXIT_TIMEOUT);
+    });
+}
+
+// The watchdog is a thread, which a build without the parallel feature
+// must not spawn.
+#[cfg(not(feature = "parallel"))]
+pub fn arm(_timeout: Duration) {
+    panic!("--timeout is not available: this build has the parallel feature disabled and cannot run its watchdog thread");
+}
+
+// The cancel token to pass to cancellable operations, if --timeout is set.
+pub fn token() -> Option<&'static AtomicBool> {
+    return if ARMED.load(Ordering::Relaxed) { Some(&CANCEL) } else { None };
+}
+
+// Unwraps the result of a cancellable operation, exiting with EXIT_TIMEOUT
+// and a note on how far stage got if it was cancelled.
+pub fn or_exit<T>(result: Result<T, CancelError>, stage: &str) -> T {
+    return match result {
+        Ok(value) => value,
+        Err(err @ CancelError::Cancelled { .. }) => {
+            eprintln!("timed out during {}: {}", stage, err);
+            process::exit(EXIT_TIMEOUT);
+        }
+        Err(CancelError::Dim(err)) => panic!("{}: {}", stage, err),
+    };
+}
@@ -0,0 +1,312 @@
+use std::time::{Duration, Instant};
+use crate::pool::BufferPool;
+
+// How many untimed runs precede the measured ones.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Warmup {
+    // Discard exactly this many runs.
+    Fixed(usize),
+    // Keep running until the last samples are stable, see TimingConfig.
+    Auto,
+}
+
+impl Warmup {
+    // Parses "auto" or a number of runs.
+    pub fn parse(name: &str) -> Option<Warmup> {
+        return match name {
+            "auto" => Some(Warmup::Auto),
+            count => count.parse().ok().map(Warmup::Fixed),
+        };
+    }
+}
+
+// Window of samples checked for stability unless configured otherwise.
+pub const DEFAULT_WINDOW: usize = 5;
+
+// Coefficient of variation below which a window counts as steady.
+pub const DEFAULT_CV_THRESHOLD: f64 = 0.02;
+
+// Time after which adaptive warm-up gives up waiting for a steady state.
+pub const DEFAULT_MAX_TIME: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Debug)]
+pub struct TimingConfig {
+    pub warmup: Warmup,
+    // Measured runs after a fixed warm-up.
+    pub samples: usize,
+    // With Warmup::Auto, the loop stops once the last window samples have a
+    // coefficient of variation below cv_threshold, or after max_time. Those
+    // last window samples are the measured ones.
+    pub window: usize,
+    pub cv_threshold: f64,
+    pub max_time: Duration,
+}
+
+impl Default for TimingConfig {
+    // A single run without warm-up.
+    fn default() -> TimingConfig {
+        return TimingConfig {
+            warmup: Warmup::Fixed(0),
+            samples: 1,
+            window: DEFAULT_WINDOW,
+            cv_threshold: DEFAULT_CV_THRESHOLD,
+            max_time: DEFAULT_MAX_TIME,
+        };
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TimingReport {
+    // Measured durations, in the order they were taken.
+    pub samples: Vec<Duration>,
+    // Runs thrown away as warm-up.
+    pub discarded: usize,
+    // False if adaptive warm-up hit max_time before the samples settled.
+    pub steady: bool,
+}
+
+impl TimingReport {
+    pub fn median(&self) -> Duration {
+        let mut sorted: Vec<Duration> = self.samples.clone();
+        sorted.sort();
+
+        return sorted.get(sorted.len() / 2).copied().unwrap_or_default();
+    }
+
+    pub fn cv(&self) -> f64 {
+        return coefficient_of_variation(&self.samples);
+    }
+}
+
+// Standard deviation over mean of the samples, or 0 for an empty or all-zero
+// sequence.
+pub fn coefficient_of_variation(samples: &[Duration]) -> f64 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+
+    let values: Vec<f64> = samples.iter().map(|sample| sample.as_secs_f64()).collect();
+    let mean: f64 = values.iter().sum::<f64>() / values.len() as f64;
+
+    if mean == 0.0 {
+        return 0.0;
+    }
+
+    let variance: f64 = values.iter().map(|value| (value - mean) * (value - mean)).sum::<f64>() / values.len() as f64;
+
+    return variance.sqrt() / mean;
+}
+
+// Index of the first sample of the first window samples long run whose
+// coefficient of variation is below threshold, if there is one.
+pub fn steady_state(samples: &[Duration], window: usize, threshold: f64) -> Option<usize> {
+    let window: usize = window.max(1);
+
+    return samples.windows(window).position(|run| coefficient_of_variation(run) < threshold);
+}
+
+// Runs f according to config and returns its last result with the timings.
+pub fn measure<T>(config: &TimingConfig, f: impl FnMut() -> T) -> (T, TimingReport) {
+    return measure_prepared(config, || {}, f);
+}
+
+// measure with prepare run before every run of f, warm-up ones included, and
+// left out of its timing: e.g. to evict the input from the caches.
+pub fn measure_prepared<T>(config: &TimingConfig, mut prepare: impl FnMut(), mut f: impl FnMut() -> T) -> (T, TimingReport) {
+    // Sized up front for a fixed warm-up, so the timed runs do not allocate.
+    let mut samples: Vec<Duration> = Vec::with_capacity(match config.warmup {
+        Warmup::Fixed(warmup) => warmup + config.samples.max(1),
+        Warmup::Auto => config.window.max(1),
+    });
+    let mut result: Option<T> = None;
+    let mut timed = |samples: &mut Vec<Duration>| {
+        prepare();
+        let start = Instant::now();
+        let value: T = f();
+        samples.push(start.elapsed());
+        result = Some(value);
+    };
+
+    let report: TimingReport = match config.warmup {
+        Warmup::Fixed(warmup) => {
+            for _ in 0..warmup + config.samples.max(1) {
+                timed(&mut samples);
+            }
+
+            TimingReport { samples: samples.split_off(warmup), discarded: warmup, steady: true }
+        }
+        Warmup::Auto => {
+            let start = Instant::now();
+            let mut run = || {
+                timed(&mut samples);
+                *samples.last().expect("a run was just timed")
+            };
+
+            until_steady(config, &mut run, || start.elapsed())
+        }
+    };
+
+    return (result.expect("at least one run"), report);
+}
+
+// The adaptive warm-up loop: takes samples from run until the last window of
+// them has a coefficient of variation below cv_threshold, or until elapsed
+// reaches max_time after a sample. The last window samples are reported and
+// the earlier ones discarded. Kept apart from the clock so it can be driven
+// with synthetic samples.
+fn until_steady(config: &TimingConfig, mut run: impl FnMut() -> Duration, elapsed: impl Fn() -> Duration) -> TimingReport {
+    let window: usize = config.window.max(1);
+    let mut samples: Vec<Duration> = Vec::with_capacity(window);
+    let mut steady: bool = false;
+
+    while !steady {
+        samples.push(run());
+
+        if samples.len() >= window {
+            steady = coefficient_of_variation(&samples[samples.len() - window..]) < config.cv_threshold;
+        }
+
+        if elapsed() >= config.max_time {
+            break;
+        }
+    }
+
+    let discarded: usize = samples.len().saturating_sub(window);
+
+    return TimingReport { samples: samples.split_off(discarded), discarded, steady };
+}
+
+// measure for kernels writing into a buffer of len elements. Each run takes
+// its output from pool and the previous run's output is given back, so the
+// timed runs reuse two buffers instead of allocating one each. prepare is
+// run before each run as for measure_prepared.
+pub fn measure_pooled<T: Copy + Default>(config: &TimingConfig, pool: &mut BufferPool<T>, len: usize, prepare: impl FnMut(),
+                                         mut f: impl FnMut(&mut [T])) -> (Vec<T>, TimingReport) {
+    let mut last: Option<Vec<T>> = None;
+    let ((), report) = measure_prepared(config, prepare, || {
+        let mut out: Vec<T> = pool.take(len);
+        f(&mut out);
+
+        if let Some(previous) = last.replace(out) {
+            pool.give(previous);
+        }
+    });
+
+    return (last.expect("at least one run"), report);
+}
+
+// Parses a duration such as "10s", "250ms", "500us", "2m" or "1h". A bare number
+// is taken as seconds.
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let text: &str = text.trim();
+    let split: usize = text.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(text.len());
+    let value: f64 = text[..split].parse().ok()?;
+
+    let seconds: f64 = match &text[split..] {
+        "" | "s" => value,
+        "ms" => value / 1e3,
+        "us" => value / 1e6,
+        "m" => value * 60.0,
+        "h" => value * 3600.0,
+        _ => return None,
+    };
+
+    return Duration::try_from_secs_f64(seconds).ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn millis(values: &[u64]) -> Vec<Duration> {
+        return values.iter().map(|&value| Duration::from_millis(value)).collect();
+    }
+
+    // Feeds samples to until_steady one by one, with the elapsed time the sum
+    // of those taken so far.
+    fn drive(config: &TimingConfig, samples: &[Duration]) -> TimingReport {
+        let (taken, total): (Cell<usize>, Cell<Duration>) = (Cell::new(0), Cell::new(Duration::ZERO));
+        let run = || {
+            let sample: Duration = samples[taken.get()];
+            taken.set(taken.get() + 1);
+            total.set(total.get() + sample);
+            sample
+        };
+
+        return until_steady(config, run, || total.get());
+    }
+
+    fn auto(window: usize, max_time: Duration) -> TimingConfig {
+        return TimingConfig { warmup: Warmup::Auto, samples: window, window, cv_threshold: DEFAULT_CV_THRESHOLD, max_time };
+    }
+
+    #[test]
+    fn coefficient_of_variation_of_known_sequences() {
+        assert_eq!(coefficient_of_variation(&[]), 0.0);
+        assert_eq!(coefficient_of_variation(&millis(&[0, 0])), 0.0);
+        assert_eq!(coefficient_of_variation(&millis(&[7, 7, 7])), 0.0);
+        // Mean 2, population standard deviation 1.
+        assert!((coefficient_of_variation(&millis(&[1, 3, 1, 3])) - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn steady_state_finds_the_first_stable_window() {
+        let samples: Vec<Duration> = millis(&[90, 40, 25, 10, 10, 10, 10, 30]);
+
+        assert_eq!(steady_state(&samples, 3, 0.02), Some(3));
+        assert_eq!(steady_state(&samples, 5, 0.02), None);
+        assert_eq!(steady_state(&samples[..2], 3, 0.02), None);
+    }
+
+    #[test]
+    fn auto_warmup_discards_the_noisy_start() {
+        let samples: Vec<Duration> = millis(&[50, 30, 20, 12, 10, 10, 10, 10, 10, 999]);
+        let report: TimingReport = drive(&auto(4, Duration::from_secs(60)), &samples);
+
+        assert!(report.steady);
+        assert_eq!(report.discarded, 4);
+        assert_eq!(report.samples, millis(&[10, 10, 10, 10]));
+        assert_eq!(report.median(), Duration::from_millis(10));
+    }
+
+    #[test]
+    fn auto_warmup_gives_up_at_max_time() {
+        let samples: Vec<Duration> = millis(&[10, 20, 10, 20, 10, 20, 10, 20]);
+        let report: TimingReport = drive(&auto(3, Duration::from_millis(75)), &samples);
+
+        // 10 + 20 + 10 + 20 + 10 + 20 = 90 ms is the first total past 75 ms.
+        assert!(!report.steady);
+        assert_eq!(report.discarded, 3);
+        assert_eq!(report.samples, millis(&[20, 10, 20]));
+    }
+
+    #[test]
+    fn auto_warmup_reports_a_short_run_whole() {
+        let report: TimingReport = drive(&auto(5, Duration::ZERO), &millis(&[10, 80]));
+
+        assert_eq!((report.discarded, report.samples.len(), report.steady), (0, 1, false));
+    }
+
+    #[test]
+    fn fixed_warmup_discards_exactly_k_runs() {
+        let mut runs: usize = 0;
+        let config: TimingConfig = TimingConfig { warmup: Warmup::Fixed(3), samples: 4, ..TimingConfig::default() };
+        let (last, report) = measure(&config, || { runs += 1; runs });
+
+        assert_eq!((last, runs), (7, 7));
+        assert_eq!((report.discarded, report.samples.len(), report.steady), (3, 4, true));
+    }
+
+    #[test]
+    fn parse_warmup_and_durations() {
+        assert_eq!(Warmup::parse("auto"), Some(Warmup::Auto));
+        assert_eq!(Warmup::parse("3"), Some(Warmup::Fixed(3)));
+        assert_eq!(Warmup::parse("-1"), None);
+        assert_eq!(parse_duration("10s"), Some(Duration::from_secs(10)));
+        assert_eq!(parse_duration("250ms"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_duration("1.5"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("2m"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_duration("5 parsecs"), None);
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::VecDeque;
+use crate::error::{check_len, DimError};
+
+// Which window positions window_map visits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WindowMode {
+    // Only windows that lie entirely inside the matrix. The output is
+    // (rows - wrows + 1) x (cols - wcols + 1), or empty if the window is
+    // larger than the matrix.
+    #[default]
+    Valid,
+    // One window per element, with the element at offset (wrows / 2,
+    // wcols / 2) inside it. The output is rows x cols and windows overlapping
+    // the border see None for the elements outside the matrix.
+    Padded,
+}
+
+// A wrows x wcols window onto a matrix, passed to the window_map closure.
+// It borrows the matrix, so creating one for every position allocates
+// nothing.
+pub struct WindowView<'a, T> {
+    arr: &'a [T],
+    rows: usize,
+    cols: usize,
+    // Matrix coordinates of the window's top-left element, which can be
+    // negative in padded mode.
+    row0: isize,
+    col0: isize,
+    wrows: usize,
+    wcols: usize,
+}
+
+impl<'a, T: Copy> WindowView<'a, T> {
+    pub fn rows(&self) -> usize {
+        return self.wrows;
+    }
+
+    pub fn cols(&self) -> usize {
+        return self.wcols;
+    }
+
+    // Element (row, col) of the window, or None if it lies outside the
+    // matrix. Panics if (row, col) is outside the window.
+    #[inline]
+    pub fn get(&self, row: usize, col: usize) -> Option<T> {
+        assert!(row < self.wrows && col < self.wcols, "({}, {}) is outside the {}x{} window", row, col, self.wrows, self.wcols);
+
+        let r: isize = self.row0 + row as isize;
+        let c: isize = self.col0 + col as isize;
+
+        if r < 0 || c < 0 || r as usize >= self.rows || c as usize >= self.cols {
+            return None;
+        }
+
+        return Some(self.arr[r as usize * self.cols + c as usize]);
+    }
+
+    // The elements of the window that lie inside the matrix, row by row.
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        return (0..self.wrows).flat_map(move |row| (0..self.wcols).filter_map(move |col| self.get(row, col)));
+    }
+}
+
+// Calls f for every wrows x wcols window position of a rows x cols matrix
+// and collects the results in row-major order. This is the generic fallback
+// for local operations that have no dedicated kernel, e.g. a local standard
+// deviation; see WindowMode for the positions and output size.
+pub fn window_map<T: Copy, O>(arr: &[T], rows: usize, cols: usize, wrows: usize, wcols: usize, mode: WindowMode,
+                              f: impl Fn(&WindowView<T>) -> O) -> Result<Vec<O>, DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    if wrows == 0 || wcols == 0 {
+        return Err(DimError::Mismatch { what: "window size", expected: 1, found: 0 });
+    }
+
+    let (out_rows, out_cols, offset_rows, offset_cols) = match mode {
+        WindowMode::Valid => ((rows + 1).saturating_sub(wrows), (cols + 1).saturating_sub(wcols), 0, 0),
+        WindowMode::Padded => (rows, cols, (wrows / 2) as isize, (wcols / 2) as isize),
+    };
+
+    let mut out: Vec<O> = Vec::with_capacity(out_rows * out_cols);
+
+    for row in 0..out_rows {
+        for col in 0..out_cols {
+            let view: WindowView<T> = WindowView {
+                arr, rows, cols,
+                row0: row as isize - offset_rows,
+                col0: col as isize - offset_cols,
+                wrows, wcols,
+            };
+
+            out.push(f(&view));
+        }
+    }
+
+    return Ok(out);
+}
+
+// Maximum of every window of `window` consecutive elements along each row,
+// rows x cols like the input. The window of column col is centred on it
+// (starting window / 2 elements to its left) and shifted to stay inside the
+// row at the ends, so a window of 1 returns the input and one of cols or
+// more returns each row's maximum in every column. A monotonic deque makes
+// the cost linear in the size of the matrix whatever the window.
+pub fn rolling_max_rows<T: Copy + PartialOrd>(arr: &[T], rows: usize, cols: usize, window: usize) -> Result<Vec<T>, DimError> {
+    return rolling_rows(arr, rows, cols, window, |kept, new| kept <= new);
+}
+
+// Minimum of every window along each row; see rolling_max_rows.
+pub fn rolling_min_rows<T: Copy + PartialOrd>(arr: &[T], rows: usize, cols: usize, window: usize) -> Result<Vec<T>, DimError> {
+    return rolling_rows(arr, rows, cols, window, |kept, new| kept >= new);
+}
+
+// Rolling extreme along each row, where beaten(kept, new) says that new
+// makes kept useless for every window still to come.
+fn rolling_rows<T: Copy + PartialOrd>(arr: &[T], rows: usize, cols: usize, window: usize,
+                                      beaten: impl Fn(T, T) -> bool) -> Result<Vec<T>, DimError> {
+    check_len(arr.len(), rows, cols)?;
+
+    if window == 0 {
+        return Err(DimError::Mismatch { what: "window size", expected: 1, found: 0 });
+    }
+
+    let window: usize = window.min(cols);
+    let mut out: Vec<T> = Vec::with_capacity(rows * cols);
+    // Extremes of the windows starting at 0..=cols - window of one row.
+    let mut extremes: Vec<T> = Vec::with_capacity((cols + 1).saturating_sub(window));
+    // Columns of the current window that can still be its extreme, with
+    // their values in decreasing order of preference.
+    let mut deque: VecDeque<usize> = VecDeque::with_capacity(window);
+
+    for line in arr.chunks_exact(cols.max(1)).take(rows) {
+        extremes.clear();
+        deque.clear();
+
+        for (col, &value) in line.iter().enumerate() {
+            while deque.back().is_some_and(|&kept| beaten(line[kept], value)) {
+                deque.pop_back();
+            }
+
+            deque.push_back(col);
+
+            if deque.front().is_some_and(|&front| front + window <= col) {
+                deque.pop_front();
+            }
+
+            if col + 1 >= window {
+                extremes.push(line[deque[0]]);
+            }
+        }
+
+        out.extend((0..cols).map(|col| extremes[col.saturating_sub(window / 2).min(cols - window)]));
+    }
+
+    return Ok(out);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arith::ArithPolicy;
+    use crate::conv::{convolve_cols, convolve_rows};
+    use crate::matrix::construct_randomized_matrix_seeded;
+
+    // Weighted sum of a window, weights[i][j] for element (i, j), with the
+    // elements outside the matrix counting as 0 like the kernels' padding.
+    fn weighted_sum(view: &WindowView<u8>, weights: &[i32]) -> i32 {
+        return (0..view.rows()).flat_map(|row| (0..view.cols()).map(move |col| (row, col)))
+            .map(|(row, col)| weights[row * view.cols() + col] * view.get(row, col).map_or(0, |value| value as i32)).sum();
+    }
+
+    #[test]
+    fn a_sum_functor_matches_convolve_rows() {
+        let (rows, cols): (usize, usize) = (6, 11);
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, 5);
+
+        // The convolution flips the kernel, the window does not.
+        for kernel in [vec![1, 1, 1], vec![1, 2, 1], vec![2, -1, 0, 5], vec![4]] {
+            let len: usize = kernel.len();
+            let reversed: Vec<i32> = kernel.iter().rev().copied().collect();
+            let full: Vec<i32> = convolve_rows(&arr, rows, cols, &kernel, ArithPolicy::Checked).unwrap();
+            let full_cols: usize = cols + len - 1;
+
+            // Full output column j covers input columns j - len + 1..=j: the
+            // valid window starting at c is column c + len - 1, the padded
+            // one starting at c - len / 2 is column c + len - 1 - len / 2.
+            let valid: Vec<i32> = window_map(&arr, rows, cols, 1, len, WindowMode::Valid, |view| weighted_sum(view, &reversed)).unwrap();
+            let expected: Vec<i32> = full.chunks(full_cols).flat_map(|row| row[len - 1..cols].to_vec()).collect();
+            assert_eq!(valid, expected, "{:?}", kernel);
+
+            let padded: Vec<i32> = window_map(&arr, rows, cols, 1, len, WindowMode::Padded, |view| weighted_sum(view, &reversed)).unwrap();
+            let offset: usize = len - 1 - len / 2;
+            let expected: Vec<i32> = full.chunks(full_cols).flat_map(|row| row[offset..offset + cols].to_vec()).collect();
+            assert_eq!(padded, expected, "{:?}", kernel);
+        }
+
+        // And down the columns.
+        let full: Vec<i32> = convolve_cols(&arr, rows, cols, &[1, 1, 1], ArithPolicy::Checked).unwrap();
+        let valid: Vec<i32> = window_map(&arr, rows, cols, 3, 1, WindowMode::Valid, |view| view.iter().map(|value| value as i32).sum()).unwrap();
+        assert_eq!(valid, full[2 * cols..rows * cols].to_vec());
+    }
+
+    #[test]
+    fn window_shapes_and_sizes() {
+        let arr: Vec<u8> = (0..12).collect();
+
+        assert_eq!(window_map(&arr, 3, 4, 2, 3, WindowMode::Valid, |view| view.get(0, 0).unwrap()).unwrap(), vec![0, 1, 4, 5]);
+        assert_eq!(window_map(&arr, 3, 4, 4, 1, WindowMode::Valid, |_| 0).unwrap(), Vec::<i32>::new());
+        assert_eq!(window_map(&arr, 3, 4, 3, 3, WindowMode::Padded, |view| view.iter().count()).unwrap(), vec![4, 6, 6, 4, 6, 9, 9, 6, 4, 6, 6, 4]);
+        assert!(window_map(&arr, 3, 4, 0, 1, WindowMode::Valid, |_| 0).is_err());
+        assert!(window_map(&arr, 3, 3, 1, 1, WindowMode::Valid, |_| 0).is_err());
+    }
+
+    // The rolling extreme by scanning every window, placed as
+    // rolling_max_rows places it.
+    fn naive_rolling(arr: &[i16], cols: usize, window: usize, pick: fn(i16, i16) -> i16) -> Vec<i16> {
+        let window: usize = window.min(cols);
+
+        return arr.chunks(cols).flat_map(|line| (0..cols).map(move |col| {
+            let start: usize = col.saturating_sub(window / 2).min(cols - window);
+            line[start..start + window].iter().copied().reduce(pick).unwrap()
+        })).collect();
+    }
+
+    #[test]
+    fn rolling_extremes_match_a_naive_scan() {
+        let (rows, cols): (usize, usize) = (5, 23);
+        let arr: Vec<i16> = construct_randomized_matrix_seeded(rows, cols, 17).iter().map(|&value| value as i16 % 13 - 6).collect();
+
+        for window in [1, 2, 3, 4, 7, cols - 1, cols, cols + 1, 5 * cols] {
+            assert_eq!(rolling_max_rows(&arr, rows, cols, window).unwrap(), naive_rolling(&arr, cols, window, i16::max), "max of {}", window);
+            assert_eq!(rolling_min_rows(&arr, rows, cols, window).unwrap(), naive_rolling(&arr, cols, window, i16::min), "min of {}", window);
+        }
+
+        // A window of 1 is the input and one of at least cols the row extreme.
+        assert_eq!(rolling_max_rows(&arr, rows, cols, 1).unwrap(), arr);
+        let maxima: Vec<i16> = arr.chunks(cols).flat_map(|line| vec![*line.iter().max().unwrap(); cols]).collect();
+        assert_eq!(rolling_max_rows(&arr, rows, cols, cols).unwrap(), maxima);
+        assert_eq!(rolling_max_rows(&arr, rows, cols, cols + 10).unwrap(), maxima);
+    }
+
+    #[test]
+    fn rolling_extremes_of_edge_shapes() {
+        assert_eq!(rolling_min_rows(&[3, 1, 2], 1, 3, 2).unwrap(), vec![1, 1, 1]);
+        assert_eq!(rolling_max_rows(&[3, 1, 2], 3, 1, 4).unwrap(), vec![3, 1, 2]);
+        assert_eq!(rolling_max_rows::<i16>(&[], 0, 4, 2).unwrap(), vec![]);
+        assert_eq!(rolling_max_rows::<i16>(&[], 3, 0, 2).unwrap(), vec![]);
+        assert_eq!(rolling_max_rows(&[1, 2], 1, 2, 0), Err(DimError::Mismatch { what: "window size", expected: 1, found: 0 }));
+        assert!(rolling_min_rows(&[1, 2, 3], 2, 2, 1).is_err());
+    }
+}
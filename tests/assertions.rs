@@ -0,0 +1,96 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{run, scratch};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Output;
+
+fn stdout(output: &Output) -> String {
+    return String::from_utf8_lossy(&output.stdout).into_owned();
+}
+
+fn stderr(output: &Output) -> String {
+    return String::from_utf8_lossy(&output.stderr).into_owned();
+}
+
+// Dx and Dy of a seeded 16x16 matrix, run with extra.
+fn assert_run(extra: &[&str]) -> Output {
+    let mut args: Vec<&str> = vec!["16", "16", "--seed", "1"];
+    args.extend_from_slice(extra);
+
+    return run(&args);
+}
+
+#[test]
+fn passing_bounds_exit_zero() {
+    let output: Output = assert_run(&["--assert-min-max", "dx:-255:255", "dy:-255:255"]);
+
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Assertions passed: dx:-255:255, dy:-255:255\n"), "{}", stdout(&output));
+}
+
+#[test]
+fn violated_bounds_exit_one_naming_each() {
+    let output: Output = assert_run(&["--assert-min-max", "dx:-10:10", "dy:-255:255,dy:0:0"]);
+    let stderr: String = stderr(&output);
+
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    // The later dy bound replaced the earlier one.
+    assert!(stderr.contains("assertion failed: dx:-10:10: expected min/max within -10..=10, observed "), "{}", stderr);
+    assert!(stderr.contains("assertion failed: dy:0:0: expected min/max within 0..=0, observed "), "{}", stderr);
+    assert!(!stdout(&output).contains("Assertions passed"));
+}
+
+#[test]
+fn checksums_are_compared() {
+    let wrong: Output = assert_run(&["--assert-checksum", "0"]);
+    let message: String = stderr(&wrong);
+
+    assert_eq!(wrong.status.code(), Some(1), "{}", message);
+    let observed: &str = message.split("observed ").nth(1).expect("the observed checksum").trim();
+    assert!(message.contains("assertion failed: checksum: expected 0000000000000000, observed "), "{}", message);
+
+    // The observed checksum passes, with or without 0x.
+    for checksum in [observed.to_string(), format!("0x{}", observed)] {
+        let output: Output = assert_run(&["--assert-checksum", &checksum]);
+
+        assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+        assert!(stdout(&output).contains(&format!("Assertions passed: checksum {}", observed)), "{}", stdout(&output));
+    }
+
+    // A different seed gives a different checksum.
+    assert_eq!(run(&["16", "16", "--seed", "2", "--assert-checksum", observed]).status.code(), Some(1));
+}
+
+#[test]
+fn config_assertions_can_be_overridden() {
+    let dir: PathBuf = scratch("assertions-config");
+    let config: PathBuf = dir.join("run.toml");
+    fs::write(&config, "rows = 16\ncols = 16\nseed = 1\nassert-min-max = [\"dx:-1:1\", \"dy:-255:255\"]\n").unwrap();
+    let config: &str = config.to_str().unwrap();
+
+    let failing: Output = run(&["--config", config]);
+    assert_eq!(failing.status.code(), Some(1));
+    assert!(stderr(&failing).contains("assertion failed: dx:-1:1: "), "{}", stderr(&failing));
+
+    let overridden: Output = run(&["--config", config, "--assert-min-max", "dx:-300:300"]);
+    assert_eq!(overridden.status.code(), Some(0), "{}", stderr(&overridden));
+    assert!(stdout(&overridden).contains("Assertions passed: dy:-255:255, dx:-300:300\n"), "{}", stdout(&overridden));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn malformed_assertions_are_refused() {
+    for (args, message) in [(["--assert-min-max", "dx:5"], "Invalid --assert-min-max dx:5, expected dx:MIN:MAX or dy:MIN:MAX"),
+                            (["--assert-min-max", "dz:0:1"], "Invalid --assert-min-max dz:0:1"),
+                            (["--assert-checksum", "xyz"], "Invalid --assert-checksum xyz, expected a hex checksum")] {
+        let output: Output = assert_run(&args);
+
+        assert!(!output.status.success(), "{:?}", args);
+        assert_ne!(output.status.code(), Some(1), "{:?} must not look like a failed assertion", args);
+        assert!(stderr(&output).contains(message), "{}", stderr(&output));
+    }
+}
@@ -0,0 +1,88 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+#[cfg(not(feature = "parallel"))]
+use common::run;
+use common::{run_ok, scratch};
+use rmm::io::{read_matrix, StoredMatrix};
+use rmm::kernels::{compute_dx, compute_dy};
+use rmm::matrix::construct_randomized_matrix_seeded;
+use std::fs;
+use std::path::{Path, PathBuf};
+#[cfg(not(feature = "parallel"))]
+use std::process::Output;
+
+// Runs a batch of count seeded 6x9 matrices into dir, with extra flags, and
+// returns the names of the files it wrote, sorted, and its stdout.
+fn batch(dir: &Path, count: usize, extra: &[&str]) -> (Vec<String>, String) {
+    let count: String = count.to_string();
+    let mut args: Vec<&str> = vec!["batch", "6", "9", "--count", &count, "--seed", "40", "--out-dir", dir.to_str().unwrap()];
+    args.extend_from_slice(extra);
+
+    let stdout: String = String::from_utf8_lossy(&run_ok(&args).stdout).into_owned();
+    let mut names: Vec<String> = fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned()).collect();
+    names.sort();
+
+    return (names, stdout);
+}
+
+fn values(path: &Path) -> (usize, usize, Vec<i64>) {
+    let stored: StoredMatrix = read_matrix(path).unwrap();
+
+    return (stored.rows, stored.cols, stored.values);
+}
+
+#[test]
+fn each_matrix_gets_the_gradients_of_its_seed() {
+    let dir: PathBuf = scratch("batch-sequential");
+    let (names, stdout) = batch(&dir, 3, &[]);
+
+    assert_eq!(names, ["0_dx.bin", "0_dy.bin", "1_dx.bin", "1_dy.bin", "2_dx.bin", "2_dy.bin"]);
+    assert!(stdout.contains("=== Batch 3 x 6x9"), "{}", stdout);
+    assert!(stdout.contains("(1 worker)"), "{}", stdout);
+
+    for index in 0..3 {
+        // Matrix i is generated from seed + i.
+        let input: Vec<u8> = construct_randomized_matrix_seeded(6, 9, 40 + index as u64);
+        let (dx, dy) = (compute_dx(&input, 6, 9), compute_dy(&input, 6, 9));
+
+        assert_eq!(values(&dir.join(format!("{}_dx.bin", index))), (6, 11, dx.data.iter().map(|&v| v as i64).collect()));
+        assert_eq!(values(&dir.join(format!("{}_dy.bin", index))), (8, 9, dy.data.iter().map(|&v| v as i64).collect()));
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn pipelined_batches_write_the_sequential_bytes() {
+    let dir: PathBuf = scratch("batch-pipelined");
+    let (sequential, _) = batch(&dir.join("sequential"), 12, &[]);
+
+    for workers in ["1", "3"] {
+        let out: PathBuf = dir.join(workers);
+        let (names, stdout) = batch(&out, 12, &["--pipeline", "--workers", workers]);
+
+        assert_eq!(names, sequential);
+        assert!(stdout.contains(&format!("({} worker", workers)), "{}", stdout);
+
+        for name in &names {
+            assert!(fs::read(out.join(name)).unwrap() == fs::read(dir.join("sequential").join(name)).unwrap(), "{} with {} workers", name, workers);
+        }
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(not(feature = "parallel"))]
+#[test]
+fn pipeline_is_refused_in_a_serial_build() {
+    let dir: PathBuf = scratch("batch-serial");
+    let output: Output = run(&["batch", "6", "9", "--count", "2", "--out-dir", dir.to_str().unwrap(), "--pipeline"]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--pipeline is not available"), "{}", String::from_utf8_lossy(&output.stderr));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,24 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::run_ok;
+use std::process::Output;
+
+// bench const on shapes the fixed-size paths and the generic loops take: every
+// variant is timed and agrees with the kernels, or the run exits 1.
+#[test]
+fn const_variants_agree_with_the_kernels() {
+    for dims in [&["7"][..], &["5", "300"][..]] {
+        let mut args: Vec<&str> = vec!["bench", "const"];
+        args.extend_from_slice(dims);
+        args.extend_from_slice(&["--iterations", "2"]);
+        let output: Output = run_ok(&args);
+        let stdout: String = String::from_utf8_lossy(&output.stdout).into_owned();
+
+        for variant in ["dx-const", "dx-slice", "dy-const", "dy-slice"] {
+            assert!(stdout.lines().any(|line| line.starts_with(&format!("{} ", variant))), "{}", stdout);
+        }
+        assert!(stdout.contains("All results match dx\n") && stdout.contains("All results match dy\n"), "{}", stdout);
+    }
+}
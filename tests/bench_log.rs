@@ -0,0 +1,47 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{run_ok, scratch};
+use std::fs;
+use std::path::PathBuf;
+
+// Two bench runs appending to one log: a header once, then a row per kernel
+// per run, all with the header's columns.
+#[test]
+fn bench_log_appends_one_row_per_kernel_per_run() {
+    let dir: PathBuf = scratch("bench-log");
+    let log: PathBuf = dir.join("results.csv");
+    let args: [&str; 7] = ["bench", "12", "9", "--iterations", "2", "--log", log.to_str().unwrap()];
+
+    run_ok(&args);
+    let first: String = fs::read_to_string(&log).unwrap();
+    run_ok(&args);
+    let text: String = fs::read_to_string(&log).unwrap();
+
+    assert!(text.starts_with(&first), "the second run rewrote the log");
+
+    let lines: Vec<&str> = text.lines().collect();
+    let header: Vec<&str> = lines[0].split(',').collect();
+    assert_eq!(header[..3], ["timestamp", "version", "rows"]);
+    assert_eq!(lines.iter().filter(|line| line.starts_with("timestamp,")).count(), 1);
+
+    let rows: Vec<Vec<&str>> = lines[1..].iter().map(|line| line.split(',').collect()).collect();
+    assert_eq!(rows.len(), 4);
+
+    let column = |name: &str| header.iter().position(|field| *field == name).unwrap();
+
+    for row in &rows {
+        assert_eq!(row.len(), header.len(), "{:?}", row);
+        assert_eq!((row[column("rows")], row[column("cols")]), ("12", "9"));
+        assert_eq!(row[column("version")], env!("CARGO_PKG_VERSION"));
+        assert!(row[column("median_ns")].parse::<u64>().is_ok());
+    }
+
+    // The same input and kernel give the same checksum on both runs.
+    let kernels: Vec<(&str, &str)> = rows.iter().map(|row| (row[column("kernel")], row[column("checksum")])).collect();
+    assert_eq!(kernels[..2], kernels[2..]);
+    assert_eq!((kernels[0].0, kernels[1].0), ("dx", "dy"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,31 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{run, run_ok};
+use std::process::Output;
+
+// Exit status of a run refused for its memory estimate.
+const EXIT_OVER_BUDGET: i32 = 3;
+
+// Whether or not the build can detect the system memory, a run far beyond it
+// is refused before anything is allocated.
+#[test]
+fn a_huge_run_is_refused_in_every_build() {
+    let output: Output = run(&["200000", "200000"]);
+    let stderr: String = String::from_utf8_lossy(&output.stderr).into_owned();
+
+    assert_eq!(output.status.code(), Some(EXIT_OVER_BUDGET), "{}", stderr);
+    assert!(stderr.contains("Refusing to run on 200000x200000: it needs an estimated 335.3 GiB (input 37.3 GiB, "), "{}", stderr);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Memory estimate: 335.3 GiB (limit "));
+}
+
+#[test]
+fn max_memory_sets_the_limit() {
+    let refused: Output = run(&["64", "64", "--max-memory", "1K"]);
+    assert_eq!(refused.status.code(), Some(EXIT_OVER_BUDGET));
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("more than the 1.0 KiB limit (--max-memory)"), "{}", String::from_utf8_lossy(&refused.stderr));
+
+    let output: Output = run_ok(&["64", "64", "--max-memory", "1M"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("(limit 1.0 MiB, --max-memory)"), "{}", String::from_utf8_lossy(&output.stdout));
+}
@@ -0,0 +1,40 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{run, run_ok, scratch};
+use std::fs;
+use std::path::PathBuf;
+
+#[test]
+fn both_cache_states_are_timed_and_leave_the_output_alone() {
+    let (both, plain): (PathBuf, PathBuf) = (scratch("cache-state-both"), scratch("cache-state-plain"));
+    let stdout: String = String::from_utf8_lossy(&run_ok(&["5", "7", "--seed", "2", "--cache-state", "both", "--llc-size", "64K", "--warmup", "1",
+                                                           "--output-bin", both.to_str().unwrap()]).stdout).into_owned();
+    run_ok(&["5", "7", "--seed", "2", "--output-bin", plain.to_str().unwrap()]);
+
+    let warm: usize = stdout.find("Cache warm: ").expect(&stdout);
+    let cold: usize = stdout.find("Cache cold: ").expect(&stdout);
+    assert!(warm < cold, "{}", stdout);
+
+    for line in stdout.lines().filter(|line| line.starts_with("Cache ")) {
+        // Both kernels, each with the full timing window.
+        assert_eq!(line.matches("(median of 5, cv ").count(), 2, "{}", line);
+        assert_eq!(line.matches("1 warm-up runs discarded").count(), 2, "{}", line);
+    }
+
+    if cfg!(all(feature = "cache-flush", target_arch = "x86_64")) {
+        assert!(stdout.contains("(input flushed with clflush before every run)"), "{}", stdout);
+    } else {
+        assert!(stdout.contains("(evicted before every run by streaming 128.0 KiB for a 64.0 KiB last-level cache)"), "{}", stdout);
+    }
+
+    for name in ["dx.bin", "dy.bin"] {
+        assert_eq!(fs::read(both.join(name)).unwrap(), fs::read(plain.join(name)).unwrap(), "{}", name);
+    }
+}
+
+#[test]
+fn unknown_cache_states_are_rejected() {
+    assert!(!run(&["5", "7", "--cache-state", "lukewarm"]).status.success());
+}
@@ -0,0 +1,84 @@
+#![allow(clippy::needless_return)]
+
+use rmm::error::CancelError;
+use rmm::kernels::{compute_dx, compute_dx_cancellable, compute_dy, compute_dy_cancellable};
+use rmm::matmul::{matmul_blocked, matmul_blocked_cancellable};
+use rmm::matrix::{construct_randomized_matrix_cancellable, construct_randomized_matrix_seeded};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+// Runs run with a token another thread sets after delay, while run is busy.
+fn cancelled_midway<T>(delay: Duration, run: impl FnOnce(&AtomicBool) -> Result<T, CancelError>) -> CancelError {
+    let cancel: AtomicBool = AtomicBool::new(false);
+
+    return thread::scope(|scope| {
+        scope.spawn(|| {
+            thread::sleep(delay);
+            cancel.store(true, Ordering::Relaxed);
+        });
+
+        return run(&cancel).err().expect("the run finished before it was cancelled");
+    });
+}
+
+// completed of a Cancelled error, checked to be short of total.
+fn partial(err: CancelError, expected_total: usize) -> usize {
+    let CancelError::Cancelled { completed, total } = err else {
+        panic!("expected Cancelled, got {:?}", err);
+    };
+
+    assert_eq!(total, expected_total);
+    assert!(completed < total, "completed {} of {}", completed, total);
+
+    return completed;
+}
+
+#[test]
+fn generation_stops_when_cancelled_from_another_thread() {
+    // 16384 x 16384 takes far longer than the delay.
+    let err: CancelError = cancelled_midway(Duration::from_millis(20), |cancel| construct_randomized_matrix_cancellable(16384, 16384, Some(1), cancel));
+
+    partial(err, 16384);
+}
+
+#[test]
+fn kernels_stop_when_cancelled_from_another_thread() {
+    let (rows, cols): (usize, usize) = (8192, 8192);
+    let arr: Vec<u8> = (0..rows * cols).map(|index| (index % 251) as u8).collect();
+
+    partial(cancelled_midway(Duration::from_millis(5), |cancel| compute_dx_cancellable(&arr, rows, cols, cancel)), rows);
+    partial(cancelled_midway(Duration::from_millis(5), |cancel| compute_dy_cancellable(&arr, rows, cols, cancel)), rows);
+}
+
+#[test]
+fn matmul_stops_when_cancelled_from_another_thread() {
+    let n: usize = 1024;
+    let a: Vec<f32> = (0..n * n).map(|index| (index % 7) as f32).collect();
+
+    let err: CancelError = cancelled_midway(Duration::from_millis(20), |cancel| matmul_blocked_cancellable(&a, n, n, &a, n, n, 32, cancel));
+    let completed: usize = partial(err, n);
+
+    // Checked before every block row.
+    assert_eq!(completed % 32, 0);
+}
+
+#[test]
+fn a_set_token_stops_before_any_work_and_an_unset_one_changes_nothing() {
+    let set: AtomicBool = AtomicBool::new(true);
+    let unset: AtomicBool = AtomicBool::new(false);
+    let arr: Vec<u8> = construct_randomized_matrix_seeded(70, 9, 4);
+    let a: Vec<f32> = arr[..63].iter().map(|&value| value as f32).collect();
+
+    assert_eq!(construct_randomized_matrix_cancellable(70, 9, Some(4), &set), Err(CancelError::Cancelled { completed: 0, total: 70 }));
+    assert_eq!(compute_dx_cancellable(&arr, 70, 9, &set), Err(CancelError::Cancelled { completed: 0, total: 70 }));
+    assert_eq!(compute_dy_cancellable(&arr, 70, 9, &set), Err(CancelError::Cancelled { completed: 0, total: 70 }));
+    assert_eq!(matmul_blocked_cancellable(&a, 7, 9, &a, 9, 7, 4, &set), Err(CancelError::Cancelled { completed: 0, total: 7 }));
+
+    assert_eq!(construct_randomized_matrix_cancellable(70, 9, Some(4), &unset).unwrap(), arr);
+    assert_eq!(compute_dx_cancellable(&arr, 70, 9, &unset).unwrap(), compute_dx(&arr, 70, 9).data);
+    assert_eq!(compute_dy_cancellable(&arr, 70, 9, &unset).unwrap(), compute_dy(&arr, 70, 9).data);
+    assert_eq!(matmul_blocked_cancellable(&a, 7, 9, &a, 9, 7, 4, &unset).unwrap(), matmul_blocked(&a, 7, 9, &a, 9, 7, 4).unwrap());
+
+    assert_eq!(CancelError::Cancelled { completed: 64, total: 70 }.to_string(), "cancelled after 64 of 70 rows");
+}
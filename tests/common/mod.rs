@@ -0,0 +1,30 @@
+// Helpers shared by the integration tests, which drive the binary the way a
+// user would.
+#![allow(dead_code, clippy::needless_return)]
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+// Runs the binary with args and returns what it wrote and how it exited.
+pub fn run(args: &[&str]) -> Output {
+    return Command::new(env!("CARGO_BIN_EXE_matician-coding-challenge")).args(args).output()
+        .expect("Failed to run the binary");
+}
+
+// Like run, panicking with the binary's stderr unless it succeeded.
+pub fn run_ok(args: &[&str]) -> Output {
+    let output: Output = run(args);
+    assert!(output.status.success(), "{:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+
+    return output;
+}
+
+// An empty directory for one test, under the system temp directory. Tests
+// run in parallel, so name must be unique to the test.
+pub fn scratch(name: &str) -> PathBuf {
+    let dir: PathBuf = std::env::temp_dir().join(format!("rmm-test-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("Failed to create the scratch directory");
+
+    return dir;
+}
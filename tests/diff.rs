@@ -0,0 +1,94 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{run, run_ok, scratch};
+use rmm::io::{read_matrix, write_bin, StoredMatrix};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+// Writes Dx and Dy of a seeded 6x9 matrix to dir, both as binary and as CSV.
+fn results(dir: &Path) -> (PathBuf, PathBuf) {
+    let (bin, csv) = (dir.join("bin"), dir.join("csv"));
+    run_ok(&["6", "9", "--seed", "4", "--output-bin", bin.to_str().unwrap(), "--output-csv", csv.to_str().unwrap()]);
+
+    return (bin, csv);
+}
+
+fn diff(a: &Path, b: &Path) -> Output {
+    return run(&["diff", a.to_str().unwrap(), b.to_str().unwrap()]);
+}
+
+#[test]
+fn identical_files_exit_zero() {
+    let dir: PathBuf = scratch("diff-identical");
+    let (bin, csv) = results(&dir);
+
+    // Dx is 6x11 and Dy 8x9; binary and CSV may be mixed.
+    for (a, b, shape) in [(bin.join("dx.bin"), bin.join("dx.bin"), "6x11"), (bin.join("dx.bin"), csv.join("dx.csv"), "6x11"),
+                          (csv.join("dy.csv"), bin.join("dy.bin"), "8x9")] {
+        let output: Output = diff(&a, &b);
+
+        assert_eq!(output.status.code(), Some(0), "{:?} vs {:?}", a, b);
+        assert_eq!(String::from_utf8_lossy(&output.stdout), format!("identical: {}\n", shape));
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_planted_difference_is_reported() {
+    let dir: PathBuf = scratch("diff-planted");
+    let (bin, csv) = results(&dir);
+
+    let dx: StoredMatrix = read_matrix(&bin.join("dx.bin")).unwrap();
+    let mut changed: Vec<i16> = dx.values.iter().map(|&value| value as i16).collect();
+    // (2, 7) of the 11 columns.
+    let index: usize = 29;
+    let original: i16 = changed[index];
+    changed[index] = original.wrapping_add(1000);
+    let planted: PathBuf = dir.join("planted.bin");
+    write_bin(&planted, &changed, dx.rows, dx.cols).unwrap();
+
+    for reference in [bin.join("dx.bin"), csv.join("dx.csv")] {
+        let output: Output = diff(&reference, &planted);
+        let stdout: String = String::from_utf8_lossy(&output.stdout).into_owned();
+
+        assert_eq!(output.status.code(), Some(1), "{}", stdout);
+        assert!(stdout.starts_with("1 of 66 elements differ, max absolute difference: 1000 at (2, 7)"), "{}", stdout);
+        assert!(stdout.contains(&format!("  (2, 7): {} vs {}\n", original, original.wrapping_add(1000))), "{}", stdout);
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn mismatched_shapes_and_types_exit_two() {
+    let dir: PathBuf = scratch("diff-mismatch");
+    let (bin, csv) = results(&dir);
+
+    for (a, b) in [(bin.join("dx.bin"), bin.join("dy.bin")), (csv.join("dx.csv"), bin.join("dy.bin"))] {
+        let output: Output = diff(&a, &b);
+
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("shape mismatch"), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+
+    // The same values stored as i32 rather than i16.
+    let dx: StoredMatrix = read_matrix(&bin.join("dx.bin")).unwrap();
+    let wide: PathBuf = dir.join("wide.bin");
+    write_bin(&wide, &dx.values.iter().map(|&value| value as i32).collect::<Vec<i32>>(), dx.rows, dx.cols).unwrap();
+    let output: Output = diff(&bin.join("dx.bin"), &wide);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("element type mismatch: "), "{}", String::from_utf8_lossy(&output.stderr));
+    // CSV carries no type, so the values alone decide.
+    assert_eq!(diff(&csv.join("dx.csv"), &wide).status.code(), Some(0));
+
+    let missing: Output = diff(&bin.join("dx.bin"), &dir.join("missing.bin"));
+    assert_eq!(missing.status.code(), Some(2));
+    assert_eq!(run(&["diff", bin.join("dx.bin").to_str().unwrap()]).status.code(), Some(2));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,73 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{run, scratch};
+use rmm::histogram::{value_histogram, write_hist, HIST_MAX, HIST_MIN};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+// Writes the histogram of a ramp of values centered on center to path.
+fn hist(path: &Path, center: i16, bins: usize) -> String {
+    let data: Vec<i16> = (0..5000).map(|index| center + (index % 81) as i16 - 40).collect();
+    write_hist(path, &value_histogram(&data, HIST_MIN, HIST_MAX, bins)).unwrap();
+
+    return path.to_str().unwrap().to_string();
+}
+
+fn diff_hist(args: &[&str]) -> (Option<i32>, String, String) {
+    let output: Output = run(&[&["diff-hist"], args].concat());
+
+    return (output.status.code(), String::from_utf8_lossy(&output.stdout).into_owned(), String::from_utf8_lossy(&output.stderr).into_owned());
+}
+
+#[test]
+fn identical_histograms_match() {
+    let dir: PathBuf = scratch("diff-hist-identical");
+    let (a, b) = (hist(&dir.join("a.hist"), 3, 511), hist(&dir.join("b.hist"), 3, 511));
+    let (code, stdout, _) = diff_hist(&[&a, &b]);
+
+    assert_eq!(code, Some(0), "{}", stdout);
+    assert!(stdout.contains("chi-squared: 0.000000 earth mover's: 0.000000 threshold: 0.01"), "{}", stdout);
+    assert!(stdout.ends_with("Distributions match\n"), "{}", stdout);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_shifted_distribution_drifts() {
+    let dir: PathBuf = scratch("diff-hist-shifted");
+    let (a, b) = (hist(&dir.join("a.hist"), 0, 511), hist(&dir.join("b.hist"), 25, 511));
+    let (code, stdout, _) = diff_hist(&[&a, &b]);
+
+    assert_eq!(code, Some(1), "{}", stdout);
+    assert!(stdout.ends_with("Distributions drifted\n"), "{}", stdout);
+
+    // Within a threshold loose enough for the shift.
+    let (code, stdout, _) = diff_hist(&[&a, &b, "--threshold", "1"]);
+    assert_eq!(code, Some(0), "{}", stdout);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn mismatched_histograms_are_incomparable() {
+    let dir: PathBuf = scratch("diff-hist-mismatched");
+    let (a, b) = (hist(&dir.join("a.hist"), 0, 511), hist(&dir.join("b.hist"), 0, 64));
+    let (code, _, stderr) = diff_hist(&[&a, &b]);
+
+    // An error status and message, not a panic.
+    assert_eq!(code, Some(2), "{}", stderr);
+    assert!(stderr.contains("histogram bins") && !stderr.contains("panicked"), "{}", stderr);
+
+    let missing: String = dir.join("missing.hist").to_str().unwrap().to_string();
+    let (code, _, stderr) = diff_hist(&[&a, &missing]);
+    assert_eq!(code, Some(2), "{}", stderr);
+    assert!(stderr.starts_with(&format!("Failed to read {}", missing)), "{}", stderr);
+
+    let (code, _, _) = diff_hist(&[&a]);
+    assert_eq!(code, Some(2));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
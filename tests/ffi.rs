@@ -0,0 +1,96 @@
+#![cfg(feature = "ffi")]
+#![allow(clippy::needless_return)]
+
+use rmm::ffi::{rmm_compute_dx_into, rmm_compute_dy_into, rmm_dx_output_len, rmm_dy_output_len, RmmStatus, RMM_LEN_OVERFLOW};
+use rmm::index::{padded_dims, Axis, Padding};
+use rmm::kernels::{compute_dx, compute_dy};
+use rmm::matrix::construct_randomized_matrix_seeded;
+use rmm::memstats::{thread_reset, thread_snapshot, CountingAllocator};
+use std::ptr;
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// A value no kernel output takes, to see what was written.
+const UNTOUCHED: i16 = i16::MIN;
+
+// rmm_compute_dx_into or rmm_compute_dy_into.
+type Entry = unsafe extern "C" fn(*const u8, usize, usize, *mut i16, usize) -> RmmStatus;
+
+// rmm_dx_output_len or rmm_dy_output_len.
+type OutputLen = extern "C" fn(usize, usize) -> usize;
+
+// The Vec kernel an entry point must match.
+type Kernel = fn(&[u8], usize, usize) -> Vec<i16>;
+
+#[test]
+fn output_lens_are_those_of_the_kernels() {
+    for rows in 0..=6 {
+        for cols in 0..=6 {
+            let (dx_rows, dx_cols): (usize, usize) = padded_dims(rows, cols, 3, Axis::Cols, Padding::Full);
+            let (dy_rows, dy_cols): (usize, usize) = padded_dims(rows, cols, 3, Axis::Rows, Padding::Full);
+            let arr: Vec<u8> = vec![0; rows * cols];
+
+            assert_eq!(rmm_dx_output_len(rows, cols), dx_rows * dx_cols);
+            assert_eq!(rmm_dx_output_len(rows, cols), compute_dx(&arr, rows, cols).data.len());
+            assert_eq!(rmm_dy_output_len(rows, cols), dy_rows * dy_cols);
+            assert_eq!(rmm_dy_output_len(rows, cols), compute_dy(&arr, rows, cols).data.len());
+        }
+    }
+
+    assert_eq!(rmm_dx_output_len(usize::MAX, 1), RMM_LEN_OVERFLOW);
+    assert_eq!(rmm_dx_output_len(1, usize::MAX - 1), RMM_LEN_OVERFLOW);
+    assert_eq!(rmm_dy_output_len(usize::MAX - 1, 1), RMM_LEN_OVERFLOW);
+    assert_eq!(rmm_dy_output_len(1 << 32, 1 << 32), RMM_LEN_OVERFLOW);
+}
+
+// Both entry points match the Vec kernels, write only the output length of an
+// oversized buffer and allocate nothing.
+#[test]
+fn entry_points_write_the_kernel_results_without_allocating() {
+    let entries: [(Entry, OutputLen, Kernel); 2] = [
+        (rmm_compute_dx_into, rmm_dx_output_len, |arr, rows, cols| compute_dx(arr, rows, cols).data),
+        (rmm_compute_dy_into, rmm_dy_output_len, |arr, rows, cols| compute_dy(arr, rows, cols).data),
+    ];
+
+    for (rows, cols) in [(1, 1), (2, 3), (16, 16), (37, 53)] {
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, 9);
+
+        for (entry, output_len, kernel) in entries {
+            let len: usize = output_len(rows, cols);
+            let mut out: Vec<i16> = vec![UNTOUCHED; len + 5];
+
+            thread_reset();
+            // SAFETY: arr holds rows * cols bytes and out holds out.len() i16s.
+            let status: RmmStatus = unsafe { entry(arr.as_ptr(), rows, cols, out.as_mut_ptr(), out.len()) };
+            let allocations: usize = thread_snapshot().alloc_count;
+
+            assert_eq!(status, RmmStatus::Ok);
+            assert_eq!(allocations, 0, "{}x{}", rows, cols);
+            assert_eq!(out[..len], kernel(&arr, rows, cols)[..]);
+            assert!(out[len..].iter().all(|&value| value == UNTOUCHED));
+        }
+    }
+}
+
+#[test]
+fn every_failure_has_its_own_status() {
+    let arr: Vec<u8> = vec![1; 12];
+
+    for (entry, needed) in [(rmm_compute_dx_into as Entry, rmm_dx_output_len(3, 4)), (rmm_compute_dy_into, rmm_dy_output_len(3, 4))] {
+        let mut out: Vec<i16> = vec![UNTOUCHED; needed];
+
+        // SAFETY: every pointer passed is NULL or covers the length given
+        // with it; the calls return before reading past them.
+        unsafe {
+            assert_eq!(entry(ptr::null(), 3, 4, out.as_mut_ptr(), needed), RmmStatus::NullPointer);
+            assert_eq!(entry(arr.as_ptr(), 3, 4, ptr::null_mut(), needed), RmmStatus::NullPointer);
+            assert_eq!(entry(arr.as_ptr(), 3, 4, out.as_mut_ptr(), needed - 1), RmmStatus::BufferTooSmall);
+            assert_eq!(entry(arr.as_ptr(), usize::MAX, 2, out.as_mut_ptr(), needed), RmmStatus::DimensionOverflow);
+            assert_eq!(entry(arr.as_ptr(), 1 << 32, 1 << 32, out.as_mut_ptr(), needed), RmmStatus::DimensionOverflow);
+        }
+
+        // Nothing is written unless the call succeeds.
+        assert!(out.iter().all(|&value| value == UNTOUCHED));
+    }
+}
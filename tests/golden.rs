@@ -0,0 +1,93 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{run_ok, scratch};
+use rmm::io::write_bin;
+use rmm::kernels::{compute_dx, compute_dy};
+use rmm::matrix::{construct_randomized_matrix_seeded, Matrix};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+const GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden");
+
+// (seed, rows, cols, kernel) from a name such as seed2_7x5_dx.bin.
+fn parse_case(name: &str) -> Option<(u64, usize, usize, String)> {
+    let (case, kernel) = name.strip_suffix(".bin")?.rsplit_once('_')?;
+    let (seed, dims) = case.strip_prefix("seed")?.split_once('_')?;
+    let (rows, cols) = dims.split_once('x')?;
+
+    return Some((seed.parse().ok()?, rows.parse().ok()?, cols.parse().ok()?, kernel.to_string()));
+}
+
+// Every committed golden matrix, regenerated through the library and written
+// the same way, must match byte for byte. RMM_BLESS=1 rewrites them first,
+// as golden --bless does.
+#[test]
+fn golden_files_match_regenerated_outputs() {
+    if std::env::var("RMM_BLESS").is_ok_and(|value| value == "1") {
+        run_ok(&["golden", "--bless", "--dir", GOLDEN_DIR]);
+    }
+
+    let dir: PathBuf = scratch("golden-regenerated");
+    let mut checked: usize = 0;
+
+    for entry in fs::read_dir(GOLDEN_DIR).unwrap() {
+        let name: String = entry.unwrap().file_name().into_string().unwrap();
+        if !name.ends_with(".bin") {
+            continue;
+        }
+
+        let (seed, rows, cols, kernel) = parse_case(&name).unwrap_or_else(|| panic!("Unexpected golden file {}", name));
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, seed);
+        let output: Matrix<i16> = match kernel.as_str() {
+            "dx" => compute_dx(&arr, rows, cols),
+            "dy" => compute_dy(&arr, rows, cols),
+            other => panic!("Unknown kernel {} in {}", other, name),
+        };
+        let generated: PathBuf = dir.join(&name);
+        write_bin(&generated, &output.data, output.rows, output.cols).unwrap();
+
+        assert!(fs::read(&generated).unwrap() == fs::read(Path::new(GOLDEN_DIR).join(&name)).unwrap(),
+                "{} differs from its regenerated output; rerun with RMM_BLESS=1 if the change is intended", name);
+        checked += 1;
+    }
+
+    assert_eq!(checked, 10);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Runs golden --dir dir, never blessing whatever the environment says.
+fn check(dir: &Path) -> Output {
+    return Command::new(env!("CARGO_BIN_EXE_matician-coding-challenge")).args(["golden", "--dir", dir.to_str().unwrap()])
+        .env_remove("RMM_BLESS").output().expect("Failed to run the binary");
+}
+
+#[test]
+fn golden_subcommand_catches_a_changed_file() {
+    assert!(check(Path::new(GOLDEN_DIR)).status.success());
+
+    let dir: PathBuf = scratch("golden-changed");
+    for entry in fs::read_dir(GOLDEN_DIR).unwrap() {
+        let path: PathBuf = entry.unwrap().path();
+        fs::copy(&path, dir.join(path.file_name().unwrap())).unwrap();
+    }
+
+    // The last byte of a data element.
+    let changed: PathBuf = dir.join("seed3_16x16_dy.bin");
+    let mut bytes: Vec<u8> = fs::read(&changed).unwrap();
+    *bytes.last_mut().unwrap() ^= 1;
+    fs::write(&changed, bytes).unwrap();
+    fs::remove_file(dir.join("seed2_7x5_dx.bin")).unwrap();
+
+    let output: Output = check(&dir);
+    let stdout: String = String::from_utf8_lossy(&output.stdout).into_owned();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout.contains("seed3_16x16_dy.bin           MISMATCH"), "{}", stdout);
+    assert!(stdout.contains("seed2_7x5_dx.bin             MISSING"), "{}", stdout);
+    assert!(stdout.contains("seed1_1x1_dx.bin             ok"), "{}", stdout);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
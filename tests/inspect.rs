@@ -0,0 +1,41 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{run, run_ok, scratch};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Output;
+
+// inspect on the binary's own --output-stream files: complete, then cut
+// short, then with the wrong magic.
+#[test]
+fn inspect_reports_each_kind_of_file() {
+    let dir: PathBuf = scratch("inspect");
+    run_ok(&["5", "6", "--seed", "2", "--output-stream", dir.to_str().unwrap()]);
+    let (dx, dy) = (dir.join("dx.rmms"), dir.join("dy.rmms"));
+
+    let complete: Output = run_ok(&["inspect", dx.to_str().unwrap(), dy.to_str().unwrap()]);
+    let stdout: String = String::from_utf8_lossy(&complete.stdout).into_owned();
+    assert!(stdout.contains("dx.rmms: stream format version 1, 5x8 i16\n  complete: 40 elements, checksum "), "{}", stdout);
+    assert!(stdout.contains("dy.rmms: stream format version 1, 7x6 i16\n  complete: 42 elements, checksum "), "{}", stdout);
+
+    // Three rows of Dx and 4 bytes of the fourth.
+    let bytes: Vec<u8> = fs::read(&dx).unwrap();
+    fs::write(&dx, &bytes[..24 + 3 * 16 + 4]).unwrap();
+    let truncated: Output = run(&["inspect", dx.to_str().unwrap(), dy.to_str().unwrap()]);
+    let stdout: String = String::from_utf8_lossy(&truncated.stdout).into_owned();
+    assert_eq!(truncated.status.code(), Some(1));
+    assert!(stdout.contains("  truncated: footer missing, 3 of 5 rows complete and recoverable\n  4 bytes of row 3 written\n"), "{}", stdout);
+    assert!(stdout.contains("dy.rmms: stream format version 1, 7x6 i16\n  complete"), "{}", stdout);
+
+    let mut bytes: Vec<u8> = fs::read(&dy).unwrap();
+    bytes[..4].copy_from_slice(b"P5\n6");
+    fs::write(&dy, &bytes).unwrap();
+    let wrong: Output = run(&["inspect", dy.to_str().unwrap()]);
+    assert_eq!(wrong.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&wrong.stderr).contains("dy.rmms: not a stream file: magic \"P5\\n6\", expected \"RMMS\""),
+            "{}", String::from_utf8_lossy(&wrong.stderr));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
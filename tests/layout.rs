@@ -0,0 +1,26 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{run_ok, scratch};
+use std::fs;
+use std::path::PathBuf;
+
+// --layout col stores the input col-major but must write the same Dx and Dy.
+#[test]
+fn col_layout_writes_the_row_major_results() {
+    for (rows, cols) in [("5", "7"), ("1", "9"), ("8", "1"), ("16", "16")] {
+        let dir: PathBuf = scratch(&format!("layout-{}x{}", rows, cols));
+        let (row_dir, col_dir) = (dir.join("row"), dir.join("col"));
+
+        run_ok(&[rows, cols, "--seed", "3", "--output-bin", row_dir.to_str().unwrap()]);
+        run_ok(&[rows, cols, "--seed", "3", "--layout", "col", "--output-bin", col_dir.to_str().unwrap()]);
+
+        for name in ["dx.bin", "dy.bin"] {
+            let expected: Vec<u8> = fs::read(row_dir.join(name)).unwrap();
+            assert_eq!(fs::read(col_dir.join(name)).unwrap(), expected, "{} for {}x{}", name, rows, cols);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,61 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{run, run_ok, scratch};
+use std::fs;
+use std::path::{Path, PathBuf};
+#[cfg(not(feature = "parallel"))]
+use std::process::Output;
+
+// Writes Dx and Dy of a seeded 37x29 matrix to dir, run with extra.
+fn results(dir: &Path, extra: &[&str]) -> (Vec<u8>, Vec<u8>) {
+    let mut args: Vec<&str> = vec!["37", "29", "--seed", "8", "--output-bin", dir.to_str().unwrap()];
+    args.extend_from_slice(extra);
+    run_ok(&args);
+
+    return (fs::read(dir.join("dx.bin")).unwrap(), fs::read(dir.join("dy.bin")).unwrap());
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn threads_give_the_serial_results() {
+    let dir: PathBuf = scratch("parallel-threads");
+    let serial: (Vec<u8>, Vec<u8>) = results(&dir.join("serial"), &[]);
+
+    for threads in ["1", "2", "7"] {
+        assert!(results(&dir.join(threads), &["--threads", threads]) == serial, "{} threads", threads);
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(not(feature = "parallel"))]
+#[test]
+fn threads_is_refused_in_a_serial_build() {
+    let output: Output = run(&["37", "29", "--threads", "2"]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--threads is not available: this build has the parallel feature disabled and runs serially"),
+            "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[cfg(not(feature = "parallel"))]
+#[test]
+fn timeout_is_refused_in_a_serial_build() {
+    let output: Output = run(&["37", "29", "--timeout", "10s"]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--timeout is not available"), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+// Both builds: a run without --threads works and is deterministic.
+#[test]
+fn serial_runs_are_reproducible() {
+    let dir: PathBuf = scratch("parallel-serial");
+
+    assert!(results(&dir.join("a"), &[]) == results(&dir.join("b"), &[]));
+    assert!(run(&["37", "29", "--seed", "8"]).status.success());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
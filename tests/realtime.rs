@@ -0,0 +1,68 @@
+#![allow(clippy::needless_return)]
+
+use rmm::convert::normalize_u8;
+use rmm::gradient::magnitude;
+use rmm::kernels::{compute_dx, compute_dy};
+use rmm::matrix::construct_randomized_matrix_seeded;
+use rmm::memstats::{thread_reset, thread_snapshot, CountingAllocator};
+use rmm::realtime::{compute_dx_into, compute_dxdy_into, compute_dy_into, magnitude_into, normalize_u8_into};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// Allocations the calling thread makes in run.
+fn allocations(run: impl FnOnce()) -> usize {
+    thread_reset();
+    run();
+
+    return thread_snapshot().alloc_count;
+}
+
+#[test]
+fn the_counter_sees_allocations() {
+    assert_eq!(allocations(|| drop(std::hint::black_box(vec![0u8; 64]))), 1);
+}
+
+// Every realtime function, on buffers sized up front, allocates nothing,
+// including on the fixed-size paths of small shapes and on the generic loops.
+#[test]
+fn realtime_kernels_make_no_allocations() {
+    for (rows, cols) in [(1, 1), (3, 3), (16, 16), (37, 53), (300, 200)] {
+        let arr: Vec<u8> = construct_randomized_matrix_seeded(rows, cols, 2);
+        let mut dx: Vec<i16> = vec![0; rows * (cols + 2)];
+        let mut dy: Vec<i16> = vec![0; (rows + 2) * cols];
+        let mut fused_dx: Vec<i16> = vec![0; rows * (cols + 2)];
+        let mut fused_dy: Vec<i16> = vec![0; (rows + 2) * cols];
+        let mut magnitude_out: Vec<f32> = vec![0.0; rows * cols];
+        let mut normalized: Vec<u8> = vec![0; rows * cols];
+
+        assert_eq!(allocations(|| compute_dx_into(&arr, rows, cols, &mut dx).unwrap()), 0, "dx {}x{}", rows, cols);
+        assert_eq!(allocations(|| compute_dy_into(&arr, rows, cols, &mut dy).unwrap()), 0, "dy {}x{}", rows, cols);
+        assert_eq!(allocations(|| compute_dxdy_into(&arr, rows, cols, &mut fused_dx, &mut fused_dy).unwrap()), 0, "dxdy {}x{}", rows, cols);
+        assert_eq!(allocations(|| magnitude_into(&dx, &dy, rows, cols, &mut magnitude_out).unwrap()), 0, "magnitude {}x{}", rows, cols);
+        assert_eq!(allocations(|| normalize_u8_into(&magnitude_out, &mut normalized).unwrap()), 0, "normalize {}x{}", rows, cols);
+
+        // The same results as the allocating functions.
+        assert_eq!(dx, compute_dx(&arr, rows, cols).data);
+        assert_eq!(dy, compute_dy(&arr, rows, cols).data);
+        assert_eq!((&fused_dx, &fused_dy), (&dx, &dy));
+        assert_eq!(magnitude_out, magnitude(&dx, &dy, rows, cols).unwrap());
+        assert_eq!(normalized, normalize_u8(&magnitude_out));
+    }
+}
+
+// Not even an error allocates.
+#[test]
+fn realtime_errors_make_no_allocations() {
+    let arr: Vec<u8> = vec![0; 12];
+    let mut short: Vec<i16> = vec![0; 5];
+    let mut other: Vec<i16> = vec![0; 5];
+    let mut out: Vec<f32> = vec![0.0; 5];
+    let mut bytes: Vec<u8> = vec![0; 4];
+
+    assert_eq!(allocations(|| assert!(compute_dx_into(&arr, 3, 4, &mut short).is_err())), 0);
+    assert_eq!(allocations(|| assert!(compute_dy_into(&arr, 3, 5, &mut short).is_err())), 0);
+    assert_eq!(allocations(|| assert!(compute_dxdy_into(&arr, 3, 4, &mut short, &mut other).is_err())), 0);
+    assert_eq!(allocations(|| assert!(magnitude_into(&short, &short, 3, 4, &mut out).is_err())), 0);
+    assert_eq!(allocations(|| assert!(normalize_u8_into(&out, &mut bytes).is_err())), 0);
+}
@@ -0,0 +1,135 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{run, run_ok, scratch};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+use toml::{Table, Value};
+
+// Exit status of a replay refused for not matching this build or machine.
+const EXIT_REPRO_MISMATCH: i32 = 4;
+
+fn stdout(output: &Output) -> String {
+    return String::from_utf8_lossy(&output.stdout).into_owned();
+}
+
+fn stderr(output: &Output) -> String {
+    return String::from_utf8_lossy(&output.stderr).into_owned();
+}
+
+// Runs extra, recording it to dir/name, and returns the file and the
+// checksum it recorded.
+fn emit(dir: &Path, name: &str, extra: &[&str]) -> (PathBuf, String) {
+    let path: PathBuf = dir.join(name);
+    let mut args: Vec<&str> = vec!["--emit-repro", path.to_str().unwrap()];
+    args.extend_from_slice(extra);
+    run_ok(&args);
+
+    let table: Table = fs::read_to_string(&path).unwrap().parse().unwrap();
+    let checksum: String = table["result"]["checksum"].as_str().unwrap().to_string();
+
+    return (path, checksum);
+}
+
+// Rewrites the repro file at path through change.
+fn doctor(path: &Path, change: impl FnOnce(&mut Table)) {
+    let mut table: Table = fs::read_to_string(path).unwrap().parse().unwrap();
+    change(&mut table);
+    fs::write(path, toml::to_string(&table).unwrap()).unwrap();
+}
+
+fn replay(path: &Path, force: bool) -> Output {
+    let mut args: Vec<&str> = vec!["--repro", path.to_str().unwrap()];
+    if force {
+        args.push("--force");
+    }
+
+    return run(&args);
+}
+
+#[test]
+fn a_replay_reproduces_the_recorded_checksum() {
+    // Unseeded, so the replay depends on the recorded seed.
+    for extra in [&["16", "12"][..], &["9", "20", "--kernel", "1,2,1", "--pad", "0"], &["11", "7", "--seed", "5", "--crop-output"]] {
+        let dir: PathBuf = scratch(&format!("repro-replay-{}", extra.join("-").replace(',', "_")));
+        let (path, checksum) = emit(&dir, "repro.toml", extra);
+        let output: Output = replay(&path, false);
+
+        assert_eq!(output.status.code(), Some(0), "{:?}: {}", extra, stderr(&output));
+        assert!(stdout(&output).contains("Replaying "), "{}", stdout(&output));
+        assert!(stdout(&output).contains(&format!("Assertions passed: checksum {}", checksum)), "{:?}: {}", extra, stdout(&output));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+#[test]
+fn two_unseeded_runs_differ_but_their_replays_do_not() {
+    let dir: PathBuf = scratch("repro-unseeded");
+    let (first, first_checksum) = emit(&dir, "a.toml", &["16", "16"]);
+    let (second, second_checksum) = emit(&dir, "b.toml", &["16", "16"]);
+
+    assert_ne!(first_checksum, second_checksum);
+    assert!(stdout(&replay(&first, false)).contains(&format!("checksum {}", first_checksum)));
+    assert!(stdout(&replay(&second, false)).contains(&format!("checksum {}", second_checksum)));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_doctored_version_is_refused_unless_forced() {
+    let dir: PathBuf = scratch("repro-version");
+    let (path, checksum) = emit(&dir, "repro.toml", &["16", "12"]);
+    doctor(&path, |table| {
+        table.insert("version".to_string(), Value::from("0.0.0-doctored"));
+    });
+
+    let refused: Output = replay(&path, false);
+    assert_eq!(refused.status.code(), Some(EXIT_REPRO_MISMATCH), "{}", stderr(&refused));
+    assert!(stderr(&refused).contains(&format!("recorded by version 0.0.0-doctored, this is {}", env!("CARGO_PKG_VERSION"))), "{}", stderr(&refused));
+    assert!(stderr(&refused).contains("Refusing to replay "), "{}", stderr(&refused));
+    assert!(!stdout(&refused).contains("Assertions passed"));
+
+    let forced: Output = replay(&path, true);
+    assert_eq!(forced.status.code(), Some(0), "{}", stderr(&forced));
+    assert!(stderr(&forced).contains("anyway (--force); the results may differ"), "{}", stderr(&forced));
+    assert!(stdout(&forced).contains(&format!("Assertions passed: checksum {}", checksum)));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn other_doctored_files_are_refused() {
+    let dir: PathBuf = scratch("repro-doctored");
+    let (path, _) = emit(&dir, "repro.toml", &["16", "12"]);
+    let original: String = fs::read_to_string(&path).unwrap();
+
+    // A CPU feature this machine lacks, and a setting the replay does not
+    // resolve to.
+    let features = |table: &mut Table| table["cpu-features"].as_array_mut().unwrap().push(Value::from("no-such-feature"));
+    let settings = |table: &mut Table| {
+        table["resolved"].as_table_mut().unwrap().insert("pad".to_string(), Value::from(5));
+    };
+    for (change, message) in [(&features as &dyn Fn(&mut Table), "recorded on a CPU with no-such-feature, which this one lacks"),
+                              (&settings, "pad resolves to 2, recorded as 5")] {
+        fs::write(&path, &original).unwrap();
+        doctor(&path, change);
+        let output: Output = replay(&path, false);
+
+        assert_eq!(output.status.code(), Some(EXIT_REPRO_MISMATCH), "{}", stderr(&output));
+        assert!(stderr(&output).contains(message), "{}", stderr(&output));
+    }
+
+    // A different result is a failed assertion, not a mismatch.
+    fs::write(&path, &original).unwrap();
+    doctor(&path, |table| {
+        table["result"].as_table_mut().unwrap().insert("checksum".to_string(), Value::from("0000000000000000"));
+    });
+    let output: Output = replay(&path, false);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr(&output));
+    assert!(stderr(&output).contains("assertion failed: checksum: expected 0000000000000000"), "{}", stderr(&output));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,57 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{run_ok, scratch};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Runs --time-strips strip_rows over a seeded rows x cols input, writing the
+// table and the heatmap to dir.
+fn time_strips(dir: &Path, rows: usize, cols: usize, strip_rows: usize) -> String {
+    let (rows, cols, strip_rows): (String, String, String) = (rows.to_string(), cols.to_string(), strip_rows.to_string());
+    let dir: &str = dir.to_str().unwrap();
+
+    return String::from_utf8_lossy(&run_ok(&[&rows, &cols, "--seed", "3", "--time-strips", &strip_rows, "--output-csv", dir, "--output-heatmap", dir]).stdout)
+        .into_owned();
+}
+
+// (cols, rows, pixel bytes) of a binary PPM.
+fn ppm_dims(path: &Path) -> (usize, usize, usize) {
+    let bytes: Vec<u8> = fs::read(path).unwrap();
+    // The header is three text lines: magic, "cols rows" and the maximum.
+    let mut lines = bytes.splitn(4, |&byte| byte == b'\n');
+    let (magic, dims, max, pixels) = (lines.next().unwrap(), lines.next().unwrap(), lines.next().unwrap(), lines.next().unwrap());
+    let dims: Vec<usize> = String::from_utf8_lossy(dims).split(' ').map(|value| value.parse().unwrap()).collect();
+
+    assert_eq!((magic, max), (&b"P6"[..], &b"255"[..]));
+    return (dims[0], dims[1], pixels.len());
+}
+
+#[test]
+fn the_heatmap_has_one_row_per_strip_and_a_column_per_kernel() {
+    let dir: PathBuf = scratch("time-strips-heatmap");
+    let stdout: String = time_strips(&dir, 37, 29, 8);
+
+    // 37 rows in strips of 8: four full strips and one of 5 rows.
+    assert!(stdout.contains("=== Strip timings (5 strips of 8 rows over 37x29) ==="), "{}", stdout);
+    assert_eq!(ppm_dims(&dir.join("strips.ppm")), (2, 5, 5 * 2 * 3));
+
+    let table: Vec<Vec<u128>> = fs::read_to_string(dir.join("strips.csv")).unwrap().lines()
+        .map(|line| line.split(',').map(|value| value.parse().unwrap()).collect()).collect();
+
+    assert_eq!(table.iter().map(|row| (row[0], row[1])).collect::<Vec<(u128, u128)>>(), vec![(0, 8), (8, 8), (16, 8), (24, 8), (32, 5)]);
+    assert!(table.iter().all(|row| row.len() == 4));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_strip_taller_than_the_input_is_a_single_heatmap_row() {
+    let dir: PathBuf = scratch("time-strips-single");
+
+    time_strips(&dir, 6, 40, 256);
+    assert_eq!(ppm_dims(&dir.join("strips.ppm")), (2, 1, 6));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
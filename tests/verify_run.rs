@@ -0,0 +1,68 @@
+#![allow(clippy::needless_return)]
+
+mod common;
+
+use common::{run, run_ok, scratch};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+// Rewrites the manifest in dir with edit applied to its pipeline entry for
+// stage.
+fn edit_stage(dir: &Path, stage: &str, edit: impl FnOnce(&mut Value)) {
+    let path: PathBuf = dir.join("manifest.json");
+    let mut manifest: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    let entry: &mut Value = manifest["pipeline"].as_array_mut().unwrap().iter_mut().find(|entry| entry["stage"] == stage).unwrap();
+    edit(entry);
+    fs::write(&path, serde_json::to_string_pretty(&manifest).unwrap()).unwrap();
+}
+
+fn verify(dir: &Path) -> (Option<i32>, String) {
+    let output: Output = run(&["verify-run", dir.to_str().unwrap()]);
+
+    return (output.status.code(), String::from_utf8_lossy(&output.stdout).into_owned());
+}
+
+#[test]
+fn an_artifact_reproduces_until_a_checksum_is_corrupted() {
+    let dir: PathBuf = scratch("verify-run").join("artifact");
+    run_ok(&["9", "11", "--seed", "5", "--output-dir", dir.to_str().unwrap()]);
+
+    let (status, stdout) = verify(&dir);
+    assert_eq!(status, Some(0), "{}", stdout);
+    assert!(stdout.contains(&format!("Artifact version {}, verifying with {}", env!("CARGO_PKG_VERSION"), env!("CARGO_PKG_VERSION"))), "{}", stdout);
+    assert!(stdout.contains("generate   ok ") && stdout.contains("dx         ok ") && stdout.contains("dy         ok "), "{}", stdout);
+    assert!(stdout.contains("Run reproduced: all 3 stages match"), "{}", stdout);
+
+    edit_stage(&dir, "dy", |entry| entry["checksum"] = Value::from("0123456789abcdef"));
+    let (status, stdout) = verify(&dir);
+    assert_eq!(status, Some(1), "{}", stdout);
+    assert!(stdout.contains("dy         MISMATCH  expected 0123456789abcdef, got "), "{}", stdout);
+    assert!(stdout.contains("dx         ok "), "{}", stdout);
+    assert!(stdout.ends_with("Run diverged at stage dy\n"), "{}", stdout);
+
+    // The first stage to diverge is named, not the last.
+    edit_stage(&dir, "generate", |entry| entry["checksum"] = Value::from("0000000000000001"));
+    let (status, stdout) = verify(&dir);
+    assert_eq!(status, Some(1));
+    assert!(stdout.contains("generate   MISMATCH  expected 0000000000000001, got b53d59f514d829ac"), "{}", stdout);
+    assert!(stdout.ends_with("Run diverged at stage generate\n"), "{}", stdout);
+
+    fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn a_stage_this_version_does_not_produce_diverges() {
+    let dir: PathBuf = scratch("verify-run-missing").join("artifact");
+    run_ok(&["6", "4", "--seed", "1", "--output-dir", dir.to_str().unwrap()]);
+
+    edit_stage(&dir, "dx", |entry| entry["stage"] = Value::from("blur"));
+    let (status, stdout) = verify(&dir);
+
+    assert_eq!(status, Some(1), "{}", stdout);
+    assert!(stdout.contains("blur       MISSING   not produced by this version"), "{}", stdout);
+    assert!(stdout.ends_with("Run diverged at stage blur\n"), "{}", stdout);
+
+    fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+}